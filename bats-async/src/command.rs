@@ -1,4 +1,27 @@
-use bats_lib::{builder::AnyPlugin, plugin::MidiEvent, Bats};
+use bats_dsp::{buffers::Buffers, position::Position, sample_rate::SampleRate};
+use bats_lib::{
+    automation::AutomationLane,
+    builder::{AnyEffect, BatsBuilder},
+    capture::Capture,
+    control_surface::ControlSurface,
+    expression::ExpressionRoute,
+    freeze::FrozenTrack,
+    lfo::ParamLfo,
+    macros::MacroMapping,
+    midi_filter::MidiFilter,
+    plugin::{compressor::Compressor, BatsEffect},
+    plugin_slot::PluginSlot,
+    preset::Preset,
+    recorder::Recorder,
+    scene::MorphParam,
+    scope::Scope,
+    sequence::Sequence,
+    track::{MidiDestination, Track, TrackColor},
+    transport::{MetronomeSubdivision, TransportSync},
+    tuning::Tuning,
+    Bats,
+};
+use bmidi::{Channel, ControlFunction, MidiMessage, Note, U7};
 use log::error;
 
 /// Contains commands for bats.
@@ -8,10 +31,21 @@ pub enum Command {
     None,
     /// Set the metrenome.
     SetMetronomeVolume(f32),
+    /// Set the clicks that the metronome plays between beats.
+    SetMetronomeSubdivision(MetronomeSubdivision),
     /// Set the BPM of the transport.
     SetTransportBpm(f32),
-    /// Add a new track.
-    SetPlugin { track_id: usize, plugin: AnyPlugin },
+    /// Start or stop the transport. The output is faded out before the transport stops and faded
+    /// in when it starts.
+    SetPlaying(bool),
+    /// Set if the transport loops. If `false`, the transport runs linearly.
+    SetLooping(bool),
+    /// Set the region that the transport loops within. Empty regions are ignored.
+    SetLoopRange { start: Position, end: Position },
+    /// Set the plugin for a track to the plugin in the slot. The old plugin is crossfaded with the
+    /// new one and is put into the same slot once the crossfade is complete. The undo command sets
+    /// the plugin from the slot again.
+    SetPlugin { track_id: usize, plugin: PluginSlot },
     /// Set the armed track.
     SetArmedTrack(usize),
     /// Set the track volume.
@@ -22,17 +56,255 @@ pub enum Command {
         param_id: u32,
         value: f32,
     },
+    /// Preview a sound by sending a note on and note off to the plugin of the track. The note is
+    /// not recorded. Only plugins that play their whole sound on note on, like a sliced sampler,
+    /// are audible.
+    PreviewNote { track_id: usize, note: Note },
     /// Set the sequence for the track.
     SetSequence {
         track_id: usize,
-        sequence: Vec<MidiEvent>,
+        sequence: Box<Sequence>,
     },
+    /// Freeze the track by swapping its plugin and sequence with those in `frozen`, usually
+    /// created with `FrozenTrack::render`. The original plugin and sequence are kept on the track
+    /// until it is unfrozen. Tracks that are already frozen are not changed.
+    FreezeTrack {
+        track_id: usize,
+        frozen: Box<FrozenTrack>,
+    },
+    /// Restore the original plugin and sequence of a frozen track.
+    UnfreezeTrack { track_id: usize },
+    /// Set the name of the track. An empty name clears the name.
+    SetTrackName { track_id: usize, name: Box<String> },
+    /// Set the color of the track or clear it if `color` is `None`.
+    SetTrackColor {
+        track_id: usize,
+        color: Option<TrackColor>,
+    },
+    /// Set the filter that is applied to the midi input of the track.
+    SetMidiFilter { track_id: usize, filter: MidiFilter },
+    /// Set the tuning that the plugin of the track plays notes in.
+    SetTuning {
+        track_id: usize,
+        tuning: Box<Tuning>,
+    },
+    /// Set where the sequence and midi input of the track are sent.
+    SetMidiDestination {
+        track_id: usize,
+        destination: MidiDestination,
+    },
+    /// Set the number of beats after which the sequence of the track repeats. `None` repeats the
+    /// sequence with the transport loop.
+    SetLoopLength {
+        track_id: usize,
+        length: Option<Position>,
+    },
+    /// Set the number of frames that recorded midi is moved earlier by to compensate for input
+    /// latency.
+    SetRecordLatency(u32),
+    /// Set the DSP load percentage that engages overload protection. `None` disables overload
+    /// protection.
+    SetOverloadThreshold(Option<f32>),
+    /// Set the number of semitones to transpose the midi that is sent to the armed track by.
+    SetTranspose(i8),
+    /// Set how the transport is synced with the transport of the audio backend.
+    SetTransportSync(TransportSync),
+    /// Set the param automation lanes for the track.
+    SetAutomation {
+        track_id: usize,
+        automation: Box<Vec<AutomationLane>>,
+    },
+    /// Set the LFOs that modulate plugin params for the track.
+    SetLfos {
+        track_id: usize,
+        lfos: Box<Vec<ParamLfo>>,
+    },
+    /// Set the routes from midi expression controllers to plugin params for the track.
+    SetExpressionRoutes {
+        track_id: usize,
+        routes: Box<Vec<ExpressionRoute>>,
+    },
+    /// Set the track that receives midi from the midi input `port`. If `track_id` is `None`, then
+    /// the port's midi goes to the armed track.
+    SetMidiInputRoute {
+        port: usize,
+        track_id: Option<usize>,
+    },
+    /// Mix the output of the track at `input` into the track at `track_id`, or remove the input if
+    /// `input` is `None`. Routes that would create a cycle are ignored.
+    SetTrackInput {
+        track_id: usize,
+        input: Option<usize>,
+    },
+    /// Set the compressor for the track or for the master bus if `track_id` is `None`. A
+    /// `compressor` of `None` removes the compressor.
+    SetCompressor {
+        track_id: Option<usize>,
+        compressor: Option<Box<Compressor>>,
+    },
+    /// Set a compressor parameter for the track or for the master bus if `track_id` is `None`.
+    SetCompressorParam {
+        track_id: Option<usize>,
+        param_id: u32,
+        value: f32,
+    },
+    /// Set the value of the macro knob. The targets of the knob are set at the start of the next
+    /// buffer.
+    SetMacroValue { knob: usize, value: f32 },
+    /// Set the midi CC that controls the macro knob or `None` to only control it with
+    /// `Command::SetMacroValue`.
+    SetMacroCc {
+        knob: usize,
+        cc: Option<ControlFunction>,
+    },
+    /// Set the targets of the macro knob. The new targets are set at the start of the next
+    /// buffer.
+    SetMacroMappings {
+        knob: usize,
+        mappings: Box<Vec<MacroMapping>>,
+    },
+    /// Set the params that are morphed between two scenes. The params are set at the start of the
+    /// next buffer.
+    SetMorphParams(Box<Vec<MorphParam>>),
+    /// Set the position that the scene morph moves towards. `0.0` is the first scene and `1.0` is
+    /// the second.
+    SetMorphTarget(f32),
+    /// Set the number of seconds it takes to morph from one scene to the other.
+    SetMorphSeconds(f32),
+    /// Set the level that the track sends to the aux bus.
+    SetAuxSend {
+        track_id: usize,
+        bus: usize,
+        level: f32,
+    },
+    /// Set the return volume of the aux bus.
+    SetAuxVolume { bus: usize, volume: f32 },
+    /// Set the effects of the aux bus.
+    SetAuxEffects {
+        bus: usize,
+        effects: Box<Vec<AnyEffect>>,
+    },
+    /// Set a param for the effect at index `effect` within the aux bus.
+    SetAuxEffectParam {
+        bus: usize,
+        effect: usize,
+        param_id: u32,
+        value: f32,
+    },
+    /// Set the per-track direct outputs. There should be one buffer for each track with a length of
+    /// the buffer size, or no buffers to disable direct outputs.
+    SetDirectOutputs(Box<Vec<Buffers>>),
+    /// Set the capture for the output. When starting a capture, the transport is moved to the start
+    /// of the loop. Completed captures are returned with `Notification::CaptureComplete`.
+    SetCapture(Option<Box<Capture>>),
+    /// Set the recorder that streams the master output or a track output to another thread, or
+    /// remove it with `None`. See `disk_writer::DiskWriter`.
+    SetRecorder(Option<Box<Recorder>>),
+    /// Set the scope that streams a downsampled copy of the master output to another thread, or
+    /// remove it with `None`.
+    SetScope(Option<Box<Scope>>),
+    /// Set the control surface that arms tracks and toggles the transport, or remove it with
+    /// `None`.
+    SetControlSurface(Option<Box<ControlSurface>>),
     /// Set if recording is enabled or disabled.
     SetRecord(bool),
+    /// Set the buffer size. This allocates so it should only be executed outside of the audio
+    /// processing loop, for example from JACK's buffer size callback.
+    SetBufferSize(usize),
+    /// Set the sample rate.
+    SetSampleRate(SampleRate),
+    /// Set the seed for the random number generators and reseed the plugins of all tracks. Plugins
+    /// are also reseeded when a capture starts so that exports with the same seed are identical.
+    SetSeed(u64),
+    /// Load the param values from a preset onto the track. The preset must be for the same type of
+    /// plugin as the track.
+    LoadPreset {
+        track_id: usize,
+        preset: Box<Preset>,
+    },
+    /// Copy the sequence and/or the plugin params from track `from` onto track `to`.
+    ///
+    /// The copied sequence is written into `sequence` before it replaces the sequence of `to`, so
+    /// `sequence` should have enough capacity to hold the sequence of `from` to avoid allocating.
+    /// `sequence` must be `Some` if and only if `what` contains the sequence. Plugin params are
+    /// only copied if both tracks have the same type of plugin. Use `Command::SetPlugin`
+    /// beforehand, in the same `Command::Batch`, to change the plugin of `to`.
+    ///
+    /// The commands that restore the old params and sequence of `to` are written into `undo`,
+    /// which is returned as a `Command::Batch`. `undo` should have capacity for one command per
+    /// param of the plugin plus one for the sequence to avoid allocating.
+    CopyTrack {
+        from: usize,
+        to: usize,
+        what: TrackContents,
+        sequence: Option<Box<Sequence>>,
+        undo: Box<Vec<Command>>,
+    },
+    /// Add the recent midi input of the armed track, kept even while recording is disabled, to the
+    /// sequence of the track. `sequence` should be empty with enough capacity for the captured
+    /// midi. It is filled with the captured notes and messages and sent back with
+    /// `Notification::Captured`.
+    CaptureMidiHistory {
+        track_id: usize,
+        sequence: Box<Sequence>,
+    },
+    /// Copy the state of bats into the builder and send it back with `Notification::Snapshot`. The
+    /// builder should have enough capacity for the tracks and their names to avoid allocating on
+    /// the audio thread.
+    RequestSnapshot(Box<BatsBuilder>),
+    /// Execute the commands in order within a single buffer. The undo is a batch of the undo
+    /// commands in reverse order. The undo reuses the storage of the batch so executing a batch
+    /// does not allocate.
+    Batch(Box<Vec<Command>>),
+    /// Execute `command`. The undo is the undo of `command` tagged with the same `id` so that the
+    /// sender can tell which command it belongs to. The undo reuses the storage of `command`.
+    Tagged { id: u64, command: Box<Command> },
+}
+
+/// The contents of a track that are copied by `Command::CopyTrack`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TrackContents {
+    /// The sequence.
+    Sequence,
+    /// The plugin params.
+    Plugin,
+    /// The sequence and the plugin params.
+    All,
+}
+
+impl TrackContents {
+    /// All the track contents.
+    pub const ALL: &'static [TrackContents] = &[
+        TrackContents::Sequence,
+        TrackContents::Plugin,
+        TrackContents::All,
+    ];
+
+    /// The human readable name.
+    pub fn name(self) -> &'static str {
+        match self {
+            TrackContents::Sequence => "sequence",
+            TrackContents::Plugin => "plugin",
+            TrackContents::All => "sequence and plugin",
+        }
+    }
+
+    /// Returns true if the sequence is copied.
+    pub fn has_sequence(self) -> bool {
+        matches!(self, TrackContents::Sequence | TrackContents::All)
+    }
+
+    /// Returns true if the plugin params are copied.
+    pub fn has_plugin(self) -> bool {
+        matches!(self, TrackContents::Plugin | TrackContents::All)
+    }
 }
 
 impl Command {
     /// The command to execute. It returns the command to undo the current command.
+    ///
+    /// Heap-owned data displaced by the command is always returned so that it can be dropped
+    /// outside of the audio thread.
     pub fn execute(self, b: &mut Bats) -> Command {
         match self {
             Command::None => Command::None,
@@ -41,20 +313,46 @@ impl Command {
                 b.transport.metronome_volume = v;
                 Command::SetMetronomeVolume(old)
             }
+            Command::SetMetronomeSubdivision(subdivision) => {
+                let old = b.transport.metronome_subdivision;
+                b.transport.metronome_subdivision = subdivision;
+                Command::SetMetronomeSubdivision(old)
+            }
             Command::SetTransportBpm(bpm) => {
                 let previous_bpm = b.transport.bpm();
                 b.transport.set_bpm(b.sample_rate, bpm);
                 Command::SetTransportBpm(previous_bpm)
             }
+            Command::SetPlaying(playing) => {
+                let undo = Command::SetPlaying(b.playing);
+                b.playing = playing;
+                undo
+            }
+            Command::SetLooping(looping) => {
+                let undo = Command::SetLooping(b.transport.looping());
+                b.transport.set_looping(looping);
+                undo
+            }
+            Command::SetLoopRange { start, end } => {
+                let old = b.transport.loop_range();
+                b.transport.set_loop_range(start..end);
+                Command::SetLoopRange {
+                    start: old.start,
+                    end: old.end,
+                }
+            }
             Command::SetPlugin { track_id, plugin } => match b.tracks.get_mut(track_id) {
-                None => Command::None,
+                None => {
+                    error!("track {track_id} does not exist, will not set the plugin.");
+                    Command::SetPlugin { track_id, plugin }
+                }
                 Some(t) => {
-                    let mut old_plugin = plugin;
-                    std::mem::swap(&mut t.plugin, &mut old_plugin);
-                    Command::SetPlugin {
-                        track_id,
-                        plugin: old_plugin,
+                    let crossfade_frames =
+                        (b.sample_rate.sample_rate() * Track::CROSSFADE_SECONDS) as usize;
+                    if let Err(err) = t.set_plugin(&plugin, crossfade_frames) {
+                        error!("{err}, will not set the plugin for track {track_id}.");
                     }
+                    Command::SetPlugin { track_id, plugin }
                 }
             },
             Command::SetTrackVolume { track_id, volume } => match b.tracks.get_mut(track_id) {
@@ -96,319 +394,1944 @@ impl Command {
                     Command::None
                 }
             },
+            Command::PreviewNote { track_id, note } => {
+                match b.tracks.get_mut(track_id) {
+                    Some(t) => {
+                        let p = t.plugin.plugin_mut();
+                        p.handle_midi(&MidiMessage::NoteOn(Channel::Ch1, note, U7::MAX));
+                        p.handle_midi(&MidiMessage::NoteOff(Channel::Ch1, note, U7::MIN));
+                    }
+                    None => error!("track {track_id} does not exist, will not preview {note:?}."),
+                }
+                Command::None
+            }
             Command::SetSequence {
                 track_id,
                 mut sequence,
             } => match b.tracks.get_mut(track_id) {
                 Some(t) => {
-                    std::mem::swap(&mut sequence, &mut t.sequence);
+                    std::mem::swap(sequence.as_mut(), &mut t.sequence);
                     Command::SetSequence { track_id, sequence }
                 }
                 None => {
                     error!("track {track_id} does not exist, will not clear the sequence.");
+                    Command::SetSequence { track_id, sequence }
+                }
+            },
+            Command::FreezeTrack {
+                track_id,
+                mut frozen,
+            } => match b.tracks.get_mut(track_id) {
+                Some(t) if t.frozen.is_none() => {
+                    std::mem::swap(&mut frozen.plugin, &mut t.plugin);
+                    std::mem::swap(&mut frozen.sequence, &mut t.sequence);
+                    t.frozen = Some(frozen);
+                    Command::UnfreezeTrack { track_id }
+                }
+                Some(_) => {
+                    error!("track {track_id} is already frozen, will not freeze it.");
+                    Command::FreezeTrack { track_id, frozen }
+                }
+                None => {
+                    error!("track {track_id} does not exist, will not freeze it.");
+                    Command::FreezeTrack { track_id, frozen }
+                }
+            },
+            Command::UnfreezeTrack { track_id } => {
+                let Some(t) = b.tracks.get_mut(track_id) else {
+                    error!("track {track_id} does not exist, will not unfreeze it.");
+                    return Command::None;
+                };
+                match t.frozen.take() {
+                    Some(mut frozen) => {
+                        std::mem::swap(&mut frozen.plugin, &mut t.plugin);
+                        std::mem::swap(&mut frozen.sequence, &mut t.sequence);
+                        // The tuning may have changed while the track was frozen.
+                        t.plugin.plugin_mut().set_tuning(&t.tuning);
+                        Command::FreezeTrack { track_id, frozen }
+                    }
+                    None => Command::None,
+                }
+            }
+            Command::SetTrackName { track_id, mut name } => match b.tracks.get_mut(track_id) {
+                Some(t) => {
+                    std::mem::swap(name.as_mut(), &mut t.name);
+                    Command::SetTrackName { track_id, name }
+                }
+                None => {
+                    error!("track {track_id} does not exist, will not set the name.");
+                    Command::SetTrackName { track_id, name }
+                }
+            },
+            Command::SetTrackColor { track_id, color } => match b.tracks.get_mut(track_id) {
+                Some(t) => {
+                    let old = std::mem::replace(&mut t.color, color);
+                    Command::SetTrackColor {
+                        track_id,
+                        color: old,
+                    }
+                }
+                None => {
+                    error!("track {track_id} does not exist, will not set the color.");
+                    Command::None
+                }
+            },
+            Command::SetMidiFilter { track_id, filter } => match b.tracks.get_mut(track_id) {
+                Some(t) => Command::SetMidiFilter {
+                    track_id,
+                    filter: std::mem::replace(&mut t.midi_filter, filter),
+                },
+                None => {
+                    error!("track {track_id} does not exist, will not set the midi filter.");
+                    Command::None
+                }
+            },
+            Command::SetTuning { track_id, tuning } => match b.tracks.get_mut(track_id) {
+                Some(t) => Command::SetTuning {
+                    track_id,
+                    tuning: t.set_tuning(tuning),
+                },
+                None => {
+                    error!("track {track_id} does not exist, will not set the tuning.");
+                    Command::None
+                }
+            },
+            Command::SetMidiDestination {
+                track_id,
+                destination,
+            } => match b.tracks.get_mut(track_id) {
+                Some(t) => Command::SetMidiDestination {
+                    track_id,
+                    destination: std::mem::replace(&mut t.midi_destination, destination),
+                },
+                None => {
+                    error!("track {track_id} does not exist, will not set the midi destination.");
+                    Command::None
+                }
+            },
+            Command::SetLoopLength { track_id, length } => match b.tracks.get_mut(track_id) {
+                Some(t) => Command::SetLoopLength {
+                    track_id,
+                    length: std::mem::replace(&mut t.loop_length, length),
+                },
+                None => {
+                    error!("track {track_id} does not exist, will not set the loop length.");
+                    Command::None
+                }
+            },
+            Command::SetRecordLatency(frames) => {
+                Command::SetRecordLatency(std::mem::replace(&mut b.record_latency, frames))
+            }
+            Command::SetOverloadThreshold(threshold) => Command::SetOverloadThreshold(
+                std::mem::replace(&mut b.overload.threshold, threshold),
+            ),
+            Command::SetTranspose(semitones) => {
+                Command::SetTranspose(std::mem::replace(&mut b.transpose.semitones, semitones))
+            }
+            Command::SetTransportSync(sync) => {
+                Command::SetTransportSync(std::mem::replace(&mut b.transport_sync, sync))
+            }
+            Command::SetAutomation {
+                track_id,
+                mut automation,
+            } => match b.tracks.get_mut(track_id) {
+                Some(t) => {
+                    std::mem::swap(automation.as_mut(), &mut t.automation);
+                    Command::SetAutomation {
+                        track_id,
+                        automation,
+                    }
+                }
+                None => {
+                    error!("track {track_id} does not exist, will not set automation.");
+                    Command::SetAutomation {
+                        track_id,
+                        automation,
+                    }
+                }
+            },
+            Command::SetLfos { track_id, mut lfos } => match b.tracks.get_mut(track_id) {
+                Some(t) => {
+                    std::mem::swap(lfos.as_mut(), &mut t.lfos);
+                    Command::SetLfos { track_id, lfos }
+                }
+                None => {
+                    error!("track {track_id} does not exist, will not set LFOs.");
+                    Command::SetLfos { track_id, lfos }
+                }
+            },
+            Command::SetExpressionRoutes {
+                track_id,
+                mut routes,
+            } => match b.tracks.get_mut(track_id) {
+                Some(t) => {
+                    std::mem::swap(routes.as_mut(), &mut t.expression_routes);
+                    Command::SetExpressionRoutes { track_id, routes }
+                }
+                None => {
+                    error!("track {track_id} does not exist, will not set expression routes.");
+                    Command::SetExpressionRoutes { track_id, routes }
+                }
+            },
+            Command::SetMidiInputRoute { port, track_id } => {
+                match b.midi_input_routes.get_mut(port) {
+                    Some(route) => {
+                        let undo = Command::SetMidiInputRoute {
+                            port,
+                            track_id: *route,
+                        };
+                        *route = track_id;
+                        undo
+                    }
+                    None => {
+                        error!("midi input port {port} does not exist, will not set route.");
+                        Command::None
+                    }
+                }
+            }
+            Command::SetTrackInput { track_id, input } => {
+                match b.set_track_input(track_id, input) {
+                    Ok(previous) => Command::SetTrackInput {
+                        track_id,
+                        input: previous,
+                    },
+                    Err(err) => {
+                        error!("{err}, will not set the input of track {track_id}.");
+                        Command::None
+                    }
+                }
+            }
+            Command::SetCompressor {
+                track_id,
+                compressor,
+            } => match b.compressor_mut(track_id) {
+                Some(slot) => Command::SetCompressor {
+                    track_id,
+                    compressor: std::mem::replace(slot, compressor),
+                },
+                None => {
+                    error!("track {track_id:?} does not exist, will not set compressor.");
+                    Command::SetCompressor {
+                        track_id,
+                        compressor,
+                    }
+                }
+            },
+            Command::SetCompressorParam {
+                track_id,
+                param_id,
+                value,
+            } => match b.compressor_mut(track_id).and_then(|c| c.as_mut()) {
+                Some(c) => {
+                    let undo = Command::SetCompressorParam {
+                        track_id,
+                        param_id,
+                        value: c.param(param_id),
+                    };
+                    c.set_param(param_id, value);
+                    undo
+                }
+                None => {
+                    error!(
+                        "track {track_id:?} has no compressor, will not set param {param_id} to {value}."
+                    );
+                    Command::None
+                }
+            },
+            Command::SetMacroValue { knob, value } => match b.macros.get_mut(knob) {
+                Some(k) => {
+                    let undo = Command::SetMacroValue {
+                        knob,
+                        value: k.value,
+                    };
+                    k.value = value;
+                    undo
+                }
+                None => {
+                    error!("macro knob {knob} does not exist, will not set value.");
+                    Command::None
+                }
+            },
+            Command::SetMacroCc { knob, cc } => match b.macros.get_mut(knob) {
+                Some(k) => Command::SetMacroCc {
+                    knob,
+                    cc: std::mem::replace(&mut k.cc, cc),
+                },
+                None => {
+                    error!("macro knob {knob} does not exist, will not set cc.");
+                    Command::None
+                }
+            },
+            Command::SetMacroMappings { knob, mut mappings } => match b.macros.get_mut(knob) {
+                Some(k) => {
+                    std::mem::swap(mappings.as_mut(), &mut k.mappings);
+                    k.applied = None;
+                    Command::SetMacroMappings { knob, mappings }
+                }
+                None => {
+                    error!("macro knob {knob} does not exist, will not set mappings.");
+                    Command::SetMacroMappings { knob, mappings }
+                }
+            },
+            Command::SetMorphParams(mut params) => {
+                std::mem::swap(params.as_mut(), &mut b.morph.params);
+                b.morph.applied = None;
+                Command::SetMorphParams(params)
+            }
+            Command::SetMorphTarget(target) => {
+                Command::SetMorphTarget(std::mem::replace(&mut b.morph.target, target))
+            }
+            Command::SetMorphSeconds(seconds) => {
+                Command::SetMorphSeconds(std::mem::replace(&mut b.morph.seconds, seconds))
+            }
+            Command::SetAuxSend {
+                track_id,
+                bus,
+                level,
+            } => match b
+                .tracks
+                .get_mut(track_id)
+                .and_then(|t| t.sends.get_mut(bus))
+            {
+                Some(send) => {
+                    let undo = Command::SetAuxSend {
+                        track_id,
+                        bus,
+                        level: *send,
+                    };
+                    *send = level;
+                    undo
+                }
+                None => {
+                    error!("track {track_id} or aux bus {bus} does not exist, will not set send.");
+                    Command::None
+                }
+            },
+            Command::SetAuxVolume { bus, volume } => match b.aux_buses.get_mut(bus) {
+                Some(a) => {
+                    let undo = Command::SetAuxVolume {
+                        bus,
+                        volume: a.volume,
+                    };
+                    a.volume = volume;
+                    undo
+                }
+                None => {
+                    error!("aux bus {bus} does not exist, will not set volume.");
+                    Command::None
+                }
+            },
+            Command::SetAuxEffects { bus, mut effects } => match b.aux_buses.get_mut(bus) {
+                Some(a) => {
+                    std::mem::swap(effects.as_mut(), &mut a.effects);
+                    Command::SetAuxEffects { bus, effects }
+                }
+                None => {
+                    error!("aux bus {bus} does not exist, will not set effects.");
+                    Command::SetAuxEffects { bus, effects }
+                }
+            },
+            Command::SetAuxEffectParam {
+                bus,
+                effect,
+                param_id,
+                value,
+            } => match b
+                .aux_buses
+                .get_mut(bus)
+                .and_then(|a| a.effects.get_mut(effect))
+            {
+                Some(e) => {
+                    let e = e.effect_mut();
+                    let undo = Command::SetAuxEffectParam {
+                        bus,
+                        effect,
+                        param_id,
+                        value: e.param(param_id),
+                    };
+                    e.set_param(param_id, value);
+                    undo
+                }
+                None => {
+                    error!(
+                        "aux bus {bus} has no effect {effect}, will not set param {param_id} to {value}."
+                    );
                     Command::None
                 }
             },
+            Command::SetDirectOutputs(mut outputs) => {
+                std::mem::swap(outputs.as_mut(), &mut b.direct_outputs);
+                Command::SetDirectOutputs(outputs)
+            }
+            Command::SetCapture(capture) => {
+                if capture.is_some() {
+                    b.transport.set_position(b.transport.loop_range().start);
+                    b.set_seed(b.seed);
+                }
+                Command::SetCapture(std::mem::replace(&mut b.capture, capture))
+            }
+            Command::SetRecorder(recorder) => {
+                Command::SetRecorder(std::mem::replace(&mut b.recorder, recorder))
+            }
+            Command::SetScope(scope) => Command::SetScope(std::mem::replace(&mut b.scope, scope)),
+            Command::SetControlSurface(surface) => {
+                Command::SetControlSurface(std::mem::replace(&mut b.control_surface, surface))
+            }
             Command::SetRecord(enabled) => {
                 let undo = Command::SetRecord(b.recording_enabled);
                 b.recording_enabled = enabled;
                 undo
             }
+            Command::SetBufferSize(buffer_size) => {
+                let undo = Command::SetBufferSize(b.buffer_size);
+                b.set_buffer_size(buffer_size);
+                undo
+            }
+            Command::SetSeed(seed) => {
+                let undo = Command::SetSeed(b.seed);
+                b.set_seed(seed);
+                undo
+            }
+            Command::SetSampleRate(sample_rate) => {
+                let undo = Command::SetSampleRate(b.sample_rate);
+                b.set_sample_rate(sample_rate);
+                undo
+            }
+            Command::LoadPreset {
+                track_id,
+                mut preset,
+            } => match b.tracks.get_mut(track_id) {
+                Some(t) => {
+                    if let Err(err) = preset.swap_with_plugin(&mut t.plugin) {
+                        error!("Failed to load preset on track {track_id}: {err}");
+                    }
+                    Command::LoadPreset { track_id, preset }
+                }
+                None => {
+                    error!("track {track_id} does not exist, will not load preset.");
+                    Command::LoadPreset { track_id, preset }
+                }
+            },
+            Command::CopyTrack {
+                from,
+                to,
+                what,
+                sequence,
+                mut undo,
+            } => {
+                if from >= b.tracks.len() || to >= b.tracks.len() {
+                    error!("track {from} or {to} does not exist, will not copy track.");
+                    return Command::CopyTrack {
+                        from,
+                        to,
+                        what,
+                        sequence,
+                        undo,
+                    };
+                }
+                if what.has_sequence() != sequence.is_some() {
+                    error!("the sequence does not match what is copied, will not copy track.");
+                    return Command::CopyTrack {
+                        from,
+                        to,
+                        what,
+                        sequence,
+                        undo,
+                    };
+                }
+                undo.clear();
+                if what.has_plugin() {
+                    copy_params(b, from, to, &mut undo);
+                }
+                if let Some(mut sequence) = sequence {
+                    sequence.as_mut().clone_from(&b.tracks[from].sequence);
+                    std::mem::swap(sequence.as_mut(), &mut b.tracks[to].sequence);
+                    undo.push(Command::SetSequence {
+                        track_id: to,
+                        sequence,
+                    });
+                }
+                Command::Batch(undo)
+            }
+            Command::CaptureMidiHistory {
+                track_id,
+                mut sequence,
+            } => {
+                if b.captured.is_some() {
+                    error!("A capture is already pending, dropping the request.");
+                    return Command::CaptureMidiHistory { track_id, sequence };
+                }
+                if !b.capture_midi_history(track_id, &mut sequence) {
+                    error!("track {track_id} does not exist, will not capture the midi history.");
+                    return Command::CaptureMidiHistory { track_id, sequence };
+                }
+                b.captured = Some((track_id, sequence));
+                Command::None
+            }
+            Command::RequestSnapshot(mut snapshot) => {
+                if b.snapshot.is_some() {
+                    error!("A snapshot is already pending, dropping the request.");
+                    return Command::RequestSnapshot(snapshot);
+                }
+                snapshot.copy_from_bats(b);
+                b.snapshot = Some(snapshot);
+                Command::None
+            }
+            Command::Batch(mut commands) => {
+                for cmd in commands.iter_mut() {
+                    *cmd = std::mem::replace(cmd, Command::None).execute(b);
+                }
+                commands.reverse();
+                Command::Batch(commands)
+            }
+            Command::Tagged { id, mut command } => {
+                *command = std::mem::replace(command.as_mut(), Command::None).execute(b);
+                Command::Tagged { id, command }
+            }
+        }
+    }
+}
+
+/// Copy the plugin param values from track `from` to track `to` and push the commands that
+/// restore the old values onto `undo`. Nothing is copied if the tracks have different types of
+/// plugins.
+fn copy_params(b: &mut Bats, from: usize, to: usize, undo: &mut Vec<Command>) {
+    let metadata = b.tracks[from].plugin.plugin().metadata();
+    if metadata.name != b.tracks[to].plugin.plugin().metadata().name {
+        error!("tracks {from} and {to} have different plugins, will not copy params.");
+        return;
+    }
+    for param in metadata.params {
+        let value = b.tracks[from].plugin.plugin().param(param.id);
+        let plugin = b.tracks[to].plugin.plugin_mut();
+        undo.push(Command::SetParam {
+            track_id: to,
+            param_id: param.id,
+            value: plugin.param(param.id),
+        });
+        plugin.set_param(param.id, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bats_lib::{
+        builder::{AnyPlugin, BatsBuilder},
+        expression::ExpressionSource,
+        graph::Node,
+        lfo::LfoWaveform,
+        plugin::{
+            delay::Delay, empty::Empty, sampler::Sampler, toof::Toof, BatsInstrumentExt, MidiEvent,
+        },
+        recorder::RecordSource,
+        scene::SceneMorph,
+        track::ReplacedPlugin,
+    };
+
+    use super::*;
+
+    /// Get all the track name for non-empty tracks.
+    fn get_track_names(b: &Bats) -> Vec<&'static str> {
+        b.tracks
+            .iter()
+            .map(|t| t.plugin.plugin().metadata().name)
+            .collect()
+    }
+
+    #[test]
+    fn command_size_is_reasonable() {
+        // `CopyTrack` holds the storage for both the copied sequence and its undo commands.
+        let size = std::mem::size_of::<Command>();
+        assert_eq!(size, 40);
+    }
+
+    #[test]
+    fn none_command_undo_is_none() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let undo = Command::None.execute(&mut b);
+        assert_eq!(undo, Command::None);
+    }
+
+    #[test]
+    fn set_metronome_volume_sets_new_volume_and_returns_old_as_undo() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.transport.metronome_volume = 1.0;
+
+        let undo = Command::SetMetronomeVolume(0.5).execute(&mut b);
+        assert_eq!(b.transport.metronome_volume, 0.5);
+        assert_eq!(undo, Command::SetMetronomeVolume(1.0));
+    }
+
+    #[test]
+    fn set_metronome_subdivision_returns_old_as_undo() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let subdivision = MetronomeSubdivision {
+            clicks_per_beat: 4,
+            swing: 0.6,
+        };
+
+        let undo = Command::SetMetronomeSubdivision(subdivision).execute(&mut b);
+        assert_eq!(b.transport.metronome_subdivision, subdivision);
+        assert_eq!(
+            undo,
+            Command::SetMetronomeSubdivision(MetronomeSubdivision::default())
+        );
+    }
+
+    #[test]
+    fn metrenome_set_bpm() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.transport.set_bpm(b.sample_rate, 100.0);
+
+        let undo = Command::SetTransportBpm(90.0).execute(&mut b);
+        assert_eq!(b.transport.bpm(), 90.0);
+        assert_eq!(undo, Command::SetTransportBpm(100.0));
+    }
+
+    #[test]
+    fn set_seed_reseeds_plugins_and_returns_old_seed_as_undo() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let mut toof = Toof::new(b.sample_rate);
+        toof.set_param_by_name("noise level", 1.0).unwrap();
+        b.tracks[0].plugin = AnyPlugin::Toof(toof);
+        b.set_seed(b.seed);
+        let old_seed = b.seed;
+        let note_on = [(0, MidiMessage::NoteOn(Channel::Ch1, Note::A4, U7::MAX))];
+        let render = |b: &Bats| b.clone().process_to_buffer(64, &note_on);
+        let before = render(&b);
+
+        let undo = Command::SetSeed(1234).execute(&mut b);
+        assert_eq!(undo, Command::SetSeed(old_seed));
+        assert_eq!(b.seed, 1234);
+        assert_ne!(render(&b), before);
+
+        undo.execute(&mut b);
+        assert_eq!(render(&b), before);
+    }
+
+    #[test]
+    fn preview_note_plays_sampler_slice() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let buffers = Buffers::with_iter((0..8).map(|n| (n as f32, n as f32)));
+        b.tracks[1].plugin = AnyPlugin::Sampler(Sampler::sliced(buffers, vec![0, 4]));
+        let undo = Command::PreviewNote {
+            track_id: 1,
+            note: Sampler::slice_note(1),
+        }
+        .execute(&mut b);
+        assert_eq!(undo, Command::None);
+        let p = b.tracks[1].plugin.plugin_mut();
+        let out: Vec<f32> = (0..6).map(|_| p.process().0).collect();
+        assert_eq!(out, vec![4.0, 5.0, 6.0, 7.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn batch_executes_in_order_and_undoes_in_reverse() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.tracks[0].volume = 1.0;
+        let before = b.clone();
+
+        let undo = Command::Batch(Box::new(vec![
+            Command::SetTransportBpm(90.0),
+            Command::SetTrackVolume {
+                track_id: 0,
+                volume: 0.5,
+            },
+            Command::SetTransportBpm(100.0),
+        ]))
+        .execute(&mut b);
+        assert_eq!(b.transport.bpm(), 100.0);
+        assert_eq!(b.tracks[0].volume, 0.5);
+        assert_eq!(
+            undo,
+            Command::Batch(Box::new(vec![
+                Command::SetTransportBpm(90.0),
+                Command::SetTrackVolume {
+                    track_id: 0,
+                    volume: 1.0,
+                },
+                Command::SetTransportBpm(120.0),
+            ]))
+        );
+
+        undo.execute(&mut b);
+        assert_eq!(b, before);
+    }
+
+    #[test]
+    fn tagged_undo_keeps_the_tag() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let undo = Command::Tagged {
+            id: 7,
+            command: Box::new(Command::SetTransportBpm(90.0)),
+        }
+        .execute(&mut b);
+        assert_eq!(b.transport.bpm(), 90.0);
+        assert_eq!(
+            undo,
+            Command::Tagged {
+                id: 7,
+                command: Box::new(Command::SetTransportBpm(120.0)),
+            }
+        );
+    }
+
+    #[test]
+    fn set_recorder_returns_previous_recorder() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let (recorder, _reader) = Recorder::new(RecordSource::Track(2), 64);
+        let recorder = Box::new(recorder);
+
+        let undo = Command::SetRecorder(Some(recorder.clone())).execute(&mut b);
+        assert_eq!(undo, Command::SetRecorder(None));
+        assert_eq!(b.recorder.as_ref(), Some(&recorder));
+
+        let undo = Command::SetRecorder(None).execute(&mut b);
+        assert_eq!(undo, Command::SetRecorder(Some(recorder)));
+        assert_eq!(b.recorder, None);
+    }
+
+    #[test]
+    fn set_playing_looping_and_loop_range() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let default_range = b.transport.loop_range();
+
+        let undo = Command::SetPlaying(false).execute(&mut b);
+        assert!(!b.playing);
+        assert_eq!(undo, Command::SetPlaying(true));
+
+        let undo = Command::SetLooping(false).execute(&mut b);
+        assert!(!b.transport.looping());
+        assert_eq!(undo, Command::SetLooping(true));
+
+        let undo = Command::SetLoopRange {
+            start: Position::new(4.0),
+            end: Position::new(8.0),
+        }
+        .execute(&mut b);
+        assert_eq!(
+            b.transport.loop_range(),
+            Position::new(4.0)..Position::new(8.0)
+        );
+        assert_eq!(
+            undo,
+            Command::SetLoopRange {
+                start: default_range.start,
+                end: default_range.end,
+            }
+        );
+    }
+
+    #[test]
+    fn set_plugin() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let plugin = AnyPlugin::Toof(Toof::new(b.sample_rate));
+        b.tracks[0].plugin = plugin.clone();
+        assert_eq!(
+            get_track_names(&b),
+            vec!["toof", "empty", "empty", "empty", "empty", "empty", "empty", "empty"]
+        );
+
+        let slot = PluginSlot::new(plugin.clone());
+        let cmd = Command::SetPlugin {
+            track_id: 1,
+            plugin: slot.clone(),
+        };
+        let undo = cmd.clone().execute(&mut b);
+        assert_eq!(
+            get_track_names(&b),
+            vec!["toof", "toof", "empty", "empty", "empty", "empty", "empty", "empty"]
+        );
+        assert_eq!(undo, cmd);
+        assert_eq!(
+            b.tracks[1].fading_plugin,
+            Some(ReplacedPlugin {
+                plugin: AnyPlugin::Empty(Empty),
+                slot: slot.clone()
+            })
+        );
+
+        let other = PluginSlot::new(AnyPlugin::Empty(Empty));
+        Command::SetPlugin {
+            track_id: 1,
+            plugin: other.clone(),
+        }
+        .execute(&mut b);
+        assert_eq!(
+            get_track_names(&b),
+            vec!["toof", "empty", "empty", "empty", "empty", "empty", "empty", "empty"]
+        );
+        assert_eq!(
+            b.tracks[1].fading_plugin,
+            Some(ReplacedPlugin {
+                plugin,
+                slot: other
+            })
+        );
+        // The plugin that was still fading out is retired immediately.
+        assert_eq!(
+            b.tracks[1].retired_plugins.as_slice(),
+            &[ReplacedPlugin {
+                plugin: AnyPlugin::Empty(Empty),
+                slot
+            }]
+        );
+    }
+
+    #[test]
+    fn set_plugin_undo_restores_the_old_plugin() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let plugin = AnyPlugin::Toof(Toof::new(b.sample_rate));
+        let undo = Command::SetPlugin {
+            track_id: 1,
+            plugin: PluginSlot::new(plugin.clone()),
+        }
+        .execute(&mut b);
+        let redo = undo.execute(&mut b);
+        assert_eq!(b.tracks[1].plugin, AnyPlugin::Empty(Empty));
+        redo.execute(&mut b);
+        assert_eq!(b.tracks[1].plugin, plugin);
+    }
+
+    #[test]
+    fn set_plugin_from_empty_slot_does_nothing() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let plugin = AnyPlugin::Toof(Toof::new(b.sample_rate));
+        b.tracks[1].plugin = plugin.clone();
+        let cmd = Command::SetPlugin {
+            track_id: 1,
+            plugin: PluginSlot::default(),
+        };
+        assert_eq!(cmd.clone().execute(&mut b), cmd);
+        assert_eq!(b.tracks[1].plugin, plugin);
+        assert_eq!(b.tracks[1].fading_plugin, None);
+    }
+
+    #[test]
+    fn set_plugin_on_track_that_does_not_exist_returns_plugin() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let plugin = AnyPlugin::Toof(Toof::new(b.sample_rate));
+        let slot = PluginSlot::new(plugin.clone());
+        let cmd = Command::SetPlugin {
+            track_id: 100,
+            plugin: slot.clone(),
+        };
+        assert_eq!(cmd.clone().execute(&mut b), cmd);
+        assert_eq!(slot.try_take(), Some(plugin));
+    }
+
+    #[test]
+    fn set_armed_track() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.armed_track = 100;
+
+        let undo = Command::SetArmedTrack(10).execute(&mut b);
+        assert_eq!(b.armed_track, 10);
+        assert_eq!(undo, Command::SetArmedTrack(100));
+
+        let undo = Command::SetArmedTrack(20).execute(&mut b);
+        assert_eq!(b.armed_track, 20);
+        assert_eq!(undo, Command::SetArmedTrack(10));
+
+        let undo = Command::SetArmedTrack(100).execute(&mut b);
+        assert_eq!(b.armed_track, 100);
+        assert_eq!(undo, Command::SetArmedTrack(20));
+    }
+
+    #[test]
+    fn set_track_volume_sets_volume() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.tracks[0].volume = 0.1;
+        b.tracks[1].volume = 0.2;
+        let undo = Command::SetTrackVolume {
+            track_id: 0,
+            volume: 0.3,
+        }
+        .execute(&mut b);
+        assert_eq!(
+            undo,
+            Command::SetTrackVolume {
+                track_id: 0,
+                volume: 0.1
+            }
+        );
+        assert_eq!(b.tracks[0].volume, 0.3);
+        assert_eq!(b.tracks[1].volume, 0.2);
+    }
+
+    #[test]
+    fn set_track_volume_on_track_that_does_not_exist_does_nothing() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let undo = Command::SetTrackVolume {
+            track_id: 1000, // Out of range.
+            volume: 0.3,
+        }
+        .execute(&mut b);
+        assert_eq!(undo, Command::None);
+    }
+
+    #[test]
+    fn set_sequence_sets_sequence_on_track() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.tracks[4].sequence = Sequence::from(vec![MidiEvent {
+            position: Position::new(0.0),
+            midi: MidiMessage::TuneRequest,
+        }]);
+        let undo = Command::SetSequence {
+            track_id: 4,
+            sequence: Box::new(Sequence::from(vec![MidiEvent {
+                position: Position::new(1.2),
+                midi: MidiMessage::Reset,
+            }])),
+        }
+        .execute(&mut b);
+        assert_eq!(
+            undo,
+            Command::SetSequence {
+                track_id: 4,
+                sequence: Box::new(Sequence::from(vec![MidiEvent {
+                    position: Position::new(0.0),
+                    midi: MidiMessage::TuneRequest,
+                }])),
+            }
+        );
+        assert_eq!(
+            b.tracks[4].sequence.events(),
+            &[MidiEvent {
+                position: Position::new(1.2),
+                midi: MidiMessage::Reset
+            }]
+        );
+    }
+
+    #[test]
+    fn freeze_track_swaps_in_sampler_and_unfreeze_restores_original() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.tracks[2].plugin = AnyPlugin::Toof(Toof::new(b.sample_rate));
+        b.tracks[2].sequence = Sequence::from(vec![MidiEvent {
+            position: Position::new(0.5),
+            midi: MidiMessage::NoteOn(Channel::Ch1, Note::A4, U7::MAX),
+        }]);
+        let original = b.tracks[2].clone();
+        let frozen = FrozenTrack::render(
+            original.plugin.clone(),
+            &original.sequence,
+            b.sample_rate,
+            120.0,
+            Position::MIN..Position::new(1.0),
+        );
+
+        let undo = Command::FreezeTrack {
+            track_id: 2,
+            frozen: Box::new(frozen.clone()),
+        }
+        .execute(&mut b);
+        assert_eq!(undo, Command::UnfreezeTrack { track_id: 2 });
+        assert_eq!(b.tracks[2].plugin, frozen.plugin);
+        assert_eq!(b.tracks[2].sequence, frozen.sequence);
+        assert_eq!(b.tracks[2].unfrozen_plugin(), &original.plugin);
+
+        let redo = undo.execute(&mut b);
+        assert_eq!(
+            redo,
+            Command::FreezeTrack {
+                track_id: 2,
+                frozen: Box::new(frozen),
+            }
+        );
+        assert_eq!(b.tracks[2], original);
+        assert_eq!(
+            Command::UnfreezeTrack { track_id: 2 }.execute(&mut b),
+            Command::None
+        );
+    }
+
+    #[test]
+    fn freeze_frozen_track_returns_command() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let frozen = Box::new(FrozenTrack {
+            plugin: AnyPlugin::Empty(Empty),
+            sequence: Sequence::new(),
+        });
+        let freeze = Command::FreezeTrack {
+            track_id: 0,
+            frozen: frozen.clone(),
+        };
+        freeze.clone().execute(&mut b);
+        assert_eq!(freeze.clone().execute(&mut b), freeze);
+        let missing = Command::FreezeTrack {
+            track_id: 100,
+            frozen,
+        };
+        assert_eq!(missing.clone().execute(&mut b), missing);
+    }
+
+    #[test]
+    fn set_track_name_and_color() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let undo = Command::SetTrackName {
+            track_id: 3,
+            name: Box::new("bass".to_string()),
+        }
+        .execute(&mut b);
+        assert_eq!(b.tracks[3].name, "bass");
+        assert_eq!(
+            undo,
+            Command::SetTrackName {
+                track_id: 3,
+                name: Box::default(),
+            }
+        );
+        let undo = Command::SetTrackColor {
+            track_id: 3,
+            color: Some(TrackColor::Cyan),
+        }
+        .execute(&mut b);
+        assert_eq!(b.tracks[3].color, Some(TrackColor::Cyan));
+        assert_eq!(
+            undo,
+            Command::SetTrackColor {
+                track_id: 3,
+                color: None,
+            }
+        );
+    }
+
+    #[test]
+    fn set_midi_destination_sends_midi_to_midi_out() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.armed_track = 0;
+        let undo = Command::SetMidiDestination {
+            track_id: 0,
+            destination: MidiDestination::MidiOut,
+        }
+        .execute(&mut b);
+        assert_eq!(
+            undo,
+            Command::SetMidiDestination {
+                track_id: 0,
+                destination: MidiDestination::Plugin,
+            }
+        );
+        let note_on = MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX);
+        let out = b.process_to_buffer(64, &[(3, note_on)]);
+        assert!(out.is_zero());
+        assert_eq!(b.tracks[0].midi_out.as_slice(), &[(3, note_on)]);
+    }
+
+    #[test]
+    fn set_loop_length_returns_previous_length() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let length = Some(Position::new(4.0));
+        let undo = Command::SetLoopLength {
+            track_id: 1,
+            length,
+        }
+        .execute(&mut b);
+        assert_eq!(
+            undo,
+            Command::SetLoopLength {
+                track_id: 1,
+                length: None,
+            }
+        );
+        assert_eq!(b.tracks[1].loop_length, length);
+    }
+
+    #[test]
+    fn set_midi_filter_filters_midi_input() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.armed_track = 0;
+        let filter = MidiFilter {
+            high_note: Note::B3,
+            ..MidiFilter::default()
+        };
+        let undo = Command::SetMidiFilter {
+            track_id: 0,
+            filter,
+        }
+        .execute(&mut b);
+        assert_eq!(b.tracks[0].midi_filter, filter);
+        assert_eq!(
+            undo,
+            Command::SetMidiFilter {
+                track_id: 0,
+                filter: MidiFilter::default(),
+            }
+        );
+        let out = b.process_to_buffer(
+            64,
+            &[(0, MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX))],
+        );
+        assert!(out.is_zero());
+    }
+
+    #[test]
+    fn set_tuning_returns_old_tuning_as_undo() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let scl = "quarter tones\n24\n50.0\n100.0\n150.0\n200.0\n250.0\n300.0\n350.0\n400.0\n\
+                   450.0\n500.0\n550.0\n600.0\n650.0\n700.0\n750.0\n800.0\n850.0\n900.0\n\
+                   950.0\n1000.0\n1050.0\n1100.0\n1150.0\n2/1\n";
+        let tuning = Tuning::from_scala("quarter tones", scl, None).unwrap();
+        let undo = Command::SetTuning {
+            track_id: 0,
+            tuning: Box::new(tuning),
+        }
+        .execute(&mut b);
+        assert_eq!(*b.tracks[0].tuning, tuning);
+        assert_eq!(
+            undo,
+            Command::SetTuning {
+                track_id: 0,
+                tuning: Box::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn set_transpose_returns_old_transpose_as_undo() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let undo = Command::SetTranspose(-12).execute(&mut b);
+        assert_eq!(b.transpose.semitones, -12);
+        assert_eq!(undo, Command::SetTranspose(0));
+    }
+
+    #[test]
+    fn set_transport_sync_returns_old_sync_as_undo() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let sync = TransportSync {
+            follow: true,
+            timebase_master: true,
+        };
+        let undo = Command::SetTransportSync(sync).execute(&mut b);
+        assert_eq!(b.transport_sync, sync);
+        assert_eq!(undo, Command::SetTransportSync(TransportSync::default()));
+    }
+
+    #[test]
+    fn set_record_latency_returns_old_latency_as_undo() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let undo = Command::SetRecordLatency(256).execute(&mut b);
+        assert_eq!(b.record_latency, 256);
+        assert_eq!(undo, Command::SetRecordLatency(0));
+    }
+
+    #[test]
+    fn set_overload_threshold_returns_previous_threshold() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let undo = Command::SetOverloadThreshold(Some(80.0)).execute(&mut b);
+        assert_eq!(b.overload.threshold, Some(80.0));
+        assert_eq!(undo, Command::SetOverloadThreshold(None));
+    }
+
+    #[test]
+    fn copy_track_copies_sequence_without_allocating() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let sequence = Sequence::from(vec![MidiEvent {
+            position: Position::new(1.0),
+            midi: MidiMessage::Reset,
+        }]);
+        b.tracks[1].sequence = sequence.clone();
+        let old_sequence = b.tracks[2].sequence.clone();
+        let storage = Box::new(Sequence::with_capacity(Track::SEQUENCE_CAPACITY));
+        let capacity = storage.capacity();
+        let undo = Command::CopyTrack {
+            from: 1,
+            to: 2,
+            what: TrackContents::Sequence,
+            sequence: Some(storage),
+            undo: Box::new(Vec::with_capacity(1)),
+        }
+        .execute(&mut b);
+        assert_eq!(b.tracks[2].sequence, sequence);
+        assert_eq!(b.tracks[2].sequence.capacity(), capacity);
+        assert_eq!(
+            undo,
+            Command::Batch(Box::new(vec![Command::SetSequence {
+                track_id: 2,
+                sequence: Box::new(old_sequence),
+            }]))
+        );
+    }
+
+    #[test]
+    fn copy_track_copies_params_of_same_plugin() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.tracks[0].plugin = Toof::new(b.sample_rate).into();
+        b.tracks[0].plugin.plugin_mut().set_param(5, 0.25);
+        b.tracks[1].plugin = Toof::new(b.sample_rate).into();
+        b.tracks[0].sequence = Sequence::from(vec![MidiEvent {
+            position: Position::new(1.0),
+            midi: MidiMessage::Reset,
+        }]);
+        Command::CopyTrack {
+            from: 0,
+            to: 1,
+            what: TrackContents::Plugin,
+            sequence: None,
+            undo: Box::default(),
+        }
+        .execute(&mut b);
+        assert_eq!(b.tracks[1].plugin.plugin().param(5), 0.25);
+        assert!(b.tracks[1].sequence.is_empty());
+    }
+
+    #[test]
+    fn copy_track_undo_restores_params_and_sequence() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.tracks[0].plugin = Toof::new(b.sample_rate).into();
+        b.tracks[0].plugin.plugin_mut().set_param(5, 0.25);
+        b.tracks[0].sequence = Sequence::from(vec![MidiEvent {
+            position: Position::new(1.0),
+            midi: MidiMessage::Reset,
+        }]);
+        b.tracks[1].plugin = Toof::new(b.sample_rate).into();
+        b.tracks[1].plugin.plugin_mut().set_param(5, 0.75);
+        let undo = Command::CopyTrack {
+            from: 0,
+            to: 1,
+            what: TrackContents::All,
+            sequence: Some(Box::default()),
+            undo: Box::default(),
+        }
+        .execute(&mut b);
+        assert_eq!(b.tracks[1].plugin.plugin().param(5), 0.25);
+        assert_eq!(b.tracks[1].sequence, b.tracks[0].sequence);
+
+        undo.execute(&mut b);
+        assert_eq!(b.tracks[1].plugin.plugin().param(5), 0.75);
+        assert!(b.tracks[1].sequence.is_empty());
+    }
+
+    #[test]
+    fn copy_track_does_not_copy_params_of_different_plugin() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
         }
+        .build();
+        b.tracks[0].plugin = Toof::new(b.sample_rate).into();
+        let before = b.clone();
+        let undo = Command::CopyTrack {
+            from: 0,
+            to: 1,
+            what: TrackContents::Plugin,
+            sequence: None,
+            undo: Box::default(),
+        }
+        .execute(&mut b);
+        assert_eq!(b, before);
+        assert_eq!(undo, Command::Batch(Box::default()));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use bats_dsp::{position::Position, sample_rate::SampleRate};
-    use bats_lib::{
-        builder::BatsBuilder,
-        plugin::{empty::Empty, toof::Toof},
-    };
-    use bmidi::MidiMessage;
+    #[test]
+    fn set_record() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.recording_enabled = true;
 
-    use super::*;
+        // true -> true
+        let undo = Command::SetRecord(true).execute(&mut b);
+        assert!(b.recording_enabled);
+        assert_eq!(undo, Command::SetRecord(true));
 
-    /// Get all the track name for non-empty tracks.
-    fn get_track_names(b: &Bats) -> Vec<&'static str> {
-        b.tracks
-            .iter()
-            .map(|t| t.plugin.plugin().metadata().name)
-            .collect()
+        // true -> false
+        let undo = Command::SetRecord(false).execute(&mut b);
+        assert!(!b.recording_enabled);
+        assert_eq!(undo, Command::SetRecord(true));
+
+        // false -> false
+        let undo = Command::SetRecord(false).execute(&mut b);
+        assert!(!b.recording_enabled);
+        assert_eq!(undo, Command::SetRecord(false));
+
+        // false -> true
+        let undo = Command::SetRecord(true).execute(&mut b);
+        assert!(b.recording_enabled);
+        assert_eq!(undo, Command::SetRecord(false));
     }
 
     #[test]
-    fn command_size_is_reasonable() {
-        let size = std::mem::size_of::<Command>();
-        assert_eq!(size, 40);
+    fn set_buffer_size() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let undo = Command::SetBufferSize(128).execute(&mut b);
+        assert_eq!(undo, Command::SetBufferSize(64));
+        assert_eq!(b.buffer_size, 128);
+        assert_eq!(b.tracks[0].output.len(), 128);
     }
 
     #[test]
-    fn none_command_undo_is_none() {
+    fn set_sample_rate() {
         let mut b = BatsBuilder {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: 64,
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
-        let undo = Command::None.execute(&mut b);
-        assert_eq!(undo, Command::None);
+        let undo = Command::SetSampleRate(SampleRate::new(48000.0)).execute(&mut b);
+        assert_eq!(undo, Command::SetSampleRate(SampleRate::new(44100.0)));
+        assert_eq!(b.sample_rate, SampleRate::new(48000.0));
+        assert_eq!(b.transport.bpm(), 120.0);
     }
 
     #[test]
-    fn set_metronome_volume_sets_new_volume_and_returns_old_as_undo() {
+    fn load_preset_sets_params_and_undo_restores_them() {
         let mut b = BatsBuilder {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: 64,
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
-        b.transport.metronome_volume = 1.0;
+        b.tracks[1].plugin = AnyPlugin::Toof(Toof::new(b.sample_rate));
+        let original = Preset::from_plugin("preset".to_string(), &b.tracks[1].plugin);
+        let mut other = b.tracks[1].plugin.clone();
+        for param in other.plugin().metadata().params {
+            other.plugin_mut().set_param(param.id, param.max_value);
+        }
+        let preset = Preset::from_plugin("preset".to_string(), &other);
 
-        let undo = Command::SetMetronomeVolume(0.5).execute(&mut b);
-        assert_eq!(b.transport.metronome_volume, 0.5);
-        assert_eq!(undo, Command::SetMetronomeVolume(1.0));
+        let undo = Command::LoadPreset {
+            track_id: 1,
+            preset: Box::new(preset.clone()),
+        }
+        .execute(&mut b);
+        assert_eq!(
+            Preset::from_plugin("preset".to_string(), &b.tracks[1].plugin),
+            preset
+        );
+        undo.execute(&mut b);
+        assert_eq!(
+            Preset::from_plugin("preset".to_string(), &b.tracks[1].plugin),
+            original
+        );
     }
 
     #[test]
-    fn metrenome_set_bpm() {
+    fn morph_commands_set_morph_and_return_old_state_as_undo() {
         let mut b = BatsBuilder {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: 64,
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
-        b.transport.set_bpm(b.sample_rate, 100.0);
+        let params = vec![MorphParam {
+            track_id: 0,
+            param_id: 2,
+            a: 100.0,
+            b: 500.0,
+        }];
+        b.morph.applied = Some(0.0);
+        let undo = Command::SetMorphParams(Box::new(params.clone())).execute(&mut b);
+        assert_eq!(b.morph.params, params);
+        assert_eq!(b.morph.applied, None);
+        assert_eq!(undo, Command::SetMorphParams(Box::default()));
 
-        let undo = Command::SetTransportBpm(90.0).execute(&mut b);
-        assert_eq!(b.transport.bpm(), 90.0);
-        assert_eq!(undo, Command::SetTransportBpm(100.0));
+        let undo = Command::SetMorphTarget(1.0).execute(&mut b);
+        assert_eq!(b.morph.target, 1.0);
+        assert_eq!(undo, Command::SetMorphTarget(0.0));
+
+        let undo = Command::SetMorphSeconds(4.0).execute(&mut b);
+        assert_eq!(b.morph.seconds, 4.0);
+        assert_eq!(undo, Command::SetMorphSeconds(SceneMorph::DEFAULT_SECONDS));
     }
 
     #[test]
-    fn set_plugin() {
+    fn macro_commands_set_knob_and_return_old_state_as_undo() {
         let mut b = BatsBuilder {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: 64,
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
-        let plugin = AnyPlugin::Toof(Toof::new(b.sample_rate));
-        b.tracks[0].plugin = plugin.clone();
+        let mappings = MacroMapping::crossfader(&[0], &[1]);
+        let undo = Command::SetMacroMappings {
+            knob: 1,
+            mappings: Box::new(mappings.clone()),
+        }
+        .execute(&mut b);
+        assert_eq!(b.macros[1].mappings, mappings);
+        assert_eq!(b.macros[1].applied, None);
         assert_eq!(
-            get_track_names(&b),
-            vec!["toof", "empty", "empty", "empty", "empty", "empty", "empty", "empty"]
+            undo,
+            Command::SetMacroMappings {
+                knob: 1,
+                mappings: Box::default(),
+            }
         );
 
-        let undo = Command::SetPlugin {
-            track_id: 1,
-            plugin: plugin.clone(),
+        let undo = Command::SetMacroValue {
+            knob: 1,
+            value: 0.75,
         }
         .execute(&mut b);
         assert_eq!(
-            get_track_names(&b),
-            vec!["toof", "toof", "empty", "empty", "empty", "empty", "empty", "empty"]
+            undo,
+            Command::SetMacroValue {
+                knob: 1,
+                value: 0.0,
+            }
         );
+        b.process_to_buffer(64, &[]);
+        assert_eq!(b.tracks[1].volume, 0.75);
+
+        let undo = Command::SetMacroCc {
+            knob: 1,
+            cc: Some(ControlFunction::PAN),
+        }
+        .execute(&mut b);
+        assert_eq!(b.macros[1].cc, Some(ControlFunction::PAN));
+        assert_eq!(undo, Command::SetMacroCc { knob: 1, cc: None });
         assert_eq!(
-            undo,
-            Command::SetPlugin {
-                track_id: 1,
-                plugin: AnyPlugin::Empty(Empty)
+            Command::SetMacroValue {
+                knob: Bats::MACRO_COUNT,
+                value: 1.0,
             }
+            .execute(&mut b),
+            Command::None
         );
+    }
 
-        let undo = Command::SetPlugin {
-            track_id: 1,
-            plugin: AnyPlugin::Empty(Empty),
+    #[test]
+    fn set_aux_send_and_volume_return_old_values_as_undo() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let undo = Command::SetAuxSend {
+            track_id: 3,
+            bus: 1,
+            level: 0.5,
         }
         .execute(&mut b);
+        assert_eq!(b.tracks[3].sends, [0.0, 0.5]);
         assert_eq!(
-            get_track_names(&b),
-            vec!["toof", "empty", "empty", "empty", "empty", "empty", "empty", "empty"]
+            undo,
+            Command::SetAuxSend {
+                track_id: 3,
+                bus: 1,
+                level: 0.0,
+            }
         );
+        let undo = Command::SetAuxVolume {
+            bus: 0,
+            volume: 0.25,
+        }
+        .execute(&mut b);
+        assert_eq!(b.aux_buses[0].volume, 0.25);
         assert_eq!(
             undo,
-            Command::SetPlugin {
-                track_id: 1,
-                plugin: plugin.clone()
+            Command::SetAuxVolume {
+                bus: 0,
+                volume: 1.0,
             }
         );
+        let missing = Command::SetAuxSend {
+            track_id: 3,
+            bus: Bats::AUX_BUS_COUNT,
+            level: 0.5,
+        };
+        assert_eq!(missing.execute(&mut b), Command::None);
     }
 
     #[test]
-    fn remove_plugin_that_does_not_exist_does_nothing() {
+    fn set_aux_effects_swaps_effects_and_sets_params() {
         let mut b = BatsBuilder {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: 64,
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
-        let plugin = AnyPlugin::Toof(Toof::new(b.sample_rate));
-        b.tracks[0].plugin = plugin.clone();
-        b.tracks[2].plugin = plugin.clone();
+        let delay = AnyEffect::Delay(Delay::new(b.sample_rate));
+        let undo = Command::SetAuxEffects {
+            bus: 1,
+            effects: Box::new(vec![delay.clone()]),
+        }
+        .execute(&mut b);
+        assert_eq!(b.aux_buses[1].effects, vec![delay]);
         assert_eq!(
-            get_track_names(&b),
-            vec!["toof", "empty", "toof", "empty", "empty", "empty", "empty", "empty"]
+            undo,
+            Command::SetAuxEffects {
+                bus: 1,
+                effects: Box::default(),
+            }
         );
+
+        let set_feedback = Command::SetAuxEffectParam {
+            bus: 1,
+            effect: 0,
+            param_id: 2,
+            value: 0.75,
+        };
+        let undo = set_feedback.execute(&mut b);
+        assert_eq!(b.aux_buses[1].effects[0].effect().param(2), 0.75);
         assert_eq!(
-            Command::SetPlugin {
-                track_id: 1,
-                plugin: AnyPlugin::Empty(Empty),
-            }
-            .execute(&mut b),
-            Command::SetPlugin {
-                track_id: 1,
-                plugin: AnyPlugin::default()
+            undo,
+            Command::SetAuxEffectParam {
+                bus: 1,
+                effect: 0,
+                param_id: 2,
+                value: 0.4,
             }
         );
+        let missing = Command::SetAuxEffectParam {
+            bus: 0,
+            effect: 0,
+            param_id: 2,
+            value: 0.75,
+        };
+        assert_eq!(missing.execute(&mut b), Command::None);
+    }
+
+    #[test]
+    fn set_automation_swaps_lanes() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let mut lane = AutomationLane::new(1);
+        lane.record(Position::new(1.0), 0.5);
+        let undo = Command::SetAutomation {
+            track_id: 2,
+            automation: Box::new(vec![lane.clone()]),
+        }
+        .execute(&mut b);
+        assert_eq!(b.tracks[2].automation, vec![lane]);
         assert_eq!(
-            get_track_names(&b),
-            vec!["toof", "empty", "toof", "empty", "empty", "empty", "empty", "empty"]
+            undo,
+            Command::SetAutomation {
+                track_id: 2,
+                automation: Box::default(),
+            }
         );
     }
 
     #[test]
-    fn set_armed_track() {
+    fn set_lfos_swaps_lfos() {
         let mut b = BatsBuilder {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: 64,
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
-        b.armed_track = 100;
-
-        let undo = Command::SetArmedTrack(10).execute(&mut b);
-        assert_eq!(b.armed_track, 10);
-        assert_eq!(undo, Command::SetArmedTrack(100));
-
-        let undo = Command::SetArmedTrack(20).execute(&mut b);
-        assert_eq!(b.armed_track, 20);
-        assert_eq!(undo, Command::SetArmedTrack(10));
-
-        let undo = Command::SetArmedTrack(100).execute(&mut b);
-        assert_eq!(b.armed_track, 100);
-        assert_eq!(undo, Command::SetArmedTrack(20));
+        let lfo = ParamLfo {
+            param_id: 1,
+            waveform: LfoWaveform::Square,
+            beats: 1.0,
+            center: 0.5,
+            depth: 0.25,
+        };
+        let undo = Command::SetLfos {
+            track_id: 2,
+            lfos: Box::new(vec![lfo]),
+        }
+        .execute(&mut b);
+        assert_eq!(b.tracks[2].lfos, vec![lfo]);
+        assert_eq!(
+            undo,
+            Command::SetLfos {
+                track_id: 2,
+                lfos: Box::default(),
+            }
+        );
     }
 
     #[test]
-    fn set_track_volume_sets_volume() {
+    fn set_expression_routes_swaps_routes() {
         let mut b = BatsBuilder {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: 64,
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
-        b.tracks[0].volume = 0.1;
-        b.tracks[1].volume = 0.2;
-        let undo = Command::SetTrackVolume {
-            track_id: 0,
-            volume: 0.3,
+        let route = ExpressionRoute {
+            source: ExpressionSource::PitchBend,
+            param_id: 1,
+            min: 0.0,
+            max: 1.0,
+        };
+        let undo = Command::SetExpressionRoutes {
+            track_id: 3,
+            routes: Box::new(vec![route]),
         }
         .execute(&mut b);
+        assert_eq!(b.tracks[3].expression_routes, vec![route]);
         assert_eq!(
             undo,
-            Command::SetTrackVolume {
-                track_id: 0,
-                volume: 0.1
+            Command::SetExpressionRoutes {
+                track_id: 3,
+                routes: Box::default(),
             }
         );
-        assert_eq!(b.tracks[0].volume, 0.3);
-        assert_eq!(b.tracks[1].volume, 0.2);
     }
 
     #[test]
-    fn set_track_volume_on_track_that_does_not_exist_does_nothing() {
+    fn set_midi_input_route_returns_previous_route() {
         let mut b = BatsBuilder {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: 64,
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
-        let undo = Command::SetTrackVolume {
-            track_id: 1000, // Out of range.
-            volume: 0.3,
+        let undo = Command::SetMidiInputRoute {
+            port: 1,
+            track_id: Some(4),
         }
         .execute(&mut b);
-        assert_eq!(undo, Command::None);
+        assert_eq!(b.midi_input_routes[1], Some(4));
+        assert_eq!(
+            undo,
+            Command::SetMidiInputRoute {
+                port: 1,
+                track_id: None,
+            }
+        );
+        assert_eq!(
+            Command::SetMidiInputRoute {
+                port: 100,
+                track_id: Some(4),
+            }
+            .execute(&mut b),
+            Command::None
+        );
     }
 
     #[test]
-    fn set_sequence_sets_sequence_on_track() {
+    fn set_track_input_returns_previous_input_and_rejects_cycles() {
         let mut b = BatsBuilder {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: 64,
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
-        b.tracks[4].sequence = vec![MidiEvent {
-            position: Position::new(0.0),
-            midi: MidiMessage::TuneRequest,
-        }];
-        let undo = Command::SetSequence {
-            track_id: 4,
-            sequence: vec![MidiEvent {
-                position: Position::new(1.2),
-                midi: MidiMessage::Reset,
-            }],
+        let undo = Command::SetTrackInput {
+            track_id: 0,
+            input: Some(3),
         }
         .execute(&mut b);
+        assert_eq!(b.tracks[0].input, Some(3));
+        assert_eq!(b.graph.schedule()[..2], [Node::Track(1), Node::Track(2)]);
         assert_eq!(
             undo,
-            Command::SetSequence {
-                track_id: 4,
-                sequence: vec![MidiEvent {
-                    position: Position::new(0.0),
-                    midi: MidiMessage::TuneRequest,
-                },]
+            Command::SetTrackInput {
+                track_id: 0,
+                input: None,
             }
         );
         assert_eq!(
-            b.tracks[4].sequence,
-            vec![MidiEvent {
-                position: Position::new(1.2),
-                midi: MidiMessage::Reset
-            }]
+            Command::SetTrackInput {
+                track_id: 3,
+                input: Some(0),
+            }
+            .execute(&mut b),
+            Command::None
         );
+        assert_eq!(b.tracks[3].input, None);
+
+        undo.execute(&mut b);
+        assert_eq!(b.tracks[0].input, None);
+        assert_eq!(b.graph.schedule()[0], Node::Track(0));
     }
 
     #[test]
-    fn set_record() {
+    fn set_direct_outputs_swaps_buffers() {
         let mut b = BatsBuilder {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: 64,
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
-        b.recording_enabled = true;
+        let outputs = vec![Buffers::new(64); b.tracks.len()];
+        let undo = Command::SetDirectOutputs(Box::new(outputs.clone())).execute(&mut b);
+        assert_eq!(b.direct_outputs, outputs);
+        assert_eq!(undo, Command::SetDirectOutputs(Box::default()));
+    }
 
-        // true -> true
-        let undo = Command::SetRecord(true).execute(&mut b);
-        assert_eq!(b.recording_enabled, true);
-        assert_eq!(undo, Command::SetRecord(true));
+    #[test]
+    fn set_compressor_swaps_compressor() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let compressor = Compressor::new(b.sample_rate);
+        let undo = Command::SetCompressor {
+            track_id: Some(1),
+            compressor: Some(compressor.clone()),
+        }
+        .execute(&mut b);
+        assert_eq!(b.tracks[1].compressor, Some(compressor.clone()));
+        assert_eq!(
+            undo,
+            Command::SetCompressor {
+                track_id: Some(1),
+                compressor: None
+            }
+        );
 
-        // true -> false
-        let undo = Command::SetRecord(false).execute(&mut b);
-        assert_eq!(b.recording_enabled, false);
-        assert_eq!(undo, Command::SetRecord(true));
+        let undo = Command::SetCompressor {
+            track_id: None,
+            compressor: Some(compressor.clone()),
+        }
+        .execute(&mut b);
+        assert_eq!(b.master_compressor, Some(compressor));
+        assert_eq!(
+            undo,
+            Command::SetCompressor {
+                track_id: None,
+                compressor: None
+            }
+        );
+    }
 
-        // false -> false
-        let undo = Command::SetRecord(false).execute(&mut b);
-        assert_eq!(b.recording_enabled, false);
-        assert_eq!(undo, Command::SetRecord(false));
+    #[test]
+    fn set_compressor_param_sets_param_and_returns_old_as_undo() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let set_ratio = Command::SetCompressorParam {
+            track_id: None,
+            param_id: 2,
+            value: 8.0,
+        };
+        assert_eq!(set_ratio.clone().execute(&mut b), Command::None);
 
-        // false -> true
-        let undo = Command::SetRecord(true).execute(&mut b);
-        assert_eq!(b.recording_enabled, true);
-        assert_eq!(undo, Command::SetRecord(false));
+        b.master_compressor = Some(Compressor::new(b.sample_rate));
+        let undo = set_ratio.execute(&mut b);
+        assert_eq!(b.master_compressor.as_ref().unwrap().param(2), 8.0);
+        assert_eq!(
+            undo,
+            Command::SetCompressorParam {
+                track_id: None,
+                param_id: 2,
+                value: 4.0
+            }
+        );
     }
 }
@@ -0,0 +1,160 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use bats_dsp::sample_rate::SampleRate;
+use bats_lib::recorder::{RecordSource, Recorder, RecordingReader};
+use log::{info, warn};
+
+/// Writes the audio from a `Recorder` to a wav file on a background thread.
+#[derive(Debug)]
+pub struct DiskWriter {
+    /// The path of the wav file.
+    path: PathBuf,
+    /// The audio that is being recorded.
+    source: RecordSource,
+    /// Set to true to stop the writer thread.
+    stop: Arc<AtomicBool>,
+    /// The writer thread. Returns the number of frames that were written.
+    thread: JoinHandle<Result<usize>>,
+}
+
+impl DiskWriter {
+    /// The number of seconds of audio that can be buffered before the writer thread has to catch
+    /// up.
+    const BUFFER_SECONDS: f32 = 2.0;
+
+    /// How long the writer thread sleeps when there is no audio to write.
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// Create the wav file at `path` and start the writer thread. The returned `Recorder` should
+    /// be sent to bats with `Command::SetRecorder`.
+    pub fn start(
+        path: PathBuf,
+        sample_rate: SampleRate,
+        source: RecordSource,
+    ) -> Result<(DiskWriter, Recorder)> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: sample_rate.sample_rate() as u32,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let writer = hound::WavWriter::create(&path, spec)
+            .map_err(|err| anyhow!("Could not write to {path:?} with error: {err}"))?;
+        let capacity = (sample_rate.sample_rate() * DiskWriter::BUFFER_SECONDS) as usize;
+        let (recorder, reader) = Recorder::new(source, capacity);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            std::thread::Builder::new()
+                .name("bats-disk-writer".to_string())
+                .spawn(move || write_until_stopped(writer, reader, &stop))?
+        };
+        info!("Recording {source:?} to {path:?}.");
+        let disk_writer = DiskWriter {
+            path,
+            source,
+            stop,
+            thread,
+        };
+        Ok((disk_writer, recorder))
+    }
+
+    /// The path of the wav file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The audio that is being recorded.
+    pub fn source(&self) -> RecordSource {
+        self.source
+    }
+
+    /// Write any remaining audio, finalize the wav file, and stop the writer thread. Returns the
+    /// number of frames that were written.
+    ///
+    /// The `Recorder` should be removed from bats first, otherwise any audio that it pushes after
+    /// this is lost.
+    pub fn stop(self) -> Result<usize> {
+        self.stop.store(true, Ordering::Release);
+        let frames = self
+            .thread
+            .join()
+            .map_err(|_| anyhow!("Disk writer for {:?} panicked.", self.path))??;
+        info!("Wrote {frames} frames to {:?}.", self.path);
+        Ok(frames)
+    }
+}
+
+/// Write the audio from `reader` to `writer` until `stop` is set. Returns the number of frames
+/// that were written.
+fn write_until_stopped(
+    mut writer: hound::WavWriter<BufWriter<File>>,
+    mut reader: RecordingReader,
+    stop: &AtomicBool,
+) -> Result<usize> {
+    let convert_sample = |v: f32| (v.clamp(-1.0, 1.0) as f64 * i32::MAX as f64) as i32;
+    let mut frames = Vec::new();
+    let mut written = 0;
+    loop {
+        // Check before reading so that all audio pushed before the stop is written.
+        let stopping = stop.load(Ordering::Acquire);
+        frames.clear();
+        written += reader.read_into(&mut frames);
+        for (l, r) in frames.iter() {
+            writer.write_sample(convert_sample(*l))?;
+            writer.write_sample(convert_sample(*r))?;
+        }
+        if stopping {
+            break;
+        }
+        if frames.is_empty() {
+            std::thread::sleep(DiskWriter::POLL_INTERVAL);
+        }
+    }
+    writer.finalize()?;
+    let dropped = reader.dropped_frames();
+    if dropped > 0 {
+        warn!("Dropped {dropped} frames while recording to disk.");
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use bats_dsp::buffers::Buffers;
+
+    use super::*;
+
+    #[test]
+    fn recorded_audio_is_written_to_wav() {
+        let path =
+            std::env::temp_dir().join(format!("bats-disk-writer-{}.wav", std::process::id()));
+        let sample_rate = SampleRate::new(44100.0);
+        let (writer, mut recorder) =
+            DiskWriter::start(path.clone(), sample_rate, RecordSource::Master).unwrap();
+        recorder.push(&[0.0, 1.0], &[0.5, -0.5]);
+        recorder.push(&[-1.0], &[0.25]);
+        assert_eq!(writer.stop().unwrap(), 3);
+        let data = Buffers::from_wav(&path, sample_rate).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(data.left, vec![0.0, 1.0, -1.0]);
+        assert_eq!(data.right, vec![0.5, -0.5, 0.25]);
+    }
+
+    #[test]
+    fn start_with_bad_path_returns_error() {
+        let path = std::env::temp_dir().join("bats-no-such-dir/recording.wav");
+        assert!(DiskWriter::start(path, SampleRate::new(44100.0), RecordSource::Master).is_err());
+    }
+}
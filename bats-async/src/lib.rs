@@ -1,18 +1,49 @@
-use bats_lib::Bats;
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{anyhow, Result};
+
+use bats_dsp::{position::Position, sample_rate::SampleRate};
+use bats_lib::{track::ReplacedPlugin, Bats, BatsEvent};
 use command::Command;
-use crossbeam_channel::{Receiver, Sender};
+use crossbeam_channel::{Receiver, Sender, TrySendError};
 use log::{error, info};
 use notification::Notification;
 
 pub mod command;
+pub mod disk_writer;
 pub mod notification;
+pub mod plugin_loader;
+pub mod sample_streamer;
+pub mod undo;
 
 /// Send commands to a bats instance.
+///
+/// Clones send commands to the same bats instance. Each notification is only delivered to one
+/// of the clones.
+#[derive(Clone)]
 pub struct CommandSender {
     /// The channel to send commands to.
     sender: Sender<Command>,
     /// Then channel to receive notifications from.
     notifications: Receiver<Notification>,
+    /// The transport position as of the last processed buffer.
+    position: Arc<AtomicU64>,
+    /// The bits of the `f32` CPU load reported by the audio backend.
+    cpu_load: Arc<AtomicU32>,
+    /// The bits of the `f32` DSP load measured by bats as of the last processed buffer.
+    dsp_load: Arc<AtomicU32>,
+    /// The number of xruns reported by the audio backend.
+    xruns: Arc<AtomicUsize>,
+    /// The round trip latency in frames reported by the audio backend. `u32::MAX` if unknown.
+    latency: Arc<AtomicU32>,
+    /// True while the audio backend has lost its connection to the audio server.
+    disconnected: Arc<AtomicBool>,
 }
 
 /// Receive commands for a bats instance.
@@ -22,57 +53,347 @@ pub struct CommandReceiver {
     receiver: Receiver<Command>,
     /// The channel to send notifications to.
     notifications: Sender<Notification>,
+    /// The channel to send data that should be dropped outside of the audio thread.
+    disposal: Sender<Command>,
+    /// The commands that could not be sent to `disposal` because it was full. They are sent again
+    /// by `publish_events`.
+    pending_disposal: RefCell<Vec<Command>>,
+    /// The number of notifications that were dropped because the notification queue was full and
+    /// that have not yet been reported with `Notification::Dropped`.
+    dropped_notifications: AtomicUsize,
+    /// The transport position as of the last processed buffer.
+    position: Arc<AtomicU64>,
+    /// The bits of the `f32` CPU load reported by the audio backend.
+    cpu_load: Arc<AtomicU32>,
+    /// The bits of the `f32` DSP load measured by bats as of the last processed buffer.
+    dsp_load: Arc<AtomicU32>,
+    /// The number of xruns reported by the audio backend.
+    xruns: Arc<AtomicUsize>,
+    /// The round trip latency in frames reported by the audio backend. `u32::MAX` if unknown.
+    latency: Arc<AtomicU32>,
+    /// True while the audio backend has lost its connection to the audio server.
+    disconnected: Arc<AtomicBool>,
 }
 
 /// Create a new `CommandSender` and `CommandReceiver`.
 pub fn new_async_commander() -> (CommandSender, CommandReceiver) {
+    let (d_sender, d_receiver) = crossbeam_channel::bounded(CommandReceiver::DISPOSAL_CAPACITY);
+    spawn_garbage_thread(d_receiver);
+    new_async_commander_with_disposal(d_sender)
+}
+
+/// Create a new `CommandSender` and `CommandReceiver` that dispose of data by sending it to
+/// `disposal`.
+fn new_async_commander_with_disposal(
+    disposal: Sender<Command>,
+) -> (CommandSender, CommandReceiver) {
     let (sender, receiver) = crossbeam_channel::bounded(1024);
     let (n_sender, n_receiver) = crossbeam_channel::bounded(1024);
+    let position = Arc::new(AtomicU64::new(Position::MIN.to_bits()));
+    let cpu_load = Arc::new(AtomicU32::new(f32::NAN.to_bits()));
+    let dsp_load = Arc::new(AtomicU32::new(f32::NAN.to_bits()));
+    let xruns = Arc::new(AtomicUsize::new(0));
+    let latency = Arc::new(AtomicU32::new(u32::MAX));
+    let disconnected = Arc::new(AtomicBool::new(false));
     (
         CommandSender {
             sender,
             notifications: n_receiver,
+            position: position.clone(),
+            cpu_load: cpu_load.clone(),
+            dsp_load: dsp_load.clone(),
+            xruns: xruns.clone(),
+            latency: latency.clone(),
+            disconnected: disconnected.clone(),
         },
         CommandReceiver {
             receiver,
             notifications: n_sender,
+            disposal,
+            pending_disposal: RefCell::new(Vec::with_capacity(CommandReceiver::DISPOSAL_CAPACITY)),
+            dropped_notifications: AtomicUsize::new(0),
+            position,
+            cpu_load,
+            dsp_load,
+            xruns,
+            latency,
+            disconnected,
         },
     )
 }
 
+/// Spawn a thread that drops everything sent to `disposal`. The thread exits once all senders
+/// have been dropped.
+fn spawn_garbage_thread(disposal: Receiver<Command>) {
+    std::thread::Builder::new()
+        .name("bats-garbage".to_string())
+        .spawn(move || {
+            for cmd in disposal.iter() {
+                drop(cmd);
+            }
+        })
+        .expect("failed to spawn garbage thread");
+}
+
 impl CommandSender {
-    /// Send a single command.
-    pub fn send(&self, cmd: Command) {
+    /// Send a single command. This never blocks. If the command queue is full, the command is
+    /// dropped and an error is returned.
+    pub fn send(&self, cmd: Command) -> Result<()> {
         info!("Sending command: {:?}", cmd);
-        self.sender.send(cmd).unwrap();
+        self.sender.try_send(cmd).map_err(|err| match err {
+            TrySendError::Full(cmd) => anyhow!("Command queue is full, dropped {cmd:?}."),
+            TrySendError::Disconnected(cmd) => {
+                anyhow!("Bats is no longer running, dropped {cmd:?}.")
+            }
+        })
     }
 
     /// Get all pending notifications
     pub fn notifications(&self) -> Vec<Notification> {
         self.notifications.try_iter().collect()
     }
+
+    /// Get the transport position as of the last processed buffer.
+    pub fn position(&self) -> Position {
+        Position::from_bits(self.position.load(Ordering::Relaxed))
+    }
+
+    /// Get the CPU load percentage reported by the audio backend or `None` if the backend does
+    /// not report it.
+    pub fn cpu_load(&self) -> Option<f32> {
+        let load = f32::from_bits(self.cpu_load.load(Ordering::Relaxed));
+        if load.is_nan() {
+            None
+        } else {
+            Some(load)
+        }
+    }
+
+    /// Get the percentage of the time available for each buffer that bats spends processing it,
+    /// or `None` if no buffers have been processed. Unlike `cpu_load`, this only includes the
+    /// time spent inside bats.
+    pub fn dsp_load(&self) -> Option<f32> {
+        let load = f32::from_bits(self.dsp_load.load(Ordering::Relaxed));
+        if load.is_nan() {
+            None
+        } else {
+            Some(load)
+        }
+    }
+
+    /// Get the number of xruns reported by the audio backend.
+    pub fn xruns(&self) -> usize {
+        self.xruns.load(Ordering::Relaxed)
+    }
+
+    /// Get the round trip latency in frames, from midi input to audio output, reported by the
+    /// audio backend or `None` if the backend does not report it.
+    pub fn latency(&self) -> Option<u32> {
+        match self.latency.load(Ordering::Relaxed) {
+            u32::MAX => None,
+            frames => Some(frames),
+        }
+    }
+
+    /// Returns true while the audio backend has lost its connection to the audio server and is
+    /// trying to reconnect. No audio is processed while disconnected.
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected.load(Ordering::Relaxed)
+    }
 }
 
 impl CommandReceiver {
+    /// The capacity of the channel to the garbage thread. The same number of commands can wait to
+    /// be sent again without allocating.
+    pub const DISPOSAL_CAPACITY: usize = 1024;
+
     /// Execute all queued up commands and return an iterator of the undo commands.
     pub fn execute_all<'a>(&'a self, b: &'a mut Bats) {
         for cmd in self.receiver.try_iter() {
             let undo = cmd.execute(b);
-            if let Err(err) = self.notifications.try_send(Notification::Undo(undo)) {
-                error!("Failed to send undo notifcation: {err}");
+            self.notify(Notification::Undo(undo));
+        }
+    }
+
+    /// Set the buffer size for `b`. This allocates so it should only be called outside of audio
+    /// processing, for example from JACK's buffer size callback.
+    pub fn set_buffer_size(&self, b: &mut Bats, buffer_size: usize) {
+        if b.buffer_size == buffer_size {
+            return;
+        }
+        let undo = Command::SetBufferSize(buffer_size).execute(b);
+        self.notify(Notification::Undo(undo));
+        self.notify(Notification::BufferSizeChanged(buffer_size));
+    }
+
+    /// Set the sample rate for `b` and notify of the change.
+    pub fn set_sample_rate(&self, b: &mut Bats, sample_rate: SampleRate) {
+        if b.sample_rate == sample_rate {
+            return;
+        }
+        let undo = Command::SetSampleRate(sample_rate).execute(b);
+        self.notify(Notification::Undo(undo));
+        self.notify(Notification::SampleRateChanged(sample_rate));
+    }
+
+    /// Send `notification`. If the notification queue is full, the notification is counted as
+    /// dropped and any memory it owns is sent to the garbage thread.
+    fn notify(&self, notification: Notification) {
+        let err = match self.notifications.try_send(notification) {
+            Ok(()) => return,
+            Err(err) => err,
+        };
+        error!("Failed to send notification: {err}");
+        self.dropped_notifications.fetch_add(1, Ordering::Relaxed);
+        match err.into_inner() {
+            Notification::Undo(undo) => self.dispose(undo),
+            Notification::Snapshot(snapshot) => self.dispose(Command::RequestSnapshot(snapshot)),
+            Notification::Captured { track_id, sequence } => {
+                self.dispose(Command::CaptureMidiHistory { track_id, sequence })
+            }
+            Notification::CaptureComplete(capture) => {
+                self.dispose(Command::SetCapture(Some(capture)))
+            }
+            _ => (),
+        }
+    }
+
+    /// Report the notifications that were dropped with `Notification::Dropped` once there is
+    /// space in the notification queue.
+    fn report_dropped_notifications(&self) {
+        let dropped = self.dropped_notifications.swap(0, Ordering::Relaxed);
+        if dropped == 0 {
+            return;
+        }
+        if self
+            .notifications
+            .try_send(Notification::Dropped(dropped))
+            .is_err()
+        {
+            self.dropped_notifications
+                .fetch_add(dropped, Ordering::Relaxed);
+        }
+    }
+
+    /// Send `cmd` to the garbage thread so that any memory it owns is not freed on the current
+    /// thread. If the garbage thread has fallen behind, `cmd` is kept and sent again by
+    /// `publish_events`.
+    pub fn dispose(&self, cmd: Command) {
+        if let Err(err) = self.disposal.try_send(cmd) {
+            self.pending_disposal.borrow_mut().push(err.into_inner());
+        }
+    }
+
+    /// Send the commands that `dispose` could not send to the garbage thread.
+    fn send_pending_disposal(&self) {
+        let mut pending = self.pending_disposal.borrow_mut();
+        while let Some(cmd) = pending.pop() {
+            if let Err(err) = self.disposal.try_send(cmd) {
+                pending.push(err.into_inner());
+                return;
+            }
+        }
+    }
+
+    /// Report the CPU load percentage of the audio backend.
+    pub fn set_cpu_load(&self, load: f32) {
+        self.cpu_load.store(load.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Report that the audio backend had `count` more xruns.
+    pub fn add_xruns(&self, count: usize) {
+        self.xruns.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Report the round trip latency in frames of the audio backend.
+    pub fn set_latency(&self, frames: u32) {
+        self.latency.store(frames, Ordering::Relaxed);
+    }
+
+    /// Report whether the audio backend has lost its connection to the audio server.
+    pub fn set_disconnected(&self, disconnected: bool) {
+        self.disconnected.store(disconnected, Ordering::Relaxed);
+    }
+
+    /// Publish the state of `b` that changed while processing audio. This includes:
+    ///
+    /// - The transport position and DSP load.
+    /// - Events from `b`, like recorded notes, as notifications.
+    /// - Completed captures as `Notification::CaptureComplete`.
+    /// - Captured midi history as `Notification::Captured`.
+    /// - Requested snapshots as `Notification::Snapshot`.
+    /// - The count of notifications that were dropped because the notification queue was full, as
+    ///   `Notification::Dropped`.
+    ///
+    /// Retired plugins are also put into their slots and data that could not be disposed of
+    /// earlier is sent to the garbage thread.
+    pub fn publish_events(&self, b: &mut Bats) {
+        self.position
+            .store(b.transport.position().to_bits(), Ordering::Relaxed);
+        if let Some(load) = b.dsp_load.load() {
+            self.dsp_load.store(load.to_bits(), Ordering::Relaxed);
+        }
+        if b.capture.as_ref().is_some_and(|c| c.is_complete()) {
+            if let Some(capture) = b.capture.take() {
+                self.notify(Notification::CaptureComplete(capture));
+            }
+        }
+        if let Some((track_id, sequence)) = b.captured.take() {
+            self.notify(Notification::Captured { track_id, sequence });
+        }
+        if let Some(snapshot) = b.snapshot.take() {
+            self.notify(Notification::Snapshot(snapshot));
+        }
+        for (track_id, track) in b.tracks.iter_mut().enumerate() {
+            for _ in 0..track.retired_plugins.len() {
+                let ReplacedPlugin { plugin, slot } = track.retired_plugins.remove(0);
+                match slot.try_put(plugin) {
+                    // The garbage thread drops the plugin if nothing else holds the slot.
+                    Ok(()) => self.dispose(Command::SetPlugin {
+                        track_id,
+                        plugin: slot,
+                    }),
+                    // Try again on the next call.
+                    Err(plugin) => track.retired_plugins.push(ReplacedPlugin { plugin, slot }),
+                }
+            }
+        }
+        for event in b.events.drain(..) {
+            let notification = match event {
+                BatsEvent::SequenceFull { track_id, .. } => Notification::SequenceFull { track_id },
+                BatsEvent::Recorded { track_id, item } => Notification::Recorded { track_id, item },
+                BatsEvent::LoopLength { track_id, length } => {
+                    Notification::LoopLength { track_id, length }
+                }
+                BatsEvent::Overload { active } => Notification::Overload(active),
+                BatsEvent::ControlSurface {
+                    armed_track,
+                    recording_enabled,
+                    playing,
+                } => Notification::ControlSurface {
+                    armed_track,
+                    recording_enabled,
+                    playing,
+                },
             };
+            self.notify(notification);
         }
+        self.report_dropped_notifications();
+        self.send_pending_disposal();
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use bats_dsp::sample_rate::SampleRate;
     use bats_lib::{
         builder::{AnyPlugin, BatsBuilder},
+        capture::Capture,
         plugin::{empty::Empty, toof::Toof},
+        plugin_slot::PluginSlot,
+        sequence::Sequence,
     };
+    use bmidi::{Channel, MidiMessage, Note, U7};
 
     #[test]
     fn send_commands_get_executed() {
@@ -81,17 +402,20 @@ mod tests {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: 64,
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
         let plugin = AnyPlugin::Toof(Toof::new(bats.sample_rate));
         assert_eq!(bats.tracks[0].plugin, AnyPlugin::Empty(Empty));
         assert_eq!(sender.notifications(), vec![]);
-        sender.send(Command::None);
-        sender.send(Command::SetPlugin {
-            track_id: 0,
-            plugin: plugin.clone(),
-        });
+        let slot = PluginSlot::new(plugin.clone());
+        sender.send(Command::None).unwrap();
+        sender
+            .send(Command::SetPlugin {
+                track_id: 0,
+                plugin: slot.clone(),
+            })
+            .unwrap();
 
         receiver.execute_all(&mut bats);
         assert_eq!(
@@ -100,10 +424,462 @@ mod tests {
                 Notification::Undo(Command::None),
                 Notification::Undo(Command::SetPlugin {
                     track_id: 0,
-                    plugin: AnyPlugin::Empty(Empty),
+                    plugin: slot
                 })
             ]
         );
         assert_eq!(bats.tracks[0].plugin, plugin);
     }
+
+    #[test]
+    fn replaced_plugin_is_put_into_its_slot_after_crossfade() {
+        let (d_sender, d_receiver) = crossbeam_channel::bounded(16);
+        let (sender, receiver) = new_async_commander_with_disposal(d_sender);
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let slot = PluginSlot::new(AnyPlugin::Toof(Toof::new(bats.sample_rate)));
+        sender
+            .send(Command::SetPlugin {
+                track_id: 0,
+                plugin: slot.clone(),
+            })
+            .unwrap();
+        receiver.execute_all(&mut bats);
+        assert_eq!(
+            sender.notifications(),
+            vec![Notification::Undo(Command::SetPlugin {
+                track_id: 0,
+                plugin: slot.clone(),
+            })]
+        );
+        assert_eq!(slot.try_take(), None);
+
+        while bats.tracks[0].fading_plugin.is_some() {
+            bats.process_to_buffer(64, &[]);
+        }
+        receiver.publish_events(&mut bats);
+        assert!(bats.tracks[0].retired_plugins.is_empty());
+        assert_eq!(slot.try_take(), Some(AnyPlugin::Empty(Empty)));
+        // The handle of the track on the slot is dropped by the garbage thread.
+        assert_eq!(
+            d_receiver.try_iter().collect::<Vec<_>>(),
+            vec![Command::SetPlugin {
+                track_id: 0,
+                plugin: slot,
+            }]
+        );
+    }
+
+    #[test]
+    fn batch_with_plugin_params_and_sequence_is_undone() {
+        let (sender, receiver) = new_async_commander();
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let mut original = AnyPlugin::Toof(Toof::new(bats.sample_rate));
+        original.plugin_mut().set_param(5, 0.25);
+        bats.tracks[0].plugin = original.clone();
+        let sequence: Sequence = [bats_lib::sequence::Note {
+            start: Position::new(0.0),
+            length: Position::new(1.0),
+            channel: Channel::Ch1,
+            pitch: Note::C4,
+            velocity: U7::MAX,
+        }]
+        .into_iter()
+        .collect();
+        sender
+            .send(Command::Batch(Box::new(vec![
+                Command::SetPlugin {
+                    track_id: 0,
+                    plugin: PluginSlot::new(AnyPlugin::Toof(Toof::new(bats.sample_rate))),
+                },
+                Command::SetParam {
+                    track_id: 0,
+                    param_id: 5,
+                    value: 0.75,
+                },
+                Command::SetSequence {
+                    track_id: 0,
+                    sequence: Box::new(sequence.clone()),
+                },
+            ])))
+            .unwrap();
+        receiver.execute_all(&mut bats);
+        assert_eq!(bats.tracks[0].plugin.plugin().param(5), 0.75);
+        assert_eq!(bats.tracks[0].sequence, sequence);
+        while bats.tracks[0].fading_plugin.is_some() {
+            bats.process_to_buffer(64, &[]);
+        }
+        receiver.publish_events(&mut bats);
+
+        let undo = match sender.notifications().as_slice() {
+            [Notification::Undo(undo @ Command::Batch(_))] => undo.clone(),
+            notifications => panic!("unexpected notifications: {notifications:?}"),
+        };
+        sender.send(undo).unwrap();
+        receiver.execute_all(&mut bats);
+        assert_eq!(bats.tracks[0].plugin, original);
+        assert!(bats.tracks[0].sequence.is_empty());
+    }
+
+    #[test]
+    fn retired_plugin_is_kept_while_its_slot_is_full() {
+        let (sender, receiver) = new_async_commander();
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let slot = PluginSlot::new(AnyPlugin::Toof(Toof::new(bats.sample_rate)));
+        sender
+            .send(Command::SetPlugin {
+                track_id: 0,
+                plugin: slot.clone(),
+            })
+            .unwrap();
+        receiver.execute_all(&mut bats);
+        while bats.tracks[0].fading_plugin.is_some() {
+            bats.process_to_buffer(64, &[]);
+        }
+        slot.try_put(AnyPlugin::default()).unwrap();
+        receiver.publish_events(&mut bats);
+        assert_eq!(bats.tracks[0].retired_plugins.len(), 1);
+
+        slot.take();
+        receiver.publish_events(&mut bats);
+        assert!(bats.tracks[0].retired_plugins.is_empty());
+        assert_eq!(slot.take(), Some(AnyPlugin::Empty(Empty)));
+    }
+
+    #[test]
+    fn events_are_published_as_notifications() {
+        let (sender, receiver) = new_async_commander();
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        bats.events.push(BatsEvent::SequenceFull {
+            track_id: 3,
+            dropped: 10,
+        });
+        receiver.publish_events(&mut bats);
+        assert!(bats.events.is_empty());
+        assert_eq!(
+            sender.notifications(),
+            vec![Notification::SequenceFull { track_id: 3 }]
+        );
+    }
+
+    #[test]
+    fn undo_is_disposed_when_notifications_are_full() {
+        let (d_sender, d_receiver) = crossbeam_channel::bounded(16);
+        let (sender, receiver) = new_async_commander_with_disposal(d_sender);
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        for _ in 0..1024 {
+            sender.send(Command::None).unwrap();
+            receiver.execute_all(&mut bats);
+        }
+        let old_sequence = bats.tracks[0].sequence.clone();
+        let sequence: Sequence = [bats_lib::sequence::Note {
+            start: Position::new(0.0),
+            length: Position::new(1.0),
+            channel: Channel::Ch1,
+            pitch: Note::C4,
+            velocity: U7::MAX,
+        }]
+        .into_iter()
+        .collect();
+        sender
+            .send(Command::SetSequence {
+                track_id: 0,
+                sequence: Box::new(sequence.clone()),
+            })
+            .unwrap();
+        receiver.execute_all(&mut bats);
+        assert_eq!(sender.notifications().len(), 1024);
+        assert_eq!(bats.tracks[0].sequence, sequence);
+        assert_eq!(
+            d_receiver.try_iter().collect::<Vec<_>>(),
+            vec![Command::SetSequence {
+                track_id: 0,
+                sequence: Box::new(old_sequence),
+            }]
+        );
+
+        receiver.publish_events(&mut bats);
+        assert_eq!(sender.notifications(), vec![Notification::Dropped(1)]);
+        receiver.publish_events(&mut bats);
+        assert_eq!(sender.notifications(), vec![]);
+    }
+
+    #[test]
+    fn disposal_is_kept_until_the_garbage_thread_catches_up() {
+        let (d_sender, d_receiver) = crossbeam_channel::bounded(1);
+        let (_, receiver) = new_async_commander_with_disposal(d_sender);
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        receiver.dispose(Command::SetArmedTrack(1));
+        receiver.dispose(Command::SetArmedTrack(2));
+        receiver.publish_events(&mut bats);
+        assert_eq!(
+            d_receiver.try_iter().collect::<Vec<_>>(),
+            vec![Command::SetArmedTrack(1)]
+        );
+        receiver.publish_events(&mut bats);
+        assert_eq!(
+            d_receiver.try_iter().collect::<Vec<_>>(),
+            vec![Command::SetArmedTrack(2)]
+        );
+    }
+
+    #[test]
+    fn dropped_count_is_kept_until_it_can_be_reported() {
+        let (sender, receiver) = new_async_commander();
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        for _ in 0..1026 {
+            sender.send(Command::None).unwrap();
+            receiver.execute_all(&mut bats);
+        }
+        receiver.publish_events(&mut bats);
+        assert_eq!(sender.notifications().len(), 1024);
+        receiver.publish_events(&mut bats);
+        assert_eq!(sender.notifications(), vec![Notification::Dropped(2)]);
+    }
+
+    #[test]
+    fn send_to_full_command_queue_returns_error() {
+        let (sender, receiver) = new_async_commander();
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        for _ in 0..1024 {
+            sender.send(Command::SetRecord(true)).unwrap();
+        }
+        assert!(sender.send(Command::SetRecord(true)).is_err());
+        receiver.execute_all(&mut bats);
+        assert!(sender.send(Command::SetRecord(true)).is_ok());
+    }
+
+    #[test]
+    fn set_buffer_size_notifies_new_size() {
+        let (sender, receiver) = new_async_commander();
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        receiver.set_buffer_size(&mut bats, 64);
+        assert_eq!(sender.notifications(), vec![]);
+        receiver.set_buffer_size(&mut bats, 256);
+        assert_eq!(bats.buffer_size, 256);
+        assert_eq!(
+            sender.notifications(),
+            vec![
+                Notification::Undo(Command::SetBufferSize(64)),
+                Notification::BufferSizeChanged(256)
+            ]
+        );
+    }
+
+    #[test]
+    fn position_is_published() {
+        let (sender, receiver) = new_async_commander();
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        assert_eq!(sender.position(), Position::MIN);
+        bats.process_to_buffer(64, &[]);
+        receiver.publish_events(&mut bats);
+        assert_eq!(sender.position(), bats.transport.position());
+        assert!(sender.position() > Position::MIN);
+    }
+
+    #[test]
+    fn cpu_load_is_none_until_reported() {
+        let (sender, receiver) = new_async_commander();
+        assert_eq!(sender.cpu_load(), None);
+        receiver.set_cpu_load(12.5);
+        assert_eq!(sender.cpu_load(), Some(12.5));
+    }
+
+    #[test]
+    fn xruns_are_counted() {
+        let (sender, receiver) = new_async_commander();
+        assert_eq!(sender.xruns(), 0);
+        receiver.add_xruns(2);
+        receiver.add_xruns(1);
+        assert_eq!(sender.xruns(), 3);
+    }
+
+    #[test]
+    fn dsp_load_is_published_after_processing() {
+        let (sender, receiver) = new_async_commander();
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        receiver.publish_events(&mut bats);
+        assert_eq!(sender.dsp_load(), None);
+        bats.process_to_buffer(64, &[]);
+        receiver.publish_events(&mut bats);
+        assert!(sender.dsp_load().is_some_and(|load| load >= 0.0));
+    }
+
+    #[test]
+    fn completed_capture_is_sent_as_notification() {
+        let (sender, receiver) = new_async_commander();
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        sender
+            .send(Command::SetCapture(Some(Box::new(Capture::new(100)))))
+            .unwrap();
+        receiver.execute_all(&mut bats);
+        bats.process_to_buffer(64, &[]);
+        receiver.publish_events(&mut bats);
+        assert!(!sender
+            .notifications()
+            .iter()
+            .any(|n| matches!(n, Notification::CaptureComplete(_))));
+        bats.process_to_buffer(64, &[]);
+        receiver.publish_events(&mut bats);
+        assert_eq!(bats.capture, None);
+        assert!(sender
+            .notifications()
+            .iter()
+            .any(|n| matches!(n, Notification::CaptureComplete(c) if c.is_complete())));
+    }
+
+    #[test]
+    fn requested_snapshot_is_sent_as_notification() {
+        let (sender, receiver) = new_async_commander();
+        let builder = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        };
+        let mut bats = builder.build();
+        sender.send(Command::SetTransportBpm(95.0)).unwrap();
+        sender
+            .send(Command::RequestSnapshot(Box::new(builder.clone())))
+            .unwrap();
+        receiver.execute_all(&mut bats);
+        receiver.publish_events(&mut bats);
+        assert_eq!(bats.snapshot, None);
+        assert_eq!(
+            sender.notifications(),
+            vec![
+                Notification::Undo(Command::SetTransportBpm(120.0)),
+                Notification::Undo(Command::None),
+                Notification::Snapshot(Box::new(BatsBuilder {
+                    bpm: 95.0,
+                    ..builder
+                })),
+            ]
+        );
+    }
+
+    #[test]
+    fn captured_midi_history_is_sent_as_notification() {
+        let (sender, receiver) = new_async_commander();
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        bats.armed_track = 1;
+        let note_on = MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX);
+        let note_off = MidiMessage::NoteOff(Channel::Ch1, Note::C4, U7::MIN);
+        bats.process_to_buffer(64, &[(0, note_on), (32, note_off)]);
+        assert!(bats.tracks[1].sequence.is_empty());
+
+        sender
+            .send(Command::CaptureMidiHistory {
+                track_id: 1,
+                sequence: Box::new(Sequence::with_capacity(16)),
+            })
+            .unwrap();
+        receiver.execute_all(&mut bats);
+        receiver.publish_events(&mut bats);
+        assert_eq!(bats.tracks[1].sequence.notes().len(), 1);
+        let notifications = sender.notifications();
+        let captured = notifications.iter().find_map(|n| match n {
+            Notification::Captured { track_id, sequence } => Some((*track_id, sequence)),
+            _ => None,
+        });
+        assert_eq!(
+            captured,
+            Some((1, &Box::new(bats.tracks[1].sequence.clone())))
+        );
+    }
+
+    #[test]
+    fn latency_is_none_until_reported() {
+        let (sender, receiver) = new_async_commander();
+        assert_eq!(sender.latency(), None);
+        receiver.set_latency(256);
+        assert_eq!(sender.latency(), Some(256));
+    }
+
+    #[test]
+    fn disconnected_is_reported() {
+        let (sender, receiver) = new_async_commander();
+        assert!(!sender.is_disconnected());
+        receiver.set_disconnected(true);
+        assert!(sender.is_disconnected());
+        receiver.set_disconnected(false);
+        assert!(!sender.is_disconnected());
+    }
 }
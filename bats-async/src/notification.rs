@@ -1,3 +1,10 @@
+use bats_dsp::{position::Position, sample_rate::SampleRate};
+use bats_lib::{
+    builder::BatsBuilder,
+    capture::Capture,
+    sequence::{Sequence, SequenceItem},
+};
+
 use crate::command::Command;
 
 #[derive(Clone, Debug, PartialEq)]
@@ -5,6 +12,55 @@ use crate::command::Command;
 pub enum Notification {
     /// Notify that a new undo command is available.
     Undo(Command),
+    /// Notify that the buffer size has changed.
+    BufferSizeChanged(usize),
+    /// Notify that the sample rate has changed.
+    SampleRateChanged(SampleRate),
+    /// Notify that a note or midi message was recorded to the sequence of a track.
+    Recorded {
+        /// The id of the track.
+        track_id: usize,
+        /// The note or message that was recorded.
+        item: SequenceItem,
+    },
+    /// Notify that the sequence for a track is full and recorded midi was dropped.
+    SequenceFull {
+        /// The id of the track.
+        track_id: usize,
+    },
+    /// Notify that the loop length of a track was detected from its recorded sequence.
+    LoopLength {
+        /// The id of the track.
+        track_id: usize,
+        /// The number of beats after which the sequence repeats.
+        length: Option<Position>,
+    },
+    /// Notify that overload protection was engaged, `true`, or released, `false`.
+    Overload(bool),
+    /// Notify that a pad of the control surface changed the armed track, recording, or playing.
+    ControlSurface {
+        /// The id of the armed track.
+        armed_track: usize,
+        /// True if recording to sequence is enabled.
+        recording_enabled: bool,
+        /// True if the transport should be playing.
+        playing: bool,
+    },
+    /// Notify that a capture started with `Command::SetCapture` has captured all of its frames.
+    CaptureComplete(Box<Capture>),
+    /// The notes and messages that were added to the sequence of a track by
+    /// `Command::CaptureMidiHistory`.
+    Captured {
+        /// The id of the track.
+        track_id: usize,
+        /// The captured notes and messages.
+        sequence: Box<Sequence>,
+    },
+    /// The snapshot of the state of bats requested with `Command::RequestSnapshot`.
+    Snapshot(Box<BatsBuilder>),
+    /// Notify that notifications were dropped because the notification queue was full. Holds the
+    /// number of dropped notifications.
+    Dropped(usize),
 }
 
 #[cfg(test)]
@@ -13,6 +69,7 @@ mod tests {
 
     #[test]
     fn notification_size_is_reasonable() {
+        // Recorded notes hold a start and a length so notifications are larger than commands.
         let size = std::mem::size_of::<Notification>();
         assert_eq!(size, 40);
     }
@@ -0,0 +1,178 @@
+use bats_dsp::sample_rate::SampleRate;
+use bats_lib::{
+    builder::{AnyPlugin, PluginBuilder},
+    preset::PresetParam,
+};
+use crossbeam_channel::{Receiver, Sender};
+use log::{info, warn};
+
+/// Builds plugins on a background thread so that plugins that are slow to instantiate do not
+/// block the UI. Built plugins are collected with `PluginLoader::ready` and should then be sent
+/// to bats with `Command::SetPlugin`. The thread stops once the loader is dropped.
+#[derive(Debug)]
+pub struct PluginLoader {
+    /// The channel to send requests to the loader thread.
+    requests: Sender<LoadRequest>,
+    /// The channel to receive built plugins from the loader thread.
+    ready: Receiver<LoadedPlugin>,
+    /// The id of the next request.
+    next_id: u64,
+}
+
+/// A plugin that has been built by a `PluginLoader`.
+#[derive(Debug)]
+pub struct LoadedPlugin {
+    /// The id returned by `PluginLoader::load` for the request.
+    pub id: u64,
+    /// The track that the plugin was built for.
+    pub track_id: usize,
+    /// The plugin.
+    pub plugin: AnyPlugin,
+    /// The sample rate that the plugin was built with.
+    pub sample_rate: SampleRate,
+}
+
+/// A request to build a plugin.
+#[derive(Debug)]
+struct LoadRequest {
+    /// The id of the request.
+    id: u64,
+    /// The track that the plugin is for.
+    track_id: usize,
+    /// Builds the plugin.
+    builder: PluginBuilder,
+    /// The params to set on the plugin once it is built.
+    params: Vec<PresetParam>,
+    /// The sample rate to build the plugin with.
+    sample_rate: SampleRate,
+}
+
+impl PluginLoader {
+    /// Create a new loader and start its thread.
+    pub fn new() -> PluginLoader {
+        let (requests, request_receiver) = crossbeam_channel::unbounded::<LoadRequest>();
+        let (ready_sender, ready) = crossbeam_channel::unbounded();
+        let spawned = std::thread::Builder::new()
+            .name("bats-plugin-loader".to_string())
+            .spawn(move || {
+                for request in request_receiver.iter() {
+                    info!(
+                        "Building {plugin:?} for track {track_id}.",
+                        plugin = request.builder,
+                        track_id = request.track_id
+                    );
+                    let plugin = LoadedPlugin {
+                        id: request.id,
+                        track_id: request.track_id,
+                        plugin: request
+                            .builder
+                            .build_with_params(request.sample_rate, &request.params),
+                        sample_rate: request.sample_rate,
+                    };
+                    if ready_sender.send(plugin).is_err() {
+                        return;
+                    }
+                }
+            });
+        if let Err(err) = spawned {
+            warn!("Failed to start plugin loader thread: {err}");
+        }
+        PluginLoader {
+            requests,
+            ready,
+            next_id: 0,
+        }
+    }
+
+    /// Start building the plugin from `builder` for the track with `track_id`. Returns the id of
+    /// the request, which is reported with the plugin once it is ready.
+    pub fn load(
+        &mut self,
+        track_id: usize,
+        builder: PluginBuilder,
+        sample_rate: SampleRate,
+    ) -> u64 {
+        self.load_with_params(track_id, builder, Vec::new(), sample_rate)
+    }
+
+    /// Same as `load`, but `params` are set on the plugin once it is built.
+    pub fn load_with_params(
+        &mut self,
+        track_id: usize,
+        builder: PluginBuilder,
+        params: Vec<PresetParam>,
+        sample_rate: SampleRate,
+    ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = LoadRequest {
+            id,
+            track_id,
+            builder,
+            params,
+            sample_rate,
+        };
+        if self.requests.send(request).is_err() {
+            warn!("Plugin loader has stopped, will not build {builder:?} for track {track_id}.");
+        }
+        id
+    }
+
+    /// Iterate over the plugins that have been built since the last call, in the order they were
+    /// requested. Does not block.
+    pub fn ready(&self) -> impl Iterator<Item = LoadedPlugin> + '_ {
+        self.ready.try_iter()
+    }
+}
+
+impl Default for PluginLoader {
+    fn default() -> PluginLoader {
+        PluginLoader::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn loaded_plugins_are_ready_in_request_order() {
+        let mut loader = PluginLoader::new();
+        let sample_rate = SampleRate::new(44100.0);
+        let first = loader.load(3, PluginBuilder::Toof, sample_rate);
+        let second = loader.load(5, PluginBuilder::Empty, sample_rate);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut loaded = Vec::new();
+        while loaded.len() < 2 && Instant::now() < deadline {
+            loaded.extend(loader.ready());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let loaded: Vec<_> = loaded
+            .iter()
+            .map(|p| (p.id, p.track_id, p.plugin.plugin().metadata().name))
+            .collect();
+        assert_eq!(loaded, vec![(first, 3, "toof"), (second, 5, "empty")]);
+    }
+
+    #[test]
+    fn loaded_plugins_have_requested_params() {
+        let mut loader = PluginLoader::new();
+        let params = vec![PresetParam {
+            id: 10,
+            value: 0.25,
+        }];
+        let id = loader.load_with_params(0, PluginBuilder::Toof, params, SampleRate::new(44100.0));
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let loaded = loop {
+            if let Some(p) = loader.ready().next() {
+                break p;
+            }
+            assert!(Instant::now() < deadline, "plugin was not loaded");
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(loaded.id, id);
+        assert_eq!(loaded.plugin.plugin().param(10), 0.25);
+    }
+}
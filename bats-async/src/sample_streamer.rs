@@ -0,0 +1,155 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use bats_dsp::{buffers::Buffers, sample_rate::SampleRate};
+use bats_lib::stream::{SampleStream, StreamLoader};
+use log::{info, warn};
+
+/// Streams the audio of a wav file into a `SampleStream` on a background thread. The thread stops
+/// once every clone of the `SampleStream` has been dropped.
+pub struct SampleStreamer;
+
+impl SampleStreamer {
+    /// The number of seconds at the start of the file that are kept in memory so that playback
+    /// can restart while the loader seeks back to the start.
+    const PREROLL_SECONDS: f32 = 0.5;
+
+    /// The number of seconds of audio that can be loaded ahead of playback.
+    const BUFFER_SECONDS: f32 = 2.0;
+
+    /// The number of frames to read from the file at a time.
+    const CHUNK_FRAMES: usize = 4096;
+
+    /// How long the loader thread sleeps when there is no audio to load.
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// Open the wav file at `path` and start the loader thread. The returned stream should be
+    /// played with `Sampler::streaming`.
+    ///
+    /// Only stereo 32 bit wav files at `sample_rate` are supported.
+    pub fn start(path: PathBuf, sample_rate: SampleRate) -> Result<SampleStream> {
+        let mut reader = hound::WavReader::open(&path)
+            .map_err(|err| anyhow!("Could not read from {path:?} with error: {err}"))?;
+        let spec = reader.spec();
+        if spec.sample_rate != sample_rate.sample_rate() as u32 {
+            return Err(anyhow!(
+                "expected sample rate {} but got {} from {path:?}",
+                sample_rate.sample_rate(),
+                spec.sample_rate,
+            ));
+        }
+        if spec.channels != 2 {
+            return Err(anyhow!(
+                "only 2 channels are supported but got {} from {path:?}",
+                spec.channels,
+            ));
+        }
+        let len = reader.duration() as usize;
+        let preroll_len = (sample_rate.sample_rate() * SampleStreamer::PREROLL_SECONDS) as usize;
+        let preroll = read_frames(&mut reader, preroll_len.min(len))?;
+        let capacity = (sample_rate.sample_rate() * SampleStreamer::BUFFER_SECONDS) as usize;
+        let (stream, loader) =
+            SampleStream::new(Buffers::with_iter(preroll.into_iter()), len, capacity);
+        std::thread::Builder::new()
+            .name("bats-sample-streamer".to_string())
+            .spawn(move || {
+                if let Err(err) = load_until_orphaned(&path, reader, loader) {
+                    warn!("Failed to stream {path:?}: {err}");
+                }
+            })?;
+        Ok(stream)
+    }
+}
+
+/// Load audio from `reader` into `loader` until no stream is left to play it.
+fn load_until_orphaned(
+    path: &Path,
+    mut reader: hound::WavReader<BufReader<File>>,
+    mut loader: StreamLoader,
+) -> Result<()> {
+    info!("Streaming {path:?}.");
+    let mut reported_underruns = 0;
+    while !loader.is_orphaned() {
+        if let Some(frame) = loader.poll_restart() {
+            reader.seek(frame as u32)?;
+        }
+        let underruns = loader.underruns();
+        if underruns > reported_underruns {
+            warn!(
+                "Streaming {path:?} fell behind playback by {} frames.",
+                underruns - reported_underruns
+            );
+            reported_underruns = underruns;
+        }
+        let available = loader.available().min(SampleStreamer::CHUNK_FRAMES);
+        if available == 0 {
+            std::thread::sleep(SampleStreamer::POLL_INTERVAL);
+            continue;
+        }
+        let frames = read_frames(&mut reader, available)?;
+        loader.push(&frames);
+    }
+    info!("Stopped streaming {path:?}.");
+    Ok(())
+}
+
+/// Read up to `frames` stereo frames from `reader`.
+fn read_frames(
+    reader: &mut hound::WavReader<BufReader<File>>,
+    frames: usize,
+) -> Result<Vec<(f32, f32)>> {
+    let convert_sample = |v| v as f32 / i32::MAX as f32;
+    let samples = reader
+        .samples::<i32>()
+        .take(2 * frames)
+        .map(|s| s.map(convert_sample))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(samples.chunks_exact(2).map(|f| (f[0], f[1])).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streamed_audio_matches_wav_file() {
+        let path =
+            std::env::temp_dir().join(format!("bats-sample-streamer-{}.wav", std::process::id()));
+        let sample_rate = SampleRate::new(1000.0);
+        let buffers = Buffers::with_iter((0..5000).map(|n| {
+            let v = (n % 100) as f32 / 100.0;
+            (v, -v)
+        }));
+        buffers.write_wav(&path, sample_rate).unwrap();
+        let mut stream = SampleStreamer::start(path.clone(), sample_rate).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(stream.len(), 5000);
+
+        for _ in 0..2 {
+            stream.restart();
+            let mut played = Vec::new();
+            while played.len() < 5000 {
+                // Play in small batches to give the loader time to keep up.
+                played.extend(std::iter::from_fn(|| stream.next_frame()).take(250));
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            let played = Buffers::with_iter(played.into_iter());
+            assert_eq!(played.len(), buffers.len());
+            for (a, b) in played.left.iter().zip(buffers.left.iter()) {
+                assert!((a - b).abs() < 1e-6, "{a} != {b}");
+            }
+        }
+        assert_eq!(stream.underruns(), 0);
+    }
+
+    #[test]
+    fn start_with_missing_file_returns_error() {
+        let path = std::env::temp_dir().join("bats-no-such-dir/sample.wav");
+        assert!(SampleStreamer::start(path, SampleRate::new(44100.0)).is_err());
+    }
+}
@@ -0,0 +1,420 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::{command::Command, CommandSender};
+
+/// Keeps the undo and redo history for commands sent to bats.
+///
+/// Commands are sent through the manager and the undo commands from `Notification::Undo` are
+/// passed to `handle_undo`. To tell its undo commands apart from other undo notifications, the
+/// manager sends every command as a `Command::Tagged` with an id that is unique to the manager.
+#[derive(Debug)]
+pub struct UndoManager {
+    /// The maximum number of entries in the undo history.
+    pub capacity: usize,
+    /// Changes to the same value that are sent within this duration of each other are undone
+    /// together.
+    pub group_window: Duration,
+    /// The entries that can be undone. The most recent entry is at the back.
+    undo: VecDeque<Entry>,
+    /// The entries that can be redone. The most recent entry is at the back.
+    redo: Vec<Entry>,
+    /// The ids of the commands that were sent and whose undo has not yet been received.
+    pending: VecDeque<(u64, Pending)>,
+    /// The id to tag the next sent command with.
+    next_id: u64,
+}
+
+/// An entry in the undo or redo history.
+#[derive(Debug)]
+struct Entry {
+    /// The command that undoes or redoes the entry.
+    command: Box<Command>,
+    /// The value that was changed by the entry if it can be grouped with later changes.
+    group: Option<Group>,
+    /// The time the last change in the entry was sent.
+    sent: Instant,
+}
+
+/// What a command that was sent through the manager was for.
+#[derive(Debug)]
+enum Pending {
+    /// A new change.
+    Do { group: Option<Group>, sent: Instant },
+    /// Undoing an entry.
+    Undo,
+    /// Redoing an entry.
+    Redo,
+}
+
+/// The value changed by a command. Consecutive changes to the same value may be grouped.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Group {
+    MetronomeVolume,
+    MetronomeSubdivision,
+    TransportBpm,
+    TrackVolume(usize),
+    Param(usize, u32),
+    CompressorParam(Option<usize>, u32),
+}
+
+impl UndoManager {
+    /// The default for `group_window`.
+    pub const DEFAULT_GROUP_WINDOW: Duration = Duration::from_millis(500);
+
+    /// Create a new undo manager that holds up to `capacity` undo entries.
+    pub fn new(capacity: usize) -> UndoManager {
+        UndoManager {
+            capacity,
+            group_window: UndoManager::DEFAULT_GROUP_WINDOW,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            pending: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Send `cmd` and record it in the undo history once its undo is received. This clears the
+    /// redo history.
+    pub fn send(&mut self, sender: &CommandSender, cmd: Command) -> Result<()> {
+        let group = Group::from_command(&cmd);
+        self.send_tagged(
+            sender,
+            Box::new(cmd),
+            Pending::Do {
+                group,
+                sent: Instant::now(),
+            },
+        )?;
+        self.redo.clear();
+        Ok(())
+    }
+
+    /// Undo the most recent entry. Returns `false` if there was nothing to undo. The entry is lost
+    /// if it could not be sent.
+    pub fn undo(&mut self, sender: &CommandSender) -> Result<bool> {
+        match self.undo.pop_back() {
+            None => Ok(false),
+            Some(entry) => {
+                self.send_tagged(sender, entry.command, Pending::Undo)?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Redo the most recently undone entry. Returns `false` if there was nothing to redo. The
+    /// entry is lost if it could not be sent.
+    pub fn redo(&mut self, sender: &CommandSender) -> Result<bool> {
+        match self.redo.pop() {
+            None => Ok(false),
+            Some(entry) => {
+                self.send_tagged(sender, entry.command, Pending::Redo)?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Handle the command from a `Notification::Undo`. Returns the command if it was not the undo
+    /// of a command sent by the manager.
+    pub fn handle_undo(&mut self, undo: Command) -> Option<Command> {
+        let (id, undo) = match undo {
+            Command::Tagged { id, command } => (id, command),
+            undo => return Some(undo),
+        };
+        let pending = match self.pending.iter().position(|(p, _)| *p == id) {
+            Some(idx) => self.pending.remove(idx),
+            None => None,
+        };
+        let pending = match pending {
+            Some((_, p)) => p,
+            None => return Some(Command::Tagged { id, command: undo }),
+        };
+        match pending {
+            Pending::Do { group, sent } => {
+                if let Some(last) = self.undo.back_mut() {
+                    let same_group = group.is_some() && last.group == group;
+                    if same_group && sent.duration_since(last.sent) <= self.group_window {
+                        // The oldest undo restores the value from before the group so the new
+                        // undo is not needed.
+                        last.sent = sent;
+                        return None;
+                    }
+                }
+                self.push_undo(Entry {
+                    command: undo,
+                    group,
+                    sent,
+                });
+            }
+            Pending::Undo => self.redo.push(Entry {
+                command: undo,
+                group: None,
+                sent: Instant::now(),
+            }),
+            Pending::Redo => self.push_undo(Entry {
+                command: undo,
+                group: None,
+                sent: Instant::now(),
+            }),
+        }
+        None
+    }
+
+    /// The command that `undo` would send next or `None` if there is nothing to undo.
+    pub fn next_undo(&self) -> Option<&Command> {
+        self.undo.back().map(|e| e.command.as_ref())
+    }
+
+    /// The command that `redo` would send next or `None` if there is nothing to redo.
+    pub fn next_redo(&self) -> Option<&Command> {
+        self.redo.last().map(|e| e.command.as_ref())
+    }
+
+    /// The number of entries that can be undone.
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// The number of entries that can be redone.
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// Send `command` tagged with a new id and remember that its undo is for `pending`.
+    fn send_tagged(
+        &mut self,
+        sender: &CommandSender,
+        command: Box<Command>,
+        pending: Pending,
+    ) -> Result<()> {
+        let id = self.next_id;
+        sender.send(Command::Tagged { id, command })?;
+        self.next_id += 1;
+        self.pending.push_back((id, pending));
+        Ok(())
+    }
+
+    /// Push an entry onto the undo history, dropping the oldest entries if the history is full.
+    fn push_undo(&mut self, entry: Entry) {
+        self.undo.push_back(entry);
+        while self.undo.len() > self.capacity {
+            self.undo.pop_front();
+        }
+    }
+}
+
+impl Group {
+    /// Get the value changed by `cmd` or `None` if `cmd` should not be grouped.
+    fn from_command(cmd: &Command) -> Option<Group> {
+        match cmd {
+            Command::SetMetronomeVolume(_) => Some(Group::MetronomeVolume),
+            Command::SetMetronomeSubdivision(_) => Some(Group::MetronomeSubdivision),
+            Command::SetTransportBpm(_) => Some(Group::TransportBpm),
+            Command::SetTrackVolume { track_id, .. } => Some(Group::TrackVolume(*track_id)),
+            Command::SetParam {
+                track_id, param_id, ..
+            } => Some(Group::Param(*track_id, *param_id)),
+            Command::SetCompressorParam {
+                track_id, param_id, ..
+            } => Some(Group::CompressorParam(*track_id, *param_id)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bats_dsp::sample_rate::SampleRate;
+    use bats_lib::{
+        builder::{AnyPlugin, BatsBuilder},
+        plugin::{empty::Empty, toof::Toof},
+        plugin_slot::PluginSlot,
+        Bats,
+    };
+
+    use crate::{new_async_commander, notification::Notification, CommandReceiver};
+
+    use super::*;
+
+    fn new_bats() -> Bats {
+        BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build()
+    }
+
+    /// Execute the pending commands and pass the undo notifications to `undo`.
+    fn process(
+        sender: &CommandSender,
+        receiver: &CommandReceiver,
+        b: &mut Bats,
+        undo: &mut UndoManager,
+    ) {
+        receiver.execute_all(b);
+        receiver.publish_events(b);
+        for n in sender.notifications() {
+            if let Notification::Undo(cmd) = n {
+                undo.handle_undo(cmd);
+            }
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_restore_state() {
+        let (sender, receiver) = new_async_commander();
+        let mut b = new_bats();
+        let mut undo = UndoManager::new(10);
+        undo.send(&sender, Command::SetTransportBpm(90.0)).unwrap();
+        undo.send(&sender, Command::SetArmedTrack(2)).unwrap();
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!((b.transport.bpm(), b.armed_track), (90.0, 2));
+        assert_eq!(undo.undo_len(), 2);
+
+        assert!(undo.undo(&sender).unwrap());
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!((b.transport.bpm(), b.armed_track), (90.0, 0));
+        assert!(undo.undo(&sender).unwrap());
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!((b.transport.bpm(), b.armed_track), (120.0, 0));
+        assert!(!undo.undo(&sender).unwrap());
+        assert_eq!(undo.redo_len(), 2);
+
+        assert!(undo.redo(&sender).unwrap());
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!((b.transport.bpm(), b.armed_track), (90.0, 0));
+        assert_eq!((undo.undo_len(), undo.redo_len()), (1, 1));
+    }
+
+    #[test]
+    fn new_command_clears_redo() {
+        let (sender, receiver) = new_async_commander();
+        let mut b = new_bats();
+        let mut undo = UndoManager::new(10);
+        undo.send(&sender, Command::SetArmedTrack(2)).unwrap();
+        process(&sender, &receiver, &mut b, &mut undo);
+        undo.undo(&sender).unwrap();
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!(undo.redo_len(), 1);
+        undo.send(&sender, Command::SetArmedTrack(3)).unwrap();
+        assert_eq!(undo.redo_len(), 0);
+    }
+
+    #[test]
+    fn rapid_changes_to_same_value_are_grouped() {
+        let (sender, receiver) = new_async_commander();
+        let mut b = new_bats();
+        let mut undo = UndoManager::new(10);
+        undo.group_window = Duration::from_secs(3600);
+        for bpm in [100.0, 90.0, 80.0] {
+            undo.send(&sender, Command::SetTransportBpm(bpm)).unwrap();
+        }
+        undo.send(&sender, Command::SetMetronomeVolume(0.25))
+            .unwrap();
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!(undo.undo_len(), 2);
+
+        undo.undo(&sender).unwrap();
+        undo.undo(&sender).unwrap();
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!(b.transport.bpm(), 120.0);
+        undo.redo(&sender).unwrap();
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!(b.transport.bpm(), 80.0);
+    }
+
+    #[test]
+    fn changes_outside_group_window_are_not_grouped() {
+        let (sender, receiver) = new_async_commander();
+        let mut b = new_bats();
+        let mut undo = UndoManager::new(10);
+        undo.group_window = Duration::ZERO;
+        undo.send(&sender, Command::SetTransportBpm(100.0)).unwrap();
+        std::thread::sleep(Duration::from_millis(1));
+        undo.send(&sender, Command::SetTransportBpm(90.0)).unwrap();
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!(undo.undo_len(), 2);
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let (sender, receiver) = new_async_commander();
+        let mut b = new_bats();
+        let mut undo = UndoManager::new(2);
+        for track in 1..5 {
+            undo.send(&sender, Command::SetArmedTrack(track)).unwrap();
+        }
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!(undo.undo_len(), 2);
+        undo.undo(&sender).unwrap();
+        undo.undo(&sender).unwrap();
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!(b.armed_track, 2);
+    }
+
+    #[test]
+    fn other_undo_notifications_are_returned() {
+        let mut undo = UndoManager::new(10);
+        assert_eq!(
+            undo.handle_undo(Command::SetBufferSize(64)),
+            Some(Command::SetBufferSize(64))
+        );
+        let batch = Command::Batch(Box::new(vec![Command::None]));
+        assert_eq!(undo.handle_undo(batch.clone()), Some(batch));
+        let tagged = Command::Tagged {
+            id: 100,
+            command: Box::new(Command::None),
+        };
+        assert_eq!(undo.handle_undo(tagged.clone()), Some(tagged));
+        assert_eq!(undo.undo_len(), 0);
+    }
+
+    #[test]
+    fn batches_sent_around_the_manager_do_not_affect_the_history() {
+        let (sender, receiver) = new_async_commander();
+        let mut b = new_bats();
+        let mut undo = UndoManager::new(10);
+        sender
+            .send(Command::Batch(Box::new(vec![Command::SetArmedTrack(5)])))
+            .unwrap();
+        undo.send(&sender, Command::SetTransportBpm(90.0)).unwrap();
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!(undo.undo_len(), 1);
+
+        undo.undo(&sender).unwrap();
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!((b.transport.bpm(), b.armed_track), (120.0, 5));
+    }
+
+    #[test]
+    fn plugin_changes_are_undone_and_redone() {
+        let (sender, receiver) = new_async_commander();
+        let mut b = new_bats();
+        let mut undo = UndoManager::new(10);
+        let toof = AnyPlugin::Toof(Toof::new(b.sample_rate));
+        undo.send(
+            &sender,
+            Command::SetPlugin {
+                track_id: 0,
+                plugin: PluginSlot::new(toof.clone()),
+            },
+        )
+        .unwrap();
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!(b.tracks[0].plugin, toof);
+
+        undo.undo(&sender).unwrap();
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!(b.tracks[0].plugin, AnyPlugin::Empty(Empty));
+        undo.redo(&sender).unwrap();
+        process(&sender, &receiver, &mut b, &mut undo);
+        assert_eq!(b.tracks[0].plugin, toof);
+        assert_eq!((undo.undo_len(), undo.redo_len()), (1, 0));
+    }
+}
@@ -2,6 +2,7 @@ use std::time::Duration;
 
 use bats_dsp::{
     envelope::{Envelope, EnvelopeParams},
+    envelope_follower::EnvelopeFollower,
     moog_filter::MoogFilter,
     sample_rate::SampleRate,
 };
@@ -43,6 +44,40 @@ fn moog_filter_benchmark(c: &mut Criterion) {
                     *out = f.process(*out);
                 }
             });
+        })
+        .bench_function("process-tail", |b| {
+            // Let a loud signal decay for a long time so that the filter state would become
+            // denormal without protection.
+            let mut f = MoogFilter::new(SampleRate::new(SAMPLE_RATE));
+            f.process(1.0);
+            for _ in 0..SAMPLE_RATE as usize * 10 {
+                f.process(0.0);
+            }
+            let mut result = black_box(vec![0f32; BUFFER_SIZE]);
+            b.iter(move || {
+                for out in result.iter_mut() {
+                    *out = f.process(0.0);
+                }
+            });
+        });
+}
+
+fn envelope_follower_benchmark(c: &mut Criterion) {
+    c.benchmark_group("envelope-follower")
+        .measurement_time(Duration::from_secs(1))
+        .confidence_level(0.99)
+        .bench_function("process-tail", |b| {
+            let mut f = EnvelopeFollower::new(SampleRate::new(SAMPLE_RATE), 0.001, 0.01);
+            f.process(1.0);
+            for _ in 0..SAMPLE_RATE as usize * 10 {
+                f.process(0.0);
+            }
+            let mut result = black_box(vec![0f32; BUFFER_SIZE]);
+            b.iter(move || {
+                for out in result.iter_mut() {
+                    *out = f.process(0.0);
+                }
+            });
         });
 }
 
@@ -72,6 +107,7 @@ criterion_group!(
     benches,
     init_benchmark,
     moog_filter_benchmark,
+    envelope_follower_benchmark,
     envelope_benchmark
 );
 criterion_main!(benches);
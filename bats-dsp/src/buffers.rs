@@ -2,7 +2,6 @@ use std::{fmt, path::Path};
 
 use anyhow::{anyhow, Result};
 
-
 use crate::sample_rate::SampleRate;
 
 /// Buffers contains a left and right audio channel.
@@ -34,6 +33,14 @@ impl Buffers {
         Buffers { left, right }
     }
 
+    /// Create new buffers from interleaved stereo samples. A trailing sample without a pair is
+    /// ignored.
+    pub fn from_interleaved(samples: &[f32]) -> Buffers {
+        let mut buffers = Buffers::new(samples.len() / 2);
+        buffers.deinterleave(samples);
+        buffers
+    }
+
     /// Create new buffers from a wav file. `sample_rate` should be the sample rate of the returned `Buffers`.
     ///
     /// # TODO
@@ -56,17 +63,32 @@ impl Buffers {
                 p.as_ref()
             ));
         }
-        let mut buffers = Buffers {
-            left: Vec::with_capacity(reader.duration() as usize),
-            right: Vec::with_capacity(reader.duration() as usize),
+        let convert_sample = |v| v as f32 / i32::MAX as f32;
+        let samples = reader
+            .into_samples::<i32>()
+            .map(|s| s.map(convert_sample))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Buffers::from_interleaved(&samples))
+    }
+
+    /// Write the buffers to a stereo 32 bit wav file at `p`. Samples are clamped to `[-1.0, 1.0]`.
+    pub fn write_wav(&self, p: impl AsRef<Path>, sample_rate: SampleRate) -> Result<()> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: sample_rate.sample_rate() as u32,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Int,
         };
-        let mut samples = reader.into_samples::<i32>();
-        while let Some(s) = samples.next() {
-            let convert_sample = |v| v as f32 / i32::MAX as f32;
-            buffers.left.push(convert_sample(s?));
-            buffers.right.push(convert_sample(samples.next().unwrap()?));
+        let mut writer = hound::WavWriter::create(p.as_ref(), spec)
+            .map_err(|err| anyhow!("Could not write to {:?} with error: {}", p.as_ref(), err))?;
+        let convert_sample = |v: f32| (v.clamp(-1.0, 1.0) as f64 * i32::MAX as f64) as i32;
+        let mut samples = vec![0.0; 2 * self.len()];
+        self.interleave(&mut samples);
+        for sample in samples {
+            writer.write_sample(convert_sample(sample))?;
         }
-        Ok(buffers)
+        writer.finalize()?;
+        Ok(())
     }
 
     /// Get the samples at `idx`.
@@ -77,6 +99,22 @@ impl Buffers {
         )
     }
 
+    /// Get the samples at a fractional `position` by linearly interpolating between the nearest
+    /// frames. Positions outside of the buffers are `0.0`.
+    pub fn get_interpolated(&self, position: f64) -> (f32, f32) {
+        if position < 0.0 {
+            // Fade in from the silence before the first frame.
+            let (l, r) = self.get(0);
+            let t = (position + 1.0).max(0.0) as f32;
+            return (l * t, r * t);
+        }
+        let idx = position as usize;
+        let t = position.fract() as f32;
+        let (l0, r0) = self.get(idx);
+        let (l1, r1) = self.get(idx + 1);
+        (l0 + (l1 - l0) * t, r0 + (r1 - r0) * t)
+    }
+
     /// Set the samples at `idx`.
     pub fn set(&mut self, idx: usize, samples: (f32, f32)) {
         self.left[idx] = samples.0;
@@ -97,6 +135,80 @@ impl Buffers {
     pub fn is_zero(&self) -> bool {
         self.left.iter().all(|v| *v == 0.0) && self.right.iter().all(|v| *v == 0.0)
     }
+
+    /// Multiply the samples by a gain that ramps linearly from `start` on the first frame towards
+    /// `end`. `end` is reached on the frame after the last frame so that ramps over consecutive
+    /// buffers join up smoothly.
+    pub fn apply_gain_ramp(&mut self, start: f32, end: f32) {
+        let delta = (end - start) / self.len().max(1) as f32;
+        let frames = self.left.iter_mut().zip(self.right.iter_mut());
+        for (idx, (l, r)) in frames.enumerate() {
+            let gain = start + delta * idx as f32;
+            *l *= gain;
+            *r *= gain;
+        }
+    }
+
+    /// Crossfade from `from` into these buffers. The gain of `from` ramps linearly from `start`
+    /// towards `end` in the same way as `apply_gain_ramp` and is clamped to `[0.0, 1.0]`. These
+    /// buffers get the remaining gain.
+    pub fn crossfade_from(&mut self, from: &Buffers, start: f32, end: f32) {
+        let delta = (end - start) / self.len().max(1) as f32;
+        let dst = self.left.iter_mut().zip(self.right.iter_mut());
+        let src = from.left.iter().zip(from.right.iter());
+        for (idx, ((dst_l, dst_r), (src_l, src_r))) in dst.zip(src).enumerate() {
+            let gain = (start + delta * idx as f32).clamp(0.0, 1.0);
+            *dst_l = *dst_l * (1.0 - gain) + src_l * gain;
+            *dst_r = *dst_r * (1.0 - gain) + src_r * gain;
+        }
+    }
+
+    /// Add the samples of `from` onto these buffers. Frames past the end of either buffer are
+    /// ignored.
+    pub fn mix_from(&mut self, from: &Buffers) {
+        for (dst, src) in self.left.iter_mut().zip(from.left.iter()) {
+            *dst += src;
+        }
+        for (dst, src) in self.right.iter_mut().zip(from.right.iter()) {
+            *dst += src;
+        }
+    }
+
+    /// Copy `left` and `right` into the buffers starting at frame `offset`. Frames that do not fit
+    /// are ignored. Returns the number of frames that were copied.
+    pub fn copy_from(&mut self, offset: usize, left: &[f32], right: &[f32]) -> usize {
+        let offset = offset.min(self.len());
+        let len = left.len().min(right.len()).min(self.len() - offset);
+        self.left[offset..offset + len].copy_from_slice(&left[..len]);
+        self.right[offset..offset + len].copy_from_slice(&right[..len]);
+        len
+    }
+
+    /// Write the buffers into `dst` as interleaved stereo samples. Returns the number of frames
+    /// that were written, which is limited by the size of `dst`.
+    pub fn interleave(&self, dst: &mut [f32]) -> usize {
+        let src = self.left.iter().zip(self.right.iter());
+        let mut frames = 0;
+        for (frame, (l, r)) in dst.chunks_exact_mut(2).zip(src) {
+            frame[0] = *l;
+            frame[1] = *r;
+            frames += 1;
+        }
+        frames
+    }
+
+    /// Read interleaved stereo samples from `src` into the buffers. Returns the number of frames
+    /// that were read, which is limited by the size of the buffers.
+    pub fn deinterleave(&mut self, src: &[f32]) -> usize {
+        let dst = self.left.iter_mut().zip(self.right.iter_mut());
+        let mut frames = 0;
+        for ((l, r), frame) in dst.zip(src.chunks_exact(2)) {
+            *l = frame[0];
+            *r = frame[1];
+            frames += 1;
+        }
+        frames
+    }
 }
 
 impl fmt::Debug for Buffers {
@@ -136,6 +248,17 @@ mod tests {
         assert_eq!(data.right.len(), 44100);
     }
 
+    #[test]
+    fn write_and_read_wav_file() {
+        let path = std::env::temp_dir().join(format!("bats-buffers-{}.wav", std::process::id()));
+        let buffers = Buffers::with_iter([(0.0, 1.0), (-1.0, 0.5), (2.0, -2.0)].into_iter());
+        buffers.write_wav(&path, SampleRate::new(44100.0)).unwrap();
+        let data = Buffers::from_wav(&path, SampleRate::new(44100.0)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(data.left, vec![0.0, -1.0, 1.0]);
+        assert_eq!(data.right, vec![1.0, 0.5, -1.0]);
+    }
+
     #[test]
     fn read_mono_wav_file_returns_error() {
         let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -163,17 +286,82 @@ mod tests {
         assert_eq!(data.get(usize::MAX), (0.0, 0.0));
     }
 
+    #[test]
+    fn get_interpolated_blends_neighboring_frames() {
+        let buffers = Buffers::with_iter([(1.0, 2.0), (3.0, 6.0)].into_iter());
+        assert_eq!(buffers.get_interpolated(0.0), (1.0, 2.0));
+        assert_eq!(buffers.get_interpolated(0.5), (2.0, 4.0));
+        assert_eq!(buffers.get_interpolated(1.5), (1.5, 3.0));
+        assert_eq!(buffers.get_interpolated(-0.5), (0.5, 1.0));
+        assert_eq!(buffers.get_interpolated(-2.0), (0.0, 0.0));
+        assert_eq!(buffers.get_interpolated(5.0), (0.0, 0.0));
+    }
+
     #[test]
     fn set_sample_sets_the_sample() {
-        let mut buffers = Buffers::with_iter(std::iter::repeat((1.0, 1.0)).take(100));
+        let mut buffers = Buffers::with_iter(std::iter::repeat_n((1.0, 1.0), 100));
         assert_eq!(buffers.get(10), (1.0, 1.0));
         buffers.set(10, (-1.0, -1.0));
         assert_eq!(buffers.get(10), (-1.0, -1.0));
     }
 
+    #[test]
+    fn gain_ramp_joins_consecutive_buffers() {
+        let mut first = Buffers::with_iter(std::iter::repeat_n((1.0, 2.0), 4));
+        let mut second = first.clone();
+        first.apply_gain_ramp(1.0, 0.5);
+        second.apply_gain_ramp(0.5, 0.0);
+        assert_eq!(first.left, vec![1.0, 0.875, 0.75, 0.625]);
+        assert_eq!(first.right, vec![2.0, 1.75, 1.5, 1.25]);
+        assert_eq!(second.left, vec![0.5, 0.375, 0.25, 0.125]);
+    }
+
+    #[test]
+    fn crossfade_from_mixes_and_clamps() {
+        let from = Buffers::with_iter(std::iter::repeat_n((1.0, -1.0), 4));
+        let mut buffers = Buffers::new(4);
+        buffers.crossfade_from(&from, 1.0, -1.0);
+        assert_eq!(buffers.left, vec![1.0, 0.5, 0.0, 0.0]);
+        assert_eq!(buffers.right, vec![-1.0, -0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn mix_from_adds_samples() {
+        let from = Buffers::with_iter([(1.0, -1.0), (2.0, -2.0)].into_iter());
+        let mut buffers = Buffers::with_iter(std::iter::repeat_n((0.5, 0.5), 3));
+        buffers.mix_from(&from);
+        assert_eq!(buffers.left, vec![1.5, 2.5, 0.5]);
+        assert_eq!(buffers.right, vec![-0.5, -1.5, 0.5]);
+    }
+
+    #[test]
+    fn copy_from_offset_ignores_frames_that_do_not_fit() {
+        let mut buffers = Buffers::new(4);
+        assert_eq!(
+            buffers.copy_from(1, &[1.0, 2.0, 3.0, 4.0], &[5.0, 6.0, 7.0]),
+            3
+        );
+        assert_eq!(buffers.left, vec![0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(buffers.right, vec![0.0, 5.0, 6.0, 7.0]);
+        assert_eq!(buffers.copy_from(10, &[1.0], &[1.0]), 0);
+    }
+
+    #[test]
+    fn interleave_and_deinterleave_round_trip() {
+        let buffers = Buffers::with_iter([(1.0, 2.0), (3.0, 4.0), (5.0, 6.0)].into_iter());
+        let mut samples = [0.0; 5];
+        assert_eq!(buffers.interleave(&mut samples), 2);
+        assert_eq!(samples, [1.0, 2.0, 3.0, 4.0, 0.0]);
+
+        let mut samples = [0.0; 6];
+        assert_eq!(buffers.interleave(&mut samples), 3);
+        assert_eq!(Buffers::from_interleaved(&samples), buffers);
+        assert_eq!(Buffers::from_interleaved(&samples[..5]).len(), 2);
+    }
+
     #[test]
     fn debug_buffers() {
         assert!(format!("{:?}", Buffers::new(1024)).len() < 1024);
-        assert!(format!("{:?}", Buffers::new(1)).len() > 0);
+        assert!(!format!("{:?}", Buffers::new(1)).is_empty());
     }
 }
@@ -0,0 +1,28 @@
+/// Values with a smaller magnitude than this are flushed to zero by `flush_denormal`. This is well
+/// above the denormal range so that multiplying a flushed value cannot produce a denormal either.
+pub const DENORMAL_THRESHOLD: f32 = 1e-20;
+
+/// Return `0.0` if `v` is tiny enough to be inaudible. Decaying feedback state, like the state of a
+/// filter fed silence, would otherwise end up as denormal floats which are very slow to compute
+/// with on most CPUs.
+#[inline]
+pub fn flush_denormal(v: f32) -> f32 {
+    if v.abs() < DENORMAL_THRESHOLD {
+        0.0
+    } else {
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiny_values_are_flushed() {
+        assert_eq!(flush_denormal(f32::MIN_POSITIVE / 2.0), 0.0);
+        assert_eq!(flush_denormal(-1e-30), 0.0);
+        assert_eq!(flush_denormal(1e-6), 1e-6);
+        assert_eq!(flush_denormal(-0.5), -0.5);
+    }
+}
@@ -16,6 +16,41 @@ pub struct EnvelopeParams {
     /// The decay in seconds. Required in cases where recomputation is needed and decay is not
     /// computable.
     decay_seconds: f32,
+    /// The release in seconds. Required in cases where recomputation is needed, such as when the
+    /// sustain changes.
+    release_seconds: f32,
+    /// The curve of the attack phase.
+    attack_curve: EnvelopeCurve,
+    /// The curve of the decay phase.
+    decay_curve: EnvelopeCurve,
+    /// The curve of the release phase.
+    release_curve: EnvelopeCurve,
+    /// Advances the amp during the attack phase.
+    attack_step: CurveStep,
+    /// Advances the amp during the decay phase.
+    decay_step: CurveStep,
+    /// Advances the amp during the release phase.
+    release_step: CurveStep,
+}
+
+/// The shape of an envelope phase. All curves take the same amount of time to complete the phase.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EnvelopeCurve {
+    /// The amp changes by the same amount every sample.
+    #[default]
+    Linear,
+    /// The amp changes by the same number of decibels every sample.
+    Exponential,
+    /// The amp moves towards a target just past the end of the phase with a one pole filter. This
+    /// is the shape of an analog envelope.
+    OnePole,
+}
+
+/// Advances the amp by a single sample by computing `amp * mul + add`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct CurveStep {
+    mul: f32,
+    add: f32,
 }
 
 impl Default for EnvelopeParams {
@@ -28,10 +63,96 @@ impl Default for EnvelopeParams {
             release_delta: -1.0,
             sustain_amp: 1.0,
             decay_seconds: 0.0,
+            release_seconds: 0.0,
+            attack_curve: EnvelopeCurve::Linear,
+            decay_curve: EnvelopeCurve::Linear,
+            release_curve: EnvelopeCurve::Linear,
+            attack_step: CurveStep::linear(1.0),
+            decay_step: CurveStep::linear(-1.0),
+            release_step: CurveStep::linear(-1.0),
+        }
+    }
+}
+
+impl EnvelopeCurve {
+    /// All the curves.
+    pub const ALL: &'static [EnvelopeCurve] = &[
+        EnvelopeCurve::Linear,
+        EnvelopeCurve::Exponential,
+        EnvelopeCurve::OnePole,
+    ];
+
+    /// The human readable name of the curve.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnvelopeCurve::Linear => "Linear",
+            EnvelopeCurve::Exponential => "Exponential",
+            EnvelopeCurve::OnePole => "One Pole",
         }
     }
 }
 
+impl CurveStep {
+    /// The distance below `0.0` that exponential curves decay towards. Without it, an exponential
+    /// curve could never start from or reach `0.0`.
+    const EXPONENTIAL_FLOOR: f32 = 0.001;
+
+    /// How far past the target a rising one pole curve aims, relative to the size of the phase.
+    const ONE_POLE_RISING_OVERSHOOT: f32 = 0.3;
+
+    /// How far past the target a falling one pole curve aims, relative to the size of the phase.
+    const ONE_POLE_FALLING_OVERSHOOT: f32 = 0.001;
+
+    /// A step that adds `delta` every sample.
+    const fn linear(delta: f32) -> CurveStep {
+        CurveStep {
+            mul: 1.0,
+            add: delta,
+        }
+    }
+
+    /// Create a step that moves from `start` to `target` with `curve`. `delta` is the amount to
+    /// move each sample for a linear curve.
+    fn new(curve: EnvelopeCurve, start: f32, target: f32, delta: f32) -> CurveStep {
+        let frames = (target - start) / delta;
+        // Phases that take at most a single frame have no shape.
+        if frames <= 1.0 {
+            return CurveStep::linear(delta);
+        }
+        match curve {
+            EnvelopeCurve::Linear => CurveStep::linear(delta),
+            EnvelopeCurve::Exponential => {
+                // Grow or shrink geometrically around a point just below 0.0.
+                let floor = CurveStep::EXPONENTIAL_FLOOR;
+                let mul = ((target + floor) / (start + floor)).powf(frames.recip());
+                CurveStep {
+                    mul,
+                    add: floor * (mul - 1.0),
+                }
+            }
+            EnvelopeCurve::OnePole => {
+                let overshoot = if target > start {
+                    CurveStep::ONE_POLE_RISING_OVERSHOOT
+                } else {
+                    CurveStep::ONE_POLE_FALLING_OVERSHOOT
+                };
+                let aim = target + (target - start) * overshoot;
+                let mul = (overshoot / (1.0 + overshoot)).powf(frames.recip());
+                CurveStep {
+                    mul,
+                    add: aim * (1.0 - mul),
+                }
+            }
+        }
+    }
+
+    /// Advance `amp` by a single sample.
+    #[inline]
+    fn apply(&self, amp: f32) -> f32 {
+        amp * self.mul + self.add
+    }
+}
+
 impl EnvelopeParams {
     /// Create a new envelope params object.
     pub fn new(
@@ -64,6 +185,18 @@ impl EnvelopeParams {
             let attack_frames = sample_rate.sample_rate() * attack_seconds;
             self.attack_delta = 1.0 / attack_frames;
         }
+        self.set_attack_curve(self.attack_curve);
+    }
+
+    /// Get the curve of the attack phase.
+    pub fn attack_curve(&self) -> EnvelopeCurve {
+        self.attack_curve
+    }
+
+    /// Set the curve of the attack phase.
+    pub fn set_attack_curve(&mut self, curve: EnvelopeCurve) {
+        self.attack_curve = curve;
+        self.attack_step = CurveStep::new(curve, 0.0, 1.0, self.attack_delta);
     }
 
     /// Get the decay value.
@@ -82,6 +215,18 @@ impl EnvelopeParams {
             self.decay_delta = (self.sustain_amp - 1.0) / decay_frames;
         }
         debug_assert!(self.decay_delta < 0.0);
+        self.set_decay_curve(self.decay_curve);
+    }
+
+    /// Get the curve of the decay phase.
+    pub fn decay_curve(&self) -> EnvelopeCurve {
+        self.decay_curve
+    }
+
+    /// Set the curve of the decay phase.
+    pub fn set_decay_curve(&mut self, curve: EnvelopeCurve) {
+        self.decay_curve = curve;
+        self.decay_step = CurveStep::new(curve, 1.0, self.sustain_amp, self.decay_delta);
     }
 
     /// Returns the sustain of this [`EnvelopeParams`].
@@ -97,6 +242,7 @@ impl EnvelopeParams {
         );
         self.sustain_amp = sustain_amp;
         self.set_decay(sample_rate, self.decay_seconds);
+        self.set_release(sample_rate, self.release_seconds);
     }
 
     /// Get the release value in seconds.
@@ -108,12 +254,25 @@ impl EnvelopeParams {
     /// Sets the release of this [`EnvelopeParams`].
     pub fn set_release(&mut self, sample_rate: SampleRate, release_seconds: f32) {
         debug_assert!(release_seconds >= 0.0);
+        self.release_seconds = release_seconds;
         if release_seconds == 0.0 {
             self.release_delta = -1.0;
         } else {
             let release_frames = sample_rate.sample_rate() * release_seconds;
             self.release_delta = -self.sustain_amp / release_frames;
         }
+        self.set_release_curve(self.release_curve);
+    }
+
+    /// Get the curve of the release phase.
+    pub fn release_curve(&self) -> EnvelopeCurve {
+        self.release_curve
+    }
+
+    /// Set the curve of the release phase.
+    pub fn set_release_curve(&mut self, curve: EnvelopeCurve) {
+        self.release_curve = curve;
+        self.release_step = CurveStep::new(curve, self.sustain_amp, 0.0, self.release_delta);
     }
 }
 
@@ -161,14 +320,14 @@ impl Envelope {
     pub fn next_sample(&mut self, params: &EnvelopeParams) -> f32 {
         match self.stage {
             Stage::Attack => {
-                self.amp += params.attack_delta;
+                self.amp = params.attack_step.apply(self.amp);
                 if self.amp >= 1.0 {
                     self.amp = 1.0;
                     self.stage = Stage::Decay;
                 }
             }
             Stage::Decay => {
-                self.amp += params.decay_delta;
+                self.amp = params.decay_step.apply(self.amp);
                 if self.amp <= params.sustain_amp {
                     self.amp = params.sustain_amp;
                     self.stage = Stage::Sustain;
@@ -176,7 +335,7 @@ impl Envelope {
             }
             Stage::Sustain => {}
             Stage::Release => {
-                self.amp += params.release_delta;
+                self.amp = params.release_step.apply(self.amp);
                 if self.amp < 0.0 {
                     self.amp = 0.0;
                     self.stage = Stage::Done;
@@ -192,7 +351,7 @@ impl Envelope {
         &'a mut self,
         params: &'a EnvelopeParams,
         count: usize,
-    ) -> impl 'a + ExactSizeIterator + Iterator<Item = f32> {
+    ) -> impl 'a + ExactSizeIterator<Item = f32> {
         (0..count).map(|_| self.next_sample(params))
     }
 
@@ -202,10 +361,20 @@ impl Envelope {
         self.stage = Stage::Release;
     }
 
+    /// The amp that was returned by the last call to `next_sample`.
+    pub fn amp(&self) -> f32 {
+        self.amp
+    }
+
     /// Returns true if the envelope is still active.
     pub fn is_active(&self) -> bool {
         self.stage != Stage::Done
     }
+
+    /// Returns true if the envelope has been released.
+    pub fn is_released(&self) -> bool {
+        matches!(self.stage, Stage::Release | Stage::Done)
+    }
 }
 
 #[cfg(test)]
@@ -222,9 +391,10 @@ mod tests {
             assert!(active.is_active(), "{:?}", active);
         }
         {
-            let mut released = base.clone();
+            let mut released = base;
             released.release(&params);
             assert!(released.is_active(), "{:?}", released);
+            assert!(released.is_released(), "{:?}", released);
             for _ in released.iter_samples(&params, 1000) {}
             assert!(!released.is_active(), "{:?}", released);
         }
@@ -288,6 +458,16 @@ mod tests {
         assert_eq!(params.release(sample_rate), 0.8);
     }
 
+    #[test]
+    fn set_sustain_keeps_release_duration() {
+        let mut params = EnvelopeParams::default();
+        let sample_rate = SampleRate::new(64.0);
+        params.set_sustain(sample_rate, 0.5);
+        params.set_release(sample_rate, 0.8);
+        params.set_sustain(sample_rate, 0.25);
+        assert_eq!(params.release(sample_rate), 0.8);
+    }
+
     #[test]
     fn zero_second_durations_are_ok() {
         let mut params = EnvelopeParams::default();
@@ -303,6 +483,96 @@ mod tests {
         assert_eq!(params.release(sample_rate), 0.0);
     }
 
+    /// Count the number of frames in each of the attack, decay, and release phases.
+    fn phase_lengths(params: &EnvelopeParams) -> (usize, usize, usize) {
+        let mut env = Envelope::new();
+        let attack = 1 + env
+            .iter_samples(params, 100000)
+            .position(|a| a >= 1.0)
+            .unwrap();
+        let decay = 1 + env
+            .iter_samples(params, 100000)
+            .position(|a| a <= params.sustain())
+            .unwrap();
+        env.release(params);
+        let release = 1 + env
+            .iter_samples(params, 100000)
+            .position(|a| a <= 0.0)
+            .unwrap();
+        (attack, decay, release)
+    }
+
+    #[test]
+    fn curves_take_the_same_amount_of_time() {
+        let sample_rate = SampleRate::new(1000.0);
+        let mut params = EnvelopeParams::new(sample_rate, 0.1, 0.2, 0.25, 0.3);
+        for curve in EnvelopeCurve::ALL.iter().copied() {
+            params.set_attack_curve(curve);
+            params.set_decay_curve(curve);
+            params.set_release_curve(curve);
+            let (attack, decay, release) = phase_lengths(&params);
+            assert!(attack.abs_diff(100) <= 1, "{curve:?} attack={attack}");
+            assert!(decay.abs_diff(200) <= 1, "{curve:?} decay={decay}");
+            assert!(release.abs_diff(300) <= 1, "{curve:?} release={release}");
+        }
+    }
+
+    #[test]
+    fn curves_change_the_shape_of_phases() {
+        let sample_rate = SampleRate::new(1000.0);
+        let mut params = EnvelopeParams::new(sample_rate, 0.1, 0.0, 1.0, 0.1);
+        let halfway = |params: &EnvelopeParams| {
+            let mut env = Envelope::new();
+            let attack = env.iter_samples(params, 50).last().unwrap();
+            for _ in env.iter_samples(params, 100) {}
+            env.release(params);
+            let release = env.iter_samples(params, 50).last().unwrap();
+            (attack, release)
+        };
+        let (attack, release) = halfway(&params);
+        assert!((attack - 0.5).abs() < 1e-3, "{attack}");
+        assert!((release - 0.5).abs() < 1e-3, "{release}");
+
+        // Exponential curves spend most of their time at low amps.
+        params.set_attack_curve(EnvelopeCurve::Exponential);
+        params.set_release_curve(EnvelopeCurve::Exponential);
+        let (attack, release) = halfway(&params);
+        assert!(attack < 0.1, "{attack}");
+        assert!(release < 0.1, "{release}");
+
+        // One pole curves rise quickly and fall quickly.
+        params.set_attack_curve(EnvelopeCurve::OnePole);
+        params.set_release_curve(EnvelopeCurve::OnePole);
+        let (attack, release) = halfway(&params);
+        assert!(attack > 0.6, "{attack}");
+        assert!(release < 0.1, "{release}");
+    }
+
+    #[test]
+    fn curves_are_kept_when_durations_change() {
+        let sample_rate = SampleRate::new(64.0);
+        let mut params = EnvelopeParams::default();
+        params.set_attack_curve(EnvelopeCurve::OnePole);
+        params.set_decay_curve(EnvelopeCurve::Exponential);
+        params.set_release_curve(EnvelopeCurve::Exponential);
+        params.set_attack(sample_rate, 1.0);
+        params.set_sustain(sample_rate, 0.5);
+        params.set_decay(sample_rate, 1.0);
+        params.set_release(sample_rate, 1.0);
+        assert_eq!(params.attack_curve(), EnvelopeCurve::OnePole);
+        assert_eq!(params.decay_curve(), EnvelopeCurve::Exponential);
+        assert_eq!(params.release_curve(), EnvelopeCurve::Exponential);
+        assert_eq!(params.attack(sample_rate), 1.0);
+        assert_eq!(params.decay(sample_rate), 1.0);
+        assert_eq!(params.release(sample_rate), 1.0);
+
+        let mut reference = EnvelopeParams::new(sample_rate, 1.0, 1.0, 0.5, 1.0);
+        reference.set_attack_curve(EnvelopeCurve::OnePole);
+        reference.set_decay_curve(EnvelopeCurve::Exponential);
+        reference.set_release_curve(EnvelopeCurve::Exponential);
+        assert_eq!(params, reference);
+    }
+
     #[test]
     #[should_panic]
     fn bad_attack_panics() {
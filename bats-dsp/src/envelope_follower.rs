@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{denormal::flush_denormal, sample_rate::SampleRate};
+
+/// Tracks the amplitude of a signal. Rising amplitudes are followed over the attack time and
+/// falling amplitudes are followed over the release time.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EnvelopeFollower {
+    /// The attack in seconds.
+    attack_seconds: f32,
+    /// The release in seconds.
+    release_seconds: f32,
+    /// The smoothing coefficient used while the input is above the envelope.
+    attack_coefficient: f32,
+    /// The smoothing coefficient used while the input is below the envelope.
+    release_coefficient: f32,
+    /// The current envelope value.
+    envelope: f32,
+}
+
+impl EnvelopeFollower {
+    /// Create a new envelope follower.
+    pub fn new(
+        sample_rate: SampleRate,
+        attack_seconds: f32,
+        release_seconds: f32,
+    ) -> EnvelopeFollower {
+        let mut f = EnvelopeFollower {
+            attack_seconds,
+            release_seconds,
+            attack_coefficient: 0.0,
+            release_coefficient: 0.0,
+            envelope: 0.0,
+        };
+        f.set_attack(sample_rate, attack_seconds);
+        f.set_release(sample_rate, release_seconds);
+        f
+    }
+
+    /// Get the attack in seconds.
+    pub fn attack(&self) -> f32 {
+        self.attack_seconds
+    }
+
+    /// Set the attack.
+    pub fn set_attack(&mut self, sample_rate: SampleRate, attack_seconds: f32) {
+        debug_assert!(attack_seconds >= 0.0);
+        self.attack_seconds = attack_seconds;
+        self.attack_coefficient = coefficient(sample_rate, attack_seconds);
+    }
+
+    /// Get the release in seconds.
+    pub fn release(&self) -> f32 {
+        self.release_seconds
+    }
+
+    /// Set the release.
+    pub fn set_release(&mut self, sample_rate: SampleRate, release_seconds: f32) {
+        debug_assert!(release_seconds >= 0.0);
+        self.release_seconds = release_seconds;
+        self.release_coefficient = coefficient(sample_rate, release_seconds);
+    }
+
+    /// Get the current envelope value.
+    pub fn value(&self) -> f32 {
+        self.envelope
+    }
+
+    /// Process the next sample and return the new envelope value.
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let input = sample.abs();
+        let coefficient = if input > self.envelope {
+            self.attack_coefficient
+        } else {
+            self.release_coefficient
+        };
+        self.envelope = flush_denormal(input + coefficient * (self.envelope - input));
+        self.envelope
+    }
+}
+
+/// The one pole smoothing coefficient that reaches ~63% of a step over `seconds`.
+fn coefficient(sample_rate: SampleRate, seconds: f32) -> f32 {
+    if seconds == 0.0 {
+        0.0
+    } else {
+        (-1.0 / (seconds * sample_rate.sample_rate())).exp()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_attack_and_release_follow_input_immediately() {
+        let mut f = EnvelopeFollower::new(SampleRate::new(44100.0), 0.0, 0.0);
+        assert_eq!(f.process(0.5), 0.5);
+        assert_eq!(f.process(-0.75), 0.75);
+        assert_eq!(f.process(0.25), 0.25);
+    }
+
+    #[test]
+    fn envelope_rises_over_attack_and_falls_over_release() {
+        let sample_rate = SampleRate::new(1000.0);
+        let mut f = EnvelopeFollower::new(sample_rate, 0.01, 0.1);
+        for _ in 0..10 {
+            f.process(1.0);
+        }
+        // After one time constant, the envelope should be ~63% of the way to the input.
+        assert!((f.value() - 0.632).abs() < 0.01, "{}", f.value());
+        for _ in 0..100 {
+            f.process(1.0);
+        }
+        assert!(f.value() > 0.99, "{}", f.value());
+        for _ in 0..100 {
+            f.process(0.0);
+        }
+        assert!((f.value() - 0.368).abs() < 0.01, "{}", f.value());
+    }
+
+    #[test]
+    fn get_and_set_params_produces_consistent_values() {
+        let sample_rate = SampleRate::new(44100.0);
+        let mut f = EnvelopeFollower::new(sample_rate, 0.0, 0.0);
+        f.set_attack(sample_rate, 0.2);
+        f.set_release(sample_rate, 0.3);
+        assert_eq!(f.attack(), 0.2);
+        assert_eq!(f.release(), 0.3);
+    }
+
+    #[test]
+    fn silence_after_signal_decays_to_zero() {
+        let mut f = EnvelopeFollower::new(SampleRate::new(44100.0), 0.001, 0.01);
+        f.process(1.0);
+        for _ in 0..44100 {
+            f.process(0.0);
+        }
+        assert_eq!(f.value(), 0.0);
+    }
+}
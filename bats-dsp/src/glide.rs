@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+
+use crate::sample_rate::SampleRate;
+
+/// Slews a frequency towards a target frequency over a fixed duration.
+///
+/// The slew is exponential so that the pitch (as opposed to the raw frequency) changes linearly
+/// over time.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Glide {
+    /// The current frequency.
+    frequency: f32,
+    /// The frequency to glide towards.
+    target: f32,
+    /// The ratio to multiply the frequency by for each sample.
+    ratio_per_sample: f32,
+    /// The number of samples remaining until the target is reached.
+    remaining_samples: u32,
+}
+
+impl Glide {
+    /// Create a new `Glide` that is resting at `frequency`.
+    pub fn new(frequency: f32) -> Glide {
+        Glide {
+            frequency,
+            target: frequency,
+            ratio_per_sample: 1.0,
+            remaining_samples: 0,
+        }
+    }
+
+    /// Set the frequency immediately without any gliding.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        *self = Glide::new(frequency);
+    }
+
+    /// Glide from the current frequency to `target` over `duration_seconds`.
+    pub fn set_target(&mut self, sample_rate: SampleRate, target: f32, duration_seconds: f32) {
+        let samples = (duration_seconds * sample_rate.sample_rate()) as u32;
+        if samples == 0 || self.frequency <= 0.0 || target <= 0.0 {
+            self.set_frequency(target);
+            return;
+        }
+        self.target = target;
+        self.ratio_per_sample = (target / self.frequency).powf(1.0 / samples as f32);
+        self.remaining_samples = samples;
+    }
+
+    /// Returns true if the frequency is still moving towards the target.
+    pub fn is_gliding(&self) -> bool {
+        self.remaining_samples > 0
+    }
+
+    /// Get the current frequency.
+    pub fn frequency(&self) -> f32 {
+        self.frequency
+    }
+
+    /// Get the frequency that is being glided towards.
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    /// Advance by a single sample and return the new frequency.
+    pub fn next_frequency(&mut self) -> f32 {
+        match self.remaining_samples {
+            0 => (),
+            1 => {
+                self.frequency = self.target;
+                self.remaining_samples = 0;
+            }
+            _ => {
+                self.frequency *= self.ratio_per_sample;
+                self.remaining_samples -= 1;
+            }
+        }
+        self.frequency
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_glide_is_not_gliding() {
+        let mut glide = Glide::new(440.0);
+        assert!(!glide.is_gliding());
+        assert_eq!(glide.next_frequency(), 440.0);
+    }
+
+    #[test]
+    fn glide_reaches_target_after_duration() {
+        let sample_rate = SampleRate::new(100.0);
+        let mut glide = Glide::new(110.0);
+        glide.set_target(sample_rate, 220.0, 0.1);
+        let frequencies: Vec<_> = (0..10).map(|_| glide.next_frequency()).collect();
+        assert!(!glide.is_gliding());
+        assert_eq!(frequencies.last().copied(), Some(220.0));
+        for pair in frequencies.windows(2) {
+            assert!(pair[0] < pair[1], "{frequencies:?}");
+        }
+    }
+
+    #[test]
+    fn glide_is_linear_in_pitch() {
+        let sample_rate = SampleRate::new(100.0);
+        let mut glide = Glide::new(100.0);
+        glide.set_target(sample_rate, 400.0, 0.1);
+        let halfway = (0..5).map(|_| glide.next_frequency()).last().unwrap();
+        assert!((halfway - 200.0).abs() < 0.01, "{halfway}");
+    }
+
+    #[test]
+    fn zero_duration_glide_snaps_to_target() {
+        let mut glide = Glide::new(110.0);
+        glide.set_target(SampleRate::new(44100.0), 220.0, 0.0);
+        assert!(!glide.is_gliding());
+        assert_eq!(glide.frequency(), 220.0);
+    }
+}
@@ -1,6 +1,14 @@
 pub mod buffers;
+pub mod denormal;
 pub mod envelope;
+pub mod envelope_follower;
+pub mod glide;
 pub mod moog_filter;
+pub mod noise;
 pub mod position;
+pub mod rng;
 pub mod sample_rate;
 pub mod sawtooth;
+pub mod slice;
+pub mod smoothed_value;
+pub mod stretch;
@@ -1,6 +1,4 @@
-
-
-use crate::sample_rate::SampleRate;
+use crate::{denormal::flush_denormal, sample_rate::SampleRate};
 
 /// A classic Moog low pass filter.
 ///
@@ -49,9 +47,16 @@ impl MoogFilter {
         self.r = resonance * (t2 + 6.0 * t1) / (t2 - 6.0 * t1);
     }
 
+    /// Copy the cutoff and resonance of `other` while keeping the current filter state.
+    pub fn copy_cutoff(&mut self, other: &MoogFilter) {
+        self.r = other.r;
+        self.p = other.p;
+        self.k = other.k;
+    }
+
     /// Process the next sample.
     pub fn process(&mut self, sample: f32) -> f32 {
-        let x = sample - self.r * self.stage[3];
+        let x = flush_denormal(sample - self.r * self.stage[3]);
 
         // Four cascaded one-pole filters (bilinear transform).
         self.stage[0] = x * self.p + self.delay[0] * self.p - self.k * self.stage[0];
@@ -63,6 +68,9 @@ impl MoogFilter {
         // Clipping band-limited sigmoid
         self.stage[3] -= (self.stage[3] * self.stage[3] * self.stage[3]) / 6.0;
         self.stage[3] = self.stage[3].clamp(-1.0, 1.0);
+        for stage in self.stage.iter_mut() {
+            *stage = flush_denormal(*stage);
+        }
 
         self.delay[0] = x;
         self.delay[1] = self.stage[0];
@@ -98,4 +106,34 @@ mod tests {
         MoogFilter::new(sample_rate).process_batch(output.as_mut_slice());
         assert_ne!(output.as_slice(), input.as_slice());
     }
+
+    #[test]
+    fn copy_cutoff_keeps_state() {
+        let sample_rate = SampleRate::new(44100.0);
+        let mut filter = MoogFilter::new(sample_rate);
+        filter.process(1.0);
+        let mut other = MoogFilter::new(sample_rate);
+        other.set_cutoff(sample_rate, 100.0, 0.5);
+
+        let mut expected = filter;
+        expected.set_cutoff(sample_rate, 100.0, 0.5);
+        filter.copy_cutoff(&other);
+        assert_eq!(filter, expected);
+    }
+
+    #[test]
+    fn silence_after_signal_decays_to_zero() {
+        let sample_rate = SampleRate::new(44100.0);
+        let mut filter = MoogFilter::new(sample_rate);
+        filter.process(1.0);
+        for _ in 0..44100 * 10 {
+            filter.process(0.0);
+        }
+        assert_eq!(filter.process(0.0), 0.0);
+        assert!(filter
+            .stage
+            .iter()
+            .chain(filter.delay.iter())
+            .all(|v| *v == 0.0));
+    }
 }
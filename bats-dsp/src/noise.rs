@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+use crate::rng::Rng;
+
+/// A white noise generator. Noise generators created with the same seed produce the same signal.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Noise {
+    /// The xorshift state. Must never be 0.
+    state: u32,
+}
+
+impl Default for Noise {
+    /// Create a noise generator with the default seed.
+    fn default() -> Noise {
+        Noise::new(0x9E37_79B9)
+    }
+}
+
+impl Noise {
+    /// Create a new noise generator with the given seed.
+    pub fn new(seed: u32) -> Noise {
+        Noise { state: seed.max(1) }
+    }
+
+    /// Create a new noise generator that is seeded from `rng`.
+    pub fn from_rng(rng: &mut Rng) -> Noise {
+        Noise::new(rng.next_u32())
+    }
+
+    /// Get the next sample within `[-1.0, 1.0]`.
+    #[inline]
+    pub fn next_sample(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_is_within_range_and_not_constant() {
+        let mut noise = Noise::default();
+        let samples: Vec<_> = (0..4096).map(|_| noise.next_sample()).collect();
+        assert!(samples.iter().all(|s| (-1.0..=1.0).contains(s)));
+        assert!(samples.iter().any(|s| *s < -0.5));
+        assert!(samples.iter().any(|s| *s > 0.5));
+    }
+
+    #[test]
+    fn zero_seed_still_produces_noise() {
+        let mut noise = Noise::new(0);
+        assert_ne!(noise.next_sample(), noise.next_sample());
+    }
+}
@@ -21,6 +21,9 @@ impl Position {
     /// The minimum (non-zero) represntable position.
     pub const DELTA: Position = Position { beat: 1 };
 
+    /// The number of beats in a bar.
+    pub const BEATS_PER_BAR: u32 = 4;
+
     /// Create a new `Position` with the given beat and sub_beat. If
     /// `sub_beat` is greater than 0, then it is converted into the
     /// appropriate amount of sub beats.
@@ -40,6 +43,12 @@ impl Position {
         }
     }
 
+    /// Create a new `Position` that is `beats` into bar `bars`. Both `bars` and `beats` start at
+    /// 0.
+    pub fn from_beats_bars(bars: u32, beats: f64) -> Position {
+        Position::new((bars * Position::BEATS_PER_BAR) as f64 + beats)
+    }
+
     /// Get the delta for each BPM. This is the amount of position that advances for every sample.
     pub fn delta_from_bpm(sample_rate: SampleRate, bpm: f32) -> Position {
         let beats_per_second = bpm / 60.0;
@@ -56,10 +65,72 @@ impl Position {
         (self.beat & 0x00000000FFFFFFFF) as u32
     }
 
+    /// Get the bar for `self`.
+    pub fn bar(&self) -> u32 {
+        self.beat() / Position::BEATS_PER_BAR
+    }
+
+    /// Get the beat within the bar for `self`.
+    pub fn beat_in_bar(&self) -> u32 {
+        self.beat() % Position::BEATS_PER_BAR
+    }
+
+    /// Get the position as a number of beats.
+    pub fn as_beats_f64(&self) -> f64 {
+        self.beat() as f64 + self.sub_beat() as f64 / (1u64 << 32) as f64
+    }
+
+    /// Get the raw bits for `self`. Useful for storing a position in an atomic.
+    pub const fn to_bits(self) -> u64 {
+        self.beat
+    }
+
+    /// Create a position from the bits returned by `to_bits`.
+    pub const fn from_bits(bits: u64) -> Position {
+        Position { beat: bits }
+    }
+
     /// Set the beat component for `self`.
     pub fn set_beat(&mut self, beat: u32) {
         *self = Position::with_components(beat, self.sub_beat())
     }
+
+    /// Round `self` down to a multiple of `grid`. If `grid` is `Position::MIN`, then `self` is
+    /// returned unchanged.
+    pub fn floor_to_grid(self, grid: Position) -> Position {
+        match grid.beat {
+            0 => self,
+            g => Position {
+                beat: self.beat - self.beat % g,
+            },
+        }
+    }
+
+    /// Round `self` to the nearest multiple of `grid`. Positions exactly halfway between grid
+    /// lines are rounded up. If `grid` is `Position::MIN`, then `self` is returned unchanged.
+    pub fn snap_to_grid(self, grid: Position) -> Position {
+        let floor = self.floor_to_grid(grid);
+        if grid.beat != 0 && self.beat - floor.beat >= grid.beat - grid.beat / 2 {
+            floor + grid
+        } else {
+            floor
+        }
+    }
+
+    /// Get the distance from `rhs` to `self` in a loop of length `loop_length`. If `rhs` is after
+    /// `self`, then the distance wraps around the end of the loop.
+    pub fn wrapping_sub_in_loop(self, rhs: Position, loop_length: Position) -> Position {
+        debug_assert!(loop_length != Position::MIN);
+        let a = self.beat % loop_length.beat;
+        let b = rhs.beat % loop_length.beat;
+        Position {
+            beat: if a >= b {
+                a - b
+            } else {
+                loop_length.beat - (b - a)
+            },
+        }
+    }
 }
 
 impl std::ops::Add for Position {
@@ -72,6 +143,16 @@ impl std::ops::Add for Position {
     }
 }
 
+impl std::ops::Sub for Position {
+    type Output = Position;
+
+    fn sub(self, rhs: Position) -> Position {
+        Position {
+            beat: self.beat.wrapping_sub(rhs.beat),
+        }
+    }
+}
+
 impl std::ops::AddAssign for Position {
     fn add_assign(&mut self, rhs: Self) {
         *self = *self + rhs;
@@ -105,6 +186,18 @@ mod tests {
         assert_eq!(p.sub_beat(), ((1u64 << 32) / 2) as u32);
     }
 
+    #[test]
+    fn as_beats_f64_returns_fractional_beats() {
+        assert_eq!(Position::new(11.5).as_beats_f64(), 11.5);
+        assert_eq!(Position::MIN.as_beats_f64(), 0.0);
+    }
+
+    #[test]
+    fn bits_round_trip() {
+        let p = Position::new(3.25);
+        assert_eq!(Position::from_bits(p.to_bits()), p);
+    }
+
     #[test]
     fn add_beat_adds_components_and_carries_the_sub_beat() {
         assert_eq!(
@@ -138,4 +231,51 @@ mod tests {
             "\"{debug_string}\" does not contain 4.5."
         );
     }
+
+    #[test]
+    fn bars_and_beats_round_trip() {
+        let p = Position::from_beats_bars(2, 1.5);
+        assert_eq!(p, Position::new(9.5));
+        assert_eq!(p.bar(), 2);
+        assert_eq!(p.beat_in_bar(), 1);
+    }
+
+    #[test]
+    fn sub_subtracts_and_wraps() {
+        assert_eq!(
+            Position::new(5.375) - Position::new(3.75),
+            Position::new(1.625)
+        );
+        assert_eq!(Position::MIN - Position::DELTA, Position::MAX);
+    }
+
+    #[test]
+    fn floor_and_snap_to_grid() {
+        let grid = Position::new(0.25);
+        assert_eq!(Position::new(1.3).floor_to_grid(grid), Position::new(1.25));
+        assert_eq!(Position::new(1.3).snap_to_grid(grid), Position::new(1.25));
+        assert_eq!(Position::new(1.4).snap_to_grid(grid), Position::new(1.5));
+        assert_eq!(Position::new(1.375).snap_to_grid(grid), Position::new(1.5));
+        assert_eq!(
+            Position::new(1.3).snap_to_grid(Position::MIN),
+            Position::new(1.3)
+        );
+    }
+
+    #[test]
+    fn wrapping_sub_in_loop_wraps_around_loop_end() {
+        let loop_length = Position::new(16.0);
+        assert_eq!(
+            Position::new(3.0).wrapping_sub_in_loop(Position::new(1.0), loop_length),
+            Position::new(2.0)
+        );
+        assert_eq!(
+            Position::new(1.0).wrapping_sub_in_loop(Position::new(15.0), loop_length),
+            Position::new(2.0)
+        );
+        assert_eq!(
+            Position::new(4.0).wrapping_sub_in_loop(Position::new(4.0), loop_length),
+            Position::MIN
+        );
+    }
 }
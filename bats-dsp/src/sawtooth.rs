@@ -33,6 +33,22 @@ impl Sawtooth {
         self.amplitude_per_sample = amplitude_per_sample;
     }
 
+    /// Get the phase of the wave within `[0.0, 1.0)`. A new wave starts at phase `0.0`, where
+    /// the output is `0.0` and rising.
+    #[inline]
+    pub fn phase(&self) -> f32 {
+        (0.5 * self.amplitude).rem_euclid(1.0)
+    }
+
+    /// Set the phase of the wave. Values outside of `[0.0, 1.0)` wrap around.
+    #[inline]
+    pub fn set_phase(&mut self, phase: f32) {
+        self.amplitude = 2.0 * phase.rem_euclid(1.0);
+        if self.amplitude > 1.0 {
+            self.amplitude -= 2.0;
+        }
+    }
+
     /// Get the next sample in the sawtooth wave.
     #[inline]
     pub fn next_sample(&mut self) -> f32 {
@@ -57,11 +73,29 @@ mod tests {
     fn sawtooth_output_depends_on_frequency() {
         let a = Sawtooth::new(SampleRate::new(44100.0), 1000.0);
         let b = {
-            let mut b = a.clone();
+            let mut b = a;
             b.set_frequency(SampleRate::new(44100.0), 2000.0);
             b
         };
         assert_eq!(generate_signals(a), generate_signals(a));
         assert_ne!(generate_signals(a), generate_signals(b));
     }
+
+    #[test]
+    fn setting_phase_restarts_wave() {
+        let fresh = Sawtooth::new(SampleRate::new(44100.0), 1000.0);
+        let mut wave = fresh;
+        generate_signals(wave);
+        for _ in 0..100 {
+            wave.next_sample();
+        }
+        assert_ne!(wave.phase(), 0.0);
+        wave.set_phase(0.0);
+        assert_eq!(generate_signals(wave), generate_signals(fresh));
+
+        wave.set_phase(0.75);
+        assert_eq!(wave.phase(), 0.75);
+        wave.set_phase(1.25);
+        assert_eq!(wave.phase(), 0.25);
+    }
 }
@@ -0,0 +1,113 @@
+use crate::{buffers::Buffers, sample_rate::SampleRate};
+
+/// The number of seconds of audio that are compared when looking for onsets.
+const HOP_SECONDS: f32 = 0.005;
+
+/// The number of previous hops that the energy of a hop is compared against.
+const LOOKBACK_HOPS: usize = 4;
+
+/// The ratio that the energy must rise by over the previous hops to count as an onset. `4.0` is
+/// roughly a 6 dB rise.
+const RISE_RATIO: f32 = 4.0;
+
+/// The energy that a hop must have to count as an onset. Quieter hops are treated as silence.
+const SILENCE_ENERGY: f32 = 1e-5;
+
+/// The minimum number of seconds between two onsets.
+const MIN_GAP_SECONDS: f32 = 0.05;
+
+/// Find the frames where new sounds, like drum hits, start in `buffers`. Returns the first frame of
+/// each slice in order. The first slice always starts at frame `0` unless `buffers` is empty.
+///
+/// Slices start one hop before the hop where the rise was detected so that the attack of each
+/// sound is not cut off.
+pub fn onset_slices(buffers: &Buffers, sample_rate: SampleRate) -> Vec<usize> {
+    if buffers.is_empty() {
+        return Vec::new();
+    }
+    let hop = ((sample_rate.sample_rate() * HOP_SECONDS) as usize).max(1);
+    let min_gap = (sample_rate.sample_rate() * MIN_GAP_SECONDS) as usize;
+    let energies: Vec<f32> = buffers
+        .left
+        .chunks(hop)
+        .zip(buffers.right.chunks(hop))
+        .map(|(left, right)| {
+            let sum: f32 = left
+                .iter()
+                .zip(right.iter())
+                .map(|(l, r)| 0.5 * (l * l + r * r))
+                .sum();
+            sum / left.len() as f32
+        })
+        .collect();
+    let mut slices = vec![0];
+    for (idx, energy) in energies.iter().enumerate().skip(1) {
+        let previous = &energies[idx.saturating_sub(LOOKBACK_HOPS)..idx];
+        let previous_energy = previous.iter().sum::<f32>() / previous.len() as f32;
+        if *energy < SILENCE_ENERGY || *energy < RISE_RATIO * previous_energy {
+            continue;
+        }
+        let start = (idx - 1) * hop;
+        if start >= slices.last().unwrap() + min_gap {
+            slices.push(start);
+        }
+    }
+    slices
+}
+
+/// Split `len` frames into `count` slices of equal length. Returns the first frame of each slice
+/// in order. Fewer slices are returned if there are fewer than `count` frames.
+pub fn grid_slices(len: usize, count: usize) -> Vec<usize> {
+    let count = count.min(len);
+    (0..count).map(|n| n * len / count).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create a decaying burst of sine waves at each frame in `hits`.
+    fn drum_loop(hits: &[usize], len: usize) -> Buffers {
+        Buffers::with_iter((0..len).map(|n| {
+            let v = hits
+                .iter()
+                .filter(|hit| **hit <= n)
+                .map(|hit| {
+                    let t = (n - hit) as f32;
+                    (0.05 * t).sin() * (-t / 2000.0).exp()
+                })
+                .sum::<f32>();
+            (v, v)
+        }))
+    }
+
+    #[test]
+    fn onset_slices_finds_each_hit() {
+        let sample_rate = SampleRate::new(44100.0);
+        let hits = [0, 11025, 22050, 27562, 33075];
+        let slices = onset_slices(&drum_loop(&hits, 44100), sample_rate);
+        assert_eq!(slices.len(), hits.len(), "{slices:?}");
+        let hop = (44100.0 * HOP_SECONDS) as usize;
+        for (slice, hit) in slices.iter().zip(hits.iter()) {
+            assert!(*slice <= *hit && *hit < slice + 2 * hop, "{slices:?}");
+        }
+    }
+
+    #[test]
+    fn onset_slices_of_silence_is_single_slice() {
+        let sample_rate = SampleRate::new(44100.0);
+        assert_eq!(onset_slices(&Buffers::new(44100), sample_rate), vec![0]);
+        assert_eq!(
+            onset_slices(&Buffers::new(0), sample_rate),
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn grid_slices_are_evenly_spaced() {
+        assert_eq!(grid_slices(16, 4), vec![0, 4, 8, 12]);
+        assert_eq!(grid_slices(10, 4), vec![0, 2, 5, 7]);
+        assert_eq!(grid_slices(2, 4), vec![0, 1]);
+        assert_eq!(grid_slices(0, 4), Vec::<usize>::new());
+    }
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+
+use crate::sample_rate::SampleRate;
+
+/// A value that ramps linearly towards its target. Used to avoid zipper noise when a parameter
+/// changes abruptly.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SmoothedValue {
+    /// The current value.
+    value: f32,
+    /// The value to ramp towards.
+    target: f32,
+    /// The amount to add to `value` on each sample.
+    delta: f32,
+    /// The number of samples until `target` is reached.
+    remaining_samples: u32,
+}
+
+impl SmoothedValue {
+    /// Create a new `SmoothedValue` that is resting at `value`.
+    pub fn new(value: f32) -> SmoothedValue {
+        SmoothedValue {
+            value,
+            target: value,
+            delta: 0.0,
+            remaining_samples: 0,
+        }
+    }
+
+    /// Set the value immediately without any smoothing.
+    pub fn set_value(&mut self, value: f32) {
+        *self = SmoothedValue::new(value);
+    }
+
+    /// Ramp from the current value to `target` over `samples`.
+    pub fn set_target(&mut self, target: f32, samples: usize) {
+        if samples == 0 {
+            self.set_value(target);
+            return;
+        }
+        self.target = target;
+        self.delta = (target - self.value) / samples as f32;
+        self.remaining_samples = samples as u32;
+    }
+
+    /// Ramp from the current value to `target` over `duration_seconds`.
+    pub fn set_target_with_duration(
+        &mut self,
+        sample_rate: SampleRate,
+        target: f32,
+        duration_seconds: f32,
+    ) {
+        let samples = duration_seconds * sample_rate.sample_rate();
+        self.set_target(target, samples as usize);
+    }
+
+    /// Get the current value.
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Get the target value.
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    /// Returns true if the value is still ramping towards the target.
+    pub fn is_smoothing(&self) -> bool {
+        self.remaining_samples > 0
+    }
+
+    /// Advance by a single sample and return the new value.
+    #[inline]
+    pub fn next_value(&mut self) -> f32 {
+        match self.remaining_samples {
+            0 => (),
+            1 => {
+                self.value = self.target;
+                self.remaining_samples = 0;
+            }
+            _ => {
+                self.value += self.delta;
+                self.remaining_samples -= 1;
+            }
+        }
+        self.value
+    }
+}
+
+impl Default for SmoothedValue {
+    fn default() -> SmoothedValue {
+        SmoothedValue::new(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_value_is_not_smoothing() {
+        let mut v = SmoothedValue::new(0.5);
+        assert!(!v.is_smoothing());
+        assert_eq!(v.next_value(), 0.5);
+    }
+
+    #[test]
+    fn ramps_linearly_to_target() {
+        let mut v = SmoothedValue::new(0.0);
+        v.set_target(1.0, 4);
+        let values: Vec<_> = (0..6).map(|_| v.next_value()).collect();
+        assert_eq!(values, vec![0.25, 0.5, 0.75, 1.0, 1.0, 1.0]);
+        assert!(!v.is_smoothing());
+    }
+
+    #[test]
+    fn retargeting_starts_from_current_value() {
+        let mut v = SmoothedValue::new(0.0);
+        v.set_target(1.0, 4);
+        v.next_value();
+        v.next_value();
+        v.set_target(0.0, 2);
+        let values: Vec<_> = (0..2).map(|_| v.next_value()).collect();
+        assert_eq!(values, vec![0.25, 0.0]);
+    }
+
+    #[test]
+    fn zero_samples_sets_value_immediately() {
+        let mut v = SmoothedValue::new(0.0);
+        v.set_target(1.0, 0);
+        assert_eq!(v.value(), 1.0);
+        assert!(!v.is_smoothing());
+    }
+
+    #[test]
+    fn set_target_with_duration() {
+        let mut v = SmoothedValue::new(0.0);
+        v.set_target_with_duration(SampleRate::new(100.0), 1.0, 0.1);
+        for _ in 0..9 {
+            v.next_value();
+        }
+        assert!(v.is_smoothing());
+        assert_eq!(v.next_value(), 1.0);
+        assert!(!v.is_smoothing());
+    }
+}
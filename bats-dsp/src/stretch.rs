@@ -0,0 +1,212 @@
+use crate::buffers::Buffers;
+
+/// Plays audio at a different speed and pitch by overlapping short grains of the source. Speed and
+/// pitch are independent, so a loop can be repitched without changing its duration or stretched
+/// without changing its pitch.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GranularStretch {
+    /// The number of frames in each grain.
+    grain_frames: usize,
+    /// The number of source frames to advance for each output frame.
+    speed: f64,
+    /// The rate that each grain plays the source at.
+    pitch: f64,
+    /// The position within the source, in frames, that the next grain starts at.
+    position: f64,
+    /// Two grains that are half a grain apart. Their windows sum to `1.0`.
+    grains: [Grain; 2],
+}
+
+/// A single grain of audio.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Grain {
+    /// The position within the source, in frames, that plays at the peak of the grain's window.
+    center: f64,
+    /// The number of frames that the grain has played.
+    age: usize,
+}
+
+impl GranularStretch {
+    /// Create a new stretcher with grains of `grain_frames` frames. The stretcher starts at the
+    /// beginning of the source with the original speed and pitch.
+    pub fn new(grain_frames: usize) -> GranularStretch {
+        let mut stretch = GranularStretch {
+            grain_frames: grain_frames.max(2),
+            speed: 1.0,
+            pitch: 1.0,
+            position: 0.0,
+            grains: [Grain {
+                center: 0.0,
+                age: 0,
+            }; 2],
+        };
+        stretch.reset();
+        stretch
+    }
+
+    /// Restart from the beginning of the source.
+    pub fn reset(&mut self) {
+        self.seek(0.0);
+    }
+
+    /// Restart from `position` frames into the source.
+    pub fn seek(&mut self, position: f64) {
+        let half = self.grain_frames / 2;
+        self.position = position;
+        // Start the first grain at the peak of its window so that the first frame plays at full
+        // volume.
+        self.grains = [
+            Grain {
+                center: position,
+                age: half,
+            },
+            Grain {
+                center: position + half as f64 * self.speed,
+                age: 0,
+            },
+        ];
+    }
+
+    /// The number of source frames to advance for each output frame.
+    pub fn speed(&self) -> f32 {
+        self.speed as f32
+    }
+
+    /// Set the number of source frames to advance for each output frame. Values above `1.0` make
+    /// the audio shorter without changing the pitch.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0) as f64;
+    }
+
+    /// The ratio that the pitch is shifted by.
+    pub fn pitch(&self) -> f32 {
+        self.pitch as f32
+    }
+
+    /// Set the ratio that the pitch is shifted by. A value of `2.0` raises the pitch by an octave
+    /// without changing the duration.
+    pub fn set_pitch(&mut self, pitch: f32) {
+        self.pitch = pitch.max(0.0) as f64;
+    }
+
+    /// The position within the source, in frames, that playback has reached.
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    /// Get the next frame from `source`. Positions past either end of `source` are silent.
+    pub fn next_frame(&mut self, source: &Buffers) -> (f32, f32) {
+        let frame = if self.speed == 1.0 && self.pitch == 1.0 {
+            // The grains line up with the source so there is no need to mix them.
+            source.get(self.position as usize)
+        } else {
+            let half = (self.grain_frames / 2) as f64;
+            self.grains.iter().fold((0.0, 0.0), |(l, r), grain| {
+                let phase = grain.age as f32 / self.grain_frames as f32;
+                let window = (std::f32::consts::PI * phase).sin().powi(2);
+                let offset = (grain.age as f64 - half) * self.pitch;
+                let (gl, gr) = source.get_interpolated(grain.center + offset);
+                (l + window * gl, r + window * gr)
+            })
+        };
+        self.position += self.speed;
+        for grain in self.grains.iter_mut() {
+            grain.age += 1;
+            if grain.age >= self.grain_frames {
+                // The new grain reaches its peak half a grain from now.
+                let half = (self.grain_frames / 2) as f64;
+                grain.center = self.position + half * self.speed;
+                grain.age = 0;
+            }
+        }
+        frame
+    }
+}
+
+/// Convert `semitones` to the ratio that a frequency is multiplied by.
+pub fn semitones_to_ratio(semitones: f32) -> f32 {
+    (semitones / 12.0).exp2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, frames: usize) -> Buffers {
+        Buffers::with_iter((0..frames).map(|n| {
+            let v = (std::f32::consts::TAU * frequency * n as f32).sin();
+            (v, v)
+        }))
+    }
+
+    /// Count the number of times the signal goes from negative to positive.
+    fn rising_crossings(signal: &[f32]) -> usize {
+        signal
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count()
+    }
+
+    fn render(stretch: &mut GranularStretch, source: &Buffers) -> Vec<f32> {
+        let mut out = Vec::new();
+        while stretch.position() < source.len() as f64 {
+            out.push(stretch.next_frame(source).0);
+        }
+        out
+    }
+
+    #[test]
+    fn original_speed_and_pitch_plays_source() {
+        let source = sine(0.01, 1000);
+        let mut stretch = GranularStretch::new(100);
+        assert!(render(&mut stretch, &source) == source.left);
+    }
+
+    #[test]
+    fn pitch_changes_frequency_but_not_duration() {
+        let source = sine(0.01, 10000);
+        let mut stretch = GranularStretch::new(400);
+        stretch.set_pitch(semitones_to_ratio(12.0));
+        let out = render(&mut stretch, &source);
+        assert_eq!(out.len(), 10000);
+        let crossings = rising_crossings(&out);
+        assert!((190..=210).contains(&crossings), "{crossings}");
+    }
+
+    #[test]
+    fn speed_changes_duration_but_not_frequency() {
+        let source = sine(0.01, 10000);
+        let mut stretch = GranularStretch::new(400);
+        stretch.set_speed(2.0);
+        let out = render(&mut stretch, &source);
+        assert_eq!(out.len(), 5000);
+        let crossings = rising_crossings(&out);
+        assert!((45..=55).contains(&crossings), "{crossings}");
+    }
+
+    #[test]
+    fn reset_restarts_from_beginning() {
+        let source = sine(0.01, 1000);
+        let mut stretch = GranularStretch::new(100);
+        stretch.set_pitch(1.5);
+        let first = render(&mut stretch, &source);
+        stretch.reset();
+        assert_eq!(stretch.position(), 0.0);
+        assert!(render(&mut stretch, &source) == first);
+    }
+
+    #[test]
+    fn seek_matches_playing_from_position() {
+        let source = sine(0.01, 1000);
+        let mut stretch = GranularStretch::new(100);
+        stretch.seek(500.0);
+        assert!(render(&mut stretch, &source) == source.left[500..]);
+    }
+
+    #[test]
+    fn semitones_to_ratio_doubles_every_octave() {
+        assert_eq!(semitones_to_ratio(0.0), 1.0);
+        assert_eq!(semitones_to_ratio(12.0), 2.0);
+        assert_eq!(semitones_to_ratio(-24.0), 0.25);
+    }
+}
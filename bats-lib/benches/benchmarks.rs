@@ -1,9 +1,10 @@
 use std::time::Duration;
 
-use bats_dsp::{buffers::Buffers, sample_rate::SampleRate};
+use bats_dsp::{buffers::Buffers, position::Position, sample_rate::SampleRate};
 use bats_lib::{
-    builder::BatsBuilder,
+    builder::{BatsBuilder, PluginBuilder, TrackBuilder},
     plugin::{toof::Toof, BatsInstrument, BatsInstrumentExt},
+    sequence, Bats,
 };
 use bmidi::{Channel, MidiMessage, Note, U7};
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
@@ -28,7 +29,7 @@ fn bats_init_benchmark(c: &mut Criterion) {
                     sample_rate: SampleRate::new(SAMPLE_RATE),
                     buffer_size: BUFFER_SIZE,
                     bpm: 120.0,
-                    tracks: Default::default(),
+                    tracks: BatsBuilder::default_tracks(),
                 }
                 .build()
             })
@@ -44,7 +45,7 @@ fn bats_benchmark(c: &mut Criterion) {
                 sample_rate: SampleRate::new(SAMPLE_RATE),
                 buffer_size: BUFFER_SIZE,
                 bpm: 120.0,
-                tracks: Default::default(),
+                tracks: BatsBuilder::default_tracks(),
             }
             .build();
             let mut buffers = black_box(Buffers::new(BUFFER_SIZE));
@@ -59,7 +60,7 @@ fn bats_benchmark(c: &mut Criterion) {
                     sample_rate: SampleRate::new(SAMPLE_RATE),
                     buffer_size: BUFFER_SIZE,
                     bpm: 120.0,
-                    tracks: Default::default(),
+                    tracks: BatsBuilder::default_tracks(),
                 }
                 .build(),
             );
@@ -67,10 +68,7 @@ fn bats_benchmark(c: &mut Criterion) {
                 track.plugin = Toof::new(bats.sample_rate).into();
             }
             let mut buffers = black_box(Buffers::new(BUFFER_SIZE));
-            let midi = black_box([
-                (0, PRESS_C4.clone()),
-                (BUFFER_SIZE as u32 / 2, RELEASE_C4.clone()),
-            ]);
+            let midi = black_box([(0, PRESS_C4), (BUFFER_SIZE as u32 / 2, RELEASE_C4)]);
             let midi_ref = black_box(&midi);
             b.iter(move || {
                 bats.process(midi_ref, &mut buffers.left, &mut buffers.right);
@@ -78,6 +76,56 @@ fn bats_benchmark(c: &mut Criterion) {
         });
 }
 
+/// Build a bats with `track_count` tracks. Each track plays a note if `plugin` is
+/// `PluginBuilder::Toof`.
+fn bats_with_tracks(track_count: usize, plugin: PluginBuilder) -> Bats {
+    let mut bats = BatsBuilder {
+        sample_rate: SampleRate::new(SAMPLE_RATE),
+        buffer_size: BUFFER_SIZE,
+        bpm: 120.0,
+        tracks: vec![
+            TrackBuilder {
+                plugin,
+                volume: 1.0,
+                ..TrackBuilder::default()
+            };
+            track_count
+        ],
+    }
+    .build();
+    for track in bats.tracks.iter_mut() {
+        track.sequence.insert_note(sequence::Note {
+            start: Position::MIN,
+            length: Position::new(16.0),
+            channel: Channel::Ch1,
+            pitch: Note::C4,
+            velocity: U7::MAX,
+        });
+    }
+    bats
+}
+
+fn track_count_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lib-track-count");
+    group
+        .measurement_time(Duration::from_secs(5))
+        .confidence_level(0.99);
+    for track_count in [16, 32, 64] {
+        for plugin in [PluginBuilder::Empty, PluginBuilder::Toof] {
+            let name = format!("{track_count}_{plugin}s", plugin = plugin.name());
+            group.bench_function(name, |b| {
+                let mut bats = black_box(bats_with_tracks(track_count, plugin));
+                let mut buffers = black_box(Buffers::new(BUFFER_SIZE));
+                let midi = black_box(&[]);
+                b.iter(move || {
+                    bats.process(midi, &mut buffers.left, &mut buffers.right);
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
 fn transport_benchmark(c: &mut Criterion) {
     c.benchmark_group("lib-transport")
         .measurement_time(Duration::from_secs(1))
@@ -103,10 +151,10 @@ fn toof_benchmark(c: &mut Criterion) {
             let mut toof = black_box(Toof::new(SampleRate::new(SAMPLE_RATE)));
             let mut buffers = black_box(Buffers::new(BUFFER_SIZE));
             let midi = black_box([
-                (0, PRESS_C4.clone()),
-                (2 * BUFFER_SIZE as u32 / 4, PRESS_A4.clone()),
-                (3 * BUFFER_SIZE as u32 / 4, RELEASE_C4.clone()),
-                ((4 * BUFFER_SIZE as u32 - 1) / 4, RELEASE_A4.clone()),
+                (0, PRESS_C4),
+                (2 * BUFFER_SIZE as u32 / 4, PRESS_A4),
+                (3 * BUFFER_SIZE as u32 / 4, RELEASE_C4),
+                ((4 * BUFFER_SIZE as u32 - 1) / 4, RELEASE_A4),
             ]);
             let midi_ref = black_box(&midi);
             b.iter(move || {
@@ -118,15 +166,48 @@ fn toof_benchmark(c: &mut Criterion) {
             toof.set_param_by_name("bypass filter", 1.0).unwrap();
             let mut buffers = black_box(Buffers::new(BUFFER_SIZE));
             let midi = black_box([
-                (0, PRESS_C4.clone()),
-                (2 * BUFFER_SIZE as u32 / 4, PRESS_A4.clone()),
-                (3 * BUFFER_SIZE as u32 / 4, RELEASE_C4.clone()),
-                ((4 * BUFFER_SIZE as u32 - 1) / 4, RELEASE_A4.clone()),
+                (0, PRESS_C4),
+                (2 * BUFFER_SIZE as u32 / 4, PRESS_A4),
+                (3 * BUFFER_SIZE as u32 / 4, RELEASE_C4),
+                ((4 * BUFFER_SIZE as u32 - 1) / 4, RELEASE_A4),
             ]);
             let midi_ref = black_box(&midi);
             b.iter(move || {
                 toof.process_batch(midi_ref, &mut buffers);
             })
+        })
+        .bench_function("process-16-voices", |b| {
+            let mut toof = black_box(Toof::new(SampleRate::new(SAMPLE_RATE)));
+            toof.set_param_by_name("polyphonic", 1.0).unwrap();
+            let mut buffers = black_box(Buffers::new(BUFFER_SIZE));
+            let press_16: Vec<_> = (0..16)
+                .map(|n| {
+                    let note = Note::from_u8_lossy(48 + n);
+                    (0, MidiMessage::NoteOn(Channel::Ch1, note, U7::MAX))
+                })
+                .collect();
+            toof.process_batch(&press_16, &mut buffers);
+            let midi = black_box(&[]);
+            b.iter(move || {
+                toof.process_batch(midi, &mut buffers);
+            })
+        })
+        .bench_function("process-16-voices-no-filter", |b| {
+            let mut toof = black_box(Toof::new(SampleRate::new(SAMPLE_RATE)));
+            toof.set_param_by_name("polyphonic", 1.0).unwrap();
+            toof.set_param_by_name("bypass filter", 1.0).unwrap();
+            let mut buffers = black_box(Buffers::new(BUFFER_SIZE));
+            let press_16: Vec<_> = (0..16)
+                .map(|n| {
+                    let note = Note::from_u8_lossy(48 + n);
+                    (0, MidiMessage::NoteOn(Channel::Ch1, note, U7::MAX))
+                })
+                .collect();
+            toof.process_batch(&press_16, &mut buffers);
+            let midi = black_box(&[]);
+            b.iter(move || {
+                toof.process_batch(midi, &mut buffers);
+            })
         });
 }
 
@@ -134,6 +215,7 @@ criterion_group!(
     benches,
     bats_init_benchmark,
     bats_benchmark,
+    track_count_benchmark,
     transport_benchmark,
     toof_benchmark
 );
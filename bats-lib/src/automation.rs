@@ -0,0 +1,115 @@
+use bats_dsp::position::Position;
+use serde::{Deserialize, Serialize};
+
+/// A param value at a point in the loop.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AutomationPoint {
+    /// The position of the point.
+    pub position: Position,
+    /// The value of the param.
+    pub value: f32,
+}
+
+/// The values of a single param over the loop.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AutomationLane {
+    /// The id of the param to automate.
+    pub param_id: u32,
+    /// The points sorted by position.
+    pub points: Vec<AutomationPoint>,
+}
+
+impl AutomationLane {
+    /// Create a new lane with no points.
+    pub fn new(param_id: u32) -> AutomationLane {
+        AutomationLane {
+            param_id,
+            points: Vec::new(),
+        }
+    }
+
+    /// Get the value at `position` or `None` if the lane has no points. Values between points are
+    /// linearly interpolated. Positions before the first point or after the last point take the
+    /// value of the nearest point.
+    pub fn value_at(&self, position: Position) -> Option<f32> {
+        let idx = self.points.partition_point(|p| p.position <= position);
+        match (
+            idx.checked_sub(1).map(|i| &self.points[i]),
+            self.points.get(idx),
+        ) {
+            (None, None) => None,
+            (Some(p), None) | (None, Some(p)) => Some(p.value),
+            (Some(a), Some(b)) => {
+                let start = a.position.as_beats_f64();
+                let t = (position.as_beats_f64() - start) / (b.position.as_beats_f64() - start);
+                Some(a.value + (b.value - a.value) * t as f32)
+            }
+        }
+    }
+
+    /// Add a point to the lane. Points are kept sorted by position and a point that is already at
+    /// `position` is replaced.
+    pub fn record(&mut self, position: Position, value: f32) {
+        let idx = self.points.partition_point(|p| p.position < position);
+        let point = AutomationPoint { position, value };
+        match self.points.get_mut(idx) {
+            Some(p) if p.position == position => *p = point,
+            _ => self.points.insert(idx, point),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lane(points: &[(f64, f32)]) -> AutomationLane {
+        let mut lane = AutomationLane::new(0);
+        for (position, value) in points {
+            lane.record(Position::new(*position), *value);
+        }
+        lane
+    }
+
+    #[test]
+    fn empty_lane_has_no_value() {
+        assert_eq!(AutomationLane::new(0).value_at(Position::new(1.0)), None);
+    }
+
+    #[test]
+    fn value_is_interpolated_between_points() {
+        let lane = lane(&[(1.0, 0.0), (3.0, 1.0)]);
+        assert_eq!(lane.value_at(Position::new(1.0)), Some(0.0));
+        assert_eq!(lane.value_at(Position::new(2.0)), Some(0.5));
+        assert_eq!(lane.value_at(Position::new(3.0)), Some(1.0));
+    }
+
+    #[test]
+    fn value_outside_points_holds_nearest_value() {
+        let lane = lane(&[(1.0, 0.25), (3.0, 0.75)]);
+        assert_eq!(lane.value_at(Position::new(0.0)), Some(0.25));
+        assert_eq!(lane.value_at(Position::new(10.0)), Some(0.75));
+    }
+
+    #[test]
+    fn record_keeps_points_sorted_and_replaces_existing() {
+        let lane = lane(&[(3.0, 3.0), (1.0, 1.0), (2.0, 2.0), (1.0, 4.0)]);
+        assert_eq!(
+            lane.points,
+            vec![
+                AutomationPoint {
+                    position: Position::new(1.0),
+                    value: 4.0
+                },
+                AutomationPoint {
+                    position: Position::new(2.0),
+                    value: 2.0
+                },
+                AutomationPoint {
+                    position: Position::new(3.0),
+                    value: 3.0
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use bats_dsp::buffers::Buffers;
+
+use crate::{builder::AnyEffect, plugin::TransportInfo};
+
+/// A bus that tracks send part of their output to. The bus output is passed through the return
+/// effects and mixed into the master output, so effects like a delay can be shared by all tracks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuxBus {
+    /// The volume of the bus return.
+    pub volume: f32,
+    /// The effects applied to the bus, in order.
+    pub effects: Vec<AnyEffect>,
+    /// The sum of all track sends for the current buffer.
+    pub output: Buffers,
+}
+
+impl AuxBus {
+    /// Create a new bus without any effects.
+    pub fn new(buffer_size: usize) -> AuxBus {
+        AuxBus {
+            volume: 1.0,
+            effects: Vec::new(),
+            output: Buffers::new(buffer_size),
+        }
+    }
+
+    /// Set the output buffer size. This allocates so it should not be called while processing
+    /// audio.
+    pub fn set_buffer_size(&mut self, buffer_size: usize) {
+        self.output = Buffers::new(buffer_size);
+    }
+
+    /// Clear the output so that sends can be mixed in.
+    pub fn clear(&mut self) {
+        self.output.left.fill(0.0);
+        self.output.right.fill(0.0);
+    }
+
+    /// Pass the transport info for the current buffer to the effects.
+    pub fn set_transport_info(&mut self, info: &TransportInfo) {
+        for effect in self.effects.iter_mut() {
+            effect.effect_mut().set_transport_info(info);
+        }
+    }
+
+    /// Run the first `len` frames of the output through the effects and mix them onto `left` and
+    /// `right` with the bus volume applied.
+    pub fn mix_return(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let len = left.len().min(right.len());
+        for effect in self.effects.iter_mut() {
+            let effect = effect.effect_mut();
+            for i in 0..len {
+                self.output.set(i, effect.process(self.output.get(i)));
+            }
+        }
+        let src = self.output.left.iter().zip(self.output.right.iter());
+        for ((dst_left, dst_right), (src_left, src_right)) in
+            left.iter_mut().zip(right.iter_mut()).zip(src)
+        {
+            *dst_left += self.volume * src_left;
+            *dst_right += self.volume * src_right;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bats_dsp::sample_rate::SampleRate;
+
+    use crate::plugin::{compressor::Compressor, BatsEffect};
+
+    use super::*;
+
+    #[test]
+    fn return_applies_effects_and_volume() {
+        let mut bus = AuxBus::new(4);
+        let mut compressor = Compressor::new(SampleRate::new(44100.0));
+        compressor.set_param(5, 2.0);
+        bus.effects.push(AnyEffect::Compressor(compressor));
+        bus.volume = 0.5;
+        bus.output = Buffers::with_iter(std::iter::repeat_n((0.1, -0.1), 4));
+        let mut output = Buffers::with_iter(std::iter::repeat_n((1.0, 1.0), 3));
+        bus.mix_return(&mut output.left, &mut output.right);
+        assert_eq!(output.left, vec![1.1, 1.1, 1.1]);
+        assert_eq!(output.right, vec![0.9, 0.9, 0.9]);
+    }
+
+    #[test]
+    fn clear_zeroes_output() {
+        let mut bus = AuxBus::new(4);
+        bus.output = Buffers::with_iter(std::iter::repeat_n((0.1, -0.1), 4));
+        bus.clear();
+        assert!(bus.output.is_zero());
+    }
+}
@@ -1,13 +1,40 @@
-use bats_dsp::sample_rate::SampleRate;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use arrayvec::ArrayVec;
+use bats_dsp::{
+    position::Position, rng::Rng, sample_rate::SampleRate, smoothed_value::SmoothedValue,
+};
 use serde::{Deserialize, Serialize};
 
-use crate::plugin::{empty::Empty, toof::Toof, BatsInstrument};
-use crate::track::Track;
-use crate::transport::Transport;
+use crate::automation::AutomationLane;
+use crate::aux_bus::AuxBus;
+use crate::dsp_load::DspLoad;
+use crate::graph::ProcessGraph;
+use crate::lfo::ParamLfo;
+use crate::midi_filter::MidiFilter;
+use crate::midi_history::MidiHistory;
+use crate::overload::OverloadProtection;
+use crate::plugin::{
+    compressor::Compressor,
+    delay::Delay,
+    empty::Empty,
+    metadata::{Metadata, PluginCategory},
+    sampler::Sampler,
+    toof::Toof,
+    BatsEffect, BatsInstrument,
+};
+use crate::preset::PresetParam;
+use crate::registry::{PluginRegistry, RegisteredInstrument, RegisteredPlugin};
+use crate::sequence::Sequence;
+use crate::track::{Track, TrackColor};
+use crate::transport::{Transport, TransportSync};
+use crate::transpose::Transpose;
+use crate::tuning::Tuning;
 use crate::Bats;
 
 /// Creates a bats builder.
-#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct BatsBuilder {
     /// The sample rate.
     pub sample_rate: SampleRate,
@@ -15,17 +42,57 @@ pub struct BatsBuilder {
     pub buffer_size: usize,
     /// The bpm.
     pub bpm: f32,
-    /// The builders for the tracks.
-    pub tracks: [TrackBuilder; Bats::SUPPORTED_TRACKS],
+    /// The builders for the tracks. There will be one track for each builder.
+    pub tracks: Vec<TrackBuilder>,
 }
 
 /// Creates a track.
-#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct TrackBuilder {
+    /// The name of the track or an empty string if the track has not been named.
+    #[serde(default)]
+    pub name: String,
+    /// The color used to tag the track.
+    #[serde(default)]
+    pub color: Option<TrackColor>,
     /// The plugin builder.
     pub plugin: PluginBuilder,
     /// The volume for the track.
     pub volume: f32,
+    /// The param values of the plugin. Params that are missing keep their default value.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<PresetParam>,
+    /// The midi sequence.
+    #[serde(default)]
+    pub sequence: Sequence,
+    /// The filter for the midi input of the track.
+    #[serde(default)]
+    pub midi_filter: MidiFilter,
+    /// The tuning that the plugin plays notes in.
+    #[serde(default, skip_serializing_if = "Tuning::is_equal_temperament")]
+    pub tuning: Tuning,
+    /// The param automation lanes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub automation: Vec<AutomationLane>,
+    /// The LFOs that modulate plugin params.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lfos: Vec<ParamLfo>,
+    /// The number of beats after which the sequence repeats or `None` if the sequence repeats
+    /// with the transport loop.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loop_length: Option<Position>,
+}
+
+/// The contents of a project file.
+#[derive(Serialize, Deserialize)]
+struct ProjectFile<B> {
+    /// The version of the project format, see `BatsBuilder::PROJECT_VERSION`. Projects saved
+    /// before the version was added are version 0.
+    #[serde(default)]
+    version: u32,
+    /// The project.
+    #[serde(flatten)]
+    bats: B,
 }
 
 /// An object that is used to build plugins.
@@ -36,6 +103,8 @@ pub enum PluginBuilder {
     Empty,
     /// The toof plugin.
     Toof,
+    /// A plugin from another crate that was added to the `PluginRegistry`.
+    Registered(RegisteredPlugin),
 }
 
 /// Contains all the plugins.
@@ -45,21 +114,76 @@ pub enum AnyPlugin {
     Empty(Empty),
     /// The toof plugin.
     Toof(Box<Toof>),
+    /// The sampler that plays the audio of a frozen track.
+    Sampler(Box<Sampler>),
+    /// A plugin from another crate that was added to the `PluginRegistry`.
+    Registered(Box<RegisteredInstrument>),
+}
+
+/// An object that is used to build effects.
+#[derive(Copy, Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub enum EffectBuilder {
+    /// The compressor effect.
+    #[default]
+    Compressor,
+    /// The delay effect.
+    Delay,
+}
+
+/// Contains all the effects.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnyEffect {
+    /// The compressor effect.
+    Compressor(Box<Compressor>),
+    /// The delay effect.
+    Delay(Box<Delay>),
 }
 
 impl BatsBuilder {
+    /// The version of the project format that is written by `save`. This is increased whenever a
+    /// change to the format needs older projects to be migrated by `load`.
+    pub const PROJECT_VERSION: u32 = 1;
+
     /// Build the bats object.
     pub fn build(&self) -> Bats {
+        let tracks: Vec<Track> = self
+            .tracks
+            .iter()
+            .map(|t| t.build(self.sample_rate, self.buffer_size))
+            .collect();
         Bats {
             transport: Transport::new(self.sample_rate, self.buffer_size, self.bpm),
             armed_track: 0,
             recording_enabled: false,
+            record_latency: 0,
+            playing: true,
+            fade: SmoothedValue::new(1.0),
+            transport_sync: TransportSync::default(),
             sample_rate: self.sample_rate,
             buffer_size: self.buffer_size,
+            seed: Rng::DEFAULT_SEED,
             midi_buffer: Vec::with_capacity(self.buffer_size * 8),
-            tracks: core::array::from_fn(|idx| {
-                self.tracks[idx].build(self.sample_rate, self.buffer_size)
-            }),
+            port_midi: Vec::with_capacity(self.buffer_size * 8),
+            track_midi_in: Vec::with_capacity(self.buffer_size * 8),
+            midi_input_routes: [None; Bats::MIDI_INPUT_PORT_COUNT],
+            transpose: Transpose::default(),
+            midi_history: MidiHistory::new(),
+            captured: None,
+            graph: ProcessGraph::new(&tracks, Bats::AUX_BUS_COUNT),
+            tracks,
+            direct_outputs: Vec::new(),
+            macros: Default::default(),
+            morph: Default::default(),
+            aux_buses: std::array::from_fn(|_| AuxBus::new(self.buffer_size)),
+            master_compressor: None,
+            capture: None,
+            recorder: None,
+            scope: None,
+            snapshot: None,
+            dsp_load: DspLoad::new(),
+            overload: OverloadProtection::default(),
+            control_surface: None,
+            events: ArrayVec::new(),
         }
     }
 
@@ -69,31 +193,151 @@ impl BatsBuilder {
             sample_rate: b.sample_rate,
             buffer_size: b.buffer_size,
             bpm: b.transport.bpm(),
-            tracks: core::array::from_fn(|idx| {
-                let track = &b.tracks[idx];
-                TrackBuilder::from_bats(track)
-            }),
+            tracks: b.tracks.iter().map(TrackBuilder::from_bats).collect(),
+        }
+    }
+
+    /// Set `self` to the state of `b`. This reuses the allocations of `self` so it does not
+    /// allocate if `self` already has enough capacity for the tracks and their names.
+    pub fn copy_from_bats(&mut self, b: &Bats) {
+        self.sample_rate = b.sample_rate;
+        self.buffer_size = b.buffer_size;
+        self.bpm = b.transport.bpm();
+        self.tracks.truncate(b.tracks.len());
+        for (idx, track) in b.tracks.iter().enumerate() {
+            match self.tracks.get_mut(idx) {
+                Some(t) => t.copy_from_bats(track),
+                None => self.tracks.push(TrackBuilder::from_bats(track)),
+            }
+        }
+    }
+
+    /// Save the builder as a project file at `path`.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
         }
+        let project = ProjectFile {
+            version: BatsBuilder::PROJECT_VERSION,
+            bats: self,
+        };
+        std::fs::write(path, toml::to_string(&project)?)?;
+        Ok(())
+    }
+
+    /// Load a builder from the project file at `path`. Projects from older versions are migrated
+    /// to the current version. Returns an error if the project was saved by a newer version of
+    /// bats.
+    pub fn load(path: &Path) -> Result<BatsBuilder> {
+        let contents = std::fs::read_to_string(path)?;
+        let mut project: ProjectFile<toml::Table> = toml::from_str(&contents)?;
+        if project.version > BatsBuilder::PROJECT_VERSION {
+            return Err(anyhow!(
+                "project version {} is newer than the supported version {}",
+                project.version,
+                BatsBuilder::PROJECT_VERSION
+            ));
+        }
+        for migrate in &PROJECT_MIGRATIONS[project.version as usize..] {
+            migrate(&mut project.bats);
+        }
+        Ok(toml::Value::Table(project.bats).try_into()?)
+    }
+
+    /// Get the builders for the default number of tracks.
+    pub fn default_tracks() -> Vec<TrackBuilder> {
+        vec![TrackBuilder::default(); Bats::DEFAULT_TRACK_COUNT]
+    }
+}
+
+/// The steps that migrate a project to the next version. The step at index `n` migrates a project
+/// from version `n` to version `n + 1`.
+const PROJECT_MIGRATIONS: [fn(&mut toml::Table); BatsBuilder::PROJECT_VERSION as usize] =
+    [migrate_project_v0];
+
+/// Migrate a project from version 0 to version 1. Version 1 saves the params and sequence of each
+/// track, so version 0 tracks get the default params and an empty sequence.
+fn migrate_project_v0(project: &mut toml::Table) {
+    let tracks = project
+        .get_mut("tracks")
+        .and_then(toml::Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(toml::Value::as_table_mut);
+    for track in tracks {
+        track
+            .entry("params")
+            .or_insert_with(|| toml::Value::Array(Vec::new()));
+        track
+            .entry("sequence")
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
     }
 }
 
 impl TrackBuilder {
     /// Build the track.
     pub fn build(&self, sample_rate: SampleRate, buffer_size: usize) -> Track {
-        Track {
-            plugin: self.plugin.build(sample_rate),
+        let plugin = self.plugin.build_with_params(sample_rate, &self.params);
+        let mut track = Track {
+            name: self.name.clone(),
+            color: self.color,
+            plugin,
             volume: self.volume,
+            volume_smoother: SmoothedValue::new(self.volume),
+            midi_filter: self.midi_filter,
+            automation: self.automation.clone(),
+            lfos: self.lfos.clone(),
+            loop_length: self.loop_length,
             ..Track::new(buffer_size)
-        }
+        };
+        track.set_tuning(Box::new(self.tuning));
+        // Copy into the existing sequence to keep the capacity that is reserved for recording.
+        track.sequence.clone_from(&self.sequence);
+        track
     }
 
-    /// Create a track builder from a track.
+    /// Create a track builder from a track. Frozen tracks use their original plugin and sequence.
     pub fn from_bats(t: &Track) -> TrackBuilder {
         TrackBuilder {
-            plugin: PluginBuilder::from_bats(&t.plugin),
+            name: t.name.clone(),
+            color: t.color,
+            plugin: PluginBuilder::from_bats(t.unfrozen_plugin()),
             volume: t.volume,
+            params: PresetParam::all(t.unfrozen_plugin()).collect(),
+            sequence: t.unfrozen_sequence().clone(),
+            midi_filter: t.midi_filter,
+            tuning: *t.tuning,
+            automation: t.automation.clone(),
+            lfos: t.lfos.clone(),
+            loop_length: t.loop_length,
         }
     }
+
+    /// Set `self` to the state of `t`, reusing the allocations for the name, params, sequence,
+    /// automation, and LFOs.
+    pub fn copy_from_bats(&mut self, t: &Track) {
+        self.name.clone_from(&t.name);
+        self.color = t.color;
+        self.plugin = PluginBuilder::from_bats(t.unfrozen_plugin());
+        self.volume = t.volume;
+        self.params.clear();
+        self.params.extend(PresetParam::all(t.unfrozen_plugin()));
+        self.sequence.clone_from(t.unfrozen_sequence());
+        self.midi_filter = t.midi_filter;
+        self.tuning = *t.tuning;
+        self.automation.truncate(t.automation.len());
+        for (idx, lane) in t.automation.iter().enumerate() {
+            match self.automation.get_mut(idx) {
+                Some(l) => {
+                    l.param_id = lane.param_id;
+                    l.points.clone_from(&lane.points);
+                }
+                None => self.automation.push(lane.clone()),
+            }
+        }
+        self.lfos.clone_from(&t.lfos);
+        self.loop_length = t.loop_length;
+    }
 }
 
 impl AnyPlugin {
@@ -102,6 +346,8 @@ impl AnyPlugin {
         match self {
             AnyPlugin::Empty(p) => p,
             AnyPlugin::Toof(p) => p.as_ref(),
+            AnyPlugin::Sampler(p) => p.as_ref(),
+            AnyPlugin::Registered(p) => p.instrument.as_instrument(),
         }
     }
 
@@ -110,35 +356,153 @@ impl AnyPlugin {
         match self {
             AnyPlugin::Empty(p) => p,
             AnyPlugin::Toof(p) => p.as_mut(),
+            AnyPlugin::Sampler(p) => p.as_mut(),
+            AnyPlugin::Registered(p) => p.instrument.as_instrument_mut(),
         }
     }
 }
 
 impl PluginBuilder {
-    /// All the plugin builders available.
+    /// All the plugin builders that are part of `bats-lib`. See `PluginBuilder::all` for the list
+    /// that includes registered plugins.
     pub const ALL: &'static [PluginBuilder] = &[PluginBuilder::Empty, PluginBuilder::Toof];
 
+    /// All the plugin builders available, including the plugins in the global `PluginRegistry`.
+    pub fn all() -> Vec<PluginBuilder> {
+        PluginBuilder::ALL
+            .iter()
+            .copied()
+            .chain(
+                PluginRegistry::global()
+                    .all()
+                    .into_iter()
+                    .map(PluginBuilder::Registered),
+            )
+            .collect()
+    }
+
     /// The name of the plugin.
     pub fn name(self) -> &'static str {
         match self {
             PluginBuilder::Empty => "empty",
             PluginBuilder::Toof => "toof",
+            PluginBuilder::Registered(p) => p.name(),
         }
     }
 
+    /// The metadata of the plugin that is built.
+    pub fn metadata(self) -> &'static Metadata {
+        match self {
+            PluginBuilder::Empty => Empty.metadata(),
+            PluginBuilder::Toof => &Toof::METADATA,
+            PluginBuilder::Registered(p) => p.metadata(),
+        }
+    }
+
+    /// The plugin builders with the given category.
+    pub fn by_category(category: PluginCategory) -> impl Iterator<Item = PluginBuilder> {
+        PluginBuilder::all()
+            .into_iter()
+            .filter(move |b| b.metadata().category == category)
+    }
+
+    /// Get the plugin builder with the given name.
+    pub fn from_name(name: &str) -> Option<PluginBuilder> {
+        PluginBuilder::all().into_iter().find(|b| b.name() == name)
+    }
+
     /// Build the new plugin.
     pub fn build(self, sample_rate: SampleRate) -> AnyPlugin {
         match self {
             PluginBuilder::Empty => AnyPlugin::Empty(Empty),
             PluginBuilder::Toof => AnyPlugin::Toof(Toof::new(sample_rate)),
+            PluginBuilder::Registered(p) => AnyPlugin::Registered(Box::new(RegisteredInstrument {
+                plugin: p,
+                instrument: p.build(sample_rate),
+            })),
+        }
+    }
+
+    /// Build the new plugin and set its `params`.
+    pub fn build_with_params(self, sample_rate: SampleRate, params: &[PresetParam]) -> AnyPlugin {
+        let mut plugin = self.build(sample_rate);
+        // Params that already have their value are skipped so that they do not start smoothing.
+        for param in params.iter() {
+            if plugin.plugin().param(param.id) != param.value {
+                plugin.plugin_mut().set_param(param.id, param.value);
+            }
         }
+        plugin
     }
 
-    /// Create a plugin builder from an existing plugin.
+    /// True if plugins that are built must be rebuilt to change their sample rate. See
+    /// `PluginFactory::rebuild_on_sample_rate_change`.
+    pub fn rebuild_on_sample_rate_change(self) -> bool {
+        match self {
+            PluginBuilder::Empty | PluginBuilder::Toof => false,
+            PluginBuilder::Registered(p) => p.rebuild_on_sample_rate_change(),
+        }
+    }
+
+    /// Create a plugin builder from an existing plugin. The sampler of a frozen track can not be
+    /// built so it becomes `PluginBuilder::Empty`.
     pub fn from_bats(p: &AnyPlugin) -> PluginBuilder {
         match p {
-            AnyPlugin::Empty(_) => PluginBuilder::Empty,
+            AnyPlugin::Empty(_) | AnyPlugin::Sampler(_) => PluginBuilder::Empty,
             AnyPlugin::Toof(_) => PluginBuilder::Toof,
+            AnyPlugin::Registered(p) => PluginBuilder::Registered(p.plugin),
+        }
+    }
+}
+
+impl AnyEffect {
+    /// Get a reference to the underlying effect.
+    pub fn effect(&'_ self) -> &'_ dyn BatsEffect {
+        match self {
+            AnyEffect::Compressor(e) => e.as_ref(),
+            AnyEffect::Delay(e) => e.as_ref(),
+        }
+    }
+
+    /// Get a mutable reference to the underlying effect.
+    pub fn effect_mut(&'_ mut self) -> &'_ mut dyn BatsEffect {
+        match self {
+            AnyEffect::Compressor(e) => e.as_mut(),
+            AnyEffect::Delay(e) => e.as_mut(),
+        }
+    }
+}
+
+impl EffectBuilder {
+    /// All the effect builders available.
+    pub const ALL: &'static [EffectBuilder] = &[EffectBuilder::Compressor, EffectBuilder::Delay];
+
+    /// The name of the effect.
+    pub fn name(self) -> &'static str {
+        self.metadata().name
+    }
+
+    /// The metadata of the effect that is built.
+    pub fn metadata(self) -> &'static Metadata {
+        match self {
+            EffectBuilder::Compressor => &Compressor::METADATA,
+            EffectBuilder::Delay => &Delay::METADATA,
+        }
+    }
+
+    /// Build the new effect. This allocates so it should not be called from the audio thread.
+    pub fn build(self, sample_rate: SampleRate) -> AnyEffect {
+        match self {
+            EffectBuilder::Compressor => AnyEffect::Compressor(Compressor::new(sample_rate)),
+            EffectBuilder::Delay => AnyEffect::Delay(Delay::new(sample_rate)),
+        }
+    }
+
+    /// Create an effect builder from an existing effect.
+    pub fn from_bats(e: &AnyEffect) -> EffectBuilder {
+        match e {
+            AnyEffect::Compressor(_) => EffectBuilder::Compressor,
+            AnyEffect::Delay(_) => EffectBuilder::Delay,
         }
     }
 }
@@ -158,15 +522,28 @@ impl Default for AnyPlugin {
 impl Default for TrackBuilder {
     fn default() -> TrackBuilder {
         TrackBuilder {
+            name: String::new(),
+            color: None,
             plugin: PluginBuilder::default(),
             volume: 1.0,
+            params: Vec::new(),
+            sequence: Sequence::new(),
+            midi_filter: MidiFilter::default(),
+            tuning: Tuning::default(),
+            automation: Vec::new(),
+            lfos: Vec::new(),
+            loop_length: None,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use bats_dsp::position::Position;
+    use bmidi::{Channel, ControlFunction, MidiMessage, Note, U7};
+
     use super::*;
+    use crate::plugin::MidiEvent;
 
     #[test]
     fn build() {
@@ -175,11 +552,14 @@ mod tests {
                 sample_rate: SampleRate::new(48000.0),
                 buffer_size: 256,
                 bpm: 175.2,
-                tracks: Default::default(),
+                tracks: BatsBuilder::default_tracks(),
             }
             .build();
             b.tracks[1].volume = 0.65;
+            b.tracks[1].volume_smoother = SmoothedValue::new(0.65);
             b.tracks[1].plugin = Toof::new(b.sample_rate).into();
+            b.tracks[2].name = "drums".to_string();
+            b.tracks[2].color = Some(TrackColor::Red);
             b
         };
         let initial_builder = BatsBuilder::from_bats(&initial_bats);
@@ -188,4 +568,296 @@ mod tests {
         assert_eq!(initial_bats, new_bats);
         assert_eq!(initial_builder, new_builder);
     }
+
+    #[test]
+    fn first_buffer_is_not_louder_than_track_volume() {
+        let process_note = |volume: f32| {
+            let mut tracks = BatsBuilder::default_tracks();
+            tracks[0].plugin = PluginBuilder::Toof;
+            tracks[0].volume = volume;
+            let mut bats = BatsBuilder {
+                sample_rate: SampleRate::new(44100.0),
+                buffer_size: 64,
+                bpm: 120.0,
+                tracks,
+            }
+            .build();
+            bats.armed_track = 0;
+            let note_on = MidiMessage::NoteOn(Channel::Ch1, Note::A4, U7::MAX);
+            bats.process_to_buffer(64, &[(0, note_on)])
+        };
+        let full = process_note(1.0);
+        let half = process_note(0.5);
+        assert!(full.left.iter().any(|v| v.abs() > 0.0));
+        for (full, half) in full.left.iter().zip(half.left.iter()) {
+            assert!(
+                half.abs() <= 0.5 * full.abs() + f32::EPSILON,
+                "{half} > 0.5 * {full}"
+            );
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("bats-project-{}.toml", std::process::id()));
+        let mut builder = BatsBuilder {
+            sample_rate: SampleRate::new(48000.0),
+            buffer_size: 256,
+            bpm: 96.5,
+            tracks: BatsBuilder::default_tracks(),
+        };
+        builder.tracks[0].name = "bass".to_string();
+        builder.tracks[0].plugin = PluginBuilder::Toof;
+        builder.tracks[1].color = Some(TrackColor::Blue);
+        builder.save(&path).unwrap();
+        assert_eq!(BatsBuilder::load(&path).unwrap(), builder);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn project_round_trips_sequences_and_params() {
+        let path = std::env::temp_dir().join(format!(
+            "bats-project-round-trip-{}.toml",
+            std::process::id()
+        ));
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(48000.0),
+            buffer_size: 256,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.tracks[0].plugin = Toof::new(b.sample_rate).into();
+        b.tracks[0].plugin.plugin_mut().set_param(2, 432.0);
+        b.tracks[0].sequence.insert_note(crate::sequence::Note {
+            start: Position::new(1.0),
+            length: Position::new(0.5),
+            channel: Channel::Ch1,
+            pitch: Note::C4,
+            velocity: U7::MAX,
+        });
+        b.tracks[0].sequence.insert_message(MidiEvent {
+            position: Position::new(2.0),
+            midi: MidiMessage::ControlChange(
+                Channel::Ch1,
+                ControlFunction::MODULATION_WHEEL,
+                U7::MAX,
+            ),
+        });
+        BatsBuilder::from_bats(&b).save(&path).unwrap();
+        let loaded = BatsBuilder::load(&path).unwrap().build();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.tracks[0].sequence, b.tracks[0].sequence);
+        assert_eq!(loaded.tracks[0].sequence.len(), 3);
+        assert_eq!(loaded.tracks[0].plugin.plugin().param(2), 432.0);
+        assert_eq!(BatsBuilder::from_bats(&loaded), BatsBuilder::from_bats(&b));
+    }
+
+    #[test]
+    fn project_round_trips_track_settings() {
+        let path = std::env::temp_dir().join(format!(
+            "bats-project-track-settings-{}.toml",
+            std::process::id()
+        ));
+        let mut builder = BatsBuilder {
+            sample_rate: SampleRate::new(48000.0),
+            buffer_size: 256,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        };
+        let t = &mut builder.tracks[0];
+        t.plugin = PluginBuilder::Toof;
+        t.midi_filter = MidiFilter {
+            transpose: -12,
+            low_note: Note::C2,
+            velocity_curve: crate::midi_filter::VelocityCurve::Soft,
+            fixed_velocity: U7::new(100),
+            ..MidiFilter::default()
+        };
+        let scl = "pentatonic\n5\n200.0\n400.0\n700.0\n900.0\n2/1\n";
+        let kbm = "12\n0\n127\n60\n69\n432.0\n5\n0\nx\n1\nx\n2\nx\nx\n3\nx\n4\nx\nx\n";
+        t.tuning = Tuning::from_scala("pentatonic", scl, Some(kbm)).unwrap();
+        t.automation = vec![AutomationLane::new(2)];
+        t.automation[0].record(Position::new(1.0), 220.0);
+        t.automation[0].record(Position::new(3.0), 880.0);
+        t.lfos = vec![ParamLfo {
+            param_id: 3,
+            waveform: crate::lfo::LfoWaveform::Triangle,
+            beats: 2.0,
+            center: 0.5,
+            depth: 0.25,
+        }];
+        t.loop_length = Some(Position::new(8.0));
+        builder.save(&path).unwrap();
+        let loaded = BatsBuilder::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, builder);
+
+        let b = loaded.build();
+        assert_eq!(b.tracks[0].tuning.frequency(Note::A4), Some(432.0));
+        assert_eq!(b.tracks[0].tuning.frequency(Note::Db4), None);
+        let track = TrackBuilder {
+            params: Vec::new(),
+            ..TrackBuilder::from_bats(&b.tracks[0])
+        };
+        assert_eq!(track, builder.tracks[0]);
+    }
+
+    #[test]
+    fn projects_from_newer_versions_are_not_loaded() {
+        let path =
+            std::env::temp_dir().join(format!("bats-project-v2-{}.toml", std::process::id()));
+        let builder = BatsBuilder {
+            sample_rate: SampleRate::new(48000.0),
+            buffer_size: 256,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        };
+        builder.save(&path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with("version = 1\n"), "{contents}");
+        std::fs::write(&path, contents.replace("version = 1", "version = 2")).unwrap();
+        assert!(BatsBuilder::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn fixture_path(name: &str) -> std::path::PathBuf {
+        let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("../assets/test");
+        path.push(name);
+        path
+    }
+
+    fn fixture_tracks() -> Vec<TrackBuilder> {
+        vec![
+            TrackBuilder {
+                name: "lead".to_string(),
+                color: Some(TrackColor::Blue),
+                plugin: PluginBuilder::Toof,
+                volume: 0.5,
+                ..TrackBuilder::default()
+            },
+            TrackBuilder::default(),
+        ]
+    }
+
+    #[test]
+    fn version_0_project_is_migrated_on_load() {
+        let builder = BatsBuilder::load(&fixture_path("project_v0.toml")).unwrap();
+        assert_eq!(
+            builder,
+            BatsBuilder {
+                sample_rate: SampleRate::new(44100.0),
+                buffer_size: 128,
+                bpm: 90.0,
+                tracks: fixture_tracks(),
+            }
+        );
+        let b = builder.build();
+        assert_eq!(
+            b.tracks[0].plugin.plugin().param(2),
+            Toof::new(b.sample_rate).param(2)
+        );
+        assert!(b.tracks[0].sequence.is_empty());
+    }
+
+    #[test]
+    fn version_1_project_is_loaded() {
+        let builder = BatsBuilder::load(&fixture_path("project_v1.toml")).unwrap();
+        let mut tracks = fixture_tracks();
+        tracks[0].params = vec![PresetParam {
+            id: 2,
+            value: 432.0,
+        }];
+        tracks[0].sequence.insert_note(crate::sequence::Note {
+            start: Position::new(1.0),
+            length: Position::new(0.5),
+            channel: Channel::Ch1,
+            pitch: Note::C4,
+            velocity: U7::MAX,
+        });
+        assert_eq!(
+            builder,
+            BatsBuilder {
+                sample_rate: SampleRate::new(44100.0),
+                buffer_size: 128,
+                bpm: 90.0,
+                tracks,
+            }
+        );
+        assert_eq!(builder.build().tracks[0].plugin.plugin().param(2), 432.0);
+    }
+
+    #[test]
+    fn plugin_metadata_matches_built_plugin() {
+        for b in PluginBuilder::ALL.iter().copied() {
+            let plugin = b.build(SampleRate::new(44100.0));
+            assert_eq!(b.metadata(), plugin.plugin().metadata());
+            assert_eq!(b.metadata().name, b.name());
+        }
+    }
+
+    #[test]
+    fn effect_builder_round_trips_through_built_effect() {
+        for b in EffectBuilder::ALL.iter().copied() {
+            let effect = b.build(SampleRate::new(44100.0));
+            assert_eq!(b.metadata(), effect.effect().metadata());
+            assert_eq!(EffectBuilder::from_bats(&effect), b);
+        }
+    }
+
+    #[test]
+    fn by_category_returns_plugins_in_category() {
+        assert_eq!(
+            PluginBuilder::by_category(PluginCategory::Instrument).collect::<Vec<_>>(),
+            vec![PluginBuilder::Toof]
+        );
+        assert_eq!(
+            PluginBuilder::by_category(PluginCategory::Utility).collect::<Vec<_>>(),
+            vec![PluginBuilder::Empty]
+        );
+        assert_eq!(
+            PluginBuilder::by_category(PluginCategory::Effect).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn copy_from_bats_reuses_allocations() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(48000.0),
+            buffer_size: 256,
+            bpm: 175.2,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.tracks[0].name = "bass".to_string();
+        let mut builder = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: (0..b.tracks.len())
+                .map(|_| TrackBuilder {
+                    name: String::with_capacity(8),
+                    ..TrackBuilder::default()
+                })
+                .collect(),
+        };
+        let tracks_ptr = builder.tracks.as_ptr();
+        let name_ptr = builder.tracks[0].name.as_ptr();
+        builder.tracks[0].automation = vec![AutomationLane {
+            param_id: 0,
+            points: Vec::with_capacity(8),
+        }];
+        let points_ptr = builder.tracks[0].automation[0].points.as_ptr();
+
+        b.tracks[0].automation = vec![AutomationLane::new(2)];
+        b.tracks[0].automation[0].record(Position::new(1.0), 220.0);
+        b.tracks[3].volume = 0.5;
+        builder.copy_from_bats(&b);
+        assert_eq!(builder, BatsBuilder::from_bats(&b));
+        assert_eq!(builder.tracks.as_ptr(), tracks_ptr);
+        assert_eq!(builder.tracks[0].name.as_ptr(), name_ptr);
+        assert_eq!(builder.tracks[0].automation[0].points.as_ptr(), points_ptr);
+    }
 }
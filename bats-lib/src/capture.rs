@@ -0,0 +1,91 @@
+use bats_dsp::buffers::Buffers;
+
+/// Captures the output of `Bats::process` into a preallocated buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Capture {
+    /// The captured audio. The length is the number of frames to capture.
+    pub buffers: Buffers,
+    /// The output of each track with the track volume applied, indexed by track id. Empty unless
+    /// the capture was created with `Capture::with_stems`.
+    pub stems: Vec<Buffers>,
+    /// The number of frames that have been captured.
+    pub captured: usize,
+}
+
+impl Capture {
+    /// Create a new capture that holds `frames` frames. This allocates so it should not be called
+    /// while processing audio.
+    pub fn new(frames: usize) -> Capture {
+        Capture {
+            buffers: Buffers::new(frames),
+            stems: Vec::new(),
+            captured: 0,
+        }
+    }
+
+    /// Create a new capture that holds `frames` frames of the output and of each of the `tracks`
+    /// tracks. This allocates so it should not be called while processing audio.
+    pub fn with_stems(frames: usize, tracks: usize) -> Capture {
+        Capture {
+            stems: (0..tracks).map(|_| Buffers::new(frames)).collect(),
+            ..Capture::new(frames)
+        }
+    }
+
+    /// Returns true if all frames have been captured.
+    pub fn is_complete(&self) -> bool {
+        self.captured >= self.buffers.len()
+    }
+
+    /// Write the output of the track with `track_id`, multiplied by `gain`, to its stem for the
+    /// frames that the next call to `push` appends. Does nothing if there is no stem for the track.
+    pub fn push_stem(&mut self, track_id: usize, left: &[f32], right: &[f32], gain: f32) {
+        let stem = match self.stems.get_mut(track_id) {
+            Some(s) => s,
+            None => return,
+        };
+        let len = stem.copy_from(self.captured, left, right);
+        let range = self.captured..self.captured + len;
+        for v in stem.left[range.clone()]
+            .iter_mut()
+            .chain(stem.right[range].iter_mut())
+        {
+            *v *= gain;
+        }
+    }
+
+    /// Append `left` and `right` to the capture. Frames past the end of the capture are ignored.
+    pub fn push(&mut self, left: &[f32], right: &[f32]) {
+        self.captured += self.buffers.copy_from(self.captured, left, right);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_fills_until_complete() {
+        let mut capture = Capture::new(5);
+        capture.push(&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]);
+        assert!(!capture.is_complete());
+        capture.push(&[7.0, 8.0, 9.0], &[10.0, 11.0, 12.0]);
+        assert!(capture.is_complete());
+        capture.push(&[13.0], &[14.0]);
+        assert_eq!(capture.buffers.left, vec![1.0, 2.0, 3.0, 7.0, 8.0]);
+        assert_eq!(capture.buffers.right, vec![4.0, 5.0, 6.0, 10.0, 11.0]);
+    }
+
+    #[test]
+    fn stems_are_aligned_with_output() {
+        let mut capture = Capture::with_stems(3, 2);
+        capture.push_stem(1, &[1.0, 2.0], &[3.0, 4.0], 0.5);
+        capture.push(&[0.0, 0.0], &[0.0, 0.0]);
+        capture.push_stem(1, &[5.0, 6.0], &[7.0, 8.0], 0.5);
+        capture.push_stem(2, &[5.0, 6.0], &[7.0, 8.0], 0.5);
+        capture.push(&[0.0, 0.0], &[0.0, 0.0]);
+        assert_eq!(capture.stems[0], Buffers::new(3));
+        assert_eq!(capture.stems[1].left, vec![0.5, 1.0, 2.5]);
+        assert_eq!(capture.stems[1].right, vec![1.5, 2.0, 3.5]);
+    }
+}
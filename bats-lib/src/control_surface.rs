@@ -0,0 +1,243 @@
+use arrayvec::ArrayVec;
+use bmidi::{Channel, MidiMessage, Note, U7};
+use serde::{Deserialize, Serialize};
+
+use crate::Bats;
+
+/// An action that is performed when a pad of a control surface is pressed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SurfaceAction {
+    /// Arm the track with the id so that it takes user midi input.
+    ArmTrack(usize),
+    /// Toggle recording to sequence.
+    ToggleRecording,
+    /// Start or stop the transport.
+    TogglePlaying,
+}
+
+impl SurfaceAction {
+    /// Perform the action on `bats`. Arming a track that does not exist does nothing.
+    pub fn apply(self, bats: &mut Bats) {
+        match self {
+            SurfaceAction::ArmTrack(track_id) if track_id < bats.tracks.len() => {
+                bats.armed_track = track_id
+            }
+            SurfaceAction::ArmTrack(_) => (),
+            SurfaceAction::ToggleRecording => bats.recording_enabled = !bats.recording_enabled,
+            SurfaceAction::TogglePlaying => bats.playing = !bats.playing,
+        }
+    }
+
+    /// Returns true if the pad for the action should be lit.
+    pub fn is_active(self, bats: &Bats) -> bool {
+        match self {
+            SurfaceAction::ArmTrack(track_id) => bats.armed_track == track_id,
+            SurfaceAction::ToggleRecording => bats.recording_enabled,
+            SurfaceAction::TogglePlaying => bats.playing,
+        }
+    }
+}
+
+/// Maps a single pad to an action.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PadMapping {
+    /// The midi note number that the pad sends and that lights it up.
+    pub pad: u8,
+    /// The action to perform when the pad is pressed.
+    pub action: SurfaceAction,
+}
+
+/// Describes how the pads of a control surface map to actions and how the pads are lit.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SurfaceProfile {
+    /// The velocity that is sent to light up the pad of an active action. For grid controllers,
+    /// this usually selects a color from the palette of the device.
+    pub lit_velocity: u8,
+    /// The velocity that is sent for the pads of inactive actions.
+    pub unlit_velocity: u8,
+    /// The pads and their actions.
+    pub pads: Vec<PadMapping>,
+}
+
+impl Default for SurfaceProfile {
+    fn default() -> SurfaceProfile {
+        SurfaceProfile::launchpad()
+    }
+}
+
+impl SurfaceProfile {
+    /// The profile for a Launchpad in programmer mode. The top row of the grid arms the first 8
+    /// tracks and the first 2 pads of the bottom row toggle playing and recording.
+    pub fn launchpad() -> SurfaceProfile {
+        let arm_pads = (0..8).map(|track_id| PadMapping {
+            pad: 81 + track_id as u8,
+            action: SurfaceAction::ArmTrack(track_id),
+        });
+        let transport_pads = [
+            PadMapping {
+                pad: 11,
+                action: SurfaceAction::TogglePlaying,
+            },
+            PadMapping {
+                pad: 12,
+                action: SurfaceAction::ToggleRecording,
+            },
+        ];
+        SurfaceProfile {
+            lit_velocity: 21,
+            unlit_velocity: 1,
+            pads: arm_pads.chain(transport_pads).collect(),
+        }
+    }
+}
+
+/// Turns the note input of a grid controller, like a Launchpad, into actions and lights up the
+/// pads of the actions that are active.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlSurface {
+    /// The mapping from pads to actions.
+    pub profile: SurfaceProfile,
+    /// The midi to send to the control surface to update its pads. Refilled on every call to
+    /// `update_feedback`.
+    pub feedback: ArrayVec<(u32, MidiMessage), { ControlSurface::FEEDBACK_CAPACITY }>,
+    /// Whether each pad in the profile was last lit or `None` if the pad has not been sent yet.
+    lit: Vec<Option<bool>>,
+}
+
+impl ControlSurface {
+    /// The maximum number of pad updates that are sent per buffer. Any remaining updates are
+    /// sent on the next buffer.
+    pub const FEEDBACK_CAPACITY: usize = 128;
+
+    /// Create a new control surface. All pads are sent on the first call to `update_feedback`.
+    pub fn new(profile: SurfaceProfile) -> ControlSurface {
+        ControlSurface {
+            lit: vec![None; profile.pads.len()],
+            profile,
+            feedback: ArrayVec::new(),
+        }
+    }
+
+    /// Get the action for `msg` or `None` if `msg` is not the press of a mapped pad.
+    pub fn action_for(&self, msg: &MidiMessage) -> Option<SurfaceAction> {
+        let note = match msg {
+            MidiMessage::NoteOn(_, note, velocity) if *velocity != U7::MIN => *note,
+            _ => return None,
+        };
+        self.profile
+            .pads
+            .iter()
+            .find(|p| p.pad == u8::from(note))
+            .map(|p| p.action)
+    }
+
+    /// Fill `feedback` with the midi for the pads whose state has changed since they were last
+    /// sent.
+    pub fn update_feedback(&mut self, bats: &Bats) {
+        self.feedback.clear();
+        for (mapping, lit) in self.profile.pads.iter().zip(self.lit.iter_mut()) {
+            let active = mapping.action.is_active(bats);
+            if *lit == Some(active) {
+                continue;
+            }
+            let velocity = if active {
+                self.profile.lit_velocity
+            } else {
+                self.profile.unlit_velocity
+            };
+            let msg = MidiMessage::NoteOn(
+                Channel::Ch1,
+                Note::from_u8_lossy(mapping.pad),
+                U7::from_u8_lossy(velocity),
+            );
+            if self.feedback.try_push((0, msg)).is_err() {
+                return;
+            }
+            *lit = Some(active);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bats_dsp::sample_rate::SampleRate;
+
+    use super::*;
+    use crate::builder::BatsBuilder;
+
+    fn bats() -> Bats {
+        BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build()
+    }
+
+    fn pad_on(pad: u8, velocity: u8) -> MidiMessage {
+        MidiMessage::NoteOn(
+            Channel::Ch1,
+            Note::from_u8_lossy(pad),
+            U7::from_u8_lossy(velocity),
+        )
+    }
+
+    #[test]
+    fn only_presses_of_mapped_pads_have_actions() {
+        let surface = ControlSurface::new(SurfaceProfile::launchpad());
+        assert_eq!(
+            surface.action_for(&pad_on(83, 127)),
+            Some(SurfaceAction::ArmTrack(2))
+        );
+        assert_eq!(
+            surface.action_for(&pad_on(12, 127)),
+            Some(SurfaceAction::ToggleRecording)
+        );
+        assert_eq!(surface.action_for(&pad_on(83, 0)), None);
+        assert_eq!(surface.action_for(&pad_on(55, 127)), None);
+    }
+
+    #[test]
+    fn feedback_is_only_sent_for_changed_pads() {
+        let mut bats = bats();
+        let mut surface = ControlSurface::new(SurfaceProfile {
+            lit_velocity: 5,
+            unlit_velocity: 0,
+            pads: vec![
+                PadMapping {
+                    pad: 81,
+                    action: SurfaceAction::ArmTrack(0),
+                },
+                PadMapping {
+                    pad: 82,
+                    action: SurfaceAction::ArmTrack(1),
+                },
+            ],
+        });
+        surface.update_feedback(&bats);
+        assert_eq!(
+            surface.feedback.as_slice(),
+            &[(0, pad_on(81, 5)), (0, pad_on(82, 0))]
+        );
+
+        surface.update_feedback(&bats);
+        assert_eq!(surface.feedback.as_slice(), &[]);
+
+        SurfaceAction::ArmTrack(1).apply(&mut bats);
+        surface.update_feedback(&bats);
+        assert_eq!(
+            surface.feedback.as_slice(),
+            &[(0, pad_on(81, 0)), (0, pad_on(82, 5))]
+        );
+    }
+
+    #[test]
+    fn arming_missing_track_does_nothing() {
+        let mut bats = bats();
+        bats.armed_track = 1;
+        SurfaceAction::ArmTrack(100).apply(&mut bats);
+        assert_eq!(bats.armed_track, 1);
+    }
+}
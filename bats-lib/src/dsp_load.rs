@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use bats_dsp::sample_rate::SampleRate;
+
+/// Measures the share of the time available for each buffer that is spent processing it. Unlike
+/// the CPU load of the audio backend, this only includes the time spent inside bats.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DspLoad {
+    /// The smoothed load as a percentage or `None` if nothing has been measured.
+    load: Option<f32>,
+}
+
+impl DspLoad {
+    /// The number of seconds that the load takes to settle after a change.
+    pub const SMOOTHING_SECONDS: f32 = 0.5;
+
+    /// Create a new `DspLoad` with no measurements.
+    pub fn new() -> DspLoad {
+        DspLoad { load: None }
+    }
+
+    /// The smoothed load as a percentage of the time available for each buffer or `None` if
+    /// nothing has been measured. Values above `100.0` mean that processing is slower than
+    /// realtime.
+    pub fn load(&self) -> Option<f32> {
+        self.load
+    }
+
+    /// Add a measurement that processing `frames` frames took `elapsed`.
+    pub fn update(&mut self, elapsed: Duration, frames: usize, sample_rate: SampleRate) {
+        if frames == 0 {
+            return;
+        }
+        let available = frames as f32 * sample_rate.seconds_per_sample();
+        let load = 100.0 * elapsed.as_secs_f32() / available;
+        self.load = Some(match self.load {
+            None => load,
+            Some(previous) => {
+                let coefficient = 1.0 - (-available / Self::SMOOTHING_SECONDS).exp();
+                previous + coefficient * (load - previous)
+            }
+        });
+    }
+}
+
+impl Default for DspLoad {
+    fn default() -> DspLoad {
+        DspLoad::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_measurement_is_not_smoothed() {
+        let mut load = DspLoad::new();
+        assert_eq!(load.load(), None);
+        load.update(Duration::from_millis(5), 441, SampleRate::new(44100.0));
+        assert!((load.load().unwrap() - 50.0).abs() < 0.01, "{load:?}");
+    }
+
+    #[test]
+    fn load_settles_on_new_value() {
+        let sample_rate = SampleRate::new(44100.0);
+        let mut load = DspLoad::new();
+        load.update(Duration::ZERO, 441, sample_rate);
+        load.update(Duration::from_millis(10), 441, sample_rate);
+        let after_one = load.load().unwrap();
+        assert!(0.0 < after_one && after_one < 10.0, "{after_one}");
+        // Five seconds worth of buffers.
+        for _ in 0..500 {
+            load.update(Duration::from_millis(10), 441, sample_rate);
+        }
+        assert!((load.load().unwrap() - 100.0).abs() < 1.0, "{load:?}");
+    }
+}
@@ -0,0 +1,129 @@
+use bmidi::{ControlFunction, MidiMessage, U14, U7};
+
+/// A midi controller that can be routed to a plugin param.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExpressionSource {
+    /// The modulation wheel, CC1.
+    ModWheel,
+    /// Channel pressure, also known as aftertouch.
+    ChannelPressure,
+    /// The pitch bend wheel.
+    PitchBend,
+}
+
+/// Maps an expression source onto a plugin param.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ExpressionRoute {
+    /// The midi controller to read from.
+    pub source: ExpressionSource,
+    /// The id of the param to set.
+    pub param_id: u32,
+    /// The param value when the source is at its minimum.
+    pub min: f32,
+    /// The param value when the source is at its maximum.
+    pub max: f32,
+}
+
+impl ExpressionSource {
+    /// All the expression sources.
+    pub const ALL: &'static [ExpressionSource] = &[
+        ExpressionSource::ModWheel,
+        ExpressionSource::ChannelPressure,
+        ExpressionSource::PitchBend,
+    ];
+
+    /// The human readable name of the source.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExpressionSource::ModWheel => "Mod Wheel",
+            ExpressionSource::ChannelPressure => "Channel Pressure",
+            ExpressionSource::PitchBend => "Pitch Bend",
+        }
+    }
+
+    /// Get the value of the source within `[0.0, 1.0]` or `None` if `midi` does not come from
+    /// this source. A centered pitch bend has a value of `0.5`.
+    pub fn value(&self, midi: &MidiMessage) -> Option<f32> {
+        let max_u7 = u8::from(U7::MAX) as f32;
+        match (self, midi) {
+            (ExpressionSource::ModWheel, MidiMessage::ControlChange(_, cc, v))
+                if *cc == ControlFunction::MODULATION_WHEEL =>
+            {
+                Some(u8::from(*v) as f32 / max_u7)
+            }
+            (ExpressionSource::ChannelPressure, MidiMessage::ChannelPressure(_, v)) => {
+                Some(u8::from(*v) as f32 / max_u7)
+            }
+            (ExpressionSource::PitchBend, MidiMessage::PitchBendChange(_, v)) => {
+                Some(u16::from(*v) as f32 / u16::from(U14::MAX) as f32)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl ExpressionRoute {
+    /// Get the param value for `midi` or `None` if `midi` does not come from the route's source.
+    pub fn value(&self, midi: &MidiMessage) -> Option<f32> {
+        let v = self.source.value(midi)?;
+        Some(self.min + (self.max - self.min) * v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bmidi::Channel;
+
+    use super::*;
+
+    fn route(source: ExpressionSource) -> ExpressionRoute {
+        ExpressionRoute {
+            source,
+            param_id: 0,
+            min: 100.0,
+            max: 200.0,
+        }
+    }
+
+    #[test]
+    fn mod_wheel_is_scaled_to_range() {
+        let r = route(ExpressionSource::ModWheel);
+        let cc = |v| MidiMessage::ControlChange(Channel::Ch1, ControlFunction::MODULATION_WHEEL, v);
+        assert_eq!(r.value(&cc(U7::MIN)), Some(100.0));
+        assert_eq!(r.value(&cc(U7::MAX)), Some(200.0));
+        assert_eq!(
+            r.value(&MidiMessage::ControlChange(
+                Channel::Ch1,
+                ControlFunction::PAN,
+                U7::MAX
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn channel_pressure_is_scaled_to_range() {
+        let r = route(ExpressionSource::ChannelPressure);
+        assert_eq!(
+            r.value(&MidiMessage::ChannelPressure(Channel::Ch1, U7::MAX)),
+            Some(200.0)
+        );
+        assert_eq!(
+            r.value(&MidiMessage::PitchBendChange(Channel::Ch1, U14::MAX)),
+            None
+        );
+    }
+
+    #[test]
+    fn pitch_bend_is_scaled_to_range() {
+        let r = route(ExpressionSource::PitchBend);
+        assert_eq!(
+            r.value(&MidiMessage::PitchBendChange(Channel::Ch1, U14::MIN)),
+            Some(100.0)
+        );
+        assert_eq!(
+            r.value(&MidiMessage::PitchBendChange(Channel::Ch1, U14::MAX)),
+            Some(200.0)
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use std::ops::Range;
+
+use bats_dsp::{buffers::Buffers, position::Position, sample_rate::SampleRate};
+use bmidi::{Channel, U7};
+
+use crate::{
+    builder::{AnyPlugin, BatsBuilder, TrackBuilder},
+    plugin::sampler::Sampler,
+    sequence::{Note, Sequence},
+    track::Track,
+};
+
+/// A plugin and sequence that are swapped with those of a track when the track is frozen or
+/// unfrozen. Before freezing, this holds the sampler and the sequence that triggers it. While the
+/// track is frozen, this holds the original plugin and sequence.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrozenTrack {
+    /// The plugin.
+    pub plugin: AnyPlugin,
+    /// The sequence.
+    pub sequence: Sequence,
+}
+
+impl FrozenTrack {
+    /// The buffer size used to render the track.
+    const RENDER_BUFFER_SIZE: usize = 1024;
+
+    /// Render `plugin` playing `sequence` over one loop of `loop_range` and return a sampler that
+    /// plays back the audio along with a sequence that triggers it at the start of every loop.
+    ///
+    /// The loop is rendered twice and only the second pass is kept so that notes that ring past
+    /// the end of the loop are heard at the start. This allocates and is slow so it should not be
+    /// called from the audio thread.
+    pub fn render(
+        plugin: AnyPlugin,
+        sequence: &Sequence,
+        sample_rate: SampleRate,
+        bpm: f32,
+        loop_range: Range<Position>,
+    ) -> FrozenTrack {
+        let mut bats = BatsBuilder {
+            sample_rate,
+            buffer_size: FrozenTrack::RENDER_BUFFER_SIZE,
+            bpm,
+            tracks: vec![TrackBuilder::default()],
+        }
+        .build();
+        bats.tracks[0].plugin = plugin;
+        bats.tracks[0].sequence.clone_from(sequence);
+        bats.transport.set_loop_range(loop_range.clone());
+        bats.transport.set_position(loop_range.start);
+        let loop_length = loop_range.end - loop_range.start;
+        let seconds = loop_length.as_beats_f64() * 60.0 / bpm as f64;
+        let frames = (seconds * sample_rate.sample_rate() as f64).ceil() as usize;
+        let mut buffers = Buffers::new(frames);
+        for _ in 0..2 {
+            for (left, right) in buffers
+                .left
+                .chunks_mut(FrozenTrack::RENDER_BUFFER_SIZE)
+                .zip(buffers.right.chunks_mut(FrozenTrack::RENDER_BUFFER_SIZE))
+            {
+                left.fill(0.0);
+                right.fill(0.0);
+                bats.process(&[], left, right);
+            }
+        }
+
+        let mut trigger = Sequence::with_capacity(Track::SEQUENCE_CAPACITY);
+        trigger.insert_note(Note {
+            start: loop_range.start,
+            length: loop_length,
+            channel: Channel::Ch1,
+            pitch: bmidi::Note::C4,
+            velocity: U7::MAX,
+        });
+        FrozenTrack {
+            plugin: AnyPlugin::Sampler(Sampler::new(buffers)),
+            sequence: trigger,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::plugin::toof::Toof;
+
+    use super::*;
+
+    fn sequence() -> Sequence {
+        [Note {
+            start: Position::new(0.5),
+            length: Position::new(0.25),
+            channel: Channel::Ch1,
+            pitch: bmidi::Note::A4,
+            velocity: U7::MAX,
+        }]
+        .into_iter()
+        .collect()
+    }
+
+    fn render_live(plugin: AnyPlugin, sequence: &Sequence, loops: usize) -> Buffers {
+        let sample_rate = SampleRate::new(44100.0);
+        let mut bats = BatsBuilder {
+            sample_rate,
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: vec![TrackBuilder::default()],
+        }
+        .build();
+        bats.tracks[0].plugin = plugin;
+        bats.tracks[0].sequence.clone_from(sequence);
+        bats.transport
+            .set_loop_range(Position::MIN..Position::new(1.0));
+        let mut output = Buffers::new(22050 * loops);
+        for (left, right) in output.left.chunks_mut(64).zip(output.right.chunks_mut(64)) {
+            bats.process(&[], left, right);
+        }
+        output
+    }
+
+    #[test]
+    fn frozen_track_sounds_like_original() {
+        let sample_rate = SampleRate::new(44100.0);
+        let frozen = FrozenTrack::render(
+            AnyPlugin::Toof(Toof::new(sample_rate)),
+            &sequence(),
+            sample_rate,
+            120.0,
+            Position::MIN..Position::new(1.0),
+        );
+        assert_eq!(frozen.sequence.notes().len(), 1);
+        let live = render_live(AnyPlugin::Toof(Toof::new(sample_rate)), &sequence(), 3);
+        let played = render_live(frozen.plugin, &frozen.sequence, 3);
+        let loop_frames = 22050;
+        assert!(live.left[loop_frames..].iter().any(|v| v.abs() > 0.01));
+        for (a, b) in live.left[loop_frames..]
+            .iter()
+            .zip(played.left[loop_frames..].iter())
+        {
+            assert!((a - b).abs() < 1e-4, "{a} != {b}");
+        }
+    }
+
+    #[test]
+    fn render_keeps_tail_from_previous_loop() {
+        let sample_rate = SampleRate::new(44100.0);
+        let ringing = [Note {
+            start: Position::new(0.75),
+            length: Position::new(0.5),
+            channel: Channel::Ch1,
+            pitch: bmidi::Note::A4,
+            velocity: U7::MAX,
+        }]
+        .into_iter()
+        .collect();
+        let frozen = FrozenTrack::render(
+            AnyPlugin::Toof(Toof::new(sample_rate)),
+            &ringing,
+            sample_rate,
+            120.0,
+            Position::MIN..Position::new(1.0),
+        );
+        let AnyPlugin::Sampler(sampler) = frozen.plugin else {
+            panic!("expected a sampler but got {:?}", frozen.plugin);
+        };
+        let buffers = sampler.buffers().unwrap();
+        assert_eq!(buffers.len(), 22050);
+        assert!(buffers.left[..100].iter().any(|v| v.abs() > 0.0));
+    }
+}
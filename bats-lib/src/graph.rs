@@ -0,0 +1,227 @@
+use crate::{routing::RoutingError, track::Track};
+
+/// A node in the processing graph.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Node {
+    /// The plugin, input, and compressor of the track with the id.
+    Track(usize),
+    /// The return effects of the aux bus with the index.
+    AuxBus(usize),
+    /// The master compressor, fade, and captures of the mixed output.
+    Master,
+}
+
+/// The nodes that produce the output of bats and the connections between them. The graph is
+/// derived from the tracks and aux buses, which remain the way that bats is edited, and is used
+/// to schedule the nodes so that every node is processed after the nodes that feed into it.
+///
+/// Updating the graph does not allocate, so it can be done while processing audio.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcessGraph {
+    /// The number of track nodes.
+    track_count: usize,
+    /// The number of aux bus nodes.
+    aux_bus_count: usize,
+    /// The connections between nodes as `(from, to)` where the output of `from` is used by `to`.
+    edges: Vec<(Node, Node)>,
+    /// The nodes in the order that they are processed.
+    schedule: Vec<Node>,
+    /// The number of unscheduled inputs of each node, indexed by `ProcessGraph::index`. Only used
+    /// while scheduling.
+    pending: Vec<usize>,
+}
+
+impl ProcessGraph {
+    /// Create the graph for `tracks` and `aux_bus_count` aux buses. Allocates enough space to
+    /// update the graph for the same number of tracks without allocating.
+    pub fn new(tracks: &[Track], aux_bus_count: usize) -> ProcessGraph {
+        let track_count = tracks.len();
+        let node_count = track_count + aux_bus_count + 1;
+        let mut graph = ProcessGraph {
+            track_count,
+            aux_bus_count,
+            edges: Vec::with_capacity(track_count * (aux_bus_count + 2) + aux_bus_count),
+            schedule: Vec::with_capacity(node_count),
+            pending: vec![0; node_count],
+        };
+        // A graph without inputs has no cycles.
+        let _ = graph.update(tracks);
+        graph
+    }
+
+    /// Rebuild the connections from the inputs of `tracks` and schedule the nodes. Nodes that do
+    /// not depend on each other are processed in the order tracks, aux buses, and then master.
+    ///
+    /// Returns an error if the inputs of the tracks form a cycle. The schedule is left unchanged
+    /// in that case.
+    pub fn update(&mut self, tracks: &[Track]) -> Result<(), RoutingError> {
+        self.edges.clear();
+        for (id, track) in tracks.iter().enumerate().take(self.track_count) {
+            if let Some(input) = track.input.filter(|input| *input < self.track_count) {
+                self.edges.push((Node::Track(input), Node::Track(id)));
+            }
+            for bus in 0..self.aux_bus_count {
+                self.edges.push((Node::Track(id), Node::AuxBus(bus)));
+            }
+            self.edges.push((Node::Track(id), Node::Master));
+        }
+        for bus in 0..self.aux_bus_count {
+            self.edges.push((Node::AuxBus(bus), Node::Master));
+        }
+
+        // Check for cycles before replacing the schedule.
+        self.reset_pending();
+        for _ in 0..self.pending.len() {
+            if self.next_ready().is_none() {
+                // Tracks are the only nodes that can form a cycle.
+                let track_id = (0..self.track_count)
+                    .find(|id| self.pending[*id] != usize::MAX)
+                    .unwrap_or_default();
+                let input = tracks.get(track_id).and_then(|t| t.input);
+                return Err(RoutingError::Cycle {
+                    track_id,
+                    input: input.unwrap_or(track_id),
+                });
+            }
+        }
+        self.reset_pending();
+        self.schedule.clear();
+        while let Some(node) = self.next_ready() {
+            self.schedule.push(node);
+        }
+        Ok(())
+    }
+
+    /// The nodes in the order that they should be processed.
+    pub fn schedule(&self) -> &[Node] {
+        &self.schedule
+    }
+
+    /// Iterate over the nodes whose output is used by `node`.
+    pub fn inputs(&self, node: Node) -> impl Iterator<Item = Node> + '_ {
+        self.edges
+            .iter()
+            .filter(move |(_, to)| *to == node)
+            .map(|(from, _)| *from)
+    }
+
+    /// Set `pending` to the number of inputs of each node.
+    fn reset_pending(&mut self) {
+        self.pending.fill(0);
+        for (_, to) in self.edges.iter() {
+            let to = self.index(*to);
+            self.pending[to] += 1;
+        }
+    }
+
+    /// Take the first node whose inputs have all been taken and release its outputs. Taken nodes
+    /// are marked with `usize::MAX` in `pending`. Returns `None` if no node is ready.
+    fn next_ready(&mut self) -> Option<Node> {
+        let idx = self.pending.iter().position(|p| *p == 0)?;
+        self.pending[idx] = usize::MAX;
+        let node = self.node(idx);
+        for (from, to) in self.edges.iter() {
+            if *from == node {
+                let to = self.index(*to);
+                self.pending[to] -= 1;
+            }
+        }
+        Some(node)
+    }
+
+    /// Get the index of `node` within `pending`.
+    fn index(&self, node: Node) -> usize {
+        match node {
+            Node::Track(id) => id,
+            Node::AuxBus(bus) => self.track_count + bus,
+            Node::Master => self.track_count + self.aux_bus_count,
+        }
+    }
+
+    /// Get the node at `idx` within `pending`.
+    fn node(&self, idx: usize) -> Node {
+        if idx < self.track_count {
+            Node::Track(idx)
+        } else if idx < self.track_count + self.aux_bus_count {
+            Node::AuxBus(idx - self.track_count)
+        } else {
+            Node::Master
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracks(inputs: &[Option<usize>]) -> Vec<Track> {
+        inputs
+            .iter()
+            .map(|input| Track {
+                input: *input,
+                ..Track::new(4)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tracks_are_scheduled_before_aux_buses_and_master() {
+        let graph = ProcessGraph::new(&tracks(&[None, None]), 2);
+        assert_eq!(
+            graph.schedule(),
+            &[
+                Node::Track(0),
+                Node::Track(1),
+                Node::AuxBus(0),
+                Node::AuxBus(1),
+                Node::Master
+            ]
+        );
+        assert_eq!(
+            graph.inputs(Node::Master).collect::<Vec<_>>(),
+            vec![
+                Node::Track(0),
+                Node::Track(1),
+                Node::AuxBus(0),
+                Node::AuxBus(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn track_inputs_are_scheduled_first() {
+        let graph = ProcessGraph::new(&tracks(&[Some(2), None, Some(3), None]), 1);
+        assert_eq!(
+            graph.schedule(),
+            &[
+                Node::Track(1),
+                Node::Track(3),
+                Node::Track(2),
+                Node::Track(0),
+                Node::AuxBus(0),
+                Node::Master
+            ]
+        );
+        assert_eq!(
+            graph.inputs(Node::Track(0)).collect::<Vec<_>>(),
+            vec![Node::Track(2)]
+        );
+    }
+
+    #[test]
+    fn cycle_keeps_previous_schedule() {
+        let mut tracks = tracks(&[None, None]);
+        let mut graph = ProcessGraph::new(&tracks, 1);
+        let schedule = graph.schedule().to_vec();
+        tracks[0].input = Some(1);
+        tracks[1].input = Some(0);
+        assert_eq!(
+            graph.update(&tracks),
+            Err(RoutingError::Cycle {
+                track_id: 0,
+                input: 1
+            })
+        );
+        assert_eq!(graph.schedule(), schedule);
+    }
+}
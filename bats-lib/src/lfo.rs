@@ -0,0 +1,123 @@
+use bats_dsp::position::Position;
+use serde::{Deserialize, Serialize};
+
+/// The shape of an LFO.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LfoWaveform {
+    /// A sine wave.
+    #[default]
+    Sine,
+    /// A triangle wave.
+    Triangle,
+    /// A ramp that rises over the cycle and drops back down at the end.
+    Saw,
+    /// Alternates between the high and low value every half cycle.
+    Square,
+}
+
+/// Modulates a plugin param with an LFO that is synced to the transport position. The param is set
+/// once at the start of every buffer.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParamLfo {
+    /// The id of the param to modulate.
+    pub param_id: u32,
+    /// The shape of the LFO.
+    pub waveform: LfoWaveform,
+    /// The length of a single cycle in beats.
+    pub beats: f32,
+    /// The param value at the center of the LFO.
+    pub center: f32,
+    /// The amount that the param moves above and below `center`.
+    pub depth: f32,
+}
+
+impl LfoWaveform {
+    /// All the waveforms.
+    pub const ALL: &'static [LfoWaveform] = &[
+        LfoWaveform::Sine,
+        LfoWaveform::Triangle,
+        LfoWaveform::Saw,
+        LfoWaveform::Square,
+    ];
+
+    /// The human readable name of the waveform.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LfoWaveform::Sine => "Sine",
+            LfoWaveform::Triangle => "Triangle",
+            LfoWaveform::Saw => "Saw",
+            LfoWaveform::Square => "Square",
+        }
+    }
+
+    /// Get the value of the waveform within `[-1.0, 1.0]` at `phase` within `[0.0, 1.0)`. All
+    /// waveforms start at `0.0` or at the start of their rise.
+    pub fn value(&self, phase: f32) -> f32 {
+        match self {
+            LfoWaveform::Sine => (phase * std::f32::consts::TAU).sin(),
+            LfoWaveform::Triangle => 4.0 * ((phase - 0.25).rem_euclid(1.0) - 0.5).abs() - 1.0,
+            LfoWaveform::Saw => 2.0 * phase - 1.0,
+            LfoWaveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        }
+    }
+}
+
+impl ParamLfo {
+    /// The shortest cycle length in beats.
+    pub const MIN_BEATS: f32 = 1.0 / 16.0;
+
+    /// The longest cycle length in beats.
+    pub const MAX_BEATS: f32 = 64.0;
+
+    /// Get the param value at transport `position`.
+    pub fn value_at(&self, position: Position) -> f32 {
+        let beats = self.beats.clamp(ParamLfo::MIN_BEATS, ParamLfo::MAX_BEATS) as f64;
+        let phase = (position.as_beats_f64() / beats).fract() as f32;
+        self.center + self.depth * self.waveform.value(phase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn waveforms_span_full_range() {
+        for (phase, sine, triangle, saw, square) in [
+            (0.0, 0.0, 0.0, -1.0, 1.0),
+            (0.25, 1.0, 1.0, -0.5, 1.0),
+            (0.5, 0.0, 0.0, 0.0, -1.0),
+            (0.75, -1.0, -1.0, 0.5, -1.0),
+        ] {
+            assert_near(LfoWaveform::Sine.value(phase), sine);
+            assert_near(LfoWaveform::Triangle.value(phase), triangle);
+            assert_near(LfoWaveform::Saw.value(phase), saw);
+            assert_near(LfoWaveform::Square.value(phase), square);
+        }
+    }
+
+    #[test]
+    fn value_at_follows_transport_position() {
+        let lfo = ParamLfo {
+            param_id: 0,
+            waveform: LfoWaveform::Triangle,
+            beats: 2.0,
+            center: 100.0,
+            depth: 50.0,
+        };
+        assert_near(lfo.value_at(Position::new(0.0)), 100.0);
+        assert_near(lfo.value_at(Position::new(0.5)), 150.0);
+        assert_near(lfo.value_at(Position::new(1.5)), 50.0);
+        assert_near(lfo.value_at(Position::new(2.5)), 150.0);
+    }
+}
@@ -1,51 +1,597 @@
-use bats_dsp::{buffers::Buffers, sample_rate::SampleRate};
+use std::time::Instant;
+
+use arrayvec::ArrayVec;
+use bats_dsp::{
+    buffers::Buffers, position::Position, sample_rate::SampleRate, smoothed_value::SmoothedValue,
+};
 use bmidi::MidiMessage;
 
+use aux_bus::AuxBus;
+use builder::BatsBuilder;
+use capture::Capture;
+use control_surface::ControlSurface;
+use dsp_load::DspLoad;
+use graph::{Node, ProcessGraph};
+use macros::MacroKnob;
+use midi_history::MidiHistory;
+use overload::OverloadProtection;
+
+use plugin::{compressor::Compressor, BatsEffect};
+use recorder::{RecordSource, Recorder};
+use routing::RoutingError;
+use scene::SceneMorph;
+use scope::Scope;
+use sequence::{Sequence, SequenceItem};
 use track::{Track, TrackProcessContext};
-use transport::Transport;
+use transport::{Transport, TransportSync};
+use transpose::Transpose;
 
+pub mod automation;
+pub mod aux_bus;
 pub mod builder;
+pub mod capture;
+pub mod control_surface;
+pub mod dsp_load;
+pub mod expression;
+pub mod freeze;
+pub mod graph;
+pub mod lfo;
+pub mod macros;
+pub mod midi_filter;
+pub mod midi_history;
+pub mod overload;
 pub mod plugin;
+pub mod plugin_slot;
+pub mod preset;
+pub mod recorder;
+pub mod registry;
+pub mod routing;
+pub mod scene;
+pub mod scope;
+pub mod sequence;
+pub mod stream;
 pub mod track;
 pub mod transport;
+pub mod transpose;
+pub mod tuning;
 
 /// Handles all processing.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Bats {
     /// The transport.
     pub transport: Transport,
-    /// The id of the track that should take user midi input.
+    /// The id of the track that should take user midi input. Set to `Bats::NO_ARMED_TRACK` to
+    /// disarm all tracks.
     pub armed_track: usize,
     /// True if recording to sequence is enabled.
     pub recording_enabled: bool,
+    /// The number of frames between when midi is played and when it reaches `process`. Recorded
+    /// midi is moved earlier by this many frames to compensate.
+    pub record_latency: u32,
+    /// True if the transport should be playing. When this becomes false, the output is faded out
+    /// before the transport stops and stays silent until the transport starts again.
+    pub playing: bool,
+    /// The gain applied to the output to fade it in and out when the transport starts and stops.
+    pub fade: SmoothedValue,
+    /// How the transport is synced with the transport of the audio backend.
+    pub transport_sync: TransportSync,
     /// The sample rate.
     pub sample_rate: SampleRate,
     /// The buffer size.
     pub buffer_size: usize,
+    /// The seed for the random number generators of the plugins. Each track derives its own seed
+    /// from this so that renders with the same seed are reproducible.
+    pub seed: u64,
     /// Temporary buffer for midi data.
     pub midi_buffer: Vec<(u32, MidiMessage)>,
+    /// Temporary buffer for the midi of all input ports, tagged with the index of the port. The
+    /// midi for the armed track has already been transposed.
+    pub port_midi: Vec<(usize, (u32, MidiMessage))>,
+    /// Temporary buffer for the midi input of a single track.
+    pub track_midi_in: Vec<(u32, MidiMessage)>,
+    /// The track that receives the midi from each midi input port, indexed by port. Ports routed
+    /// to `None` send their midi to the armed track.
+    pub midi_input_routes: [Option<usize>; Bats::MIDI_INPUT_PORT_COUNT],
+    /// Transposes the midi that is sent to the armed track.
+    pub transpose: Transpose,
+    /// The recent midi that was sent to the armed track. Added to a sequence with
+    /// `Command::CaptureMidiHistory`.
+    pub midi_history: MidiHistory,
+    /// The midi that was added to a track by `Command::CaptureMidiHistory` and the id of the
+    /// track. Waiting to be sent back.
+    pub captured: Option<(usize, Box<Sequence>)>,
     /// The tracks.
-    pub tracks: [Track; Bats::SUPPORTED_TRACKS],
+    pub tracks: Vec<Track>,
+    /// Schedules the tracks, aux buses, and master output so that each is processed after its
+    /// inputs. Updated by `Bats::set_track_input`.
+    pub graph: ProcessGraph,
+    /// The output of each track with the track volume applied, indexed by track id. Empty unless
+    /// direct outputs have been enabled with `Command::SetDirectOutputs`.
+    pub direct_outputs: Vec<Buffers>,
+    /// The macro knobs. Each knob sets its targets at the start of the buffer whenever its value
+    /// changes.
+    pub macros: [MacroKnob; Bats::MACRO_COUNT],
+    /// Morphs the params of all tracks between two scenes.
+    pub morph: SceneMorph,
+    /// The aux buses that tracks can send to. The returns are mixed in before the master
+    /// compressor.
+    pub aux_buses: [AuxBus; Bats::AUX_BUS_COUNT],
+    /// The compressor applied to the mix of all tracks or `None` if the mix is not compressed.
+    pub master_compressor: Option<Box<Compressor>>,
+    /// Captures the output of `process`, for example to export it. Set with
+    /// `Command::SetCapture`.
+    pub capture: Option<Box<Capture>>,
+    /// Streams the master output or a track output to another thread, for example to record it
+    /// to disk. Set with `Command::SetRecorder`.
+    pub recorder: Option<Box<Recorder>>,
+    /// Streams a downsampled copy of the master output to another thread, for example to draw
+    /// it as an oscilloscope. Set with `Command::SetScope`.
+    pub scope: Option<Box<Scope>>,
+    /// A snapshot of the state of bats that is waiting to be sent back. Set with
+    /// `Command::RequestSnapshot`.
+    pub snapshot: Option<Box<BatsBuilder>>,
+    /// The share of the time available for each buffer that is spent in `process`.
+    pub dsp_load: DspLoad,
+    /// Bypasses the aux bus returns while the DSP load is too high.
+    pub overload: OverloadProtection,
+    /// The grid controller that arms tracks and toggles the transport or `None` if there is no
+    /// control surface. Set with `Command::SetControlSurface`.
+    pub control_surface: Option<Box<ControlSurface>>,
+    /// Events that occurred during processing. Should be drained by the owner of `Bats` to
+    /// forward them to non-realtime threads.
+    pub events: ArrayVec<BatsEvent, { Bats::EVENTS_CAPACITY }>,
+}
+
+/// An event that occurred while processing.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BatsEvent {
+    /// The sequence for the track is full and some recorded events were dropped.
+    SequenceFull {
+        /// The id of the track.
+        track_id: usize,
+        /// The number of midi events that were dropped.
+        dropped: usize,
+    },
+    /// A note or midi message was recorded to the sequence of the track.
+    Recorded {
+        /// The id of the track.
+        track_id: usize,
+        /// The note or message that was recorded.
+        item: SequenceItem,
+    },
+    /// The loop length of the track was detected from the recorded sequence.
+    LoopLength {
+        /// The id of the track.
+        track_id: usize,
+        /// The number of beats after which the sequence repeats.
+        length: Option<Position>,
+    },
+    /// Overload protection was engaged or released.
+    Overload {
+        /// True if protection was engaged.
+        active: bool,
+    },
+    /// A pad of the control surface changed the armed track, recording, or playing.
+    ControlSurface {
+        /// The id of the armed track.
+        armed_track: usize,
+        /// True if recording to sequence is enabled.
+        recording_enabled: bool,
+        /// True if the transport should be playing.
+        playing: bool,
+    },
 }
 
 impl Bats {
-    /// The number of supported tracks.
-    pub const SUPPORTED_TRACKS: usize = 8;
+    /// The number of tracks that are created by default.
+    pub const DEFAULT_TRACK_COUNT: usize = 8;
+
+    /// The maximum number of events that can be buffered before they are drained.
+    pub const EVENTS_CAPACITY: usize = 64;
+
+    /// The number of midi input ports that can be routed to tracks.
+    pub const MIDI_INPUT_PORT_COUNT: usize = 2;
+
+    /// The number of macro knobs.
+    pub const MACRO_COUNT: usize = 8;
+
+    /// The number of aux buses.
+    pub const AUX_BUS_COUNT: usize = 2;
 
-    /// Process midi data and output audio.
+    /// The duration of the fade in and fade out when the transport starts and stops.
+    pub const FADE_SECONDS: f32 = 0.01;
+
+    /// The value of `armed_track` when no track is armed. User midi is then only played by tracks
+    /// that have a midi input port routed to them.
+    pub const NO_ARMED_TRACK: usize = usize::MAX;
+
+    /// Process midi data and output audio. All of `midi` is treated as coming from the first midi
+    /// input port.
     pub fn process(&mut self, midi: &[(u32, MidiMessage)], left: &mut [f32], right: &mut [f32]) {
+        self.process_impl(midi.iter().map(|m| (0, *m)), left, right);
+    }
+
+    /// Process midi data from multiple midi input ports and output audio. Each event in `midi` is
+    /// tagged with the index of its port and is sent to the track from `midi_input_routes`. `midi`
+    /// must be sorted by frame.
+    pub fn process_ports(
+        &mut self,
+        midi: &[(usize, u32, MidiMessage)],
+        left: &mut [f32],
+        right: &mut [f32],
+    ) {
+        self.process_impl(
+            midi.iter().map(|(port, frame, m)| (*port, (*frame, *m))),
+            left,
+            right,
+        );
+    }
+
+    fn process_impl(
+        &mut self,
+        midi: impl Iterator<Item = (usize, (u32, MidiMessage))>,
+        left: &mut [f32],
+        right: &mut [f32],
+    ) {
+        let start = Instant::now();
+        self.start_fade();
         self.transport.process(left, right);
-        for (id, track) in self.tracks.iter_mut().enumerate() {
-            let is_armed = id == self.armed_track;
-            let midi_in = if is_armed { midi } else { &[] };
-            track.process(TrackProcessContext {
-                record_to_sequence: self.recording_enabled,
-                transport: &self.transport,
-                midi_in,
-                tmp_midi_buffer: &mut self.midi_buffer,
+        let transport_info = self.transport.info();
+        for bus in self.aux_buses.iter_mut() {
+            bus.clear();
+            bus.set_transport_info(&transport_info);
+        }
+        self.port_midi.clear();
+        for (port, (frame, m)) in midi {
+            let m = if self.track_for_port(port) == self.armed_track {
+                match self.transpose.apply(m) {
+                    Some(m) => m,
+                    None => continue,
+                }
+            } else {
+                m
+            };
+            self.port_midi.push((port, (frame, m)));
+        }
+        let (routes, armed_track) = (self.midi_input_routes, self.armed_track);
+        self.midi_history.push_buffer(
+            self.port_midi
+                .iter()
+                .filter(|(port, _)| {
+                    routes.get(*port).copied().flatten().unwrap_or(armed_track) == armed_track
+                })
+                .map(|(_, m)| *m),
+            &self.transport,
+            self.record_latency,
+        );
+        for knob in self.macros.iter_mut() {
+            for (_, (_, m)) in self.port_midi.iter() {
+                knob.handle_midi(m);
+            }
+            knob.apply(&mut self.tracks);
+        }
+        self.morph
+            .process(left.len(), self.sample_rate, &mut self.tracks);
+        for idx in 0..self.graph.schedule().len() {
+            match self.graph.schedule()[idx] {
+                Node::Track(id) => self.process_track(id, left, right),
+                Node::AuxBus(_) if self.overload.is_active() => (),
+                Node::AuxBus(bus) => self.aux_buses[bus].mix_return(left, right),
+                Node::Master => self.process_master(left, right),
+            }
+        }
+        self.dsp_load
+            .update(start.elapsed(), left.len(), self.sample_rate);
+        if let Some(active) = self.overload.update(self.dsp_load.load()) {
+            let _ = self.events.try_push(BatsEvent::Overload { active });
+        }
+        if let Some(mut surface) = self.control_surface.take() {
+            surface.update_feedback(self);
+            self.control_surface = Some(surface);
+        }
+    }
+
+    /// Perform the actions of the control surface pads that are pressed in `midi`. Should be
+    /// called before `process` so that the actions apply to the buffer and the pads are lit
+    /// accordingly. Does nothing if there is no control surface.
+    pub fn handle_control_surface(&mut self, midi: impl Iterator<Item = MidiMessage>) {
+        let Some(surface) = self.control_surface.take() else {
+            return;
+        };
+        let mut changed = false;
+        for msg in midi {
+            if let Some(action) = surface.action_for(&msg) {
+                action.apply(self);
+                changed = true;
+            }
+        }
+        self.control_surface = Some(surface);
+        if changed {
+            let _ = self.events.try_push(BatsEvent::ControlSurface {
+                armed_track: self.armed_track,
+                recording_enabled: self.recording_enabled,
+                playing: self.playing,
+            });
+        }
+    }
+
+    /// Add the midi history to the sequence of the track with `track_id`. The added notes and
+    /// messages are also written to `captured`, which should have enough capacity to hold them. If
+    /// the sequence was empty, then the loop length of the track is set to fit the captured midi.
+    /// Returns `false` if the track does not exist.
+    ///
+    /// This does not allocate.
+    pub fn capture_midi_history(&mut self, track_id: usize, captured: &mut Sequence) -> bool {
+        let track = match self.tracks.get_mut(track_id) {
+            Some(t) => t,
+            None => return false,
+        };
+        captured.clear();
+        let was_empty = track.sequence.is_empty() && track.loop_length.is_none();
+        for item in self.midi_history.items() {
+            let (item, events) = match item {
+                SequenceItem::Note(n) => {
+                    let start = track.sequence_position(&self.transport, n.start);
+                    (SequenceItem::Note(sequence::Note { start, ..n }), 2)
+                }
+                SequenceItem::Message(e) => {
+                    let position = track.sequence_position(&self.transport, e.position);
+                    (
+                        SequenceItem::Message(plugin::MidiEvent { position, ..e }),
+                        1,
+                    )
+                }
+            };
+            if track.sequence.len() + events > Track::SEQUENCE_CAPACITY
+                || captured.len() + events > captured.capacity()
+            {
+                break;
+            }
+            track.sequence.insert_item(item);
+            captured.insert_item(item);
+        }
+        if was_empty && !captured.is_empty() {
+            track.loop_length = track.fit_loop_length(&self.transport);
+            let _ = self.events.try_push(BatsEvent::LoopLength {
+                track_id,
+                length: track.loop_length,
+            });
+        }
+        true
+    }
+
+    /// Get the track that receives the midi from the midi input `port`.
+    fn track_for_port(&self, port: usize) -> usize {
+        self.midi_input_routes
+            .get(port)
+            .copied()
+            .flatten()
+            .unwrap_or(self.armed_track)
+    }
+
+    /// Process the track with `id` and mix its output into `left`, `right`, and the aux buses.
+    fn process_track(&mut self, id: usize, left: &mut [f32], right: &mut [f32]) {
+        let filter = self.tracks[id].midi_filter;
+        self.track_midi_in.clear();
+        for (port, (frame, m)) in self.port_midi.iter().copied() {
+            let target = self.track_for_port(port);
+            if target != id && !(filter.layer && target == self.armed_track) {
+                continue;
+            }
+            if let Some(m) = filter.apply(m) {
+                self.track_midi_in.push((frame, m));
+            }
+        }
+        let (track, input) = routing::track_and_input(&mut self.tracks, id);
+        let loop_length = track.loop_length;
+        let dropped = track.process(TrackProcessContext {
+            record_to_sequence: self.recording_enabled,
+            transport: &self.transport,
+            midi_in: &self.track_midi_in,
+            record_latency: self.record_latency,
+            tmp_midi_buffer: &mut self.midi_buffer,
+            input,
+        });
+        if dropped > 0 {
+            let _ = self.events.try_push(BatsEvent::SequenceFull {
+                track_id: id,
+                dropped,
+            });
+        }
+        if track.loop_length != loop_length {
+            let _ = self.events.try_push(BatsEvent::LoopLength {
+                track_id: id,
+                length: track.loop_length,
+            });
+        }
+        for item in track.recorded.iter() {
+            let _ = self.events.try_push(BatsEvent::Recorded {
+                track_id: id,
+                item: *item,
             });
-            mix(left, &track.output.left, track.volume);
-            mix(right, &track.output.right, track.volume);
+        }
+        match self.direct_outputs.get_mut(id) {
+            Some(direct) => {
+                track.write_output(&mut direct.left, &mut direct.right);
+                for (dst, src) in left.iter_mut().zip(direct.left.iter()) {
+                    *dst += src;
+                }
+                for (dst, src) in right.iter_mut().zip(direct.right.iter()) {
+                    *dst += src;
+                }
+            }
+            None if track.is_silent() => (),
+            None => track.mix_output(left, right),
+        }
+        if !track.is_silent() {
+            track.mix_sends(&mut self.aux_buses);
+        }
+        if let Some(capture) = self.capture.as_mut() {
+            if !track.is_silent() {
+                capture.push_stem(id, &track.output.left, &track.output.right, track.volume);
+            }
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            if recorder.source == RecordSource::Track(id) {
+                recorder.push(&track.output.left, &track.output.right);
+            }
+        }
+    }
+
+    /// Apply the master compressor and fade to the mix in `left` and `right` and pass it to the
+    /// capture, recorder, and scope.
+    fn process_master(&mut self, left: &mut [f32], right: &mut [f32]) {
+        if let Some(compressor) = self.master_compressor.as_mut() {
+            for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                (*l, *r) = compressor.process((*l, *r));
+            }
+        }
+        self.apply_fade(left, right);
+        if let Some(capture) = self.capture.as_mut() {
+            capture.push(left, right);
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            if recorder.source == RecordSource::Master {
+                recorder.push(left, right);
+            }
+        }
+        if let Some(scope) = self.scope.as_mut() {
+            scope.push(left, right);
+        }
+    }
+
+    /// Start fading in or out if `playing` does not match the transport. Starting the transport
+    /// takes effect immediately while stopping waits for the fade out to complete.
+    fn start_fade(&mut self) {
+        let fade_frames = (self.sample_rate.sample_rate() * Bats::FADE_SECONDS) as usize;
+        match (self.playing, self.transport.playing()) {
+            (true, false) => {
+                self.transport.set_playing(true);
+                self.fade.set_value(0.0);
+                self.fade.set_target(1.0, fade_frames);
+            }
+            (false, true) if self.fade.target() != 0.0 => self.fade.set_target(0.0, fade_frames),
+            _ => (),
+        }
+    }
+
+    /// Apply the fade gain to `left` and `right`. Once a fade out completes, the transport is
+    /// stopped and sequenced notes are released. The gain is held at 0 until the transport starts
+    /// again so that the release of the notes is not heard.
+    fn apply_fade(&mut self, left: &mut [f32], right: &mut [f32]) {
+        if self.fade.is_smoothing() || self.fade.value() != 1.0 {
+            for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                let gain = self.fade.next_value();
+                *l *= gain;
+                *r *= gain;
+            }
+        }
+        if !self.playing && self.transport.playing() && !self.fade.is_smoothing() {
+            self.transport.set_playing(false);
+            let position = self.transport.position();
+            let frame = left.len().saturating_sub(1) as u32;
+            for track in self.tracks.iter_mut() {
+                let position = track.sequence_position(&self.transport, position);
+                track.release_sequence_notes(position, frame);
+            }
+        }
+    }
+
+    /// Get the compressor slot for the track with `track_id` or for the master bus if `track_id`
+    /// is `None`. Returns `None` if the track does not exist.
+    pub fn compressor_mut(
+        &mut self,
+        track_id: Option<usize>,
+    ) -> Option<&mut Option<Box<Compressor>>> {
+        match track_id {
+            Some(id) => self.tracks.get_mut(id).map(|t| &mut t.compressor),
+            None => Some(&mut self.master_compressor),
+        }
+    }
+
+    /// Mix the output of the track at `input` into the track at `track_id`, or remove the input of
+    /// the track if `input` is `None`. The processing order is updated so that `input` is
+    /// processed first. Returns the previous input of the track.
+    ///
+    /// This does not allocate, so it can be called while processing audio.
+    pub fn set_track_input(
+        &mut self,
+        track_id: usize,
+        input: Option<usize>,
+    ) -> Result<Option<usize>, RoutingError> {
+        match input {
+            Some(input) => routing::check_input(
+                self.tracks.len(),
+                |id| self.tracks[id].input,
+                track_id,
+                input,
+            )?,
+            None if track_id >= self.tracks.len() => {
+                return Err(RoutingError::TrackNotFound(track_id))
+            }
+            None => (),
+        }
+        let previous = std::mem::replace(&mut self.tracks[track_id].input, input);
+        if let Err(err) = self.graph.update(&self.tracks) {
+            self.tracks[track_id].input = previous;
+            return Err(err);
+        }
+        Ok(previous)
+    }
+
+    /// Set the sample rate. All sample rate dependent state, like the transport and plugin
+    /// internals, is updated to match.
+    pub fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+        self.transport.set_sample_rate(sample_rate);
+        for track in self.tracks.iter_mut() {
+            track.plugin.plugin_mut().set_sample_rate(sample_rate);
+            if let Some(c) = track.compressor.as_mut() {
+                c.set_sample_rate(sample_rate);
+            }
+        }
+        for bus in self.aux_buses.iter_mut() {
+            for effect in bus.effects.iter_mut() {
+                effect.effect_mut().set_sample_rate(sample_rate);
+            }
+        }
+        if let Some(c) = self.master_compressor.as_mut() {
+            c.set_sample_rate(sample_rate);
+        }
+    }
+
+    /// Set the seed and reseed the plugins of all tracks with their track seed.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        for (id, track) in self.tracks.iter_mut().enumerate() {
+            track
+                .plugin
+                .plugin_mut()
+                .set_seed(Bats::track_seed(seed, id));
+        }
+    }
+
+    /// Get the seed for the plugin of the track with `track_id`.
+    pub fn track_seed(seed: u64, track_id: usize) -> u64 {
+        seed.wrapping_add(track_id as u64)
+    }
+
+    /// Set the buffer size and reallocate all buffers to fit it. This allocates so it should not
+    /// be called while processing audio.
+    pub fn set_buffer_size(&mut self, buffer_size: usize) {
+        self.buffer_size = buffer_size;
+        self.transport.set_buffer_size(buffer_size);
+        self.midi_buffer = Vec::with_capacity(buffer_size * 8);
+        self.port_midi = Vec::with_capacity(buffer_size * 8);
+        self.track_midi_in = Vec::with_capacity(buffer_size * 8);
+        for track in self.tracks.iter_mut() {
+            track.set_buffer_size(buffer_size);
+        }
+        for direct in self.direct_outputs.iter_mut() {
+            *direct = Buffers::new(buffer_size);
+        }
+        for bus in self.aux_buses.iter_mut() {
+            bus.set_buffer_size(buffer_size);
         }
     }
 
@@ -64,19 +610,18 @@ impl Bats {
     }
 }
 
-/// Mix `src` onto `dst` weighted by `volume`.
-fn mix(dst: &mut [f32], src: &[f32], volume: f32) {
-    for (d, s) in dst.iter_mut().zip(src.iter()) {
-        *d += volume * s;
-    }
-}
-
 #[cfg(test)]
 mod tests {
 
+    use bats_dsp::position::Position;
     use bmidi::{Channel, Note, U7};
 
-    use crate::{builder::BatsBuilder, plugin::toof::Toof};
+    use crate::{
+        builder::BatsBuilder,
+        midi_filter::MidiFilter,
+        plugin::toof::Toof,
+        sequence::{self, Sequence},
+    };
 
     use super::*;
 
@@ -90,7 +635,7 @@ mod tests {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: 16,
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
         let _: &dyn std::fmt::Debug = &b;
@@ -102,10 +647,41 @@ mod tests {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: 1024,
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        assert_eq!(b.tracks.len(), Bats::DEFAULT_TRACK_COUNT);
+    }
+
+    #[test]
+    fn set_buffer_size_resizes_buffers() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.set_buffer_size(512);
+        assert_eq!(b.buffer_size, 512);
+        assert!(b.midi_buffer.capacity() >= 512);
+        for track in b.tracks.iter() {
+            assert_eq!(track.output.len(), 512);
+        }
+        let buffers = b.process_to_buffer(512, &[]);
+        assert_eq!(buffers.len(), 512);
+    }
+
+    #[test]
+    fn bats_track_count_is_configurable() {
+        let b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 1024,
+            bpm: 120.0,
+            tracks: vec![Default::default(); 16],
         }
         .build();
-        assert_eq!(b.tracks.len(), Bats::SUPPORTED_TRACKS);
+        assert_eq!(b.tracks.len(), 16);
     }
 
     #[test]
@@ -119,7 +695,7 @@ mod tests {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: buffers.len(),
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
         b.process(&[], &mut buffers.left, &mut buffers.right);
@@ -134,7 +710,7 @@ mod tests {
             sample_rate: SampleRate::new(16.0),
             buffer_size: left.len(),
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
         b.transport.set_synth_decay(SampleRate::new(16.0), 0.0);
@@ -159,14 +735,15 @@ mod tests {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: sample_count,
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
         b.tracks[0] = Track {
             plugin: Toof::new(SampleRate::new(44100.0)).into(),
             volume: 1.0,
             output: Buffers::new(sample_count),
-            sequence: Vec::new(),
+            sequence: Sequence::new(),
+            ..Track::new(sample_count)
         };
         b.armed_track = 100;
         let buffers = b.process_to_buffer(
@@ -183,14 +760,15 @@ mod tests {
             sample_rate: SampleRate::new(44100.0),
             buffer_size: sample_count,
             bpm: 120.0,
-            tracks: Default::default(),
+            tracks: BatsBuilder::default_tracks(),
         }
         .build();
         b.tracks[0] = Track {
             plugin: Toof::new(SampleRate::new(44100.0)).into(),
             volume: 1.0,
             output: Buffers::new(sample_count),
-            sequence: Vec::new(),
+            sequence: Sequence::new(),
+            ..Track::new(sample_count)
         };
         b.armed_track = 0;
         let buffers = b.process_to_buffer(
@@ -199,4 +777,440 @@ mod tests {
         );
         assert!(!buffers.is_zero());
     }
+
+    #[test]
+    fn recorded_midi_is_reported_as_event() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.armed_track = 2;
+        b.recording_enabled = true;
+        let note_on = MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX);
+        let note_off = MidiMessage::NoteOff(Channel::Ch1, Note::C4, U7::MIN);
+        b.process_to_buffer(64, &[(0, note_on), (32, note_off)]);
+        assert_eq!(
+            b.events.as_slice(),
+            &[BatsEvent::Recorded {
+                track_id: 2,
+                item: SequenceItem::Note(b.tracks[2].sequence.notes()[0]),
+            }]
+        );
+        assert_eq!(b.tracks[2].sequence.notes()[0].pitch, Note::C4);
+    }
+
+    #[test]
+    fn direct_outputs_are_filled_per_track() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.tracks[1].plugin = Toof::new(SampleRate::new(44100.0)).into();
+        b.armed_track = 1;
+        b.direct_outputs = vec![Buffers::new(64); b.tracks.len()];
+        let buffers = b.process_to_buffer(
+            64,
+            &[(0, MidiMessage::NoteOn(Channel::Ch1, Note::C3, U7::MAX))],
+        );
+        assert!(b.direct_outputs[0].is_zero());
+        assert_eq!(b.direct_outputs[1], buffers);
+    }
+
+    #[test]
+    fn master_compressor_is_applied_to_mix() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.tracks[0].plugin = Toof::new(SampleRate::new(44100.0)).into();
+        let mut compressed = b.clone();
+        let mut compressor = Compressor::new(SampleRate::new(44100.0));
+        compressor.set_param(1, 1.0);
+        compressor.set_param(5, 2.0);
+        *compressed.compressor_mut(None).unwrap() = Some(compressor);
+        let midi = [(0, MidiMessage::NoteOn(Channel::Ch1, Note::C3, U7::MAX))];
+        let plain = b.process_to_buffer(64, &midi);
+        let compressed = compressed.process_to_buffer(64, &midi);
+        assert!(!plain.is_zero());
+        let doubled: Vec<_> = plain.left.iter().map(|v| v * 2.0).collect();
+        assert_eq!(compressed.left, doubled);
+    }
+
+    #[test]
+    fn track_sends_are_mixed_through_aux_bus_effects() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.tracks[0].plugin = Toof::new(SampleRate::new(44100.0)).into();
+        let mut sent = b.clone();
+        sent.tracks[0].sends[1] = 0.5;
+        let mut compressor = Compressor::new(SampleRate::new(44100.0));
+        compressor.set_param(1, 1.0);
+        compressor.set_param(5, 2.0);
+        sent.aux_buses[1]
+            .effects
+            .push(builder::AnyEffect::Compressor(compressor));
+        let midi = [(0, MidiMessage::NoteOn(Channel::Ch1, Note::C3, U7::MAX))];
+        let plain = b.process_to_buffer(64, &midi);
+        let sent = sent.process_to_buffer(64, &midi);
+        assert!(!plain.is_zero());
+        let doubled: Vec<_> = plain.left.iter().map(|v| v * 2.0).collect();
+        assert_eq!(sent.left, doubled);
+    }
+
+    #[test]
+    fn macro_cc_sets_targets_before_tracks_are_processed() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.macros[2] = MacroKnob {
+            cc: Some(bmidi::ControlFunction::MODULATION_WHEEL),
+            mappings: macros::MacroMapping::crossfader(&[0], &[1]),
+            ..MacroKnob::default()
+        };
+        b.process_to_buffer(
+            64,
+            &[(
+                10,
+                MidiMessage::ControlChange(
+                    Channel::Ch1,
+                    bmidi::ControlFunction::MODULATION_WHEEL,
+                    U7::MAX,
+                ),
+            )],
+        );
+        assert_eq!(b.tracks[0].volume, 0.0);
+        assert_eq!(b.tracks[1].volume, 1.0);
+    }
+
+    #[test]
+    fn morph_interpolates_params_per_buffer() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(64.0),
+            buffer_size: 16,
+            bpm: 120.0,
+            tracks: vec![builder::TrackBuilder {
+                plugin: builder::PluginBuilder::Toof,
+                ..builder::TrackBuilder::default()
+            }],
+        }
+        .build();
+        let scene = |value| scene::Scene {
+            params: vec![scene::SceneParam {
+                track_id: 0,
+                param_id: 2,
+                value,
+            }],
+        };
+        b.morph = SceneMorph {
+            params: scene::MorphParam::between(&scene(100.0), &scene(500.0)),
+            target: 1.0,
+            seconds: 1.0,
+            ..SceneMorph::default()
+        };
+        let mut values = Vec::new();
+        for _ in 0..5 {
+            b.process_to_buffer(16, &[]);
+            values.push(b.tracks[0].plugin.plugin().param(2));
+        }
+        assert_eq!(values, vec![200.0, 300.0, 400.0, 500.0, 500.0]);
+    }
+
+    #[test]
+    fn capture_records_output() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(16.0),
+            buffer_size: 4,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.transport.metronome_volume = 1.0;
+        b.capture = Some(Box::new(Capture::new(6)));
+        let first = b.process_to_buffer(4, &[]);
+        let second = b.process_to_buffer(4, &[]);
+        let capture = b.capture.unwrap();
+        assert!(capture.is_complete());
+        assert_eq!(capture.buffers.left[..4], first.left);
+        assert_eq!(capture.buffers.left[4..], second.left[..2]);
+    }
+
+    #[test]
+    fn stopping_fades_out_before_transport_stops_and_starting_fades_in() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 1024,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.tracks[0].plugin = Toof::new(SampleRate::new(44100.0)).into();
+        b.tracks[0].sequence = Sequence::from_iter([sequence::Note {
+            start: Position::MIN,
+            length: Position::new(8.0),
+            channel: Channel::Ch1,
+            pitch: Note::C3,
+            velocity: U7::MAX,
+        }]);
+        assert!(!b.process_to_buffer(1024, &[]).is_zero());
+
+        b.playing = false;
+        let fade_frames = (44100.0 * Bats::FADE_SECONDS) as usize;
+        let out = b.process_to_buffer(1024, &[]);
+        assert!(out.left[..fade_frames / 2].iter().any(|v| v.abs() > 0.01));
+        assert!(out.left[fade_frames..].iter().all(|v| *v == 0.0));
+        assert!(!b.transport.playing());
+        let position = b.transport.position();
+        for _ in 0..8 {
+            assert!(b.process_to_buffer(1024, &[]).is_zero());
+        }
+        assert_eq!(b.transport.position(), position);
+
+        b.playing = true;
+        let out = b.process_to_buffer(1024, &[]);
+        assert!(b.transport.playing());
+        assert!(b.transport.position() > position);
+        assert!(out.left[0].abs() < 0.01, "{}", out.left[0]);
+    }
+
+    #[test]
+    fn recorder_records_master_or_track_output() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.tracks[0].plugin = Toof::new(SampleRate::new(44100.0)).into();
+        b.tracks[0].volume = 0.5;
+        let midi = [(0, MidiMessage::NoteOn(Channel::Ch1, Note::C3, U7::MAX))];
+        let mut track_b = b.clone();
+
+        let (recorder, mut reader) = Recorder::new(RecordSource::Master, 1024);
+        b.recorder = Some(Box::new(recorder));
+        let output = b.process_to_buffer(64, &midi);
+        let mut frames = Vec::new();
+        assert_eq!(reader.read_into(&mut frames), 64);
+        let expected: Vec<_> = output.left.into_iter().zip(output.right).collect();
+        assert_eq!(frames, expected);
+
+        let (recorder, mut reader) = Recorder::new(RecordSource::Track(0), 1024);
+        track_b.recorder = Some(Box::new(recorder));
+        track_b.process_to_buffer(64, &midi);
+        frames.clear();
+        assert_eq!(reader.read_into(&mut frames), 64);
+        let output = &track_b.tracks[0].output;
+        let expected: Vec<_> = output
+            .left
+            .iter()
+            .copied()
+            .zip(output.right.iter().copied())
+            .collect();
+        assert!(!output.is_zero());
+        assert_eq!(frames, expected);
+    }
+
+    #[test]
+    fn midi_ports_are_routed_to_tracks() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.armed_track = 2;
+        b.recording_enabled = true;
+        b.midi_input_routes[1] = Some(5);
+        let press = |note| MidiMessage::NoteOn(Channel::Ch1, note, U7::MAX);
+        let release = |note| MidiMessage::NoteOff(Channel::Ch1, note, U7::MIN);
+        let mut buffers = Buffers::new(64);
+        b.process_ports(
+            &[
+                (0, 0, press(Note::C4)),
+                (1, 0, press(Note::D4)),
+                (0, 10, release(Note::C4)),
+                (1, 10, release(Note::D4)),
+            ],
+            &mut buffers.left,
+            &mut buffers.right,
+        );
+        let recorded = |track: &Track| {
+            track
+                .sequence
+                .notes()
+                .iter()
+                .map(|n| n.pitch)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(recorded(&b.tracks[2]), vec![Note::C4]);
+        assert_eq!(recorded(&b.tracks[5]), vec![Note::D4]);
+    }
+
+    #[test]
+    fn transpose_only_applies_to_armed_track() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.armed_track = 2;
+        b.recording_enabled = true;
+        b.midi_input_routes[1] = Some(5);
+        b.transpose.semitones = 12;
+        let press = |note| MidiMessage::NoteOn(Channel::Ch1, note, U7::MAX);
+        let release = |note| MidiMessage::NoteOff(Channel::Ch1, note, U7::MIN);
+        let mut buffers = Buffers::new(64);
+        b.process_ports(
+            &[
+                (0, 0, press(Note::C4)),
+                (1, 0, press(Note::D4)),
+                (0, 10, release(Note::C4)),
+                (1, 10, release(Note::D4)),
+            ],
+            &mut buffers.left,
+            &mut buffers.right,
+        );
+        let pitches = |track: &Track| {
+            track
+                .sequence
+                .notes()
+                .iter()
+                .map(|n| n.pitch)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pitches(&b.tracks[2]), vec![Note::C5]);
+        assert_eq!(pitches(&b.tracks[5]), vec![Note::D4]);
+    }
+
+    #[test]
+    fn layered_tracks_with_note_ranges_split_the_keyboard() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.armed_track = 0;
+        b.recording_enabled = true;
+        b.tracks[0].midi_filter = MidiFilter {
+            high_note: Note::B3,
+            ..MidiFilter::default()
+        };
+        b.tracks[1].midi_filter = MidiFilter {
+            low_note: Note::C4,
+            transpose: -12,
+            layer: true,
+            ..MidiFilter::default()
+        };
+        let press = |note| MidiMessage::NoteOn(Channel::Ch1, note, U7::MAX);
+        let release = |note| MidiMessage::NoteOff(Channel::Ch1, note, U7::MIN);
+        b.process_to_buffer(
+            64,
+            &[
+                (0, press(Note::C3)),
+                (0, press(Note::C4)),
+                (10, release(Note::C3)),
+                (10, release(Note::C4)),
+            ],
+        );
+        let recorded = |track: &Track| {
+            track
+                .sequence
+                .notes()
+                .iter()
+                .map(|n| n.pitch)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(recorded(&b.tracks[0]), vec![Note::C3]);
+        assert_eq!(recorded(&b.tracks[1]), vec![Note::C3]);
+        assert_eq!(recorded(&b.tracks[2]), vec![]);
+    }
+
+    #[test]
+    fn track_input_is_processed_before_track() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.tracks[2].plugin = Toof::new(b.sample_rate).into();
+        b.tracks[2].volume = 0.0;
+        b.armed_track = 2;
+        assert_eq!(b.set_track_input(0, Some(2)), Ok(None));
+        assert_eq!(
+            b.set_track_input(2, Some(0)),
+            Err(RoutingError::Cycle {
+                track_id: 2,
+                input: 0
+            })
+        );
+        // Let the volume of track 2 ramp down so that only track 0 is heard.
+        b.process_to_buffer(64, &[]);
+        let out = b.process_to_buffer(
+            64,
+            &[(0, MidiMessage::NoteOn(Channel::Ch1, Note::C3, U7::MAX))],
+        );
+        assert!(!b.tracks[2].output.is_zero());
+        assert_eq!(b.tracks[0].output, b.tracks[2].output);
+        assert_eq!(out, b.tracks[0].output);
+    }
+
+    #[test]
+    fn control_surface_pads_arm_tracks_and_light_up() {
+        let mut b = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        b.control_surface = Some(Box::new(ControlSurface::new(
+            control_surface::SurfaceProfile::launchpad(),
+        )));
+        let pad = |pad, velocity| {
+            MidiMessage::NoteOn(
+                Channel::Ch1,
+                Note::from_u8_lossy(pad),
+                U7::from_u8_lossy(velocity),
+            )
+        };
+        b.handle_control_surface([pad(83, 127), pad(83, 0)].into_iter());
+        assert_eq!(b.armed_track, 2);
+        assert_eq!(
+            b.events.as_slice(),
+            &[BatsEvent::ControlSurface {
+                armed_track: 2,
+                recording_enabled: false,
+                playing: true,
+            }]
+        );
+        b.process_to_buffer(64, &[]);
+        let feedback = &b.control_surface.as_ref().unwrap().feedback;
+        assert!(feedback.contains(&(0, pad(83, 21))));
+        assert!(feedback.contains(&(0, pad(81, 1))));
+    }
 }
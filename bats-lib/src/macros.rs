@@ -0,0 +1,201 @@
+use bmidi::{ControlFunction, MidiMessage, U7};
+
+use crate::track::Track;
+
+/// Something that is set by a macro knob.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MacroTarget {
+    /// A plugin param of a track.
+    Param { track_id: usize, param_id: u32 },
+    /// The volume of a track.
+    TrackVolume { track_id: usize },
+}
+
+/// Maps the value of a macro knob onto a target.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MacroMapping {
+    /// The target to set.
+    pub target: MacroTarget,
+    /// The target value when the knob is at `0.0`.
+    pub min: f32,
+    /// The target value when the knob is at `1.0`.
+    pub max: f32,
+}
+
+/// A knob that sets many targets at once. The knob can be set from the UI or from a midi CC.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MacroKnob {
+    /// The value of the knob within `[0.0, 1.0]`.
+    pub value: f32,
+    /// The midi CC that sets the knob or `None` if the knob is not controlled by midi.
+    pub cc: Option<ControlFunction>,
+    /// The targets that are set by the knob.
+    pub mappings: Vec<MacroMapping>,
+    /// The value that was last applied to the targets or `None` if the targets should be set on
+    /// the next buffer.
+    pub applied: Option<f32>,
+}
+
+impl MacroMapping {
+    /// Create the mappings for a crossfader that fades from the tracks in `a` at `0.0` to the
+    /// tracks in `b` at `1.0`.
+    pub fn crossfader(a: &[usize], b: &[usize]) -> Vec<MacroMapping> {
+        let mapping = |track_id, min, max| MacroMapping {
+            target: MacroTarget::TrackVolume { track_id },
+            min,
+            max,
+        };
+        a.iter()
+            .map(|id| mapping(*id, 1.0, 0.0))
+            .chain(b.iter().map(|id| mapping(*id, 0.0, 1.0)))
+            .collect()
+    }
+
+    /// Get the target value for the knob `value`.
+    pub fn value(&self, value: f32) -> f32 {
+        self.min + (self.max - self.min) * value
+    }
+
+    /// Set the target within `tracks` for the knob `value`. Targets on tracks that do not exist
+    /// are ignored.
+    pub fn apply(&self, value: f32, tracks: &mut [Track]) {
+        let v = self.value(value);
+        match self.target {
+            MacroTarget::Param { track_id, param_id } => {
+                if let Some(t) = tracks.get_mut(track_id) {
+                    t.plugin.plugin_mut().set_param(param_id, v);
+                }
+            }
+            MacroTarget::TrackVolume { track_id } => {
+                if let Some(t) = tracks.get_mut(track_id) {
+                    t.volume = v;
+                }
+            }
+        }
+    }
+}
+
+impl MacroKnob {
+    /// Update the value from `midi` if it is the CC of the knob. Messages on all channels are
+    /// accepted.
+    pub fn handle_midi(&mut self, midi: &MidiMessage) {
+        match (self.cc, midi) {
+            (Some(cc), MidiMessage::ControlChange(_, msg_cc, v)) if cc == *msg_cc => {
+                self.value = u8::from(*v) as f32 / u8::from(U7::MAX) as f32;
+            }
+            _ => (),
+        }
+    }
+
+    /// Set the targets within `tracks` if the value has changed since it was last applied.
+    pub fn apply(&mut self, tracks: &mut [Track]) {
+        if self.applied == Some(self.value) {
+            return;
+        }
+        for mapping in self.mappings.iter() {
+            mapping.apply(self.value, tracks);
+        }
+        self.applied = Some(self.value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bats_dsp::sample_rate::SampleRate;
+    use bmidi::Channel;
+
+    use crate::{builder::AnyPlugin, plugin::toof::Toof};
+
+    use super::*;
+
+    fn tracks() -> Vec<Track> {
+        (0..3)
+            .map(|_| Track {
+                plugin: AnyPlugin::Toof(Toof::new(SampleRate::new(44100.0))),
+                ..Track::new(64)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn apply_sets_all_targets_scaled_to_range() {
+        let mut tracks = tracks();
+        let mut knob = MacroKnob {
+            value: 0.25,
+            mappings: vec![
+                MacroMapping {
+                    target: MacroTarget::Param {
+                        track_id: 0,
+                        param_id: 2,
+                    },
+                    min: 100.0,
+                    max: 500.0,
+                },
+                MacroMapping {
+                    target: MacroTarget::TrackVolume { track_id: 1 },
+                    min: 1.0,
+                    max: 0.0,
+                },
+                MacroMapping {
+                    target: MacroTarget::TrackVolume { track_id: 100 },
+                    min: 1.0,
+                    max: 0.0,
+                },
+            ],
+            ..MacroKnob::default()
+        };
+        knob.apply(&mut tracks);
+        assert_eq!(tracks[0].plugin.plugin().param(2), 200.0);
+        assert_eq!(tracks[1].volume, 0.75);
+        assert_eq!(knob.applied, Some(0.25));
+    }
+
+    #[test]
+    fn apply_does_nothing_if_value_is_unchanged() {
+        let mut tracks = tracks();
+        let mut knob = MacroKnob {
+            value: 0.5,
+            mappings: MacroMapping::crossfader(&[0], &[1]),
+            ..MacroKnob::default()
+        };
+        knob.apply(&mut tracks);
+        tracks[0].volume = 0.1;
+        knob.apply(&mut tracks);
+        assert_eq!(tracks[0].volume, 0.1);
+    }
+
+    #[test]
+    fn crossfader_fades_between_groups() {
+        let mut tracks = tracks();
+        let mut knob = MacroKnob {
+            mappings: MacroMapping::crossfader(&[0, 1], &[2]),
+            ..MacroKnob::default()
+        };
+        knob.apply(&mut tracks);
+        let volumes = |tracks: &[Track]| tracks.iter().map(|t| t.volume).collect::<Vec<_>>();
+        assert_eq!(volumes(&tracks), vec![1.0, 1.0, 0.0]);
+        knob.value = 1.0;
+        knob.apply(&mut tracks);
+        assert_eq!(volumes(&tracks), vec![0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn cc_sets_value() {
+        let mut knob = MacroKnob {
+            cc: Some(ControlFunction::MODULATION_WHEEL),
+            ..MacroKnob::default()
+        };
+        knob.handle_midi(&MidiMessage::ControlChange(
+            Channel::Ch3,
+            ControlFunction::MODULATION_WHEEL,
+            U7::MAX,
+        ));
+        assert_eq!(knob.value, 1.0);
+        knob.handle_midi(&MidiMessage::ControlChange(
+            Channel::Ch1,
+            ControlFunction::PAN,
+            U7::MIN,
+        ));
+        assert_eq!(knob.value, 1.0);
+    }
+}
@@ -0,0 +1,263 @@
+use bmidi::{MidiMessage, Note, U7};
+use serde::{Deserialize, Serialize};
+
+/// Filters and transforms the midi input of a track before it reaches the plugin.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MidiFilter {
+    /// If polyphonic key pressure and channel pressure should be dropped.
+    pub ignore_aftertouch: bool,
+    /// If control change messages should be dropped.
+    pub ignore_cc: bool,
+    /// The lowest note that is let through, before transposing.
+    pub low_note: Note,
+    /// The highest note that is let through, before transposing.
+    pub high_note: Note,
+    /// The number of semitones to transpose notes by. Notes that are transposed out of the midi
+    /// range are dropped.
+    pub transpose: i8,
+    /// If the track should also receive the midi that is sent to the armed track. Combined with
+    /// the note range, this splits the keyboard across tracks.
+    pub layer: bool,
+    /// The curve that is applied to the velocity of notes.
+    pub velocity_curve: VelocityCurve,
+    /// If set, all notes are played with this velocity and `velocity_curve` is ignored.
+    pub fixed_velocity: Option<U7>,
+}
+
+/// Changes how hard notes must be played to reach a velocity.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VelocityCurve {
+    /// The velocity is left unchanged.
+    #[default]
+    Linear,
+    /// Soft notes are boosted so less force is needed for loud notes.
+    Soft,
+    /// Soft notes are made quieter so more force is needed for loud notes.
+    Hard,
+}
+
+impl Default for MidiFilter {
+    fn default() -> MidiFilter {
+        MidiFilter {
+            ignore_aftertouch: false,
+            ignore_cc: false,
+            low_note: Note::LOWEST_NOTE,
+            high_note: Note::HIGHEST_NOTE,
+            transpose: 0,
+            layer: false,
+            velocity_curve: VelocityCurve::Linear,
+            fixed_velocity: None,
+        }
+    }
+}
+
+impl VelocityCurve {
+    /// All the velocity curves.
+    pub const ALL: &'static [VelocityCurve] = &[
+        VelocityCurve::Linear,
+        VelocityCurve::Soft,
+        VelocityCurve::Hard,
+    ];
+
+    /// The human readable name of the curve.
+    pub fn name(&self) -> &'static str {
+        match self {
+            VelocityCurve::Linear => "Linear",
+            VelocityCurve::Soft => "Soft",
+            VelocityCurve::Hard => "Hard",
+        }
+    }
+
+    /// Apply the curve to `velocity`. Velocities above `0` are kept above `0` so that a note on is
+    /// never turned into a note off.
+    pub fn apply(&self, velocity: U7) -> U7 {
+        let v = u8::from(velocity);
+        if v == 0 {
+            return velocity;
+        }
+        let x = v as f32 / 127.0;
+        let y = match self {
+            VelocityCurve::Linear => return velocity,
+            VelocityCurve::Soft => x.sqrt(),
+            VelocityCurve::Hard => x * x,
+        };
+        U7::from_u8_lossy((y * 127.0).round().clamp(1.0, 127.0) as u8)
+    }
+}
+
+impl MidiFilter {
+    /// The maximum number of semitones that notes can be transposed by in either direction.
+    pub const MAX_TRANSPOSE: i8 = 48;
+    /// The velocity that is used when the fixed velocity is first turned on.
+    pub const DEFAULT_FIXED_VELOCITY: U7 = U7::from_u8_lossy(100);
+
+    /// Returns true if the filter lets all midi through unchanged.
+    pub fn is_passthrough(&self) -> bool {
+        MidiFilter {
+            layer: false,
+            ..*self
+        } == MidiFilter::default()
+    }
+
+    /// Apply the filter to `msg`. Returns the transformed message or `None` if the message is
+    /// dropped.
+    pub fn apply(&self, msg: MidiMessage) -> Option<MidiMessage> {
+        if msg
+            .note()
+            .is_some_and(|note| !(self.low_note..=self.high_note).contains(&note))
+        {
+            return None;
+        }
+        match msg {
+            MidiMessage::PolyphonicKeyPressure(..) | MidiMessage::ChannelPressure(..)
+                if self.ignore_aftertouch =>
+            {
+                None
+            }
+            MidiMessage::ControlChange(..) if self.ignore_cc => None,
+            msg => msg
+                .transposed(self.transpose)
+                .map(|m| self.apply_velocity(m)),
+        }
+    }
+
+    /// Apply the fixed velocity or velocity curve to note on messages. Note ons with a velocity of
+    /// `0` are note offs and are left unchanged.
+    fn apply_velocity(&self, msg: MidiMessage) -> MidiMessage {
+        match msg {
+            MidiMessage::NoteOn(c, n, v) if u8::from(v) > 0 => {
+                let v = match self.fixed_velocity {
+                    Some(fixed) => fixed,
+                    None => self.velocity_curve.apply(v),
+                };
+                MidiMessage::NoteOn(c, n, v)
+            }
+            msg => msg,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bmidi::{Channel, ControlFunction};
+
+    use super::*;
+
+    #[test]
+    fn default_filter_lets_everything_through() {
+        let filter = MidiFilter::default();
+        assert!(filter.is_passthrough());
+        for msg in [
+            MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX),
+            MidiMessage::ChannelPressure(Channel::Ch1, U7::MAX),
+            MidiMessage::ControlChange(Channel::Ch1, ControlFunction::MODULATION_WHEEL, U7::MAX),
+        ] {
+            assert_eq!(filter.apply(msg), Some(msg));
+        }
+    }
+
+    #[test]
+    fn ignores_aftertouch_and_cc() {
+        let filter = MidiFilter {
+            ignore_aftertouch: true,
+            ignore_cc: true,
+            ..MidiFilter::default()
+        };
+        assert_eq!(
+            filter.apply(MidiMessage::ChannelPressure(Channel::Ch1, U7::MAX)),
+            None
+        );
+        assert_eq!(
+            filter.apply(MidiMessage::PolyphonicKeyPressure(
+                Channel::Ch1,
+                Note::C4,
+                U7::MAX
+            )),
+            None
+        );
+        assert_eq!(
+            filter.apply(MidiMessage::ControlChange(
+                Channel::Ch1,
+                ControlFunction::MODULATION_WHEEL,
+                U7::MAX
+            )),
+            None
+        );
+        let pitch_bend = MidiMessage::PitchBendChange(Channel::Ch1, bmidi::U14::MAX);
+        assert_eq!(filter.apply(pitch_bend), Some(pitch_bend));
+    }
+
+    #[test]
+    fn note_range_is_checked_before_transpose() {
+        let filter = MidiFilter {
+            low_note: Note::C4,
+            high_note: Note::B4,
+            transpose: 12,
+            ..MidiFilter::default()
+        };
+        assert_eq!(
+            filter.apply(MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX)),
+            Some(MidiMessage::NoteOn(Channel::Ch1, Note::C5, U7::MAX))
+        );
+        assert_eq!(
+            filter.apply(MidiMessage::NoteOff(Channel::Ch1, Note::B4, U7::MIN)),
+            Some(MidiMessage::NoteOff(Channel::Ch1, Note::B5, U7::MIN))
+        );
+        assert_eq!(
+            filter.apply(MidiMessage::NoteOn(Channel::Ch1, Note::C5, U7::MAX)),
+            None
+        );
+    }
+
+    #[test]
+    fn notes_transposed_out_of_range_are_dropped() {
+        let filter = MidiFilter {
+            transpose: -12,
+            ..MidiFilter::default()
+        };
+        assert_eq!(
+            filter.apply(MidiMessage::NoteOn(Channel::Ch1, Note::CMinus1, U7::MAX)),
+            None
+        );
+    }
+
+    #[test]
+    fn velocity_curves_keep_the_range_and_note_ons() {
+        for curve in VelocityCurve::ALL {
+            assert_eq!(curve.apply(U7::MIN), U7::MIN);
+            assert_eq!(curve.apply(U7::MAX), U7::MAX);
+            assert!(u8::from(curve.apply(U7::from_u8_lossy(1))) >= 1);
+        }
+        let mid = U7::from_u8_lossy(64);
+        assert_eq!(VelocityCurve::Linear.apply(mid), mid);
+        assert!(VelocityCurve::Soft.apply(mid) > mid);
+        assert!(VelocityCurve::Hard.apply(mid) < mid);
+    }
+
+    #[test]
+    fn fixed_velocity_replaces_note_on_velocity() {
+        let filter = MidiFilter {
+            velocity_curve: VelocityCurve::Hard,
+            fixed_velocity: Some(MidiFilter::DEFAULT_FIXED_VELOCITY),
+            ..MidiFilter::default()
+        };
+        assert!(!filter.is_passthrough());
+        assert_eq!(
+            filter.apply(MidiMessage::NoteOn(
+                Channel::Ch1,
+                Note::C4,
+                U7::from_u8_lossy(10)
+            )),
+            Some(MidiMessage::NoteOn(
+                Channel::Ch1,
+                Note::C4,
+                MidiFilter::DEFAULT_FIXED_VELOCITY
+            ))
+        );
+        let note_off = MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MIN);
+        assert_eq!(filter.apply(note_off), Some(note_off));
+        let note_off = MidiMessage::NoteOff(Channel::Ch1, Note::C4, U7::MAX);
+        assert_eq!(filter.apply(note_off), Some(note_off));
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+
+use bats_dsp::position::Position;
+use bmidi::MidiMessage;
+
+use crate::{
+    plugin::MidiEvent,
+    sequence::{Note, SequenceItem},
+    transport::Transport,
+};
+
+/// A midi message in the history.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HistoryEvent {
+    /// The number of beats that the transport had played when the message arrived. Unlike the
+    /// position, this does not wrap around the loop.
+    pub elapsed: Position,
+    /// The transport position of the message.
+    pub position: Position,
+    /// The message.
+    pub midi: MidiMessage,
+}
+
+/// Keeps the midi input of the last `bars` bars, even while recording is disabled, so that a take
+/// can be added to a sequence after it was played.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MidiHistory {
+    /// The number of bars of midi to keep.
+    pub bars: u32,
+    /// The messages in the order that they arrived.
+    events: VecDeque<HistoryEvent>,
+    /// The number of beats that the transport has played.
+    elapsed: Position,
+}
+
+impl MidiHistory {
+    /// The number of bars that are kept by default.
+    pub const DEFAULT_BARS: u32 = 8;
+
+    /// The maximum number of messages that are kept. The oldest messages are dropped first.
+    pub const CAPACITY: usize = 2048;
+
+    /// Create a new empty history.
+    pub fn new() -> MidiHistory {
+        MidiHistory {
+            bars: MidiHistory::DEFAULT_BARS,
+            events: VecDeque::with_capacity(MidiHistory::CAPACITY),
+            elapsed: Position::MIN,
+        }
+    }
+
+    /// Iterate over the messages in the order that they arrived.
+    pub fn events(&self) -> impl '_ + Iterator<Item = &HistoryEvent> {
+        self.events.iter()
+    }
+
+    /// Add the channel messages in `midi` for the current buffer of `transport` and drop the
+    /// messages that are older than `bars` bars. Each message is moved `latency` frames earlier,
+    /// like recorded midi.
+    ///
+    /// This does not allocate.
+    pub fn push_buffer(
+        &mut self,
+        midi: impl Iterator<Item = (u32, MidiMessage)>,
+        transport: &Transport,
+        latency: u32,
+    ) {
+        let (start, end) = match (
+            transport.iter_transport().next(),
+            transport.iter_transport().last(),
+        ) {
+            (Some(first), Some(last)) => (first.start, last.end),
+            _ => return,
+        };
+        for (frame, midi) in midi.filter(|(_, m)| m.channel().is_some()) {
+            let arrived = transport.range_for_frame(frame).start;
+            if self.events.len() == MidiHistory::CAPACITY {
+                self.events.pop_front();
+            }
+            self.events.push_back(HistoryEvent {
+                elapsed: self.elapsed + transport.elapsed(start, arrived),
+                position: transport.rewind(arrived, latency),
+                midi,
+            });
+        }
+        self.elapsed += transport.elapsed(start, end);
+        let window = Position::from_beats_bars(self.bars, 0.0);
+        while self
+            .events
+            .front()
+            .is_some_and(|e| e.elapsed + window < self.elapsed)
+        {
+            self.events.pop_front();
+        }
+    }
+
+    /// Iterate over the notes and messages in the history. Notes that are still held are
+    /// released at the current position.
+    pub fn items(&self) -> impl '_ + Iterator<Item = SequenceItem> {
+        self.events
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, event)| match event.midi {
+                MidiMessage::NoteOn(channel, pitch, velocity) => {
+                    // Pressing a note that is already held ends the previous press.
+                    let end = self
+                        .events
+                        .range(idx + 1..)
+                        .find(|e| match e.midi {
+                            MidiMessage::NoteOn(c, p, _) | MidiMessage::NoteOff(c, p, _) => {
+                                c == channel && p == pitch
+                            }
+                            _ => false,
+                        })
+                        .map_or(self.elapsed, |e| e.elapsed);
+                    Some(SequenceItem::Note(Note {
+                        start: event.position,
+                        length: end - event.elapsed,
+                        channel,
+                        pitch,
+                        velocity,
+                    }))
+                }
+                MidiMessage::NoteOff(..) => None,
+                midi => Some(SequenceItem::Message(MidiEvent {
+                    position: event.position,
+                    midi,
+                })),
+            })
+    }
+}
+
+impl Default for MidiHistory {
+    fn default() -> MidiHistory {
+        MidiHistory::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bats_dsp::{buffers::Buffers, sample_rate::SampleRate};
+    use bmidi::{Channel, U7};
+
+    use super::*;
+
+    const NOTE_ON: MidiMessage = MidiMessage::note_on(Channel::Ch1, bmidi::Note::C4, U7::MAX);
+    const NOTE_OFF: MidiMessage = MidiMessage::note_off(Channel::Ch1, bmidi::Note::C4, U7::MIN);
+
+    #[test]
+    fn notes_are_paired_across_buffers() {
+        let mut transport = Transport::new(SampleRate::new(4.0), 4, 60.0);
+        let mut buffers = Buffers::new(4);
+        let mut history = MidiHistory::new();
+        transport.process(&mut buffers.left, &mut buffers.right);
+        history.push_buffer([(2, NOTE_ON)].into_iter(), &transport, 0);
+        transport.process(&mut buffers.left, &mut buffers.right);
+        history.push_buffer([(1, NOTE_OFF)].into_iter(), &transport, 0);
+        assert_eq!(
+            history.items().collect::<Vec<_>>(),
+            vec![SequenceItem::Note(Note {
+                start: Position::new(0.5),
+                length: Position::new(0.75),
+                channel: Channel::Ch1,
+                pitch: bmidi::Note::C4,
+                velocity: U7::MAX,
+            })]
+        );
+    }
+
+    #[test]
+    fn messages_older_than_bars_are_dropped() {
+        let mut transport = Transport::new(SampleRate::new(4.0), 4, 60.0);
+        let mut buffers = Buffers::new(4);
+        let mut history = MidiHistory {
+            bars: 1,
+            ..MidiHistory::new()
+        };
+        transport.process(&mut buffers.left, &mut buffers.right);
+        history.push_buffer([(0, NOTE_ON), (1, NOTE_OFF)].into_iter(), &transport, 0);
+        for _ in 0..3 {
+            transport.process(&mut buffers.left, &mut buffers.right);
+            history.push_buffer(std::iter::empty(), &transport, 0);
+        }
+        assert_eq!(history.events().count(), 2);
+        transport.process(&mut buffers.left, &mut buffers.right);
+        history.push_buffer(std::iter::empty(), &transport, 0);
+        assert_eq!(history.events().count(), 0);
+    }
+}
@@ -0,0 +1,73 @@
+/// Bypasses the aux bus returns while the DSP load is too high so that the rest of the mix keeps
+/// playing without xruns. The returns usually hold the most expensive effects, such as delays, and
+/// are the least disruptive part of the mix to drop.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct OverloadProtection {
+    /// The DSP load percentage that engages protection or `None` if protection is disabled.
+    pub threshold: Option<f32>,
+    /// True while protection is engaged.
+    active: bool,
+}
+
+impl OverloadProtection {
+    /// Protection is released once the load falls below this ratio of the threshold. Keeps
+    /// protection from toggling every buffer when the load hovers around the threshold.
+    pub const RELEASE_RATIO: f32 = 0.75;
+
+    /// True while protection is engaged and the aux bus returns are bypassed.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Engage or release protection based on the current DSP `load`. Returns the new state if it
+    /// changed.
+    pub fn update(&mut self, load: Option<f32>) -> Option<bool> {
+        let active = match (self.threshold, load) {
+            (Some(threshold), Some(load)) if self.active => load >= threshold * Self::RELEASE_RATIO,
+            (Some(threshold), Some(load)) => load > threshold,
+            _ => false,
+        };
+        if active == self.active {
+            return None;
+        }
+        self.active = active;
+        Some(active)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_protection_never_engages() {
+        let mut protection = OverloadProtection::default();
+        assert_eq!(protection.update(Some(500.0)), None);
+        assert!(!protection.is_active());
+    }
+
+    #[test]
+    fn protection_engages_above_threshold_and_releases_below_release_ratio() {
+        let mut protection = OverloadProtection {
+            threshold: Some(80.0),
+            ..OverloadProtection::default()
+        };
+        assert_eq!(protection.update(Some(70.0)), None);
+        assert_eq!(protection.update(Some(90.0)), Some(true));
+        assert!(protection.is_active());
+        assert_eq!(protection.update(Some(70.0)), None);
+        assert_eq!(protection.update(Some(50.0)), Some(false));
+        assert!(!protection.is_active());
+    }
+
+    #[test]
+    fn disabling_protection_releases_it() {
+        let mut protection = OverloadProtection {
+            threshold: Some(80.0),
+            ..OverloadProtection::default()
+        };
+        protection.update(Some(90.0));
+        protection.threshold = None;
+        assert_eq!(protection.update(Some(90.0)), Some(false));
+    }
+}
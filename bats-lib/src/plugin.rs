@@ -1,16 +1,23 @@
 use anyhow::anyhow;
-use bats_dsp::{buffers::Buffers, position::Position};
+use bats_dsp::{buffers::Buffers, position::Position, sample_rate::SampleRate};
 use bmidi::MidiMessage;
 use serde::{Deserialize, Serialize};
 
 use self::metadata::Metadata;
+use crate::tuning::Tuning;
 
+pub mod compressor;
+pub mod delay;
 pub mod empty;
 pub mod metadata;
+pub mod sampler;
+pub mod soundfont;
 pub mod toof;
+#[cfg(all(feature = "vst3", target_os = "linux"))]
+pub mod vst3;
 
 /// Contains a midi event along with its `Position` timestamp.
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MidiEvent {
     /// The position of the midi event.
     pub position: Position,
@@ -18,6 +25,19 @@ pub struct MidiEvent {
     pub midi: MidiMessage,
 }
 
+/// The state of the transport at the start of a buffer. Plugins use it to sync to the tempo.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TransportInfo {
+    /// The position at the first frame of the buffer.
+    pub position: Position,
+    /// The beats per minute.
+    pub bpm: f32,
+    /// The number of frames in a single beat.
+    pub samples_per_beat: f32,
+    /// True if the transport is playing. The position does not advance while stopped.
+    pub playing: bool,
+}
+
 /// Defines a generic instrument plugin.
 pub trait BatsInstrument {
     /// The name of the plugin.
@@ -38,24 +58,75 @@ pub trait BatsInstrument {
     /// Run any batch cleanup operations.
     fn batch_cleanup(&mut self);
 
-    /// Handle processing of `midi_in` and output to `left_out` and
-    /// `right_out`.
+    /// Update any internal state that depends on the sample rate.
+    fn set_sample_rate(&mut self, sample_rate: SampleRate);
+
+    /// Reset the random number generator of the plugin to `seed`. Plugins that use randomness
+    /// must produce the same output for the same seed and input. Does nothing by default.
+    fn set_seed(&mut self, _seed: u64) {}
+
+    /// Update the transport info. Called once before every buffer is processed. Does nothing by
+    /// default.
+    fn set_transport_info(&mut self, _info: &TransportInfo) {}
+
+    /// Update the tuning that maps notes to frequencies. Called when the plugin is placed on a
+    /// track and when the tuning of the track changes. Plugins that do not play pitched notes can
+    /// ignore it. Does nothing by default.
+    fn set_tuning(&mut self, _tuning: &Tuning) {}
+
+    /// Fill `out` while handling each message in `midi` at its frame. `midi` is sorted by frame.
     ///
-    /// Prefer using this default behavior unless benchmarking shows significant performance
-    /// improvements.
-    fn process_batch(&mut self, midi_in: &[(u32, MidiMessage)], output: &mut Buffers) {
-        let sample_count = output.len();
-        let mut midi_iter = midi_in.iter().peekable();
+    /// The default calls `handle_midi` and `process` for every frame. Plugins may override this
+    /// to process the frames between midi messages as a single block, for example to vectorize
+    /// their internal loops.
+    fn process_block(&mut self, midi: &[(u32, MidiMessage)], out: &mut Buffers) {
+        let sample_count = out.len();
+        let mut midi_iter = midi.iter().peekable();
         for i in 0..sample_count {
             while let Some((_, msg)) = midi_iter.next_if(|(frame, _)| *frame <= i as u32) {
                 self.handle_midi(msg);
             }
-            output.set(i, self.process())
+            out.set(i, self.process())
         }
+    }
+
+    /// Handle processing of `midi_in` and output to `output` with `process_block` and then run
+    /// `batch_cleanup`.
+    fn process_batch(&mut self, midi_in: &[(u32, MidiMessage)], output: &mut Buffers) {
+        self.process_block(midi_in, output);
         self.batch_cleanup();
     }
 }
 
+/// Defines a generic effect plugin that processes audio.
+pub trait BatsEffect {
+    /// The name of the plugin.
+    fn metadata(&self) -> &'static Metadata;
+
+    /// Process a single stereo frame.
+    fn process(&mut self, input: (f32, f32)) -> (f32, f32);
+
+    /// Get the value of the parameter.
+    fn param(&self, id: u32) -> f32;
+
+    /// Set a parameter.
+    fn set_param(&mut self, id: u32, value: f32);
+
+    /// Update any internal state that depends on the sample rate.
+    fn set_sample_rate(&mut self, sample_rate: SampleRate);
+
+    /// Update the transport info. Called once before every buffer is processed. Does nothing by
+    /// default.
+    fn set_transport_info(&mut self, _info: &TransportInfo) {}
+
+    /// Process all the frames in `buffers` in place.
+    fn process_batch(&mut self, buffers: &mut Buffers) {
+        for (left, right) in buffers.left.iter_mut().zip(buffers.right.iter_mut()) {
+            (*left, *right) = self.process((*left, *right));
+        }
+    }
+}
+
 pub trait BatsInstrumentExt: BatsInstrument {
     /// Handle processing of `midi_in` and return the results. This is
     /// often less efficient but is included for less performance
@@ -92,8 +163,6 @@ impl<T: BatsInstrument> BatsInstrumentExt for T {}
 
 #[cfg(test)]
 mod tests {
-    use bats_dsp::sample_rate::SampleRate;
-
     use super::{toof::Toof, *};
 
     #[test]
@@ -107,6 +176,54 @@ mod tests {
         assert_eq!(manual, by_name);
     }
 
+    /// Counts the calls to the block processing entry points.
+    #[derive(Default)]
+    struct BlockCounter {
+        blocks: Vec<(usize, usize)>,
+        cleanups: usize,
+    }
+
+    impl BatsInstrument for BlockCounter {
+        fn metadata(&self) -> &'static Metadata {
+            empty::Empty.metadata()
+        }
+
+        fn handle_midi(&mut self, _: &MidiMessage) {}
+
+        fn process(&mut self) -> (f32, f32) {
+            unreachable!("process_block is overridden")
+        }
+
+        fn param(&self, _: u32) -> f32 {
+            0.0
+        }
+
+        fn set_param(&mut self, _: u32, _: f32) {}
+
+        fn batch_cleanup(&mut self) {
+            self.cleanups += 1;
+        }
+
+        fn set_sample_rate(&mut self, _: SampleRate) {}
+
+        fn process_block(&mut self, midi: &[(u32, MidiMessage)], out: &mut Buffers) {
+            self.blocks.push((midi.len(), out.len()));
+            out.left.fill(1.0);
+            out.right.fill(-1.0);
+        }
+    }
+
+    #[test]
+    fn process_batch_uses_process_block_then_cleans_up() {
+        let mut plugin = BlockCounter::default();
+        let note_on = MidiMessage::NoteOn(bmidi::Channel::Ch1, bmidi::Note::C4, bmidi::U7::MAX);
+        let out = plugin.process_to_buffers(8, &[(0, note_on), (4, note_on)]);
+        assert_eq!(plugin.blocks, vec![(2, 8)]);
+        assert_eq!(plugin.cleanups, 1);
+        assert_eq!(out.left, vec![1.0; 8]);
+        assert_eq!(out.right, vec![-1.0; 8]);
+    }
+
     #[test]
     fn set_param_by_name_with_bad_name_returns_error() {
         let mut plugin = Toof::new(SampleRate::new(44100.0));
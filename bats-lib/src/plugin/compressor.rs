@@ -0,0 +1,204 @@
+use bats_dsp::{envelope_follower::EnvelopeFollower, sample_rate::SampleRate};
+
+use super::{
+    metadata::{Param, ParamType, PluginCategory},
+    BatsEffect, Metadata,
+};
+
+/// A stereo linked compressor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compressor {
+    /// The sample rate.
+    sample_rate: SampleRate,
+    /// The amplitude above which the signal is compressed.
+    threshold: f32,
+    /// The amount of compression. A ratio of `4.0` means that every 4 dB over the threshold is
+    /// reduced to 1 dB.
+    ratio: f32,
+    /// The gain applied after compression.
+    makeup_gain: f32,
+    /// Follows the level of the input signal.
+    follower: EnvelopeFollower,
+}
+
+impl Compressor {
+    /// The metadata for the plugin.
+    pub const METADATA: Metadata = Metadata {
+        name: "compressor",
+        category: PluginCategory::Effect,
+        tags: &["dynamics"],
+        params: &[
+            Param {
+                id: 1,
+                name: "threshold",
+                param_type: ParamType::Decibel,
+                default_value: 0.5,
+                min_value: 0.001,
+                max_value: 1.0,
+            },
+            Param {
+                id: 2,
+                name: "ratio",
+                param_type: ParamType::Float,
+                default_value: 4.0,
+                min_value: 1.0,
+                max_value: 20.0,
+            },
+            Param {
+                id: 3,
+                name: "attack",
+                param_type: ParamType::Duration,
+                default_value: 0.01,
+                min_value: 0.0001,
+                max_value: 1.0,
+            },
+            Param {
+                id: 4,
+                name: "release",
+                param_type: ParamType::Duration,
+                default_value: 0.1,
+                min_value: 0.001,
+                max_value: 2.0,
+            },
+            Param {
+                id: 5,
+                name: "makeup gain",
+                param_type: ParamType::Decibel,
+                default_value: 1.0,
+                min_value: 1.0,
+                max_value: 4.0,
+            },
+        ],
+        pages: &[],
+    };
+
+    /// Create a new compressor with the given sample rate.
+    pub fn new(sample_rate: SampleRate) -> Box<Compressor> {
+        Box::new(Compressor {
+            sample_rate,
+            threshold: 0.5,
+            ratio: 4.0,
+            makeup_gain: 1.0,
+            follower: EnvelopeFollower::new(sample_rate, 0.01, 0.1),
+        })
+    }
+
+    /// The gain to apply for the current envelope value, not including the makeup gain.
+    fn gain(&self) -> f32 {
+        let level = self.follower.value();
+        if level <= self.threshold {
+            1.0
+        } else {
+            (level / self.threshold).powf(1.0 / self.ratio - 1.0)
+        }
+    }
+}
+
+impl BatsEffect for Compressor {
+    /// The name of the plugin.
+    fn metadata(&self) -> &'static Metadata {
+        &Compressor::METADATA
+    }
+
+    /// Compress a single frame.
+    fn process(&mut self, (left, right): (f32, f32)) -> (f32, f32) {
+        self.follower.process(left.abs().max(right.abs()));
+        let gain = self.gain() * self.makeup_gain;
+        (left * gain, right * gain)
+    }
+
+    /// Get the value of a parameter.
+    fn param(&self, id: u32) -> f32 {
+        match id {
+            1 => self.threshold,
+            2 => self.ratio,
+            3 => self.follower.attack(),
+            4 => self.follower.release(),
+            5 => self.makeup_gain,
+            _ => 0.0,
+        }
+    }
+
+    /// Set a parameter.
+    fn set_param(&mut self, id: u32, value: f32) {
+        match id {
+            1 => self.threshold = value,
+            2 => self.ratio = value,
+            3 => self.follower.set_attack(self.sample_rate, value),
+            4 => self.follower.set_release(self.sample_rate, value),
+            5 => self.makeup_gain = value,
+            _ => (),
+        }
+    }
+
+    /// Recompute the envelope follower for the new sample rate.
+    fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+        self.follower
+            .set_attack(sample_rate, self.follower.attack());
+        self.follower
+            .set_release(sample_rate, self.follower.release());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bats_dsp::buffers::Buffers;
+
+    use super::*;
+
+    fn constant_buffers(amp: f32, frames: usize) -> Buffers {
+        Buffers {
+            left: vec![amp; frames],
+            right: vec![-amp; frames],
+        }
+    }
+
+    #[test]
+    fn signal_below_threshold_only_gets_makeup_gain() {
+        let mut c = Compressor::new(SampleRate::new(44100.0));
+        c.set_param(1, 0.5);
+        c.set_param(5, 2.0);
+        let mut buffers = constant_buffers(0.25, 1024);
+        c.process_batch(&mut buffers);
+        assert_eq!(buffers, constant_buffers(0.5, 1024));
+    }
+
+    #[test]
+    fn signal_above_threshold_is_compressed_by_ratio() {
+        let mut c = Compressor::new(SampleRate::new(44100.0));
+        c.set_param(1, 0.25);
+        c.set_param(2, 2.0);
+        c.set_param(3, 0.0001);
+        let mut buffers = constant_buffers(1.0, 44100);
+        c.process_batch(&mut buffers);
+        // 12 dB over the threshold with a 2:1 ratio should come out 6 dB over the threshold.
+        let (left, right) = buffers.get(44099);
+        assert!((left - 0.5).abs() < 1e-3, "{left}");
+        assert!((right + 0.5).abs() < 1e-3, "{right}");
+    }
+
+    #[test]
+    fn set_params_can_set_to_min_and_max() {
+        let params = Compressor::new(SampleRate::new(44100.0)).metadata().params;
+        for param in params {
+            let mut c = Compressor::new(SampleRate::new(44100.0));
+            assert_eq!(c.param(param.id), param.default_value, "{param:?}");
+            c.set_param(param.id, param.min_value);
+            assert_eq!(c.param(param.id), param.min_value, "{param:?}");
+            c.set_param(param.id, param.max_value);
+            assert_eq!(c.param(param.id), param.max_value, "{param:?}");
+        }
+    }
+
+    #[test]
+    fn set_sample_rate_keeps_params() {
+        let mut c = Compressor::new(SampleRate::new(44100.0));
+        c.set_param(3, 0.2);
+        c.set_sample_rate(SampleRate::new(22050.0));
+        assert_eq!(c.param(3), 0.2);
+        let mut reference = Compressor::new(SampleRate::new(22050.0));
+        reference.set_param(3, 0.2);
+        assert_eq!(c, reference);
+    }
+}
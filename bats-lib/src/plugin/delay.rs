@@ -0,0 +1,282 @@
+use bats_dsp::{buffers::Buffers, sample_rate::SampleRate};
+
+use super::{
+    metadata::{Param, ParamType, PluginCategory},
+    BatsEffect, Metadata, TransportInfo,
+};
+
+/// A stereo feedback delay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delay {
+    /// The sample rate.
+    sample_rate: SampleRate,
+    /// The delay time in seconds. Used when the delay is not synced to the tempo.
+    time: f32,
+    /// If the delay time follows the tempo of the transport.
+    sync: bool,
+    /// The delay time in beats when synced to the tempo.
+    beats: f32,
+    /// The number of frames in a beat according to the transport, or `0.0` if the transport info
+    /// has not been set yet.
+    samples_per_beat: f32,
+    /// The amount of the delayed signal that is fed back into the delay.
+    feedback: f32,
+    /// The amount of the delayed signal in the output. The dry signal is mixed in with
+    /// `1.0 - mix`.
+    mix: f32,
+    /// The delay time in frames.
+    delay_frames: usize,
+    /// The delay line. Holds `MAX_TIME` seconds of audio.
+    line: Buffers,
+    /// The index in `line` that is written next.
+    write_idx: usize,
+}
+
+impl Delay {
+    /// The maximum delay time in seconds.
+    pub const MAX_TIME: f32 = 2.0;
+
+    /// The metadata for the plugin.
+    pub const METADATA: Metadata = Metadata {
+        name: "delay",
+        category: PluginCategory::Effect,
+        tags: &["echo", "time"],
+        params: &[
+            Param {
+                id: 1,
+                name: "time",
+                param_type: ParamType::Duration,
+                default_value: 0.375,
+                min_value: 0.001,
+                max_value: Delay::MAX_TIME,
+            },
+            Param {
+                id: 2,
+                name: "feedback",
+                param_type: ParamType::Percent,
+                default_value: 0.4,
+                min_value: 0.0,
+                max_value: 0.95,
+            },
+            Param {
+                id: 3,
+                name: "mix",
+                param_type: ParamType::Percent,
+                default_value: 1.0,
+                min_value: 0.0,
+                max_value: 1.0,
+            },
+            Param {
+                id: 4,
+                name: "sync",
+                param_type: ParamType::Bool,
+                default_value: 0.49,
+                min_value: 0.49,
+                max_value: 0.51,
+            },
+            Param {
+                id: 5,
+                name: "beats",
+                param_type: ParamType::Float,
+                default_value: 0.75,
+                min_value: 0.0625,
+                max_value: 4.0,
+            },
+        ],
+        pages: &[],
+    };
+
+    /// Create a new delay with the given sample rate. This allocates the delay line so it should
+    /// not be called from the audio thread.
+    pub fn new(sample_rate: SampleRate) -> Box<Delay> {
+        let mut delay = Box::new(Delay {
+            sample_rate,
+            time: 0.375,
+            sync: false,
+            beats: 0.75,
+            samples_per_beat: 0.0,
+            feedback: 0.4,
+            mix: 1.0,
+            delay_frames: 0,
+            line: Buffers::new(Delay::line_len(sample_rate)),
+            write_idx: 0,
+        });
+        delay.update_delay_frames();
+        delay
+    }
+
+    /// The length of the delay line that holds `MAX_TIME` seconds at `sample_rate`.
+    fn line_len(sample_rate: SampleRate) -> usize {
+        (Delay::MAX_TIME * sample_rate.sample_rate()).ceil() as usize + 1
+    }
+
+    /// Recompute the delay time in frames. The delay is limited to the length of the delay line.
+    /// The time param is used when synced to the tempo but the tempo is not known yet.
+    fn update_delay_frames(&mut self) {
+        let frames = if self.sync && self.samples_per_beat > 0.0 {
+            self.beats * self.samples_per_beat
+        } else {
+            self.time * self.sample_rate.sample_rate()
+        };
+        let frames = frames.round() as usize;
+        self.delay_frames = frames.clamp(1, self.line.len() - 1);
+    }
+}
+
+impl BatsEffect for Delay {
+    fn metadata(&self) -> &'static Metadata {
+        &Delay::METADATA
+    }
+
+    /// Delay a single frame.
+    fn process(&mut self, (left, right): (f32, f32)) -> (f32, f32) {
+        let len = self.line.len();
+        let read_idx = (self.write_idx + len - self.delay_frames) % len;
+        let (delayed_left, delayed_right) = self.line.get(read_idx);
+        self.line.set(
+            self.write_idx,
+            (
+                left + delayed_left * self.feedback,
+                right + delayed_right * self.feedback,
+            ),
+        );
+        self.write_idx = (self.write_idx + 1) % len;
+        let dry = 1.0 - self.mix;
+        (
+            left * dry + delayed_left * self.mix,
+            right * dry + delayed_right * self.mix,
+        )
+    }
+
+    fn param(&self, id: u32) -> f32 {
+        match id {
+            1 => self.time,
+            2 => self.feedback,
+            3 => self.mix,
+            4 => {
+                if self.sync {
+                    0.51
+                } else {
+                    0.49
+                }
+            }
+            5 => self.beats,
+            _ => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, id: u32, value: f32) {
+        match id {
+            1 => {
+                self.time = value;
+                self.update_delay_frames();
+            }
+            2 => self.feedback = value,
+            3 => self.mix = value,
+            4 => {
+                self.sync = value >= 0.5;
+                self.update_delay_frames();
+            }
+            5 => {
+                self.beats = value;
+                self.update_delay_frames();
+            }
+            _ => (),
+        }
+    }
+
+    /// Recompute the delay time if it is synced to the tempo and the tempo changed.
+    fn set_transport_info(&mut self, info: &TransportInfo) {
+        if self.samples_per_beat != info.samples_per_beat {
+            self.samples_per_beat = info.samples_per_beat;
+            if self.sync {
+                self.update_delay_frames();
+            }
+        }
+    }
+
+    /// Recompute the delay time for the new sample rate. The delay line is not reallocated so
+    /// the delay time may be limited if the sample rate increases.
+    fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+        self.update_delay_frames();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn impulse(frames: usize) -> Buffers {
+        let mut buffers = Buffers::new(frames);
+        buffers.set(0, (1.0, -1.0));
+        buffers
+    }
+
+    #[test]
+    fn impulse_is_repeated_with_feedback() {
+        let mut d = Delay::new(SampleRate::new(1000.0));
+        d.set_param(1, 0.01);
+        d.set_param(2, 0.5);
+        let mut buffers = impulse(40);
+        d.process_batch(&mut buffers);
+        assert_eq!(buffers.get(0), (0.0, 0.0));
+        assert_eq!(buffers.get(10), (1.0, -1.0));
+        assert_eq!(buffers.get(20), (0.5, -0.5));
+        assert_eq!(buffers.get(30), (0.25, -0.25));
+        assert_eq!(buffers.get(15), (0.0, 0.0));
+    }
+
+    #[test]
+    fn mix_blends_dry_and_delayed_signal() {
+        let mut d = Delay::new(SampleRate::new(1000.0));
+        d.set_param(1, 0.005);
+        d.set_param(3, 0.25);
+        let mut buffers = impulse(10);
+        d.process_batch(&mut buffers);
+        assert_eq!(buffers.get(0), (0.75, -0.75));
+        assert_eq!(buffers.get(5), (0.25, -0.25));
+    }
+
+    #[test]
+    fn set_params_can_set_to_min_and_max() {
+        for param in Delay::METADATA.params {
+            let mut d = Delay::new(SampleRate::new(44100.0));
+            assert_eq!(d.param(param.id), param.default_value, "{param:?}");
+            d.set_param(param.id, param.min_value);
+            assert_eq!(d.param(param.id), param.min_value, "{param:?}");
+            d.set_param(param.id, param.max_value);
+            assert_eq!(d.param(param.id), param.max_value, "{param:?}");
+        }
+    }
+
+    #[test]
+    fn synced_delay_follows_the_tempo() {
+        let mut d = Delay::new(SampleRate::new(1000.0));
+        d.set_param(1, 0.01);
+        d.set_param(4, 0.51);
+        d.set_param(5, 0.5);
+        assert_eq!(d.delay_frames, 10);
+        let mut info = TransportInfo {
+            position: Default::default(),
+            bpm: 120.0,
+            samples_per_beat: 500.0,
+            playing: true,
+        };
+        d.set_transport_info(&info);
+        assert_eq!(d.delay_frames, 250);
+        info.samples_per_beat = 1000.0;
+        d.set_transport_info(&info);
+        assert_eq!(d.delay_frames, 500);
+        d.set_param(4, 0.49);
+        assert_eq!(d.delay_frames, 10);
+    }
+
+    #[test]
+    fn delay_is_limited_to_delay_line() {
+        let mut d = Delay::new(SampleRate::new(1000.0));
+        d.set_param(1, Delay::MAX_TIME);
+        d.set_sample_rate(SampleRate::new(4000.0));
+        assert_eq!(d.delay_frames, d.line.len() - 1);
+    }
+}
@@ -1,7 +1,10 @@
+use bats_dsp::{buffers::Buffers, sample_rate::SampleRate};
 use bmidi::MidiMessage;
 
-
-use super::{metadata::Metadata, BatsInstrument};
+use super::{
+    metadata::{Metadata, PluginCategory},
+    BatsInstrument,
+};
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
 pub struct Empty;
@@ -10,7 +13,10 @@ impl BatsInstrument for Empty {
     fn metadata(&self) -> &'static Metadata {
         &Metadata {
             name: "empty",
+            category: PluginCategory::Utility,
+            tags: &[],
             params: &[],
+            pages: &[],
         }
     }
 
@@ -27,4 +33,11 @@ impl BatsInstrument for Empty {
     fn set_param(&mut self, _: u32, _: f32) {}
 
     fn batch_cleanup(&mut self) {}
+
+    fn set_sample_rate(&mut self, _: SampleRate) {}
+
+    fn process_block(&mut self, _: &[(u32, MidiMessage)], out: &mut Buffers) {
+        out.left.fill(0.0);
+        out.right.fill(0.0);
+    }
 }
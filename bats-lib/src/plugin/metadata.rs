@@ -1,12 +1,42 @@
 use std::fmt::{Display, Formatter};
 
+use bats_dsp::rng::Rng;
+
 /// Metadata for a plugin.
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
 pub struct Metadata {
     /// The name.
     pub name: &'static str,
+    /// The category used to group the plugin in the plugin browser.
+    pub category: PluginCategory,
+    /// Extra keywords that describe the plugin, such as `"synth"`.
+    pub tags: &'static [&'static str],
     /// The parameters.
     pub params: &'static [Param],
+    /// The pages that the params are grouped into for editing. If empty, then all params are on a
+    /// single page.
+    pub pages: &'static [ParamPage],
+}
+
+/// A group of params that are edited together.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ParamPage {
+    /// The name of the page.
+    pub name: &'static str,
+    /// The ids of the params on the page.
+    pub param_ids: &'static [u32],
+}
+
+/// The kind of plugin.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PluginCategory {
+    /// A plugin that produces sound from midi.
+    #[default]
+    Instrument,
+    /// A plugin that processes audio.
+    Effect,
+    /// A plugin that does not fit in the other categories.
+    Utility,
 }
 
 /// The type for the parameter.
@@ -54,6 +84,91 @@ impl Metadata {
     pub fn param_by_id(&self, id: u32) -> Option<&Param> {
         self.params.iter().find(|p| p.id == id)
     }
+
+    /// The number of param pages. Plugins without pages have a single page with all the params.
+    pub fn page_count(&self) -> usize {
+        self.pages.len().max(1)
+    }
+
+    /// Get the name of the page at index `page` or `None` if the plugin has no pages.
+    pub fn page_name(&self, page: usize) -> Option<&'static str> {
+        self.pages.get(page).map(|p| p.name)
+    }
+
+    /// Iterate over the params on the page at index `page`. Params are in the same order as in
+    /// `params`.
+    pub fn page_params(&self, page: usize) -> impl Iterator<Item = &'static Param> {
+        let ids = self.pages.get(page).map(|p| p.param_ids);
+        let is_single_page = self.pages.is_empty() && page == 0;
+        self.params.iter().filter(move |p| match ids {
+            Some(ids) => ids.contains(&p.id),
+            None => is_single_page,
+        })
+    }
+}
+
+impl PluginCategory {
+    /// All the categories in the order they should be displayed.
+    pub const ALL: &'static [PluginCategory] = &[
+        PluginCategory::Instrument,
+        PluginCategory::Effect,
+        PluginCategory::Utility,
+    ];
+
+    /// The human readable name of the category.
+    pub fn name(self) -> &'static str {
+        match self {
+            PluginCategory::Instrument => "Instrument",
+            PluginCategory::Effect => "Effect",
+            PluginCategory::Utility => "Utility",
+        }
+    }
+}
+
+impl Param {
+    /// Get the position of `value` between the min and max value as a number between `0.0` and
+    /// `1.0`. Frequencies and decibels use a logarithmic scale to match how they are heard.
+    pub fn normalized(&self, value: f32) -> f32 {
+        let (value, min, max) = if self.is_log() {
+            (
+                value.max(f32::MIN_POSITIVE).ln(),
+                self.min_value.ln(),
+                self.max_value.ln(),
+            )
+        } else {
+            (value, self.min_value, self.max_value)
+        };
+        if max <= min {
+            return 0.0;
+        }
+        ((value - min) / (max - min)).clamp(0.0, 1.0)
+    }
+
+    /// Get the value at `normalized` between the min and max value. This is the inverse of
+    /// `normalized`.
+    pub fn denormalized(&self, normalized: f32) -> f32 {
+        let normalized = normalized.clamp(0.0, 1.0);
+        if self.is_log() {
+            let (min, max) = (self.min_value.ln(), self.max_value.ln());
+            (min + (max - min) * normalized).exp()
+        } else {
+            self.min_value + (self.max_value - self.min_value) * normalized
+        }
+    }
+
+    /// Get a random value between the min and max value. Values are spread evenly on the same
+    /// scale as `normalized` and bools are either on or off.
+    pub fn random_value(&self, rng: &mut Rng) -> f32 {
+        match self.param_type {
+            ParamType::Bool => (rng.next_f32() < 0.5) as u8 as f32,
+            _ => self.denormalized(rng.next_f32()),
+        }
+    }
+
+    /// Returns true if the param is on a logarithmic scale.
+    fn is_log(&self) -> bool {
+        matches!(self.param_type, ParamType::Frequency | ParamType::Decibel) && self.min_value > 0.0
+    }
 }
 
 impl ParamType {
@@ -64,6 +179,35 @@ impl ParamType {
             value,
         }
     }
+
+    /// Parse a human readable value such as `"438 Hz"` or `"-6 dB"`. Units are optional and case
+    /// insensitive. Returns `None` if `text` is not a valid value for the param type.
+    pub fn parse(&self, text: &str) -> Option<f32> {
+        let text = text.trim().to_lowercase();
+        if *self == ParamType::Bool {
+            match text.as_str() {
+                "on" | "true" => return Some(1.0),
+                "off" | "false" => return Some(0.0),
+                _ => (),
+            }
+        }
+        let number = text.trim_end_matches(|c: char| c.is_alphabetic() || c == '%');
+        let unit = text[number.len()..].trim();
+        let value: f32 = number.trim().parse().ok()?;
+        if !value.is_finite() {
+            return None;
+        }
+        match (self, unit) {
+            (ParamType::Float | ParamType::Bool, "") => Some(value),
+            (ParamType::Decibel, "" | "db") => Some(10f32.powf(value / 20.0)),
+            (ParamType::Percent, "" | "%") => Some(value / 100.0),
+            (ParamType::Frequency, "" | "hz") => Some(value),
+            (ParamType::Frequency, "khz") => Some(value * 1000.0),
+            (ParamType::Duration, "" | "s") => Some(value),
+            (ParamType::Duration, "ms") => Some(value / 1000.0),
+            _ => None,
+        }
+    }
 }
 
 /// A formatter for params.
@@ -112,6 +256,8 @@ mod tests {
 
     const TEST_METADATA: Metadata = Metadata {
         name: "test_metadata",
+        category: PluginCategory::Utility,
+        tags: &[],
         params: &[
             Param {
                 id: 10,
@@ -138,6 +284,7 @@ mod tests {
                 max_value: 30.0,
             },
         ],
+        pages: &[],
     };
 
     #[test]
@@ -180,6 +327,104 @@ mod tests {
         assert_eq!(TEST_METADATA.param_by_id(0), None);
     }
 
+    #[test]
+    fn metadata_without_pages_has_all_params_on_single_page() {
+        assert_eq!(TEST_METADATA.page_count(), 1);
+        assert_eq!(TEST_METADATA.page_name(0), None);
+        assert_eq!(TEST_METADATA.page_params(0).count(), 3);
+        assert_eq!(TEST_METADATA.page_params(1).count(), 0);
+    }
+
+    #[test]
+    fn page_params_returns_params_on_page() {
+        let metadata = Metadata {
+            pages: &[
+                ParamPage {
+                    name: "first",
+                    param_ids: &[20],
+                },
+                ParamPage {
+                    name: "second",
+                    param_ids: &[10],
+                },
+            ],
+            ..TEST_METADATA
+        };
+        assert_eq!(metadata.page_count(), 2);
+        assert_eq!(metadata.page_name(1), Some("second"));
+        assert_eq!(
+            metadata.page_params(0).map(|p| p.name).collect::<Vec<_>>(),
+            vec!["param 20", "duplicate param 20"]
+        );
+        assert_eq!(
+            metadata.page_params(1).map(|p| p.name).collect::<Vec<_>>(),
+            vec!["param 10"]
+        );
+        assert_eq!(metadata.page_params(2).count(), 0);
+    }
+
+    #[test]
+    fn normalized_is_linear_for_percent_and_log_for_frequency() {
+        let percent = Param {
+            param_type: ParamType::Percent,
+            min_value: 0.0,
+            max_value: 1.0,
+            ..Param::default()
+        };
+        assert_eq!(percent.normalized(0.25), 0.25);
+        assert_eq!(percent.normalized(2.0), 1.0);
+        let frequency = Param {
+            param_type: ParamType::Frequency,
+            min_value: 10.0,
+            max_value: 1000.0,
+            ..Param::default()
+        };
+        assert!((frequency.normalized(100.0) - 0.5).abs() < 1e-6);
+        assert_eq!(frequency.normalized(0.0), 0.0);
+    }
+
+    #[test]
+    fn denormalized_is_inverse_of_normalized() {
+        let frequency = Param {
+            param_type: ParamType::Frequency,
+            min_value: 10.0,
+            max_value: 1000.0,
+            ..Param::default()
+        };
+        assert!((frequency.denormalized(0.5) - 100.0).abs() < 1e-3);
+        assert_eq!(frequency.denormalized(2.0), frequency.denormalized(1.0));
+        let percent = Param {
+            param_type: ParamType::Percent,
+            min_value: 0.0,
+            max_value: 1.0,
+            ..Param::default()
+        };
+        assert_eq!(percent.normalized(percent.denormalized(0.25)), 0.25);
+    }
+
+    #[test]
+    fn random_value_is_within_range() {
+        let mut rng = Rng::default();
+        let frequency = Param {
+            param_type: ParamType::Frequency,
+            min_value: 10.0,
+            max_value: 1000.0,
+            ..Param::default()
+        };
+        let toggle = Param {
+            param_type: ParamType::Bool,
+            min_value: 0.0,
+            max_value: 1.0,
+            ..Param::default()
+        };
+        for _ in 0..100 {
+            let v = frequency.random_value(&mut rng);
+            assert!((10.0..=1000.0).contains(&v), "{v}");
+            let v = toggle.random_value(&mut rng);
+            assert!(v == 0.0 || v == 1.0, "{v}");
+        }
+    }
+
     #[test]
     fn format_float() {
         assert_eq!(ParamType::Float.formatted(0.1).to_string(), "0.1");
@@ -199,6 +444,27 @@ mod tests {
         assert_eq!(ParamType::Decibel.formatted(0.0125).to_string(), "-38.1 dB");
     }
 
+    #[test]
+    fn parse_with_units() {
+        assert_eq!(ParamType::Float.parse(" 1.5 "), Some(1.5));
+        assert_eq!(ParamType::Bool.parse("on"), Some(1.0));
+        assert_eq!(ParamType::Bool.parse("0"), Some(0.0));
+        assert_eq!(ParamType::Decibel.parse("0 dB"), Some(1.0));
+        assert_eq!(ParamType::Percent.parse("50%"), Some(0.5));
+        assert_eq!(ParamType::Frequency.parse("438.0 Hz"), Some(438.0));
+        assert_eq!(ParamType::Frequency.parse("1.5kHz"), Some(1500.0));
+        assert_eq!(ParamType::Duration.parse("250ms"), Some(0.25));
+        assert_eq!(ParamType::Duration.parse("2 s"), Some(2.0));
+    }
+
+    #[test]
+    fn parse_invalid_returns_none() {
+        assert_eq!(ParamType::Float.parse(""), None);
+        assert_eq!(ParamType::Float.parse("abc"), None);
+        assert_eq!(ParamType::Float.parse("inf"), None);
+        assert_eq!(ParamType::Frequency.parse("10 dB"), None);
+    }
+
     #[test]
     fn format_percent() {
         assert_eq!(ParamType::Percent.formatted(0.00).to_string(), "0.0%");
@@ -0,0 +1,318 @@
+use bats_dsp::{
+    buffers::Buffers,
+    sample_rate::SampleRate,
+    stretch::{semitones_to_ratio, GranularStretch},
+};
+use bmidi::{MidiMessage, Note};
+
+use crate::stream::SampleStream;
+
+use super::{
+    metadata::{Metadata, Param, ParamType, PluginCategory},
+    BatsInstrument,
+};
+
+/// Plays back stereo audio from the start whenever a note on is received. Used to play the audio
+/// of frozen tracks and long samples that are streamed from disk.
+///
+/// Audio that is held in memory can also be chopped into slices, such as the hits of a drum loop.
+/// Each slice is played by its own note, starting at `Sampler::FIRST_SLICE_NOTE`.
+///
+/// Audio that is held in memory can be played at a different speed and pitch. Streamed audio
+/// always plays at its original speed and pitch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sampler {
+    /// The audio to play.
+    source: SampleSource,
+    /// The number of source frames to advance for each output frame.
+    speed: f32,
+    /// The number of semitones to shift the pitch by.
+    pitch: f32,
+}
+
+/// The audio played by a `Sampler`.
+#[derive(Clone, Debug, PartialEq)]
+enum SampleSource {
+    /// Audio that is held in memory.
+    Buffers {
+        /// The audio to play.
+        buffers: Buffers,
+        /// The first frame of each slice in order or empty if every note plays all of `buffers`.
+        slices: Vec<usize>,
+        /// Plays `buffers` at the sampler's speed and pitch.
+        stretch: GranularStretch,
+        /// The frame that playback stops at.
+        end: usize,
+    },
+    /// Audio that is loaded while it plays.
+    Stream(SampleStream),
+}
+
+impl Sampler {
+    /// The metadata for the plugin.
+    pub const METADATA: Metadata = Metadata {
+        name: "sampler",
+        category: PluginCategory::Utility,
+        tags: &["frozen"],
+        params: &[
+            Param {
+                id: 1,
+                name: "speed",
+                param_type: ParamType::Float,
+                default_value: 1.0,
+                min_value: 0.25,
+                max_value: 4.0,
+            },
+            Param {
+                id: 2,
+                name: "pitch",
+                param_type: ParamType::Float,
+                default_value: 0.0,
+                min_value: -24.0,
+                max_value: 24.0,
+            },
+        ],
+        pages: &[],
+    };
+
+    /// The note that plays the first slice. Each following slice is played by the next note up.
+    pub const FIRST_SLICE_NOTE: Note = Note::C2;
+
+    /// The maximum number of slices.
+    pub const MAX_SLICES: usize = 128 - Sampler::FIRST_SLICE_NOTE as usize;
+
+    /// The number of frames in each grain when playing at a different speed or pitch.
+    const GRAIN_FRAMES: usize = 2048;
+
+    /// Create a new sampler that plays `buffers`. The sampler is silent until it receives a note
+    /// on.
+    pub fn new(buffers: Buffers) -> Box<Sampler> {
+        Sampler::sliced(buffers, Vec::new())
+    }
+
+    /// Create a new sampler that chops `buffers` into slices. `slices` contains the first frame of
+    /// each slice in increasing order, for example from `bats_dsp::slice::onset_slices`. Each slice
+    /// plays until the start of the next slice. Slices past `Sampler::MAX_SLICES` are dropped. If
+    /// `slices` is empty, then every note plays all of `buffers`.
+    pub fn sliced(buffers: Buffers, mut slices: Vec<usize>) -> Box<Sampler> {
+        slices.truncate(Sampler::MAX_SLICES);
+        Box::new(Sampler {
+            source: SampleSource::Buffers {
+                buffers,
+                slices,
+                stretch: GranularStretch::new(Sampler::GRAIN_FRAMES),
+                end: 0,
+            },
+            speed: 1.0,
+            pitch: 0.0,
+        })
+    }
+
+    /// Create a new sampler that plays the audio from `stream`. The sampler is silent until it
+    /// receives a note on.
+    pub fn streaming(stream: SampleStream) -> Box<Sampler> {
+        Box::new(Sampler {
+            source: SampleSource::Stream(stream),
+            speed: 1.0,
+            pitch: 0.0,
+        })
+    }
+
+    /// Get the audio that the sampler plays or `None` if the audio is streamed.
+    pub fn buffers(&self) -> Option<&Buffers> {
+        match &self.source {
+            SampleSource::Buffers { buffers, .. } => Some(buffers),
+            SampleSource::Stream(_) => None,
+        }
+    }
+
+    /// Get the first frame of each slice. Empty if the sampler is not sliced.
+    pub fn slices(&self) -> &[usize] {
+        match &self.source {
+            SampleSource::Buffers { slices, .. } => slices,
+            SampleSource::Stream(_) => &[],
+        }
+    }
+
+    /// Get the note that plays the slice at index `slice`.
+    pub fn slice_note(slice: usize) -> Note {
+        Note::from_u8_lossy(Sampler::FIRST_SLICE_NOTE as u8 + slice.min(Sampler::MAX_SLICES) as u8)
+    }
+}
+
+impl BatsInstrument for Sampler {
+    fn metadata(&self) -> &'static Metadata {
+        &Sampler::METADATA
+    }
+
+    fn handle_midi(&mut self, msg: &MidiMessage) {
+        if let MidiMessage::NoteOn(_, note, _) = msg {
+            match &mut self.source {
+                SampleSource::Buffers {
+                    buffers,
+                    slices,
+                    stretch,
+                    end,
+                } => {
+                    if slices.is_empty() {
+                        stretch.reset();
+                        *end = buffers.len();
+                        return;
+                    }
+                    let Some(idx) =
+                        (*note as usize).checked_sub(Sampler::FIRST_SLICE_NOTE as usize)
+                    else {
+                        return;
+                    };
+                    if let Some(start) = slices.get(idx) {
+                        stretch.seek(*start as f64);
+                        *end = slices.get(idx + 1).copied().unwrap_or(buffers.len());
+                    }
+                }
+                SampleSource::Stream(stream) => stream.restart(),
+            }
+        }
+    }
+
+    fn process(&mut self) -> (f32, f32) {
+        match &mut self.source {
+            SampleSource::Buffers {
+                buffers,
+                stretch,
+                end,
+                ..
+            } => {
+                if stretch.position() >= *end as f64 {
+                    return (0.0, 0.0);
+                }
+                stretch.next_frame(buffers)
+            }
+            SampleSource::Stream(stream) => stream.next_frame().unwrap_or_default(),
+        }
+    }
+
+    fn param(&self, id: u32) -> f32 {
+        match id {
+            1 => self.speed,
+            2 => self.pitch,
+            _ => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, id: u32, value: f32) {
+        match id {
+            1 => self.speed = value,
+            2 => self.pitch = value,
+            _ => return,
+        }
+        if let SampleSource::Buffers { stretch, .. } = &mut self.source {
+            stretch.set_speed(self.speed);
+            stretch.set_pitch(semitones_to_ratio(self.pitch));
+        }
+    }
+
+    fn batch_cleanup(&mut self) {}
+
+    fn set_sample_rate(&mut self, _: SampleRate) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use bmidi::{Channel, Note, U7};
+
+    use crate::plugin::BatsInstrumentExt;
+
+    use super::*;
+
+    #[test]
+    fn sampler_is_silent_until_note_on() {
+        let buffers = Buffers::with_iter([(1.0, 2.0), (3.0, 4.0)].into_iter());
+        let mut sampler = Sampler::new(buffers);
+        assert!(sampler.process_to_buffers(4, &[]).is_zero());
+    }
+
+    #[test]
+    fn note_on_plays_buffer_from_start() {
+        let buffers = Buffers::with_iter([(1.0, 2.0), (3.0, 4.0)].into_iter());
+        let mut sampler = Sampler::new(buffers);
+        let note_on = MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX);
+        let out = sampler.process_to_buffers(4, &[(1, note_on)]);
+        assert_eq!(out.left, vec![0.0, 1.0, 3.0, 0.0]);
+        assert_eq!(out.right, vec![0.0, 2.0, 4.0, 0.0]);
+
+        let out = sampler.process_to_buffers(3, &[(0, note_on), (1, note_on)]);
+        assert_eq!(out.left, vec![1.0, 1.0, 3.0]);
+    }
+
+    #[test]
+    fn note_on_restarts_stream() {
+        let preroll = Buffers::with_iter([(1.0, 2.0), (3.0, 4.0)].into_iter());
+        let (stream, mut loader) = SampleStream::new(preroll, 3, 4);
+        let mut sampler = Sampler::streaming(stream);
+        assert!(sampler.process_to_buffers(4, &[]).is_zero());
+
+        let note_on = MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX);
+        sampler.handle_midi(&note_on);
+        loader.poll_restart();
+        loader.push(&[(5.0, 6.0)]);
+        let out = sampler.process_to_buffers(4, &[]);
+        assert_eq!(out.left, vec![1.0, 3.0, 5.0, 0.0]);
+        assert_eq!(out.right, vec![2.0, 4.0, 6.0, 0.0]);
+    }
+
+    #[test]
+    fn slices_are_played_by_consecutive_notes() {
+        let buffers = Buffers::with_iter((1..=6).map(|n| (n as f32, -(n as f32))));
+        let mut sampler = Sampler::sliced(buffers, vec![0, 2, 5]);
+        assert_eq!(sampler.slices(), &[0, 2, 5]);
+        assert_eq!(Sampler::slice_note(1), Note::Db2);
+        let note_on = |note| MidiMessage::NoteOn(Channel::Ch1, note, U7::MAX);
+
+        let out = sampler.process_to_buffers(5, &[(0, note_on(Sampler::slice_note(1)))]);
+        assert_eq!(out.left, vec![3.0, 4.0, 5.0, 0.0, 0.0]);
+        let out = sampler.process_to_buffers(3, &[(0, note_on(Sampler::slice_note(2)))]);
+        assert_eq!(out.left, vec![6.0, 0.0, 0.0]);
+        let out = sampler.process_to_buffers(3, &[(0, note_on(Sampler::slice_note(0)))]);
+        assert_eq!(out.left, vec![1.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn notes_without_slices_are_ignored() {
+        let buffers = Buffers::with_iter((1..=6).map(|n| (n as f32, n as f32)));
+        let mut sampler = Sampler::sliced(buffers, vec![0, 3]);
+        let note_on = |note| MidiMessage::NoteOn(Channel::Ch1, note, U7::MAX);
+        let out = sampler.process_to_buffers(
+            4,
+            &[
+                (0, note_on(Sampler::slice_note(0))),
+                (1, note_on(Note::B1)),
+                (2, note_on(Sampler::slice_note(2))),
+            ],
+        );
+        assert_eq!(out.left, vec![1.0, 2.0, 3.0, 0.0]);
+    }
+
+    #[test]
+    fn speed_changes_playback_length() {
+        let buffers = Buffers::with_iter((0..100).map(|n| (n as f32, n as f32)));
+        let mut sampler = Sampler::new(buffers);
+        sampler.set_param(1, 2.0);
+        assert_eq!(sampler.param(1), 2.0);
+        let note_on = MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX);
+        let out = sampler.process_to_buffers(100, &[(0, note_on)]);
+        assert!(out.left[..50].iter().any(|v| *v != 0.0));
+        assert!(out.left[50..].iter().all(|v| *v == 0.0));
+    }
+
+    #[test]
+    fn pitch_keeps_playback_length() {
+        let buffers = Buffers::with_iter((0..100).map(|_| (1.0, 1.0)));
+        let mut sampler = Sampler::new(buffers);
+        sampler.set_param(2, 12.0);
+        assert_eq!(sampler.param(2), 12.0);
+        let note_on = MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX);
+        let out = sampler.process_to_buffers(101, &[(0, note_on)]);
+        assert!(out.left[..100].iter().any(|v| *v != 0.0));
+        assert_eq!(out.left[100], 0.0);
+    }
+}
@@ -0,0 +1,1241 @@
+use std::{
+    f32::consts::FRAC_PI_4,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+};
+
+use anyhow::{anyhow, Result};
+use arrayvec::ArrayVec;
+use bats_dsp::{
+    buffers::Buffers,
+    envelope::{Envelope, EnvelopeCurve, EnvelopeParams},
+    sample_rate::SampleRate,
+};
+use bmidi::{ControlFunction, MidiMessage, Note, U7};
+
+use super::{
+    metadata::{Metadata, Param, ParamType, PluginCategory},
+    BatsInstrument,
+};
+use crate::registry::{find_files, PluginFactory, PluginRegistry, RegisteredPlugin};
+
+/// Plays the presets of a SoundFont 2 (`.sf2`) file. The preset is picked with the bank and
+/// program params or with bank select and program change messages.
+///
+/// Only the sample, tuning, pan, attenuation, and volume envelope of each zone are used. Filters,
+/// LFOs, modulators, and the delay and hold of the volume envelope are ignored.
+#[derive(Clone)]
+pub struct SoundFontPlayer {
+    /// The metadata for the SoundFont file that is played.
+    metadata: &'static Metadata,
+    /// The sample rate.
+    sample_rate: SampleRate,
+    /// The SoundFont or `None` if it could not be loaded.
+    soundfont: Option<Arc<SoundFont>>,
+    /// The reason that the SoundFont could not be loaded.
+    error: Option<String>,
+    /// The bank of the preset.
+    bank: f32,
+    /// The program of the preset.
+    program: f32,
+    /// The volume.
+    volume: f32,
+    /// The index of the preset that new notes are played with.
+    preset: Option<usize>,
+    /// The playing voices. A note may play several voices, such as one for each side of a stereo
+    /// sample.
+    voices: ArrayVec<SoundFontVoice, { SoundFontPlayer::MAX_VOICES }>,
+}
+
+/// The contents of a SoundFont 2 file.
+#[derive(Debug, PartialEq)]
+pub struct SoundFont {
+    /// The name of the SoundFont.
+    name: String,
+    /// The samples of all the sample headers.
+    samples: Vec<i16>,
+    /// The presets sorted by bank and program.
+    presets: Vec<Preset>,
+}
+
+/// A sound that can be played by a `SoundFontPlayer`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preset {
+    /// The name of the preset.
+    pub name: String,
+    /// The bank. Bank `128` holds the drum kits of General MIDI SoundFonts.
+    pub bank: u16,
+    /// The program.
+    pub program: u16,
+    /// The regions that notes may play.
+    regions: Vec<Region>,
+}
+
+/// A sample that is played for a range of keys and velocities, with all the generators of the
+/// preset and instrument combined.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Region {
+    /// The lowest and highest key, inclusive.
+    keys: (u8, u8),
+    /// The lowest and highest velocity, inclusive.
+    velocities: (u8, u8),
+    /// The index of the first sample.
+    start: usize,
+    /// The index just past the last sample.
+    end: usize,
+    /// The index of the first sample of the loop.
+    loop_start: usize,
+    /// The index just past the last sample of the loop.
+    loop_end: usize,
+    /// How the loop is played.
+    loop_mode: LoopMode,
+    /// The key that plays the sample at its original pitch.
+    root_key: u8,
+    /// The number of cents to tune the sample by.
+    tune_cents: f32,
+    /// The number of cents between each key.
+    scale_tuning: f32,
+    /// The sample rate of the sample.
+    sample_rate: f32,
+    /// The pan between `-1.0` for left and `1.0` for right.
+    pan: f32,
+    /// The amp to multiply the sample by.
+    amp: f32,
+    /// The attack of the volume envelope in seconds.
+    attack: f32,
+    /// The decay of the volume envelope in seconds.
+    decay: f32,
+    /// The sustain amp of the volume envelope.
+    sustain: f32,
+    /// The release of the volume envelope in seconds.
+    release: f32,
+}
+
+/// How the loop of a region is played.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+enum LoopMode {
+    /// The sample is played once.
+    #[default]
+    None,
+    /// The loop is played until the voice is done.
+    Continuous,
+    /// The loop is played until the note is released and then the rest of the sample is played.
+    UntilRelease,
+}
+
+/// A single region that is playing.
+#[derive(Clone, Debug, PartialEq)]
+struct SoundFontVoice {
+    /// The note that started the voice.
+    note: Note,
+    /// The region that is played.
+    region: Region,
+    /// The position within the samples.
+    position: f64,
+    /// The number of samples to advance for each frame.
+    step: f64,
+    /// The amp for the left and right channel.
+    amp: (f32, f32),
+    /// The volume envelope.
+    envelope: Envelope,
+    /// The params for the volume envelope.
+    envelope_params: EnvelopeParams,
+    /// True if the end of the sample was reached.
+    finished: bool,
+}
+
+/// A SoundFont file that is loaded the first time that it is played. The file is unloaded once it
+/// is no longer used.
+struct SoundFontFile {
+    /// The path to the file.
+    path: PathBuf,
+    /// The loaded SoundFont.
+    loaded: Mutex<Weak<SoundFont>>,
+}
+
+impl SoundFontPlayer {
+    /// The params for every SoundFont.
+    pub const PARAMS: &'static [Param] = &[
+        Param {
+            id: 1,
+            name: "bank",
+            param_type: ParamType::Float,
+            default_value: 0.0,
+            min_value: 0.0,
+            max_value: 128.0,
+        },
+        Param {
+            id: 2,
+            name: "program",
+            param_type: ParamType::Float,
+            default_value: 0.0,
+            min_value: 0.0,
+            max_value: 127.0,
+        },
+        Param {
+            id: 3,
+            name: "volume",
+            param_type: ParamType::Decibel,
+            default_value: 1.0,
+            min_value: 0.001,
+            max_value: 4.0,
+        },
+    ];
+
+    /// The maximum number of voices that can play at once.
+    pub const MAX_VOICES: usize = 64;
+
+    /// Create a new player for `soundfont`. If the SoundFont could not be loaded, then the player
+    /// is silent.
+    fn new(
+        metadata: &'static Metadata,
+        soundfont: Result<Arc<SoundFont>>,
+        sample_rate: SampleRate,
+    ) -> Box<SoundFontPlayer> {
+        let (soundfont, error) = match soundfont {
+            Ok(sf) => (Some(sf), None),
+            Err(err) => (None, Some(format!("{err:#}"))),
+        };
+        let mut player = Box::new(SoundFontPlayer {
+            metadata,
+            sample_rate,
+            soundfont,
+            error,
+            bank: 0.0,
+            program: 0.0,
+            volume: 1.0,
+            preset: None,
+            voices: ArrayVec::new(),
+        });
+        player.select_preset();
+        player
+    }
+
+    /// The reason that the SoundFont could not be loaded or `None` if it was loaded.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// The preset that new notes are played with.
+    pub fn preset(&self) -> Option<&Preset> {
+        let soundfont = self.soundfont.as_ref()?;
+        soundfont.presets.get(self.preset?)
+    }
+
+    /// Pick the preset for the bank and program params.
+    fn select_preset(&mut self) {
+        self.preset = self
+            .soundfont
+            .as_ref()
+            .and_then(|sf| sf.find_preset(self.bank.round() as u16, self.program.round() as u16));
+    }
+
+    /// Start the voices for `note`.
+    fn note_on(&mut self, note: Note, velocity: U7) {
+        let (Some(soundfont), Some(preset)) = (self.soundfont.as_ref(), self.preset) else {
+            return;
+        };
+        let (key, velocity) = (note as u8, u8::from(velocity));
+        for region in soundfont.presets[preset].regions.iter() {
+            let in_range = |(low, high): (u8, u8), value: u8| low <= value && value <= high;
+            if !in_range(region.keys, key) || !in_range(region.velocities, velocity) {
+                continue;
+            }
+            if self.voices.is_full() {
+                self.voices.retain(|v| v.is_active());
+            }
+            if self.voices.is_full() {
+                // Steal the oldest released voice before stealing a held one.
+                let idx = self
+                    .voices
+                    .iter()
+                    .position(|v| v.envelope.is_released())
+                    .unwrap_or(0);
+                self.voices.remove(idx);
+            }
+            self.voices.push(SoundFontVoice::new(
+                self.sample_rate,
+                region,
+                note,
+                velocity,
+            ));
+        }
+    }
+
+    /// Release the voices for `note`.
+    fn note_off(&mut self, note: Note) {
+        for voice in self.voices.iter_mut().filter(|v| v.note == note) {
+            voice.release();
+        }
+    }
+}
+
+impl BatsInstrument for SoundFontPlayer {
+    fn metadata(&self) -> &'static Metadata {
+        self.metadata
+    }
+
+    fn handle_midi(&mut self, msg: &MidiMessage) {
+        match msg {
+            MidiMessage::NoteOn(_, note, velocity) if *velocity != U7::MIN => {
+                self.note_on(*note, *velocity)
+            }
+            MidiMessage::NoteOn(_, note, _) | MidiMessage::NoteOff(_, note, _) => {
+                self.note_off(*note)
+            }
+            MidiMessage::ProgramChange(_, program) => {
+                self.program = u8::from(*program) as f32;
+                self.select_preset();
+            }
+            MidiMessage::ControlChange(_, ControlFunction::BANK_SELECT, bank) => {
+                self.bank = u8::from(*bank) as f32;
+                self.select_preset();
+            }
+            _ => {}
+        }
+    }
+
+    fn process(&mut self) -> (f32, f32) {
+        let Some(soundfont) = self.soundfont.as_ref() else {
+            return (0.0, 0.0);
+        };
+        let (mut left, mut right) = (0.0, 0.0);
+        for voice in self.voices.iter_mut() {
+            let (l, r) = voice.next_frame(&soundfont.samples);
+            left += l;
+            right += r;
+        }
+        (left * self.volume, right * self.volume)
+    }
+
+    /// Process the frames and then remove the voices that have finished.
+    fn process_block(&mut self, midi: &[(u32, MidiMessage)], out: &mut Buffers) {
+        let mut midi_iter = midi.iter().peekable();
+        for i in 0..out.len() {
+            while let Some((_, msg)) = midi_iter.next_if(|(frame, _)| *frame <= i as u32) {
+                self.handle_midi(msg);
+            }
+            out.set(i, self.process());
+        }
+        self.voices.retain(|v| v.is_active());
+    }
+
+    fn param(&self, id: u32) -> f32 {
+        match id {
+            1 => self.bank,
+            2 => self.program,
+            3 => self.volume,
+            _ => 0.0,
+        }
+    }
+
+    fn set_param(&mut self, id: u32, value: f32) {
+        match id {
+            1 => self.bank = value,
+            2 => self.program = value,
+            3 => self.volume = value,
+            _ => return,
+        }
+        if id != 3 {
+            self.select_preset();
+        }
+    }
+
+    fn batch_cleanup(&mut self) {}
+
+    fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+        self.voices.clear();
+    }
+}
+
+impl PartialEq for SoundFontPlayer {
+    /// Players are equal if they play the same SoundFont with the same params and voices.
+    fn eq(&self, other: &SoundFontPlayer) -> bool {
+        let same_soundfont = match (&self.soundfont, &other.soundfont) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        same_soundfont
+            && self.metadata == other.metadata
+            && self.sample_rate == other.sample_rate
+            && (self.bank, self.program, self.volume) == (other.bank, other.program, other.volume)
+            && self.voices == other.voices
+    }
+}
+
+impl fmt::Debug for SoundFontPlayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SoundFontPlayer")
+            .field("name", &self.metadata.name)
+            .field("preset", &self.preset().map(|p| p.name.as_str()))
+            .field("bank", &self.bank)
+            .field("program", &self.program)
+            .field("volume", &self.volume)
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SoundFontVoice {
+    /// Create a voice that plays `region` for `note`.
+    fn new(sample_rate: SampleRate, region: &Region, note: Note, velocity: u8) -> SoundFontVoice {
+        let cents =
+            (note as u8 as f32 - region.root_key as f32) * region.scale_tuning + region.tune_cents;
+        let step = 2f64.powf(cents as f64 / 1200.0) * region.sample_rate as f64
+            / sample_rate.sample_rate() as f64;
+        let velocity = velocity as f32 / u8::from(U7::MAX) as f32;
+        let amp = region.amp * velocity * velocity;
+        let angle = (region.pan + 1.0) * FRAC_PI_4;
+        let mut envelope_params = EnvelopeParams::new(
+            sample_rate,
+            region.attack,
+            region.decay,
+            region.sustain,
+            region.release,
+        );
+        envelope_params.set_decay_curve(EnvelopeCurve::Exponential);
+        envelope_params.set_release_curve(EnvelopeCurve::Exponential);
+        SoundFontVoice {
+            note,
+            region: *region,
+            position: region.start as f64,
+            step,
+            amp: (amp * angle.cos(), amp * angle.sin()),
+            envelope: Envelope::new(),
+            envelope_params,
+            finished: false,
+        }
+    }
+
+    /// Returns true if the voice is still producing sound.
+    fn is_active(&self) -> bool {
+        !self.finished && self.envelope.is_active()
+    }
+
+    /// Returns true if the loop of the region should be played.
+    fn is_looping(&self) -> bool {
+        match self.region.loop_mode {
+            LoopMode::None => false,
+            LoopMode::Continuous => true,
+            LoopMode::UntilRelease => !self.envelope.is_released(),
+        }
+    }
+
+    /// Start the release of the volume envelope.
+    fn release(&mut self) {
+        self.envelope.release(&self.envelope_params);
+    }
+
+    /// Produce the next frame from `samples`.
+    fn next_frame(&mut self, samples: &[i16]) -> (f32, f32) {
+        if self.finished {
+            return (0.0, 0.0);
+        }
+        let region = &self.region;
+        let idx = self.position as usize;
+        let next_idx = if self.is_looping() && idx + 1 >= region.loop_end {
+            region.loop_start
+        } else {
+            idx + 1
+        };
+        let sample = |idx: usize| samples.get(idx).map(|s| *s as f32 / 32768.0).unwrap_or(0.0);
+        let frac = (self.position - idx as f64) as f32;
+        let value = sample(idx) + (sample(next_idx) - sample(idx)) * frac;
+        let amp = self.envelope.next_sample(&self.envelope_params);
+        self.position += self.step;
+        if self.is_looping() {
+            let loop_len = (region.loop_end - region.loop_start) as f64;
+            while self.position >= region.loop_end as f64 {
+                self.position -= loop_len;
+            }
+        } else if self.position >= region.end as f64 {
+            self.finished = true;
+        }
+        (value * amp * self.amp.0, value * amp * self.amp.1)
+    }
+}
+
+/// The directories that are searched for SoundFonts.
+pub fn default_search_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Some(home) = std::env::var_os("HOME") {
+        paths.push(PathBuf::from(home).join(".local/share/soundfonts"));
+    }
+    paths.push(PathBuf::from("/usr/share/soundfonts"));
+    paths.push(PathBuf::from("/usr/share/sounds/sf2"));
+    paths
+}
+
+/// Add a plugin to the global `PluginRegistry` for each `.sf2` file within `dirs`. The plugin is
+/// named after the file. Files are only read once they are played. Returns the registered plugins
+/// and an error for each file that could not be registered.
+pub fn register_soundfonts(dirs: &[PathBuf]) -> (Vec<RegisteredPlugin>, Vec<anyhow::Error>) {
+    let (mut registered, mut errors) = (Vec::new(), Vec::new());
+    for path in find_files(dirs, "sf2") {
+        let Some(name) = path.file_stem().map(|s| s.to_string_lossy().into_owned()) else {
+            continue;
+        };
+        let metadata: &'static Metadata = Box::leak(Box::new(Metadata {
+            name: Box::leak(name.into_boxed_str()),
+            category: PluginCategory::Instrument,
+            tags: &["soundfont"],
+            params: SoundFontPlayer::PARAMS,
+            pages: &[],
+        }));
+        let file = SoundFontFile {
+            path,
+            loaded: Mutex::new(Weak::new()),
+        };
+        let factory = PluginFactory {
+            metadata,
+            build: Box::new(move |sample_rate| {
+                SoundFontPlayer::new(metadata, file.load(), sample_rate)
+            }),
+            rebuild_on_sample_rate_change: false,
+        };
+        match PluginRegistry::global().register(factory) {
+            Ok(p) => registered.push(p),
+            Err(err) => errors.push(err),
+        }
+    }
+    (registered, errors)
+}
+
+impl SoundFontFile {
+    /// Get the SoundFont, loading it if it is not already loaded.
+    fn load(&self) -> Result<Arc<SoundFont>> {
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(soundfont) = loaded.upgrade() {
+            return Ok(soundfont);
+        }
+        let soundfont = Arc::new(SoundFont::load(&self.path)?);
+        *loaded = Arc::downgrade(&soundfont);
+        Ok(soundfont)
+    }
+}
+
+impl SoundFont {
+    /// Load the SoundFont 2 file at `path`.
+    pub fn load(path: &Path) -> Result<SoundFont> {
+        let bytes = std::fs::read(path)
+            .map_err(|err| anyhow!("failed to read SoundFont {path:?}: {err}"))?;
+        SoundFont::parse(&bytes).map_err(|err| anyhow!("invalid SoundFont {path:?}: {err}"))
+    }
+
+    /// Parse the contents of a SoundFont 2 file.
+    pub fn parse(bytes: &[u8]) -> Result<SoundFont> {
+        let riff = match read_chunks(bytes)?.as_slice() {
+            [(b"RIFF", body), ..] if body.starts_with(b"sfbk") => &body[4..],
+            _ => return Err(anyhow!("file is not a SoundFont")),
+        };
+        let (mut name, mut samples, mut pdta) = (String::new(), Vec::new(), None);
+        for (id, body) in read_chunks(riff)? {
+            if id != b"LIST" || body.len() < 4 {
+                continue;
+            }
+            for (sub_id, sub_body) in read_chunks(&body[4..])? {
+                match (&body[..4], sub_id) {
+                    (b"INFO", b"INAM") => name = read_name(sub_body),
+                    (b"sdta", b"smpl") => {
+                        samples = sub_body
+                            .chunks_exact(2)
+                            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                            .collect();
+                    }
+                    _ => {}
+                }
+            }
+            if &body[..4] == b"pdta" {
+                pdta = Some(Pdta::parse(&body[4..])?);
+            }
+        }
+        let pdta = pdta.ok_or_else(|| anyhow!("SoundFont has no preset data"))?;
+        let mut presets = pdta.presets(samples.len());
+        presets.sort_by_key(|p| (p.bank, p.program));
+        Ok(SoundFont {
+            name,
+            samples,
+            presets,
+        })
+    }
+
+    /// The name of the SoundFont.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The presets sorted by bank and program.
+    pub fn presets(&self) -> &[Preset] {
+        &self.presets
+    }
+
+    /// Get the index of the preset for `bank` and `program`. If there is no such preset, then the
+    /// same program in bank `0` is used and then the first preset.
+    fn find_preset(&self, bank: u16, program: u16) -> Option<usize> {
+        let find = |bank| {
+            self.presets
+                .iter()
+                .position(|p| p.bank == bank && p.program == program)
+        };
+        find(bank)
+            .or_else(|| find(0))
+            .or((!self.presets.is_empty()).then_some(0))
+    }
+}
+
+/// Split `data` into its RIFF chunks as `(id, body)`.
+fn read_chunks(mut data: &[u8]) -> Result<Vec<(&[u8; 4], &[u8])>> {
+    let mut chunks = Vec::new();
+    while data.len() >= 8 {
+        let id: &[u8; 4] = data[..4].try_into()?;
+        let len = u32::from_le_bytes(data[4..8].try_into()?) as usize;
+        let body = data.get(8..8 + len).ok_or_else(|| {
+            anyhow!(
+                "chunk {:?} is truncated",
+                String::from_utf8_lossy(id.as_slice())
+            )
+        })?;
+        chunks.push((id, body));
+        // Chunks are padded to an even number of bytes.
+        data = data.get(8 + len + len % 2..).unwrap_or(&[]);
+    }
+    Ok(chunks)
+}
+
+/// Read a null terminated name.
+fn read_name(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).trim().to_string()
+}
+
+/// Read the little endian `u16` at `offset`.
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+/// Read the little endian `u32` at `offset`.
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// The preset data of a SoundFont. Each list ends with a terminal record.
+struct Pdta {
+    /// The name, program, bank, and first zone of each preset.
+    presets: Vec<(String, u16, u16, usize)>,
+    /// The first generator of each preset zone.
+    preset_zones: Vec<usize>,
+    /// The generators of the preset zones.
+    preset_generators: Vec<Generator>,
+    /// The first zone of each instrument.
+    instruments: Vec<usize>,
+    /// The first generator of each instrument zone.
+    instrument_zones: Vec<usize>,
+    /// The generators of the instrument zones.
+    instrument_generators: Vec<Generator>,
+    /// The sample headers.
+    samples: Vec<SampleHeader>,
+}
+
+/// A generator that sets a single property of a zone.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Generator {
+    /// The property that is set.
+    id: u16,
+    /// The value. Ranges hold the low value in the lower byte and the high value in the upper
+    /// byte.
+    amount: i16,
+}
+
+/// Describes where a sample is within the sample data.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct SampleHeader {
+    start: u32,
+    end: u32,
+    loop_start: u32,
+    loop_end: u32,
+    sample_rate: u32,
+    original_pitch: u8,
+    pitch_correction: i8,
+    sample_type: u16,
+}
+
+/// The values of all the generators of a zone.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Generators([i16; Generators::COUNT]);
+
+impl Pdta {
+    /// Parse the body of the `pdta` list.
+    fn parse(data: &[u8]) -> Result<Pdta> {
+        let mut pdta = Pdta {
+            presets: Vec::new(),
+            preset_zones: Vec::new(),
+            preset_generators: Vec::new(),
+            instruments: Vec::new(),
+            instrument_zones: Vec::new(),
+            instrument_generators: Vec::new(),
+            samples: Vec::new(),
+        };
+        let zones = |body: &[u8]| -> Vec<usize> {
+            body.chunks_exact(4)
+                .map(|r| read_u16(r, 0) as usize)
+                .collect()
+        };
+        let generators = |body: &[u8]| -> Vec<Generator> {
+            body.chunks_exact(4)
+                .map(|r| Generator {
+                    id: read_u16(r, 0),
+                    amount: read_u16(r, 2) as i16,
+                })
+                .collect()
+        };
+        for (id, body) in read_chunks(data)? {
+            match id {
+                b"phdr" => {
+                    pdta.presets = body
+                        .chunks_exact(38)
+                        .map(|r| {
+                            let name = read_name(&r[..20]);
+                            (
+                                name,
+                                read_u16(r, 20),
+                                read_u16(r, 22),
+                                read_u16(r, 24) as usize,
+                            )
+                        })
+                        .collect()
+                }
+                b"pbag" => pdta.preset_zones = zones(body),
+                b"pgen" => pdta.preset_generators = generators(body),
+                b"inst" => {
+                    pdta.instruments = body
+                        .chunks_exact(22)
+                        .map(|r| read_u16(r, 20) as usize)
+                        .collect()
+                }
+                b"ibag" => pdta.instrument_zones = zones(body),
+                b"igen" => pdta.instrument_generators = generators(body),
+                b"shdr" => {
+                    pdta.samples = body
+                        .chunks_exact(46)
+                        .map(|r| SampleHeader {
+                            start: read_u32(r, 20),
+                            end: read_u32(r, 24),
+                            loop_start: read_u32(r, 28),
+                            loop_end: read_u32(r, 32),
+                            sample_rate: read_u32(r, 36),
+                            original_pitch: r[40],
+                            pitch_correction: r[41] as i8,
+                            sample_type: read_u16(r, 44),
+                        })
+                        .collect()
+                }
+                _ => {}
+            }
+        }
+        if pdta.presets.is_empty() || pdta.instruments.is_empty() || pdta.samples.is_empty() {
+            return Err(anyhow!(
+                "SoundFont is missing presets, instruments, or samples"
+            ));
+        }
+        Ok(pdta)
+    }
+
+    /// Build the presets. The last preset is the terminal record so it is skipped.
+    fn presets(&self, sample_count: usize) -> Vec<Preset> {
+        self.presets
+            .windows(2)
+            .map(|w| {
+                let (name, program, bank, first_zone) = &w[0];
+                let zones = Pdta::zones(
+                    &self.preset_zones,
+                    &self.preset_generators,
+                    *first_zone..w[1].3,
+                );
+                let (global, zones) = Pdta::split_global(zones, Generators::INSTRUMENT);
+                let regions = zones
+                    .into_iter()
+                    .flat_map(|zone| {
+                        let mut generators = Generators::PRESET_DEFAULT;
+                        generators.apply(global);
+                        generators.apply(zone);
+                        let instrument = generators.get(Generators::INSTRUMENT) as u16 as usize;
+                        self.instrument_regions(instrument, &generators, sample_count)
+                    })
+                    .collect();
+                Preset {
+                    name: name.clone(),
+                    bank: *bank,
+                    program: *program,
+                    regions,
+                }
+            })
+            .collect()
+    }
+
+    /// Build the regions of the instrument at index `instrument` as it is used by a preset zone
+    /// with `preset` generators.
+    fn instrument_regions(
+        &self,
+        instrument: usize,
+        preset: &Generators,
+        sample_count: usize,
+    ) -> Vec<Region> {
+        let (Some(first_zone), Some(end_zone)) = (
+            self.instruments.get(instrument),
+            self.instruments.get(instrument + 1),
+        ) else {
+            return Vec::new();
+        };
+        let zones = Pdta::zones(
+            &self.instrument_zones,
+            &self.instrument_generators,
+            *first_zone..*end_zone,
+        );
+        let (global, zones) = Pdta::split_global(zones, Generators::SAMPLE_ID);
+        zones
+            .into_iter()
+            .filter_map(|zone| {
+                let mut generators = Generators::INSTRUMENT_DEFAULT;
+                generators.apply(global);
+                generators.apply(zone);
+                generators.add_preset(preset);
+                let sample = self
+                    .samples
+                    .get(generators.get(Generators::SAMPLE_ID) as u16 as usize)?;
+                Region::new(&generators, sample, sample_count)
+            })
+            .collect()
+    }
+
+    /// Get the generators of each zone in `range`.
+    fn zones<'a>(
+        zones: &[usize],
+        generators: &'a [Generator],
+        range: std::ops::Range<usize>,
+    ) -> Vec<&'a [Generator]> {
+        range
+            .filter_map(|zone| {
+                let start = *zones.get(zone)?;
+                let end = *zones.get(zone + 1)?;
+                generators.get(start..end)
+            })
+            .collect()
+    }
+
+    /// Split off the global zone, which is the first zone if it does not have the `terminal`
+    /// generator. Zones that are not global and do not have the `terminal` generator are dropped.
+    fn split_global(
+        mut zones: Vec<&[Generator]>,
+        terminal: u16,
+    ) -> (&[Generator], Vec<&[Generator]>) {
+        let has_terminal = |zone: &[Generator]| zone.iter().any(|g| g.id == terminal);
+        let global = match zones.first() {
+            Some(zone) if !has_terminal(zone) => zones.remove(0),
+            _ => &[],
+        };
+        zones.retain(|zone| has_terminal(zone));
+        (global, zones)
+    }
+}
+
+impl Generators {
+    /// The number of generators defined by the SoundFont 2 spec.
+    const COUNT: usize = 61;
+    const START_OFFSET: u16 = 0;
+    const END_OFFSET: u16 = 1;
+    const LOOP_START_OFFSET: u16 = 2;
+    const LOOP_END_OFFSET: u16 = 3;
+    const START_COARSE_OFFSET: u16 = 4;
+    const END_COARSE_OFFSET: u16 = 12;
+    const PAN: u16 = 17;
+    const ATTACK: u16 = 34;
+    const DECAY: u16 = 36;
+    const SUSTAIN: u16 = 37;
+    const RELEASE: u16 = 38;
+    const INSTRUMENT: u16 = 41;
+    const KEY_RANGE: u16 = 43;
+    const VELOCITY_RANGE: u16 = 44;
+    const LOOP_START_COARSE_OFFSET: u16 = 45;
+    const ATTENUATION: u16 = 48;
+    const LOOP_END_COARSE_OFFSET: u16 = 50;
+    const COARSE_TUNE: u16 = 51;
+    const FINE_TUNE: u16 = 52;
+    const SAMPLE_ID: u16 = 53;
+    const SAMPLE_MODES: u16 = 54;
+    const SCALE_TUNING: u16 = 56;
+    const ROOT_KEY: u16 = 58;
+
+    /// The generators that are added to the instrument generators when set in a preset.
+    const ADDITIVE: &'static [u16] = &[
+        Generators::PAN,
+        Generators::ATTACK,
+        Generators::DECAY,
+        Generators::SUSTAIN,
+        Generators::RELEASE,
+        Generators::ATTENUATION,
+        Generators::COARSE_TUNE,
+        Generators::FINE_TUNE,
+        Generators::SCALE_TUNING,
+    ];
+
+    /// A range that covers every key or velocity.
+    const FULL_RANGE: i16 = 0x7F00;
+
+    /// The values of preset generators that are not set.
+    const PRESET_DEFAULT: Generators = Generators::with_ranges([0; Generators::COUNT]);
+
+    /// The values of instrument generators that are not set.
+    const INSTRUMENT_DEFAULT: Generators = {
+        let mut values = [0; Generators::COUNT];
+        values[Generators::ATTACK as usize] = -12000;
+        values[Generators::DECAY as usize] = -12000;
+        values[Generators::RELEASE as usize] = -12000;
+        values[Generators::SCALE_TUNING as usize] = 100;
+        values[Generators::ROOT_KEY as usize] = -1;
+        Generators::with_ranges(values)
+    };
+
+    /// Create generators with `values` and ranges that cover every key and velocity.
+    const fn with_ranges(mut values: [i16; Generators::COUNT]) -> Generators {
+        values[Generators::KEY_RANGE as usize] = Generators::FULL_RANGE;
+        values[Generators::VELOCITY_RANGE as usize] = Generators::FULL_RANGE;
+        Generators(values)
+    }
+
+    /// Get the value of the generator with `id`.
+    fn get(&self, id: u16) -> i16 {
+        self.0[id as usize]
+    }
+
+    /// Get the `(low, high)` range of the generator with `id`.
+    fn range(&self, id: u16) -> (u8, u8) {
+        let [low, high] = self.get(id).to_le_bytes();
+        (low, high)
+    }
+
+    /// Set the values from `generators`. Unknown generators are ignored.
+    fn apply(&mut self, generators: &[Generator]) {
+        for g in generators {
+            if let Some(value) = self.0.get_mut(g.id as usize) {
+                *value = g.amount;
+            }
+        }
+    }
+
+    /// Combine the generators of a preset zone with `self`. Ranges are narrowed and the additive
+    /// generators are added.
+    fn add_preset(&mut self, preset: &Generators) {
+        for id in [Generators::KEY_RANGE, Generators::VELOCITY_RANGE] {
+            let ((low, high), (preset_low, preset_high)) = (self.range(id), preset.range(id));
+            self.0[id as usize] = i16::from_le_bytes([low.max(preset_low), high.min(preset_high)]);
+        }
+        for id in Generators::ADDITIVE.iter().copied() {
+            self.0[id as usize] = self.get(id).saturating_add(preset.get(id));
+        }
+    }
+
+    /// Get the sample offset from the fine generator `fine` and the coarse generator `coarse`.
+    fn offset(&self, fine: u16, coarse: u16) -> i64 {
+        self.get(fine) as i64 + self.get(coarse) as i64 * 32768
+    }
+
+    /// Get the time in seconds of the generator with `id`, which is in timecents. The shortest
+    /// time is treated as instant.
+    fn seconds(&self, id: u16) -> f32 {
+        let timecents = self.get(id).clamp(-12000, 8000);
+        if timecents == -12000 {
+            0.0
+        } else {
+            2f32.powf(timecents as f32 / 1200.0)
+        }
+    }
+
+    /// Get the amp of the generator with `id`, which is an attenuation in centibels.
+    fn amp(&self, id: u16) -> f32 {
+        let centibels = self.get(id).clamp(0, 1440);
+        10f32.powf(-(centibels as f32) / 200.0)
+    }
+}
+
+impl Region {
+    /// The lowest sustain amp. Quieter sustains would never finish their release.
+    const MIN_SUSTAIN: f32 = 0.001;
+
+    /// Create a region that plays `sample` with `generators`. Returns `None` if the sample is
+    /// stored in ROM or is not within the `sample_count` samples.
+    fn new(generators: &Generators, sample: &SampleHeader, sample_count: usize) -> Option<Region> {
+        // ROM samples are not stored in the file.
+        if sample.sample_type & 0x8000 != 0 || sample.sample_rate == 0 {
+            return None;
+        }
+        let position = |base: u32, fine: u16, coarse: u16| -> usize {
+            (base as i64 + generators.offset(fine, coarse)).clamp(0, sample_count as i64) as usize
+        };
+        let start = position(
+            sample.start,
+            Generators::START_OFFSET,
+            Generators::START_COARSE_OFFSET,
+        );
+        let end = position(
+            sample.end,
+            Generators::END_OFFSET,
+            Generators::END_COARSE_OFFSET,
+        );
+        let loop_start = position(
+            sample.loop_start,
+            Generators::LOOP_START_OFFSET,
+            Generators::LOOP_START_COARSE_OFFSET,
+        );
+        let loop_end = position(
+            sample.loop_end,
+            Generators::LOOP_END_OFFSET,
+            Generators::LOOP_END_COARSE_OFFSET,
+        );
+        if start >= end {
+            return None;
+        }
+        let has_loop = start <= loop_start && loop_start < loop_end && loop_end <= end;
+        let loop_mode = match generators.get(Generators::SAMPLE_MODES) & 3 {
+            1 if has_loop => LoopMode::Continuous,
+            3 if has_loop => LoopMode::UntilRelease,
+            _ => LoopMode::None,
+        };
+        let root_key = match generators.get(Generators::ROOT_KEY) {
+            key @ 0..=127 => key as u8,
+            _ if sample.original_pitch <= 127 => sample.original_pitch,
+            _ => Note::C4 as u8,
+        };
+        let tune_cents = generators.get(Generators::COARSE_TUNE) as f32 * 100.0
+            + generators.get(Generators::FINE_TUNE) as f32
+            + sample.pitch_correction as f32;
+        Some(Region {
+            keys: generators.range(Generators::KEY_RANGE),
+            velocities: generators.range(Generators::VELOCITY_RANGE),
+            start,
+            end,
+            loop_start,
+            loop_end,
+            loop_mode,
+            root_key,
+            tune_cents,
+            scale_tuning: generators.get(Generators::SCALE_TUNING) as f32,
+            sample_rate: sample.sample_rate as f32,
+            pan: (generators.get(Generators::PAN).clamp(-500, 500) as f32) / 500.0,
+            amp: generators.amp(Generators::ATTENUATION),
+            attack: generators.seconds(Generators::ATTACK),
+            decay: generators.seconds(Generators::DECAY),
+            sustain: generators.amp(Generators::SUSTAIN).max(Region::MIN_SUSTAIN),
+            release: generators.seconds(Generators::RELEASE),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bmidi::Channel;
+
+    use crate::plugin::BatsInstrumentExt;
+
+    use super::*;
+
+    /// Build a RIFF chunk.
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    /// Build a LIST chunk of `list_type` that holds `chunks`.
+    fn list(list_type: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = list_type.to_vec();
+        chunks.iter().for_each(|c| body.extend_from_slice(c));
+        chunk(b"LIST", &body)
+    }
+
+    /// Build a record that starts with a 20 byte `name`.
+    fn named(name: &str, rest: &[u8]) -> Vec<u8> {
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.resize(20, 0);
+        bytes.extend_from_slice(rest);
+        bytes
+    }
+
+    /// Build records of `u16` values.
+    fn u16s(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    /// Build a SoundFont with two presets.
+    ///
+    /// - "piano", bank 0 program 0: plays a rising ramp for keys up to C4 and a constant sample
+    ///   for the keys above.
+    /// - "drums", bank 128 program 5: plays the ramp 6 dB quieter and panned left.
+    fn soundfont_bytes() -> Vec<u8> {
+        let mut samples: Vec<i16> = (1..=8).map(|v| v * 1024).collect();
+        samples.extend([0; 46]);
+        samples.extend([8192; 8]);
+        samples.extend([0; 46]);
+        let sample_header = |name: &str, start: u32, end: u32| {
+            let mut rest = Vec::new();
+            for v in [start, end, start, end, 44100] {
+                rest.extend_from_slice(&v.to_le_bytes());
+            }
+            rest.extend_from_slice(&[60, 0, 0, 0, 1, 0]);
+            named(name, &rest)
+        };
+        let phdr = [
+            named("piano", &[u16s(&[0, 0, 0]), vec![0; 12]].concat()),
+            named("drums", &[u16s(&[5, 128, 1]), vec![0; 12]].concat()),
+            named("EOP", &[u16s(&[0, 0, 2]), vec![0; 12]].concat()),
+        ]
+        .concat();
+        // Each preset has a single zone for one instrument.
+        let pbag = u16s(&[0, 0, 1, 0, 4, 0]);
+        let pgen = u16s(&[
+            41, 0, // piano plays instrument 0
+            48, 60, // drums are 6 dB quieter
+            17, 0xFE0C, // panned left
+            41, 0, // drums play instrument 0
+            0, 0,
+        ]);
+        let inst = [named("ramp", &u16s(&[0])), named("EOI", &u16s(&[3]))].concat();
+        // A global zone and two zones split at C4.
+        let ibag = u16s(&[0, 0, 1, 0, 3, 0, 5, 0]);
+        let igen = u16s(&[
+            54, 0, // global zone: no loop
+            43, 0x3C00, // keys 0 to 60
+            53, 0, // sample 0
+            43, 0x7F3D, // keys 61 to 127
+            53, 1, // sample 1
+            0, 0,
+        ]);
+        let shdr = [
+            sample_header("ramp", 0, 8),
+            sample_header("flat", 54, 62),
+            sample_header("EOS", 0, 0),
+        ]
+        .concat();
+        let sample_bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut body = b"sfbk".to_vec();
+        body.extend(list(b"INFO", &[chunk(b"INAM", b"test font\0")]));
+        body.extend(list(b"sdta", &[chunk(b"smpl", &sample_bytes)]));
+        body.extend(list(
+            b"pdta",
+            &[
+                chunk(b"phdr", &phdr),
+                chunk(b"pbag", &pbag),
+                chunk(b"pmod", &[0; 10]),
+                chunk(b"pgen", &pgen),
+                chunk(b"inst", &inst),
+                chunk(b"ibag", &ibag),
+                chunk(b"imod", &[0; 10]),
+                chunk(b"igen", &igen),
+                chunk(b"shdr", &shdr),
+            ],
+        ));
+        chunk(b"RIFF", &body)
+    }
+
+    const METADATA: Metadata = Metadata {
+        name: "test soundfont",
+        category: PluginCategory::Instrument,
+        tags: &["soundfont"],
+        params: SoundFontPlayer::PARAMS,
+        pages: &[],
+    };
+
+    fn player() -> Box<SoundFontPlayer> {
+        let soundfont = SoundFont::parse(&soundfont_bytes()).map(Arc::new);
+        SoundFontPlayer::new(&METADATA, soundfont, SampleRate::new(44100.0))
+    }
+
+    #[test]
+    fn parse_reads_presets_and_regions() {
+        let soundfont = SoundFont::parse(&soundfont_bytes()).unwrap();
+        assert_eq!(soundfont.name(), "test font");
+        let presets: Vec<_> = soundfont
+            .presets()
+            .iter()
+            .map(|p| (p.name.as_str(), p.bank, p.program, p.regions.len()))
+            .collect();
+        assert_eq!(presets, vec![("piano", 0, 0, 2), ("drums", 128, 5, 2)]);
+        let piano = &soundfont.presets()[0].regions;
+        assert_eq!(
+            (
+                piano[0].keys,
+                piano[0].start,
+                piano[0].end,
+                piano[0].loop_mode
+            ),
+            ((0, 60), 0, 8, LoopMode::None)
+        );
+        assert_eq!((piano[1].keys, piano[1].start), ((61, 127), 54));
+        let drums = &soundfont.presets()[1].regions[0];
+        assert_eq!(drums.pan, -1.0);
+        assert!((drums.amp - 0.5).abs() < 0.01, "{}", drums.amp);
+        assert!(SoundFont::parse(b"RIFF\x04\x00\x00\x00WAVE").is_err());
+    }
+
+    #[test]
+    fn note_plays_region_for_key() {
+        let mut player = player();
+        assert_eq!(player.preset().unwrap().name, "piano");
+        let note_on = |note| MidiMessage::NoteOn(Channel::Ch1, note, U7::MAX);
+        let out = player.process_to_buffers(10, &[(0, note_on(Note::C4))]);
+        let expected: Vec<f32> = (1..=8)
+            .map(|v| v as f32 / 32.0 * std::f32::consts::FRAC_1_SQRT_2)
+            .chain([0.0, 0.0])
+            .collect();
+        for (left, expected) in out.left.iter().zip(expected.iter()) {
+            assert!((left - expected).abs() < 1e-6, "{:?}", out.left);
+        }
+        assert_eq!(out.left, out.right);
+        assert!(player.voices.is_empty(), "{:?}", player.voices);
+
+        let out = player.process_to_buffers(2, &[(0, note_on(Note::Db4))]);
+        assert!((out.left[1] - 0.25 * std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-2);
+    }
+
+    #[test]
+    fn program_change_selects_preset() {
+        let mut player = player();
+        player.handle_midi(&MidiMessage::ControlChange(
+            Channel::Ch1,
+            ControlFunction::BANK_SELECT,
+            U7::MAX,
+        ));
+        player.handle_midi(&MidiMessage::ProgramChange(
+            Channel::Ch1,
+            U7::from_u8_lossy(5),
+        ));
+        // Bank select only goes up to 127 so the drums are found through the bank param.
+        assert_eq!(player.preset().unwrap().name, "piano");
+        player.set_param(1, 128.0);
+        assert_eq!(player.param(2), 5.0);
+        assert_eq!(player.preset().unwrap().name, "drums");
+        let out = player.process_to_buffers(
+            1,
+            &[(0, MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX))],
+        );
+        assert!(out.left[0] > 0.0);
+        assert!(out.right[0].abs() < 1e-6);
+    }
+
+    #[test]
+    fn missing_soundfont_is_silent() {
+        let mut player = SoundFontPlayer::new(
+            &METADATA,
+            SoundFont::load(Path::new("/does/not/exist.sf2")).map(Arc::new),
+            SampleRate::new(44100.0),
+        );
+        assert!(player.error().is_some());
+        let note_on = MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX);
+        assert!(player.process_to_buffers(4, &[(0, note_on)]).is_zero());
+    }
+}
@@ -1,49 +1,144 @@
 use arrayvec::ArrayVec;
 use bats_dsp::{
-    envelope::{Envelope, EnvelopeParams},
+    envelope::{Envelope, EnvelopeCurve, EnvelopeParams},
+    glide::Glide,
     moog_filter::MoogFilter,
+    noise::Noise,
+    rng::Rng,
     sample_rate::SampleRate,
     sawtooth::Sawtooth,
+    smoothed_value::SmoothedValue,
 };
 use bmidi::{MidiMessage, Note, U7};
 
-
 use super::{
-    metadata::{Param, ParamType},
+    metadata::{Param, ParamPage, ParamType, PluginCategory},
     BatsInstrument, Metadata,
 };
+use crate::tuning::Tuning;
 
 /// A simple Sawtooth plugin.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Toof {
-    /// If the filter is disabled.
+    /// If the filters are disabled for all voices.
     bypass_filter: bool,
     /// True if toof is polyphonic.
     is_polyphonic: bool,
+    /// True if overlapping notes in monophonic mode glide without retriggering the envelope.
+    legato: bool,
+    /// True if the oscillators restart their phase on every note on. Otherwise the oscillators
+    /// free-run and new voices start at a random phase.
+    phase_reset: bool,
+    /// Which held note is played in monophonic mode.
+    note_priority: NotePriority,
+    /// Which voice is stolen when a new note is played in polyphonic mode.
+    voice_stealing: VoiceStealing,
+    /// The notes that are held down, in the order they were pressed, along with their volume.
+    held: ArrayVec<(Note, f32), { Toof::MAX_VOICES }>,
     /// The velocity sensitivity.
     velocity_sensitivity: f32,
+    /// The amount of time to glide between notes in monophonic mode.
+    glide_seconds: f32,
     /// The sample rate.
     sample_rate: SampleRate,
     /// Parameters for envelope.
     envelope: EnvelopeParams,
-    /// The low pass filter.
+    /// Holds the current filter cutoff and resonance. New voices start with a copy of this filter.
     filter: MoogFilter,
     /// The filter cutoff frequency.
-    filter_cutoff: f32,
+    filter_cutoff: SmoothedValue,
     /// The filter resonance.
-    filter_resonance: f32,
+    filter_resonance: SmoothedValue,
+    /// The unison settings shared by all voices.
+    unison: Unison,
+    /// The levels of the sub oscillator and noise that are mixed into each voice.
+    mix: Mix,
     /// The active voices for toof.
-    voices: ArrayVec<ToofVoice, 16>,
+    voices: ArrayVec<ToofVoice, { Toof::MAX_VOICES }>,
+    /// Voices that were stolen and are fading out, along with their fade gain.
+    stolen: ArrayVec<(ToofVoice, SmoothedValue), { Toof::MAX_STOLEN_VOICES }>,
+    /// The noise generator. New voices start with a copy of this noise generator.
+    noise: Noise,
+    /// Picks the starting phase of new voices when `phase_reset` is disabled.
+    rng: Rng,
+    /// Maps notes to the frequencies that the voices play.
+    tuning: Tuning,
+}
+
+/// Stacks several detuned sawtooths within a single voice.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Unison {
+    /// The number of sawtooths to play per note.
+    voices: usize,
+    /// The amount of detune between `0.0` and `1.0`.
+    detune: f32,
+    /// The amount of stereo spread between `0.0` and `1.0`.
+    spread: f32,
+    /// The frequency multiplier for each sawtooth.
+    ratios: [f32; Unison::MAX_VOICES],
+    /// The left and right gain for each sawtooth.
+    gains: [(f32, f32); Unison::MAX_VOICES],
+}
+
+/// Chooses which of the held notes is played in monophonic mode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum NotePriority {
+    /// The most recently pressed note.
+    Last,
+    /// The lowest note.
+    Low,
+    /// The highest note.
+    High,
+}
+
+/// Chooses which voice is stolen when a new note is played in polyphonic mode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum VoiceStealing {
+    /// Steal the oldest voice once all voices are in use.
+    Oldest,
+    /// Steal the quietest voice once all voices are in use.
+    Quietest,
+    /// Reuse the voice that is already playing the same note. Falls back to stealing the oldest
+    /// voice once all voices are in use.
+    SameNote,
+}
+
+/// The shape of the sub oscillator.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum SubShape {
+    /// A square wave.
+    Square,
+    /// A sine wave.
+    Sine,
+}
+
+/// The extra sources that are mixed into each voice.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Mix {
+    /// The level of the sub oscillator, which plays one octave below the note.
+    sub_level: f32,
+    /// The shape of the sub oscillator.
+    sub_shape: SubShape,
+    /// The level of the white noise.
+    noise_level: f32,
 }
 
 /// A single voice for the Toof plugin. Each voice contains a single
-/// note.
+/// note. Unison sawtooths are stacked within the voice so they do not take up extra voices.
 #[derive(Copy, Clone, Debug, PartialEq)]
 struct ToofVoice {
     /// The midi note for the voice.
     note: Note,
-    /// The sawtooth wave.
-    wave: Sawtooth,
+    /// The sawtooth waves. Only the first `Unison::voices` are played.
+    waves: [Sawtooth; Unison::MAX_VOICES],
+    /// Tracks the phase of the sub oscillator. Runs at half the frequency of the note.
+    sub: Sawtooth,
+    /// The noise generator.
+    noise: Noise,
+    /// The low pass filters for the left and right channels.
+    filters: [MoogFilter; 2],
+    /// Slews the frequency of `waves` when the note changes.
+    glide: Glide,
     /// The envelope.
     envelope: Envelope,
     /// The volume of this voice.
@@ -51,22 +146,386 @@ struct ToofVoice {
 }
 
 impl Toof {
+    /// The metadata for the plugin.
+    pub const METADATA: Metadata = Metadata {
+        name: "toof",
+        category: PluginCategory::Instrument,
+        tags: &["synth", "sawtooth", "polyphonic"],
+        params: &[
+            Param {
+                id: 1,
+                name: "bypass filter",
+                param_type: ParamType::Bool,
+                default_value: 0.49,
+                min_value: 0.49,
+                max_value: 0.51,
+            },
+            Param {
+                id: 2,
+                name: "filter cutoff",
+                param_type: ParamType::Frequency,
+                default_value: MoogFilter::DEFAULT_FREQUENCY_CUTOFF,
+                min_value: 50.0,
+                max_value: 9000.0,
+            },
+            Param {
+                id: 3,
+                name: "filter resonance",
+                param_type: ParamType::Percent,
+                default_value: MoogFilter::DEFAULT_RESONANCE,
+                min_value: 0.01,
+                max_value: 0.70,
+            },
+            Param {
+                id: 4,
+                name: "polyphonic",
+                param_type: ParamType::Bool,
+                default_value: 0.49,
+                min_value: 0.49,
+                max_value: 0.51,
+            },
+            Param {
+                id: 5,
+                name: "velocity sensitivity",
+                param_type: ParamType::Percent,
+                default_value: 0.75,
+                min_value: 0.01,
+                max_value: 1.0,
+            },
+            Param {
+                id: 6,
+                name: "attack",
+                param_type: ParamType::Duration,
+                default_value: 0.01,
+                min_value: 0.001,
+                max_value: 2.0,
+            },
+            Param {
+                id: 7,
+                name: "decay",
+                param_type: ParamType::Duration,
+                default_value: 1.0,
+                min_value: 0.001,
+                max_value: 2.0,
+            },
+            Param {
+                id: 8,
+                name: "sustain",
+                param_type: ParamType::Decibel,
+                default_value: 1.0,
+                min_value: 0.001,
+                max_value: 1.0,
+            },
+            Param {
+                id: 9,
+                name: "release",
+                param_type: ParamType::Duration,
+                default_value: 0.1,
+                min_value: 0.003,
+                max_value: 2.0,
+            },
+            Param {
+                id: 10,
+                name: "glide",
+                param_type: ParamType::Duration,
+                default_value: 0.0,
+                min_value: 0.0,
+                max_value: 2.0,
+            },
+            Param {
+                id: 11,
+                name: "unison",
+                param_type: ParamType::Float,
+                default_value: 1.0,
+                min_value: 1.0,
+                max_value: Unison::MAX_VOICES as f32,
+            },
+            Param {
+                id: 12,
+                name: "unison detune",
+                param_type: ParamType::Percent,
+                default_value: 0.2,
+                min_value: 0.0,
+                max_value: 1.0,
+            },
+            Param {
+                id: 13,
+                name: "unison spread",
+                param_type: ParamType::Percent,
+                default_value: 0.5,
+                min_value: 0.0,
+                max_value: 1.0,
+            },
+            Param {
+                id: 14,
+                name: "sub level",
+                param_type: ParamType::Percent,
+                default_value: 0.0,
+                min_value: 0.0,
+                max_value: 1.0,
+            },
+            Param {
+                id: 15,
+                name: "sub sine",
+                param_type: ParamType::Bool,
+                default_value: 0.49,
+                min_value: 0.49,
+                max_value: 0.51,
+            },
+            Param {
+                id: 16,
+                name: "noise level",
+                param_type: ParamType::Percent,
+                default_value: 0.0,
+                min_value: 0.0,
+                max_value: 1.0,
+            },
+            Param {
+                id: 17,
+                name: "legato",
+                param_type: ParamType::Bool,
+                default_value: 0.49,
+                min_value: 0.49,
+                max_value: 0.51,
+            },
+            Param {
+                id: 18,
+                name: "note priority",
+                param_type: ParamType::Float,
+                default_value: 0.0,
+                min_value: 0.0,
+                max_value: 2.0,
+            },
+            Param {
+                id: 19,
+                name: "voice stealing",
+                param_type: ParamType::Float,
+                default_value: 0.0,
+                min_value: 0.0,
+                max_value: 2.0,
+            },
+            Param {
+                id: 20,
+                name: "phase reset",
+                param_type: ParamType::Bool,
+                default_value: 0.5,
+                min_value: 0.49,
+                max_value: 0.51,
+            },
+        ],
+        pages: &[
+            ParamPage {
+                name: "voice",
+                param_ids: &[4, 5, 10, 17, 18, 19, 20],
+            },
+            ParamPage {
+                name: "envelope",
+                param_ids: &[6, 7, 8, 9],
+            },
+            ParamPage {
+                name: "filter",
+                param_ids: &[1, 2, 3],
+            },
+            ParamPage {
+                name: "unison",
+                param_ids: &[11, 12, 13],
+            },
+            ParamPage {
+                name: "mix",
+                param_ids: &[14, 15, 16],
+            },
+        ],
+    };
+
+    /// The maximum number of voices that can play at once.
+    pub const MAX_VOICES: usize = 32;
+
+    /// The maximum number of stolen voices that can fade out at once.
+    const MAX_STOLEN_VOICES: usize = 4;
+
+    /// The amount of time to fade out a stolen voice over.
+    const STEAL_FADE_SECONDS: f32 = 0.003;
+
+    /// The amount of time to ramp filter parameter changes over.
+    const PARAM_SMOOTHING_SECONDS: f32 = 0.01;
+
     /// Create a new Toof plugin with the given sample rate.
     pub fn new(sample_rate: SampleRate) -> Box<Toof> {
-        let envelope = EnvelopeParams::new(sample_rate, 0.005, 0.08, 0.4, 0.05);
+        let mut envelope = EnvelopeParams::new(sample_rate, 0.005, 0.08, 0.4, 0.05);
+        // Loudness is perceived logarithmically so the amp falls off exponentially.
+        envelope.set_attack_curve(EnvelopeCurve::OnePole);
+        envelope.set_decay_curve(EnvelopeCurve::Exponential);
+        envelope.set_release_curve(EnvelopeCurve::Exponential);
         Box::new(Toof {
             bypass_filter: false,
             is_polyphonic: false,
+            legato: false,
+            phase_reset: true,
+            note_priority: NotePriority::Last,
+            voice_stealing: VoiceStealing::Oldest,
+            held: ArrayVec::new(),
             velocity_sensitivity: 0.75,
+            glide_seconds: 0.0,
             sample_rate,
             envelope,
             filter: MoogFilter::new(sample_rate),
-            filter_cutoff: MoogFilter::DEFAULT_FREQUENCY_CUTOFF,
-            filter_resonance: MoogFilter::DEFAULT_RESONANCE,
+            filter_cutoff: SmoothedValue::new(MoogFilter::DEFAULT_FREQUENCY_CUTOFF),
+            filter_resonance: SmoothedValue::new(MoogFilter::DEFAULT_RESONANCE),
+            unison: Unison::new(1, 0.2, 0.5),
+            mix: Mix {
+                sub_level: 0.0,
+                sub_shape: SubShape::Square,
+                noise_level: 0.0,
+            },
             voices: ArrayVec::new(),
+            stolen: ArrayVec::new(),
+            noise: Noise::default(),
+            rng: Rng::default(),
+            tuning: Tuning::default(),
         })
     }
 
+    /// Advance the smoothed filter parameters by a single sample and update the filter if they
+    /// changed.
+    fn smooth_filter_params(&mut self) {
+        if self.filter_cutoff.is_smoothing() || self.filter_resonance.is_smoothing() {
+            self.filter.set_cutoff(
+                self.sample_rate,
+                self.filter_cutoff.next_value(),
+                self.filter_resonance.next_value(),
+            );
+            let stolen = self.stolen.iter_mut().map(|(v, _)| v);
+            for voice in self.voices.iter_mut().chain(stolen) {
+                for filter in voice.filters.iter_mut() {
+                    filter.copy_cutoff(&self.filter);
+                }
+            }
+        }
+    }
+
+    /// Update the unison settings and retune any playing voices.
+    fn set_unison(&mut self, unison: Unison) {
+        self.unison = unison;
+        for voice in self.voices.iter_mut() {
+            voice.set_frequency(self.sample_rate, voice.glide.frequency(), &self.unison);
+        }
+    }
+
+    /// The frequency of `note` in the current tuning. Notes that are not mapped by the tuning are
+    /// never played so they have a frequency of `0.0`.
+    fn frequency(&self, note: Note) -> f32 {
+        self.tuning.frequency(note).unwrap_or_default()
+    }
+
+    /// Mark `note` as held. If too many notes are held, the oldest one is forgotten.
+    fn hold(&mut self, note: Note, volume: f32) {
+        self.held.retain(|(n, _)| *n != note);
+        if self.held.is_full() {
+            self.held.remove(0);
+        }
+        self.held.push((note, volume));
+    }
+
+    /// Get the held note and its volume that should play in monophonic mode.
+    fn mono_note(&self) -> Option<(Note, f32)> {
+        match self.note_priority {
+            NotePriority::Last => self.held.last(),
+            NotePriority::Low => self.held.iter().min_by_key(|(n, _)| *n),
+            NotePriority::High => self.held.iter().max_by_key(|(n, _)| *n),
+        }
+        .copied()
+    }
+
+    /// Play `note` on the monophonic voice. The envelope is retriggered unless legato is enabled
+    /// and the voice is still held.
+    fn play_mono(&mut self, note: Note, volume: f32) {
+        let frequency = self.frequency(note);
+        match self.voices.first_mut() {
+            None => {
+                let voice = self.new_voice(note, volume);
+                self.voices.push(voice);
+            }
+            Some(v) => {
+                let retrigger = !self.legato || v.envelope.is_released();
+                v.set_note(
+                    self.sample_rate,
+                    note,
+                    frequency,
+                    self.glide_seconds,
+                    &self.unison,
+                    retrigger.then_some(volume),
+                );
+                if retrigger && self.phase_reset {
+                    v.set_phase(|| 0.0);
+                }
+            }
+        }
+    }
+
+    /// Create a new voice for `note`. The oscillators start at a random phase unless
+    /// `phase_reset` is enabled.
+    fn new_voice(&mut self, note: Note, volume: f32) -> ToofVoice {
+        let mut voice = ToofVoice::new(
+            self.sample_rate,
+            note,
+            self.frequency(note),
+            volume,
+            &self.unison,
+            self.filter,
+            self.noise,
+        );
+        if !self.phase_reset {
+            voice.set_phase(|| self.rng.next_f32());
+        }
+        voice
+    }
+
+    /// Make room for a new polyphonic voice that plays `note`. Stolen voices are faded out
+    /// instead of being cut off to avoid clicks.
+    fn make_room_for(&mut self, note: Note) {
+        if self.voice_stealing == VoiceStealing::SameNote {
+            if let Some(idx) = self.voices.iter().position(|v| v.note == note) {
+                self.steal(idx);
+                return;
+            }
+        }
+        if !self.voices.is_full() {
+            return;
+        }
+        self.voices.retain(|v| v.envelope.is_active());
+        if !self.voices.is_full() {
+            return;
+        }
+        let idx = match self.voice_stealing {
+            VoiceStealing::Oldest | VoiceStealing::SameNote => {
+                // Steal the oldest released voice before stealing a held one.
+                self.voices
+                    .iter()
+                    .position(|v| v.envelope.is_released())
+                    .unwrap_or(0)
+            }
+            VoiceStealing::Quietest => self
+                .voices
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.loudness().total_cmp(&b.loudness()))
+                .map(|(idx, _)| idx)
+                .unwrap_or(0),
+        };
+        self.steal(idx);
+    }
+
+    /// Remove the voice at `idx` and fade it out.
+    fn steal(&mut self, idx: usize) {
+        let voice = self.voices.remove(idx);
+        if self.stolen.is_full() {
+            self.stolen.remove(0);
+        }
+        let mut fade = SmoothedValue::new(1.0);
+        fade.set_target_with_duration(self.sample_rate, 0.0, Toof::STEAL_FADE_SECONDS);
+        self.stolen.push((voice, fade));
+    }
+
     fn velocity_to_volume(&self, velocity: U7) -> f32 {
         let velocity = u8::from(velocity) as f32 / u8::from(U7::MAX) as f32;
         velocity * self.velocity_sensitivity + (1.0 - self.velocity_sensitivity)
@@ -76,126 +535,85 @@ impl Toof {
 impl BatsInstrument for Toof {
     /// The name of the plugin.
     fn metadata(&self) -> &'static Metadata {
-        &Metadata {
-            name: "toof",
-            params: &[
-                Param {
-                    id: 1,
-                    name: "bypass filter",
-                    param_type: ParamType::Bool,
-                    default_value: 0.49,
-                    min_value: 0.49,
-                    max_value: 0.51,
-                },
-                Param {
-                    id: 2,
-                    name: "filter cutoff",
-                    param_type: ParamType::Frequency,
-                    default_value: MoogFilter::DEFAULT_FREQUENCY_CUTOFF,
-                    min_value: 50.0,
-                    max_value: 9000.0,
-                },
-                Param {
-                    id: 3,
-                    name: "filter resonance",
-                    param_type: ParamType::Percent,
-                    default_value: MoogFilter::DEFAULT_RESONANCE,
-                    min_value: 0.01,
-                    max_value: 0.70,
-                },
-                Param {
-                    id: 4,
-                    name: "polyphonic",
-                    param_type: ParamType::Bool,
-                    default_value: 0.49,
-                    min_value: 0.49,
-                    max_value: 0.51,
-                },
-                Param {
-                    id: 5,
-                    name: "velocity sensitivity",
-                    param_type: ParamType::Percent,
-                    default_value: 0.75,
-                    min_value: 0.01,
-                    max_value: 1.0,
-                },
-                Param {
-                    id: 6,
-                    name: "attack",
-                    param_type: ParamType::Duration,
-                    default_value: 0.01,
-                    min_value: 0.001,
-                    max_value: 2.0,
-                },
-                Param {
-                    id: 7,
-                    name: "decay",
-                    param_type: ParamType::Duration,
-                    default_value: 1.0,
-                    min_value: 0.001,
-                    max_value: 2.0,
-                },
-                Param {
-                    id: 8,
-                    name: "sustain",
-                    param_type: ParamType::Decibel,
-                    default_value: 1.0,
-                    min_value: 0.001,
-                    max_value: 1.0,
-                },
-                Param {
-                    id: 9,
-                    name: "release",
-                    param_type: ParamType::Duration,
-                    default_value: 0.1,
-                    min_value: 0.003,
-                    max_value: 2.0,
-                },
-            ],
-        }
+        &Toof::METADATA
     }
 
     /// Handle the processing and output to a single audio output.
     fn process(&mut self) -> (f32, f32) {
-        let v = self
-            .voices
-            .iter_mut()
-            .map(|v| v.next_sample(&self.envelope))
-            .sum();
-        if self.bypass_filter {
-            (v, v)
-        } else {
-            let v = self.filter.process(v);
-            (v, v)
-        }
+        self.smooth_filter_params();
+        let (l, r) = self.voices.iter_mut().fold((0.0, 0.0), |(l, r), v| {
+            let (vl, vr) = v.next_sample(
+                self.sample_rate,
+                &self.envelope,
+                &self.unison,
+                &self.mix,
+                self.bypass_filter,
+            );
+            (l + vl, r + vr)
+        });
+        self.stolen.iter_mut().fold((l, r), |(l, r), (v, fade)| {
+            let (vl, vr) = v.next_sample(
+                self.sample_rate,
+                &self.envelope,
+                &self.unison,
+                &self.mix,
+                self.bypass_filter,
+            );
+            let fade = fade.next_value();
+            (l + fade * vl, r + fade * vr)
+        })
     }
 
     /// Handle a midi event.
     fn handle_midi(&mut self, msg: &MidiMessage) {
         match msg {
             MidiMessage::NoteOff(_, note, _) | MidiMessage::NoteOn(_, note, U7::MIN) => {
+                self.held.retain(|(n, _)| n != note);
+                let is_mono_note = self
+                    .voices
+                    .first()
+                    .is_some_and(|v| v.note == *note && !v.envelope.is_released());
+                if !self.is_polyphonic && is_mono_note {
+                    // Fall back to the next held note.
+                    if let Some((next, volume)) = self.mono_note() {
+                        self.play_mono(next, volume);
+                        return;
+                    }
+                }
                 for v in self.voices.iter_mut() {
                     if v.note == *note {
                         v.envelope.release(&self.envelope);
                     }
                 }
             }
+            // Notes that are not mapped by the tuning are silent.
+            MidiMessage::NoteOn(_, note, _) if self.tuning.frequency(*note).is_none() => (),
             MidiMessage::NoteOn(_, note, velocity) => {
                 let volume = self.velocity_to_volume(*velocity);
-                if self.is_polyphonic || self.voices.is_empty() {
-                    if self.voices.is_full() {
-                        self.voices.retain(|v| v.envelope.is_active());
-                        if self.voices.is_full() {
-                            self.voices.remove(0);
+                self.hold(*note, volume);
+                if !self.is_polyphonic {
+                    let playing = self
+                        .voices
+                        .first()
+                        .filter(|v| !v.envelope.is_released())
+                        .map(|v| v.note);
+                    match self.mono_note() {
+                        Some((target, volume)) if target == *note || playing != Some(target) => {
+                            self.play_mono(target, volume)
                         }
+                        _ => (),
                     }
-                    self.voices
-                        .push(ToofVoice::new(self.sample_rate, *note, volume));
                 } else {
-                    self.voices[0].set_note(self.sample_rate, *note, volume);
+                    self.make_room_for(*note);
+                    let voice = self.new_voice(*note, volume);
+                    self.voices.push(voice);
                 }
             }
-            MidiMessage::Reset => self.voices.clear(),
+            MidiMessage::Reset => {
+                self.voices.clear();
+                self.stolen.clear();
+                self.held.clear();
+            }
             _ => (),
         }
     }
@@ -210,8 +628,8 @@ impl BatsInstrument for Toof {
                     0.49
                 }
             }
-            2 => self.filter_cutoff,
-            3 => self.filter_resonance,
+            2 => self.filter_cutoff.target(),
+            3 => self.filter_resonance.target(),
             4 => {
                 if self.is_polyphonic {
                     0.51
@@ -224,6 +642,43 @@ impl BatsInstrument for Toof {
             7 => self.envelope.decay(self.sample_rate),
             8 => self.envelope.sustain(),
             9 => self.envelope.release(self.sample_rate),
+            10 => self.glide_seconds,
+            11 => self.unison.voices as f32,
+            12 => self.unison.detune,
+            13 => self.unison.spread,
+            14 => self.mix.sub_level,
+            15 => {
+                if self.mix.sub_shape == SubShape::Sine {
+                    0.51
+                } else {
+                    0.49
+                }
+            }
+            16 => self.mix.noise_level,
+            17 => {
+                if self.legato {
+                    0.51
+                } else {
+                    0.49
+                }
+            }
+            18 => match self.note_priority {
+                NotePriority::Last => 0.0,
+                NotePriority::Low => 1.0,
+                NotePriority::High => 2.0,
+            },
+            19 => match self.voice_stealing {
+                VoiceStealing::Oldest => 0.0,
+                VoiceStealing::Quietest => 1.0,
+                VoiceStealing::SameNote => 2.0,
+            },
+            20 => {
+                if self.phase_reset {
+                    0.51
+                } else {
+                    0.49
+                }
+            }
             _ => 0.0,
         }
     }
@@ -234,16 +689,16 @@ impl BatsInstrument for Toof {
             1 => {
                 self.bypass_filter = value >= 0.5;
             }
-            2 => {
-                self.filter_cutoff = value;
-                self.filter
-                    .set_cutoff(self.sample_rate, self.filter_cutoff, self.filter_resonance);
-            }
-            3 => {
-                self.filter_resonance = value;
-                self.filter
-                    .set_cutoff(self.sample_rate, self.filter_cutoff, self.filter_resonance);
-            }
+            2 => self.filter_cutoff.set_target_with_duration(
+                self.sample_rate,
+                value,
+                Toof::PARAM_SMOOTHING_SECONDS,
+            ),
+            3 => self.filter_resonance.set_target_with_duration(
+                self.sample_rate,
+                value,
+                Toof::PARAM_SMOOTHING_SECONDS,
+            ),
             4 => {
                 self.is_polyphonic = value >= 0.5;
             }
@@ -252,6 +707,39 @@ impl BatsInstrument for Toof {
             7 => self.envelope.set_decay(self.sample_rate, value),
             8 => self.envelope.set_sustain(self.sample_rate, value),
             9 => self.envelope.set_release(self.sample_rate, value),
+            10 => self.glide_seconds = value,
+            11 => self.set_unison(Unison::new(
+                value.round() as usize,
+                self.unison.detune,
+                self.unison.spread,
+            )),
+            12 => self.set_unison(Unison::new(self.unison.voices, value, self.unison.spread)),
+            13 => self.set_unison(Unison::new(self.unison.voices, self.unison.detune, value)),
+            14 => self.mix.sub_level = value,
+            15 => {
+                self.mix.sub_shape = if value >= 0.5 {
+                    SubShape::Sine
+                } else {
+                    SubShape::Square
+                };
+            }
+            16 => self.mix.noise_level = value,
+            17 => self.legato = value >= 0.5,
+            18 => {
+                self.note_priority = match value.round() as i32 {
+                    i32::MIN..=0 => NotePriority::Last,
+                    1 => NotePriority::Low,
+                    _ => NotePriority::High,
+                };
+            }
+            19 => {
+                self.voice_stealing = match value.round() as i32 {
+                    i32::MIN..=0 => VoiceStealing::Oldest,
+                    1 => VoiceStealing::Quietest,
+                    _ => VoiceStealing::SameNote,
+                };
+            }
+            20 => self.phase_reset = value >= 0.5,
             _ => (),
         }
     }
@@ -259,33 +747,213 @@ impl BatsInstrument for Toof {
     /// Clean up any inactive voices.
     fn batch_cleanup(&mut self) {
         self.voices.retain(|v| v.envelope.is_active());
+        self.stolen.retain(|(_, fade)| fade.is_smoothing());
+    }
+
+    /// Recompute the envelope, filter, and oscillators for the new sample rate.
+    fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        let old = self.sample_rate;
+        let attack = self.envelope.attack(old);
+        let decay = self.envelope.decay(old);
+        let release = self.envelope.release(old);
+        self.envelope.set_attack(sample_rate, attack);
+        self.envelope.set_decay(sample_rate, decay);
+        self.envelope.set_release(sample_rate, release);
+        self.sample_rate = sample_rate;
+        self.filter.set_cutoff(
+            sample_rate,
+            self.filter_cutoff.value(),
+            self.filter_resonance.value(),
+        );
+        // Stolen voices are about to fade out so they are not worth retuning.
+        self.stolen.clear();
+        for voice in self.voices.iter_mut() {
+            for filter in voice.filters.iter_mut() {
+                filter.copy_cutoff(&self.filter);
+            }
+            voice.glide.set_frequency(voice.glide.target());
+            voice.set_frequency(sample_rate, voice.glide.frequency(), &self.unison);
+        }
+    }
+
+    /// Reseed the noise generator and the starting phases of free-running voices. Only affects
+    /// notes that are played after the seed is set.
+    fn set_seed(&mut self, seed: u64) {
+        let mut rng = Rng::new(seed);
+        self.noise = Noise::from_rng(&mut rng);
+        self.rng = rng;
+    }
+
+    /// Retune the playing voices if the tuning changed. Voices whose notes are not mapped by the
+    /// new tuning are released.
+    fn set_tuning(&mut self, tuning: &Tuning) {
+        if self.tuning == *tuning {
+            return;
+        }
+        self.tuning = *tuning;
+        self.held.retain(|(n, _)| tuning.frequency(*n).is_some());
+        for voice in self.voices.iter_mut() {
+            match tuning.frequency(voice.note) {
+                Some(frequency) => {
+                    voice.glide.set_frequency(frequency);
+                    voice.set_frequency(self.sample_rate, frequency, &self.unison);
+                }
+                None => voice.envelope.release(&self.envelope),
+            }
+        }
+    }
+}
+
+impl Unison {
+    /// The maximum number of sawtooths that can be stacked per note.
+    const MAX_VOICES: usize = 7;
+
+    /// The detune, in semitones, of the outermost sawtooths when `detune` is `1.0`.
+    const MAX_DETUNE_SEMITONES: f32 = 0.5;
+
+    /// Create new unison settings. `voices` is clamped to `[1, MAX_VOICES]`.
+    fn new(voices: usize, detune: f32, spread: f32) -> Unison {
+        let voices = voices.clamp(1, Unison::MAX_VOICES);
+        let mut ratios = [1.0; Unison::MAX_VOICES];
+        let mut gains = [(1.0, 1.0); Unison::MAX_VOICES];
+        // Keep the perceived loudness roughly constant as more sawtooths are stacked.
+        let amp = 1.0 / (voices as f32).sqrt();
+        for idx in 0..voices {
+            // The position of the sawtooth within [-1.0, 1.0].
+            let position = if voices == 1 {
+                0.0
+            } else {
+                2.0 * idx as f32 / (voices - 1) as f32 - 1.0
+            };
+            let semitones = position * detune * Unison::MAX_DETUNE_SEMITONES;
+            ratios[idx] = (semitones / 12.0).exp2();
+            let pan = position * spread;
+            gains[idx] = (amp * (1.0 - pan.max(0.0)), amp * (1.0 + pan.min(0.0)));
+        }
+        Unison {
+            voices,
+            detune,
+            spread,
+            ratios,
+            gains,
+        }
     }
 }
 
 impl ToofVoice {
     /// Create a new Toof voice.
-    fn new(sample_rate: SampleRate, note: Note, volume: f32) -> ToofVoice {
-        ToofVoice {
+    fn new(
+        sample_rate: SampleRate,
+        note: Note,
+        frequency: f32,
+        volume: f32,
+        unison: &Unison,
+        filter: MoogFilter,
+        noise: Noise,
+    ) -> ToofVoice {
+        let mut voice = ToofVoice {
             note,
-            wave: Sawtooth::new(sample_rate, note.to_freq_f32()),
+            waves: [Sawtooth::new(sample_rate, frequency); Unison::MAX_VOICES],
+            sub: Sawtooth::new(sample_rate, 0.5 * frequency),
+            noise,
+            filters: [filter; 2],
+            glide: Glide::new(frequency),
             envelope: Envelope::new(),
             volume,
+        };
+        voice.set_frequency(sample_rate, frequency, unison);
+        voice
+    }
+
+    /// Set the phase of every oscillator to the value returned by `phase`.
+    fn set_phase(&mut self, mut phase: impl FnMut() -> f32) {
+        for wave in self.waves.iter_mut() {
+            wave.set_phase(phase());
         }
+        self.sub.set_phase(phase());
+    }
+
+    /// The current loudness of the voice.
+    fn loudness(&self) -> f32 {
+        self.volume * self.envelope.amp()
     }
 
-    /// Set a new note for the current voice.
-    fn set_note(&mut self, sample_rate: SampleRate, note: Note, volume: f32) {
+    /// Set a new note for the current voice that plays at `frequency`. The frequency glides to the
+    /// new note over `glide_seconds`. If `retrigger` is set, then the envelope restarts at the
+    /// volume in `retrigger`. Otherwise the envelope and volume continue from the previous note.
+    fn set_note(
+        &mut self,
+        sample_rate: SampleRate,
+        note: Note,
+        frequency: f32,
+        glide_seconds: f32,
+        unison: &Unison,
+        retrigger: Option<f32>,
+    ) {
         self.note = note;
-        self.wave.set_frequency(sample_rate, note.to_freq_f32());
-        self.envelope = Envelope::new();
-        self.volume = volume;
+        self.glide.set_target(sample_rate, frequency, glide_seconds);
+        self.set_frequency(sample_rate, self.glide.frequency(), unison);
+        if let Some(volume) = retrigger {
+            self.envelope = Envelope::new();
+            self.volume = volume;
+        }
     }
 
-    /// Retrieve the next sample.
-    fn next_sample(&mut self, envelope: &EnvelopeParams) -> f32 {
-        let wave_amp = self.wave.next_sample();
-        let env_amp = self.envelope.next_sample(envelope);
-        self.volume * wave_amp * env_amp
+    /// Set the frequency of all the sawtooths, detuning them around `frequency`.
+    fn set_frequency(&mut self, sample_rate: SampleRate, frequency: f32, unison: &Unison) {
+        for (wave, ratio) in self.waves.iter_mut().zip(unison.ratios.iter()) {
+            wave.set_frequency(sample_rate, frequency * ratio);
+        }
+        self.sub.set_frequency(sample_rate, 0.5 * frequency);
+    }
+
+    /// Retrieve the next left and right samples.
+    fn next_sample(
+        &mut self,
+        sample_rate: SampleRate,
+        envelope: &EnvelopeParams,
+        unison: &Unison,
+        mix: &Mix,
+        bypass_filter: bool,
+    ) -> (f32, f32) {
+        if self.glide.is_gliding() {
+            let frequency = self.glide.next_frequency();
+            for (wave, ratio) in self.waves[..unison.voices]
+                .iter_mut()
+                .zip(unison.ratios.iter())
+            {
+                wave.set_frequency(sample_rate, frequency * ratio);
+            }
+            self.sub.set_frequency(sample_rate, 0.5 * frequency);
+        }
+        let (left, right) = self.waves[..unison.voices]
+            .iter_mut()
+            .zip(unison.gains.iter())
+            .fold((0.0, 0.0), |(l, r), (wave, (gl, gr))| {
+                let v = wave.next_sample();
+                (l + v * gl, r + v * gr)
+            });
+        let (left, right) = if mix.sub_level > 0.0 || mix.noise_level > 0.0 {
+            let phase = self.sub.next_sample();
+            let sub = match mix.sub_shape {
+                SubShape::Square => phase.signum(),
+                SubShape::Sine => (std::f32::consts::PI * phase).sin(),
+            };
+            let v = mix.sub_level * sub + mix.noise_level * self.noise.next_sample();
+            (left + v, right + v)
+        } else {
+            (left, right)
+        };
+        let (left, right) = if bypass_filter {
+            (left, right)
+        } else {
+            (
+                self.filters[0].process(left),
+                self.filters[1].process(right),
+            )
+        };
+        let amp = self.volume * self.envelope.next_sample(envelope);
+        (left * amp, right * amp)
     }
 }
 
@@ -298,6 +966,17 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn every_param_is_on_exactly_one_page() {
+        let metadata = &Toof::METADATA;
+        for param in metadata.params {
+            let pages = (0..metadata.page_count())
+                .filter(|page| metadata.page_params(*page).any(|p| p.id == param.id))
+                .count();
+            assert_eq!(pages, 1, "{} is on {pages} pages", param.name);
+        }
+    }
+
     #[test]
     fn note_press_produces_audio() {
         let mut s = Toof::new(SampleRate::new(44100.0));
@@ -325,8 +1004,8 @@ mod tests {
         let mut toof = Toof::new(SampleRate::new(44100.0));
         toof.bypass_filter = true;
         toof.is_polyphonic = true;
-        let signal_a = toof.clone().process_to_buffers(100, &[note_a.clone()]);
-        let signal_b = toof.clone().process_to_buffers(100, &[note_b.clone()]);
+        let signal_a = toof.clone().process_to_buffers(100, &[note_a]);
+        let signal_b = toof.clone().process_to_buffers(100, &[note_b]);
         let signal_summed = toof.clone().process_to_buffers(100, &[note_a, note_b]);
         assert_eq!(
             signal_summed.left,
@@ -351,8 +1030,8 @@ mod tests {
     #[test]
     fn clone_capacity_is_maintained() {
         let toof = Toof::new(SampleRate::new(44100.0));
-        assert_eq!(toof.voices.capacity(), 16);
-        assert_eq!(toof.clone().voices.capacity(), 16);
+        assert_eq!(toof.voices.capacity(), Toof::MAX_VOICES);
+        assert_eq!(toof.clone().voices.capacity(), Toof::MAX_VOICES);
     }
 
     #[test]
@@ -425,4 +1104,374 @@ mod tests {
         assert_eq!(buffers.len(), 44100);
         assert_eq!(buffers.left, buffers.right);
     }
+
+    #[test]
+    fn monophonic_glide_slews_to_new_note() {
+        let sample_rate = SampleRate::new(44100.0);
+        let mut toof = Toof::new(sample_rate);
+        toof.set_param_by_name("glide", 0.5).unwrap();
+        toof.process_to_buffers(
+            10,
+            &[(0, MidiMessage::NoteOn(Channel::Ch1, Note::A3, U7::MAX))],
+        );
+        toof.process_to_buffers(
+            10,
+            &[(0, MidiMessage::NoteOn(Channel::Ch1, Note::A4, U7::MAX))],
+        );
+        let frequency = toof.voices[0].glide.frequency();
+        assert!(
+            Note::A3.to_freq_f32() < frequency && frequency < Note::A4.to_freq_f32(),
+            "{frequency}"
+        );
+        assert_eq!(toof.voices[0].glide.target(), Note::A4.to_freq_f32());
+    }
+
+    #[test]
+    fn notes_play_at_tuning_frequency() {
+        // A whole tone scale on the white keys with A4 at 432Hz.
+        let scl = "whole tone\n6\n200.0\n400.0\n600.0\n800.0\n1000.0\n2/1\n";
+        let kbm = "12\n0\n127\n60\n69\n432.0\n6\n0\nx\n1\nx\n2\n3\nx\n4\nx\n5\nx\nx\n";
+        let tuning = Tuning::from_scala("whole tone", scl, Some(kbm)).unwrap();
+        let mut toof = Toof::new(SampleRate::new(44100.0));
+        toof.set_param_by_name("polyphonic", 1.0).unwrap();
+        toof.process_to_buffers(1, &[(0, note_on(Note::A4))]);
+        assert_eq!(toof.voices[0].glide.target(), Note::A4.to_freq_f32());
+
+        toof.set_tuning(&tuning);
+        assert_eq!(toof.voices[0].glide.frequency(), 432.0);
+        toof.process_to_buffers(1, &[(0, note_on(Note::C4)), (0, note_on(Note::Db4))]);
+        assert_eq!(toof.voices.len(), 2);
+        assert_eq!(
+            toof.voices[1].glide.target(),
+            tuning.frequency(Note::C4).unwrap()
+        );
+
+        toof.set_tuning(&Tuning::default());
+        assert_eq!(toof.voices[1].glide.target(), Note::C4.to_freq_f32());
+    }
+
+    fn note_on(note: Note) -> MidiMessage {
+        MidiMessage::NoteOn(Channel::Ch1, note, U7::MAX)
+    }
+
+    fn note_off(note: Note) -> MidiMessage {
+        MidiMessage::NoteOff(Channel::Ch1, note, U7::MIN)
+    }
+
+    #[test]
+    fn legato_does_not_retrigger_overlapping_notes() {
+        for legato in [false, true] {
+            let mut toof = Toof::new(SampleRate::new(44100.0));
+            toof.set_param_by_name("legato", if legato { 1.0 } else { 0.0 })
+                .unwrap();
+            toof.process_to_buffers(100, &[(0, note_on(Note::A3))]);
+            toof.handle_midi(&note_on(Note::A4));
+            assert_eq!(toof.voices.len(), 1);
+            assert_eq!(toof.voices[0].note, Note::A4);
+            assert_eq!(toof.voices[0].envelope == Envelope::new(), !legato);
+        }
+    }
+
+    #[test]
+    fn phase_reset_restarts_oscillators_on_retrigger() {
+        for phase_reset in [false, true] {
+            let mut toof = Toof::new(SampleRate::new(44100.0));
+            toof.set_param_by_name("phase reset", if phase_reset { 1.0 } else { 0.0 })
+                .unwrap();
+            toof.process_to_buffers(100, &[(0, note_on(Note::A3))]);
+            toof.handle_midi(&note_on(Note::A3));
+            let phase = toof.voices[0].waves[0].phase();
+            assert_eq!(phase == 0.0, phase_reset, "phase_reset={phase_reset}");
+        }
+    }
+
+    #[test]
+    fn free_running_voices_start_at_random_phase() {
+        let mut toof = Toof::new(SampleRate::new(44100.0));
+        toof.set_param_by_name("polyphonic", 1.0).unwrap();
+        toof.set_param_by_name("phase reset", 0.0).unwrap();
+        toof.handle_midi(&note_on(Note::A3));
+        toof.handle_midi(&note_on(Note::A4));
+        let first = toof.voices[0].waves[0].phase();
+        let second = toof.voices[1].waves[0].phase();
+        assert_ne!(first, 0.0);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn released_mono_note_falls_back_to_held_note() {
+        let mut toof = Toof::new(SampleRate::new(44100.0));
+        toof.handle_midi(&note_on(Note::A3));
+        toof.handle_midi(&note_on(Note::A4));
+        toof.handle_midi(&note_off(Note::A4));
+        assert_eq!(toof.voices[0].note, Note::A3);
+        assert!(!toof.voices[0].envelope.is_released());
+        toof.handle_midi(&note_off(Note::A3));
+        assert!(toof.voices[0].envelope.is_released());
+    }
+
+    #[test]
+    fn note_priority_chooses_held_note() {
+        for (priority, expected) in [(0.0, Note::C4), (1.0, Note::A3), (2.0, Note::A4)] {
+            let mut toof = Toof::new(SampleRate::new(44100.0));
+            toof.set_param_by_name("note priority", priority).unwrap();
+            for note in [Note::A3, Note::A4, Note::C4] {
+                toof.handle_midi(&note_on(note));
+            }
+            assert_eq!(toof.voices.len(), 1);
+            assert_eq!(toof.voices[0].note, expected, "priority {priority}");
+        }
+    }
+
+    #[test]
+    fn filter_cutoff_changes_are_smoothed() {
+        let mut toof = Toof::new(SampleRate::new(44100.0));
+        toof.set_param_by_name("filter cutoff", 100.0).unwrap();
+        assert_eq!(toof.param(2), 100.0);
+        assert!(toof.filter_cutoff.is_smoothing());
+        toof.process_to_buffers(44100, &[]);
+        assert!(!toof.filter_cutoff.is_smoothing());
+        assert_eq!(toof.filter, {
+            let mut f = MoogFilter::new(SampleRate::new(44100.0));
+            f.set_cutoff(
+                SampleRate::new(44100.0),
+                100.0,
+                MoogFilter::DEFAULT_RESONANCE,
+            );
+            f
+        });
+    }
+
+    #[test]
+    fn set_sample_rate_keeps_params_and_pitch() {
+        let mut toof = Toof::new(SampleRate::new(44100.0));
+        toof.set_param(6, 0.2);
+        toof.set_param(9, 0.3);
+        let params_before: Vec<_> = (1..=16).map(|id| toof.param(id)).collect();
+        toof.set_sample_rate(SampleRate::new(22050.0));
+        let params_after: Vec<_> = (1..=16).map(|id| toof.param(id)).collect();
+        for (before, after) in params_before.iter().zip(params_after.iter()) {
+            assert!((before - after).abs() < 1e-4, "{before} != {after}");
+        }
+
+        // Should sound the same as a toof that was created with the new sample rate.
+        let mut reference = Toof::new(SampleRate::new(22050.0));
+        reference.set_param(6, 0.2);
+        reference.set_param(9, 0.3);
+        let note_on = [(0, MidiMessage::NoteOn(Channel::Ch1, Note::A4, U7::MAX))];
+        assert_eq!(
+            toof.process_to_buffers(512, &note_on),
+            reference.process_to_buffers(512, &note_on)
+        );
+    }
+
+    #[test]
+    fn single_unison_voice_is_centered() {
+        let mut toof = Toof::new(SampleRate::new(44100.0));
+        toof.set_param_by_name("unison spread", 1.0).unwrap();
+        let buffers = toof.process_to_buffers(
+            512,
+            &[(0, MidiMessage::NoteOn(Channel::Ch1, Note::A4, U7::MAX))],
+        );
+        assert_ne!(buffers.left, vec![0f32; 512]);
+        assert_eq!(buffers.left, buffers.right);
+    }
+
+    #[test]
+    fn unison_spread_widens_stereo_image() {
+        let note_on = [(0, MidiMessage::NoteOn(Channel::Ch1, Note::A4, U7::MAX))];
+        let mut toof = Toof::new(SampleRate::new(44100.0));
+        toof.set_param_by_name("unison", 3.0).unwrap();
+        toof.set_param_by_name("unison spread", 0.0).unwrap();
+        let narrow = toof.clone().process_to_buffers(512, &note_on);
+        assert_eq!(narrow.left, narrow.right);
+
+        toof.set_param_by_name("unison spread", 1.0).unwrap();
+        let wide = toof.process_to_buffers(512, &note_on);
+        assert_ne!(wide.left, wide.right);
+    }
+
+    #[test]
+    fn unison_does_not_use_extra_voices() {
+        let mut toof = Toof::new(SampleRate::new(44100.0));
+        toof.set_param_by_name("polyphonic", 1.0).unwrap();
+        toof.set_param_by_name("unison", 7.0).unwrap();
+        let notes: Vec<_> = (0..16)
+            .map(|n| {
+                let note = Note::from_u8_lossy(60 + n);
+                (0, MidiMessage::NoteOn(Channel::Ch1, note, U7::MAX))
+            })
+            .collect();
+        toof.process_to_buffers(16, &notes);
+        assert_eq!(toof.voices.len(), 16);
+    }
+
+    #[test]
+    fn full_voice_pool_steals_released_voice_first() {
+        let mut toof = Toof::new(SampleRate::new(44100.0));
+        toof.set_param_by_name("polyphonic", 1.0).unwrap();
+        toof.set_param_by_name("release", 2.0).unwrap();
+        let note_on = |n| {
+            (
+                0,
+                MidiMessage::NoteOn(Channel::Ch1, Note::from_u8_lossy(n), U7::MAX),
+            )
+        };
+        let mut midi: Vec<_> = (60..60 + Toof::MAX_VOICES as u8).map(note_on).collect();
+        midi.push((
+            0,
+            MidiMessage::NoteOff(Channel::Ch1, Note::from_u8_lossy(65), U7::MIN),
+        ));
+        midi.push(note_on(110));
+        toof.process_to_buffers(16, &midi);
+        let notes: Vec<_> = toof.voices.iter().map(|v| u8::from(v.note)).collect();
+        assert_eq!(notes.len(), Toof::MAX_VOICES);
+        assert!(notes.contains(&60), "{notes:?}");
+        assert!(!notes.contains(&65), "{notes:?}");
+        assert!(notes.contains(&110), "{notes:?}");
+    }
+
+    #[test]
+    fn quietest_voice_is_stolen() {
+        let mut toof = Toof::new(SampleRate::new(44100.0));
+        toof.set_param_by_name("polyphonic", 1.0).unwrap();
+        toof.set_param_by_name("voice stealing", 1.0).unwrap();
+        let midi: Vec<_> = (0..Toof::MAX_VOICES as u8)
+            .map(|n| {
+                let velocity = if n == 10 {
+                    U7::from_u8_lossy(1)
+                } else {
+                    U7::MAX
+                };
+                let note = Note::from_u8_lossy(60 + n);
+                (0, MidiMessage::NoteOn(Channel::Ch1, note, velocity))
+            })
+            .collect();
+        toof.process_to_buffers(1000, &midi);
+        toof.process_to_buffers(16, &[(0, note_on(Note::from_u8_lossy(110)))]);
+        let notes: Vec<_> = toof.voices.iter().map(|v| u8::from(v.note)).collect();
+        assert_eq!(notes.len(), Toof::MAX_VOICES);
+        assert!(notes.contains(&60), "{notes:?}");
+        assert!(!notes.contains(&70), "{notes:?}");
+        assert!(notes.contains(&110), "{notes:?}");
+    }
+
+    #[test]
+    fn same_note_reuses_voice() {
+        for (voice_stealing, expected_voices) in [(0.0, 2), (2.0, 1)] {
+            let mut toof = Toof::new(SampleRate::new(44100.0));
+            toof.set_param_by_name("polyphonic", 1.0).unwrap();
+            toof.set_param_by_name("voice stealing", voice_stealing)
+                .unwrap();
+            toof.process_to_buffers(16, &[(0, note_on(Note::A4)), (8, note_on(Note::A4))]);
+            assert_eq!(toof.voices.len(), expected_voices, "{voice_stealing}");
+        }
+    }
+
+    #[test]
+    fn stolen_voices_fade_out_without_clicking() {
+        for voice_stealing in [0.0, 1.0, 2.0] {
+            let mut toof = Toof::new(SampleRate::new(44100.0));
+            toof.set_param_by_name("polyphonic", 1.0).unwrap();
+            toof.set_param_by_name("voice stealing", voice_stealing)
+                .unwrap();
+            let midi: Vec<_> = (0..Toof::MAX_VOICES as u8)
+                .map(|n| (0, note_on(Note::from_u8_lossy(40 + n))))
+                .collect();
+            toof.process_to_buffers(4096, &midi);
+
+            // Compare against the same synth where no voice is stolen. Cutting off the stolen
+            // voice, which is the first voice for all strategies, would cause a jump in the
+            // signal.
+            let unstolen = toof.clone().process_to_buffers(1, &[]).left[0];
+            let mut cut_off = toof.clone();
+            cut_off.voices.remove(0);
+            let cut_off = cut_off.process_to_buffers(1, &[]).left[0];
+            let stolen = toof
+                .clone()
+                .process_to_buffers(1, &[(0, note_on(Note::from_u8_lossy(110)))])
+                .left[0];
+            let jump = (unstolen - stolen).abs();
+            let click = (unstolen - cut_off).abs();
+            assert!(jump < 0.05 * click, "{voice_stealing}: {jump} vs {click}");
+
+            // The stolen voice is removed once it has faded out.
+            toof.process_to_buffers(1024, &[(0, note_on(Note::from_u8_lossy(110)))]);
+            assert_eq!(toof.voices.len(), Toof::MAX_VOICES);
+            assert!(toof.stolen.is_empty());
+        }
+    }
+
+    #[test]
+    fn polyphonic_notes_are_filtered_independently() {
+        let note_a = (0, MidiMessage::NoteOn(Channel::Ch1, Note::A3, U7::MAX));
+        let note_b = (0, MidiMessage::NoteOn(Channel::Ch1, Note::B4, U7::MAX));
+        let mut toof = Toof::new(SampleRate::new(44100.0));
+        toof.set_param_by_name("polyphonic", 1.0).unwrap();
+        toof.set_param_by_name("filter resonance", 0.7).unwrap();
+        let signal_a = toof.clone().process_to_buffers(100, &[note_a]);
+        let signal_b = toof.clone().process_to_buffers(100, &[note_b]);
+        let signal_summed = toof.clone().process_to_buffers(100, &[note_a, note_b]);
+        for (idx, summed) in signal_summed.left.iter().enumerate() {
+            let expected = signal_a.left[idx] + signal_b.left[idx];
+            assert!((summed - expected).abs() < 1e-6, "{summed} != {expected}");
+        }
+    }
+
+    #[test]
+    fn sub_and_noise_produce_polyphonic_sound() {
+        let note_a = (0, MidiMessage::NoteOn(Channel::Ch1, Note::A3, U7::MAX));
+        let note_b = (0, MidiMessage::NoteOn(Channel::Ch1, Note::B4, U7::MAX));
+        let mut toof = Toof::new(SampleRate::new(44100.0));
+        toof.set_param_by_name("bypass filter", 1.0).unwrap();
+        toof.set_param_by_name("polyphonic", 1.0).unwrap();
+        toof.set_param_by_name("sub level", 0.5).unwrap();
+        toof.set_param_by_name("noise level", 0.25).unwrap();
+        let signal_a = toof.clone().process_to_buffers(100, &[note_a]);
+        let signal_b = toof.clone().process_to_buffers(100, &[note_b]);
+        let signal_summed = toof.clone().process_to_buffers(100, &[note_a, note_b]);
+        assert_eq!(
+            signal_summed.left,
+            signal_a
+                .left
+                .iter()
+                .zip(signal_b.left.iter())
+                .map(|(a, b)| *a + *b)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn same_seed_produces_same_noise() {
+        let note_on = [(0, MidiMessage::NoteOn(Channel::Ch1, Note::A3, U7::MAX))];
+        let mut toof = Toof::new(SampleRate::new(44100.0));
+        toof.set_param_by_name("noise level", 1.0).unwrap();
+        let render = |seed: u64| {
+            let mut t = toof.clone();
+            t.set_seed(seed);
+            t.process_to_buffers(512, &note_on)
+        };
+        assert_eq!(render(1), render(1));
+        assert_ne!(render(1), render(2));
+    }
+
+    #[test]
+    fn sub_and_noise_change_the_sound() {
+        let note_on = [(0, MidiMessage::NoteOn(Channel::Ch1, Note::A3, U7::MAX))];
+        let toof = Toof::new(SampleRate::new(44100.0));
+        let plain = toof.clone().process_to_buffers(512, &note_on);
+        for (name, value) in [("sub level", 1.0), ("noise level", 1.0)] {
+            let mut t = toof.clone();
+            t.set_param_by_name(name, value).unwrap();
+            assert_ne!(t.process_to_buffers(512, &note_on), plain, "{name}");
+        }
+        let mut square = toof.clone();
+        square.set_param_by_name("sub level", 1.0).unwrap();
+        let mut sine = square.clone();
+        sine.set_param_by_name("sub sine", 1.0).unwrap();
+        assert_ne!(
+            square.process_to_buffers(512, &note_on),
+            sine.process_to_buffers(512, &note_on)
+        );
+    }
 }
@@ -0,0 +1,1559 @@
+use std::{
+    ffi::{c_char, c_void},
+    fmt,
+    mem::ManuallyDrop,
+    path::{Path, PathBuf},
+    ptr::{self, NonNull},
+    sync::{mpsc, Arc, OnceLock},
+};
+
+use anyhow::{anyhow, Context, Result};
+use bats_dsp::{buffers::Buffers, position::Position, sample_rate::SampleRate};
+use bmidi::MidiMessage;
+
+use super::{
+    metadata::{Metadata, Param, ParamType, PluginCategory},
+    BatsInstrument, TransportInfo,
+};
+use crate::registry::{find_files, PluginFactory, PluginRegistry, RegisteredPlugin};
+
+/// An instrument that is hosted from a VST3 module. Only note on and note off messages are sent
+/// to the plugin. Params are the normalized VST3 params between `0.0` and `1.0`.
+///
+/// The plugin is instantiated when the `Vst3Instrument` is created, which should happen outside
+/// of the audio thread. If the plugin could not be instantiated, then the instrument plays
+/// silence and `error` describes what went wrong.
+pub struct Vst3Instrument {
+    /// The class that the instrument was created from.
+    class: Arc<Vst3Class>,
+    /// The sample rate that new instances are started with. This may differ from the sample rate
+    /// of the running instance, see `set_sample_rate`.
+    sample_rate: SampleRate,
+    /// The value of each param, in the same order as the params in the metadata.
+    params: Vec<f32>,
+    /// The running plugin or `None` if it could not be instantiated.
+    instance: Option<Box<Instance>>,
+    /// The reason that the plugin could not be instantiated.
+    error: Option<String>,
+}
+
+/// A class within a VST3 module that can be instantiated as an instrument.
+struct Vst3Class {
+    /// The module that contains the class.
+    module: Arc<Vst3Module>,
+    /// The id of the class.
+    cid: Tuid,
+    /// The metadata for the class. The params are read from the edit controller when the class is
+    /// loaded.
+    metadata: &'static Metadata,
+}
+
+/// A loaded VST3 module.
+struct Vst3Module {
+    /// The path to the module.
+    path: PathBuf,
+    /// The factory for the classes in the module. This is released before the library is closed.
+    factory: ManuallyDrop<ComPtr<IPluginFactoryVtbl>>,
+    /// The shared library.
+    library: libloading::os::unix::Library,
+}
+
+/// A running instance of a VST3 plugin.
+struct Instance {
+    /// The component that owns the audio processor.
+    component: ComPtr<IComponentVtbl>,
+    /// The audio processor of the component.
+    processor: ComPtr<IAudioProcessorVtbl>,
+    /// The edit controller. Plugins may implement the controller on the component itself.
+    controller: Option<ComPtr<IEditControllerVtbl>>,
+    /// True if the controller is a separate object that must be terminated.
+    separate_controller: bool,
+    /// The number of channels in the main output bus. Either `1` or `2`.
+    output_channels: i32,
+    /// True if the plugin has an event input bus.
+    has_event_input: bool,
+    /// The param changes for the next call to `process`.
+    param_changes: Box<ParameterChanges>,
+    /// The midi events for the next call to `process`.
+    events: Box<EventList>,
+    /// The transport state that is passed to the plugin.
+    context: Box<ProcessContext>,
+    /// Keeps the module loaded while the instance is alive. This is the last field so that it is
+    /// dropped after the interfaces of the instance are released.
+    _module: Arc<Vst3Module>,
+}
+
+impl Vst3Instrument {
+    /// The largest number of frames that is passed to the plugin at once. Larger buffers are
+    /// split up.
+    pub const MAX_BLOCK_SIZE: usize = 1024;
+
+    /// Create a new instance of `class` at `sample_rate`.
+    fn new(class: Arc<Vst3Class>, sample_rate: SampleRate) -> Vst3Instrument {
+        let params = class
+            .metadata
+            .params
+            .iter()
+            .map(|p| p.default_value)
+            .collect();
+        let (instance, error) = match Instance::new(&class, sample_rate) {
+            Ok(i) => (Some(i), None),
+            Err(err) => (None, Some(format!("{err:#}"))),
+        };
+        Vst3Instrument {
+            class,
+            sample_rate,
+            params,
+            instance,
+            error,
+        }
+    }
+
+    /// The reason that the plugin could not be instantiated or `None` if the plugin is running.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// The path to the module that contains the plugin.
+    pub fn module_path(&self) -> &Path {
+        &self.class.module.path
+    }
+}
+
+impl BatsInstrument for Vst3Instrument {
+    fn metadata(&self) -> &'static Metadata {
+        self.class.metadata
+    }
+
+    /// Queue the note to be sent to the plugin at the start of the next block.
+    fn handle_midi(&mut self, msg: &MidiMessage) {
+        if let Some(instance) = self.instance.as_mut() {
+            instance.events.push(msg, 0);
+        }
+    }
+
+    /// Process a single frame. This is slow since it calls the plugin for every frame. Prefer
+    /// `process_block`.
+    fn process(&mut self) -> (f32, f32) {
+        let (mut left, mut right) = ([0.0], [0.0]);
+        if let Some(instance) = self.instance.as_mut() {
+            instance.process(&mut left, &mut right);
+        }
+        (left[0], right[0])
+    }
+
+    fn param(&self, id: u32) -> f32 {
+        self.class
+            .metadata
+            .params
+            .iter()
+            .position(|p| p.id == id)
+            .map(|idx| self.params[idx])
+            .unwrap_or(0.0)
+    }
+
+    /// Set the param and send the change to the plugin at the start of the next block.
+    fn set_param(&mut self, id: u32, value: f32) {
+        let Some(idx) = self.class.metadata.params.iter().position(|p| p.id == id) else {
+            return;
+        };
+        self.params[idx] = value;
+        if let Some(instance) = self.instance.as_mut() {
+            instance.param_changes.set(id, value as f64);
+        }
+    }
+
+    fn batch_cleanup(&mut self) {}
+
+    /// Only records the sample rate since restarting the plugin is not real-time safe. The plugin
+    /// keeps running at its old sample rate until the instrument is rebuilt, for example with
+    /// `clone`.
+    fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+    }
+
+    fn set_transport_info(&mut self, info: &TransportInfo) {
+        if let Some(instance) = self.instance.as_mut() {
+            instance.context.update(info);
+        }
+    }
+
+    /// Send the midi and process the frames in blocks of up to `MAX_BLOCK_SIZE` frames.
+    fn process_block(&mut self, midi: &[(u32, MidiMessage)], out: &mut Buffers) {
+        let Some(instance) = self.instance.as_mut() else {
+            out.left.fill(0.0);
+            out.right.fill(0.0);
+            return;
+        };
+        let len = out.len();
+        let mut midi = midi.iter().peekable();
+        let mut start = 0;
+        while start < len {
+            let end = (start + Vst3Instrument::MAX_BLOCK_SIZE).min(len);
+            while let Some((frame, msg)) = midi.next_if(|(frame, _)| (*frame as usize) < end) {
+                instance
+                    .events
+                    .push(msg, (*frame as usize).saturating_sub(start) as i32);
+            }
+            instance.process(&mut out.left[start..end], &mut out.right[start..end]);
+            start = end;
+        }
+    }
+}
+
+impl Clone for Vst3Instrument {
+    /// Create a new instance of the plugin with the same param values. The internal state of the
+    /// plugin is not copied.
+    fn clone(&self) -> Vst3Instrument {
+        let mut cloned = Vst3Instrument::new(self.class.clone(), self.sample_rate);
+        for (param, value) in self.class.metadata.params.iter().zip(self.params.iter()) {
+            cloned.set_param(param.id, *value);
+        }
+        cloned
+    }
+}
+
+impl PartialEq for Vst3Instrument {
+    /// Instruments are equal if they are created from the same class and have the same param
+    /// values.
+    fn eq(&self, other: &Vst3Instrument) -> bool {
+        Arc::ptr_eq(&self.class, &other.class) && self.params == other.params
+    }
+}
+
+impl fmt::Debug for Vst3Instrument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Vst3Instrument")
+            .field("name", &self.class.metadata.name)
+            .field("module", &self.class.module.path)
+            .field("params", &self.params)
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Vst3Instrument {
+    /// Send the instance to the garbage thread to be stopped and released.
+    fn drop(&mut self) {
+        if let Some(instance) = self.instance.take() {
+            dispose(instance);
+        }
+    }
+}
+
+// SAFETY: An instance is created on one thread and then moved to the thread that processes it,
+// usually the audio thread. The plugin is only called through `&mut Instance` so it is never used
+// by two threads at once. VST3 plugins must not be stopped or terminated on the audio thread, so
+// an instance must only ever be dropped on the garbage thread. Every instance is passed to
+// `dispose` instead of being dropped where it is used.
+unsafe impl Send for Instance {}
+
+/// The channel to the thread that drops VST3 instances.
+static GARBAGE: OnceLock<mpsc::Sender<Box<Instance>>> = OnceLock::new();
+
+/// Send `instance` to the garbage thread, which is the only thread that drops instances. The
+/// garbage thread is started the first time this is called. If the garbage thread is not running,
+/// the instance is leaked instead of being dropped on the current thread.
+fn dispose(instance: Box<Instance>) {
+    let garbage = GARBAGE.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Box<Instance>>();
+        // If the thread can not be spawned then `receiver` is dropped and every send fails.
+        let _ = std::thread::Builder::new()
+            .name("bats-vst3-garbage".to_string())
+            .spawn(move || {
+                for instance in receiver.iter() {
+                    drop(instance);
+                }
+            });
+        sender
+    });
+    if let Err(mpsc::SendError(instance)) = garbage.send(instance) {
+        std::mem::forget(instance);
+    }
+}
+
+/// The directories that are searched for VST3 plugins on Linux.
+pub fn default_search_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Some(home) = std::env::var_os("HOME") {
+        paths.push(PathBuf::from(home).join(".vst3"));
+    }
+    paths.push(PathBuf::from("/usr/lib/vst3"));
+    paths.push(PathBuf::from("/usr/local/lib/vst3"));
+    paths
+}
+
+/// Load every instrument in the VST3 modules within `dirs` and add them to the global
+/// `PluginRegistry`. Returns the registered plugins and an error for each module or class that
+/// could not be loaded.
+pub fn register_instruments(dirs: &[PathBuf]) -> (Vec<RegisteredPlugin>, Vec<anyhow::Error>) {
+    let (mut registered, mut errors) = (Vec::new(), Vec::new());
+    for path in find_files(dirs, "vst3") {
+        let classes = match Vst3Class::load_all(&path) {
+            Ok(c) => c,
+            Err(err) => {
+                errors.push(err.context(format!("failed to load VST3 module {path:?}")));
+                continue;
+            }
+        };
+        for class in classes {
+            let class = match class {
+                Ok(c) => Arc::new(c),
+                Err(err) => {
+                    errors.push(err.context(format!("failed to load VST3 class in {path:?}")));
+                    continue;
+                }
+            };
+            let factory = PluginFactory {
+                metadata: class.metadata,
+                build: Box::new(move |sample_rate| {
+                    Box::new(Vst3Instrument::new(class.clone(), sample_rate))
+                }),
+                // Restarting the plugin is not real-time safe.
+                rebuild_on_sample_rate_change: true,
+            };
+            match PluginRegistry::global().register(factory) {
+                Ok(p) => registered.push(p),
+                Err(err) => errors.push(err),
+            }
+        }
+    }
+    (registered, errors)
+}
+
+impl Vst3Class {
+    /// Load the instrument classes of the module at `path`. The outer error is set if the module
+    /// could not be loaded and the inner errors are set for classes that could not be loaded.
+    fn load_all(path: &Path) -> Result<Vec<Result<Vst3Class>>> {
+        let module = Arc::new(Vst3Module::load(path)?);
+        let factory = &module.factory;
+        let factory2: Option<ComPtr<IPluginFactory2Vtbl>> = factory.query(&IPLUGIN_FACTORY2_IID);
+        let count = unsafe { (factory.vtbl().count_classes)(factory.as_ptr()) };
+        let mut classes = Vec::new();
+        for idx in 0..count {
+            let (cid, category, name, is_instrument) = match factory2.as_ref() {
+                Some(f) => {
+                    let mut info: PClassInfo2 = unsafe { std::mem::zeroed() };
+                    if unsafe { (f.vtbl().get_class_info2)(f.as_ptr(), idx, &mut info) }
+                        != K_RESULT_OK
+                    {
+                        continue;
+                    }
+                    let sub_categories = c_string(&info.sub_categories);
+                    (
+                        info.cid,
+                        c_string(&info.category),
+                        c_string(&info.name),
+                        sub_categories.split('|').any(|c| c == "Instrument"),
+                    )
+                }
+                None => {
+                    let mut info: PClassInfo = unsafe { std::mem::zeroed() };
+                    if unsafe { (factory.vtbl().get_class_info)(factory.as_ptr(), idx, &mut info) }
+                        != K_RESULT_OK
+                    {
+                        continue;
+                    }
+                    (
+                        info.cid,
+                        c_string(&info.category),
+                        c_string(&info.name),
+                        true,
+                    )
+                }
+            };
+            if category != AUDIO_MODULE_CLASS || !is_instrument {
+                continue;
+            }
+            classes.push(Vst3Class::load(module.clone(), cid, name));
+        }
+        Ok(classes)
+    }
+
+    /// Load the class with `cid` from `module`. The plugin is instantiated once to read its params.
+    fn load(module: Arc<Vst3Module>, cid: Tuid, name: String) -> Result<Vst3Class> {
+        let mut class = Vst3Class {
+            module,
+            cid,
+            metadata: &EMPTY_METADATA,
+        };
+        let instance = Instance::new(&class, SampleRate::new(44100.0))
+            .with_context(|| format!("failed to instantiate {name:?}"))?;
+        let params = instance.read_params();
+        dispose(instance);
+        class.metadata = Box::leak(Box::new(Metadata {
+            name: Box::leak(name.into_boxed_str()),
+            category: PluginCategory::Instrument,
+            tags: &["vst3"],
+            params: Box::leak(params.into_boxed_slice()),
+            pages: &[],
+        }));
+        Ok(class)
+    }
+}
+
+/// The placeholder metadata for a class whose params have not been read yet.
+const EMPTY_METADATA: Metadata = Metadata {
+    name: "",
+    category: PluginCategory::Instrument,
+    tags: &[],
+    params: &[],
+    pages: &[],
+};
+
+impl Vst3Module {
+    /// Load the module at `path`. `path` may be a `.vst3` bundle directory or a single shared
+    /// library.
+    fn load(path: &Path) -> Result<Vst3Module> {
+        let binary = if path.is_dir() {
+            let stem = path
+                .file_stem()
+                .ok_or_else(|| anyhow!("module has no name"))?;
+            path.join("Contents")
+                .join(format!("{}-linux", std::env::consts::ARCH))
+                .join(stem)
+                .with_extension("so")
+        } else {
+            path.to_path_buf()
+        };
+        let library = unsafe { libloading::os::unix::Library::new(&binary) }
+            .with_context(|| format!("failed to open {binary:?}"))?;
+        let handle = library.into_raw();
+        let library = unsafe { libloading::os::unix::Library::from_raw(handle) };
+        if let Ok(entry) =
+            unsafe { library.get::<unsafe extern "C" fn(*mut c_void) -> bool>(b"ModuleEntry\0") }
+        {
+            if !unsafe { entry(handle) } {
+                return Err(anyhow!("ModuleEntry failed"));
+            }
+        }
+        let factory = unsafe {
+            let get_factory = library
+                .get::<unsafe extern "system" fn() -> *mut c_void>(b"GetPluginFactory\0")
+                .context("module does not export GetPluginFactory")?;
+            ComPtr::from_raw(get_factory())
+        }
+        .ok_or_else(|| anyhow!("GetPluginFactory returned null"))?;
+        Ok(Vst3Module {
+            path: path.to_path_buf(),
+            factory: ManuallyDrop::new(factory),
+            library,
+        })
+    }
+
+    /// Create an instance of the class with `cid` that implements the interface with `iid`.
+    fn create_instance<V>(&self, cid: &Tuid, iid: &Tuid) -> Result<ComPtr<V>> {
+        let mut obj = ptr::null_mut();
+        let res = unsafe {
+            (self.factory.vtbl().create_instance)(
+                self.factory.as_ptr(),
+                cid.as_ptr() as *const c_char,
+                iid.as_ptr() as *const c_char,
+                &mut obj,
+            )
+        };
+        match res {
+            K_RESULT_OK => unsafe { ComPtr::from_raw(obj) }
+                .ok_or_else(|| anyhow!("createInstance returned null")),
+            res => Err(anyhow!("createInstance failed with {res}")),
+        }
+    }
+}
+
+impl Drop for Vst3Module {
+    fn drop(&mut self) {
+        unsafe {
+            ManuallyDrop::drop(&mut self.factory);
+            if let Ok(exit) = self
+                .library
+                .get::<unsafe extern "C" fn() -> bool>(b"ModuleExit\0")
+            {
+                exit();
+            }
+        }
+    }
+}
+
+// The factory is only used to create instances, which VST3 allows from any thread.
+unsafe impl Send for Vst3Module {}
+unsafe impl Sync for Vst3Module {}
+
+impl Instance {
+    /// Instantiate `class`, set up a single stereo output, and start processing.
+    fn new(class: &Vst3Class, sample_rate: SampleRate) -> Result<Box<Instance>> {
+        let host = &HOST_APPLICATION as *const HostApplication as *mut c_void;
+        let module = &class.module;
+        let component: ComPtr<IComponentVtbl> =
+            module.create_instance(&class.cid, &ICOMPONENT_IID)?;
+        let res = unsafe { (component.vtbl().base.initialize)(component.as_ptr(), host) };
+        if res != K_RESULT_OK {
+            return Err(anyhow!("component initialize failed with {res}"));
+        }
+        let processor: ComPtr<IAudioProcessorVtbl> = component
+            .query(&IAUDIO_PROCESSOR_IID)
+            .ok_or_else(|| anyhow!("component is not an audio processor"))?;
+        let (controller, separate_controller) =
+            match component.query::<IEditControllerVtbl>(&IEDIT_CONTROLLER_IID) {
+                Some(c) => (Some(c), false),
+                None => (Instance::create_controller(module, &component, host), true),
+            };
+        let mut instance = Box::new(Instance {
+            component,
+            processor,
+            controller,
+            separate_controller,
+            output_channels: 2,
+            has_event_input: false,
+            param_changes: Box::new(ParameterChanges::new(class.metadata.params.len())),
+            events: Box::new(EventList::new()),
+            context: Box::default(),
+            _module: module.clone(),
+        });
+        match instance.prepare(sample_rate) {
+            Ok(()) => Ok(instance),
+            Err(err) => {
+                dispose(instance);
+                Err(err)
+            }
+        }
+    }
+
+    /// Set up the buses and the param changes and start processing at `sample_rate`.
+    fn prepare(&mut self, sample_rate: SampleRate) -> Result<()> {
+        self.setup_buses()?;
+        if self.param_changes.queues.is_empty() {
+            let count = self.read_params().len();
+            *self.param_changes = ParameterChanges::new(count);
+        }
+        self.start(sample_rate)
+    }
+
+    /// Create and initialize the separate edit controller of `component`. Returns `None` if the
+    /// component has no controller.
+    fn create_controller(
+        module: &Vst3Module,
+        component: &ComPtr<IComponentVtbl>,
+        host: *mut c_void,
+    ) -> Option<ComPtr<IEditControllerVtbl>> {
+        let mut cid = [0u8; 16];
+        let res =
+            unsafe { (component.vtbl().get_controller_class_id)(component.as_ptr(), &mut cid) };
+        if res != K_RESULT_OK || cid == [0u8; 16] {
+            return None;
+        }
+        let controller: ComPtr<IEditControllerVtbl> =
+            module.create_instance(&cid, &IEDIT_CONTROLLER_IID).ok()?;
+        let res = unsafe { (controller.vtbl().base.initialize)(controller.as_ptr(), host) };
+        (res == K_RESULT_OK).then_some(controller)
+    }
+
+    /// Activate the first event input and the first audio output. All other buses are turned
+    /// off.
+    fn setup_buses(&mut self) -> Result<()> {
+        let component = &self.component;
+        let vtbl = component.vtbl();
+        let this = component.as_ptr();
+        unsafe {
+            let inputs = (vtbl.get_bus_count)(this, K_AUDIO, K_INPUT).max(0);
+            let outputs = (vtbl.get_bus_count)(this, K_AUDIO, K_OUTPUT).max(0);
+            if outputs == 0 {
+                return Err(anyhow!("plugin has no audio outputs"));
+            }
+            let mut input_arrangements = vec![K_STEREO; inputs as usize];
+            let mut output_arrangements = vec![K_STEREO; outputs as usize];
+            (self.processor.vtbl().set_bus_arrangements)(
+                self.processor.as_ptr(),
+                input_arrangements.as_mut_ptr(),
+                inputs,
+                output_arrangements.as_mut_ptr(),
+                outputs,
+            );
+            for idx in 0..inputs {
+                (vtbl.activate_bus)(this, K_AUDIO, K_INPUT, idx, 0);
+            }
+            for idx in 0..outputs {
+                (vtbl.activate_bus)(this, K_AUDIO, K_OUTPUT, idx, (idx == 0) as u8);
+            }
+            let mut info: BusInfo = std::mem::zeroed();
+            if (vtbl.get_bus_info)(this, K_AUDIO, K_OUTPUT, 0, &mut info) == K_RESULT_OK {
+                self.output_channels = info.channel_count;
+            }
+            if !(1..=2).contains(&self.output_channels) {
+                return Err(anyhow!(
+                    "main output has {} channels but only mono and stereo are supported",
+                    self.output_channels
+                ));
+            }
+            if (vtbl.get_bus_count)(this, K_EVENT, K_INPUT) > 0 {
+                (vtbl.activate_bus)(this, K_EVENT, K_INPUT, 0, 1);
+                self.has_event_input = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Set up processing for `sample_rate` and activate the plugin.
+    fn start(&mut self, sample_rate: SampleRate) -> Result<()> {
+        let mut setup = ProcessSetup {
+            process_mode: K_REALTIME,
+            symbolic_sample_size: K_SAMPLE_32,
+            max_samples_per_block: Vst3Instrument::MAX_BLOCK_SIZE as i32,
+            sample_rate: sample_rate.sample_rate() as f64,
+        };
+        unsafe {
+            let res = (self.processor.vtbl().setup_processing)(self.processor.as_ptr(), &mut setup);
+            if res != K_RESULT_OK {
+                return Err(anyhow!("setupProcessing failed with {res}"));
+            }
+            let res = (self.component.vtbl().set_active)(self.component.as_ptr(), 1);
+            if res != K_RESULT_OK {
+                return Err(anyhow!("setActive failed with {res}"));
+            }
+            (self.processor.vtbl().set_processing)(self.processor.as_ptr(), 1);
+        }
+        self.context.sample_rate = sample_rate.sample_rate() as f64;
+        Ok(())
+    }
+
+    /// Stop processing and deactivate the plugin.
+    fn stop(&mut self) {
+        unsafe {
+            (self.processor.vtbl().set_processing)(self.processor.as_ptr(), 0);
+            (self.component.vtbl().set_active)(self.component.as_ptr(), 0);
+        }
+    }
+
+    /// Read the params that can be changed from the edit controller. Read only and hidden params
+    /// are skipped.
+    fn read_params(&self) -> Vec<Param> {
+        let Some(controller) = self.controller.as_ref() else {
+            return Vec::new();
+        };
+        let count = unsafe { (controller.vtbl().get_parameter_count)(controller.as_ptr()) };
+        (0..count)
+            .filter_map(|idx| {
+                let mut info: ParameterInfo = unsafe { std::mem::zeroed() };
+                let res = unsafe {
+                    (controller.vtbl().get_parameter_info)(controller.as_ptr(), idx, &mut info)
+                };
+                if res != K_RESULT_OK || info.flags & (K_IS_READ_ONLY | K_IS_HIDDEN) != 0 {
+                    return None;
+                }
+                Some(Param {
+                    id: info.id,
+                    name: Box::leak(utf16_string(&info.title).into_boxed_str()),
+                    param_type: if info.step_count == 1 {
+                        ParamType::Bool
+                    } else {
+                        ParamType::Percent
+                    },
+                    default_value: info.default_normalized_value as f32,
+                    min_value: 0.0,
+                    max_value: 1.0,
+                })
+            })
+            .collect()
+    }
+
+    /// Run the plugin over `left.len()` frames with the queued events and param changes.
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let mut channels = [left.as_mut_ptr(), right.as_mut_ptr()];
+        let mut outputs = AudioBusBuffers {
+            num_channels: self.output_channels,
+            silence_flags: 0,
+            channel_buffers_32: channels.as_mut_ptr(),
+        };
+        let mut data = ProcessData {
+            process_mode: K_REALTIME,
+            symbolic_sample_size: K_SAMPLE_32,
+            num_samples: left.len() as i32,
+            num_inputs: 0,
+            num_outputs: 1,
+            inputs: ptr::null_mut(),
+            outputs: &mut outputs,
+            input_parameter_changes: self.param_changes.as_mut() as *mut ParameterChanges
+                as *mut c_void,
+            output_parameter_changes: ptr::null_mut(),
+            input_events: if self.has_event_input {
+                self.events.as_mut() as *mut EventList as *mut c_void
+            } else {
+                ptr::null_mut()
+            },
+            output_events: ptr::null_mut(),
+            process_context: self.context.as_mut(),
+        };
+        let res = unsafe { (self.processor.vtbl().process)(self.processor.as_ptr(), &mut data) };
+        self.events.clear();
+        self.param_changes.clear();
+        if res != K_RESULT_OK {
+            left.fill(0.0);
+            right.fill(0.0);
+        } else if self.output_channels == 1 {
+            right.copy_from_slice(left);
+        }
+        self.context.advance(left.len());
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        self.stop();
+        unsafe {
+            if let Some(controller) = self
+                .controller
+                .as_ref()
+                .filter(|_| self.separate_controller)
+            {
+                (controller.vtbl().base.terminate)(controller.as_ptr());
+            }
+            (self.component.vtbl().base.terminate)(self.component.as_ptr());
+        }
+    }
+}
+
+/// Convert a null terminated string from a VST3 struct.
+fn c_string(chars: &[c_char]) -> String {
+    let bytes: Vec<u8> = chars
+        .iter()
+        .take_while(|c| **c != 0)
+        .map(|c| *c as u8)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Convert a null terminated UTF-16 string from a VST3 struct.
+fn utf16_string(chars: &[u16]) -> String {
+    let len = chars.iter().position(|c| *c == 0).unwrap_or(chars.len());
+    String::from_utf16_lossy(&chars[..len])
+}
+
+/// A 16 byte interface or class id.
+type Tuid = [u8; 16];
+
+/// The result code returned by VST3 functions.
+type TResult = i32;
+
+const K_RESULT_OK: TResult = 0;
+const K_RESULT_FALSE: TResult = 1;
+const K_INVALID_ARGUMENT: TResult = 2;
+const K_NO_INTERFACE: TResult = -1;
+
+/// Build an id from 4 integers the same way as the `INLINE_UID` macro of the VST3 SDK on Linux.
+const fn uid(l1: u32, l2: u32, l3: u32, l4: u32) -> Tuid {
+    let (a, b, c, d) = (
+        l1.to_be_bytes(),
+        l2.to_be_bytes(),
+        l3.to_be_bytes(),
+        l4.to_be_bytes(),
+    );
+    [
+        a[0], a[1], a[2], a[3], b[0], b[1], b[2], b[3], c[0], c[1], c[2], c[3], d[0], d[1], d[2],
+        d[3],
+    ]
+}
+
+const FUNKNOWN_IID: Tuid = uid(0x00000000, 0x00000000, 0xC0000000, 0x00000046);
+const IPLUGIN_FACTORY2_IID: Tuid = uid(0x0007B650, 0xF24B4C0B, 0xA464EDB9, 0xF00B2ABB);
+const ICOMPONENT_IID: Tuid = uid(0xE831FF31, 0xF2D54301, 0x928EBBEE, 0x25697802);
+const IAUDIO_PROCESSOR_IID: Tuid = uid(0x42043F99, 0xB7DA453C, 0xA569E79D, 0x9AAEC33D);
+const IEDIT_CONTROLLER_IID: Tuid = uid(0xDCD7BBE3, 0x7742448D, 0xA874AACC, 0x979C759E);
+const IHOST_APPLICATION_IID: Tuid = uid(0x58E595CC, 0xDB2D4969, 0x8B6AAF8C, 0x36A664E5);
+const IEVENT_LIST_IID: Tuid = uid(0x3A2C4214, 0x346349FE, 0xB2C4F397, 0xB9695A44);
+const IPARAMETER_CHANGES_IID: Tuid = uid(0xA4779663, 0x0BB64A56, 0xB44384A8, 0x466FEB9D);
+const IPARAM_VALUE_QUEUE_IID: Tuid = uid(0x01263A18, 0xED074F6F, 0x98C9D356, 0x4686F9BA);
+
+/// The category of classes that process audio.
+const AUDIO_MODULE_CLASS: &str = "Audio Module Class";
+
+const K_AUDIO: i32 = 0;
+const K_EVENT: i32 = 1;
+const K_INPUT: i32 = 0;
+const K_OUTPUT: i32 = 1;
+const K_STEREO: u64 = 0b11;
+const K_REALTIME: i32 = 0;
+const K_SAMPLE_32: i32 = 0;
+const K_IS_READ_ONLY: i32 = 1 << 1;
+const K_IS_HIDDEN: i32 = 1 << 4;
+const K_NOTE_ON_EVENT: u16 = 0;
+const K_NOTE_OFF_EVENT: u16 = 1;
+const K_PLAYING: u32 = 1 << 1;
+const K_PROJECT_TIME_MUSIC_VALID: u32 = 1 << 9;
+const K_TEMPO_VALID: u32 = 1 << 10;
+const K_BAR_POSITION_VALID: u32 = 1 << 11;
+const K_TIME_SIG_VALID: u32 = 1 << 13;
+
+/// A reference counted pointer to a VST3 object whose vtable is `V`.
+struct ComPtr<V> {
+    /// The object. The first field of every object is a pointer to its vtable.
+    ptr: NonNull<*const V>,
+}
+
+impl<V> ComPtr<V> {
+    /// Take ownership of a reference to the object at `ptr`. Returns `None` if `ptr` is null.
+    ///
+    /// # Safety
+    /// `ptr` must be null or point to an object that implements `V`.
+    unsafe fn from_raw(ptr: *mut c_void) -> Option<ComPtr<V>> {
+        NonNull::new(ptr as *mut *const V).map(|ptr| ComPtr { ptr })
+    }
+
+    /// The pointer to the object for passing as `this`.
+    fn as_ptr(&self) -> *mut c_void {
+        self.ptr.as_ptr() as *mut c_void
+    }
+
+    /// The vtable of the object.
+    fn vtbl(&self) -> &V {
+        unsafe { &**self.ptr.as_ptr() }
+    }
+
+    /// The `FUnknown` part of the vtable. Every vtable starts with it.
+    fn unknown(&self) -> &FUnknownVtbl {
+        unsafe { &*(*self.ptr.as_ptr() as *const FUnknownVtbl) }
+    }
+
+    /// Get the interface with `iid` from the object.
+    fn query<U>(&self, iid: &Tuid) -> Option<ComPtr<U>> {
+        let mut obj = ptr::null_mut();
+        let res = unsafe { (self.unknown().query_interface)(self.as_ptr(), iid, &mut obj) };
+        match res {
+            K_RESULT_OK => unsafe { ComPtr::from_raw(obj) },
+            _ => None,
+        }
+    }
+}
+
+impl<V> Drop for ComPtr<V> {
+    fn drop(&mut self) {
+        unsafe { (self.unknown().release)(self.as_ptr()) };
+    }
+}
+
+#[repr(C)]
+struct FUnknownVtbl {
+    query_interface:
+        unsafe extern "system" fn(*mut c_void, *const Tuid, *mut *mut c_void) -> TResult,
+    add_ref: unsafe extern "system" fn(*mut c_void) -> u32,
+    release: unsafe extern "system" fn(*mut c_void) -> u32,
+}
+
+#[repr(C)]
+struct IPluginFactoryVtbl {
+    unknown: FUnknownVtbl,
+    get_factory_info: unsafe extern "system" fn(*mut c_void, *mut c_void) -> TResult,
+    count_classes: unsafe extern "system" fn(*mut c_void) -> i32,
+    get_class_info: unsafe extern "system" fn(*mut c_void, i32, *mut PClassInfo) -> TResult,
+    create_instance: unsafe extern "system" fn(
+        *mut c_void,
+        *const c_char,
+        *const c_char,
+        *mut *mut c_void,
+    ) -> TResult,
+}
+
+#[repr(C)]
+struct IPluginFactory2Vtbl {
+    factory: IPluginFactoryVtbl,
+    get_class_info2: unsafe extern "system" fn(*mut c_void, i32, *mut PClassInfo2) -> TResult,
+}
+
+#[repr(C)]
+struct PClassInfo {
+    cid: Tuid,
+    cardinality: i32,
+    category: [c_char; 32],
+    name: [c_char; 64],
+}
+
+#[repr(C)]
+struct PClassInfo2 {
+    cid: Tuid,
+    cardinality: i32,
+    category: [c_char; 32],
+    name: [c_char; 64],
+    class_flags: u32,
+    sub_categories: [c_char; 128],
+    vendor: [c_char; 64],
+    version: [c_char; 64],
+    sdk_version: [c_char; 64],
+}
+
+#[repr(C)]
+struct IPluginBaseVtbl {
+    unknown: FUnknownVtbl,
+    initialize: unsafe extern "system" fn(*mut c_void, *mut c_void) -> TResult,
+    terminate: unsafe extern "system" fn(*mut c_void) -> TResult,
+}
+
+#[repr(C)]
+struct IComponentVtbl {
+    base: IPluginBaseVtbl,
+    get_controller_class_id: unsafe extern "system" fn(*mut c_void, *mut Tuid) -> TResult,
+    set_io_mode: unsafe extern "system" fn(*mut c_void, i32) -> TResult,
+    get_bus_count: unsafe extern "system" fn(*mut c_void, i32, i32) -> i32,
+    get_bus_info: unsafe extern "system" fn(*mut c_void, i32, i32, i32, *mut BusInfo) -> TResult,
+    get_routing_info: unsafe extern "system" fn(*mut c_void, *mut c_void, *mut c_void) -> TResult,
+    activate_bus: unsafe extern "system" fn(*mut c_void, i32, i32, i32, u8) -> TResult,
+    set_active: unsafe extern "system" fn(*mut c_void, u8) -> TResult,
+    set_state: unsafe extern "system" fn(*mut c_void, *mut c_void) -> TResult,
+    get_state: unsafe extern "system" fn(*mut c_void, *mut c_void) -> TResult,
+}
+
+#[repr(C)]
+struct BusInfo {
+    media_type: i32,
+    direction: i32,
+    channel_count: i32,
+    name: [u16; 128],
+    bus_type: i32,
+    flags: u32,
+}
+
+#[repr(C)]
+struct IAudioProcessorVtbl {
+    unknown: FUnknownVtbl,
+    set_bus_arrangements:
+        unsafe extern "system" fn(*mut c_void, *mut u64, i32, *mut u64, i32) -> TResult,
+    get_bus_arrangement: unsafe extern "system" fn(*mut c_void, i32, i32, *mut u64) -> TResult,
+    can_process_sample_size: unsafe extern "system" fn(*mut c_void, i32) -> TResult,
+    get_latency_samples: unsafe extern "system" fn(*mut c_void) -> u32,
+    setup_processing: unsafe extern "system" fn(*mut c_void, *mut ProcessSetup) -> TResult,
+    set_processing: unsafe extern "system" fn(*mut c_void, u8) -> TResult,
+    process: unsafe extern "system" fn(*mut c_void, *mut ProcessData) -> TResult,
+    get_tail_samples: unsafe extern "system" fn(*mut c_void) -> u32,
+}
+
+#[repr(C)]
+struct ProcessSetup {
+    process_mode: i32,
+    symbolic_sample_size: i32,
+    max_samples_per_block: i32,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+struct AudioBusBuffers {
+    num_channels: i32,
+    silence_flags: u64,
+    channel_buffers_32: *mut *mut f32,
+}
+
+#[repr(C)]
+struct ProcessData {
+    process_mode: i32,
+    symbolic_sample_size: i32,
+    num_samples: i32,
+    num_inputs: i32,
+    num_outputs: i32,
+    inputs: *mut AudioBusBuffers,
+    outputs: *mut AudioBusBuffers,
+    input_parameter_changes: *mut c_void,
+    output_parameter_changes: *mut c_void,
+    input_events: *mut c_void,
+    output_events: *mut c_void,
+    process_context: *mut ProcessContext,
+}
+
+/// The transport state that is passed to the plugin.
+#[repr(C)]
+#[derive(Default)]
+struct ProcessContext {
+    state: u32,
+    sample_rate: f64,
+    project_time_samples: i64,
+    system_time: i64,
+    continous_time_samples: i64,
+    project_time_music: f64,
+    bar_position_music: f64,
+    cycle_start_music: f64,
+    cycle_end_music: f64,
+    tempo: f64,
+    time_sig_numerator: i32,
+    time_sig_denominator: i32,
+    chord_key_note: u8,
+    chord_root_note: u8,
+    chord_mask: i16,
+    smpte_offset_subframes: i32,
+    frames_per_second: u32,
+    frame_rate_flags: u32,
+    samples_to_next_clock: i32,
+}
+
+impl ProcessContext {
+    /// Set the context to the transport at the start of the next block. The sample rate is set
+    /// when the plugin is started.
+    fn update(&mut self, info: &TransportInfo) {
+        let beats = info.position.as_beats_f64();
+        let beats_per_bar = Position::BEATS_PER_BAR as f64;
+        self.state = K_TEMPO_VALID
+            | K_PROJECT_TIME_MUSIC_VALID
+            | K_BAR_POSITION_VALID
+            | K_TIME_SIG_VALID
+            | if info.playing { K_PLAYING } else { 0 };
+        self.project_time_samples = (beats * info.samples_per_beat as f64) as i64;
+        self.project_time_music = beats;
+        self.bar_position_music = (beats / beats_per_bar).floor() * beats_per_bar;
+        self.tempo = info.bpm as f64;
+        self.time_sig_numerator = Position::BEATS_PER_BAR as i32;
+        self.time_sig_denominator = 4;
+    }
+
+    /// Move the context forward by `frames` when a buffer is split into multiple blocks.
+    fn advance(&mut self, frames: usize) {
+        self.continous_time_samples += frames as i64;
+        if self.state & K_PLAYING == 0 || self.sample_rate <= 0.0 {
+            return;
+        }
+        self.project_time_samples += frames as i64;
+        self.project_time_music += frames as f64 * self.tempo / 60.0 / self.sample_rate;
+    }
+}
+
+#[repr(C)]
+struct IEditControllerVtbl {
+    base: IPluginBaseVtbl,
+    set_component_state: unsafe extern "system" fn(*mut c_void, *mut c_void) -> TResult,
+    set_state: unsafe extern "system" fn(*mut c_void, *mut c_void) -> TResult,
+    get_state: unsafe extern "system" fn(*mut c_void, *mut c_void) -> TResult,
+    get_parameter_count: unsafe extern "system" fn(*mut c_void) -> i32,
+    get_parameter_info: unsafe extern "system" fn(*mut c_void, i32, *mut ParameterInfo) -> TResult,
+    get_param_string_by_value:
+        unsafe extern "system" fn(*mut c_void, u32, f64, *mut u16) -> TResult,
+    get_param_value_by_string:
+        unsafe extern "system" fn(*mut c_void, u32, *const u16, *mut f64) -> TResult,
+    normalized_param_to_plain: unsafe extern "system" fn(*mut c_void, u32, f64) -> f64,
+    plain_param_to_normalized: unsafe extern "system" fn(*mut c_void, u32, f64) -> f64,
+    get_param_normalized: unsafe extern "system" fn(*mut c_void, u32) -> f64,
+    set_param_normalized: unsafe extern "system" fn(*mut c_void, u32, f64) -> TResult,
+    set_component_handler: unsafe extern "system" fn(*mut c_void, *mut c_void) -> TResult,
+    create_view: unsafe extern "system" fn(*mut c_void, *const c_char) -> *mut c_void,
+}
+
+#[repr(C)]
+struct ParameterInfo {
+    id: u32,
+    title: [u16; 128],
+    short_title: [u16; 128],
+    units: [u16; 128],
+    step_count: i32,
+    default_normalized_value: f64,
+    unit_id: i32,
+    flags: i32,
+}
+
+/// A midi event that is sent to the plugin.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Event {
+    bus_index: i32,
+    sample_offset: i32,
+    ppq_position: f64,
+    flags: u16,
+    event_type: u16,
+    data: EventData,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+union EventData {
+    note_on: NoteOnEvent,
+    note_off: NoteOffEvent,
+    /// Pads the union to the size of the largest VST3 event.
+    _size: [u64; 3],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct NoteOnEvent {
+    channel: i16,
+    pitch: i16,
+    tuning: f32,
+    velocity: f32,
+    length: i32,
+    note_id: i32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct NoteOffEvent {
+    channel: i16,
+    pitch: i16,
+    velocity: f32,
+    note_id: i32,
+    tuning: f32,
+}
+
+impl Event {
+    /// Convert `msg` into an event at `sample_offset`. Returns `None` for messages other than
+    /// note on and note off.
+    fn from_midi(msg: &MidiMessage, sample_offset: i32) -> Option<Event> {
+        let (event_type, data) = match *msg {
+            MidiMessage::NoteOn(channel, note, velocity) if u8::from(velocity) > 0 => (
+                K_NOTE_ON_EVENT,
+                EventData {
+                    note_on: NoteOnEvent {
+                        channel: channel.index() as i16,
+                        pitch: u8::from(note) as i16,
+                        tuning: 0.0,
+                        velocity: u8::from(velocity) as f32 / 127.0,
+                        length: 0,
+                        note_id: -1,
+                    },
+                },
+            ),
+            MidiMessage::NoteOn(channel, note, velocity)
+            | MidiMessage::NoteOff(channel, note, velocity) => (
+                K_NOTE_OFF_EVENT,
+                EventData {
+                    note_off: NoteOffEvent {
+                        channel: channel.index() as i16,
+                        pitch: u8::from(note) as i16,
+                        velocity: u8::from(velocity) as f32 / 127.0,
+                        note_id: -1,
+                        tuning: 0.0,
+                    },
+                },
+            ),
+            _ => return None,
+        };
+        Some(Event {
+            bus_index: 0,
+            sample_offset,
+            ppq_position: 0.0,
+            flags: 0,
+            event_type,
+            data,
+        })
+    }
+}
+
+#[repr(C)]
+struct IHostApplicationVtbl {
+    unknown: FUnknownVtbl,
+    get_name: unsafe extern "system" fn(*mut c_void, *mut u16) -> TResult,
+    create_instance: unsafe extern "system" fn(
+        *mut c_void,
+        *const Tuid,
+        *const Tuid,
+        *mut *mut c_void,
+    ) -> TResult,
+}
+
+/// The host context that is passed to plugins when they are initialized.
+#[repr(C)]
+struct HostApplication {
+    vtbl: &'static IHostApplicationVtbl,
+}
+
+static HOST_APPLICATION: HostApplication = HostApplication {
+    vtbl: &IHostApplicationVtbl {
+        unknown: FUnknownVtbl {
+            query_interface: host_query_interface,
+            add_ref: static_add_ref,
+            release: static_release,
+        },
+        get_name: host_get_name,
+        create_instance: host_create_instance,
+    },
+};
+
+unsafe extern "system" fn host_query_interface(
+    this: *mut c_void,
+    iid: *const Tuid,
+    obj: *mut *mut c_void,
+) -> TResult {
+    query_interface(this, iid, obj, &IHOST_APPLICATION_IID)
+}
+
+unsafe extern "system" fn host_get_name(_: *mut c_void, name: *mut u16) -> TResult {
+    for (idx, c) in "bats".encode_utf16().chain(std::iter::once(0)).enumerate() {
+        *name.add(idx) = c;
+    }
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn host_create_instance(
+    _: *mut c_void,
+    _: *const Tuid,
+    _: *const Tuid,
+    obj: *mut *mut c_void,
+) -> TResult {
+    *obj = ptr::null_mut();
+    K_RESULT_FALSE
+}
+
+/// Set `obj` to `this` if `iid` is `FUnknown` or `supported`.
+unsafe fn query_interface(
+    this: *mut c_void,
+    iid: *const Tuid,
+    obj: *mut *mut c_void,
+    supported: &Tuid,
+) -> TResult {
+    if *iid == FUNKNOWN_IID || *iid == *supported {
+        *obj = this;
+        K_RESULT_OK
+    } else {
+        *obj = ptr::null_mut();
+        K_NO_INTERFACE
+    }
+}
+
+/// Reference counting for objects that are owned by the host and outlive the plugin.
+unsafe extern "system" fn static_add_ref(_: *mut c_void) -> u32 {
+    1
+}
+
+/// Reference counting for objects that are owned by the host and outlive the plugin.
+unsafe extern "system" fn static_release(_: *mut c_void) -> u32 {
+    1
+}
+
+#[repr(C)]
+struct IEventListVtbl {
+    unknown: FUnknownVtbl,
+    get_event_count: unsafe extern "system" fn(*mut c_void) -> i32,
+    get_event: unsafe extern "system" fn(*mut c_void, i32, *mut Event) -> TResult,
+    add_event: unsafe extern "system" fn(*mut c_void, *mut Event) -> TResult,
+}
+
+/// The midi events for a block. Events past the capacity are dropped so that adding events never
+/// allocates.
+#[repr(C)]
+struct EventList {
+    vtbl: &'static IEventListVtbl,
+    events: Vec<Event>,
+}
+
+static EVENT_LIST_VTBL: IEventListVtbl = IEventListVtbl {
+    unknown: FUnknownVtbl {
+        query_interface: event_list_query_interface,
+        add_ref: static_add_ref,
+        release: static_release,
+    },
+    get_event_count: event_list_get_event_count,
+    get_event: event_list_get_event,
+    add_event: event_list_add_event,
+};
+
+impl EventList {
+    /// The maximum number of events in a block.
+    const CAPACITY: usize = 512;
+
+    /// Create a new empty event list.
+    fn new() -> EventList {
+        EventList {
+            vtbl: &EVENT_LIST_VTBL,
+            events: Vec::with_capacity(EventList::CAPACITY),
+        }
+    }
+
+    /// Add `msg` at `sample_offset` if it is a note on or note off and there is space.
+    fn push(&mut self, msg: &MidiMessage, sample_offset: i32) {
+        if self.events.len() == EventList::CAPACITY {
+            return;
+        }
+        if let Some(event) = Event::from_midi(msg, sample_offset) {
+            self.events.push(event);
+        }
+    }
+
+    /// Remove all events.
+    fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+
+unsafe extern "system" fn event_list_query_interface(
+    this: *mut c_void,
+    iid: *const Tuid,
+    obj: *mut *mut c_void,
+) -> TResult {
+    query_interface(this, iid, obj, &IEVENT_LIST_IID)
+}
+
+unsafe extern "system" fn event_list_get_event_count(this: *mut c_void) -> i32 {
+    (*(this as *mut EventList)).events.len() as i32
+}
+
+unsafe extern "system" fn event_list_get_event(
+    this: *mut c_void,
+    index: i32,
+    event: *mut Event,
+) -> TResult {
+    let list = &*(this as *const EventList);
+    match list.events.get(index as usize) {
+        Some(e) => {
+            *event = *e;
+            K_RESULT_OK
+        }
+        None => K_INVALID_ARGUMENT,
+    }
+}
+
+unsafe extern "system" fn event_list_add_event(_: *mut c_void, _: *mut Event) -> TResult {
+    K_RESULT_FALSE
+}
+
+#[repr(C)]
+struct IParameterChangesVtbl {
+    unknown: FUnknownVtbl,
+    get_parameter_count: unsafe extern "system" fn(*mut c_void) -> i32,
+    get_parameter_data: unsafe extern "system" fn(*mut c_void, i32) -> *mut c_void,
+    add_parameter_data: unsafe extern "system" fn(*mut c_void, *const u32, *mut i32) -> *mut c_void,
+}
+
+#[repr(C)]
+struct IParamValueQueueVtbl {
+    unknown: FUnknownVtbl,
+    get_parameter_id: unsafe extern "system" fn(*mut c_void) -> u32,
+    get_point_count: unsafe extern "system" fn(*mut c_void) -> i32,
+    get_point: unsafe extern "system" fn(*mut c_void, i32, *mut i32, *mut f64) -> TResult,
+    add_point: unsafe extern "system" fn(*mut c_void, i32, f64, *mut i32) -> TResult,
+}
+
+/// The param changes for a block. Each param that changed has a single value that applies from
+/// the start of the block. The queues are allocated up front so setting params never allocates.
+#[repr(C)]
+struct ParameterChanges {
+    vtbl: &'static IParameterChangesVtbl,
+    /// One queue for each param. Only the first `used` queues are sent to the plugin.
+    queues: Vec<ParamValueQueue>,
+    /// The number of params that changed.
+    used: usize,
+}
+
+/// The new value for a single param.
+#[repr(C)]
+struct ParamValueQueue {
+    vtbl: &'static IParamValueQueueVtbl,
+    id: u32,
+    value: f64,
+}
+
+static PARAMETER_CHANGES_VTBL: IParameterChangesVtbl = IParameterChangesVtbl {
+    unknown: FUnknownVtbl {
+        query_interface: parameter_changes_query_interface,
+        add_ref: static_add_ref,
+        release: static_release,
+    },
+    get_parameter_count: parameter_changes_get_parameter_count,
+    get_parameter_data: parameter_changes_get_parameter_data,
+    add_parameter_data: parameter_changes_add_parameter_data,
+};
+
+static PARAM_VALUE_QUEUE_VTBL: IParamValueQueueVtbl = IParamValueQueueVtbl {
+    unknown: FUnknownVtbl {
+        query_interface: param_value_queue_query_interface,
+        add_ref: static_add_ref,
+        release: static_release,
+    },
+    get_parameter_id: param_value_queue_get_parameter_id,
+    get_point_count: param_value_queue_get_point_count,
+    get_point: param_value_queue_get_point,
+    add_point: param_value_queue_add_point,
+};
+
+impl ParameterChanges {
+    /// Create a new set of changes with space for `param_count` params.
+    fn new(param_count: usize) -> ParameterChanges {
+        ParameterChanges {
+            vtbl: &PARAMETER_CHANGES_VTBL,
+            queues: (0..param_count)
+                .map(|_| ParamValueQueue {
+                    vtbl: &PARAM_VALUE_QUEUE_VTBL,
+                    id: 0,
+                    value: 0.0,
+                })
+                .collect(),
+            used: 0,
+        }
+    }
+
+    /// Set the param with `id` to `value`. The change is dropped if there is no space left.
+    fn set(&mut self, id: u32, value: f64) {
+        let idx = match self.queues[..self.used].iter().position(|q| q.id == id) {
+            Some(idx) => idx,
+            None if self.used < self.queues.len() => {
+                self.used += 1;
+                self.used - 1
+            }
+            None => return,
+        };
+        self.queues[idx].id = id;
+        self.queues[idx].value = value;
+    }
+
+    /// Remove all changes.
+    fn clear(&mut self) {
+        self.used = 0;
+    }
+}
+
+unsafe extern "system" fn parameter_changes_query_interface(
+    this: *mut c_void,
+    iid: *const Tuid,
+    obj: *mut *mut c_void,
+) -> TResult {
+    query_interface(this, iid, obj, &IPARAMETER_CHANGES_IID)
+}
+
+unsafe extern "system" fn parameter_changes_get_parameter_count(this: *mut c_void) -> i32 {
+    (*(this as *mut ParameterChanges)).used as i32
+}
+
+unsafe extern "system" fn parameter_changes_get_parameter_data(
+    this: *mut c_void,
+    index: i32,
+) -> *mut c_void {
+    let changes = &mut *(this as *mut ParameterChanges);
+    match changes.queues[..changes.used].get_mut(index as usize) {
+        Some(q) => q as *mut ParamValueQueue as *mut c_void,
+        None => ptr::null_mut(),
+    }
+}
+
+unsafe extern "system" fn parameter_changes_add_parameter_data(
+    _: *mut c_void,
+    _: *const u32,
+    _: *mut i32,
+) -> *mut c_void {
+    ptr::null_mut()
+}
+
+unsafe extern "system" fn param_value_queue_query_interface(
+    this: *mut c_void,
+    iid: *const Tuid,
+    obj: *mut *mut c_void,
+) -> TResult {
+    query_interface(this, iid, obj, &IPARAM_VALUE_QUEUE_IID)
+}
+
+unsafe extern "system" fn param_value_queue_get_parameter_id(this: *mut c_void) -> u32 {
+    (*(this as *mut ParamValueQueue)).id
+}
+
+unsafe extern "system" fn param_value_queue_get_point_count(_: *mut c_void) -> i32 {
+    1
+}
+
+unsafe extern "system" fn param_value_queue_get_point(
+    this: *mut c_void,
+    index: i32,
+    sample_offset: *mut i32,
+    value: *mut f64,
+) -> TResult {
+    if index != 0 {
+        return K_INVALID_ARGUMENT;
+    }
+    *sample_offset = 0;
+    *value = (*(this as *mut ParamValueQueue)).value;
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn param_value_queue_add_point(
+    _: *mut c_void,
+    _: i32,
+    _: f64,
+    _: *mut i32,
+) -> TResult {
+    K_RESULT_FALSE
+}
+
+#[cfg(test)]
+mod tests {
+    use bmidi::{Channel, Note, U7};
+
+    use super::*;
+
+    #[test]
+    fn structs_match_vst3_layout() {
+        assert_eq!(std::mem::size_of::<Event>(), 48);
+        assert_eq!(std::mem::offset_of!(Event, data), 24);
+        assert_eq!(std::mem::size_of::<ParameterInfo>(), 792);
+        assert_eq!(std::mem::size_of::<PClassInfo2>(), 440);
+        assert_eq!(std::mem::size_of::<ProcessData>(), 80);
+        assert_eq!(
+            FUNKNOWN_IID,
+            [0, 0, 0, 0, 0, 0, 0, 0, 0xC0, 0, 0, 0, 0, 0, 0, 0x46]
+        );
+    }
+
+    #[test]
+    fn event_list_sends_notes_through_vtable() {
+        let mut events = EventList::new();
+        events.push(&MidiMessage::NoteOn(Channel::Ch2, Note::C4, U7::MAX), 3);
+        events.push(&MidiMessage::TimingClock, 4);
+        events.push(&MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MIN), 5);
+        let this = &mut events as *mut EventList as *mut c_void;
+        let vtbl = &EVENT_LIST_VTBL;
+        unsafe {
+            assert_eq!((vtbl.get_event_count)(this), 2);
+            let mut event: Event = std::mem::zeroed();
+            assert_eq!((vtbl.get_event)(this, 0, &mut event), K_RESULT_OK);
+            assert_eq!(
+                (event.event_type, event.sample_offset),
+                (K_NOTE_ON_EVENT, 3)
+            );
+            assert_eq!(event.data.note_on.channel, 1);
+            assert_eq!(event.data.note_on.pitch, 60);
+            assert_eq!(event.data.note_on.velocity, 1.0);
+            assert_eq!((vtbl.get_event)(this, 1, &mut event), K_RESULT_OK);
+            assert_eq!(
+                (event.event_type, event.sample_offset),
+                (K_NOTE_OFF_EVENT, 5)
+            );
+            assert_eq!((vtbl.get_event)(this, 2, &mut event), K_INVALID_ARGUMENT);
+        }
+    }
+
+    #[test]
+    fn parameter_changes_keep_last_value_per_param() {
+        let mut changes = ParameterChanges::new(2);
+        changes.set(7, 0.25);
+        changes.set(9, 0.5);
+        changes.set(7, 0.75);
+        changes.set(11, 1.0);
+        let this = &mut changes as *mut ParameterChanges as *mut c_void;
+        let vtbl = &PARAMETER_CHANGES_VTBL;
+        let queue_vtbl = &PARAM_VALUE_QUEUE_VTBL;
+        let mut points = Vec::new();
+        unsafe {
+            for idx in 0..(vtbl.get_parameter_count)(this) {
+                let queue = (vtbl.get_parameter_data)(this, idx);
+                let (mut offset, mut value) = (-1, 0.0);
+                assert_eq!((queue_vtbl.get_point_count)(queue), 1);
+                assert_eq!(
+                    (queue_vtbl.get_point)(queue, 0, &mut offset, &mut value),
+                    K_RESULT_OK
+                );
+                points.push(((queue_vtbl.get_parameter_id)(queue), offset, value));
+            }
+            assert!((vtbl.get_parameter_data)(this, 2).is_null());
+        }
+        assert_eq!(points, vec![(7, 0, 0.75), (9, 0, 0.5)]);
+        changes.clear();
+        assert_eq!(unsafe { (vtbl.get_parameter_count)(this) }, 0);
+    }
+
+    #[test]
+    fn host_application_only_provides_its_interfaces() {
+        let this = &HOST_APPLICATION as *const HostApplication as *mut c_void;
+        let mut obj = ptr::null_mut();
+        unsafe {
+            let unknown = &HOST_APPLICATION.vtbl.unknown;
+            assert_eq!(
+                (unknown.query_interface)(this, &IHOST_APPLICATION_IID, &mut obj),
+                K_RESULT_OK
+            );
+            assert_eq!(obj, this);
+            assert_eq!(
+                (unknown.query_interface)(this, &ICOMPONENT_IID, &mut obj),
+                K_NO_INTERFACE
+            );
+            assert!(obj.is_null());
+            let mut name = [0u16; 128];
+            (HOST_APPLICATION.vtbl.get_name)(this, name.as_mut_ptr());
+            assert_eq!(utf16_string(&name), "bats");
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+
+use crate::builder::AnyPlugin;
+
+/// A shared slot that holds a plugin while it moves between threads. A plugin that is replaced on
+/// the audio thread keeps playing while it fades out, so it is put into the slot once the fade is
+/// complete. Clones of the slot refer to the same plugin.
+#[derive(Clone, Default)]
+pub struct PluginSlot(Arc<Mutex<Option<AnyPlugin>>>);
+
+impl PluginSlot {
+    /// Create a new slot that holds `plugin`.
+    pub fn new(plugin: AnyPlugin) -> PluginSlot {
+        PluginSlot(Arc::new(Mutex::new(Some(plugin))))
+    }
+
+    /// Take the plugin out of the slot. Returns `None` if the slot is empty or is being used by
+    /// another thread. Does not block so it is safe to call on the audio thread.
+    pub fn try_take(&self) -> Option<AnyPlugin> {
+        self.try_lock().and_then(|mut p| p.take())
+    }
+
+    /// Put `plugin` into the slot. If the slot is already full or is being used by another
+    /// thread, `plugin` is returned. Does not block so it is safe to call on the audio thread.
+    pub fn try_put(&self, plugin: AnyPlugin) -> Result<(), AnyPlugin> {
+        match self.try_lock() {
+            Some(mut p) if p.is_none() => {
+                *p = Some(plugin);
+                Ok(())
+            }
+            _ => Err(plugin),
+        }
+    }
+
+    /// Take the plugin out of the slot, waiting for other threads that are using the slot. Returns
+    /// `None` if the slot is empty.
+    pub fn take(&self) -> Option<AnyPlugin> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+
+    /// Call `f` with the plugin in the slot, waiting for other threads that are using the slot.
+    pub fn with_plugin<R>(&self, f: impl FnOnce(Option<&AnyPlugin>) -> R) -> R {
+        f(self.0.lock().unwrap_or_else(|e| e.into_inner()).as_ref())
+    }
+
+    /// Returns true if `self` and `other` refer to the same slot.
+    pub fn is_same(&self, other: &PluginSlot) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    fn try_lock(&self) -> Option<MutexGuard<'_, Option<AnyPlugin>>> {
+        match self.0.try_lock() {
+            Ok(p) => Some(p),
+            Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+}
+
+impl PartialEq for PluginSlot {
+    /// Slots are equal if they refer to the same slot.
+    fn eq(&self, other: &PluginSlot) -> bool {
+        self.is_same(other)
+    }
+}
+
+impl std::fmt::Debug for PluginSlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.try_lock() {
+            Some(p) => f.debug_tuple("PluginSlot").field(&*p).finish(),
+            None => f.debug_tuple("PluginSlot").field(&"<locked>").finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plugin_moves_through_clones_of_the_slot() {
+        let slot = PluginSlot::new(AnyPlugin::default());
+        let other = slot.clone();
+        assert_eq!(other.try_take(), Some(AnyPlugin::default()));
+        assert_eq!(slot.try_take(), None);
+        assert_eq!(slot.try_put(AnyPlugin::default()), Ok(()));
+        assert_eq!(
+            other.try_put(AnyPlugin::default()),
+            Err(AnyPlugin::default())
+        );
+        assert_eq!(other.take(), Some(AnyPlugin::default()));
+    }
+
+    #[test]
+    fn slots_are_equal_only_to_their_clones() {
+        let slot = PluginSlot::default();
+        assert_eq!(slot, slot.clone());
+        assert_ne!(slot, PluginSlot::default());
+    }
+
+    #[test]
+    fn locked_slot_is_not_taken_without_blocking() {
+        let slot = PluginSlot::new(AnyPlugin::default());
+        let _guard = slot.0.lock().unwrap();
+        assert_eq!(slot.try_take(), None);
+        assert_eq!(
+            slot.try_put(AnyPlugin::default()),
+            Err(AnyPlugin::default())
+        );
+    }
+}
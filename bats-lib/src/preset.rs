@@ -0,0 +1,170 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::builder::{AnyPlugin, PluginBuilder};
+
+/// A named set of param values for a plugin.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    /// The name of the preset.
+    pub name: String,
+    /// The type of plugin that the preset is for.
+    pub plugin: PluginBuilder,
+    /// The param values.
+    pub params: Vec<PresetParam>,
+}
+
+/// The value for a single param within a preset.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PresetParam {
+    /// The id of the param.
+    pub id: u32,
+    /// The value of the param.
+    pub value: f32,
+}
+
+impl PresetParam {
+    /// Get the current value of every param of `plugin`.
+    pub fn all(plugin: &AnyPlugin) -> impl '_ + Iterator<Item = PresetParam> {
+        let p = plugin.plugin();
+        p.metadata().params.iter().map(move |param| PresetParam {
+            id: param.id,
+            value: p.param(param.id),
+        })
+    }
+}
+
+impl Preset {
+    /// Create a preset from the current param values of `plugin`.
+    pub fn from_plugin(name: String, plugin: &AnyPlugin) -> Preset {
+        Preset {
+            name,
+            plugin: PluginBuilder::from_bats(plugin),
+            params: PresetParam::all(plugin).collect(),
+        }
+    }
+
+    /// Apply the preset to `plugin` and replace the values in the preset with the previous values
+    /// from `plugin`. Returns an error if the preset is for a different type of plugin.
+    ///
+    /// This does not allocate so it is safe to call from the audio thread.
+    pub fn swap_with_plugin(&mut self, plugin: &mut AnyPlugin) -> Result<()> {
+        if PluginBuilder::from_bats(plugin) != self.plugin {
+            return Err(anyhow!(
+                "preset {:?} is for plugin {} but got plugin {}",
+                self.name,
+                self.plugin.name(),
+                plugin.plugin().metadata().name
+            ));
+        }
+        let p = plugin.plugin_mut();
+        for param in self.params.iter_mut() {
+            let previous = p.param(param.id);
+            p.set_param(param.id, param.value);
+            param.value = previous;
+        }
+        Ok(())
+    }
+
+    /// The path to the preset within the presets directory `dir`.
+    pub fn path(&self, dir: &Path) -> PathBuf {
+        dir.join(self.plugin.name())
+            .join(format!("{}.toml", self.name))
+    }
+
+    /// Save the preset to the presets directory `dir`.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf> {
+        if self.name.is_empty() || self.name.contains(['/', '\\']) || self.name.starts_with('.') {
+            return Err(anyhow!("{:?} is not a valid preset name", self.name));
+        }
+        let path = self.path(dir);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, toml::to_string(self)?)?;
+        Ok(path)
+    }
+
+    /// Load the preset from `path`.
+    pub fn load(path: &Path) -> Result<Preset> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// Load all presets for `plugin` from the presets directory `dir`. The presets are sorted by
+    /// name. Files that fail to load are skipped.
+    pub fn load_all(dir: &Path, plugin: PluginBuilder) -> Vec<Preset> {
+        let entries = match std::fs::read_dir(dir.join(plugin.name())) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        let mut presets: Vec<Preset> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "toml"))
+            .filter_map(|p| Preset::load(&p).ok())
+            .filter(|p| p.plugin == plugin)
+            .collect();
+        presets.sort_by(|a, b| a.name.cmp(&b.name));
+        presets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bats_dsp::sample_rate::SampleRate;
+
+    use super::*;
+    use crate::plugin::{empty::Empty, toof::Toof};
+
+    #[test]
+    fn swap_with_plugin_sets_params_and_keeps_old_values() {
+        let mut plugin = AnyPlugin::Toof(Toof::new(SampleRate::new(44100.0)));
+        let original = Preset::from_plugin("preset".to_string(), &plugin);
+        let mut other = plugin.clone();
+        for param in other.plugin().metadata().params {
+            other.plugin_mut().set_param(param.id, param.max_value);
+        }
+        let expected = Preset::from_plugin("preset".to_string(), &other);
+        assert_ne!(original, expected);
+
+        let mut preset = expected.clone();
+        preset.swap_with_plugin(&mut plugin).unwrap();
+        assert_eq!(Preset::from_plugin("preset".to_string(), &plugin), expected);
+        assert_eq!(preset, original);
+    }
+
+    #[test]
+    fn swap_with_different_plugin_returns_error() {
+        let mut preset = Preset::from_plugin(
+            "toof".to_string(),
+            &AnyPlugin::Toof(Toof::new(SampleRate::new(44100.0))),
+        );
+        let before = preset.clone();
+        assert!(preset
+            .swap_with_plugin(&mut AnyPlugin::Empty(Empty))
+            .is_err());
+        assert_eq!(preset, before);
+    }
+
+    #[test]
+    fn save_and_load_all() {
+        let dir = std::env::temp_dir().join(format!("bats-presets-{}", std::process::id()));
+        let plugin = AnyPlugin::Toof(Toof::new(SampleRate::new(44100.0)));
+        let b = Preset::from_plugin("b".to_string(), &plugin);
+        let a = Preset::from_plugin("a".to_string(), &plugin);
+        b.save(&dir).unwrap();
+        a.save(&dir).unwrap();
+        assert_eq!(Preset::load_all(&dir, PluginBuilder::Toof), vec![a, b]);
+        assert_eq!(Preset::load_all(&dir, PluginBuilder::Empty), vec![]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn save_with_invalid_name_returns_error() {
+        let preset = Preset::from_plugin("../escape".to_string(), &AnyPlugin::Empty(Empty));
+        assert!(preset.save(&std::env::temp_dir()).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc,
+};
+
+/// The audio that is recorded by a `Recorder`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RecordSource {
+    /// The master output.
+    #[default]
+    Master,
+    /// The output of a single track, before the track volume is applied.
+    Track(usize),
+}
+
+/// Sends audio from the audio thread to another thread through a lock-free ring buffer. Pushing
+/// audio never locks or allocates.
+///
+/// Clones share the same ring buffer, so only a single clone should push audio.
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    /// The audio to record.
+    pub source: RecordSource,
+    /// The ring buffer shared with the `RecordingReader`.
+    shared: Arc<Shared>,
+}
+
+/// Reads the audio pushed by a `Recorder`.
+#[derive(Debug)]
+pub struct RecordingReader {
+    /// The ring buffer shared with the `Recorder`.
+    shared: Arc<Shared>,
+}
+
+/// The ring buffer shared by a `Recorder` and a `RecordingReader`.
+#[derive(Debug)]
+struct Shared {
+    /// The stereo frames. The left sample is stored in the upper 32 bits and the right sample is
+    /// stored in the lower 32 bits.
+    frames: Box<[AtomicU64]>,
+    /// The total number of frames that have been pushed. Only modified by the `Recorder`.
+    pushed: AtomicUsize,
+    /// The total number of frames that have been read. Only modified by the `RecordingReader`.
+    read: AtomicUsize,
+    /// The number of frames that were dropped because the ring buffer was full.
+    dropped: AtomicUsize,
+}
+
+impl Recorder {
+    /// Create a new recorder for `source` and the reader for its audio. The ring buffer holds
+    /// `capacity` frames. This allocates so it should not be called while processing audio.
+    pub fn new(source: RecordSource, capacity: usize) -> (Recorder, RecordingReader) {
+        let shared = Arc::new(Shared {
+            frames: (0..capacity.max(1)).map(|_| AtomicU64::new(0)).collect(),
+            pushed: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        });
+        let recorder = Recorder {
+            source,
+            shared: shared.clone(),
+        };
+        (recorder, RecordingReader { shared })
+    }
+
+    /// Push the frames in `left` and `right`. If there is not enough space for all the frames,
+    /// then none of them are pushed and they are counted as dropped.
+    pub fn push(&mut self, left: &[f32], right: &[f32]) {
+        let len = left.len().min(right.len());
+        let capacity = self.shared.frames.len();
+        let pushed = self.shared.pushed.load(Ordering::Relaxed);
+        let read = self.shared.read.load(Ordering::Acquire);
+        if capacity - (pushed - read) < len {
+            self.shared.dropped.fetch_add(len, Ordering::Relaxed);
+            return;
+        }
+        for (idx, (l, r)) in left.iter().zip(right.iter()).enumerate() {
+            let frame = (l.to_bits() as u64) << 32 | r.to_bits() as u64;
+            self.shared.frames[(pushed + idx) % capacity].store(frame, Ordering::Relaxed);
+        }
+        self.shared.pushed.store(pushed + len, Ordering::Release);
+    }
+}
+
+impl PartialEq for Recorder {
+    /// Recorders are equal if they record the same source into the same ring buffer.
+    fn eq(&self, other: &Recorder) -> bool {
+        self.source == other.source && Arc::ptr_eq(&self.shared, &other.shared)
+    }
+}
+
+impl RecordingReader {
+    /// Append all the frames that have been pushed but not read to `dst`. Returns the number of
+    /// frames that were read.
+    pub fn read_into(&mut self, dst: &mut Vec<(f32, f32)>) -> usize {
+        let capacity = self.shared.frames.len();
+        let read = self.shared.read.load(Ordering::Relaxed);
+        let pushed = self.shared.pushed.load(Ordering::Acquire);
+        dst.extend((read..pushed).map(|idx| {
+            let frame = self.shared.frames[idx % capacity].load(Ordering::Relaxed);
+            (
+                f32::from_bits((frame >> 32) as u32),
+                f32::from_bits(frame as u32),
+            )
+        }));
+        self.shared.read.store(pushed, Ordering::Release);
+        pushed - read
+    }
+
+    /// The number of frames that were dropped because the reader did not keep up.
+    pub fn dropped_frames(&self) -> usize {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pushed_frames_are_read_in_order() {
+        let (mut recorder, mut reader) = Recorder::new(RecordSource::Master, 8);
+        recorder.push(&[1.0, 2.0], &[-1.0, -2.0]);
+        recorder.push(&[3.0], &[-3.0]);
+        let mut frames = Vec::new();
+        assert_eq!(reader.read_into(&mut frames), 3);
+        assert_eq!(frames, vec![(1.0, -1.0), (2.0, -2.0), (3.0, -3.0)]);
+        assert_eq!(reader.read_into(&mut frames), 0);
+        assert_eq!(reader.dropped_frames(), 0);
+    }
+
+    #[test]
+    fn frames_wrap_around_the_ring_buffer() {
+        let (mut recorder, mut reader) = Recorder::new(RecordSource::Master, 4);
+        let mut frames = Vec::new();
+        for idx in 0..10 {
+            let v = idx as f32;
+            recorder.push(&[v, v + 0.5], &[-v, -v - 0.5]);
+            frames.clear();
+            reader.read_into(&mut frames);
+            assert_eq!(frames, vec![(v, -v), (v + 0.5, -v - 0.5)]);
+        }
+    }
+
+    #[test]
+    fn frames_that_do_not_fit_are_dropped() {
+        let (mut recorder, mut reader) = Recorder::new(RecordSource::Track(1), 4);
+        recorder.push(&[1.0, 2.0, 3.0], &[1.0, 2.0, 3.0]);
+        recorder.push(&[4.0, 5.0], &[4.0, 5.0]);
+        let mut frames = Vec::new();
+        reader.read_into(&mut frames);
+        assert_eq!(frames, vec![(1.0, 1.0), (2.0, 2.0), (3.0, 3.0)]);
+        assert_eq!(reader.dropped_frames(), 2);
+    }
+}
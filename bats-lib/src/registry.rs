@@ -0,0 +1,322 @@
+use std::{
+    any::Any,
+    fmt::{self, Debug},
+    path::PathBuf,
+    sync::RwLock,
+};
+
+use anyhow::{anyhow, Result};
+use bats_dsp::sample_rate::SampleRate;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    builder::PluginBuilder,
+    plugin::{metadata::Metadata, BatsInstrument},
+};
+
+/// An instrument that can be stored in `AnyPlugin::Registered`. This is implemented for every
+/// instrument that implements `Clone`, `PartialEq`, `Debug`, and `Send`.
+pub trait DynInstrument: BatsInstrument + Debug + Send {
+    /// Clone the instrument into a new box.
+    fn clone_box(&self) -> Box<dyn DynInstrument>;
+
+    /// Returns true if `other` is the same type as `self` and is equal to it.
+    fn eq_dyn(&self, other: &dyn DynInstrument) -> bool;
+
+    /// Get the instrument as `Any` so that it can be downcast to its concrete type.
+    fn as_any(&self) -> &dyn Any;
+
+    /// Get the instrument as a `BatsInstrument`.
+    fn as_instrument(&self) -> &dyn BatsInstrument;
+
+    /// Get the instrument as a mutable `BatsInstrument`.
+    fn as_instrument_mut(&mut self) -> &mut dyn BatsInstrument;
+}
+
+impl<T> DynInstrument for T
+where
+    T: BatsInstrument + Clone + PartialEq + Debug + Send + 'static,
+{
+    fn clone_box(&self) -> Box<dyn DynInstrument> {
+        Box::new(self.clone())
+    }
+
+    fn eq_dyn(&self, other: &dyn DynInstrument) -> bool {
+        other.as_any().downcast_ref::<T>() == Some(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_instrument(&self) -> &dyn BatsInstrument {
+        self
+    }
+
+    fn as_instrument_mut(&mut self) -> &mut dyn BatsInstrument {
+        self
+    }
+}
+
+impl Clone for Box<dyn DynInstrument> {
+    fn clone(&self) -> Box<dyn DynInstrument> {
+        self.clone_box()
+    }
+}
+
+/// Builds an instrument that is not part of `bats-lib`.
+pub struct PluginFactory {
+    /// The metadata of the instrument. The name must be unique across all plugins.
+    pub metadata: &'static Metadata,
+    /// Build a new instance of the instrument. This is not called from the audio thread so it may
+    /// allocate.
+    pub build: Box<dyn Fn(SampleRate) -> Box<dyn DynInstrument> + Send + Sync>,
+    /// True if `set_sample_rate` can not be applied on the audio thread. The instrument keeps
+    /// running at its old sample rate and must be rebuilt for the new sample rate instead.
+    pub rebuild_on_sample_rate_change: bool,
+}
+
+/// A plugin that was added to a `PluginRegistry`. Registered plugins are saved by name so they
+/// can only be loaded once the same plugin is registered again.
+#[derive(Copy, Clone, Debug)]
+pub struct RegisteredPlugin(&'static PluginFactory);
+
+/// An instance of a registered plugin.
+#[derive(Clone, Debug)]
+pub struct RegisteredInstrument {
+    /// The plugin that built the instrument.
+    pub plugin: RegisteredPlugin,
+    /// The instrument.
+    pub instrument: Box<dyn DynInstrument>,
+}
+
+/// Holds the plugins that are added by other crates. Plugins can not be removed once they are
+/// registered.
+#[derive(Debug)]
+pub struct PluginRegistry {
+    /// The registered plugins in the order they were registered.
+    factories: RwLock<Vec<&'static PluginFactory>>,
+}
+
+/// The registry that is used for listing plugins and loading them by name.
+static GLOBAL_REGISTRY: PluginRegistry = PluginRegistry::new();
+
+impl PluginRegistry {
+    /// Create a new empty registry.
+    pub const fn new() -> PluginRegistry {
+        PluginRegistry {
+            factories: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// The registry that is used by `PluginBuilder` to list plugins and to load them by name.
+    pub fn global() -> &'static PluginRegistry {
+        &GLOBAL_REGISTRY
+    }
+
+    /// Register the plugin built by `factory`. Returns an error if a plugin with the same name
+    /// already exists.
+    pub fn register(&self, factory: PluginFactory) -> Result<RegisteredPlugin> {
+        let name = factory.metadata.name;
+        let is_builtin = PluginBuilder::ALL.iter().any(|b| b.name() == name);
+        let mut factories = self.factories.write().unwrap();
+        if is_builtin || factories.iter().any(|f| f.metadata.name == name) {
+            return Err(anyhow!("a plugin named {name:?} is already registered"));
+        }
+        // Registered plugins live for the rest of the program so the factory is leaked to allow
+        // `RegisteredPlugin` to be `Copy`.
+        let factory: &'static PluginFactory = Box::leak(Box::new(factory));
+        factories.push(factory);
+        Ok(RegisteredPlugin(factory))
+    }
+
+    /// Get all the registered plugins in the order they were registered.
+    pub fn all(&self) -> Vec<RegisteredPlugin> {
+        self.factories
+            .read()
+            .unwrap()
+            .iter()
+            .map(|f| RegisteredPlugin(f))
+            .collect()
+    }
+
+    /// Get the registered plugin with the given name.
+    pub fn find(&self, name: &str) -> Option<RegisteredPlugin> {
+        self.factories
+            .read()
+            .unwrap()
+            .iter()
+            .find(|f| f.metadata.name == name)
+            .map(|f| RegisteredPlugin(f))
+    }
+}
+
+/// Find the files and directories within `dirs` that end with `extension`, such as `"sf2"`, for
+/// plugins that are loaded from disk. Directories are searched recursively unless they end with
+/// `extension` and directories that do not exist are skipped.
+pub fn find_files(dirs: &[PathBuf], extension: &str) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending: Vec<PathBuf> = dirs.to_vec();
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for path in entries.flatten().map(|e| e.path()) {
+            let matches = path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case(extension));
+            if matches {
+                files.push(path);
+            } else if path.is_dir() {
+                pending.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+impl Debug for PluginFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PluginFactory")
+            .field("metadata", &self.metadata)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for PluginRegistry {
+    fn default() -> PluginRegistry {
+        PluginRegistry::new()
+    }
+}
+
+impl RegisteredPlugin {
+    /// The name of the plugin.
+    pub fn name(self) -> &'static str {
+        self.0.metadata.name
+    }
+
+    /// The metadata of the plugin.
+    pub fn metadata(self) -> &'static Metadata {
+        self.0.metadata
+    }
+
+    /// Build a new instance of the plugin.
+    pub fn build(self, sample_rate: SampleRate) -> Box<dyn DynInstrument> {
+        (self.0.build)(sample_rate)
+    }
+
+    /// True if instances must be rebuilt to change their sample rate. See
+    /// `PluginFactory::rebuild_on_sample_rate_change`.
+    pub fn rebuild_on_sample_rate_change(self) -> bool {
+        self.0.rebuild_on_sample_rate_change
+    }
+}
+
+impl PartialEq for RegisteredPlugin {
+    /// Registered plugins are equal if they have the same name.
+    fn eq(&self, other: &RegisteredPlugin) -> bool {
+        self.name() == other.name()
+    }
+}
+
+impl PartialEq for RegisteredInstrument {
+    fn eq(&self, other: &RegisteredInstrument) -> bool {
+        self.plugin == other.plugin && self.instrument.eq_dyn(other.instrument.as_ref())
+    }
+}
+
+impl Serialize for RegisteredPlugin {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for RegisteredPlugin {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        PluginRegistry::global()
+            .find(&name)
+            .ok_or_else(|| serde::de::Error::custom(format!("plugin {name:?} is not registered")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::AnyPlugin,
+        plugin::{metadata::PluginCategory, toof::Toof},
+    };
+
+    use super::*;
+
+    const METADATA: Metadata = Metadata {
+        name: "registry test toof",
+        category: PluginCategory::Instrument,
+        tags: &[],
+        params: Toof::METADATA.params,
+        pages: Toof::METADATA.pages,
+    };
+
+    fn factory() -> PluginFactory {
+        PluginFactory {
+            metadata: &METADATA,
+            build: Box::new(|sample_rate| Toof::new(sample_rate)),
+            rebuild_on_sample_rate_change: false,
+        }
+    }
+
+    #[test]
+    fn registered_plugins_are_found_by_name() {
+        let registry = PluginRegistry::new();
+        let plugin = registry.register(factory()).unwrap();
+        assert_eq!(plugin.name(), "registry test toof");
+        assert_eq!(registry.all(), vec![plugin]);
+        assert_eq!(registry.find("registry test toof"), Some(plugin));
+        assert_eq!(registry.find("toof"), None);
+    }
+
+    #[test]
+    fn names_must_be_unique() {
+        let registry = PluginRegistry::new();
+        registry.register(factory()).unwrap();
+        assert!(registry.register(factory()).is_err());
+        let builtin = PluginFactory {
+            metadata: &Toof::METADATA,
+            ..factory()
+        };
+        assert!(registry.register(builtin).is_err());
+    }
+
+    #[test]
+    fn registered_plugin_round_trips_through_built_plugin() {
+        let registry = PluginRegistry::new();
+        let builder = PluginBuilder::Registered(registry.register(factory()).unwrap());
+        let mut plugin = builder.build(SampleRate::new(44100.0));
+        assert_eq!(plugin.clone(), plugin);
+        assert_eq!(PluginBuilder::from_bats(&plugin), builder);
+        let other = builder.build(SampleRate::new(44100.0));
+        plugin.plugin_mut().set_param(2, 432.0);
+        assert_ne!(plugin, other);
+        assert_ne!(plugin, AnyPlugin::Toof(Toof::new(SampleRate::new(44100.0))));
+    }
+
+    #[test]
+    fn find_files_searches_nested_directories() {
+        let dir = std::env::temp_dir().join(format!("bats-find-files-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("vendor/Synth.vst3/Contents")).unwrap();
+        std::fs::write(dir.join("Single.VST3"), []).unwrap();
+        std::fs::write(dir.join("readme.txt"), []).unwrap();
+        assert_eq!(
+            find_files(&[dir.clone(), dir.join("does-not-exist")], "vst3"),
+            vec![dir.join("Single.VST3"), dir.join("vendor/Synth.vst3")]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unregistered_plugin_can_not_be_loaded() {
+        let err = toml::from_str::<PluginBuilder>(r#"Registered = "not registered""#);
+        assert!(err.is_err());
+    }
+}
@@ -0,0 +1,127 @@
+use std::fmt;
+
+use bats_dsp::buffers::Buffers;
+
+use crate::track::Track;
+
+/// An error from routing the output of a track into the input of another track.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RoutingError {
+    /// The track with the id does not exist.
+    TrackNotFound(usize),
+    /// The track would take its own output as input, either directly or through other tracks.
+    Cycle { track_id: usize, input: usize },
+}
+
+impl fmt::Display for RoutingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoutingError::TrackNotFound(track_id) => write!(f, "track {track_id} does not exist"),
+            RoutingError::Cycle { track_id, input } => write!(
+                f,
+                "routing track {input} into track {track_id} would create a cycle"
+            ),
+        }
+    }
+}
+
+/// Check that the output of the track at `input` can be routed into the track at `track_id`
+/// without creating a cycle. `input_of` returns the current input of each of the `track_count`
+/// tracks.
+pub fn check_input(
+    track_count: usize,
+    input_of: impl Fn(usize) -> Option<usize>,
+    track_id: usize,
+    input: usize,
+) -> Result<(), RoutingError> {
+    for id in [track_id, input] {
+        if id >= track_count {
+            return Err(RoutingError::TrackNotFound(id));
+        }
+    }
+    let mut upstream = Some(input);
+    // Every track has at most one input so a path without cycles visits each track once.
+    for _ in 0..track_count {
+        match upstream {
+            Some(id) if id == track_id => return Err(RoutingError::Cycle { track_id, input }),
+            Some(id) => upstream = input_of(id).filter(|id| *id < track_count),
+            None => return Ok(()),
+        }
+    }
+    Err(RoutingError::Cycle { track_id, input })
+}
+
+/// Get the track at `track_id` and the output of the track that it takes its input from.
+pub fn track_and_input(tracks: &mut [Track], track_id: usize) -> (&mut Track, Option<&Buffers>) {
+    match tracks[track_id].input {
+        Some(input) if input < track_id => {
+            let (before, after) = tracks.split_at_mut(track_id);
+            (&mut after[0], Some(&before[input].output))
+        }
+        Some(input) if track_id < input && input < tracks.len() => {
+            let (before, after) = tracks.split_at_mut(input);
+            (&mut before[track_id], Some(&after[0].output))
+        }
+        _ => (&mut tracks[track_id], None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracks(inputs: &[Option<usize>]) -> Vec<Track> {
+        inputs
+            .iter()
+            .map(|input| Track {
+                input: *input,
+                ..Track::new(4)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn check_input_detects_cycles() {
+        let inputs = [Some(1), Some(2), None];
+        let check_input = |track_id, input| check_input(3, |id| inputs[id], track_id, input);
+        assert_eq!(
+            check_input(2, 1),
+            Err(RoutingError::Cycle {
+                track_id: 2,
+                input: 1
+            })
+        );
+        assert_eq!(
+            check_input(2, 2),
+            Err(RoutingError::Cycle {
+                track_id: 2,
+                input: 2
+            })
+        );
+        assert_eq!(check_input(2, 3), Err(RoutingError::TrackNotFound(3)));
+        assert_eq!(
+            check_input(1, 0),
+            Err(RoutingError::Cycle {
+                track_id: 1,
+                input: 0
+            })
+        );
+        assert_eq!(check_input(0, 2), Ok(()));
+    }
+
+    #[test]
+    fn track_and_input_returns_output_of_input() {
+        let mut tracks = tracks(&[None, Some(0), Some(3), None]);
+        tracks[0].output.set(0, (1.0, 1.0));
+        tracks[3].output.set(0, (3.0, 3.0));
+        assert_eq!(track_and_input(&mut tracks, 0).1, None);
+        assert_eq!(
+            track_and_input(&mut tracks, 1).1.unwrap().get(0),
+            (1.0, 1.0)
+        );
+        assert_eq!(
+            track_and_input(&mut tracks, 2).1.unwrap().get(0),
+            (3.0, 3.0)
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use bats_dsp::sample_rate::SampleRate;
+
+use crate::track::Track;
+
+/// The value of a plugin param of a track.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SceneParam {
+    /// The id of the track.
+    pub track_id: usize,
+    /// The id of the param.
+    pub param_id: u32,
+    /// The value of the param.
+    pub value: f32,
+}
+
+/// The param values of all tracks at a point in time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Scene {
+    /// The param values.
+    pub params: Vec<SceneParam>,
+}
+
+/// A param that is interpolated between its value in two scenes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MorphParam {
+    /// The id of the track.
+    pub track_id: usize,
+    /// The id of the param.
+    pub param_id: u32,
+    /// The value when the morph is at `0.0`.
+    pub a: f32,
+    /// The value when the morph is at `1.0`.
+    pub b: f32,
+}
+
+/// Morphs the params of all tracks between two scenes. The position moves towards the target at a
+/// constant rate and the params are interpolated once per buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneMorph {
+    /// The params to morph.
+    pub params: Vec<MorphParam>,
+    /// The current position within `[0.0, 1.0]`. `0.0` is the first scene and `1.0` is the
+    /// second.
+    pub position: f32,
+    /// The position to move towards.
+    pub target: f32,
+    /// The number of seconds it takes to morph from one scene to the other.
+    pub seconds: f32,
+    /// The position that was last applied to the params or `None` if the params should be set on
+    /// the next buffer.
+    pub applied: Option<f32>,
+}
+
+impl Default for SceneMorph {
+    fn default() -> SceneMorph {
+        SceneMorph {
+            params: Vec::new(),
+            position: 0.0,
+            target: 0.0,
+            seconds: SceneMorph::DEFAULT_SECONDS,
+            applied: None,
+        }
+    }
+}
+
+impl MorphParam {
+    /// Create the params that morph from scene `a` to scene `b`. Params that are not in both
+    /// scenes are not morphed.
+    pub fn between(a: &Scene, b: &Scene) -> Vec<MorphParam> {
+        a.params
+            .iter()
+            .filter_map(|pa| {
+                let pb = b
+                    .params
+                    .iter()
+                    .find(|pb| pb.track_id == pa.track_id && pb.param_id == pa.param_id)?;
+                Some(MorphParam {
+                    track_id: pa.track_id,
+                    param_id: pa.param_id,
+                    a: pa.value,
+                    b: pb.value,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the value of the param at morph `position`.
+    pub fn value(&self, position: f32) -> f32 {
+        self.a + (self.b - self.a) * position
+    }
+}
+
+impl SceneMorph {
+    /// The default number of seconds it takes to morph from one scene to the other.
+    pub const DEFAULT_SECONDS: f32 = 1.0;
+
+    /// The maximum number of seconds it takes to morph from one scene to the other.
+    pub const MAX_SECONDS: f32 = 60.0;
+
+    /// Move the position towards the target by the duration of `frames` and set the params within
+    /// `tracks` if the position has changed since it was last applied. Params on tracks that do
+    /// not exist are ignored.
+    pub fn process(&mut self, frames: usize, sample_rate: SampleRate, tracks: &mut [Track]) {
+        if self.seconds > 0.0 {
+            let step = frames as f32 * sample_rate.seconds_per_sample() / self.seconds;
+            self.position += (self.target - self.position).clamp(-step, step);
+        } else {
+            self.position = self.target;
+        }
+        if self.applied == Some(self.position) {
+            return;
+        }
+        for p in self.params.iter() {
+            if let Some(t) = tracks.get_mut(p.track_id) {
+                t.plugin
+                    .plugin_mut()
+                    .set_param(p.param_id, p.value(self.position));
+            }
+        }
+        self.applied = Some(self.position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{builder::AnyPlugin, plugin::toof::Toof};
+
+    use super::*;
+
+    fn tracks() -> Vec<Track> {
+        (0..2)
+            .map(|_| Track {
+                plugin: AnyPlugin::Toof(Toof::new(SampleRate::new(44100.0))),
+                ..Track::new(64)
+            })
+            .collect()
+    }
+
+    fn scene(values: &[(usize, u32, f32)]) -> Scene {
+        Scene {
+            params: values
+                .iter()
+                .map(|(track_id, param_id, value)| SceneParam {
+                    track_id: *track_id,
+                    param_id: *param_id,
+                    value: *value,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn between_only_contains_params_in_both_scenes() {
+        let a = scene(&[(0, 2, 100.0), (1, 2, 200.0)]);
+        let b = scene(&[(1, 2, 400.0), (0, 3, 0.5)]);
+        assert_eq!(
+            MorphParam::between(&a, &b),
+            vec![MorphParam {
+                track_id: 1,
+                param_id: 2,
+                a: 200.0,
+                b: 400.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn process_moves_towards_target_over_time() {
+        let mut tracks = tracks();
+        let mut morph = SceneMorph {
+            params: MorphParam::between(
+                &scene(&[(0, 2, 100.0), (5, 2, 100.0)]),
+                &scene(&[(0, 2, 500.0), (5, 2, 500.0)]),
+            ),
+            target: 1.0,
+            seconds: 1.0,
+            ..SceneMorph::default()
+        };
+        let sample_rate = SampleRate::new(100.0);
+        morph.process(25, sample_rate, &mut tracks);
+        assert_eq!(morph.position, 0.25);
+        assert_eq!(tracks[0].plugin.plugin().param(2), 200.0);
+        morph.process(100, sample_rate, &mut tracks);
+        assert_eq!(morph.position, 1.0);
+        assert_eq!(tracks[0].plugin.plugin().param(2), 500.0);
+
+        morph.target = 0.5;
+        morph.process(25, sample_rate, &mut tracks);
+        assert_eq!(morph.position, 0.75);
+        assert_eq!(tracks[0].plugin.plugin().param(2), 400.0);
+    }
+
+    #[test]
+    fn process_jumps_to_target_with_zero_seconds() {
+        let mut tracks = tracks();
+        let mut morph = SceneMorph {
+            params: MorphParam::between(&scene(&[(1, 2, 100.0)]), &scene(&[(1, 2, 500.0)])),
+            target: 1.0,
+            seconds: 0.0,
+            ..SceneMorph::default()
+        };
+        morph.process(1, SampleRate::new(44100.0), &mut tracks);
+        assert_eq!(tracks[1].plugin.plugin().param(2), 500.0);
+    }
+
+    #[test]
+    fn process_does_nothing_if_position_is_unchanged() {
+        let mut tracks = tracks();
+        let mut morph = SceneMorph {
+            params: MorphParam::between(&scene(&[(0, 2, 100.0)]), &scene(&[(0, 2, 500.0)])),
+            ..SceneMorph::default()
+        };
+        morph.process(64, SampleRate::new(44100.0), &mut tracks);
+        assert_eq!(tracks[0].plugin.plugin().param(2), 100.0);
+        tracks[0].plugin.plugin_mut().set_param(2, 300.0);
+        morph.process(64, SampleRate::new(44100.0), &mut tracks);
+        assert_eq!(tracks[0].plugin.plugin().param(2), 300.0);
+    }
+}
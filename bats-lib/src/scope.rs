@@ -0,0 +1,152 @@
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Sends a downsampled copy of audio from the audio thread to another thread, for example to draw
+/// it as an oscilloscope. Each point holds the lowest and highest sample of both channels over
+/// `frames_per_point` frames. Pushing audio never locks or allocates.
+///
+/// Unlike `Recorder`, points are never dropped. The oldest points are overwritten once the ring
+/// buffer is full so that the reader always sees the most recent audio.
+///
+/// Clones share the same ring buffer, so only a single clone should push audio.
+#[derive(Clone, Debug)]
+pub struct Scope {
+    /// The number of frames that are combined into each point.
+    frames_per_point: usize,
+    /// The lowest and highest sample of the point that is being filled.
+    current: (f32, f32),
+    /// The number of frames in the point that is being filled.
+    current_frames: usize,
+    /// The ring buffer shared with the `ScopeReader`.
+    shared: Arc<Shared>,
+}
+
+/// Reads the points pushed by a `Scope`.
+#[derive(Debug)]
+pub struct ScopeReader {
+    /// The number of frames that are combined into each point.
+    frames_per_point: usize,
+    /// The ring buffer shared with the `Scope`.
+    shared: Arc<Shared>,
+}
+
+/// The ring buffer shared by a `Scope` and a `ScopeReader`.
+#[derive(Debug)]
+struct Shared {
+    /// The points. The lowest sample is stored in the upper 32 bits and the highest sample is
+    /// stored in the lower 32 bits.
+    points: Box<[AtomicU64]>,
+    /// The total number of points that have been pushed.
+    pushed: AtomicUsize,
+}
+
+impl Scope {
+    /// Create a new scope that combines `frames_per_point` frames into each point and the reader
+    /// for its points. The ring buffer holds `capacity` points. This allocates so it should not
+    /// be called while processing audio.
+    pub fn new(frames_per_point: usize, capacity: usize) -> (Scope, ScopeReader) {
+        let frames_per_point = frames_per_point.max(1);
+        let shared = Arc::new(Shared {
+            points: (0..capacity.max(1)).map(|_| AtomicU64::new(0)).collect(),
+            pushed: AtomicUsize::new(0),
+        });
+        let scope = Scope {
+            frames_per_point,
+            current: (f32::INFINITY, f32::NEG_INFINITY),
+            current_frames: 0,
+            shared: shared.clone(),
+        };
+        let reader = ScopeReader {
+            frames_per_point,
+            shared,
+        };
+        (scope, reader)
+    }
+
+    /// Push the frames in `left` and `right`. Frames that do not complete a point are kept until
+    /// the next push.
+    pub fn push(&mut self, left: &[f32], right: &[f32]) {
+        for (l, r) in left.iter().zip(right.iter()) {
+            self.current = (self.current.0.min(l.min(*r)), self.current.1.max(l.max(*r)));
+            self.current_frames += 1;
+            if self.current_frames == self.frames_per_point {
+                self.push_point();
+            }
+        }
+    }
+
+    /// Push the point that is being filled and start a new one.
+    fn push_point(&mut self) {
+        let (low, high) = self.current;
+        let point = (low.to_bits() as u64) << 32 | high.to_bits() as u64;
+        let pushed = self.shared.pushed.load(Ordering::Relaxed);
+        self.shared.points[pushed % self.shared.points.len()].store(point, Ordering::Relaxed);
+        self.shared.pushed.store(pushed + 1, Ordering::Release);
+        self.current = (f32::INFINITY, f32::NEG_INFINITY);
+        self.current_frames = 0;
+    }
+}
+
+impl PartialEq for Scope {
+    /// Scopes are equal if they push into the same ring buffer.
+    fn eq(&self, other: &Scope) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+}
+
+impl ScopeReader {
+    /// The number of frames that are combined into each point.
+    pub fn frames_per_point(&self) -> usize {
+        self.frames_per_point
+    }
+
+    /// Replace the contents of `dst` with the most recent `count` points, oldest first, as
+    /// `(lowest, highest)` samples. Fewer points are returned if fewer have been pushed or if
+    /// `count` is larger than the ring buffer.
+    pub fn read_latest(&self, dst: &mut Vec<(f32, f32)>, count: usize) {
+        let capacity = self.shared.points.len();
+        let pushed = self.shared.pushed.load(Ordering::Acquire);
+        let start = pushed - count.min(capacity).min(pushed);
+        dst.clear();
+        dst.extend((start..pushed).map(|idx| {
+            let point = self.shared.points[idx % capacity].load(Ordering::Relaxed);
+            (
+                f32::from_bits((point >> 32) as u32),
+                f32::from_bits(point as u32),
+            )
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_hold_lowest_and_highest_sample_of_both_channels() {
+        let (mut scope, reader) = Scope::new(2, 8);
+        scope.push(&[0.5, -0.25, 0.1], &[0.0, 0.75, 0.2]);
+        let mut points = Vec::new();
+        reader.read_latest(&mut points, 8);
+        assert_eq!(points, vec![(-0.25, 0.75)]);
+        scope.push(&[-1.0], &[0.0]);
+        reader.read_latest(&mut points, 8);
+        assert_eq!(points, vec![(-0.25, 0.75), (-1.0, 0.2)]);
+    }
+
+    #[test]
+    fn oldest_points_are_overwritten() {
+        let (mut scope, reader) = Scope::new(1, 4);
+        for idx in 0..6 {
+            let v = idx as f32;
+            scope.push(&[v], &[v]);
+        }
+        let mut points = Vec::new();
+        reader.read_latest(&mut points, 100);
+        assert_eq!(points, vec![(2.0, 2.0), (3.0, 3.0), (4.0, 4.0), (5.0, 5.0)]);
+        reader.read_latest(&mut points, 2);
+        assert_eq!(points, vec![(4.0, 4.0), (5.0, 5.0)]);
+    }
+}
@@ -0,0 +1,629 @@
+use std::ops::Range;
+
+use bats_dsp::position::Position;
+use bmidi::{Channel, MidiMessage, U7};
+use serde::{Deserialize, Serialize};
+
+use crate::plugin::MidiEvent;
+
+/// A midi sequence made up of whole notes and other midi messages. The notes are converted to
+/// note on and note off events that are kept sorted by position so they can be found with a
+/// binary search during playback.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(into = "SequenceData", from = "SequenceData")]
+pub struct Sequence {
+    /// The notes sorted by start. Notes with the same start are kept in insertion order.
+    notes: Vec<Note>,
+    /// The midi events to play. Contains the note on and note off for every note along with
+    /// all other midi messages, sorted by position.
+    events: Vec<MidiEvent>,
+}
+
+/// A note within a sequence.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Note {
+    /// The position of the note on.
+    pub start: Position,
+    /// The length of the note.
+    pub length: Position,
+    /// The midi channel.
+    pub channel: Channel,
+    /// The pitch of the note.
+    pub pitch: bmidi::Note,
+    /// The note on velocity.
+    pub velocity: U7,
+}
+
+/// How a `Sequence` is saved. The note ons and note offs are not saved since they are recreated
+/// from the notes.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct SequenceData {
+    /// The notes.
+    #[serde(default)]
+    notes: Vec<Note>,
+    /// The midi messages that are not note ons or note offs.
+    #[serde(default)]
+    messages: Vec<MidiEvent>,
+}
+
+/// An item that can be added to a sequence.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SequenceItem {
+    /// A whole note.
+    Note(Note),
+    /// A midi message that is not a note on or note off.
+    Message(MidiEvent),
+}
+
+impl Note {
+    /// The shortest length a note can have.
+    pub const MIN_LENGTH: Position = Position::from_bits(1);
+
+    /// The length of notes that are created from a note on without a note off.
+    pub const DEFAULT_LENGTH: Position = Position::from_bits(1 << 32);
+
+    /// Get the position of the note off. Note offs that land past the end of the loop are played
+    /// when the transport wraps around the loop.
+    pub fn end(&self) -> Position {
+        self.start + self.length
+    }
+
+    /// The note on event.
+    fn note_on(&self) -> MidiEvent {
+        MidiEvent {
+            position: self.start,
+            midi: MidiMessage::NoteOn(self.channel, self.pitch, self.velocity),
+        }
+    }
+
+    /// The note off event.
+    fn note_off(&self) -> MidiEvent {
+        MidiEvent {
+            position: self.end(),
+            midi: MidiMessage::NoteOff(self.channel, self.pitch, U7::MIN),
+        }
+    }
+
+    /// Clamp the length so the note off never lands on or before the note on.
+    fn clamp_length(mut self) -> Note {
+        self.length = self.length.max(Note::MIN_LENGTH);
+        self
+    }
+}
+
+impl Sequence {
+    /// Create a new empty sequence. This does not allocate.
+    pub fn new() -> Sequence {
+        Sequence {
+            notes: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Create a new empty sequence that can hold `capacity` midi events without allocating. Each
+    /// note takes up 2 events.
+    pub fn with_capacity(capacity: usize) -> Sequence {
+        Sequence {
+            notes: Vec::with_capacity(capacity / 2),
+            events: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// The number of midi events the sequence can hold without allocating.
+    pub fn capacity(&self) -> usize {
+        self.events.capacity().min(self.notes.capacity() * 2)
+    }
+
+    /// Reserve space for at least `additional` more midi events.
+    pub fn reserve(&mut self, additional: usize) {
+        self.notes.reserve(additional / 2);
+        self.events.reserve(additional);
+    }
+
+    /// The number of midi events in the sequence.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Returns true if the sequence has no notes or messages.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Get the notes sorted by start.
+    pub fn notes(&self) -> &[Note] {
+        &self.notes
+    }
+
+    /// Get the midi events to play sorted by position. At the same position, note offs come
+    /// before other messages and other messages come before note ons.
+    pub fn events(&self) -> &[MidiEvent] {
+        &self.events
+    }
+
+    /// Insert `note` after all notes that start at or before it and return its index within
+    /// `notes`. This does not allocate as long as the sequence has spare capacity.
+    pub fn insert_note(&mut self, note: Note) -> usize {
+        let note = note.clamp_length();
+        let idx = self.notes.partition_point(|n| n.start <= note.start);
+        self.notes.insert(idx, note);
+        self.insert_event(note.note_on());
+        self.insert_event(note.note_off());
+        idx
+    }
+
+    /// Remove and return the note at `idx`.
+    ///
+    /// # Panics
+    /// Panics if `idx` is out of bounds.
+    pub fn remove_note(&mut self, idx: usize) -> Note {
+        let note = self.notes.remove(idx);
+        self.remove_event(note.note_on());
+        self.remove_event(note.note_off());
+        note
+    }
+
+    /// Move the note at `idx` to start at `start` and return its new index.
+    ///
+    /// # Panics
+    /// Panics if `idx` is out of bounds.
+    pub fn move_note(&mut self, idx: usize, start: Position) -> usize {
+        let note = self.remove_note(idx);
+        self.insert_note(Note { start, ..note })
+    }
+
+    /// Set the length of the note at `idx`.
+    ///
+    /// # Panics
+    /// Panics if `idx` is out of bounds.
+    pub fn set_note_length(&mut self, idx: usize, length: Position) {
+        let note = self.notes[idx];
+        self.remove_event(note.note_off());
+        self.notes[idx] = Note { length, ..note }.clamp_length();
+        self.insert_event(self.notes[idx].note_off());
+    }
+
+    /// Insert a midi message that is not a note on or note off. Returns false if `event` is a note
+    /// on or note off and was not inserted. Notes should be added with `insert_note`.
+    pub fn insert_message(&mut self, event: MidiEvent) -> bool {
+        if matches!(
+            event.midi,
+            MidiMessage::NoteOn(..) | MidiMessage::NoteOff(..)
+        ) {
+            return false;
+        }
+        self.insert_event(event);
+        true
+    }
+
+    /// Insert a note or midi message.
+    pub fn insert_item(&mut self, item: SequenceItem) {
+        match item {
+            SequenceItem::Note(n) => {
+                self.insert_note(n);
+            }
+            SequenceItem::Message(m) => {
+                self.insert_message(m);
+            }
+        }
+    }
+
+    /// Remove all notes and messages. The capacity is kept.
+    pub fn clear(&mut self) {
+        self.notes.clear();
+        self.events.clear();
+    }
+
+    /// The index within `events` of the first event at or after `position`.
+    pub fn index_of(&self, position: Position) -> usize {
+        self.events.partition_point(|e| e.position < position)
+    }
+
+    /// Get the events within `range`.
+    pub fn range(&self, range: Range<Position>) -> &[MidiEvent] {
+        if range.end <= range.start {
+            return &[];
+        }
+        let start = self.index_of(range.start);
+        let end = start + self.events[start..].partition_point(|e| e.position < range.end);
+        &self.events[start..end]
+    }
+
+    /// Get the events from `start` to `end` for a loop that restarts at `Position::MIN`. If `end`
+    /// is before `start`, the range wraps around the loop and contains the events from `start`
+    /// onward followed by the events before `end`.
+    pub fn range_in_loop(
+        &self,
+        start: Position,
+        end: Position,
+    ) -> impl '_ + Iterator<Item = &MidiEvent> {
+        let (before_wrap, after_wrap) = if start <= end {
+            (self.range(start..end), &[][..])
+        } else {
+            (
+                &self.events[self.index_of(start)..],
+                self.range(Position::MIN..end),
+            )
+        };
+        before_wrap.iter().chain(after_wrap)
+    }
+
+    /// Insert `event` into `events` after all events that sort at or before it.
+    fn insert_event(&mut self, event: MidiEvent) {
+        let key = event_sort_key(&event);
+        let idx = self.events.partition_point(|e| event_sort_key(e) <= key);
+        self.events.insert(idx, event);
+    }
+
+    /// Remove the first event in `events` that is equal to `event`.
+    fn remove_event(&mut self, event: MidiEvent) {
+        let start = self.index_of(event.position);
+        if let Some(offset) = self.events[start..].iter().position(|e| *e == event) {
+            self.events.remove(start + offset);
+        }
+    }
+}
+
+/// The key that `Sequence::events` is sorted by.
+fn event_sort_key(event: &MidiEvent) -> (Position, u8) {
+    let rank = match event.midi {
+        MidiMessage::NoteOff(..) => 0,
+        MidiMessage::NoteOn(..) => 2,
+        _ => 1,
+    };
+    (event.position, rank)
+}
+
+impl Clone for Sequence {
+    fn clone(&self) -> Sequence {
+        Sequence {
+            notes: self.notes.clone(),
+            events: self.events.clone(),
+        }
+    }
+
+    /// Overwrite `self` with the contents of `source`. This does not allocate if `self` has enough
+    /// capacity to hold `source`.
+    fn clone_from(&mut self, source: &Sequence) {
+        self.notes.clone_from(&source.notes);
+        self.events.clone_from(&source.events);
+    }
+}
+
+impl From<Vec<MidiEvent>> for Sequence {
+    /// Create a sequence from raw midi events. Each note on is paired with the next note off for
+    /// the same channel and pitch. Note ons without a note off last for `Note::DEFAULT_LENGTH` and
+    /// note offs without a note on are dropped.
+    fn from(mut events: Vec<MidiEvent>) -> Sequence {
+        events.sort_by_key(|e| e.position);
+        let mut sequence = Sequence::with_capacity(events.len());
+        for (idx, event) in events.iter().enumerate() {
+            match event.midi {
+                MidiMessage::NoteOn(channel, pitch, velocity) => {
+                    let is_note_off = |e: &&MidiEvent| matches!(e.midi, MidiMessage::NoteOff(c, p, _) if c == channel && p == pitch);
+                    let length = events[idx + 1..]
+                        .iter()
+                        .find(is_note_off)
+                        .map(|off| off.position - event.position)
+                        .unwrap_or(Note::DEFAULT_LENGTH);
+                    sequence.insert_note(Note {
+                        start: event.position,
+                        length,
+                        channel,
+                        pitch,
+                        velocity,
+                    });
+                }
+                MidiMessage::NoteOff(..) => (),
+                _ => {
+                    sequence.insert_message(*event);
+                }
+            }
+        }
+        sequence
+    }
+}
+
+impl From<Sequence> for SequenceData {
+    fn from(sequence: Sequence) -> SequenceData {
+        let messages = sequence
+            .events
+            .iter()
+            .filter(|e| !matches!(e.midi, MidiMessage::NoteOn(..) | MidiMessage::NoteOff(..)))
+            .copied()
+            .collect();
+        SequenceData {
+            notes: sequence.notes,
+            messages,
+        }
+    }
+}
+
+impl From<SequenceData> for Sequence {
+    fn from(data: SequenceData) -> Sequence {
+        let mut sequence: Sequence = data.notes.into_iter().collect();
+        for message in data.messages {
+            sequence.insert_message(message);
+        }
+        sequence
+    }
+}
+
+impl FromIterator<Note> for Sequence {
+    fn from_iter<T: IntoIterator<Item = Note>>(iter: T) -> Sequence {
+        let iter = iter.into_iter();
+        let mut sequence = Sequence::with_capacity(iter.size_hint().0 * 2);
+        for note in iter {
+            sequence.insert_note(note);
+        }
+        sequence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(beat: f64, length: f64, pitch: bmidi::Note) -> Note {
+        Note {
+            start: Position::new(beat),
+            length: Position::new(length),
+            channel: Channel::Ch1,
+            pitch,
+            velocity: U7::MAX,
+        }
+    }
+
+    fn note_on(beat: f64, pitch: bmidi::Note) -> MidiEvent {
+        MidiEvent {
+            position: Position::new(beat),
+            midi: MidiMessage::NoteOn(Channel::Ch1, pitch, U7::MAX),
+        }
+    }
+
+    fn note_off(beat: f64, pitch: bmidi::Note) -> MidiEvent {
+        MidiEvent {
+            position: Position::new(beat),
+            midi: MidiMessage::NoteOff(Channel::Ch1, pitch, U7::MIN),
+        }
+    }
+
+    fn pitches(notes: &[Note]) -> Vec<bmidi::Note> {
+        notes.iter().map(|n| n.pitch).collect()
+    }
+
+    fn test_sequence() -> Sequence {
+        Sequence::from_iter([
+            note(3.0, 1.0, bmidi::Note::D4),
+            note(1.0, 0.5, bmidi::Note::A4),
+            note(2.0, 1.0, bmidi::Note::B4),
+            note(1.0, 2.0, bmidi::Note::C4),
+        ])
+    }
+
+    #[test]
+    fn notes_are_sorted_and_keep_order_of_equal_starts() {
+        use bmidi::Note::*;
+        assert_eq!(pitches(test_sequence().notes()), vec![A4, C4, B4, D4]);
+    }
+
+    #[test]
+    fn events_contain_note_on_and_off_for_every_note() {
+        use bmidi::Note::*;
+        assert_eq!(
+            test_sequence().events(),
+            &[
+                note_on(1.0, A4),
+                note_on(1.0, C4),
+                note_off(1.5, A4),
+                note_on(2.0, B4),
+                note_off(3.0, B4),
+                note_off(3.0, C4),
+                note_on(3.0, D4),
+                note_off(4.0, D4),
+            ]
+        );
+    }
+
+    #[test]
+    fn note_off_comes_before_note_on_at_same_position() {
+        use bmidi::Note::*;
+        let s = Sequence::from_iter([note(1.0, 1.0, C4), note(2.0, 1.0, C4)]);
+        assert_eq!(
+            s.events(),
+            &[
+                note_on(1.0, C4),
+                note_off(2.0, C4),
+                note_on(2.0, C4),
+                note_off(3.0, C4)
+            ]
+        );
+    }
+
+    #[test]
+    fn note_past_loop_end_does_not_wrap_note_off() {
+        let s = Sequence::from_iter([note(15.0, 2.0, bmidi::Note::C4)]);
+        assert_eq!(
+            s.events(),
+            &[
+                note_on(15.0, bmidi::Note::C4),
+                note_off(17.0, bmidi::Note::C4)
+            ]
+        );
+    }
+
+    #[test]
+    fn note_length_is_clamped() {
+        let s = Sequence::from_iter([
+            note(1.0, 0.0, bmidi::Note::C4),
+            note(2.0, 100.0, bmidi::Note::D4),
+        ]);
+        assert_eq!(s.notes()[0].length, Note::MIN_LENGTH);
+        assert_eq!(s.notes()[1].length, Position::new(100.0));
+        assert!(s.events()[0].position < s.events()[1].position);
+    }
+
+    #[test]
+    fn insert_note_returns_index() {
+        use bmidi::Note::*;
+        let mut s = test_sequence();
+        assert_eq!(s.insert_note(note(1.0, 1.0, E4)), 2);
+        assert_eq!(s.insert_note(note(0.0, 1.0, F4)), 0);
+        assert_eq!(s.insert_note(note(10.0, 1.0, G4)), 6);
+        assert_eq!(pitches(s.notes()), vec![F4, A4, C4, E4, B4, D4, G4]);
+        assert_eq!(s.len(), 14);
+    }
+
+    #[test]
+    fn insert_with_spare_capacity_does_not_allocate() {
+        let mut s = Sequence::with_capacity(32);
+        let capacity = s.capacity();
+        for beat in (0..16).rev() {
+            s.insert_note(note(beat as f64, 0.5, bmidi::Note::C4));
+        }
+        assert_eq!(s.capacity(), capacity);
+        assert_eq!(s.len(), 32);
+    }
+
+    #[test]
+    fn clone_from_with_capacity_does_not_allocate() {
+        let mut s = Sequence::with_capacity(32);
+        let capacity = s.capacity();
+        s.clone_from(&test_sequence());
+        assert_eq!(s, test_sequence());
+        assert_eq!(s.capacity(), capacity);
+    }
+
+    #[test]
+    fn remove_note_removes_note_on_and_off() {
+        use bmidi::Note::*;
+        let mut s = test_sequence();
+        assert_eq!(s.remove_note(1), note(1.0, 2.0, C4));
+        assert_eq!(pitches(s.notes()), vec![A4, B4, D4]);
+        assert_eq!(
+            s.events(),
+            &[
+                note_on(1.0, A4),
+                note_off(1.5, A4),
+                note_on(2.0, B4),
+                note_off(3.0, B4),
+                note_on(3.0, D4),
+                note_off(4.0, D4),
+            ]
+        );
+    }
+
+    #[test]
+    fn move_note_moves_note_on_and_off() {
+        use bmidi::Note::*;
+        let mut s = Sequence::from_iter([note(1.0, 1.0, C4), note(2.0, 1.0, D4)]);
+        assert_eq!(s.move_note(0, Position::new(4.0)), 1);
+        assert_eq!(s.notes()[1], note(4.0, 1.0, C4));
+        assert_eq!(
+            s.events(),
+            &[
+                note_on(2.0, D4),
+                note_off(3.0, D4),
+                note_on(4.0, C4),
+                note_off(5.0, C4)
+            ]
+        );
+    }
+
+    #[test]
+    fn set_note_length_moves_note_off() {
+        use bmidi::Note::*;
+        let mut s = Sequence::from_iter([note(1.0, 1.0, C4), note(2.0, 1.0, D4)]);
+        s.set_note_length(0, Position::new(3.0));
+        assert_eq!(s.notes()[0], note(1.0, 3.0, C4));
+        assert_eq!(
+            s.events(),
+            &[
+                note_on(1.0, C4),
+                note_on(2.0, D4),
+                note_off(3.0, D4),
+                note_off(4.0, C4)
+            ]
+        );
+    }
+
+    #[test]
+    fn insert_message_rejects_notes() {
+        let mut s = Sequence::new();
+        let pitch_bend = MidiEvent {
+            position: Position::new(1.0),
+            midi: MidiMessage::ProgramChange(Channel::Ch1, U7::MAX),
+        };
+        assert!(s.insert_message(pitch_bend));
+        assert!(!s.insert_message(note_on(1.0, bmidi::Note::C4)));
+        assert!(!s.insert_message(note_off(1.0, bmidi::Note::C4)));
+        assert_eq!(s.events(), &[pitch_bend]);
+        assert!(s.notes().is_empty());
+    }
+
+    #[test]
+    fn from_events_pairs_note_on_and_off() {
+        use bmidi::Note::*;
+        let s = Sequence::from(vec![
+            note_off(3.0, C4),
+            note_on(1.0, C4),
+            note_on(15.0, D4),
+            note_off(1.0, D4),
+            note_off(5.0, E4),
+            note_on(6.0, F4),
+        ]);
+        assert_eq!(
+            s.notes(),
+            &[note(1.0, 2.0, C4), note(6.0, 1.0, F4), note(15.0, 1.0, D4)]
+        );
+    }
+
+    #[test]
+    fn index_of_returns_first_event_at_or_after_position() {
+        let s = test_sequence();
+        assert_eq!(s.index_of(Position::MIN), 0);
+        assert_eq!(s.index_of(Position::new(1.0)), 0);
+        assert_eq!(s.index_of(Position::new(1.2)), 2);
+        assert_eq!(s.index_of(Position::new(3.0)), 4);
+        assert_eq!(s.index_of(Position::new(4.5)), 8);
+    }
+
+    #[test]
+    fn range_includes_start_and_excludes_end() {
+        use bmidi::Note::*;
+        let s = test_sequence();
+        assert_eq!(
+            s.range(Position::new(1.0)..Position::new(2.0)),
+            &[note_on(1.0, A4), note_on(1.0, C4), note_off(1.5, A4)]
+        );
+        assert_eq!(
+            s.range(Position::new(3.5)..Position::new(10.0)),
+            &[note_off(4.0, D4)]
+        );
+        assert!(s.range(Position::new(1.6)..Position::new(2.0)).is_empty());
+        assert!(s.range(Position::new(3.0)..Position::new(1.0)).is_empty());
+        assert!(Sequence::new()
+            .range(Position::MIN..Position::MAX)
+            .is_empty());
+    }
+
+    #[test]
+    fn range_in_loop_without_wrap_is_same_as_range() {
+        let s = test_sequence();
+        assert!(s
+            .range_in_loop(Position::new(1.5), Position::new(3.5))
+            .eq(s.range(Position::new(1.5)..Position::new(3.5))));
+    }
+
+    #[test]
+    fn range_in_loop_with_wrap_returns_end_of_loop_then_start_of_loop() {
+        use bmidi::Note::*;
+        let s = test_sequence();
+        assert_eq!(
+            s.range_in_loop(Position::new(3.5), Position::new(1.2))
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![note_off(4.0, D4), note_on(1.0, A4), note_on(1.0, C4)]
+        );
+    }
+}
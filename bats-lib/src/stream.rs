@@ -0,0 +1,291 @@
+use std::sync::{
+    atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    Arc,
+};
+
+use bats_dsp::buffers::Buffers;
+
+/// Plays audio that is loaded from another thread through a lock-free ring buffer. The start of
+/// the audio is kept in memory as a preroll so that playback can restart immediately while the
+/// loader catches up. Reading audio never locks or allocates.
+///
+/// Clones share the same ring buffer, so only a single clone should play audio.
+#[derive(Clone, Debug)]
+pub struct SampleStream {
+    /// The ring buffer shared with the `StreamLoader`.
+    shared: Arc<Shared>,
+    /// Incremented every time playback restarts.
+    generation: u32,
+    /// The index of the next frame to play.
+    position: usize,
+}
+
+/// Loads the audio that is played by a `SampleStream`.
+#[derive(Debug)]
+pub struct StreamLoader {
+    /// The ring buffer shared with the `SampleStream`.
+    shared: Arc<Shared>,
+    /// The generation of the playback that is being loaded.
+    generation: u32,
+    /// The index of the next frame to load.
+    loaded: usize,
+}
+
+/// The ring buffer shared by a `SampleStream` and a `StreamLoader`.
+#[derive(Debug)]
+struct Shared {
+    /// The first frames of the audio.
+    preroll: Buffers,
+    /// The total number of frames in the audio.
+    len: usize,
+    /// The stereo frames after the preroll. Frame `n` is stored at index `n % frames.len()`. The
+    /// left sample is stored in the upper 32 bits and the right sample is stored in the lower 32
+    /// bits.
+    frames: Box<[AtomicU64]>,
+    /// The generation in the upper 32 bits and the number of frames that have been loaded for
+    /// that generation in the lower 32 bits. Only modified by the `StreamLoader`.
+    loaded: AtomicU64,
+    /// The generation that the `SampleStream` is playing. Only modified by the `SampleStream`.
+    requested: AtomicU32,
+    /// The index of the next frame that the `SampleStream` will play.
+    position: AtomicUsize,
+    /// The number of frames that were played as silence because they were not loaded in time.
+    underruns: AtomicUsize,
+}
+
+impl SampleStream {
+    /// Create a new stream of `len` frames that starts with `preroll` and the loader for the rest
+    /// of its audio. The ring buffer holds `capacity` frames. This allocates so it should not be
+    /// called while processing audio.
+    pub fn new(preroll: Buffers, len: usize, capacity: usize) -> (SampleStream, StreamLoader) {
+        let shared = Arc::new(Shared {
+            preroll,
+            len,
+            frames: (0..capacity.max(1)).map(|_| AtomicU64::new(0)).collect(),
+            loaded: AtomicU64::new(0),
+            requested: AtomicU32::new(0),
+            position: AtomicUsize::new(len),
+            underruns: AtomicUsize::new(0),
+        });
+        let stream = SampleStream {
+            shared: shared.clone(),
+            generation: 0,
+            position: len,
+        };
+        let loader = StreamLoader {
+            shared,
+            generation: 0,
+            loaded: 0,
+        };
+        (stream, loader)
+    }
+
+    /// The total number of frames in the audio.
+    pub fn len(&self) -> usize {
+        self.shared.len
+    }
+
+    /// Returns true if the audio has no frames.
+    pub fn is_empty(&self) -> bool {
+        self.shared.len == 0
+    }
+
+    /// Restart playback from the first frame.
+    pub fn restart(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+        self.position = 0;
+        self.shared.position.store(0, Ordering::Relaxed);
+        self.shared
+            .requested
+            .store(self.generation, Ordering::Release);
+    }
+
+    /// Get the next frame or `None` if playback has reached the end. Frames that have not been
+    /// loaded yet are played as silence and counted as underruns.
+    pub fn next_frame(&mut self) -> Option<(f32, f32)> {
+        if self.position >= self.shared.len {
+            return None;
+        }
+        let position = self.position;
+        self.position += 1;
+        self.shared.position.store(self.position, Ordering::Relaxed);
+        if position < self.shared.preroll.len() {
+            return Some(self.shared.preroll.get(position));
+        }
+        let loaded = self.shared.loaded.load(Ordering::Acquire);
+        let (generation, end) = ((loaded >> 32) as u32, loaded as u32 as usize);
+        let capacity = self.shared.frames.len();
+        if generation != self.generation || position >= end || position + capacity < end {
+            self.shared.underruns.fetch_add(1, Ordering::Relaxed);
+            return Some((0.0, 0.0));
+        }
+        let frame = self.shared.frames[position % capacity].load(Ordering::Relaxed);
+        Some((
+            f32::from_bits((frame >> 32) as u32),
+            f32::from_bits(frame as u32),
+        ))
+    }
+
+    /// The number of frames that were played as silence because they were not loaded in time.
+    pub fn underruns(&self) -> usize {
+        self.shared.underruns.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for SampleStream {
+    /// Streams are equal if they play from the same ring buffer.
+    fn eq(&self, other: &SampleStream) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+}
+
+impl StreamLoader {
+    /// Check if playback has restarted since the last call. If it has, returns the index of the
+    /// frame that should be loaded next.
+    pub fn poll_restart(&mut self) -> Option<usize> {
+        let requested = self.shared.requested.load(Ordering::Acquire);
+        if requested == self.generation {
+            return None;
+        }
+        self.generation = requested;
+        self.loaded = self.shared.preroll.len().min(self.shared.len);
+        self.publish();
+        Some(self.loaded)
+    }
+
+    /// The number of frames that can be pushed without overwriting frames that have not been
+    /// played.
+    pub fn available(&self) -> usize {
+        if self.generation == 0 {
+            return 0;
+        }
+        // Frames in the preroll are never read from the ring buffer.
+        let position = self
+            .shared
+            .position
+            .load(Ordering::Relaxed)
+            .max(self.shared.preroll.len());
+        let end = (position + self.shared.frames.len()).min(self.shared.len);
+        end.saturating_sub(self.loaded)
+    }
+
+    /// Push the frames that follow the previously pushed frames. Frames past `available` are
+    /// ignored. Returns the number of frames that were pushed.
+    pub fn push(&mut self, frames: &[(f32, f32)]) -> usize {
+        let len = frames.len().min(self.available());
+        let capacity = self.shared.frames.len();
+        for (idx, (l, r)) in frames[..len].iter().enumerate() {
+            let frame = (l.to_bits() as u64) << 32 | r.to_bits() as u64;
+            self.shared.frames[(self.loaded + idx) % capacity].store(frame, Ordering::Relaxed);
+        }
+        self.loaded += len;
+        self.publish();
+        len
+    }
+
+    /// Returns true if no `SampleStream` is left to play the audio.
+    pub fn is_orphaned(&self) -> bool {
+        Arc::strong_count(&self.shared) == 1
+    }
+
+    /// The number of frames that were played as silence because they were not loaded in time.
+    pub fn underruns(&self) -> usize {
+        self.shared.underruns.load(Ordering::Relaxed)
+    }
+
+    /// Make the loaded frames visible to the `SampleStream`.
+    fn publish(&self) {
+        let loaded = (self.generation as u64) << 32 | self.loaded as u32 as u64;
+        self.shared.loaded.store(loaded, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(range: std::ops::Range<usize>) -> Vec<(f32, f32)> {
+        range.map(|n| (n as f32, -(n as f32))).collect()
+    }
+
+    fn play(stream: &mut SampleStream) -> Vec<(f32, f32)> {
+        std::iter::from_fn(|| stream.next_frame()).collect()
+    }
+
+    #[test]
+    fn stream_is_silent_until_restart() {
+        let (mut stream, mut loader) = SampleStream::new(Buffers::new(2), 8, 4);
+        assert_eq!(stream.next_frame(), None);
+        assert_eq!(loader.poll_restart(), None);
+        assert_eq!(loader.available(), 0);
+    }
+
+    #[test]
+    fn plays_preroll_then_loaded_frames() {
+        let preroll = Buffers::with_iter(frames(0..2).into_iter());
+        let (mut stream, mut loader) = SampleStream::new(preroll, 8, 4);
+        stream.restart();
+        assert_eq!(loader.poll_restart(), Some(2));
+        // Only the frames that fit in the ring buffer can be pushed.
+        assert_eq!(loader.push(&frames(2..8)), 4);
+        assert_eq!(
+            (0..6)
+                .map(|_| stream.next_frame().unwrap())
+                .collect::<Vec<_>>(),
+            frames(0..6)
+        );
+        assert_eq!(loader.push(&frames(6..8)), 2);
+        assert_eq!(play(&mut stream), frames(6..8));
+        assert_eq!(stream.underruns(), 0);
+    }
+
+    #[test]
+    fn frames_that_are_not_loaded_are_underruns() {
+        let preroll = Buffers::with_iter(frames(0..2).into_iter());
+        let (mut stream, loader) = SampleStream::new(preroll, 4, 4);
+        stream.restart();
+        assert_eq!(
+            play(&mut stream),
+            vec![(0.0, 0.0), (1.0, -1.0), (0.0, 0.0), (0.0, 0.0)]
+        );
+        assert_eq!(stream.underruns(), 2);
+        assert_eq!(loader.underruns(), 2);
+    }
+
+    #[test]
+    fn restart_ignores_frames_from_previous_playback() {
+        let preroll = Buffers::with_iter(frames(0..2).into_iter());
+        let (mut stream, mut loader) = SampleStream::new(preroll, 6, 4);
+        stream.restart();
+        loader.poll_restart();
+        loader.push(&frames(2..6));
+        stream.restart();
+        assert_eq!(
+            play(&mut stream),
+            vec![
+                (0.0, 0.0),
+                (1.0, -1.0),
+                (0.0, 0.0),
+                (0.0, 0.0),
+                (0.0, 0.0),
+                (0.0, 0.0)
+            ]
+        );
+
+        stream.restart();
+        assert_eq!(loader.poll_restart(), Some(2));
+        loader.push(&frames(2..6));
+        assert_eq!(play(&mut stream), frames(0..6));
+    }
+
+    #[test]
+    fn loader_is_orphaned_once_streams_are_dropped() {
+        let (stream, loader) = SampleStream::new(Buffers::new(0), 0, 1);
+        let clone = stream.clone();
+        assert_eq!(stream, clone);
+        drop(stream);
+        assert!(!loader.is_orphaned());
+        drop(clone);
+        assert!(loader.is_orphaned());
+    }
+}
@@ -1,20 +1,147 @@
-use bats_dsp::{buffers::Buffers, position::Position};
-use bmidi::MidiMessage;
+use std::fmt;
 
+use arrayvec::ArrayVec;
+use bats_dsp::{buffers::Buffers, position::Position, smoothed_value::SmoothedValue};
+use bmidi::{Channel, MidiMessage, U7};
+use serde::{Deserialize, Serialize};
 
-use crate::{builder::AnyPlugin, plugin::MidiEvent, transport::Transport};
+use crate::{
+    automation::AutomationLane,
+    aux_bus::AuxBus,
+    builder::AnyPlugin,
+    expression::ExpressionRoute,
+    freeze::FrozenTrack,
+    lfo::ParamLfo,
+    midi_filter::MidiFilter,
+    plugin::{compressor::Compressor, BatsEffect, MidiEvent, TransportInfo},
+    plugin_slot::PluginSlot,
+    sequence::{Note, Sequence, SequenceItem},
+    transport::Transport,
+    tuning::Tuning,
+    Bats,
+};
 
 /// An plugin with output buffers.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Track {
+    /// The user defined name of the track or an empty string if the track has not been named.
+    pub name: String,
+    /// The color used to tag the track in the UI.
+    pub color: Option<TrackColor>,
     /// The plugin.
     pub plugin: AnyPlugin,
+    /// Filters the midi input before it is recorded and sent to the plugin.
+    pub midi_filter: MidiFilter,
+    /// Maps the notes played by the plugin to frequencies.
+    pub tuning: Box<Tuning>,
     /// The track volume.
     pub volume: f32,
     /// The buffers to output data to.
     pub output: Buffers,
     /// The midi sequence to play.
-    pub sequence: Vec<MidiEvent>,
+    pub sequence: Sequence,
+    /// Smooths out changes to `volume` to avoid zipper noise.
+    pub volume_smoother: SmoothedValue,
+    /// The notes and messages that were recorded to `sequence` during the last call to `process`.
+    pub recorded: ArrayVec<SequenceItem, { Track::RECORDED_CAPACITY }>,
+    /// The notes that are being recorded but have not been released yet. The start of each note
+    /// is the transport position and is moved within `loop_length` once the note is released,
+    /// along with setting its length.
+    pub recording_notes: ArrayVec<Note, { Track::RECORDING_NOTES_CAPACITY }>,
+    /// The param automation lanes. Each lane is applied at the start of every buffer.
+    pub automation: Vec<AutomationLane>,
+    /// Routes from midi expression controllers, like the mod wheel, to plugin params.
+    pub expression_routes: Vec<ExpressionRoute>,
+    /// The LFOs that modulate plugin params. LFOs are applied after `automation` at the start of
+    /// every buffer.
+    pub lfos: Vec<ParamLfo>,
+    /// The compressor applied to the plugin output or `None` if the track is not compressed.
+    pub compressor: Option<Box<Compressor>>,
+    /// The plugin that was replaced by `set_plugin` and is being faded out.
+    pub fading_plugin: Option<ReplacedPlugin>,
+    /// The output of `fading_plugin`.
+    pub fading_output: Buffers,
+    /// The total length of the current crossfade in frames.
+    pub crossfade_frames: usize,
+    /// The number of frames remaining in the current crossfade.
+    pub crossfade_remaining: usize,
+    /// Plugins that are no longer used. Should be drained by the owner of the track by putting
+    /// each plugin into its slot.
+    pub retired_plugins: ArrayVec<ReplacedPlugin, { Track::RETIRED_PLUGINS_CAPACITY }>,
+    /// The level that the track output is sent to each aux bus at, after the track volume is
+    /// applied.
+    pub sends: [f32; Bats::AUX_BUS_COUNT],
+    /// The original plugin and sequence if the track is frozen. While frozen, `plugin` plays back
+    /// a render of the original plugin.
+    pub frozen: Option<Box<FrozenTrack>>,
+    /// The track whose output is mixed into this track before the compressor, or `None` if the
+    /// track has no input. Set with `Bats::set_track_input` so that the processing order is kept
+    /// up to date.
+    pub input: Option<usize>,
+    /// Where the sequence and midi input of the track are sent.
+    pub midi_destination: MidiDestination,
+    /// The midi sent to the midi output of the track during the last buffer. Only filled if
+    /// `midi_destination` includes the midi output. Should be written to the midi output port by
+    /// the owner of the track.
+    pub midi_out: ArrayVec<(u32, MidiMessage), { Track::MIDI_OUT_CAPACITY }>,
+    /// The number of beats after which the sequence repeats, starting from the start of the loop
+    /// range, or `None` if the sequence repeats with the transport loop.
+    pub loop_length: Option<Position>,
+    /// True if the last buffer was recorded to the sequence.
+    pub recording: bool,
+    /// True if `loop_length` should be set to fit the sequence once recording stops. Set when
+    /// recording starts on an empty sequence that has no loop length.
+    pub detect_loop_length: bool,
+}
+
+/// A plugin that was replaced by `Track::set_plugin`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplacedPlugin {
+    /// The plugin.
+    pub plugin: AnyPlugin,
+    /// The slot that `plugin` should be put into once it is no longer used.
+    pub slot: PluginSlot,
+}
+
+/// The reason that `Track::set_plugin` did not change the plugin.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SetPluginError {
+    /// The slot has no plugin or is being used by another thread.
+    EmptySlot,
+    /// `retired_plugins` is full so the plugin that is fading out can not be retired.
+    TooManyRetired,
+}
+
+impl fmt::Display for SetPluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetPluginError::EmptySlot => write!(f, "the plugin slot is empty"),
+            SetPluginError::TooManyRetired => write!(f, "the plugin is changing too often"),
+        }
+    }
+}
+
+/// Where the midi of a track is sent.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MidiDestination {
+    /// The plugin of the track.
+    #[default]
+    Plugin,
+    /// The midi output of the track, for example to drive an external synth.
+    MidiOut,
+    /// Both the plugin and the midi output of the track.
+    Both,
+}
+
+/// A color used to tag a track.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrackColor {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
 }
 
 /// Context for processing a track.
@@ -26,121 +153,586 @@ pub struct TrackProcessContext<'a> {
     pub transport: &'a Transport,
     /// The midi input.
     pub midi_in: &'a [(u32, MidiMessage)],
+    /// The number of frames between when midi is played and when it arrives in `midi_in`.
+    /// Recorded midi is moved earlier by this many frames.
+    pub record_latency: u32,
     /// Temporary midi buffer to use for scratch operations.
     pub tmp_midi_buffer: &'a mut Vec<(u32, MidiMessage)>,
+    /// The output of the track that `input` refers to. The input track must already have been
+    /// processed for this buffer.
+    pub input: Option<&'a Buffers>,
 }
 
 impl Track {
-    /// The capacity for sequences.
+    /// The capacity for sequences in midi events. Each note takes up 2 events.
     pub const SEQUENCE_CAPACITY: usize = 4096;
 
+    /// The maximum number of recorded events that are reported per call to `process`.
+    pub const RECORDED_CAPACITY: usize = 32;
+
+    /// The maximum number of notes that can be held down while recording.
+    pub const RECORDING_NOTES_CAPACITY: usize = 32;
+
+    /// The duration of the crossfade between the old and new plugin when the plugin is changed.
+    pub const CROSSFADE_SECONDS: f32 = 0.01;
+
+    /// The maximum number of retired plugins that can be held before they are drained.
+    pub const RETIRED_PLUGINS_CAPACITY: usize = 4;
+
+    /// The maximum number of midi events that can be sent to the midi output per buffer.
+    pub const MIDI_OUT_CAPACITY: usize = 256;
+
     /// Create a new track.
     pub fn new(buffer_size: usize) -> Track {
         Track {
+            name: String::new(),
+            color: None,
             plugin: AnyPlugin::default(),
+            midi_filter: MidiFilter::default(),
+            tuning: Box::default(),
             volume: 1.0,
             output: Buffers::new(buffer_size),
-            sequence: Vec::with_capacity(Track::SEQUENCE_CAPACITY),
+            sequence: Sequence::with_capacity(Track::SEQUENCE_CAPACITY),
+            volume_smoother: SmoothedValue::new(1.0),
+            recorded: ArrayVec::new(),
+            recording_notes: ArrayVec::new(),
+            automation: Vec::new(),
+            expression_routes: Vec::new(),
+            lfos: Vec::new(),
+            compressor: None,
+            fading_plugin: None,
+            fading_output: Buffers::new(buffer_size),
+            crossfade_frames: 0,
+            crossfade_remaining: 0,
+            retired_plugins: ArrayVec::new(),
+            sends: [0.0; Bats::AUX_BUS_COUNT],
+            frozen: None,
+            input: None,
+            midi_destination: MidiDestination::default(),
+            midi_out: ArrayVec::new(),
+            loop_length: None,
+            recording: false,
+            detect_loop_length: false,
+        }
+    }
+
+    /// Get the plugin of the track, or the original plugin if the track is frozen.
+    pub fn unfrozen_plugin(&self) -> &AnyPlugin {
+        match self.frozen.as_ref() {
+            Some(f) => &f.plugin,
+            None => &self.plugin,
+        }
+    }
+
+    /// Get the sequence of the track, or the original sequence if the track is frozen.
+    pub fn unfrozen_sequence(&self) -> &Sequence {
+        match self.frozen.as_ref() {
+            Some(f) => &f.sequence,
+            None => &self.sequence,
+        }
+    }
+
+    /// Set the output buffer size. This allocates so it should not be called while processing
+    /// audio.
+    pub fn set_buffer_size(&mut self, buffer_size: usize) {
+        self.output = Buffers::new(buffer_size);
+        self.fading_output = Buffers::new(buffer_size);
+    }
+
+    /// Replace the plugin with the plugin in `slot`. The old plugin keeps playing and is faded out
+    /// over `crossfade_frames` while the new plugin is faded in. Once the fade is complete, the old
+    /// plugin is moved to `retired_plugins` to be put into `slot`.
+    ///
+    /// If a plugin that was replaced by `slot` has not been put into `slot` yet, it is taken back
+    /// instead so that the change can be undone before its crossfade is complete. If a previous
+    /// crossfade is still in progress, its old plugin is retired immediately.
+    pub fn set_plugin(
+        &mut self,
+        slot: &PluginSlot,
+        crossfade_frames: usize,
+    ) -> Result<(), SetPluginError> {
+        let mut plugin = match self.take_replaced_plugin(slot) {
+            Some(p) => p,
+            None if self.fading_plugin.is_some() && self.retired_plugins.is_full() => {
+                return Err(SetPluginError::TooManyRetired)
+            }
+            None => slot.try_take().ok_or(SetPluginError::EmptySlot)?,
+        };
+        plugin.plugin_mut().set_tuning(&self.tuning);
+        let old = std::mem::replace(&mut self.plugin, plugin);
+        let replaced = ReplacedPlugin {
+            plugin: old,
+            slot: slot.clone(),
+        };
+        if let Some(p) = self.fading_plugin.replace(replaced) {
+            self.retired_plugins.push(p);
+        }
+        self.crossfade_frames = crossfade_frames;
+        self.crossfade_remaining = crossfade_frames;
+        Ok(())
+    }
+
+    /// Take back the plugin that was replaced by `slot` and has not been put into `slot` yet.
+    fn take_replaced_plugin(&mut self, slot: &PluginSlot) -> Option<AnyPlugin> {
+        if self
+            .fading_plugin
+            .as_ref()
+            .is_some_and(|p| p.slot.is_same(slot))
+        {
+            return self.fading_plugin.take().map(|p| p.plugin);
         }
+        let idx = self
+            .retired_plugins
+            .iter()
+            .position(|p| p.slot.is_same(slot))?;
+        Some(self.retired_plugins.remove(idx).plugin)
+    }
+
+    /// Set the tuning of the track and its plugins. Returns the old tuning.
+    pub fn set_tuning(&mut self, tuning: Box<Tuning>) -> Box<Tuning> {
+        let old = std::mem::replace(&mut self.tuning, tuning);
+        self.plugin.plugin_mut().set_tuning(&self.tuning);
+        if let Some(p) = self.fading_plugin.as_mut() {
+            p.plugin.plugin_mut().set_tuning(&self.tuning);
+        }
+        old
     }
 
     /// Process the track. The resulting audio is updated in `self.output`.
-    pub fn process(&mut self, ctx: TrackProcessContext) {
+    ///
+    /// Returns the number of midi events that could not be recorded because the sequence is full.
+    pub fn process(&mut self, ctx: TrackProcessContext) -> usize {
         ctx.tmp_midi_buffer.clear();
-        self.sequence_to_midi_frames(ctx.tmp_midi_buffer, ctx.transport);
-        if !ctx.midi_in.is_empty() {
-            let should_sort = !ctx.tmp_midi_buffer.is_empty();
-            ctx.tmp_midi_buffer.extend_from_slice(ctx.midi_in);
-            if should_sort {
-                ctx.tmp_midi_buffer.sort_by_key(|(frame, _)| *frame);
-            }
-            if ctx.record_to_sequence {
-                self.record_to_sequence(ctx.midi_in.iter(), ctx.transport);
-            }
+        self.recorded.clear();
+        self.midi_out.clear();
+        if let Some(range) = ctx.transport.iter_transport().next() {
+            self.apply_automation(range.start);
+            self.apply_lfos(range.start);
         }
+        self.set_transport_info(&ctx.transport.info());
+        self.update_loop_length_detection(ctx.record_to_sequence, ctx.transport);
+        self.sequence_to_midi_frames(ctx.tmp_midi_buffer, ctx.midi_in, ctx.transport);
+        if !ctx.record_to_sequence {
+            self.recording_notes.clear();
+        }
+        let dropped = if ctx.record_to_sequence && !ctx.midi_in.is_empty() {
+            self.record_to_sequence(ctx.midi_in.iter(), ctx.transport, ctx.record_latency)
+        } else {
+            0
+        };
+        self.apply_expression(ctx.tmp_midi_buffer);
+        if self.midi_destination.to_midi_out() {
+            let len = ctx.tmp_midi_buffer.len().min(Track::MIDI_OUT_CAPACITY);
+            self.midi_out
+                .extend(ctx.tmp_midi_buffer[..len].iter().copied());
+        }
+        let plugin_midi: &[_] = if self.midi_destination.to_plugin() {
+            ctx.tmp_midi_buffer
+        } else {
+            &[]
+        };
         self.plugin
             .plugin_mut()
-            .process_batch(ctx.tmp_midi_buffer.as_slice(), &mut self.output);
+            .process_batch(plugin_midi, &mut self.output);
+        self.process_crossfade();
+        if let Some(input) = ctx.input {
+            self.output.mix_from(input);
+        }
+        if let Some(compressor) = self.compressor.as_mut() {
+            compressor.process_batch(&mut self.output);
+        }
+        dropped
     }
 
-    fn sequence_to_midi_frames(&self, dst: &mut Vec<(u32, MidiMessage)>, transport: &Transport) {
-        if self.sequence.is_empty() {
-            return;
+    /// Pass the transport info for the current buffer to the plugins and the compressor.
+    fn set_transport_info(&mut self, info: &TransportInfo) {
+        self.plugin.plugin_mut().set_transport_info(info);
+        if let Some(p) = self.fading_plugin.as_mut() {
+            p.plugin.plugin_mut().set_transport_info(info);
         }
-        let initial_len = dst.len();
-        let placeholder_event = MidiEvent {
-            position: Position::MAX,
-            midi: MidiMessage::Reserved(0),
-        };
-        let transport_start = transport.iter_transport().next().unwrap_or_default();
-        // TODO: Use binary search for performance improvement.
-        let start = self
-            .sequence
-            .iter()
-            .position(|e| e.position >= transport_start.start)
-            .unwrap_or(self.sequence.len());
-        let mut sequence_iter = self
+        if let Some(compressor) = self.compressor.as_mut() {
+            compressor.set_transport_info(info);
+        }
+    }
+
+    /// Start detecting the loop length when recording starts on an empty sequence and set the loop
+    /// length to fit the recorded sequence once recording stops.
+    fn update_loop_length_detection(&mut self, recording: bool, transport: &Transport) {
+        match (self.recording, recording) {
+            (false, true) => {
+                self.detect_loop_length = self.sequence.is_empty() && self.loop_length.is_none();
+            }
+            (true, false) if self.detect_loop_length => {
+                self.loop_length = self.fit_loop_length(transport);
+                self.detect_loop_length = false;
+            }
+            _ => (),
+        }
+        self.recording = recording;
+    }
+
+    /// Get the loop length that fits the sequence, rounded up to the bar after the last event.
+    /// Returns `None` if the sequence is empty or if the length is not shorter than the transport
+    /// loop.
+    pub fn fit_loop_length(&self, transport: &Transport) -> Option<Position> {
+        let loop_range = transport.loop_range();
+        let last = self
             .sequence
-            .iter()
-            .chain(std::iter::once(&placeholder_event))
-            .cycle()
-            .skip(start)
-            .peekable();
-        for (frame, rng) in transport.iter_transport().enumerate() {
-            let is_in_range = |event: &&MidiEvent| {
-                if rng.start <= rng.end {
-                    rng.contains(&event.position)
-                } else {
-                    !(rng.end..rng.start).contains(&event.position)
+            .events()
+            .last()?
+            .position
+            .max(loop_range.start);
+        let bar = Position::new(Position::BEATS_PER_BAR as f64).to_bits();
+        let bars = (last - loop_range.start).to_bits().div_ceil(bar).max(1);
+        let length = Position::from_bits(bars * bar);
+        if transport.looping() && length >= loop_range.end - loop_range.start {
+            return None;
+        }
+        Some(length)
+    }
+
+    /// Get the position within the sequence that plays at the transport `position`.
+    pub fn sequence_position(&self, transport: &Transport, position: Position) -> Position {
+        match self.loop_length {
+            Some(length) => transport.position_in_loop_length(position, length),
+            None => position,
+        }
+    }
+
+    /// Mix the output of `fading_plugin` into `output` and retire the fading plugin once the
+    /// crossfade is complete. The fading plugin is kept, silent, until `retired_plugins` has space
+    /// for it.
+    fn process_crossfade(&mut self) {
+        let fading_plugin = match self.fading_plugin.as_mut() {
+            Some(p) => p,
+            None => return,
+        };
+        fading_plugin
+            .plugin
+            .plugin_mut()
+            .process_batch(&[], &mut self.fading_output);
+        let frames = self.output.len().min(self.fading_output.len());
+        let total = self.crossfade_frames.max(1) as f32;
+        let start = self.crossfade_remaining as f32 / total;
+        let end = (self.crossfade_remaining as f32 - self.output.len() as f32) / total;
+        self.output.crossfade_from(&self.fading_output, start, end);
+        self.crossfade_remaining = self.crossfade_remaining.saturating_sub(frames);
+        if self.crossfade_remaining == 0 && !self.retired_plugins.is_full() {
+            if let Some(p) = self.fading_plugin.take() {
+                self.retired_plugins.push(p);
+            }
+        }
+    }
+
+    /// Returns true if the track produces no output because it has no plugin, is not fading out
+    /// an old plugin, and has no input.
+    pub fn is_silent(&self) -> bool {
+        matches!(self.plugin, AnyPlugin::Empty(_))
+            && self.fading_plugin.is_none()
+            && self.input.is_none()
+    }
+
+    /// Release the notes in the sequence that are held at `position`. Called when the transport
+    /// stops so that sequenced notes do not keep ringing. Note offs for the midi output are sent
+    /// at `frame` of the current buffer.
+    pub fn release_sequence_notes(&mut self, position: Position, frame: u32) {
+        let plugin = self.plugin.plugin_mut();
+        for note in self.sequence.notes() {
+            if note.start <= position && position < note.end() {
+                let note_off = MidiMessage::NoteOff(note.channel, note.pitch, U7::MIN);
+                if self.midi_destination.to_plugin() {
+                    plugin.handle_midi(&note_off);
                 }
+                if self.midi_destination.to_midi_out() {
+                    let _ = self.midi_out.try_push((frame, note_off));
+                }
+            }
+        }
+    }
+
+    /// Mix the track output onto `left` and `right` with the track volume applied. Changes to
+    /// the volume are ramped over the length of the buffer.
+    pub fn mix_output(&mut self, left: &mut [f32], right: &mut [f32]) {
+        self.apply_output(left, right, |dst, v| *dst += v);
+    }
+
+    /// Write the track output to `left` and `right` with the track volume applied, replacing their
+    /// contents.
+    pub fn write_output(&mut self, left: &mut [f32], right: &mut [f32]) {
+        self.apply_output(left, right, |dst, v| *dst = v);
+    }
+
+    /// Mix the track output onto the output of each aux bus that the track sends to. The send
+    /// level is multiplied by the track volume without ramping.
+    pub fn mix_sends(&self, buses: &mut [AuxBus]) {
+        for (bus, send) in buses.iter_mut().zip(self.sends.iter()) {
+            if *send == 0.0 {
+                continue;
+            }
+            let gain = send * self.volume;
+            let dst = bus.output.left.iter_mut().zip(bus.output.right.iter_mut());
+            let src = self.output.left.iter().zip(self.output.right.iter());
+            for ((dst_left, dst_right), (src_left, src_right)) in dst.zip(src) {
+                *dst_left += gain * src_left;
+                *dst_right += gain * src_right;
+            }
+        }
+    }
+
+    /// Call `f` with each destination sample and its output sample with the volume applied.
+    #[inline]
+    fn apply_output(&mut self, left: &mut [f32], right: &mut [f32], f: impl Fn(&mut f32, f32)) {
+        if self.volume != self.volume_smoother.target() {
+            self.volume_smoother.set_target(self.volume, left.len());
+        }
+        let dst = left.iter_mut().zip(right.iter_mut());
+        let src = self.output.left.iter().zip(self.output.right.iter());
+        if self.volume_smoother.is_smoothing() {
+            for ((dst_left, dst_right), (src_left, src_right)) in dst.zip(src) {
+                let volume = self.volume_smoother.next_value();
+                f(dst_left, volume * src_left);
+                f(dst_right, volume * src_right);
+            }
+        } else {
+            // Avoid the per-sample smoother update so the loop can be vectorized.
+            let volume = self.volume_smoother.value();
+            for ((dst_left, dst_right), (src_left, src_right)) in dst.zip(src) {
+                f(dst_left, volume * src_left);
+                f(dst_right, volume * src_right);
+            }
+        }
+    }
+
+    /// Set all automated params to their value at `position`.
+    fn apply_automation(&mut self, position: Position) {
+        let plugin = self.plugin.plugin_mut();
+        for lane in self.automation.iter() {
+            if let Some(value) = lane.value_at(position) {
+                if plugin.param(lane.param_id) != value {
+                    plugin.set_param(lane.param_id, value);
+                }
+            }
+        }
+    }
+
+    /// Set the params that are modulated by the LFOs to their value at `position`. Values are
+    /// clamped to the range of the param.
+    fn apply_lfos(&mut self, position: Position) {
+        let plugin = self.plugin.plugin_mut();
+        for lfo in self.lfos.iter() {
+            let value = lfo.value_at(position);
+            let value = match plugin.metadata().param_by_id(lfo.param_id) {
+                Some(p) => value.clamp(p.min_value, p.max_value),
+                None => value,
             };
-            let mut has_looped = false;
-            while let Some(event) = sequence_iter.next_if(is_in_range) {
-                if event != &placeholder_event {
-                    dst.push((frame as u32, event.midi));
-                } else if has_looped {
-                    // Only allow wrapping over once per position range.
-                    continue;
-                } else {
-                    has_looped = true;
+            plugin.set_param(lfo.param_id, value);
+        }
+    }
+
+    /// Set the params that are routed from the expression controllers in `midi`. Params are set
+    /// before the buffer is processed so the last value in `midi` takes effect for the whole
+    /// buffer.
+    fn apply_expression(&mut self, midi: &[(u32, MidiMessage)]) {
+        if self.expression_routes.is_empty() {
+            return;
+        }
+        let plugin = self.plugin.plugin_mut();
+        for (_, m) in midi {
+            for route in self.expression_routes.iter() {
+                if let Some(value) = route.value(m) {
+                    plugin.set_param(route.param_id, value);
                 }
             }
         }
-        // Sorting is not required under the following:
-        //   - The sequence initial length was 0. New items should be inserted in sorted manner.
-        //   - The final length did not change. No new items were inserted.
-        let is_sorted = initial_len == 0 || dst.len() == initial_len;
-        if !is_sorted {
-            dst.sort_by_key(|(frame, _)| *frame)
+    }
+
+    /// Fill `dst` with the midi events from the sequence that fall within the current buffer,
+    /// merged with `midi_in`. `midi_in` must be sorted by frame. Events from the sequence come
+    /// before events from `midi_in` that occur on the same frame.
+    fn sequence_to_midi_frames(
+        &self,
+        dst: &mut Vec<(u32, MidiMessage)>,
+        midi_in: &[(u32, MidiMessage)],
+        transport: &Transport,
+    ) {
+        debug_assert!(midi_in.windows(2).all(|w| w[0].0 <= w[1].0));
+        let mut midi_in = midi_in.iter().peekable();
+        let loop_end = match self.loop_length {
+            Some(length) => Some(transport.loop_range().start + length),
+            None if transport.looping() => Some(transport.loop_range().end),
+            None => None,
+        };
+        let mut push_event = |frame: u32, event: &MidiEvent| {
+            // Only note offs are played from past the end of the loop so that notes held over
+            // the loop end are released.
+            let past_loop_end = loop_end.is_some_and(|end| event.position >= end);
+            if past_loop_end && !matches!(event.midi, MidiMessage::NoteOff(..)) {
+                return;
+            }
+            while let Some(m) = midi_in.next_if(|(f, _)| *f < frame) {
+                dst.push(*m);
+            }
+            dst.push((frame, event.midi));
+        };
+        match self.loop_length {
+            Some(length) => transport.for_each_in_buffer_with_loop_length(
+                self.sequence.events(),
+                |event| event.position,
+                length,
+                &mut push_event,
+            ),
+            None => transport.for_each_in_buffer(
+                self.sequence.events(),
+                |event| event.position,
+                &mut push_event,
+            ),
         }
+        dst.extend(midi_in);
     }
 
+    /// Record the midi events onto the sequence. Note ons are held in `recording_notes` until
+    /// their note off arrives and are then recorded as a whole note, so the sequence never holds a
+    /// note on without a note off.
+    ///
+    /// Recording never allocates. Notes and messages that do not fit within `SEQUENCE_CAPACITY`
+    /// events are dropped, as are note ons that arrive while `recording_notes` is full. Returns
+    /// the number of dropped notes and messages.
+    ///
+    /// Recorded notes and messages are also stored in `recorded`. Each event is recorded
+    /// `latency` frames before the frame it arrived on.
     fn record_to_sequence<'a>(
         &mut self,
         midi_iter: impl 'a + Iterator<Item = &'a (u32, MidiMessage)>,
         transport: &Transport,
-    ) {
-        let mut did_change = false;
+        latency: u32,
+    ) -> usize {
+        let mut dropped = 0;
         for (frame, midi) in midi_iter {
-            let position = transport.range_for_frame(*frame).start;
-            self.sequence.push(MidiEvent {
-                position,
-                midi: *midi,
-            });
-            did_change = true;
+            let position = transport.rewind(transport.range_for_frame(*frame).start, latency);
+            let item = match *midi {
+                MidiMessage::NoteOn(channel, pitch, velocity) => {
+                    // Pressing a note that is already held ends the previous press.
+                    let previous = self.release_recording_note(transport, channel, pitch, position);
+                    let note = Note {
+                        start: position,
+                        length: Position::MIN,
+                        channel,
+                        pitch,
+                        velocity,
+                    };
+                    if self.recording_notes.try_push(note).is_err() {
+                        dropped += 1;
+                    }
+                    previous
+                }
+                MidiMessage::NoteOff(channel, pitch, _) => {
+                    self.release_recording_note(transport, channel, pitch, position)
+                }
+                midi => Some(SequenceItem::Message(MidiEvent {
+                    position: self.sequence_position(transport, position),
+                    midi,
+                })),
+            };
+            let item = match item {
+                Some(item) => item,
+                None => continue,
+            };
+            let events = match item {
+                SequenceItem::Note(_) => 2,
+                SequenceItem::Message(_) => 1,
+            };
+            if self.sequence.len() + events > Track::SEQUENCE_CAPACITY {
+                dropped += 1;
+                continue;
+            }
+            self.sequence.insert_item(item);
+            let _ = self.recorded.try_push(item);
         }
-        if did_change {
-            self.sequence.sort_by_key(|e| e.position);
+        dropped
+    }
+
+    /// Remove the held note for `channel` and `pitch` from `recording_notes` and return it as a
+    /// note that ends at `end`. Returns `None` if the note is not held.
+    fn release_recording_note(
+        &mut self,
+        transport: &Transport,
+        channel: Channel,
+        pitch: bmidi::Note,
+        end: Position,
+    ) -> Option<SequenceItem> {
+        let idx = self
+            .recording_notes
+            .iter()
+            .position(|n| n.channel == channel && n.pitch == pitch)?;
+        let mut note = self.recording_notes.swap_remove(idx);
+        note.length = transport.elapsed(note.start, end);
+        note.start = self.sequence_position(transport, note.start);
+        Some(SequenceItem::Note(note))
+    }
+}
+
+impl MidiDestination {
+    /// All the midi destinations.
+    pub const ALL: &'static [MidiDestination] = &[
+        MidiDestination::Plugin,
+        MidiDestination::MidiOut,
+        MidiDestination::Both,
+    ];
+
+    /// The human readable name of the destination.
+    pub fn name(self) -> &'static str {
+        match self {
+            MidiDestination::Plugin => "plugin",
+            MidiDestination::MidiOut => "midi out",
+            MidiDestination::Both => "plugin and midi out",
+        }
+    }
+
+    /// Returns true if midi is sent to the plugin.
+    pub fn to_plugin(self) -> bool {
+        matches!(self, MidiDestination::Plugin | MidiDestination::Both)
+    }
+
+    /// Returns true if midi is sent to the midi output.
+    pub fn to_midi_out(self) -> bool {
+        matches!(self, MidiDestination::MidiOut | MidiDestination::Both)
+    }
+}
+
+impl TrackColor {
+    /// All the track colors.
+    pub const ALL: &'static [TrackColor] = &[
+        TrackColor::Red,
+        TrackColor::Green,
+        TrackColor::Yellow,
+        TrackColor::Blue,
+        TrackColor::Magenta,
+        TrackColor::Cyan,
+    ];
+
+    /// The human readable name of the color.
+    pub fn name(self) -> &'static str {
+        match self {
+            TrackColor::Red => "red",
+            TrackColor::Green => "green",
+            TrackColor::Yellow => "yellow",
+            TrackColor::Blue => "blue",
+            TrackColor::Magenta => "magenta",
+            TrackColor::Cyan => "cyan",
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use bats_dsp::{position::Position, sample_rate::SampleRate};
+    use bats_dsp::sample_rate::SampleRate;
     use bmidi::{Channel, Note, U7};
 
-    use crate::plugin::toof::Toof;
+    use crate::{
+        expression::ExpressionSource,
+        lfo::{LfoWaveform, ParamLfo},
+        plugin::{toof::Toof, BatsInstrument},
+    };
 
     use super::*;
 
@@ -155,7 +747,8 @@ mod tests {
             plugin: AnyPlugin::Toof(Toof::new(sample_rate)),
             volume: 1.0,
             output: Buffers::new(buffer_size),
-            sequence: Vec::new(),
+            sequence: Sequence::new(),
+            ..Track::new(buffer_size)
         };
         assert!(track.output.is_zero());
         let mut midi = Vec::new();
@@ -163,7 +756,9 @@ mod tests {
             record_to_sequence: false,
             transport: &Transport::new_prepopulated(sample_rate, buffer_size, 120.0),
             midi_in: &[],
+            record_latency: 0,
             tmp_midi_buffer: &mut midi,
+            input: None,
         });
         assert!(track.output.is_zero());
         assert_eq!(midi, vec![]);
@@ -177,10 +772,14 @@ mod tests {
             plugin: AnyPlugin::Toof(Toof::new(sample_rate)),
             volume: 1.0,
             output: Buffers::new(buffer_size),
-            sequence: vec![MidiEvent {
-                position: Position::MIN,
-                midi: NOTE_ON,
-            }],
+            sequence: Sequence::from_iter([crate::sequence::Note {
+                start: Position::MIN,
+                length: Position::new(1.0),
+                channel: Channel::Ch1,
+                pitch: Note::C3,
+                velocity: U7::MAX,
+            }]),
+            ..Track::new(buffer_size)
         };
         assert!(track.output.is_zero());
         let mut midi = Vec::new();
@@ -188,12 +787,76 @@ mod tests {
             record_to_sequence: false,
             transport: &Transport::new_prepopulated(sample_rate, buffer_size, 120.0),
             midi_in: &[],
+            record_latency: 0,
             tmp_midi_buffer: &mut midi,
+            input: None,
         });
         assert!(!track.output.is_zero());
         assert_eq!(midi, vec![(0, NOTE_ON)]);
     }
 
+    #[test]
+    fn midi_out_destination_sends_sequence_to_midi_out_instead_of_plugin() {
+        let sample_rate = SampleRate::new(44100.0);
+        let buffer_size = 256;
+        let mut track = Track {
+            plugin: AnyPlugin::Toof(Toof::new(sample_rate)),
+            sequence: Sequence::from_iter([crate::sequence::Note {
+                start: Position::MIN,
+                length: Position::new(1.0),
+                channel: Channel::Ch1,
+                pitch: Note::C3,
+                velocity: U7::MAX,
+            }]),
+            midi_destination: MidiDestination::MidiOut,
+            ..Track::new(buffer_size)
+        };
+        track.process(TrackProcessContext {
+            record_to_sequence: false,
+            transport: &Transport::new_prepopulated(sample_rate, buffer_size, 120.0),
+            midi_in: &[],
+            record_latency: 0,
+            tmp_midi_buffer: &mut Vec::new(),
+            input: None,
+        });
+        assert!(track.output.is_zero());
+        assert_eq!(track.midi_out.as_slice(), &[(0, NOTE_ON)]);
+        track.release_sequence_notes(Position::new(0.5), 255);
+        assert_eq!(track.midi_out.as_slice(), &[(0, NOTE_ON), (255, NOTE_OFF)]);
+    }
+
+    #[test]
+    fn note_off_past_loop_end_is_played_when_transport_loops() {
+        let sample_rate = SampleRate::new(4.0);
+        let note = |start: f64| crate::sequence::Note {
+            start: Position::new(start),
+            length: Position::new(1.0),
+            channel: Channel::Ch1,
+            pitch: Note::C3,
+            velocity: U7::MAX,
+        };
+        let mut track = Track {
+            sequence: Sequence::from_iter([note(15.5), note(17.0)]),
+            ..Track::new(8)
+        };
+        let mut transport = Transport::new(sample_rate, 8, 60.0);
+        transport.set_position(Position::new(15.0));
+        let mut buffers = Buffers::new(8);
+        transport.process(&mut buffers.left, &mut buffers.right);
+        let mut midi = Vec::new();
+        track.process(TrackProcessContext {
+            record_to_sequence: false,
+            transport: &transport,
+            midi_in: &[],
+            record_latency: 0,
+            tmp_midi_buffer: &mut midi,
+            input: None,
+        });
+        // The note on past the loop end is skipped. Its note off is still sent but is harmless
+        // since the note is not playing.
+        assert_eq!(midi, vec![(2, NOTE_ON), (3, NOTE_OFF), (3, NOTE_OFF)]);
+    }
+
     #[test]
     fn sequence_out_of_range_of_transport_remains_silent() {
         let sample_rate = SampleRate::new(44100.0);
@@ -202,10 +865,11 @@ mod tests {
             plugin: AnyPlugin::Toof(Toof::new(sample_rate)),
             volume: 1.0,
             output: Buffers::new(buffer_size),
-            sequence: vec![MidiEvent {
+            sequence: Sequence::from(vec![MidiEvent {
                 position: Position::new(1000.0),
                 midi: NOTE_ON,
-            }],
+            }]),
+            ..Track::new(buffer_size)
         };
         assert!(track.output.is_zero());
         let mut midi = Vec::new();
@@ -213,7 +877,9 @@ mod tests {
             record_to_sequence: false,
             transport: &Transport::new_prepopulated(sample_rate, buffer_size, 120.0),
             midi_in: &[],
+            record_latency: 0,
             tmp_midi_buffer: &mut midi,
+            input: None,
         });
         assert!(track.output.is_zero());
         assert_eq!(midi, vec![]);
@@ -227,7 +893,8 @@ mod tests {
             plugin: AnyPlugin::Toof(Toof::new(sample_rate)),
             volume: 1.0,
             output: Buffers::new(buffer_size),
-            sequence: Vec::new(),
+            sequence: Sequence::new(),
+            ..Track::new(buffer_size)
         };
         assert!(track.output.is_zero());
         let mut midi = Vec::new();
@@ -235,7 +902,9 @@ mod tests {
             record_to_sequence: false,
             transport: &Transport::new_prepopulated(sample_rate, buffer_size, 120.0),
             midi_in: &[(0, NOTE_ON)],
+            record_latency: 0,
             tmp_midi_buffer: &mut midi,
+            input: None,
         });
         assert!(!track.output.is_zero());
         assert_eq!(midi, vec![(0, NOTE_ON)]);
@@ -247,7 +916,7 @@ mod tests {
         let buffer_size = 256;
 
         let transport = Transport::new_prepopulated(sample_rate, buffer_size, 120.0);
-        let sequence = vec![
+        let sequence = Sequence::from(vec![
             MidiEvent {
                 position: Position::new(0.0),
                 midi: NOTE_ON,
@@ -256,19 +925,22 @@ mod tests {
                 position: transport.iter_transport().nth(100).unwrap().start,
                 midi: NOTE_OFF,
             },
-        ];
+        ]);
         let mut track = Track {
             plugin: AnyPlugin::Toof(Toof::new(sample_rate)),
             volume: 1.0,
             output: Buffers::new(buffer_size),
             sequence,
+            ..Track::new(buffer_size)
         };
         let mut midi = Vec::new();
         track.process(TrackProcessContext {
             record_to_sequence: false,
             transport: &transport,
             midi_in: &[(10, NOTE_OFF), (20, NOTE_ON)],
+            record_latency: 0,
             tmp_midi_buffer: &mut midi,
+            input: None,
         });
         assert_eq!(
             midi,
@@ -284,7 +956,8 @@ mod tests {
             plugin: AnyPlugin::Toof(Toof::new(sample_rate)),
             volume: 1.0,
             output: Buffers::new(buffer_size),
-            sequence: Vec::new(),
+            sequence: Sequence::new(),
+            ..Track::new(buffer_size)
         };
         assert!(track.output.is_zero());
         assert!(track.sequence.is_empty());
@@ -292,10 +965,12 @@ mod tests {
             record_to_sequence: false,
             transport: &Transport::new_prepopulated(sample_rate, buffer_size, 120.0),
             midi_in: &[(0, NOTE_ON)],
+            record_latency: 0,
             tmp_midi_buffer: &mut Vec::new(),
+            input: None,
         });
         assert!(!track.output.is_zero());
-        assert_eq!(track.sequence, vec![]);
+        assert!(track.sequence.is_empty());
     }
 
     #[test]
@@ -306,7 +981,8 @@ mod tests {
             plugin: AnyPlugin::Toof(Toof::new(sample_rate)),
             volume: 1.0,
             output: Buffers::new(buffer_size),
-            sequence: Vec::new(),
+            sequence: Sequence::new(),
+            ..Track::new(buffer_size)
         };
         assert!(track.output.is_zero());
         assert!(track.sequence.is_empty());
@@ -314,16 +990,498 @@ mod tests {
         track.process(TrackProcessContext {
             record_to_sequence: true,
             transport: &transport,
-            midi_in: &[(40, NOTE_ON)],
+            midi_in: &[(40, NOTE_ON), (100, NOTE_OFF)],
+            record_latency: 0,
             tmp_midi_buffer: &mut Vec::new(),
+            input: None,
         });
         assert!(!track.output.is_zero());
+        let start = transport.range_for_frame(40).start;
         assert_eq!(
-            track.sequence,
-            vec![MidiEvent {
-                position: transport.iter_transport().nth(40).unwrap().start,
-                midi: NOTE_ON
+            track.sequence.notes(),
+            &[crate::sequence::Note {
+                start,
+                length: transport.range_for_frame(100).start - start,
+                channel: Channel::Ch1,
+                pitch: Note::C3,
+                velocity: U7::MAX,
             }]
         );
     }
+
+    #[test]
+    fn record_latency_moves_recorded_midi_earlier() {
+        let sample_rate = SampleRate::new(44100.0);
+        let buffer_size = 256;
+        let mut track = Track {
+            plugin: AnyPlugin::Toof(Toof::new(sample_rate)),
+            ..Track::new(buffer_size)
+        };
+        let transport = Transport::new_prepopulated(sample_rate, buffer_size, 120.0);
+        track.process(TrackProcessContext {
+            record_to_sequence: true,
+            transport: &transport,
+            midi_in: &[(40, NOTE_ON), (100, NOTE_OFF)],
+            record_latency: 30,
+            tmp_midi_buffer: &mut Vec::new(),
+            input: None,
+        });
+        let note = track.sequence.notes()[0];
+        let expected_start = transport.range_for_frame(10).start;
+        let expected_length =
+            transport.range_for_frame(100).start - transport.range_for_frame(40).start;
+        assert!((note.start.as_beats_f64() - expected_start.as_beats_f64()).abs() < 1e-6);
+        assert!((note.length.as_beats_f64() - expected_length.as_beats_f64()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn volume_changes_are_ramped_over_buffer() {
+        let mut track = Track {
+            output: Buffers::with_iter(std::iter::repeat_n((1.0, 1.0), 4)),
+            ..Track::new(4)
+        };
+        track.volume = 0.0;
+        let mut output = Buffers::new(4);
+        track.mix_output(&mut output.left, &mut output.right);
+        assert_eq!(output.left, vec![0.75, 0.5, 0.25, 0.0]);
+        assert_eq!(output.right, vec![0.75, 0.5, 0.25, 0.0]);
+    }
+
+    #[test]
+    fn recording_holds_notes_until_released() {
+        let sample_rate = SampleRate::new(44100.0);
+        let transport = Transport::new_prepopulated(sample_rate, 64, 120.0);
+        let mut track = Track::new(64);
+        let mut record = |midi_in: &[(u32, MidiMessage)]| {
+            track.process(TrackProcessContext {
+                record_to_sequence: true,
+                transport: &transport,
+                midi_in,
+                record_latency: 0,
+                tmp_midi_buffer: &mut Vec::new(),
+                input: None,
+            });
+            track.sequence.notes().to_vec()
+        };
+        assert!(record(&[(10, NOTE_ON)]).is_empty());
+        let notes = record(&[(30, NOTE_OFF)]);
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].start, transport.range_for_frame(10).start);
+        assert_eq!(
+            notes[0].length,
+            transport.range_for_frame(30).start - transport.range_for_frame(10).start
+        );
+        assert!(track.recording_notes.is_empty());
+    }
+
+    #[test]
+    fn repeated_note_on_ends_previous_note() {
+        let sample_rate = SampleRate::new(44100.0);
+        let transport = Transport::new_prepopulated(sample_rate, 64, 120.0);
+        let mut track = Track::new(64);
+        track.process(TrackProcessContext {
+            record_to_sequence: true,
+            transport: &transport,
+            midi_in: &[(10, NOTE_ON), (20, NOTE_ON), (30, NOTE_OFF)],
+            record_latency: 0,
+            tmp_midi_buffer: &mut Vec::new(),
+            input: None,
+        });
+        let starts: Vec<_> = track.sequence.notes().iter().map(|n| n.start).collect();
+        assert_eq!(
+            starts,
+            vec![
+                transport.range_for_frame(10).start,
+                transport.range_for_frame(20).start
+            ]
+        );
+        assert_eq!(
+            track
+                .sequence
+                .events()
+                .iter()
+                .map(|e| e.midi)
+                .collect::<Vec<_>>(),
+            vec![NOTE_ON, NOTE_OFF, NOTE_ON, NOTE_OFF]
+        );
+    }
+
+    #[test]
+    fn held_notes_are_dropped_when_recording_stops() {
+        let sample_rate = SampleRate::new(44100.0);
+        let transport = Transport::new_prepopulated(sample_rate, 64, 120.0);
+        let mut track = Track::new(64);
+        let mut process = |record_to_sequence, midi_in: &[(u32, MidiMessage)]| {
+            track.process(TrackProcessContext {
+                record_to_sequence,
+                transport: &transport,
+                midi_in,
+                record_latency: 0,
+                tmp_midi_buffer: &mut Vec::new(),
+                input: None,
+            });
+        };
+        process(true, &[(10, NOTE_ON)]);
+        process(false, &[]);
+        process(true, &[(30, NOTE_OFF)]);
+        assert!(track.sequence.is_empty());
+        assert!(track.recording_notes.is_empty());
+    }
+
+    #[test]
+    fn loop_length_is_detected_when_recording_into_empty_track_stops() {
+        let sample_rate = SampleRate::new(44100.0);
+        let mut transport = Transport::new(sample_rate, 64, 120.0);
+        transport.set_position(Position::new(5.0));
+        let mut buffers = Buffers::new(64);
+        transport.process(&mut buffers.left, &mut buffers.right);
+        let mut track = Track::new(64);
+        let mut process = |record_to_sequence, midi_in: &[(u32, MidiMessage)]| {
+            track.process(TrackProcessContext {
+                record_to_sequence,
+                transport: &transport,
+                midi_in,
+                record_latency: 0,
+                tmp_midi_buffer: &mut Vec::new(),
+                input: None,
+            });
+            track.loop_length
+        };
+        assert_eq!(process(true, &[(10, NOTE_ON), (30, NOTE_OFF)]), None);
+        // The note ends in the second bar.
+        assert_eq!(process(false, &[]), Some(Position::new(8.0)));
+        // Recording into a track that is not empty keeps the loop length.
+        process(true, &[(10, NOTE_ON), (30, NOTE_OFF)]);
+        assert_eq!(process(false, &[]), Some(Position::new(8.0)));
+    }
+
+    #[test]
+    fn recording_and_playback_repeat_within_loop_length() {
+        let sample_rate = SampleRate::new(44100.0);
+        let mut transport = Transport::new(sample_rate, 64, 120.0);
+        transport.set_position(Position::new(9.0));
+        let mut buffers = Buffers::new(64);
+        transport.process(&mut buffers.left, &mut buffers.right);
+        let mut track = Track {
+            loop_length: Some(Position::new(4.0)),
+            ..Track::new(64)
+        };
+        let mut tmp_midi_buffer = Vec::new();
+        track.process(TrackProcessContext {
+            record_to_sequence: true,
+            transport: &transport,
+            midi_in: &[(10, NOTE_ON), (30, NOTE_OFF)],
+            record_latency: 0,
+            tmp_midi_buffer: &mut tmp_midi_buffer,
+            input: None,
+        });
+        let start = transport.range_for_frame(10).start - Position::new(8.0);
+        assert_eq!(track.sequence.notes()[0].start, start);
+        assert!(start < Position::new(4.0));
+
+        // The note plays back at the same frame when the loop repeats.
+        track.process(TrackProcessContext {
+            record_to_sequence: false,
+            transport: &transport,
+            midi_in: &[],
+            record_latency: 0,
+            tmp_midi_buffer: &mut tmp_midi_buffer,
+            input: None,
+        });
+        assert_eq!(tmp_midi_buffer, vec![(10, NOTE_ON), (30, NOTE_OFF)]);
+    }
+
+    #[test]
+    fn recording_to_full_sequence_drops_notes_without_allocating() {
+        let sample_rate = SampleRate::new(44100.0);
+        let transport = Transport::new_prepopulated(sample_rate, 64, 120.0);
+        let mut track = Track::new(64);
+        let filler = crate::sequence::Note {
+            start: Position::MIN,
+            length: Position::new(1.0),
+            channel: Channel::Ch1,
+            pitch: Note::C4,
+            velocity: U7::MAX,
+        };
+        for _ in 0..Track::SEQUENCE_CAPACITY / 2 - 1 {
+            track.sequence.insert_note(filler);
+        }
+        let capacity = track.sequence.capacity();
+        let dropped = track.process(TrackProcessContext {
+            record_to_sequence: true,
+            transport: &transport,
+            midi_in: &[(0, NOTE_ON), (1, NOTE_OFF), (2, NOTE_ON), (3, NOTE_OFF)],
+            record_latency: 0,
+            tmp_midi_buffer: &mut Vec::new(),
+            input: None,
+        });
+        assert_eq!(dropped, 1);
+        assert_eq!(track.sequence.len(), Track::SEQUENCE_CAPACITY);
+        assert_eq!(track.sequence.capacity(), capacity);
+    }
+
+    #[test]
+    fn automation_sets_param_at_start_of_buffer() {
+        let sample_rate = SampleRate::new(44100.0);
+        let transport = Transport::new_prepopulated(sample_rate, 64, 120.0);
+        let mut lane = AutomationLane::new(2);
+        lane.record(Position::MIN, 1000.0);
+        lane.record(Position::new(1.0), 2000.0);
+        let mut track = Track {
+            plugin: AnyPlugin::Toof(Toof::new(sample_rate)),
+            automation: vec![lane],
+            ..Track::new(64)
+        };
+        track.process(TrackProcessContext {
+            record_to_sequence: false,
+            transport: &transport,
+            midi_in: &[],
+            record_latency: 0,
+            tmp_midi_buffer: &mut Vec::new(),
+            input: None,
+        });
+        assert_eq!(track.plugin.plugin().param(2), 1000.0);
+    }
+
+    #[test]
+    fn lfos_override_automation_and_are_clamped() {
+        let sample_rate = SampleRate::new(44100.0);
+        let mut transport = Transport::new(sample_rate, 64, 120.0);
+        transport.set_position(Position::new(0.25));
+        let mut buffers = Buffers::new(64);
+        transport.process(&mut buffers.left, &mut buffers.right);
+        let mut lane = AutomationLane::new(2);
+        lane.record(Position::MIN, 1000.0);
+        let mut track = Track {
+            plugin: AnyPlugin::Toof(Toof::new(sample_rate)),
+            automation: vec![lane],
+            lfos: vec![ParamLfo {
+                param_id: 2,
+                waveform: LfoWaveform::Square,
+                beats: 1.0,
+                center: 5000.0,
+                depth: 5000.0,
+            }],
+            ..Track::new(64)
+        };
+        track.process(TrackProcessContext {
+            record_to_sequence: false,
+            transport: &transport,
+            midi_in: &[],
+            record_latency: 0,
+            tmp_midi_buffer: &mut Vec::new(),
+            input: None,
+        });
+        assert_eq!(track.plugin.plugin().param(2), 9000.0);
+    }
+
+    #[test]
+    fn expression_routes_set_params() {
+        let sample_rate = SampleRate::new(44100.0);
+        let transport = Transport::new_prepopulated(sample_rate, 64, 120.0);
+        let mut track = Track {
+            plugin: AnyPlugin::Toof(Toof::new(sample_rate)),
+            expression_routes: vec![ExpressionRoute {
+                source: ExpressionSource::ModWheel,
+                param_id: 2,
+                min: 1000.0,
+                max: 2000.0,
+            }],
+            ..Track::new(64)
+        };
+        let mod_wheel = |v| {
+            MidiMessage::ControlChange(Channel::Ch1, bmidi::ControlFunction::MODULATION_WHEEL, v)
+        };
+        track.process(TrackProcessContext {
+            record_to_sequence: false,
+            transport: &transport,
+            midi_in: &[(0, mod_wheel(U7::MAX)), (10, mod_wheel(U7::MIN))],
+            record_latency: 0,
+            tmp_midi_buffer: &mut Vec::new(),
+            input: None,
+        });
+        assert_eq!(track.plugin.plugin().param(2), 1000.0);
+    }
+
+    #[test]
+    fn set_plugin_crossfades_from_old_plugin() {
+        let sample_rate = SampleRate::new(44100.0);
+        let transport = Transport::new_prepopulated(sample_rate, 64, 120.0);
+        let mut track = Track {
+            plugin: AnyPlugin::Toof(Toof::new(sample_rate)),
+            ..Track::new(64)
+        };
+        let process = |track: &mut Track, midi_in: &[(u32, MidiMessage)]| {
+            track.process(TrackProcessContext {
+                record_to_sequence: false,
+                transport: &transport,
+                midi_in,
+                record_latency: 0,
+                tmp_midi_buffer: &mut Vec::new(),
+                input: None,
+            });
+        };
+        process(&mut track, &[(0, NOTE_ON)]);
+        assert!(!track.output.is_zero());
+
+        track
+            .set_plugin(&PluginSlot::new(AnyPlugin::default()), 128)
+            .unwrap();
+        process(&mut track, &[]);
+        assert!(!track.output.is_zero());
+        assert!(track.fading_plugin.is_some());
+        process(&mut track, &[]);
+        assert_eq!(track.fading_plugin, None);
+        assert_eq!(track.retired_plugins.len(), 1);
+        process(&mut track, &[]);
+        assert!(track.output.is_zero());
+    }
+
+    #[test]
+    fn set_plugin_is_refused_while_retired_plugins_is_full() {
+        let mut track = Track::new(64);
+        for _ in 0..Track::RETIRED_PLUGINS_CAPACITY + 1 {
+            track
+                .set_plugin(&PluginSlot::new(AnyPlugin::default()), 128)
+                .unwrap();
+        }
+        assert!(track.retired_plugins.is_full());
+        let slot = PluginSlot::new(AnyPlugin::default());
+        assert_eq!(
+            track.set_plugin(&slot, 128),
+            Err(SetPluginError::TooManyRetired)
+        );
+        assert_eq!(slot.try_take(), Some(AnyPlugin::default()));
+        assert_eq!(track.retired_plugins.len(), Track::RETIRED_PLUGINS_CAPACITY);
+        assert!(track.fading_plugin.is_some());
+    }
+
+    #[test]
+    fn replaced_plugin_is_taken_back_by_its_slot() {
+        let sample_rate = SampleRate::new(44100.0);
+        let toof = AnyPlugin::Toof(Toof::new(sample_rate));
+        let mut track = Track::new(64);
+        track.plugin = toof.clone();
+        let slot = PluginSlot::new(AnyPlugin::default());
+        track.set_plugin(&slot, 128).unwrap();
+        assert_eq!(track.plugin, AnyPlugin::default());
+        assert_eq!(slot.try_take(), None);
+
+        // Undo before the crossfade is complete.
+        track.set_plugin(&slot, 128).unwrap();
+        assert_eq!(track.plugin, toof);
+        assert_eq!(
+            track.fading_plugin,
+            Some(ReplacedPlugin {
+                plugin: AnyPlugin::default(),
+                slot: slot.clone()
+            })
+        );
+        assert!(track.retired_plugins.is_empty());
+    }
+
+    #[test]
+    fn set_plugin_from_empty_slot_is_refused() {
+        let mut track = Track::new(64);
+        assert_eq!(
+            track.set_plugin(&PluginSlot::default(), 128),
+            Err(SetPluginError::EmptySlot)
+        );
+        assert_eq!(track.fading_plugin, None);
+    }
+
+    #[test]
+    fn compressor_is_applied_to_output() {
+        let sample_rate = SampleRate::new(44100.0);
+        let transport = Transport::new_prepopulated(sample_rate, 64, 120.0);
+        let mut compressor = Compressor::new(sample_rate);
+        compressor.set_param(1, 1.0);
+        compressor.set_param(5, 2.0);
+        let mut plain = Track {
+            plugin: AnyPlugin::Toof(Toof::new(sample_rate)),
+            ..Track::new(64)
+        };
+        let mut compressed = Track {
+            compressor: Some(compressor),
+            ..plain.clone()
+        };
+        for track in [&mut plain, &mut compressed] {
+            track.process(TrackProcessContext {
+                record_to_sequence: false,
+                transport: &transport,
+                midi_in: &[(0, NOTE_ON)],
+                record_latency: 0,
+                tmp_midi_buffer: &mut Vec::new(),
+                input: None,
+            });
+        }
+        assert!(!plain.output.is_zero());
+        let doubled: Vec<_> = plain.output.left.iter().map(|v| v * 2.0).collect();
+        assert_eq!(compressed.output.left, doubled);
+    }
+
+    #[test]
+    fn write_output_replaces_and_mix_output_adds() {
+        let mut track = Track::new(4);
+        track.output.left.fill(1.0);
+        track.output.right.fill(2.0);
+        track.volume = 0.5;
+        track.volume_smoother.set_value(0.5);
+        let mut left = vec![1.0; 4];
+        let mut right = vec![1.0; 4];
+        track.mix_output(&mut left, &mut right);
+        assert_eq!(
+            (left.as_slice(), right.as_slice()),
+            (&[1.5; 4][..], &[2.0; 4][..])
+        );
+        track.write_output(&mut left, &mut right);
+        assert_eq!(
+            (left.as_slice(), right.as_slice()),
+            (&[0.5; 4][..], &[1.0; 4][..])
+        );
+    }
+
+    #[test]
+    fn track_is_silent_only_with_empty_plugin_and_no_crossfade() {
+        let sample_rate = SampleRate::new(44100.0);
+        let mut track = Track::new(64);
+        assert!(track.is_silent());
+        track
+            .set_plugin(&PluginSlot::new(AnyPlugin::Toof(Toof::new(sample_rate))), 0)
+            .unwrap();
+        assert!(!track.is_silent());
+        track
+            .set_plugin(&PluginSlot::new(AnyPlugin::default()), 128)
+            .unwrap();
+        assert!(!track.is_silent());
+        track.fading_plugin = None;
+        assert!(track.is_silent());
+    }
+
+    #[test]
+    fn tuning_is_sent_to_plugins_when_set_and_when_installed() {
+        let sample_rate = SampleRate::new(44100.0);
+        let scl = "pythagorean\n12\n256/243\n9/8\n32/27\n81/64\n4/3\n729/512\n3/2\n128/81\n\
+                   27/16\n16/9\n243/128\n2/1\n";
+        let tuning = Tuning::from_scala("pythagorean", scl, None).unwrap();
+        let tuned = {
+            let mut toof = Toof::new(sample_rate);
+            toof.set_tuning(&tuning);
+            AnyPlugin::Toof(toof)
+        };
+        let mut track = Track::new(64);
+        track
+            .set_plugin(&PluginSlot::new(AnyPlugin::Toof(Toof::new(sample_rate))), 0)
+            .unwrap();
+        assert_eq!(*track.set_tuning(Box::new(tuning)), Tuning::default());
+        assert_eq!(track.plugin, tuned);
+        track
+            .set_plugin(
+                &PluginSlot::new(AnyPlugin::Toof(Toof::new(sample_rate))),
+                128,
+            )
+            .unwrap();
+        assert_eq!(track.plugin, tuned);
+        assert_eq!(track.fading_plugin.unwrap().plugin, tuned);
+    }
 }
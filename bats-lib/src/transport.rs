@@ -3,34 +3,103 @@ use std::ops::Range;
 use bats_dsp::{position::Position, sample_rate::SampleRate, sawtooth::Sawtooth};
 use bmidi::{Channel, MidiMessage, Note, U7};
 
-
-use crate::plugin::BatsInstrument;
+use crate::plugin::{BatsInstrument, TransportInfo};
 
 /// Tracks position according to the specified BPM.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Transport {
     /// The volume of the metronome.
     pub metronome_volume: f32,
+    /// The clicks that the metronome plays between beats.
+    pub metronome_subdivision: MetronomeSubdivision,
     /// The positions for each frame.
     transport: Vec<Position>,
+    /// The frames in `transport` where the position loops back around to the start. This is
+    /// precomputed once per buffer so that it can be shared by all tracks.
+    loop_frames: Vec<usize>,
     /// The beats per minute of the transport.
     bpm: f32,
     /// The current position fo the transport.
     position: Position,
+    /// True if the transport is playing. The position does not advance while stopped.
+    playing: bool,
+    /// True if the transport wraps around `loop_range`. If false, the transport runs linearly.
+    looping: bool,
+    /// The region that the transport loops within.
+    loop_range: Range<Position>,
     /// The amount of advancement the transport undergoes per frame.
     position_per_sample: Position,
     /// The metronome synth.
     sound_gen: MetronomeSynth,
 }
 
+/// Extra metronome clicks between beats for practicing fast material.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MetronomeSubdivision {
+    /// The number of clicks per beat, including the click on the beat. `1` only clicks on the
+    /// beat, `2` clicks on 8th notes, and `4` clicks on 16th notes.
+    pub clicks_per_beat: u32,
+    /// Where the second click of every pair of clicks lands as a ratio of the pair. `0.5` is
+    /// straight and `2/3` is a triplet shuffle.
+    pub swing: f32,
+}
+
+impl MetronomeSubdivision {
+    /// The swing for evenly spaced clicks.
+    pub const MIN_SWING: f32 = 0.5;
+
+    /// The largest supported swing.
+    pub const MAX_SWING: f32 = 0.75;
+
+    /// Get the index of the last click within the beat at or before `sub_beat`.
+    fn click_index(&self, sub_beat: u32) -> u32 {
+        let pairs_per_beat = self.clicks_per_beat as f64 / 2.0;
+        let pairs = sub_beat as f64 / (1u64 << 32) as f64 * pairs_per_beat;
+        let swing = self.swing.clamp(Self::MIN_SWING, Self::MAX_SWING) as f64;
+        let is_second = pairs.fract() >= swing;
+        pairs.floor() as u32 * 2 + is_second as u32
+    }
+}
+
+impl Default for MetronomeSubdivision {
+    fn default() -> MetronomeSubdivision {
+        MetronomeSubdivision {
+            clicks_per_beat: 1,
+            swing: MetronomeSubdivision::MIN_SWING,
+        }
+    }
+}
+
+/// How the transport is synced with the transport of the audio backend. Backends without a
+/// transport of their own ignore this.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransportSync {
+    /// If the transport should start, stop, and move along with the backend transport.
+    pub follow: bool,
+    /// If the bpm should be published to the backend transport so that other clients can follow
+    /// the tempo.
+    pub timebase_master: bool,
+}
+
 impl Transport {
+    /// The number of beats in the default loop.
+    pub const LOOP_BEATS: u32 = 16;
+
+    /// The number of loop points that can be stored per buffer without allocating.
+    const LOOP_FRAMES_CAPACITY: usize = 8;
+
     /// Create a new transport with the given sample rate and beats per minute.
     pub fn new(sample_rate: SampleRate, buffer_size: usize, bpm: f32) -> Transport {
         Transport {
             metronome_volume: 0.0,
+            metronome_subdivision: MetronomeSubdivision::default(),
             transport: Vec::with_capacity(buffer_size + 1),
+            loop_frames: Vec::with_capacity(Transport::LOOP_FRAMES_CAPACITY),
             bpm,
             position: Position::default(),
+            playing: true,
+            looping: true,
+            loop_range: Position::MIN..Position::new(Transport::LOOP_BEATS as f64),
             position_per_sample: Position::delta_from_bpm(sample_rate, bpm),
             sound_gen: MetronomeSynth::new(sample_rate),
         }
@@ -44,17 +113,121 @@ impl Transport {
         t
     }
 
+    /// Reserve enough space to process buffers of `buffer_size` without allocating.
+    pub fn set_buffer_size(&mut self, buffer_size: usize) {
+        let additional = (buffer_size + 1).saturating_sub(self.transport.len());
+        self.transport.reserve(additional);
+    }
+
     /// Set the beats per minute for a metronome.
     pub fn set_bpm(&mut self, sample_rate: SampleRate, bpm: f32) {
         self.bpm = bpm;
         self.position_per_sample = Position::delta_from_bpm(sample_rate, bpm);
     }
 
+    /// Set the sample rate. The bpm and metronome decay are preserved.
+    pub fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.position_per_sample = Position::delta_from_bpm(sample_rate, self.bpm);
+        self.sound_gen.set_sample_rate(sample_rate);
+    }
+
+    /// Get the position at the start of the next buffer.
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// Set the position at the start of the next buffer.
+    pub fn set_position(&mut self, position: Position) {
+        self.position = position;
+    }
+
+    /// Returns true if the transport is playing.
+    pub fn playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Start or stop the transport. The position does not advance while the transport is stopped.
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+
+    /// Returns true if the transport wraps around the loop range.
+    pub fn looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Set if the transport should wrap around the loop range or run linearly.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    /// Get the region that the transport loops within.
+    pub fn loop_range(&self) -> Range<Position> {
+        self.loop_range.clone()
+    }
+
+    /// Set the region that the transport loops within. Empty ranges are ignored.
+    pub fn set_loop_range(&mut self, loop_range: Range<Position>) {
+        if loop_range.is_empty() {
+            return;
+        }
+        self.loop_range = loop_range;
+    }
+
+    /// Get the amount of time that passes when moving from `start` to `end`. If `end` is before
+    /// `start`, the transport is assumed to have wrapped around the loop.
+    pub fn elapsed(&self, start: Position, end: Position) -> Position {
+        if start <= end {
+            return end - start;
+        }
+        let (loop_start, loop_end) = (self.loop_range.start, self.loop_range.end);
+        (loop_end.max(start) - start) + (end.max(loop_start) - loop_start)
+    }
+
+    /// Get the position `frames` frames before `position` at the current bpm. When looping,
+    /// positions within the loop wrap around to the end of the loop. Otherwise the position stops
+    /// at the start of the transport.
+    pub fn rewind(&self, position: Position, frames: u32) -> Position {
+        let delta = Position::new(self.position_per_sample.as_beats_f64() * frames as f64);
+        let (loop_start, loop_end) = (self.loop_range.start, self.loop_range.end);
+        if !self.looping || position < loop_start {
+            return if delta > position {
+                Position::MIN
+            } else {
+                position - delta
+            };
+        }
+        let offset = (position - loop_start).wrapping_sub_in_loop(delta, loop_end - loop_start);
+        loop_start + offset
+    }
+
+    /// Get the position that is `frame` frames after the start of the transport at the current
+    /// bpm. When looping, positions past the end of the loop wrap around to the start of the loop.
+    pub fn position_at_frame(&self, frame: u64) -> Position {
+        let position = Position::new(self.position_per_sample.as_beats_f64() * frame as f64);
+        let (loop_start, loop_end) = (self.loop_range.start, self.loop_range.end);
+        if !self.looping || position < loop_end {
+            return position;
+        }
+        loop_start
+            + (position - loop_start).wrapping_sub_in_loop(Position::MIN, loop_end - loop_start)
+    }
+
     /// Get the current bpm.
     pub fn bpm(&self) -> f32 {
         self.bpm
     }
 
+    /// Get the transport info for the current buffer.
+    pub fn info(&self) -> TransportInfo {
+        TransportInfo {
+            position: self.transport.first().copied().unwrap_or(self.position),
+            bpm: self.bpm,
+            samples_per_beat: (1.0 / self.position_per_sample.as_beats_f64()) as f32,
+            playing: self.playing,
+        }
+    }
+
     /// Set the decay of the synth.
     pub fn set_synth_decay(&mut self, sample_rate: SampleRate, duration_seconds: f32) {
         if duration_seconds <= 0.0 {
@@ -80,13 +253,27 @@ impl Transport {
         self.transport.clear();
         self.transport.extend((0..samples).map(|_| {
             let ret = self.position;
+            if !self.playing {
+                return ret;
+            }
             self.position += self.position_per_sample;
-            if self.position.beat() >= 16 {
-                self.position.set_beat(self.position.beat() % 16);
+            if self.looping && self.position >= self.loop_range.end {
+                let loop_length = self.loop_range.end - self.loop_range.start;
+                let past_end = self.position - self.loop_range.end;
+                self.position = self.loop_range.start
+                    + Position::from_bits(past_end.to_bits() % loop_length.to_bits());
             }
             ret
         }));
         self.transport.push(self.position);
+        self.loop_frames.clear();
+        self.loop_frames.extend(
+            self.transport
+                .windows(2)
+                .enumerate()
+                .filter(|(_, rng)| rng[1] < rng[0])
+                .map(|(frame, _)| frame),
+        );
         debug_assert!(
             self.transport.len() == samples + 1,
             "{} == {} + 1",
@@ -103,6 +290,97 @@ impl Transport {
         })
     }
 
+    /// Call `f` with the frame and item for every item in `sorted` that falls within the current
+    /// buffer. `sorted` must be sorted by `position_fn`. `f` is called in order of increasing
+    /// frame.
+    ///
+    /// Items are found by binary search so the cost scales with the number of items in the buffer
+    /// rather than the number of frames or items.
+    ///
+    /// The frame that wraps around the loop also includes all items after the end of the loop so
+    /// that events like note offs that land past the loop are not lost.
+    pub fn for_each_in_buffer<T>(
+        &self,
+        sorted: &[T],
+        position_fn: impl Fn(&T) -> Position,
+        mut f: impl FnMut(u32, &T),
+    ) {
+        if self.transport.len() < 2 || sorted.is_empty() {
+            return;
+        }
+        let mut segment_start = 0;
+        for &loop_frame in self.loop_frames.iter() {
+            let positions = &self.transport[segment_start..=loop_frame];
+            for_each_in_range(sorted, &position_fn, &mut f, positions, segment_start);
+            // The frame that loops covers the end of the loop and the start of the loop.
+            let loop_end = [self.transport[loop_frame], Position::MAX];
+            for_each_in_range(sorted, &position_fn, &mut f, &loop_end, loop_frame);
+            let loop_start = [self.loop_range.start, self.transport[loop_frame + 1]];
+            for_each_in_range(sorted, &position_fn, &mut f, &loop_start, loop_frame);
+            segment_start = loop_frame + 1;
+        }
+        let positions = &self.transport[segment_start..];
+        for_each_in_range(sorted, &position_fn, &mut f, positions, segment_start);
+    }
+
+    /// Like `for_each_in_buffer` but for items that repeat every `loop_length` beats from the
+    /// start of the loop range, such as the sequence of a track that is shorter than the loop.
+    /// Item positions are relative to the start of the transport, so items from
+    /// `loop_range().start` to `loop_range().start + loop_length` are played on every repeat.
+    ///
+    /// The frame where the items repeat also includes all items after the end of the repeat so
+    /// that events like note offs that land past the end are not lost.
+    pub fn for_each_in_buffer_with_loop_length<T>(
+        &self,
+        sorted: &[T],
+        position_fn: impl Fn(&T) -> Position,
+        loop_length: Position,
+        mut f: impl FnMut(u32, &T),
+    ) {
+        if self.transport.len() < 2 || sorted.is_empty() || loop_length == Position::MIN {
+            return;
+        }
+        let shift = |position: Position| {
+            let start = self.loop_range.start;
+            if position < start {
+                return Position::MIN;
+            }
+            let repeats = (position - start).to_bits() / loop_length.to_bits();
+            Position::from_bits(repeats * loop_length.to_bits())
+        };
+        let mut run_start = 0;
+        for frame in 0..self.transport.len() - 1 {
+            let (a, b) = (self.transport[frame], self.transport[frame + 1]);
+            let (shift_a, shift_b) = (shift(a), shift(b));
+            if b >= a && shift_a == shift_b {
+                continue;
+            }
+            let shifted = |item: &T| position_fn(item) + shift_a;
+            let positions = &self.transport[run_start..=frame];
+            for_each_in_range(sorted, &shifted, &mut f, positions, run_start);
+            // The frame that repeats covers the end of the repeat and the start of the next one.
+            for_each_in_range(sorted, &shifted, &mut f, &[a, Position::MAX], frame);
+            let shifted = |item: &T| position_fn(item) + shift_b;
+            let repeat_start = self.loop_range.start + shift_b;
+            for_each_in_range(sorted, &shifted, &mut f, &[repeat_start, b], frame);
+            run_start = frame + 1;
+        }
+        let shift_run = shift(self.transport[run_start]);
+        let shifted = |item: &T| position_fn(item) + shift_run;
+        let positions = &self.transport[run_start..];
+        for_each_in_range(sorted, &shifted, &mut f, positions, run_start);
+    }
+
+    /// Get the position within a repeat of `loop_length` beats that starts at the start of the
+    /// loop range. Positions before the loop range are returned unchanged.
+    pub fn position_in_loop_length(&self, position: Position, loop_length: Position) -> Position {
+        let start = self.loop_range.start;
+        if position < start || loop_length == Position::MIN {
+            return position;
+        }
+        start + Position::from_bits((position - start).to_bits() % loop_length.to_bits())
+    }
+
     /// Get the range for the given frame.
     pub fn range_for_frame(&self, frame: u32) -> Range<Position> {
         self.transport[frame as usize]..self.transport[(frame + 1) as usize]
@@ -114,6 +392,7 @@ impl Transport {
         let default_note = MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX);
         let new_measure_note = MidiMessage::NoteOn(Channel::Ch1, Note::C5, U7::MAX);
         let loop_note = MidiMessage::NoteOn(Channel::Ch1, Note::G5, U7::MAX);
+        let subdivision_note = MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::from_u8_lossy(48));
         for (idx, pos) in {
             let transport: &[Position] = &self.transport;
             transport.windows(2).map(|rng| match rng {
@@ -123,13 +402,19 @@ impl Transport {
         }
         .enumerate()
         {
-            if pos.0.beat() != pos.1.beat() || pos.0 == Position::MIN {
+            let is_new_beat = pos.0.beat() != pos.1.beat() || pos.0 == Position::MIN;
+            if self.playing && is_new_beat {
                 let note = match pos.1.beat() {
-                    0 => &loop_note,
-                    b if b % 4 == 0 => &new_measure_note,
+                    b if self.looping && b == self.loop_range.start.beat() => &loop_note,
+                    b if b % Position::BEATS_PER_BAR == 0 => &new_measure_note,
                     _ => &default_note,
                 };
                 self.sound_gen.handle_midi(note);
+            } else if self.playing
+                && self.metronome_subdivision.click_index(pos.0.sub_beat())
+                    != self.metronome_subdivision.click_index(pos.1.sub_beat())
+            {
+                self.sound_gen.handle_midi(&subdivision_note);
             }
             let (v, _) = self.sound_gen.process();
             left[idx] = v * self.metronome_volume;
@@ -138,6 +423,30 @@ impl Transport {
     }
 }
 
+/// Call `f` for every item in `sorted` that falls between the first and last values of
+/// `positions`. `positions` must be non-decreasing and `positions[i]` must be the start position
+/// of frame `first_frame + i`.
+fn for_each_in_range<T>(
+    sorted: &[T],
+    position_fn: &impl Fn(&T) -> Position,
+    f: &mut impl FnMut(u32, &T),
+    positions: &[Position],
+    first_frame: usize,
+) {
+    let (start, end) = match positions {
+        [start, .., end] => (*start, *end),
+        _ => return,
+    };
+    let start_idx = sorted.partition_point(|item| position_fn(item) < start);
+    for item in sorted[start_idx..]
+        .iter()
+        .take_while(|item| position_fn(item) < end)
+    {
+        let offset = positions.partition_point(|p| *p <= position_fn(item)) - 1;
+        f((first_frame + offset) as u32, item);
+    }
+}
+
 /// A simple synthesize for the metronome.
 #[derive(Copy, Clone, Debug, PartialEq)]
 struct MetronomeSynth {
@@ -169,14 +478,17 @@ impl BatsInstrument for MetronomeSynth {
     fn metadata(&self) -> &'static crate::plugin::metadata::Metadata {
         &crate::plugin::metadata::Metadata {
             name: "metronome_synth",
+            category: crate::plugin::metadata::PluginCategory::Utility,
+            tags: &[],
             params: &[],
+            pages: &[],
         }
     }
 
     fn handle_midi(&mut self, msg: &MidiMessage) {
-        if let MidiMessage::NoteOn(_, n, _) = msg {
+        if let MidiMessage::NoteOn(_, n, v) = msg {
             self.wave = Sawtooth::new(self.sample_rate, n.to_freq_f32());
-            self.amp = 1.0;
+            self.amp = u8::from(*v) as f32 / 127.0;
         }
     }
 
@@ -199,6 +511,15 @@ impl BatsInstrument for MetronomeSynth {
     fn set_param(&mut self, _id: u32, _value: f32) {}
 
     fn batch_cleanup(&mut self) {}
+
+    fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        // Keep the decay duration, in seconds, the same.
+        if self.amp_delta > -1.0 {
+            self.amp_delta *=
+                sample_rate.seconds_per_sample() / self.sample_rate.seconds_per_sample();
+        }
+        self.sample_rate = sample_rate;
+    }
 }
 
 #[cfg(test)]
@@ -242,4 +563,240 @@ mod tests {
         assert_eq!(buffers.left.iter().filter(|v| 0.0 != **v).count(), 2);
         assert_eq!(buffers.right.iter().filter(|v| 0.0 != **v).count(), 2);
     }
+
+    #[test]
+    fn metronome_subdivision_clicks_with_swing() {
+        // 8 frames per beat.
+        let sample_rate = SampleRate::new(32.0);
+        let clicks = |swing: f32| {
+            let mut transport = Transport::new(sample_rate, 16, 4.0 * 60.0);
+            let mut buffers = Buffers::new(16);
+            transport.metronome_volume = 1.0;
+            transport.metronome_subdivision = MetronomeSubdivision {
+                clicks_per_beat: 2,
+                swing,
+            };
+            transport.set_synth_decay(sample_rate, 0.0);
+            transport.process(&mut buffers.left, &mut buffers.right);
+            (0..16)
+                .filter(|idx| buffers.left[*idx] != 0.0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(clicks(0.5), vec![0, 3, 7, 11, 15]);
+        assert_eq!(clicks(0.75), vec![0, 5, 7, 13, 15]);
+    }
+
+    #[test]
+    fn info_has_position_at_start_of_buffer() {
+        let mut transport = Transport::new_prepopulated(SampleRate::new(4.0), 8, 60.0);
+        let info = transport.info();
+        assert_eq!(info.position, Position::new(0.0));
+        assert_eq!(info.bpm, 60.0);
+        assert_eq!(info.samples_per_beat, 4.0);
+        assert!(info.playing);
+        transport.process(&mut [0.0; 8], &mut [0.0; 8]);
+        assert_eq!(transport.info().position, Position::new(2.0));
+    }
+
+    #[test]
+    fn for_each_in_buffer_finds_items_by_frame() {
+        let transport = Transport::new_prepopulated(SampleRate::new(4.0), 8, 60.0);
+        let items = [
+            Position::new(0.0),
+            Position::new(0.3),
+            Position::new(1.0),
+            Position::new(1.5),
+            Position::new(2.0),
+        ];
+        let mut found = Vec::new();
+        transport.for_each_in_buffer(&items, |p| *p, |frame, p| found.push((frame, *p)));
+        assert_eq!(
+            found,
+            vec![
+                (0, Position::new(0.0)),
+                (1, Position::new(0.3)),
+                (4, Position::new(1.0)),
+                (6, Position::new(1.5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn for_each_in_buffer_handles_loop() {
+        let mut transport = Transport::new(SampleRate::new(4.0), 8, 60.0);
+        transport.position = Position::new(15.0);
+        transport.populate_transport(8);
+        let items = [
+            Position::new(0.0),
+            Position::new(0.25),
+            Position::new(14.0),
+            Position::new(15.5),
+            Position::new(15.9),
+        ];
+        let mut found = Vec::new();
+        transport.for_each_in_buffer(&items, |p| *p, |frame, p| found.push((frame, *p)));
+        assert_eq!(
+            found,
+            vec![
+                (2, Position::new(15.5)),
+                (3, Position::new(15.9)),
+                (4, Position::new(0.0)),
+                (5, Position::new(0.25)),
+            ]
+        );
+    }
+
+    #[test]
+    fn for_each_in_buffer_with_loop_length_repeats_items() {
+        let mut transport = Transport::new(SampleRate::new(4.0), 8, 60.0);
+        transport.position = Position::new(3.0);
+        transport.populate_transport(8);
+        let items = [
+            Position::new(0.0),
+            Position::new(0.5),
+            Position::new(1.9),
+            Position::new(2.5),
+        ];
+        let mut found = Vec::new();
+        transport.for_each_in_buffer_with_loop_length(
+            &items,
+            |p| *p,
+            Position::new(2.0),
+            |frame, p| found.push((frame, *p)),
+        );
+        // The transport covers beats 3.0 to 5.0 which repeat the items from 1.0 to 2.0 and
+        // then 0.0 to 1.0.
+        assert_eq!(
+            found,
+            vec![
+                (3, Position::new(1.9)),
+                (3, Position::new(2.5)),
+                (4, Position::new(0.0)),
+                (6, Position::new(0.5)),
+            ]
+        );
+        assert_eq!(
+            transport.position_in_loop_length(Position::new(4.5), Position::new(2.0)),
+            Position::new(0.5)
+        );
+    }
+
+    #[test]
+    fn transport_wraps_within_loop_range() {
+        let mut transport = Transport::new(SampleRate::new(4.0), 8, 60.0);
+        transport.set_loop_range(Position::new(4.0)..Position::new(5.0));
+        transport.position = Position::new(4.5);
+        transport.populate_transport(4);
+        assert_eq!(
+            transport.transport,
+            vec![
+                Position::new(4.5),
+                Position::new(4.75),
+                Position::new(4.0),
+                Position::new(4.25),
+                Position::new(4.5),
+            ]
+        );
+        assert_eq!(transport.loop_frames, vec![1]);
+    }
+
+    #[test]
+    fn transport_without_looping_runs_linearly() {
+        let mut transport = Transport::new(SampleRate::new(4.0), 8, 60.0);
+        transport.set_looping(false);
+        transport.position = Position::new(15.5);
+        transport.populate_transport(4);
+        assert_eq!(
+            transport.transport,
+            vec![
+                Position::new(15.5),
+                Position::new(15.75),
+                Position::new(16.0),
+                Position::new(16.25),
+                Position::new(16.5),
+            ]
+        );
+        assert!(transport.loop_frames.is_empty());
+    }
+
+    #[test]
+    fn empty_loop_range_is_ignored() {
+        let mut transport = Transport::new(SampleRate::new(4.0), 8, 60.0);
+        transport.set_loop_range(Position::new(4.0)..Position::new(4.0));
+        assert_eq!(
+            transport.loop_range(),
+            Position::MIN..Position::new(Transport::LOOP_BEATS as f64)
+        );
+    }
+
+    #[test]
+    fn elapsed_wraps_around_loop_range() {
+        let mut transport = Transport::new(SampleRate::new(4.0), 8, 60.0);
+        transport.set_loop_range(Position::new(4.0)..Position::new(8.0));
+        assert_eq!(
+            transport.elapsed(Position::new(5.0), Position::new(6.5)),
+            Position::new(1.5)
+        );
+        assert_eq!(
+            transport.elapsed(Position::new(7.0), Position::new(4.5)),
+            Position::new(1.5)
+        );
+    }
+
+    #[test]
+    fn rewind_wraps_around_loop_range() {
+        let mut transport = Transport::new(SampleRate::new(4.0), 8, 60.0);
+        transport.set_loop_range(Position::new(4.0)..Position::new(8.0));
+        assert_eq!(transport.rewind(Position::new(5.0), 4), Position::new(4.0));
+        assert_eq!(transport.rewind(Position::new(4.5), 4), Position::new(7.5));
+        transport.set_looping(false);
+        assert_eq!(transport.rewind(Position::new(4.5), 4), Position::new(3.5));
+        assert_eq!(transport.rewind(Position::new(0.5), 4), Position::MIN);
+    }
+
+    #[test]
+    fn position_at_frame_wraps_around_loop_range() {
+        let mut transport = Transport::new(SampleRate::new(4.0), 8, 60.0);
+        transport.set_loop_range(Position::new(4.0)..Position::new(8.0));
+        assert_eq!(transport.position_at_frame(6), Position::new(1.5));
+        assert_eq!(transport.position_at_frame(36), Position::new(5.0));
+        transport.set_looping(false);
+        assert_eq!(transport.position_at_frame(36), Position::new(9.0));
+    }
+
+    #[test]
+    fn for_each_in_buffer_handles_loop_range() {
+        let mut transport = Transport::new(SampleRate::new(4.0), 8, 60.0);
+        transport.set_loop_range(Position::new(4.0)..Position::new(5.0));
+        transport.position = Position::new(4.5);
+        transport.populate_transport(4);
+        let items = [
+            Position::new(0.0),
+            Position::new(4.0),
+            Position::new(4.75),
+            Position::new(6.0),
+        ];
+        let mut found = Vec::new();
+        transport.for_each_in_buffer(&items, |p| *p, |frame, p| found.push((frame, *p)));
+        assert_eq!(
+            found,
+            vec![
+                (1, Position::new(4.75)),
+                (1, Position::new(6.0)),
+                (2, Position::new(4.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn stopped_transport_does_not_advance_or_tick() {
+        let mut buffers = Buffers::new(64);
+        let mut transport = Transport::new(SampleRate::new(44100.0), 64, 120.0);
+        transport.metronome_volume = 1.0;
+        transport.set_playing(false);
+        transport.process(&mut buffers.left, &mut buffers.right);
+        assert!(transport.iter_transport().all(|r| r.is_empty()));
+        assert_eq!(transport.position(), Position::MIN);
+        assert!(buffers.is_zero());
+    }
 }
@@ -0,0 +1,96 @@
+use arrayvec::ArrayVec;
+use bmidi::{Channel, MidiMessage, Note};
+
+/// Transposes midi notes by a number of semitones. Notes that are held while the transpose changes
+/// are released at the pitch they were started at so that they do not get stuck.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Transpose {
+    /// The number of semitones to transpose by.
+    pub semitones: i8,
+    /// The notes that are held down as `(channel, played note, transposed note)`.
+    pub held: ArrayVec<(Channel, Note, Note), { Transpose::HELD_CAPACITY }>,
+}
+
+impl Transpose {
+    /// The maximum number of semitones to transpose by in either direction.
+    pub const MAX_SEMITONES: i8 = 48;
+
+    /// The maximum number of held notes that are tracked. Notes that are held past this keep
+    /// their pitch only while the transpose does not change.
+    pub const HELD_CAPACITY: usize = 32;
+
+    /// Transpose `msg`. Returns `None` if the note is transposed out of the midi range.
+    pub fn apply(&mut self, msg: MidiMessage) -> Option<MidiMessage> {
+        match msg {
+            MidiMessage::NoteOn(ch, note, v) => {
+                let transposed = note.step(self.semitones).ok()?;
+                self.release(ch, note);
+                let _ = self.held.try_push((ch, note, transposed));
+                Some(MidiMessage::NoteOn(ch, transposed, v))
+            }
+            MidiMessage::NoteOff(ch, note, v) => {
+                let transposed = match self.release(ch, note) {
+                    Some(n) => n,
+                    None => note.step(self.semitones).ok()?,
+                };
+                Some(MidiMessage::NoteOff(ch, transposed, v))
+            }
+            MidiMessage::PolyphonicKeyPressure(ch, note, v) => {
+                let transposed = match self.held.iter().find(|(c, n, _)| *c == ch && *n == note) {
+                    Some((_, _, n)) => *n,
+                    None => note.step(self.semitones).ok()?,
+                };
+                Some(MidiMessage::PolyphonicKeyPressure(ch, transposed, v))
+            }
+            msg => Some(msg),
+        }
+    }
+
+    /// Stop tracking the held `note` and return the pitch it was transposed to.
+    fn release(&mut self, ch: Channel, note: Note) -> Option<Note> {
+        let idx = self
+            .held
+            .iter()
+            .position(|(c, n, _)| *c == ch && *n == note)?;
+        Some(self.held.swap_remove(idx).2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bmidi::{ControlFunction, U7};
+
+    use super::*;
+
+    fn note_on(note: Note) -> MidiMessage {
+        MidiMessage::NoteOn(Channel::Ch1, note, U7::MAX)
+    }
+
+    fn note_off(note: Note) -> MidiMessage {
+        MidiMessage::NoteOff(Channel::Ch1, note, U7::MIN)
+    }
+
+    #[test]
+    fn notes_are_transposed() {
+        let mut t = Transpose {
+            semitones: -12,
+            ..Transpose::default()
+        };
+        assert_eq!(t.apply(note_on(Note::C4)), Some(note_on(Note::C3)));
+        assert_eq!(t.apply(note_off(Note::C4)), Some(note_off(Note::C3)));
+        assert_eq!(t.apply(note_on(Note::CMinus1)), None);
+        let cc = MidiMessage::ControlChange(Channel::Ch1, ControlFunction::PAN, U7::MAX);
+        assert_eq!(t.apply(cc), Some(cc));
+    }
+
+    #[test]
+    fn held_notes_are_released_at_original_pitch() {
+        let mut t = Transpose::default();
+        assert_eq!(t.apply(note_on(Note::C4)), Some(note_on(Note::C4)));
+        t.semitones = 1;
+        assert_eq!(t.apply(note_on(Note::D4)), Some(note_on(Note::Eb4)));
+        assert_eq!(t.apply(note_off(Note::C4)), Some(note_off(Note::C4)));
+        assert_eq!(t.apply(note_off(Note::D4)), Some(note_off(Note::Eb4)));
+        assert!(t.held.is_empty());
+    }
+}
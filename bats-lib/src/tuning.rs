@@ -0,0 +1,455 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use arrayvec::ArrayString;
+use bmidi::Note;
+use serde::{Deserialize, Serialize};
+
+/// Maps each midi note to the frequency that instruments should play it at. The default tuning
+/// is 12 tone equal temperament with A4 at 440Hz.
+///
+/// Tunings are imported from Scala files. The `.scl` file holds the pitches of a scale and the
+/// optional `.kbm` file maps the scale onto the midi keyboard. See
+/// <https://www.huygens-fokker.org/scala/scl_format.html> for the formats.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(into = "TuningData", from = "TuningData")]
+pub struct Tuning {
+    /// The name of the tuning.
+    name: ArrayString<{ Tuning::NAME_CAPACITY }>,
+    /// The frequency of each midi note or `None` if the note is not mapped by the tuning.
+    frequencies: [Option<f32>; 128],
+}
+
+/// How a `Tuning` is saved. Only the notes that are mapped by the tuning are saved.
+#[derive(Serialize, Deserialize)]
+struct TuningData {
+    /// The name of the tuning.
+    name: String,
+    /// The frequency of each note that is mapped by the tuning.
+    notes: Vec<TunedNote>,
+}
+
+/// The frequency of a single note within `TuningData`.
+#[derive(Serialize, Deserialize)]
+struct TunedNote {
+    /// The note.
+    note: Note,
+    /// The frequency that the note is played at.
+    frequency: f32,
+}
+
+/// The pitches of a scale from a `.scl` file.
+#[derive(Clone, Debug, PartialEq)]
+struct Scale {
+    /// The pitch of each degree in cents, starting from the second degree. The first degree is
+    /// always 0 cents and the last pitch is the period of the scale, usually an octave.
+    cents: Vec<f64>,
+}
+
+/// Maps the degrees of a scale onto midi notes, from a `.kbm` file.
+#[derive(Clone, Debug, PartialEq)]
+struct KeyboardMapping {
+    /// The lowest note that is mapped.
+    first_note: i32,
+    /// The highest note that is mapped.
+    last_note: i32,
+    /// The note that plays the first entry of `mapping`.
+    middle_note: i32,
+    /// The note that is tuned to `reference_frequency`.
+    reference_note: i32,
+    /// The frequency of `reference_note`.
+    reference_frequency: f64,
+    /// The scale degree that the mapping repeats at.
+    octave_degree: i32,
+    /// The scale degree played by each key within a repeat of the mapping or `None` if the key
+    /// is not mapped. If empty, every key plays the next scale degree.
+    mapping: Vec<Option<i32>>,
+}
+
+impl Tuning {
+    /// The maximum number of bytes in the name of a tuning. Longer names are truncated.
+    pub const NAME_CAPACITY: usize = 32;
+
+    /// The name of the tuning.
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// The frequency of `note` or `None` if `note` is not mapped by the tuning. Notes that are not
+    /// mapped should not be played.
+    pub fn frequency(&self, note: Note) -> Option<f32> {
+        self.frequencies[u8::from(note) as usize]
+    }
+
+    /// Returns true if the tuning is the default 12 tone equal temperament.
+    pub fn is_equal_temperament(&self) -> bool {
+        *self == Tuning::default()
+    }
+
+    /// Load a tuning from the Scala scale at `scl` and the optional keyboard mapping at `kbm`. If
+    /// `kbm` is `None`, the scale is mapped linearly starting from middle C with A4 at 440Hz. The
+    /// tuning is named after the scale file.
+    pub fn load(scl: &Path, kbm: Option<&Path>) -> Result<Tuning> {
+        let read = |path: &Path| {
+            std::fs::read_to_string(path)
+                .map_err(|err| anyhow!("failed to read tuning file {path:?}: {err}"))
+        };
+        let name = scl
+            .file_stem()
+            .map(|s| s.to_string_lossy())
+            .unwrap_or_default();
+        let kbm = kbm.map(read).transpose()?;
+        Tuning::from_scala(&name, &read(scl)?, kbm.as_deref())
+    }
+
+    /// Create a tuning named `name` from the contents of a Scala `.scl` file and an optional
+    /// `.kbm` file.
+    pub fn from_scala(name: &str, scl: &str, kbm: Option<&str>) -> Result<Tuning> {
+        let scale = Scale::parse(scl).map_err(|err| anyhow!("invalid scale file: {err}"))?;
+        let mapping = match kbm {
+            Some(kbm) => KeyboardMapping::parse(kbm)
+                .map_err(|err| anyhow!("invalid keyboard mapping file: {err}"))?,
+            None => KeyboardMapping::default(),
+        };
+        let reference_cents = mapping
+            .cents(&scale, mapping.reference_note)
+            .ok_or_else(|| anyhow!("the reference note of the keyboard mapping is not mapped"))?;
+        let mut frequencies = [None; 128];
+        for (note, frequency) in frequencies.iter_mut().enumerate() {
+            let note = note as i32;
+            if note < mapping.first_note || note > mapping.last_note {
+                continue;
+            }
+            *frequency = mapping.cents(&scale, note).map(|cents| {
+                let octaves = (cents - reference_cents) / 1200.0;
+                (mapping.reference_frequency * octaves.exp2()) as f32
+            });
+        }
+        Ok(Tuning {
+            name: truncated_name(name),
+            frequencies,
+        })
+    }
+}
+
+impl Default for Tuning {
+    /// Create a 12 tone equal temperament tuning.
+    fn default() -> Tuning {
+        let mut frequencies = [None; 128];
+        for (note, frequency) in frequencies.iter_mut().enumerate() {
+            *frequency = Some(Note::from_u8_lossy(note as u8).to_freq_f32());
+        }
+        Tuning {
+            name: truncated_name("12-TET"),
+            frequencies,
+        }
+    }
+}
+
+impl From<Tuning> for TuningData {
+    fn from(tuning: Tuning) -> TuningData {
+        let notes = tuning
+            .frequencies
+            .iter()
+            .enumerate()
+            .filter_map(|(note, frequency)| {
+                frequency.map(|frequency| TunedNote {
+                    note: Note::from_u8_lossy(note as u8),
+                    frequency,
+                })
+            })
+            .collect();
+        TuningData {
+            name: tuning.name().to_string(),
+            notes,
+        }
+    }
+}
+
+impl From<TuningData> for Tuning {
+    fn from(data: TuningData) -> Tuning {
+        let mut frequencies = [None; 128];
+        for n in data.notes {
+            frequencies[u8::from(n.note) as usize] = Some(n.frequency);
+        }
+        Tuning {
+            name: truncated_name(&data.name),
+            frequencies,
+        }
+    }
+}
+
+impl Scale {
+    /// Parse the contents of a `.scl` file.
+    fn parse(scl: &str) -> Result<Scale> {
+        let mut lines = scl.lines().filter(|l| !l.trim_start().starts_with('!'));
+        // The first line is the description, which may be empty.
+        lines.next().ok_or_else(|| anyhow!("missing description"))?;
+        let mut values = lines.map(str::trim).filter(|l| !l.is_empty());
+        let count = values
+            .next()
+            .and_then(first_word)
+            .ok_or_else(|| anyhow!("missing number of notes"))?;
+        let count: usize = count
+            .parse()
+            .map_err(|_| anyhow!("invalid number of notes {count:?}"))?;
+        let cents = values
+            .take(count)
+            .map(|l| parse_pitch(first_word(l).unwrap_or(l)))
+            .collect::<Result<Vec<f64>>>()?;
+        if cents.len() != count {
+            return Err(anyhow!("expected {count} notes but found {}", cents.len()));
+        }
+        if cents.is_empty() {
+            return Err(anyhow!("scale has no notes"));
+        }
+        Ok(Scale { cents })
+    }
+
+    /// The pitch of `degree` in cents. Degrees past the end of the scale continue into the next
+    /// period and negative degrees continue into the previous period.
+    fn degree_cents(&self, degree: i32) -> f64 {
+        let len = self.cents.len() as i32;
+        let period = self.cents[self.cents.len() - 1];
+        let step = match degree.rem_euclid(len) {
+            0 => 0.0,
+            idx => self.cents[idx as usize - 1],
+        };
+        degree.div_euclid(len) as f64 * period + step
+    }
+}
+
+impl KeyboardMapping {
+    /// Parse the contents of a `.kbm` file.
+    fn parse(kbm: &str) -> Result<KeyboardMapping> {
+        let mut values = kbm
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('!'))
+            .map(|l| first_word(l).unwrap_or(l));
+        let mut next_int = |field: &str| -> Result<i32> {
+            let value = values.next().ok_or_else(|| anyhow!("missing {field}"))?;
+            value
+                .parse()
+                .map_err(|_| anyhow!("invalid {field} {value:?}"))
+        };
+        let map_size = next_int("map size")?;
+        let first_note = next_int("first note")?;
+        let last_note = next_int("last note")?;
+        let middle_note = next_int("middle note")?;
+        let reference_note = next_int("reference note")?;
+        let reference_frequency = values
+            .next()
+            .ok_or_else(|| anyhow!("missing reference frequency"))?;
+        let reference_frequency: f64 = reference_frequency
+            .parse()
+            .ok()
+            .filter(|f: &f64| *f > 0.0)
+            .ok_or_else(|| anyhow!("invalid reference frequency {reference_frequency:?}"))?;
+        let octave_degree = values
+            .next()
+            .ok_or_else(|| anyhow!("missing octave degree"))?;
+        let octave_degree: i32 = octave_degree
+            .parse()
+            .map_err(|_| anyhow!("invalid octave degree {octave_degree:?}"))?;
+        if map_size < 0 {
+            return Err(anyhow!("invalid map size {map_size}"));
+        }
+        // Keys that are missing from the end of the mapping are not mapped.
+        let mut mapping = vec![None; map_size as usize];
+        for (key, value) in mapping.iter_mut().zip(values) {
+            *key = match value {
+                "x" | "X" => None,
+                value => Some(
+                    value
+                        .parse()
+                        .map_err(|_| anyhow!("invalid mapping entry {value:?}"))?,
+                ),
+            };
+        }
+        Ok(KeyboardMapping {
+            first_note,
+            last_note,
+            middle_note,
+            reference_note,
+            reference_frequency,
+            octave_degree,
+            mapping,
+        })
+    }
+
+    /// The pitch of `note` in cents relative to the first degree of the scale at `middle_note` or
+    /// `None` if `note` is not mapped.
+    fn cents(&self, scale: &Scale, note: i32) -> Option<f64> {
+        let offset = note - self.middle_note;
+        if self.mapping.is_empty() {
+            return Some(scale.degree_cents(offset));
+        }
+        let len = self.mapping.len() as i32;
+        let degree = self.mapping[offset.rem_euclid(len) as usize]?;
+        let octave_degree = match self.octave_degree {
+            0 => scale.cents.len() as i32,
+            degree => degree,
+        };
+        let octave = offset.div_euclid(len) as f64 * scale.degree_cents(octave_degree);
+        Some(octave + scale.degree_cents(degree))
+    }
+}
+
+impl Default for KeyboardMapping {
+    /// Map each key to the next degree of the scale, starting from middle C with A4 at 440Hz.
+    fn default() -> KeyboardMapping {
+        KeyboardMapping {
+            first_note: 0,
+            last_note: 127,
+            middle_note: 60,
+            reference_note: 69,
+            reference_frequency: 440.0,
+            octave_degree: 0,
+            mapping: Vec::new(),
+        }
+    }
+}
+
+/// Get the first whitespace separated word of `line`. Scala files allow a comment after the value
+/// on the same line.
+fn first_word(line: &str) -> Option<&str> {
+    line.split_whitespace().next()
+}
+
+/// Parse a Scala pitch. Values with a period are in cents and all other values are ratios like
+/// `3/2` or `2`.
+fn parse_pitch(pitch: &str) -> Result<f64> {
+    let invalid = || anyhow!("invalid pitch {pitch:?}");
+    if pitch.contains('.') {
+        return pitch.parse().map_err(|_| invalid());
+    }
+    let (numerator, denominator) = pitch.split_once('/').unwrap_or((pitch, "1"));
+    let numerator: u64 = numerator.parse().map_err(|_| invalid())?;
+    let denominator: u64 = denominator.parse().map_err(|_| invalid())?;
+    if numerator == 0 || denominator == 0 {
+        return Err(invalid());
+    }
+    Ok(1200.0 * (numerator as f64 / denominator as f64).log2())
+}
+
+/// Convert `name` into a tuning name, truncating it if it is too long.
+fn truncated_name(name: &str) -> ArrayString<{ Tuning::NAME_CAPACITY }> {
+    let mut truncated = ArrayString::new();
+    for c in name.chars() {
+        if truncated.try_push(c).is_err() {
+            break;
+        }
+    }
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 12 tone equal temperament as written by Scala.
+    const EQUAL_TEMPERAMENT: &str = "! 12-edo.scl
+!
+12 tone equal temperament
+ 12
+!
+ 100.0
+ 200.
+ 300.0
+ 400.0
+ 500.0
+ 600.0
+ 700.0
+ 800.0
+ 900.0
+ 1000.0
+ 1100.0
+ 2/1
+";
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3 * b, "{a} != {b}");
+    }
+
+    #[test]
+    fn default_is_equal_temperament() {
+        let tuning = Tuning::default();
+        assert_eq!(tuning.name(), "12-TET");
+        assert!(tuning.is_equal_temperament());
+        for note in [Note::CMinus1, Note::A3, Note::C4, Note::G9] {
+            assert_eq!(tuning.frequency(note), Some(note.to_freq_f32()));
+        }
+    }
+
+    #[test]
+    fn equal_temperament_scale_matches_default() {
+        let tuning = Tuning::from_scala("12-edo", EQUAL_TEMPERAMENT, None).unwrap();
+        assert_eq!(tuning.name(), "12-edo");
+        for note in 0..128 {
+            let note = Note::from_u8_lossy(note);
+            assert_near(tuning.frequency(note).unwrap(), note.to_freq_f32());
+        }
+    }
+
+    #[test]
+    fn scale_parses_cents_and_ratios() {
+        let scl = "!comment\n\n 3\n 3/2 perfect fifth\n 701.955\n 2\n";
+        let scale = Scale::parse(scl).unwrap();
+        assert_eq!(scale.cents.len(), 3);
+        assert!((scale.cents[0] - 701.955).abs() < 1e-3, "{:?}", scale.cents);
+        assert!((scale.cents[1] - 701.955).abs() < 1e-9);
+        assert!((scale.cents[2] - 1200.0).abs() < 1e-9);
+        assert!((scale.degree_cents(-1) - (701.955 - 1200.0)).abs() < 1e-3);
+        assert!((scale.degree_cents(4) - 1901.955).abs() < 1e-3);
+
+        assert!(Scale::parse("description\n 2\n 3/2\n").is_err());
+        assert!(Scale::parse("description\n 1\n 0/1\n").is_err());
+        assert!(Scale::parse("description\n 0\n").is_err());
+    }
+
+    #[test]
+    fn keyboard_mapping_places_scale_and_skips_unmapped_keys() {
+        // A pentatonic scale where each octave of 12 keys only maps the white keys.
+        let scl = "pentatonic\n5\n200.0\n400.0\n700.0\n900.0\n2/1\n";
+        let kbm = "! white keys\n12\n0\n127\n60\n69\n432.0\n5\n\
+                   0\nx\n1\nx\n2\nx\nx\n3\nx\n4\nx\nx\n";
+        let tuning = Tuning::from_scala("pentatonic", scl, Some(kbm)).unwrap();
+        assert_eq!(tuning.frequency(Note::A4), Some(432.0));
+        assert_eq!(tuning.frequency(Note::Db4), None);
+        assert_eq!(tuning.frequency(Note::B4), None);
+        assert_near(tuning.frequency(Note::C5).unwrap(), 432.0 * 0.25f32.exp2());
+        assert_near(
+            tuning.frequency(Note::C5).unwrap(),
+            2.0 * tuning.frequency(Note::C4).unwrap(),
+        );
+        assert_near(
+            tuning.frequency(Note::G4).unwrap() / tuning.frequency(Note::C4).unwrap(),
+            (700.0f32 / 1200.0).exp2(),
+        );
+    }
+
+    #[test]
+    fn keyboard_mapping_limits_note_range() {
+        let kbm = "0\n60\n72\n60\n69\n440.0\n0\n";
+        let tuning = Tuning::from_scala("12-edo", EQUAL_TEMPERAMENT, Some(kbm)).unwrap();
+        assert_eq!(tuning.frequency(Note::B3), None);
+        assert_near(tuning.frequency(Note::C4).unwrap(), Note::C4.to_freq_f32());
+        assert_near(tuning.frequency(Note::C5).unwrap(), Note::C5.to_freq_f32());
+        assert_eq!(tuning.frequency(Note::Db5), None);
+    }
+
+    #[test]
+    fn unmapped_reference_note_is_an_error() {
+        let kbm = "2\n0\n127\n60\n61\n440.0\n0\n0\nx\n";
+        assert!(Tuning::from_scala("12-edo", EQUAL_TEMPERAMENT, Some(kbm)).is_err());
+        assert!(Tuning::from_scala("12-edo", EQUAL_TEMPERAMENT, Some("12\n0\n")).is_err());
+    }
+
+    #[test]
+    fn long_names_are_truncated() {
+        let name = "a very long name for a tuning that does not fit";
+        let tuning = Tuning::from_scala(name, EQUAL_TEMPERAMENT, None).unwrap();
+        assert_eq!(tuning.name(), &name[..Tuning::NAME_CAPACITY]);
+    }
+}
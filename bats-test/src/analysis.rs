@@ -0,0 +1,42 @@
+/// Compute the root mean square of `samples`. Returns `0.0` if `samples` is empty.
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let sum: f32 = samples.iter().map(|s| s * s).sum();
+    (sum / samples.len() as f32).sqrt()
+}
+
+/// Compute the largest absolute value in `samples`.
+pub fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0, |acc, s| acc.max(s.abs()))
+}
+
+/// Get the index of the first sample whose absolute value is above `threshold` or `None` if all
+/// samples are at or below `threshold`.
+pub fn onset(samples: &[f32], threshold: f32) -> Option<usize> {
+    samples.iter().position(|s| s.abs() > threshold)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rms_of_constant_signal_is_its_magnitude() {
+        assert_eq!(rms(&[]), 0.0);
+        assert_eq!(rms(&[0.5, -0.5, 0.5, -0.5]), 0.5);
+    }
+
+    #[test]
+    fn peak_is_largest_magnitude() {
+        assert_eq!(peak(&[]), 0.0);
+        assert_eq!(peak(&[0.1, -0.7, 0.5]), 0.7);
+    }
+
+    #[test]
+    fn onset_returns_first_sample_above_threshold() {
+        assert_eq!(onset(&[0.0, 0.01, 0.5, 0.0], 0.1), Some(2));
+        assert_eq!(onset(&[0.0, 0.01], 0.1), None);
+    }
+}
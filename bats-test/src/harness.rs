@@ -0,0 +1,325 @@
+use bats_async::{
+    command::Command, new_async_commander, notification::Notification, CommandReceiver,
+    CommandSender,
+};
+use bats_dsp::{buffers::Buffers, position::Position};
+use bats_lib::{builder::BatsBuilder, Bats};
+use bmidi::MidiMessage;
+
+/// Drives a `Bats` instance without an audio backend. Midi and commands are scheduled at absolute
+/// frames and take effect when `render` reaches them.
+pub struct Harness {
+    /// The bats instance that is driven.
+    pub bats: Bats,
+    /// Sends the scheduled commands.
+    sender: CommandSender,
+    /// Executes the scheduled commands on `bats`.
+    receiver: CommandReceiver,
+    /// The scheduled midi and commands sorted by frame.
+    script: Vec<(u64, ScriptEvent)>,
+    /// The number of frames that have been rendered.
+    frame: u64,
+    /// The notifications that have been received and not yet taken.
+    notifications: Vec<Notification>,
+}
+
+/// An event that is scheduled to occur on a frame.
+enum ScriptEvent {
+    /// Midi that is sent to the first midi input port.
+    Midi(MidiMessage),
+    /// A command that is executed at the start of the buffer that contains the frame.
+    Command(Command),
+}
+
+impl Harness {
+    /// Create a new harness for the bats instance built by `builder`.
+    pub fn new(builder: &BatsBuilder) -> Harness {
+        let (sender, receiver) = new_async_commander();
+        Harness {
+            bats: builder.build(),
+            sender,
+            receiver,
+            script: Vec::new(),
+            frame: 0,
+            notifications: Vec::new(),
+        }
+    }
+
+    /// Schedule `midi` to be sent at `frame`. Events on the same frame are sent in the order they
+    /// were scheduled.
+    pub fn midi(&mut self, frame: u64, midi: MidiMessage) -> &mut Harness {
+        self.schedule(frame, ScriptEvent::Midi(midi))
+    }
+
+    /// Schedule `cmd` to be executed at `frame`. Commands are executed at the start of the buffer
+    /// that contains `frame`, just like with a real audio backend.
+    pub fn command(&mut self, frame: u64, cmd: Command) -> &mut Harness {
+        self.schedule(frame, ScriptEvent::Command(cmd))
+    }
+
+    /// Insert `event` after all other events at or before `frame`.
+    fn schedule(&mut self, frame: u64, event: ScriptEvent) -> &mut Harness {
+        let idx = self.script.partition_point(|(f, _)| *f <= frame);
+        self.script.insert(idx, (frame, event));
+        self
+    }
+
+    /// Render the next `frames` frames in chunks of the buffer size and return the output.
+    pub fn render(&mut self, frames: usize) -> Buffers {
+        let mut output = Buffers::new(frames);
+        let mut midi = Vec::new();
+        let mut start = 0;
+        while start < frames {
+            let len = self.bats.buffer_size.min(frames - start);
+            let buffer_start = self.frame;
+            let buffer_end = buffer_start + len as u64;
+            let due = self.script.partition_point(|(f, _)| *f < buffer_end);
+            midi.clear();
+            for (frame, event) in self.script.drain(..due) {
+                match event {
+                    ScriptEvent::Midi(m) => {
+                        midi.push((frame.saturating_sub(buffer_start) as u32, m));
+                    }
+                    ScriptEvent::Command(cmd) => self.sender.send(cmd).unwrap(),
+                }
+            }
+            self.receiver.execute_all(&mut self.bats);
+            let end = start + len;
+            self.bats.process(
+                &midi,
+                &mut output.left[start..end],
+                &mut output.right[start..end],
+            );
+            self.receiver.publish_events(&mut self.bats);
+            self.notifications.extend(self.sender.notifications());
+            self.frame = buffer_end;
+            start = end;
+        }
+        output
+    }
+
+    /// Render frames until the start of the next scheduled event, or return empty buffers if
+    /// nothing is scheduled.
+    pub fn render_until_next_event(&mut self) -> Buffers {
+        match self.script.first() {
+            Some((frame, _)) => self.render(frame.saturating_sub(self.frame) as usize),
+            None => Buffers::new(0),
+        }
+    }
+
+    /// The number of frames that have been rendered.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// The number of frames that `beats` beats take at the current BPM.
+    pub fn frames_for_beats(&self, beats: f64) -> u64 {
+        let seconds = beats * 60.0 / self.bats.transport.bpm() as f64;
+        (seconds * self.bats.sample_rate.sample_rate() as f64).round() as u64
+    }
+
+    /// The current position of the transport.
+    pub fn position(&self) -> Position {
+        self.bats.transport.position()
+    }
+
+    /// Take all the notifications that have been received so far.
+    pub fn take_notifications(&mut self) -> Vec<Notification> {
+        std::mem::take(&mut self.notifications)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bats_dsp::sample_rate::SampleRate;
+    use bats_lib::{
+        builder::{AnyPlugin, PluginBuilder, TrackBuilder},
+        plugin::{empty::Empty, toof::Toof},
+        plugin_slot::PluginSlot,
+    };
+    use bmidi::{Channel, Note, U7};
+
+    use crate::analysis::{onset, peak, rms};
+
+    use super::*;
+
+    /// The threshold for a sample to be considered audible.
+    const THRESHOLD: f32 = 1e-4;
+
+    /// Assert that the onset of `samples` is within a few frames of `frame`. The attack of the
+    /// envelope takes a few frames to become audible.
+    fn assert_onset_near(samples: &[f32], frame: usize) {
+        let start = onset(samples, THRESHOLD);
+        assert!(
+            start.is_some_and(|s| (frame..frame + 4).contains(&s)),
+            "expected onset near {frame} but got {start:?}"
+        );
+    }
+
+    fn toof_harness() -> Harness {
+        Harness::new(&BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: vec![TrackBuilder {
+                plugin: PluginBuilder::Toof,
+                ..TrackBuilder::default()
+            }],
+        })
+    }
+
+    fn note_on() -> MidiMessage {
+        MidiMessage::NoteOn(Channel::Ch1, Note::A4, U7::MAX)
+    }
+
+    fn note_off() -> MidiMessage {
+        MidiMessage::NoteOff(Channel::Ch1, Note::A4, U7::MIN)
+    }
+
+    #[test]
+    fn render_without_midi_is_silent() {
+        let mut h = toof_harness();
+        let out = h.render(1000);
+        assert_eq!(out.len(), 1000);
+        assert_eq!(rms(&out.left), 0.0);
+        assert_eq!(h.frame(), 1000);
+    }
+
+    #[test]
+    fn midi_starts_at_scheduled_frame() {
+        let mut h = toof_harness();
+        h.midi(1000, note_on());
+        let out = h.render(2000);
+        assert_onset_near(&out.left, 1000);
+        assert!(rms(&out.left[1000..]) > 0.01);
+    }
+
+    #[test]
+    fn render_until_next_event_stops_before_event() {
+        let mut h = toof_harness();
+        h.midi(100, note_on());
+        assert_eq!(h.render_until_next_event().len(), 100);
+        assert_eq!(h.frame(), 100);
+        assert_eq!(h.render_until_next_event().len(), 0);
+    }
+
+    #[test]
+    fn frames_for_beats_matches_transport() {
+        let mut h = toof_harness();
+        let frames = h.frames_for_beats(1.0);
+        assert_eq!(frames, 22050);
+        h.render(frames as usize);
+        assert!((h.position().as_beats_f64() - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn recorded_note_is_replayed_on_next_loop() {
+        let mut h = toof_harness();
+        let loop_frames = h.frames_for_beats(1.0);
+        h.command(
+            0,
+            Command::SetLoopRange {
+                start: Position::MIN,
+                end: Position::new(1.0),
+            },
+        )
+        .command(0, Command::SetRecord(true))
+        .midi(1000, note_on())
+        .midi(5000, note_off())
+        .command(loop_frames, Command::SetRecord(false));
+        let first_loop = h.render(loop_frames as usize);
+        assert_onset_near(&first_loop.left, 1000);
+        assert!(h
+            .take_notifications()
+            .iter()
+            .any(|n| matches!(n, Notification::Recorded { track_id: 0, .. })));
+
+        let second_loop = h.render(loop_frames as usize);
+        assert_onset_near(&second_loop.left, 1000);
+        assert_eq!(rms(&second_loop.left[20000..]), 0.0);
+    }
+
+    #[test]
+    fn midi_is_not_recorded_when_recording_is_disabled() {
+        let mut h = toof_harness();
+        let loop_frames = h.frames_for_beats(1.0);
+        h.command(
+            0,
+            Command::SetLoopRange {
+                start: Position::MIN,
+                end: Position::new(1.0),
+            },
+        )
+        .midi(1000, note_on())
+        .midi(5000, note_off());
+        h.render(loop_frames as usize);
+        let second_loop = h.render(loop_frames as usize);
+        assert_eq!(peak(&second_loop.left), 0.0);
+    }
+
+    #[test]
+    fn renders_with_same_seed_are_identical() {
+        let render = |seed: u64| {
+            let mut h = toof_harness();
+            let noise_level = Toof::METADATA.param_by_name("noise level").unwrap().id;
+            h.command(0, Command::SetSeed(seed))
+                .command(
+                    0,
+                    Command::SetParam {
+                        track_id: 0,
+                        param_id: noise_level,
+                        value: 1.0,
+                    },
+                )
+                .midi(100, note_on());
+            h.render(2000)
+        };
+        assert_eq!(render(5), render(5));
+        assert_ne!(render(5), render(6));
+    }
+
+    #[test]
+    fn plugin_swap_fades_out_held_note() {
+        let mut h = toof_harness();
+        let slot = PluginSlot::new(AnyPlugin::Empty(Empty));
+        h.midi(0, note_on()).command(
+            4096,
+            Command::SetPlugin {
+                track_id: 0,
+                plugin: slot.clone(),
+            },
+        );
+        let before = h.render(4096);
+        assert!(rms(&before.left[2048..]) > 0.01);
+        let after = h.render(8192);
+        assert!(peak(&after.left[..64]) > 0.0);
+        assert_eq!(peak(&after.left[4096..]), 0.0);
+        assert!(h.take_notifications().iter().any(|n| matches!(
+            n,
+            Notification::Undo(Command::SetPlugin { track_id: 0, .. })
+        )));
+        assert!(matches!(slot.take(), Some(AnyPlugin::Toof(_))));
+    }
+
+    #[test]
+    fn plugin_swap_takes_effect_on_next_buffer() {
+        let mut h = Harness::new(&BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: vec![TrackBuilder::default()],
+        });
+        let toof = AnyPlugin::Toof(Toof::new(h.bats.sample_rate));
+        h.midi(100, note_on())
+            .command(
+                1000,
+                Command::SetPlugin {
+                    track_id: 0,
+                    plugin: PluginSlot::new(toof),
+                },
+            )
+            .midi(2000, note_on());
+        let out = h.render(4000);
+        assert_onset_near(&out.left, 2000);
+    }
+}
@@ -0,0 +1,4 @@
+pub use harness::Harness;
+
+pub mod analysis;
+pub mod harness;
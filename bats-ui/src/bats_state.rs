@@ -1,23 +1,79 @@
-use std::{cell::RefCell, collections::HashMap};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    ops::{Range, RangeInclusive},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use bats_async::{command::Command, notification::Notification, CommandSender};
-use bats_dsp::sample_rate::SampleRate;
+use anyhow::{anyhow, Result};
+use bats_async::{
+    command::{Command, TrackContents},
+    disk_writer::DiskWriter,
+    notification::Notification,
+    plugin_loader::{LoadedPlugin, PluginLoader},
+    sample_streamer::SampleStreamer,
+    undo::UndoManager,
+    CommandSender,
+};
+use bats_dsp::{
+    buffers::Buffers,
+    position::Position,
+    rng::Rng,
+    sample_rate::SampleRate,
+    slice::{grid_slices, onset_slices},
+};
 use bats_lib::{
-    builder::AnyPlugin,
-    plugin::{metadata::Metadata, MidiEvent},
-    track::Track,
+    automation::AutomationLane,
+    builder::{AnyEffect, AnyPlugin, BatsBuilder, EffectBuilder, PluginBuilder, TrackBuilder},
+    capture::Capture,
+    expression::{ExpressionRoute, ExpressionSource},
+    freeze::FrozenTrack,
+    lfo::ParamLfo,
+    macros::{MacroKnob, MacroMapping},
+    midi_filter::MidiFilter,
+    plugin::{
+        compressor::Compressor,
+        metadata::{Metadata, PluginCategory},
+        sampler::Sampler,
+        BatsEffect,
+    },
+    plugin_slot::PluginSlot,
+    preset::{Preset, PresetParam},
+    recorder::RecordSource,
+    routing,
+    scene::{MorphParam, Scene, SceneMorph, SceneParam},
+    scope::{Scope, ScopeReader},
+    sequence::Sequence,
+    track::{MidiDestination, Track, TrackColor},
+    transport::{MetronomeSubdivision, TransportSync},
+    transpose::Transpose,
+    tuning::Tuning,
     Bats,
 };
-use log::{error, info};
+use bmidi::{ControlFunction, Note};
+use log::{error, info, warn};
+
+use crate::handle::UiRequest;
 
 /// Contains state for dealing with
 pub struct BatsState {
     /// The sample rate.
-    sample_rate: SampleRate,
+    sample_rate: Cell<SampleRate>,
     /// The buffer size.
-    buffer_size: usize,
+    buffer_size: Cell<usize>,
     /// Used to send commands to bats.
     commands: CommandSender,
+    /// The undo and redo history of the changes made by the user.
+    history: RefCell<UndoManager>,
+    /// The number of commands and notifications that were dropped because their queue was full.
+    dropped: Cell<usize>,
+    /// Generates the values for randomized params.
+    rng: Cell<Rng>,
+    /// Builds plugins in the background.
+    plugin_loader: RefCell<PluginLoader>,
+    /// Requests from other threads, such as saving the project.
+    requests: crossbeam_channel::Receiver<UiRequest>,
     /// The inner state.
     state: RefCell<InnerState>,
 }
@@ -27,21 +83,166 @@ struct InnerState {
     armed_track: usize,
     /// True if recording is enabled.
     recording_enabled: bool,
+    /// The number of semitones that the midi for the armed track is transposed by.
+    transpose: i8,
+    /// The number of frames that recorded midi is moved earlier by.
+    record_latency: u32,
+    /// The DSP load percentage that engages overload protection or `None` if it is disabled.
+    overload_threshold: Option<f32>,
+    /// True while overload protection is bypassing the aux bus returns.
+    overloaded: bool,
     /// The current BPM.
     bpm: f32,
     /// The volume of the metronome.
     metronome_volume: f32,
+    /// The clicks that the metronome plays between beats.
+    metronome_subdivision: MetronomeSubdivision,
+    /// True if the transport is playing.
+    playing: bool,
+    /// True if the transport loops.
+    looping: bool,
+    /// How the transport is synced with the transport of the audio backend.
+    transport_sync: TransportSync,
+    /// The region that the transport loops within.
+    loop_range: Range<Position>,
     /// Details for all the tracks.
-    tracks: [TrackDetails; Bats::SUPPORTED_TRACKS],
+    tracks: Vec<TrackDetails>,
+    /// The track that receives midi from each midi input port.
+    midi_input_routes: [Option<usize>; Bats::MIDI_INPUT_PORT_COUNT],
+    /// True if each track is also sent to its own output.
+    direct_outputs: bool,
+    /// The param values of the master bus compressor or `None` if there is no compressor.
+    master_compressor: Option<HashMap<u32, f32>>,
+    /// The macro knobs.
+    macros: [MacroKnob; Bats::MACRO_COUNT],
+    /// Details for the aux buses.
+    aux_buses: [AuxBusDetails; Bats::AUX_BUS_COUNT],
+    /// The saved scenes.
+    scenes: Vec<Scene>,
+    /// Details for the scene morph.
+    morph: MorphDetails,
+    /// The params that are morphed between the scenes of `morph`.
+    morph_params: Vec<MorphParam>,
+    /// The path to write the capture to once it completes. `None` if there is no export in
+    /// progress.
+    export_path: Option<PathBuf>,
+    /// Writes the recorded audio to disk or `None` if audio is not being recorded to disk.
+    disk_writer: Option<DiskWriter>,
+    /// Reads the downsampled master output or `None` if the scope is not running.
+    scope: Option<ScopeReader>,
+    /// The most recent snapshot of the state of bats or `None` if no snapshot has been received.
+    snapshot: Option<BatsBuilder>,
+    /// The path to save the project to once the next snapshot is received and where to send the
+    /// result. `None` if there is no save in progress.
+    project_path: Option<(PathBuf, crossbeam_channel::Sender<Result<()>>)>,
 }
 
 /// Contains track details.
 #[derive(Clone, Debug, PartialEq)]
 pub struct TrackDetails {
     pub id: usize,
+    /// The user defined name of the track or an empty string if the track has not been named.
+    pub name: String,
+    /// The color used to tag the track.
+    pub color: Option<TrackColor>,
     pub plugin_metadata: &'static Metadata,
     pub volume: f32,
     pub params: HashMap<u32, f32>,
+    /// The params that are left unchanged when the params are randomized.
+    pub locked_params: HashSet<u32>,
+    /// The A/B comparison of the params or `None` if the params are not being compared.
+    pub ab_compare: Option<AbCompare>,
+    /// The filter applied to the midi input of the track.
+    pub midi_filter: MidiFilter,
+    /// The tuning that the plugin of the track plays notes in.
+    pub tuning: Tuning,
+    /// True if the sequence is full and recording has dropped events.
+    pub sequence_full: bool,
+    /// The midi sequence for the track.
+    pub sequence: Sequence,
+    /// The param automation lanes for the track.
+    pub automation: Vec<AutomationLane>,
+    /// The routes from midi expression controllers to params for the track.
+    pub expression_routes: Vec<ExpressionRoute>,
+    /// The LFOs that modulate params for the track.
+    pub lfos: Vec<ParamLfo>,
+    /// The param values of the track compressor or `None` if there is no compressor.
+    pub compressor: Option<HashMap<u32, f32>>,
+    /// The level that the track sends to each aux bus.
+    pub sends: [f32; Bats::AUX_BUS_COUNT],
+    /// The track whose output is mixed into this track or `None` if the track has no input.
+    pub input: Option<usize>,
+    /// Where the sequence and midi input of the track are sent.
+    pub midi_destination: MidiDestination,
+    /// The number of beats after which the sequence repeats or `None` if the sequence repeats
+    /// with the transport loop.
+    pub loop_length: Option<Position>,
+    /// The original plugin and sequence if the track is frozen.
+    pub frozen: Option<Box<FrozenDetails>>,
+    /// The number of slices that the sampler of the track is chopped into or `0` if the track does
+    /// not have a sliced sampler.
+    pub slice_count: usize,
+    /// The id of the plugin that is being built for the track or `None` if no plugin is being
+    /// built.
+    pub loading_plugin: Option<u64>,
+}
+
+/// Compares two sets of param values for a track. The current params of the track are one side of
+/// the comparison and the other side is stored until the sides are toggled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AbCompare {
+    /// The name of the plugin that the params are for.
+    pub plugin_name: &'static str,
+    /// The param values of the side that is not active.
+    pub stored: HashMap<u32, f32>,
+    /// True if the B side is active.
+    pub is_b: bool,
+}
+
+/// Contains aux bus details.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AuxBusDetails {
+    /// The volume of the bus return.
+    pub volume: f32,
+    /// The effects applied to the bus, in order.
+    pub effects: Vec<EffectDetails>,
+}
+
+/// Contains the details of an effect.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EffectDetails {
+    /// The type of effect.
+    pub effect: EffectBuilder,
+    /// The param values.
+    pub params: HashMap<u32, f32>,
+}
+
+/// Contains the details of the scene morph.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MorphDetails {
+    /// The index of the saved scene at each end of the morph.
+    pub scenes: [Option<usize>; 2],
+    /// The position that the morph moves towards.
+    pub target: f32,
+    /// The number of seconds it takes to morph from one scene to the other.
+    pub seconds: f32,
+}
+
+/// The original plugin and sequence of a frozen track.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrozenDetails {
+    pub plugin_metadata: &'static Metadata,
+    pub params: HashMap<u32, f32>,
+    pub sequence: Sequence,
+}
+
+/// How `BatsState::slice_sample` chops a sample into slices.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SliceMode {
+    /// Start a slice at each new sound, such as each hit of a drum loop.
+    Onsets,
+    /// Chop the sample into this many slices of equal length.
+    Grid(usize),
 }
 
 impl Default for TrackDetails {
@@ -49,12 +250,34 @@ impl Default for TrackDetails {
     fn default() -> TrackDetails {
         TrackDetails {
             id: 0,
+            name: String::new(),
+            color: None,
             plugin_metadata: &Metadata {
                 name: "default_plugin",
+                category: PluginCategory::Utility,
+                tags: &[],
                 params: &[],
+                pages: &[],
             },
             volume: 1.0,
             params: HashMap::new(),
+            locked_params: HashSet::new(),
+            ab_compare: None,
+            midi_filter: MidiFilter::default(),
+            tuning: Tuning::default(),
+            sequence_full: false,
+            sequence: Sequence::new(),
+            automation: Vec::new(),
+            expression_routes: Vec::new(),
+            lfos: Vec::new(),
+            compressor: None,
+            sends: [0.0; Bats::AUX_BUS_COUNT],
+            input: None,
+            midi_destination: MidiDestination::default(),
+            loop_length: None,
+            frozen: None,
+            slice_count: 0,
+            loading_plugin: None,
         }
     }
 }
@@ -66,59 +289,574 @@ impl TrackDetails {
         let params = param_values(&t.plugin);
         TrackDetails {
             id,
+            name: t.name.clone(),
+            color: t.color,
             plugin_metadata,
             volume: t.volume,
             params,
+            locked_params: HashSet::new(),
+            ab_compare: None,
+            midi_filter: t.midi_filter,
+            tuning: *t.tuning,
+            sequence_full: false,
+            sequence: t.sequence.clone(),
+            automation: t.automation.clone(),
+            expression_routes: t.expression_routes.clone(),
+            lfos: t.lfos.clone(),
+            compressor: t.compressor.as_deref().map(effect_param_values),
+            sends: t.sends,
+            input: t.input,
+            midi_destination: t.midi_destination,
+            loop_length: t.loop_length,
+            frozen: t.frozen.as_ref().map(|f| {
+                Box::new(FrozenDetails {
+                    plugin_metadata: f.plugin.plugin().metadata(),
+                    params: param_values(&f.plugin),
+                    sequence: f.sequence.clone(),
+                })
+            }),
+            slice_count: slice_count(&t.plugin),
+            loading_plugin: None,
         }
     }
 
-    /// Return the human readable title of the track.
+    /// Return the human readable title of the track. Named tracks show the name followed by the
+    /// plugin name.
     pub fn title(&self) -> String {
+        let plugin_name = self.plugin_metadata.name;
         format!(
-            "{track_number} - {plugin_name}",
+            "{track_number} - {name}{frozen}{loading}{full}",
             track_number = self.id + 1,
-            plugin_name = self.plugin_metadata.name
+            name = if self.name.is_empty() {
+                plugin_name.to_string()
+            } else {
+                format!("{} ({plugin_name})", self.name)
+            },
+            frozen = if self.frozen.is_some() {
+                " (frozen)"
+            } else {
+                ""
+            },
+            loading = if self.loading_plugin.is_some() {
+                " (loading)"
+            } else {
+                ""
+            },
+            full = if self.sequence_full {
+                " (sequence full)"
+            } else {
+                ""
+            },
         )
     }
 }
 
 impl BatsState {
+    /// The range of valid BPM values.
+    pub const BPM_RANGE: RangeInclusive<f32> = 10.0..=360.0;
+
+    /// The range of DSP load percentages that can engage overload protection.
+    pub const OVERLOAD_THRESHOLD_RANGE: RangeInclusive<f32> = 50.0..=100.0;
+
+    /// The amount that the overload threshold is changed by with left and right.
+    pub const OVERLOAD_THRESHOLD_STEP: f32 = 5.0;
+
+    /// The overload threshold used when overload protection is turned on.
+    pub const DEFAULT_OVERLOAD_THRESHOLD: f32 = 80.0;
+
+    /// The number of scope points for each second of audio.
+    pub const SCOPE_POINTS_PER_SECOND: usize = 200;
+
+    /// The number of seconds of audio that are kept by the scope.
+    pub const SCOPE_SECONDS: usize = 8;
+
+    /// The maximum number of changes that can be undone.
+    pub const UNDO_CAPACITY: usize = 100;
+
     /// Create a new `BatsState`.
     pub fn new(bats: &Bats, commands: CommandSender) -> BatsState {
         BatsState {
             commands,
-            sample_rate: bats.sample_rate,
-            buffer_size: bats.buffer_size,
+            history: UndoManager::new(BatsState::UNDO_CAPACITY).into(),
+            dropped: Cell::new(0),
+            sample_rate: bats.sample_rate.into(),
+            buffer_size: bats.buffer_size.into(),
+            rng: Rng::new(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_nanos() as u64)
+                    .unwrap_or(Rng::DEFAULT_SEED),
+            )
+            .into(),
+            plugin_loader: PluginLoader::new().into(),
+            requests: crossbeam_channel::never(),
             state: InnerState::new(bats).into(),
         }
     }
 
-    /// Handle all notifications.
+    /// Handle the requests from `requests` along with the notifications.
+    pub fn with_requests(self, requests: crossbeam_channel::Receiver<UiRequest>) -> BatsState {
+        BatsState { requests, ..self }
+    }
+
+    /// Handle all requests and notifications.
     pub fn handle_notifications(&self) {
+        for request in self.requests.try_iter() {
+            match request {
+                UiRequest::SaveProject { path, done } => self.save_project(path, done),
+            }
+        }
+        let loaded: Vec<_> = self.plugin_loader.borrow().ready().collect();
+        for p in loaded {
+            let is_latest = self
+                .state
+                .borrow()
+                .tracks
+                .get(p.track_id)
+                .is_some_and(|t| t.loading_plugin == Some(p.id));
+            if !is_latest {
+                info!(
+                    "Discarding {plugin_name} for track {track_id} since it was replaced.",
+                    plugin_name = p.plugin.plugin().metadata().name,
+                    track_id = p.track_id
+                );
+                continue;
+            }
+            if p.sample_rate != self.sample_rate.get() {
+                self.rebuild_loaded_plugin(p);
+                continue;
+            }
+            if let Some(cmd) = self.set_plugin_command(p.track_id, p.plugin) {
+                self.send_undoable(cmd);
+            }
+        }
         for notification in self.commands.notifications() {
             match notification {
-                Notification::Undo(_) => {
-                    // TODO: Implement undo functionality.
+                Notification::Undo(undo) => {
+                    // Undo commands for changes that were not made by the user are not needed.
+                    self.history.borrow_mut().handle_undo(undo);
+                }
+                Notification::BufferSizeChanged(buffer_size) => {
+                    info!("Buffer size changed to {buffer_size}.");
+                    self.buffer_size.set(buffer_size);
+                }
+                Notification::SampleRateChanged(sample_rate) => {
+                    info!("Sample rate changed to {}.", sample_rate.sample_rate());
+                    if self.sample_rate.replace(sample_rate) != sample_rate {
+                        self.rebuild_plugins_for_sample_rate();
+                    }
+                }
+                Notification::Recorded { track_id, item } => {
+                    if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
+                        t.sequence.insert_item(item);
+                    }
+                }
+                Notification::LoopLength { track_id, length } => {
+                    if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
+                        t.loop_length = length;
+                    }
+                }
+                Notification::SequenceFull { track_id } => {
+                    if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
+                        t.sequence_full = true;
+                    }
+                }
+                Notification::Overload(active) => {
+                    if active {
+                        warn!("DSP load is too high, bypassing the aux bus returns.");
+                    } else {
+                        info!("DSP load has recovered, restoring the aux bus returns.");
+                    }
+                    self.state.borrow_mut().overloaded = active;
+                }
+                Notification::ControlSurface {
+                    armed_track,
+                    recording_enabled,
+                    playing,
+                } => {
+                    let mut state = self.state.borrow_mut();
+                    state.armed_track = armed_track;
+                    state.recording_enabled = recording_enabled;
+                    state.playing = playing;
+                }
+                Notification::Dropped(count) => {
+                    self.dropped.set(self.dropped.get() + count);
+                }
+                Notification::Captured { track_id, sequence } => {
+                    info!(
+                        "Captured {count} notes and messages into track {track_id}.",
+                        count = sequence.notes().len() + sequence.events().len()
+                    );
+                    if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
+                        for note in sequence.notes() {
+                            t.sequence.insert_note(*note);
+                        }
+                        // Note ons and note offs are not inserted since they come from notes.
+                        for event in sequence.events() {
+                            t.sequence.insert_message(*event);
+                        }
+                    }
+                }
+                Notification::Snapshot(snapshot) => {
+                    let mut state = self.state.borrow_mut();
+                    if let Some((path, done)) = state.project_path.take() {
+                        let res = snapshot.save(&path);
+                        match &res {
+                            Ok(()) => info!("Saved project to {path:?}."),
+                            Err(err) => error!("Failed to save project to {path:?}: {err}"),
+                        }
+                        let _ = done.send(res);
+                    }
+                    state.snapshot = Some(*snapshot);
+                }
+                Notification::CaptureComplete(capture) => {
+                    let path = match self.state.borrow_mut().export_path.take() {
+                        Some(p) => p,
+                        None => continue,
+                    };
+                    match capture.buffers.write_wav(&path, self.sample_rate.get()) {
+                        Ok(()) => info!("Exported loop to {path:?}."),
+                        Err(err) => error!("Failed to export loop to {path:?}: {err}"),
+                    }
+                    let state = self.state.borrow();
+                    for (track, stem) in state.tracks.iter().zip(capture.stems.iter()) {
+                        if track.plugin_metadata.name == "empty" {
+                            continue;
+                        }
+                        let stem_path = stem_path(&path, track.id);
+                        match stem.write_wav(&stem_path, self.sample_rate.get()) {
+                            Ok(()) => info!("Exported stem to {stem_path:?}."),
+                            Err(err) => error!("Failed to export stem to {stem_path:?}: {err}"),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send `cmd` to bats. If the command queue is full, the command is dropped and counted in
+    /// `dropped`.
+    fn send(&self, cmd: Command) {
+        if let Err(err) = self.commands.send(cmd) {
+            error!("{err}");
+            self.dropped.set(self.dropped.get() + 1);
+        }
+    }
+
+    /// Send `cmd` to bats and record it in the undo history. If the command queue is full, the
+    /// command is dropped and counted in `dropped`.
+    fn send_undoable(&self, cmd: Command) {
+        if let Err(err) = self.history.borrow_mut().send(&self.commands, cmd) {
+            error!("{err}");
+            self.dropped.set(self.dropped.get() + 1);
+        }
+    }
+
+    /// Undo the most recent change made by the user. Returns false if there was nothing to undo.
+    pub fn undo(&self) -> bool {
+        self.handle_notifications();
+        let cmd = match self.history.borrow().next_undo() {
+            Some(cmd) => cmd.clone(),
+            None => return false,
+        };
+        self.apply_to_state(&cmd);
+        if let Err(err) = self.history.borrow_mut().undo(&self.commands) {
+            error!("{err}");
+            self.dropped.set(self.dropped.get() + 1);
+        }
+        true
+    }
+
+    /// Redo the most recently undone change. Returns false if there was nothing to redo.
+    pub fn redo(&self) -> bool {
+        self.handle_notifications();
+        let cmd = match self.history.borrow().next_redo() {
+            Some(cmd) => cmd.clone(),
+            None => return false,
+        };
+        self.apply_to_state(&cmd);
+        if let Err(err) = self.history.borrow_mut().redo(&self.commands) {
+            error!("{err}");
+            self.dropped.set(self.dropped.get() + 1);
+        }
+        true
+    }
+
+    /// Update the state to match what bats will be once `cmd` is executed. Only the commands that
+    /// are sent with `send_undoable`, and their undo commands, are handled.
+    fn apply_to_state(&self, cmd: &Command) {
+        match cmd {
+            Command::Batch(commands) => {
+                for cmd in commands.iter() {
+                    self.apply_to_state(cmd);
+                }
+            }
+            Command::SetPlugin { track_id, plugin } => {
+                let mut state = self.state.borrow_mut();
+                if let Some(track) = state.tracks.get_mut(*track_id) {
+                    plugin.with_plugin(|p| match p {
+                        Some(p) => set_track_plugin(track, p),
+                        None => warn!("The plugin for track {track_id} is not ready."),
+                    });
+                }
+            }
+            Command::SetParam {
+                track_id,
+                param_id,
+                value,
+            } => {
+                if let Some(t) = self.state.borrow_mut().tracks.get_mut(*track_id) {
+                    t.params.insert(*param_id, *value);
                 }
             }
+            Command::LoadPreset { track_id, preset } => {
+                if let Some(t) = self.state.borrow_mut().tracks.get_mut(*track_id) {
+                    for p in preset.params.iter() {
+                        t.params.insert(p.id, p.value);
+                    }
+                }
+            }
+            Command::SetSequence { track_id, sequence } => {
+                if let Some(t) = self.state.borrow_mut().tracks.get_mut(*track_id) {
+                    t.sequence_full = false;
+                    t.sequence.clone_from(sequence);
+                }
+            }
+            Command::SetTrackVolume { track_id, volume } => {
+                if let Some(t) = self.state.borrow_mut().tracks.get_mut(*track_id) {
+                    t.volume = *volume;
+                }
+            }
+            Command::SetTrackName { track_id, name } => {
+                if let Some(t) = self.state.borrow_mut().tracks.get_mut(*track_id) {
+                    t.name.clone_from(name);
+                }
+            }
+            Command::SetTrackColor { track_id, color } => {
+                if let Some(t) = self.state.borrow_mut().tracks.get_mut(*track_id) {
+                    t.color = *color;
+                }
+            }
+            Command::SetTransportBpm(bpm) => self.state.borrow_mut().bpm = *bpm,
+            Command::SetMetronomeVolume(volume) => {
+                self.state.borrow_mut().metronome_volume = *volume
+            }
+            Command::SetMetronomeSubdivision(subdivision) => {
+                self.state.borrow_mut().metronome_subdivision = *subdivision
+            }
+            // Other commands are not sent with `send_undoable`.
+            _ => (),
         }
     }
 
+    /// Get the number of commands and notifications that were dropped because their queue was
+    /// full.
+    pub fn dropped(&self) -> usize {
+        self.handle_notifications();
+        self.dropped.get()
+    }
+
     /// Get the sample rate.
     pub fn sample_rate(&self) -> SampleRate {
         self.handle_notifications();
-        self.sample_rate
+        self.sample_rate.get()
+    }
+
+    /// Get the current position of the transport.
+    pub fn position(&self) -> Position {
+        self.handle_notifications();
+        self.commands.position()
+    }
+
+    /// Get the CPU load percentage or `None` if the audio backend does not report it.
+    pub fn cpu_load(&self) -> Option<f32> {
+        self.commands.cpu_load()
+    }
+
+    /// Get the percentage of the time available for each buffer that bats spends processing it or
+    /// `None` if no buffers have been processed.
+    pub fn dsp_load(&self) -> Option<f32> {
+        self.commands.dsp_load()
+    }
+
+    /// Get the round trip latency in seconds, from midi input to audio output, or `None` if the
+    /// audio backend does not report it.
+    pub fn latency_seconds(&self) -> Option<f32> {
+        let frames = self.commands.latency()?;
+        Some(frames as f32 * self.sample_rate().seconds_per_sample())
+    }
+
+    /// Get the number of seconds that recorded midi is moved earlier by to compensate for input
+    /// latency.
+    pub fn record_latency_seconds(&self) -> f32 {
+        self.state.borrow().record_latency as f32 * self.sample_rate().seconds_per_sample()
+    }
+
+    /// Set the number of seconds that recorded midi is moved earlier by. Negative values are
+    /// treated as 0.
+    pub fn set_record_latency_seconds(&self, seconds: f32) {
+        let frames = (seconds.max(0.0) * self.sample_rate().sample_rate()).round() as u32;
+        self.state.borrow_mut().record_latency = frames;
+        self.send(Command::SetRecordLatency(frames));
+    }
+
+    /// Get the DSP load percentage that engages overload protection or `None` if it is disabled.
+    pub fn overload_threshold(&self) -> Option<f32> {
+        self.state.borrow().overload_threshold
+    }
+
+    /// Set the DSP load percentage that engages overload protection. `None` disables it.
+    pub fn set_overload_threshold(&self, threshold: Option<f32>) {
+        self.handle_notifications();
+        self.state.borrow_mut().overload_threshold = threshold;
+        self.send(Command::SetOverloadThreshold(threshold));
+    }
+
+    /// Returns true while overload protection is bypassing the aux bus returns.
+    pub fn overloaded(&self) -> bool {
+        self.handle_notifications();
+        self.state.borrow().overloaded
+    }
+
+    /// Get the number of xruns reported by the audio backend.
+    pub fn xruns(&self) -> usize {
+        self.commands.xruns()
+    }
+
+    /// Returns true while the audio backend has lost its connection to the audio server and is
+    /// trying to reconnect.
+    pub fn is_disconnected(&self) -> bool {
+        self.commands.is_disconnected()
     }
 
     /// Get the buffer size.
     pub fn buffer_size(&self) -> usize {
         self.handle_notifications();
-        self.buffer_size
+        self.buffer_size.get()
     }
 
     /// Set the plugin for the track.
     pub fn set_plugin(&self, track_id: usize, plugin: AnyPlugin) {
         self.handle_notifications();
+        if let Some(cmd) = self.set_plugin_command(track_id, plugin) {
+            self.send_undoable(cmd);
+        }
+    }
+
+    /// Build the plugin from `builder` in the background and replace the plugin of the track once
+    /// it is ready. The track keeps playing its current plugin until then. Setting another plugin
+    /// on the track before the build finishes discards the built plugin.
+    pub fn load_plugin(&self, track_id: usize, builder: PluginBuilder) {
+        let sample_rate = self.sample_rate();
+        let id = self
+            .plugin_loader
+            .borrow_mut()
+            .load(track_id, builder, sample_rate);
+        match self.state.borrow_mut().tracks.get_mut(track_id) {
+            Some(track) => track.loading_plugin = Some(id),
+            None => error!("Could not find track with id {track_id}."),
+        }
+    }
+
+    /// Rebuild the plugins that can not change their sample rate on the audio thread, see
+    /// `PluginBuilder::rebuild_on_sample_rate_change`. The plugins are rebuilt in the background
+    /// with the current sample rate and params and then replace the running plugins.
+    fn rebuild_plugins_for_sample_rate(&self) {
+        let sample_rate = self.sample_rate.get();
+        let mut state = self.state.borrow_mut();
+        let mut plugin_loader = self.plugin_loader.borrow_mut();
+        for track in state.tracks.iter_mut() {
+            // Plugins that are still loading are rebuilt once they are ready, see
+            // `rebuild_loaded_plugin`.
+            if track.loading_plugin.is_some() {
+                continue;
+            }
+            let Some(builder) = PluginBuilder::from_name(track.plugin_metadata.name)
+                .filter(|b| b.rebuild_on_sample_rate_change())
+            else {
+                continue;
+            };
+            let params = track
+                .params
+                .iter()
+                .map(|(id, value)| PresetParam {
+                    id: *id,
+                    value: *value,
+                })
+                .collect();
+            info!(
+                "Rebuilding {plugin_name} for track {track_id} at sample rate {sample_rate}.",
+                plugin_name = builder.name(),
+                track_id = track.id,
+                sample_rate = sample_rate.sample_rate()
+            );
+            let id = plugin_loader.load_with_params(track.id, builder, params, sample_rate);
+            track.loading_plugin = Some(id);
+        }
+    }
+
+    /// Build `loaded` again since the sample rate changed while it was being built.
+    fn rebuild_loaded_plugin(&self, loaded: LoadedPlugin) {
+        let sample_rate = self.sample_rate.get();
+        let builder = PluginBuilder::from_bats(&loaded.plugin);
+        info!(
+            "Rebuilding {plugin_name} for track {track_id} at sample rate {sample_rate}.",
+            plugin_name = builder.name(),
+            track_id = loaded.track_id,
+            sample_rate = sample_rate.sample_rate()
+        );
+        let params = PresetParam::all(&loaded.plugin).collect();
+        let id = self.plugin_loader.borrow_mut().load_with_params(
+            loaded.track_id,
+            builder,
+            params,
+            sample_rate,
+        );
+        if let Some(track) = self.state.borrow_mut().tracks.get_mut(loaded.track_id) {
+            track.loading_plugin = Some(id);
+        }
+    }
+
+    /// Replace the plugin of the track with a sampler that streams the wav file at `path` from
+    /// disk.
+    pub fn stream_sample(&self, track_id: usize, path: PathBuf) {
+        match SampleStreamer::start(path, self.sample_rate()) {
+            Ok(stream) => self.set_plugin(track_id, AnyPlugin::Sampler(Sampler::streaming(stream))),
+            Err(err) => error!("Failed to stream sample: {err}"),
+        }
+    }
+
+    /// Replace the plugin of the track with a sampler that plays the wav file at `path` chopped
+    /// into slices. Each slice is played by its own note, see `Sampler::slice_note`.
+    pub fn slice_sample(&self, track_id: usize, path: PathBuf, mode: SliceMode) {
+        let sample_rate = self.sample_rate();
+        let buffers = match Buffers::from_wav(&path, sample_rate) {
+            Ok(b) => b,
+            Err(err) => {
+                error!("Failed to slice sample: {err}");
+                return;
+            }
+        };
+        let slices = match mode {
+            SliceMode::Onsets => onset_slices(&buffers, sample_rate),
+            SliceMode::Grid(count) => grid_slices(buffers.len(), count),
+        };
+        info!("Sliced {path:?} into {} slices.", slices.len());
+        self.set_plugin(
+            track_id,
+            AnyPlugin::Sampler(Sampler::sliced(buffers, slices)),
+        );
+    }
+
+    /// Play `note` on the track without recording it. Used to preview the slices of a sliced
+    /// sampler.
+    pub fn preview_note(&self, track_id: usize, note: Note) {
+        self.send(Command::PreviewNote { track_id, note });
+    }
+
+    /// Update the plugin for the track and return the command that sets it in bats. Returns
+    /// `None` if the track does not exist.
+    fn set_plugin_command(&self, track_id: usize, plugin: AnyPlugin) -> Option<Command> {
         info!(
             "Setting track {track_id} plugin to {plugin_name}.",
             plugin_name = plugin.plugin().metadata().name
@@ -126,13 +864,70 @@ impl BatsState {
         match self.state.borrow_mut().tracks.get_mut(track_id) {
             None => {
                 error!("Could not find track with id {track_id}.");
+                None
             }
             Some(track) => {
-                track.plugin_metadata = plugin.plugin().metadata();
-                track.params = param_values(&plugin);
-                self.commands.send(Command::SetPlugin { track_id, plugin });
+                set_track_plugin(track, &plugin);
+                track.loading_plugin = None;
+                Some(Command::SetPlugin {
+                    track_id,
+                    plugin: PluginSlot::new(plugin),
+                })
+            }
+        }
+    }
+
+    /// Copy the sequence and/or the plugin params from track `from` onto track `to`. If the
+    /// plugin is copied and `to` has a different type of plugin, then the plugin of `to` is
+    /// replaced first. The plugin and the copy are applied together with `Command::Batch`.
+    pub fn copy_track(&self, from: usize, to: usize, what: TrackContents) {
+        self.handle_notifications();
+        let source = match self.track_by_id(from) {
+            Some(t) => t,
+            None => {
+                error!("Could not find track with id {from}.");
+                return;
             }
+        };
+        if self.track_by_id(to).is_none() {
+            error!("Could not find track with id {to}.");
+            return;
+        }
+        let mut commands = Vec::with_capacity(2);
+        if what.has_plugin() {
+            let plugin_name = source.plugin_metadata.name;
+            if self.track_by_id(to).unwrap().plugin_metadata.name != plugin_name {
+                match PluginBuilder::from_name(plugin_name) {
+                    Some(b) => {
+                        commands.extend(self.set_plugin_command(to, b.build(self.sample_rate())))
+                    }
+                    None => error!("Could not find plugin {plugin_name}."),
+                }
+            }
+        }
+        let mut state = self.state.borrow_mut();
+        let target = &mut state.tracks[to];
+        if what.has_plugin() && target.plugin_metadata.name == source.plugin_metadata.name {
+            target.params = source.params.clone();
         }
+        let sequence = if what.has_sequence() {
+            target.sequence_full = false;
+            target.sequence = source.sequence.clone();
+            Some(Box::new(Sequence::with_capacity(
+                Track::SEQUENCE_CAPACITY.max(source.sequence.len()),
+            )))
+        } else {
+            None
+        };
+        commands.push(Command::CopyTrack {
+            from,
+            to,
+            what,
+            sequence,
+            undo: Box::new(Vec::with_capacity(source.plugin_metadata.params.len() + 1)),
+        });
+        drop(state);
+        self.send_undoable(Command::Batch(Box::new(commands)));
     }
 
     /// Return the currently armed track.
@@ -149,7 +944,17 @@ impl BatsState {
             return;
         }
         state.armed_track = armed;
-        self.commands.send(Command::SetArmedTrack(armed));
+        self.send(Command::SetArmedTrack(armed));
+    }
+
+    /// Arm the track with `track_id`, or disarm all tracks if it is already armed.
+    pub fn toggle_armed(&self, track_id: usize) {
+        let armed = if self.armed() == track_id {
+            Bats::NO_ARMED_TRACK
+        } else {
+            track_id
+        };
+        self.set_armed(armed);
     }
 
     /// True if recording is enabled.
@@ -164,6 +969,17 @@ impl BatsState {
         self.set_recording(enabled);
     }
 
+    /// Add the recently played midi of the armed track to its sequence, even if recording was not
+    /// enabled. The notes are added once the capture has been processed.
+    pub fn capture_midi_history(&self) {
+        self.handle_notifications();
+        let track_id = self.state.borrow().armed_track;
+        self.send(Command::CaptureMidiHistory {
+            track_id,
+            sequence: Box::new(Sequence::with_capacity(Track::SEQUENCE_CAPACITY)),
+        });
+    }
+
     /// Set if recording is enabled.
     pub fn set_recording(&self, enabled: bool) {
         self.handle_notifications();
@@ -172,7 +988,7 @@ impl BatsState {
             return;
         }
         state.recording_enabled = enabled;
-        self.commands.send(Command::SetRecord(enabled));
+        self.send(Command::SetRecord(enabled));
     }
 
     /// Set the track volume.
@@ -180,19 +996,134 @@ impl BatsState {
         self.handle_notifications();
         if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
             t.volume = f(t).clamp(0.00796, 4.0);
-            self.commands.send(Command::SetTrackVolume {
+            self.send_undoable(Command::SetTrackVolume {
                 track_id,
                 volume: t.volume,
             });
         }
     }
 
+    /// Set the name of the track. Leading and trailing whitespace is removed and an empty name
+    /// clears the name.
+    pub fn set_track_name(&self, track_id: usize, name: &str) {
+        self.handle_notifications();
+        if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
+            t.name = name.trim().to_string();
+            self.send_undoable(Command::SetTrackName {
+                track_id,
+                name: Box::new(t.name.clone()),
+            });
+        }
+    }
+
+    /// Set the color of the track or clear it if `color` is `None`.
+    pub fn set_track_color(&self, track_id: usize, color: Option<TrackColor>) {
+        self.handle_notifications();
+        if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
+            t.color = color;
+            self.send_undoable(Command::SetTrackColor { track_id, color });
+        }
+    }
+
+    /// Move the midi destination of the track `offset` places forward through
+    /// `MidiDestination::ALL`, wrapping around at the end.
+    pub fn cycle_midi_destination(&self, track_id: usize, offset: usize) {
+        self.handle_notifications();
+        if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
+            let all = MidiDestination::ALL;
+            let idx = all
+                .iter()
+                .position(|d| *d == t.midi_destination)
+                .unwrap_or(0);
+            t.midi_destination = all[(idx + offset) % all.len()];
+            self.send(Command::SetMidiDestination {
+                track_id,
+                destination: t.midi_destination,
+            });
+        }
+    }
+
+    /// Set the number of beats after which the sequence of the track repeats. `None` repeats the
+    /// sequence with the transport loop.
+    pub fn set_loop_length(&self, track_id: usize, length: Option<Position>) {
+        self.handle_notifications();
+        if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
+            t.loop_length = length;
+            self.send(Command::SetLoopLength { track_id, length });
+        }
+    }
+
+    /// Lengthen the loop of the track by `bars` bars, or shorten it if `bars` is negative. A loop
+    /// that is shortened below one bar repeats with the transport loop.
+    pub fn modify_loop_length_bars(&self, track_id: usize, bars: i32) {
+        let length = match self.track_by_id(track_id) {
+            Some(t) => t.loop_length,
+            None => return,
+        };
+        let current = length.map_or(0, |l| l.bar() as i32);
+        let length = match current + bars {
+            b if b <= 0 => None,
+            b => Some(Position::from_beats_bars(b as u32, 0.0)),
+        };
+        self.set_loop_length(track_id, length);
+    }
+
+    /// Modify the filter that is applied to the midi input of the track.
+    pub fn modify_midi_filter(&self, track_id: usize, f: impl Fn(MidiFilter) -> MidiFilter) {
+        self.handle_notifications();
+        if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
+            t.midi_filter = f(t.midi_filter);
+            self.send(Command::SetMidiFilter {
+                track_id,
+                filter: t.midi_filter,
+            });
+        }
+    }
+
+    /// Set the tuning that the plugin of the track plays notes in.
+    pub fn set_tuning(&self, track_id: usize, tuning: Tuning) {
+        self.handle_notifications();
+        if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
+            t.tuning = tuning;
+            self.send(Command::SetTuning {
+                track_id,
+                tuning: Box::new(tuning),
+            });
+        }
+    }
+
+    /// Set the tuning of the track to the Scala scale at `scl` mapped to the keyboard by the
+    /// optional `.kbm` file at `kbm`.
+    pub fn load_tuning(&self, track_id: usize, scl: PathBuf, kbm: Option<PathBuf>) {
+        match Tuning::load(&scl, kbm.as_deref()) {
+            Ok(tuning) => {
+                info!("Loaded tuning {:?} from {scl:?}.", tuning.name());
+                self.set_tuning(track_id, tuning);
+            }
+            Err(err) => error!("Failed to load tuning: {err}"),
+        }
+    }
+
+    /// Get the number of semitones that the midi for the armed track is transposed by.
+    pub fn transpose(&self) -> i8 {
+        self.state.borrow().transpose
+    }
+
+    /// Modify the number of semitones that the midi for the armed track is transposed by.
+    pub fn modify_transpose(&self, f: impl Fn(i8) -> i8) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        state.transpose =
+            f(state.transpose).clamp(-Transpose::MAX_SEMITONES, Transpose::MAX_SEMITONES);
+        self.send(Command::SetTranspose(state.transpose));
+    }
+
     /// Modify the bpm.
     pub fn modify_bpm(&self, f: impl Fn(f32) -> f32) {
         self.handle_notifications();
         let mut state = self.state.borrow_mut();
-        state.bpm = f(state.bpm).clamp(10.0, 360.0);
-        self.commands.send(Command::SetTransportBpm(state.bpm));
+        state.bpm = f(state.bpm).clamp(*Self::BPM_RANGE.start(), *Self::BPM_RANGE.end());
+        self.send_undoable(Command::SetTransportBpm(state.bpm));
     }
 
     /// The current BPM.
@@ -207,8 +1138,7 @@ impl BatsState {
         let mut state = self.state.borrow_mut();
         let v = f(state.metronome_volume).clamp(0.0, 1.0);
         state.metronome_volume = v;
-        self.commands
-            .send(Command::SetMetronomeVolume(state.metronome_volume));
+        self.send_undoable(Command::SetMetronomeVolume(state.metronome_volume));
     }
 
     /// Get the metronome volume.
@@ -217,45 +1147,450 @@ impl BatsState {
         self.state.borrow().metronome_volume
     }
 
-    /// Get all the tracks.
-    pub fn tracks_vec(&self) -> Vec<TrackDetails> {
+    /// Get the clicks that the metronome plays between beats.
+    pub fn metronome_subdivision(&self) -> MetronomeSubdivision {
         self.handle_notifications();
-        self.state.borrow().tracks.to_vec()
+        self.state.borrow().metronome_subdivision
     }
 
-    /// Get a track by its id.
-    pub fn track_by_id(&self, track_id: usize) -> Option<TrackDetails> {
+    /// Modify the clicks that the metronome plays between beats. The swing is kept within the
+    /// supported range.
+    pub fn modify_metronome_subdivision(
+        &self,
+        f: impl Fn(MetronomeSubdivision) -> MetronomeSubdivision,
+    ) {
         self.handle_notifications();
-        self.state.borrow().tracks.get(track_id).cloned()
+        let mut state = self.state.borrow_mut();
+        let subdivision = f(state.metronome_subdivision);
+        state.metronome_subdivision = MetronomeSubdivision {
+            clicks_per_beat: subdivision.clicks_per_beat.max(1),
+            swing: subdivision.swing.clamp(
+                MetronomeSubdivision::MIN_SWING,
+                MetronomeSubdivision::MAX_SWING,
+            ),
+        };
+        self.send_undoable(Command::SetMetronomeSubdivision(
+            state.metronome_subdivision,
+        ));
     }
 
-    /// Get the param value for the given `param_id` for `track_id`.
-    pub fn param(&self, track_id: usize, param_id: u32) -> f32 {
+    /// Returns true if the transport is playing.
+    pub fn playing(&self) -> bool {
         self.handle_notifications();
-        let state = self.state.borrow();
-        let track = match state.tracks.get(track_id) {
-            Some(t) => t,
-            None => {
-                error!("Attempted to get track for invalid track id {track_id}.");
-                return 0.0;
-            }
-        };
-        let get_default_value = || match track.plugin_metadata.param_by_id(param_id) {
-            None => 0.0,
-            Some(p) => p.default_value,
-        };
-        track
-            .params
-            .get(&param_id)
-            .copied()
-            .unwrap_or_else(get_default_value)
+        self.state.borrow().playing
+    }
+
+    /// Start or stop the transport.
+    pub fn set_playing(&self, playing: bool) {
+        self.handle_notifications();
+        self.state.borrow_mut().playing = playing;
+        self.send(Command::SetPlaying(playing));
+    }
+
+    /// Returns true if the transport loops.
+    pub fn looping(&self) -> bool {
+        self.handle_notifications();
+        self.state.borrow().looping
+    }
+
+    /// Set if the transport loops or runs linearly.
+    pub fn set_looping(&self, looping: bool) {
+        self.handle_notifications();
+        self.state.borrow_mut().looping = looping;
+        self.send(Command::SetLooping(looping));
+    }
+
+    /// Get how the transport is synced with the transport of the audio backend.
+    pub fn transport_sync(&self) -> TransportSync {
+        self.state.borrow().transport_sync
+    }
+
+    /// Set how the transport is synced with the transport of the audio backend.
+    pub fn set_transport_sync(&self, sync: TransportSync) {
+        self.handle_notifications();
+        self.state.borrow_mut().transport_sync = sync;
+        self.send(Command::SetTransportSync(sync));
+    }
+
+    /// Get the region that the transport loops within.
+    pub fn loop_range(&self) -> Range<Position> {
+        self.handle_notifications();
+        self.state.borrow().loop_range.clone()
+    }
+
+    /// Modify the region that the transport loops within. Empty regions are ignored.
+    pub fn modify_loop_range(&self, f: impl Fn(Range<Position>) -> Range<Position>) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        let loop_range = f(state.loop_range.clone());
+        if loop_range.is_empty() {
+            return;
+        }
+        state.loop_range = loop_range.clone();
+        self.send(Command::SetLoopRange {
+            start: loop_range.start,
+            end: loop_range.end,
+        });
+    }
+
+    /// Get all the tracks.
+    pub fn tracks_vec(&self) -> Vec<TrackDetails> {
+        self.handle_notifications();
+        self.state.borrow().tracks.to_vec()
+    }
+
+    /// Get a track by its id.
+    pub fn track_by_id(&self, track_id: usize) -> Option<TrackDetails> {
+        self.handle_notifications();
+        self.state.borrow().tracks.get(track_id).cloned()
+    }
+
+    /// Get the track that receives midi from the midi input `port`. `None` means that the midi
+    /// goes to the armed track.
+    pub fn midi_input_route(&self, port: usize) -> Option<usize> {
+        self.handle_notifications();
+        self.state
+            .borrow()
+            .midi_input_routes
+            .get(port)
+            .copied()
+            .flatten()
+    }
+
+    /// Set the track that receives midi from the midi input `port`.
+    pub fn set_midi_input_route(&self, port: usize, track_id: Option<usize>) {
+        self.handle_notifications();
+        match self.state.borrow_mut().midi_input_routes.get_mut(port) {
+            Some(route) => *route = track_id,
+            None => {
+                error!("Could not find midi input port {port}.");
+                return;
+            }
+        }
+        self.send(Command::SetMidiInputRoute { port, track_id });
+    }
+
+    /// Returns true if the output of the track at `input` can be mixed into the track at
+    /// `track_id` without creating a cycle.
+    pub fn can_set_track_input(&self, track_id: usize, input: usize) -> bool {
+        self.handle_notifications();
+        let state = self.state.borrow();
+        routing::check_input(
+            state.tracks.len(),
+            |id| state.tracks[id].input,
+            track_id,
+            input,
+        )
+        .is_ok()
+    }
+
+    /// Mix the output of the track at `input` into the track at `track_id`, or remove the input if
+    /// `input` is `None`. Routes that would create a cycle are ignored.
+    pub fn set_track_input(&self, track_id: usize, input: Option<usize>) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        if let Some(input) = input {
+            let tracks = &state.tracks;
+            if let Err(err) =
+                routing::check_input(tracks.len(), |id| tracks[id].input, track_id, input)
+            {
+                error!("Could not set the input of track {track_id}: {err}.");
+                return;
+            }
+        }
+        match state.tracks.get_mut(track_id) {
+            Some(t) => t.input = input,
+            None => {
+                error!("Could not find track with id {track_id}.");
+                return;
+            }
+        }
+        drop(state);
+        self.send(Command::SetTrackInput { track_id, input });
+    }
+
+    /// Request a snapshot of the state of bats from the audio thread. The snapshot is available
+    /// from `snapshot` once it has been received.
+    pub fn request_snapshot(&self) {
+        self.handle_notifications();
+        // Preallocate the tracks, names, params, sequences, automation, and LFOs so the audio
+        // thread does not have to.
+        let tracks = self
+            .state
+            .borrow()
+            .tracks
+            .iter()
+            .map(|t| TrackBuilder {
+                name: String::with_capacity(t.name.len()),
+                params: Vec::with_capacity(t.plugin_metadata.params.len()),
+                sequence: Sequence::with_capacity(Track::SEQUENCE_CAPACITY),
+                automation: t.automation.clone(),
+                lfos: Vec::with_capacity(t.lfos.len()),
+                ..TrackBuilder::default()
+            })
+            .collect();
+        let builder = BatsBuilder {
+            sample_rate: self.sample_rate.get(),
+            buffer_size: self.buffer_size.get(),
+            bpm: self.bpm(),
+            tracks,
+        };
+        self.send(Command::RequestSnapshot(Box::new(builder)));
+    }
+
+    /// Save the bpm and the tracks to a project file at `path`. Each track is saved with its
+    /// plugin, params, sequence, midi filter, tuning, automation, LFOs, and loop length. The
+    /// project is written once the audio thread reports a snapshot and the result is sent to
+    /// `done`.
+    pub fn save_project(&self, path: PathBuf, done: crossbeam_channel::Sender<Result<()>>) {
+        let mut state = self.state.borrow_mut();
+        if state.project_path.is_some() {
+            let _ = done.send(Err(anyhow!(
+                "a save is already in progress, will not save to {path:?}"
+            )));
+            return;
+        }
+        state.project_path = Some((path, done));
+        drop(state);
+        self.request_snapshot();
+    }
+
+    /// Get the most recent snapshot of the state of bats. This is the state reported by the audio
+    /// thread as opposed to the state mirrored by `BatsState`.
+    pub fn snapshot(&self) -> Option<BatsBuilder> {
+        self.handle_notifications();
+        self.state.borrow().snapshot.clone()
+    }
+
+    /// Export a single loop, starting from the beginning of the loop region, to a wav file at
+    /// `path`. The audio backend may render faster than realtime while exporting.
+    ///
+    /// If `stems` is true, then the output of each track is also written next to `path`, with the
+    /// track number appended to the file name. Tracks without a plugin are skipped.
+    pub fn export_loop(&self, path: PathBuf, stems: bool) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        if state.export_path.is_some() {
+            error!("An export is already in progress, will not export to {path:?}.");
+            return;
+        }
+        // Count frames the same way the transport advances so that the export ends exactly where
+        // the loop wraps around.
+        let loop_length = state.loop_range.end - state.loop_range.start;
+        let delta = Position::delta_from_bpm(self.sample_rate.get(), state.bpm);
+        let frames = loop_length.to_bits().div_ceil(delta.to_bits().max(1)) as usize;
+        info!("Exporting {frames} frames to {path:?}.");
+        let capture = if stems {
+            Capture::with_stems(frames, state.tracks.len())
+        } else {
+            Capture::new(frames)
+        };
+        state.export_path = Some(path);
+        self.send(Command::SetCapture(Some(Box::new(capture))));
+    }
+
+    /// Start recording `source` to a wav file at `path` while playing.
+    pub fn start_disk_recording(&self, path: PathBuf, source: RecordSource) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        if state.disk_writer.is_some() {
+            error!("Already recording to disk, will not record to {path:?}.");
+            return;
+        }
+        match DiskWriter::start(path, self.sample_rate.get(), source) {
+            Ok((disk_writer, recorder)) => {
+                state.disk_writer = Some(disk_writer);
+                self.send(Command::SetRecorder(Some(Box::new(recorder))));
+            }
+            Err(err) => error!("Failed to start recording to disk: {err}"),
+        }
+    }
+
+    /// Stop recording to disk and finalize the wav file.
+    pub fn stop_disk_recording(&self) {
+        self.handle_notifications();
+        let disk_writer = match self.state.borrow_mut().disk_writer.take() {
+            Some(w) => w,
+            None => return,
+        };
+        self.send(Command::SetRecorder(None));
+        let path = disk_writer.path().to_path_buf();
+        if let Err(err) = disk_writer.stop() {
+            error!("Failed to finish recording to {path:?}: {err}");
+        }
+    }
+
+    /// Start streaming the downsampled master output for `scope_points`. Does nothing if the
+    /// scope is already running.
+    pub fn start_scope(&self) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        if state.scope.is_some() {
+            return;
+        }
+        let frames_per_point =
+            self.sample_rate.get().sample_rate() as usize / BatsState::SCOPE_POINTS_PER_SECOND;
+        let (scope, reader) = Scope::new(
+            frames_per_point,
+            BatsState::SCOPE_POINTS_PER_SECOND * BatsState::SCOPE_SECONDS,
+        );
+        state.scope = Some(reader);
+        self.send(Command::SetScope(Some(Box::new(scope))));
+    }
+
+    /// Stop streaming the master output to the scope.
+    pub fn stop_scope(&self) {
+        self.handle_notifications();
+        if self.state.borrow_mut().scope.take().is_some() {
+            self.send(Command::SetScope(None));
+        }
+    }
+
+    /// Get the `(lowest, highest)` sample of the master output for each point over the last
+    /// `seconds`, oldest first. Empty if the scope is not running.
+    pub fn scope_points(&self, seconds: f32) -> Vec<(f32, f32)> {
+        let mut points = Vec::new();
+        if let Some(reader) = self.state.borrow().scope.as_ref() {
+            let count = (seconds * BatsState::SCOPE_POINTS_PER_SECOND as f32) as usize;
+            reader.read_latest(&mut points, count);
+        }
+        points
+    }
+
+    /// Get the source that is being recorded to disk or `None` if audio is not being recorded to
+    /// disk.
+    pub fn disk_recording(&self) -> Option<RecordSource> {
+        self.handle_notifications();
+        self.state.borrow().disk_writer.as_ref().map(|w| w.source())
+    }
+
+    /// Returns true if an export is in progress.
+    pub fn is_exporting(&self) -> bool {
+        self.handle_notifications();
+        self.state.borrow().export_path.is_some()
+    }
+
+    /// Returns true if each track is also sent to its own output.
+    pub fn direct_outputs(&self) -> bool {
+        self.handle_notifications();
+        self.state.borrow().direct_outputs
+    }
+
+    /// Enable or disable sending each track to its own output.
+    pub fn set_direct_outputs(&self, enabled: bool) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        if state.direct_outputs == enabled {
+            return;
+        }
+        state.direct_outputs = enabled;
+        let outputs = if enabled {
+            vec![Buffers::new(self.buffer_size.get()); state.tracks.len()]
+        } else {
+            Vec::new()
+        };
+        self.send(Command::SetDirectOutputs(Box::new(outputs)));
+    }
+
+    /// Get the compressor param values for the track or for the master bus if `track_id` is `None`.
+    /// Returns `None` if there is no compressor.
+    pub fn compressor(&self, track_id: Option<usize>) -> Option<HashMap<u32, f32>> {
+        self.handle_notifications();
+        self.state.borrow_mut().compressor_mut(track_id)?.clone()
+    }
+
+    /// Add or remove the compressor for the track or for the master bus if `track_id` is `None`.
+    pub fn set_compressor_enabled(&self, track_id: Option<usize>, enabled: bool) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        let slot = match state.compressor_mut(track_id) {
+            Some(s) => s,
+            None => {
+                error!("Could not find track {track_id:?} to set compressor.");
+                return;
+            }
+        };
+        if slot.is_some() == enabled {
+            return;
+        }
+        let compressor = enabled.then(|| Compressor::new(self.sample_rate.get()));
+        *slot = compressor.as_deref().map(effect_param_values);
+        self.send(Command::SetCompressor {
+            track_id,
+            compressor,
+        });
+    }
+
+    /// Modify the compressor param value by applying `f`. The value is clamped to the param's min
+    /// and max values.
+    pub fn modify_compressor_param(
+        &self,
+        track_id: Option<usize>,
+        param_id: u32,
+        f: impl Fn(f32) -> f32,
+    ) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        let params = match state.compressor_mut(track_id).and_then(|c| c.as_mut()) {
+            Some(p) => p,
+            None => {
+                error!("Track {track_id:?} has no compressor to modify param {param_id}.");
+                return;
+            }
+        };
+        let param = match Compressor::new(self.sample_rate.get())
+            .metadata()
+            .param_by_id(param_id)
+        {
+            Some(p) => *p,
+            None => {
+                error!("Could not find compressor param with id {param_id}.");
+                return;
+            }
+        };
+        let current_value = params
+            .get(&param_id)
+            .copied()
+            .unwrap_or(param.default_value);
+        let value = f(current_value).clamp(param.min_value, param.max_value);
+        params.insert(param_id, value);
+        self.send(Command::SetCompressorParam {
+            track_id,
+            param_id,
+            value,
+        });
+    }
+
+    /// Get the param value for the given `param_id` for `track_id`.
+    pub fn param(&self, track_id: usize, param_id: u32) -> f32 {
+        self.handle_notifications();
+        let state = self.state.borrow();
+        let track = match state.tracks.get(track_id) {
+            Some(t) => t,
+            None => {
+                error!("Attempted to get track for invalid track id {track_id}.");
+                return 0.0;
+            }
+        };
+        let get_default_value = || match track.plugin_metadata.param_by_id(param_id) {
+            None => 0.0,
+            Some(p) => p.default_value,
+        };
+        track
+            .params
+            .get(&param_id)
+            .copied()
+            .unwrap_or_else(get_default_value)
     }
 
     /// Modify the param value by applying `f`. This function also handles keeping the param valid,
     /// like adjusting according to the min and max values.
+    ///
+    /// If recording is enabled, the change is also recorded to the param's automation lane.
     pub fn modify_param(&self, track_id: usize, param_id: u32, f: impl Fn(f32) -> f32) {
         self.handle_notifications();
         let mut state = self.state.borrow_mut();
+        let recording_enabled = state.recording_enabled;
         let track = match state.tracks.get_mut(track_id) {
             None => {
                 error!("Could not find track {track_id} to modify param {param_id}.");
@@ -276,19 +1611,632 @@ impl BatsState {
         let current_value = *track.params.get(&param_id).unwrap();
         let value = f(current_value).clamp(param.min_value, param.max_value);
         track.params.insert(param_id, value);
-        self.commands.send(Command::SetParam {
+        self.send_undoable(Command::SetParam {
             track_id,
             param_id,
             value,
         });
+        if recording_enabled {
+            let position = self.commands.position();
+            match track.automation.iter_mut().find(|l| l.param_id == param_id) {
+                Some(lane) => lane.record(position, value),
+                None => {
+                    let mut lane = AutomationLane::new(param_id);
+                    lane.record(position, value);
+                    track.automation.push(lane);
+                }
+            }
+            self.send(Command::SetAutomation {
+                track_id,
+                automation: Box::new(track.automation.clone()),
+            });
+        }
+    }
+
+    /// Clear all param automation for the track.
+    pub fn clear_automation(&self, track_id: usize) {
+        self.handle_notifications();
+        if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
+            t.automation.clear();
+        }
+        self.send(Command::SetAutomation {
+            track_id,
+            automation: Box::default(),
+        });
+    }
+
+    /// Get the expression route for `source` on the track or `None` if `source` is not routed.
+    pub fn expression_route(
+        &self,
+        track_id: usize,
+        source: ExpressionSource,
+    ) -> Option<ExpressionRoute> {
+        self.handle_notifications();
+        let state = self.state.borrow();
+        let track = state.tracks.get(track_id)?;
+        track
+            .expression_routes
+            .iter()
+            .find(|r| r.source == source)
+            .copied()
+    }
+
+    /// Set the expression route for `source` on the track. If `route` is `None`, then `source` is
+    /// no longer routed.
+    pub fn set_expression_route(
+        &self,
+        track_id: usize,
+        source: ExpressionSource,
+        route: Option<ExpressionRoute>,
+    ) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        let track = match state.tracks.get_mut(track_id) {
+            Some(t) => t,
+            None => {
+                error!("Could not find track {track_id} to set {source:?} route.");
+                return;
+            }
+        };
+        track.expression_routes.retain(|r| r.source != source);
+        track.expression_routes.extend(route);
+        self.send(Command::SetExpressionRoutes {
+            track_id,
+            routes: Box::new(track.expression_routes.clone()),
+        });
+    }
+
+    /// Get the LFOs that modulate params for the track.
+    pub fn lfos(&self, track_id: usize) -> Vec<ParamLfo> {
+        self.handle_notifications();
+        let state = self.state.borrow();
+        state
+            .tracks
+            .get(track_id)
+            .map(|t| t.lfos.clone())
+            .unwrap_or_default()
+    }
+
+    /// Modify the LFOs that modulate params for the track.
+    pub fn modify_lfos(&self, track_id: usize, f: impl FnOnce(&mut Vec<ParamLfo>)) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        let track = match state.tracks.get_mut(track_id) {
+            Some(t) => t,
+            None => {
+                error!("Could not find track {track_id} to set LFOs.");
+                return;
+            }
+        };
+        f(&mut track.lfos);
+        self.send(Command::SetLfos {
+            track_id,
+            lfos: Box::new(track.lfos.clone()),
+        });
+    }
+
+    /// Create a preset named `name` from the current param values of the track.
+    pub fn preset(&self, track_id: usize, name: String) -> Option<Preset> {
+        self.handle_notifications();
+        let state = self.state.borrow();
+        let track = state.tracks.get(track_id)?;
+        let plugin = PluginBuilder::from_name(track.plugin_metadata.name)?;
+        Some(Preset {
+            name,
+            plugin,
+            params: track
+                .plugin_metadata
+                .params
+                .iter()
+                .map(|p| PresetParam {
+                    id: p.id,
+                    value: track.params.get(&p.id).copied().unwrap_or(p.default_value),
+                })
+                .collect(),
+        })
+    }
+
+    /// Set the params of the track to random values between their min and max. Locked params are
+    /// left unchanged. The values are loaded like a preset so they can be undone.
+    pub fn randomize_params(&self, track_id: usize) {
+        self.handle_notifications();
+        let preset = {
+            let state = self.state.borrow();
+            let track = match state.tracks.get(track_id) {
+                Some(t) => t,
+                None => {
+                    error!("Could not find track with id {track_id}.");
+                    return;
+                }
+            };
+            let plugin = match PluginBuilder::from_name(track.plugin_metadata.name) {
+                Some(p) => p,
+                None => {
+                    error!("Could not find plugin {}.", track.plugin_metadata.name);
+                    return;
+                }
+            };
+            let mut rng = self.rng.get();
+            let params = track
+                .plugin_metadata
+                .params
+                .iter()
+                .filter(|p| !track.locked_params.contains(&p.id))
+                .map(|p| PresetParam {
+                    id: p.id,
+                    value: p.random_value(&mut rng),
+                })
+                .collect();
+            self.rng.set(rng);
+            Preset {
+                name: "Random".to_string(),
+                plugin,
+                params,
+            }
+        };
+        self.load_preset(track_id, preset);
+    }
+
+    /// Returns the A/B comparison of the params of the track or `None` if the params are not being
+    /// compared.
+    pub fn ab_compare(&self, track_id: usize) -> Option<AbCompare> {
+        self.handle_notifications();
+        self.state
+            .borrow()
+            .tracks
+            .get(track_id)
+            .and_then(|t| t.ab_compare.clone())
+    }
+
+    /// Switch between the A and B params of the track. If the params are not being compared, then
+    /// the current params are stored as A and further changes are made to B. The params that
+    /// differ between the sides are applied together with `Command::Batch`.
+    pub fn toggle_ab(&self, track_id: usize) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        let Some(track) = state.tracks.get_mut(track_id) else {
+            error!("Could not find track with id {track_id}.");
+            return;
+        };
+        let plugin_name = track.plugin_metadata.name;
+        let ab = match track.ab_compare.as_mut() {
+            Some(ab) if ab.plugin_name == plugin_name => ab,
+            _ => {
+                info!("Storing the params of track {track_id} as A.");
+                track.ab_compare = Some(AbCompare {
+                    plugin_name,
+                    stored: track.params.clone(),
+                    is_b: true,
+                });
+                return;
+            }
+        };
+        std::mem::swap(&mut ab.stored, &mut track.params);
+        ab.is_b = !ab.is_b;
+        let commands: Vec<Command> = track
+            .params
+            .iter()
+            .filter(|(id, value)| ab.stored.get(id) != Some(value))
+            .map(|(&param_id, &value)| Command::SetParam {
+                track_id,
+                param_id,
+                value,
+            })
+            .collect();
+        drop(state);
+        if !commands.is_empty() {
+            self.send(Command::Batch(Box::new(commands)));
+        }
+    }
+
+    /// Stop comparing the params of the track. The params of the active side are kept.
+    pub fn stop_ab(&self, track_id: usize) {
+        if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
+            t.ab_compare = None;
+        }
+    }
+
+    /// Returns true if the param is left unchanged when the params of the track are randomized.
+    pub fn param_locked(&self, track_id: usize, param_id: u32) -> bool {
+        self.state
+            .borrow()
+            .tracks
+            .get(track_id)
+            .is_some_and(|t| t.locked_params.contains(&param_id))
+    }
+
+    /// Set if the param is left unchanged when the params of the track are randomized.
+    pub fn set_param_locked(&self, track_id: usize, param_id: u32, locked: bool) {
+        if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
+            if locked {
+                t.locked_params.insert(param_id);
+            } else {
+                t.locked_params.remove(&param_id);
+            }
+        }
+    }
+
+    /// Load the param values from `preset` onto the track.
+    pub fn load_preset(&self, track_id: usize, preset: Preset) {
+        self.handle_notifications();
+        info!(
+            "Loading preset {name:?} on track {track_id}.",
+            name = preset.name
+        );
+        match self.state.borrow_mut().tracks.get_mut(track_id) {
+            Some(track) if track.plugin_metadata.name == preset.plugin.name() => {
+                for p in preset.params.iter() {
+                    track.params.insert(p.id, p.value);
+                }
+            }
+            _ => {
+                error!(
+                    "Track {track_id} does not have plugin {plugin_name}, will not load preset.",
+                    plugin_name = preset.plugin.name()
+                );
+                return;
+            }
+        }
+        self.send_undoable(Command::LoadPreset {
+            track_id,
+            preset: Box::new(preset),
+        });
     }
 
     /// Set the sequence for the track.
-    pub fn set_sequence(&self, track_id: usize, mut sequence: Vec<MidiEvent>) {
+    pub fn set_sequence(&self, track_id: usize, mut sequence: Sequence) {
         self.handle_notifications();
+        if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
+            t.sequence_full = false;
+            t.sequence = sequence.clone();
+        }
         sequence.reserve(Track::SEQUENCE_CAPACITY);
-        self.commands
-            .send(Command::SetSequence { track_id, sequence });
+        self.send_undoable(Command::SetSequence {
+            track_id,
+            sequence: Box::new(sequence),
+        });
+    }
+
+    /// Get the macro knob or `None` if it does not exist.
+    pub fn macro_knob(&self, knob: usize) -> Option<MacroKnob> {
+        self.handle_notifications();
+        self.state.borrow().macros.get(knob).cloned()
+    }
+
+    /// Modify the value of the macro knob by applying `f`. The value is clamped to `[0.0, 1.0]`.
+    pub fn modify_macro_value(&self, knob: usize, f: impl Fn(f32) -> f32) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        let Some(k) = state.macros.get_mut(knob) else {
+            error!("Could not find macro knob {knob} to modify value.");
+            return;
+        };
+        k.value = f(k.value).clamp(0.0, 1.0);
+        self.send(Command::SetMacroValue {
+            knob,
+            value: k.value,
+        });
+    }
+
+    /// Set the midi CC that controls the macro knob.
+    pub fn set_macro_cc(&self, knob: usize, cc: Option<ControlFunction>) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        let Some(k) = state.macros.get_mut(knob) else {
+            error!("Could not find macro knob {knob} to set cc.");
+            return;
+        };
+        k.cc = cc;
+        self.send(Command::SetMacroCc { knob, cc });
+    }
+
+    /// Add `mappings` to the targets of the macro knob.
+    pub fn add_macro_mappings(&self, knob: usize, mappings: &[MacroMapping]) {
+        self.modify_macro_mappings(knob, |m| m.extend_from_slice(mappings));
+    }
+
+    /// Remove the target at index `mapping` from the macro knob.
+    pub fn remove_macro_mapping(&self, knob: usize, mapping: usize) {
+        self.modify_macro_mappings(knob, |m| {
+            if mapping < m.len() {
+                m.remove(mapping);
+            }
+        });
+    }
+
+    /// Modify the targets of the macro knob with `f`.
+    fn modify_macro_mappings(&self, knob: usize, f: impl FnOnce(&mut Vec<MacroMapping>)) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        let Some(k) = state.macros.get_mut(knob) else {
+            error!("Could not find macro knob {knob} to modify mappings.");
+            return;
+        };
+        f(&mut k.mappings);
+        self.send(Command::SetMacroMappings {
+            knob,
+            mappings: Box::new(k.mappings.clone()),
+        });
+    }
+
+    /// Get the number of saved scenes.
+    pub fn scene_count(&self) -> usize {
+        self.state.borrow().scenes.len()
+    }
+
+    /// Save the current param values of all tracks as a new scene and return its index.
+    pub fn save_scene(&self) -> usize {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        let params = state
+            .tracks
+            .iter()
+            .flat_map(|t| {
+                t.plugin_metadata.params.iter().map(|p| SceneParam {
+                    track_id: t.id,
+                    param_id: p.id,
+                    value: t.params.get(&p.id).copied().unwrap_or(p.default_value),
+                })
+            })
+            .collect();
+        state.scenes.push(Scene { params });
+        state.scenes.len() - 1
+    }
+
+    /// Get the details of the scene morph.
+    pub fn morph(&self) -> MorphDetails {
+        self.state.borrow().morph
+    }
+
+    /// Set the saved scene at one end of the morph. `side` is `0` for the start and `1` for the
+    /// end. The morph starts once both ends have a scene.
+    pub fn set_morph_scene(&self, side: usize, scene: usize) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        if scene >= state.scenes.len() {
+            error!("Could not find scene {scene} to morph.");
+            return;
+        }
+        let Some(s) = state.morph.scenes.get_mut(side) else {
+            error!("Morph side {side} does not exist.");
+            return;
+        };
+        *s = Some(scene);
+        if let [Some(a), Some(b)] = state.morph.scenes {
+            state.morph_params = MorphParam::between(&state.scenes[a], &state.scenes[b]);
+            self.send(Command::SetMorphParams(Box::new(
+                state.morph_params.clone(),
+            )));
+        }
+    }
+
+    /// Set the position that the morph moves towards. The params are updated to the values they
+    /// have once the morph reaches the target.
+    pub fn set_morph_target(&self, target: f32) {
+        self.handle_notifications();
+        let target = target.clamp(0.0, 1.0);
+        let mut state = self.state.borrow_mut();
+        state.morph.target = target;
+        let InnerState {
+            tracks,
+            morph_params,
+            ..
+        } = &mut *state;
+        for p in morph_params.iter() {
+            if let Some(t) = tracks.get_mut(p.track_id) {
+                t.params.insert(p.param_id, p.value(target));
+            }
+        }
+        self.send(Command::SetMorphTarget(target));
+    }
+
+    /// Modify the number of seconds it takes to morph from one scene to the other.
+    pub fn modify_morph_seconds(&self, f: impl Fn(f32) -> f32) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        state.morph.seconds = f(state.morph.seconds).clamp(0.0, SceneMorph::MAX_SECONDS);
+        self.send(Command::SetMorphSeconds(state.morph.seconds));
+    }
+
+    /// Modify the level that the track sends to the aux bus by applying `f`.
+    pub fn modify_aux_send(&self, track_id: usize, bus: usize, f: impl Fn(f32) -> f32) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        let Some(send) = state
+            .tracks
+            .get_mut(track_id)
+            .and_then(|t| t.sends.get_mut(bus))
+        else {
+            error!("Could not find track {track_id} or aux bus {bus} to modify send.");
+            return;
+        };
+        *send = f(*send).clamp(0.0, 4.0);
+        self.send(Command::SetAuxSend {
+            track_id,
+            bus,
+            level: *send,
+        });
+    }
+
+    /// Get the details for the aux bus or `None` if it does not exist.
+    pub fn aux_bus(&self, bus: usize) -> Option<AuxBusDetails> {
+        self.handle_notifications();
+        self.state.borrow().aux_buses.get(bus).cloned()
+    }
+
+    /// Modify the return volume of the aux bus by applying `f`.
+    pub fn modify_aux_volume(&self, bus: usize, f: impl Fn(f32) -> f32) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        let Some(details) = state.aux_buses.get_mut(bus) else {
+            error!("Could not find aux bus {bus} to modify volume.");
+            return;
+        };
+        details.volume = f(details.volume).clamp(0.0, 4.0);
+        self.send(Command::SetAuxVolume {
+            bus,
+            volume: details.volume,
+        });
+    }
+
+    /// Add `effect` to the end of the effects of the aux bus.
+    pub fn add_aux_effect(&self, bus: usize, effect: EffectBuilder) {
+        let sample_rate = self.sample_rate();
+        self.modify_aux_effects(bus, |effects| {
+            effects.push(EffectDetails {
+                effect,
+                params: effect_param_values(effect.build(sample_rate).effect()),
+            })
+        });
+    }
+
+    /// Remove the effect at index `effect` from the aux bus.
+    pub fn remove_aux_effect(&self, bus: usize, effect: usize) {
+        self.modify_aux_effects(bus, |effects| {
+            if effect < effects.len() {
+                effects.remove(effect);
+            }
+        });
+    }
+
+    /// Modify the effects of the aux bus with `f` and replace the effects in bats with newly built
+    /// effects. The new effects start without any delay tails from the old effects.
+    fn modify_aux_effects(&self, bus: usize, f: impl FnOnce(&mut Vec<EffectDetails>)) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        let Some(details) = state.aux_buses.get_mut(bus) else {
+            error!("Could not find aux bus {bus} to modify effects.");
+            return;
+        };
+        f(&mut details.effects);
+        let sample_rate = self.sample_rate.get();
+        let effects: Vec<AnyEffect> = details
+            .effects
+            .iter()
+            .map(|d| {
+                let mut effect = d.effect.build(sample_rate);
+                for (id, value) in d.params.iter() {
+                    effect.effect_mut().set_param(*id, *value);
+                }
+                effect
+            })
+            .collect();
+        self.send(Command::SetAuxEffects {
+            bus,
+            effects: Box::new(effects),
+        });
+    }
+
+    /// Modify the param of the effect at index `effect` in the aux bus by applying `f`. The value
+    /// is clamped to the param's min and max values.
+    pub fn modify_aux_effect_param(
+        &self,
+        bus: usize,
+        effect: usize,
+        param_id: u32,
+        f: impl Fn(f32) -> f32,
+    ) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        let Some(details) = state
+            .aux_buses
+            .get_mut(bus)
+            .and_then(|b| b.effects.get_mut(effect))
+        else {
+            error!("Could not find effect {effect} on aux bus {bus} to modify param {param_id}.");
+            return;
+        };
+        let Some(param) = details.effect.metadata().param_by_id(param_id) else {
+            error!(
+                "Could not find {} param with id {param_id}.",
+                details.effect.name()
+            );
+            return;
+        };
+        let current_value = details
+            .params
+            .get(&param_id)
+            .copied()
+            .unwrap_or(param.default_value);
+        let value = f(current_value).clamp(param.min_value, param.max_value);
+        details.params.insert(param_id, value);
+        self.send(Command::SetAuxEffectParam {
+            bus,
+            effect,
+            param_id,
+            value,
+        });
+    }
+
+    /// Freeze the track by rendering one loop of its plugin and sequence and playing back the
+    /// audio instead. Does nothing if the track is already frozen.
+    pub fn freeze_track(&self, track_id: usize) {
+        self.handle_notifications();
+        let track = match self.track_by_id(track_id) {
+            Some(t) if t.frozen.is_none() => t,
+            Some(_) => return,
+            None => {
+                error!("Could not find track with id {track_id}.");
+                return;
+            }
+        };
+        let Some(builder) = PluginBuilder::from_name(track.plugin_metadata.name) else {
+            error!(
+                "Plugin {plugin_name} can not be built, will not freeze track {track_id}.",
+                plugin_name = track.plugin_metadata.name
+            );
+            return;
+        };
+        info!("Freezing track {track_id}.");
+        let sample_rate = self.sample_rate();
+        let mut plugin = builder.build(sample_rate);
+        for (id, value) in track.params.iter() {
+            plugin.plugin_mut().set_param(*id, *value);
+        }
+        let frozen = FrozenTrack::render(
+            plugin,
+            &track.sequence,
+            sample_rate,
+            self.bpm(),
+            self.loop_range(),
+        );
+        if let Some(t) = self.state.borrow_mut().tracks.get_mut(track_id) {
+            t.frozen = Some(Box::new(FrozenDetails {
+                plugin_metadata: t.plugin_metadata,
+                params: std::mem::replace(&mut t.params, param_values(&frozen.plugin)),
+                sequence: std::mem::replace(&mut t.sequence, frozen.sequence.clone()),
+            }));
+            t.plugin_metadata = frozen.plugin.plugin().metadata();
+        }
+        self.send(Command::FreezeTrack {
+            track_id,
+            frozen: Box::new(frozen),
+        });
+    }
+
+    /// Restore the original plugin and sequence of a frozen track. Does nothing if the track is
+    /// not frozen.
+    pub fn unfreeze_track(&self, track_id: usize) {
+        self.handle_notifications();
+        let mut state = self.state.borrow_mut();
+        let Some(t) = state.tracks.get_mut(track_id) else {
+            error!("Could not find track with id {track_id}.");
+            return;
+        };
+        let Some(frozen) = t.frozen.take() else {
+            return;
+        };
+        info!("Unfreezing track {track_id}.");
+        let FrozenDetails {
+            plugin_metadata,
+            params,
+            sequence,
+        } = *frozen;
+        t.plugin_metadata = plugin_metadata;
+        t.params = params;
+        t.sequence = sequence;
+        drop(state);
+        self.send(Command::UnfreezeTrack { track_id });
     }
 }
 
@@ -296,13 +2244,66 @@ impl InnerState {
     /// Create a new `InnerState`.
     pub fn new(bats: &Bats) -> InnerState {
         let bpm = bats.transport.bpm();
-        let tracks = core::array::from_fn(|idx| TrackDetails::new(idx, &bats.tracks[idx]));
+        let tracks = bats
+            .tracks
+            .iter()
+            .enumerate()
+            .map(|(idx, t)| TrackDetails::new(idx, t))
+            .collect();
         InnerState {
             armed_track: bats.armed_track,
             recording_enabled: bats.recording_enabled,
+            transpose: bats.transpose.semitones,
+            record_latency: bats.record_latency,
+            overload_threshold: bats.overload.threshold,
+            overloaded: bats.overload.is_active(),
             bpm,
             metronome_volume: bats.transport.metronome_volume,
+            metronome_subdivision: bats.transport.metronome_subdivision,
+            playing: bats.playing,
+            looping: bats.transport.looping(),
+            transport_sync: bats.transport_sync,
+            loop_range: bats.transport.loop_range(),
             tracks,
+            midi_input_routes: bats.midi_input_routes,
+            direct_outputs: !bats.direct_outputs.is_empty(),
+            master_compressor: bats.master_compressor.as_deref().map(effect_param_values),
+            macros: bats.macros.clone(),
+            aux_buses: std::array::from_fn(|bus| AuxBusDetails {
+                volume: bats.aux_buses[bus].volume,
+                effects: bats.aux_buses[bus]
+                    .effects
+                    .iter()
+                    .map(|e| EffectDetails {
+                        effect: EffectBuilder::from_bats(e),
+                        params: effect_param_values(e.effect()),
+                    })
+                    .collect(),
+            }),
+            scenes: Vec::new(),
+            morph: MorphDetails {
+                scenes: [None, None],
+                target: bats.morph.target,
+                seconds: bats.morph.seconds,
+            },
+            morph_params: bats.morph.params.clone(),
+            export_path: None,
+            disk_writer: None,
+            scope: None,
+            snapshot: None,
+            project_path: None,
+        }
+    }
+
+    /// Get the compressor params for the track or for the master bus if `track_id` is `None`.
+    /// Returns `None` if the track does not exist.
+    fn compressor_mut(
+        &mut self,
+        track_id: Option<usize>,
+    ) -> Option<&mut Option<HashMap<u32, f32>>> {
+        match track_id {
+            Some(id) => self.tracks.get_mut(id).map(|t| &mut t.compressor),
+            None => Some(&mut self.master_compressor),
         }
     }
 }
@@ -319,3 +2320,231 @@ fn param_values(p: &AnyPlugin) -> HashMap<u32, f32> {
         })
         .collect()
 }
+
+/// Update the details of `track` for its plugin being replaced by `plugin`.
+fn set_track_plugin(track: &mut TrackDetails, plugin: &AnyPlugin) {
+    track.plugin_metadata = plugin.plugin().metadata();
+    track.params = param_values(plugin);
+    track.locked_params.clear();
+    track.ab_compare = None;
+    track.slice_count = slice_count(plugin);
+}
+
+/// Get the number of slices that `p` is chopped into or `0` if `p` is not a sliced sampler.
+fn slice_count(p: &AnyPlugin) -> usize {
+    match p {
+        AnyPlugin::Sampler(s) => s.slices().len(),
+        _ => 0,
+    }
+}
+
+/// Get the map from `param_id` to the parameter value for an effect.
+fn effect_param_values<E: BatsEffect + ?Sized>(e: &E) -> HashMap<u32, f32> {
+    e.metadata()
+        .params
+        .iter()
+        .map(|param| (param.id, e.param(param.id)))
+        .collect()
+}
+
+/// Get the path for the stem of the track with `track_id` when exporting to `path`. The track
+/// number is appended to the file name, for example `loop-track-1.wav` for `loop.wav`.
+fn stem_path(path: &Path, track_id: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}-track-{}.wav", track_id + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use bats_async::new_async_commander;
+    use bats_lib::{
+        plugin::{
+            metadata::{Param, ParamType},
+            BatsInstrument,
+        },
+        registry::{PluginFactory, PluginRegistry},
+    };
+    use bmidi::MidiMessage;
+
+    use super::*;
+
+    /// Wait for the plugin that is loading on `track_id` to be sent to bats.
+    fn wait_for_plugin(state: &BatsState, track_id: usize) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while state
+            .track_by_id(track_id)
+            .unwrap()
+            .loading_plugin
+            .is_some()
+            && Instant::now() < deadline
+        {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn loaded_plugin_is_sent_to_bats() {
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let (commands, receiver) = new_async_commander();
+        let state = BatsState::new(&bats, commands);
+        // The first plugin is replaced before it is ready so it should be discarded.
+        state.load_plugin(0, PluginBuilder::Empty);
+        state.load_plugin(0, PluginBuilder::Toof);
+        assert!(state.track_by_id(0).unwrap().loading_plugin.is_some());
+        wait_for_plugin(&state, 0);
+        receiver.execute_all(&mut bats);
+        // Only the `SetPlugin` for toof was executed.
+        let executed = state
+            .commands
+            .notifications()
+            .into_iter()
+            .filter(|n| matches!(n, Notification::Undo(_)))
+            .count();
+        assert_eq!(executed, 1);
+        assert!(matches!(bats.tracks[0].plugin, AnyPlugin::Toof(_)));
+        assert_eq!(state.track_by_id(0).unwrap().plugin_metadata.name, "toof");
+    }
+
+    #[test]
+    fn undo_and_redo_restore_bats_and_the_state() {
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        let (commands, receiver) = new_async_commander();
+        let state = BatsState::new(&bats, commands);
+        // Execute the commands and let the crossfade finish so that the replaced plugin is put
+        // back into its slot.
+        let process = |bats: &mut Bats| {
+            receiver.execute_all(bats);
+            while bats.tracks[0].fading_plugin.is_some() {
+                bats.process_to_buffer(64, &[]);
+            }
+            receiver.publish_events(bats);
+        };
+        state.set_plugin(0, PluginBuilder::Toof.build(bats.sample_rate));
+        state.modify_bpm(|_| 90.0);
+        process(&mut bats);
+
+        assert!(state.undo());
+        process(&mut bats);
+        assert_eq!((state.bpm(), bats.transport.bpm()), (120.0, 120.0));
+        assert!(state.undo());
+        process(&mut bats);
+        assert_eq!(bats.tracks[0].plugin, AnyPlugin::default());
+        assert_eq!(state.track_by_id(0).unwrap().plugin_metadata.name, "empty");
+        assert!(!state.undo());
+
+        assert!(state.redo());
+        process(&mut bats);
+        assert!(matches!(bats.tracks[0].plugin, AnyPlugin::Toof(_)));
+        assert_eq!(state.track_by_id(0).unwrap().plugin_metadata.name, "toof");
+    }
+
+    /// An instrument that, like VST3 plugins, keeps the sample rate it was built with.
+    #[derive(Clone, Debug, PartialEq)]
+    struct FixedSampleRate {
+        sample_rate: SampleRate,
+        value: f32,
+    }
+
+    impl BatsInstrument for FixedSampleRate {
+        fn metadata(&self) -> &'static Metadata {
+            &Metadata {
+                name: "bats state fixed sample rate",
+                category: PluginCategory::Instrument,
+                tags: &[],
+                params: &[Param {
+                    id: 0,
+                    name: "value",
+                    param_type: ParamType::Percent,
+                    default_value: 0.0,
+                    min_value: 0.0,
+                    max_value: 1.0,
+                }],
+                pages: &[],
+            }
+        }
+
+        fn handle_midi(&mut self, _: &MidiMessage) {}
+
+        fn process(&mut self) -> (f32, f32) {
+            (0.0, 0.0)
+        }
+
+        fn param(&self, _: u32) -> f32 {
+            self.value
+        }
+
+        fn set_param(&mut self, _: u32, value: f32) {
+            self.value = value;
+        }
+
+        fn batch_cleanup(&mut self) {}
+
+        fn set_sample_rate(&mut self, _: SampleRate) {}
+    }
+
+    #[test]
+    fn plugins_are_rebuilt_when_they_can_not_change_sample_rate() {
+        let plugin = PluginRegistry::global()
+            .register(PluginFactory {
+                metadata: FixedSampleRate {
+                    sample_rate: SampleRate::new(44100.0),
+                    value: 0.0,
+                }
+                .metadata(),
+                build: Box::new(|sample_rate| {
+                    Box::new(FixedSampleRate {
+                        sample_rate,
+                        value: 0.0,
+                    })
+                }),
+                rebuild_on_sample_rate_change: true,
+            })
+            .unwrap();
+        let mut bats = BatsBuilder {
+            sample_rate: SampleRate::new(44100.0),
+            buffer_size: 64,
+            bpm: 120.0,
+            tracks: BatsBuilder::default_tracks(),
+        }
+        .build();
+        bats.tracks[0].plugin = PluginBuilder::Registered(plugin).build(bats.sample_rate);
+        bats.tracks[0].plugin.plugin_mut().set_param(0, 0.25);
+        bats.tracks[1].plugin = PluginBuilder::Toof.build(bats.sample_rate);
+        let (commands, receiver) = new_async_commander();
+        let state = BatsState::new(&bats, commands);
+        receiver.set_sample_rate(&mut bats, SampleRate::new(48000.0));
+        assert!(state.track_by_id(0).unwrap().loading_plugin.is_some());
+        assert!(state.track_by_id(1).unwrap().loading_plugin.is_none());
+        wait_for_plugin(&state, 0);
+        receiver.execute_all(&mut bats);
+        let AnyPlugin::Registered(rebuilt) = &bats.tracks[0].plugin else {
+            panic!("expected a registered plugin");
+        };
+        let rebuilt = rebuilt
+            .instrument
+            .as_any()
+            .downcast_ref::<FixedSampleRate>()
+            .unwrap();
+        assert_eq!(
+            rebuilt,
+            &FixedSampleRate {
+                sample_rate: SampleRate::new(48000.0),
+                value: 0.25
+            }
+        );
+    }
+}
@@ -1,11 +1,85 @@
 use anyhow::{anyhow, Result};
 use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use log::debug;
-use std::time::{Duration, Instant};
+use serde::{Deserialize, Deserializer};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 /// Poll for events.
-#[derive(Copy, Clone, Debug, Default, PartialEq)]
-pub struct EventPoll {}
+#[derive(Clone, Debug, Default)]
+pub struct EventPoll {
+    /// The keys that map to each event.
+    pub key_bindings: KeyBindings,
+    /// If set, an `Event::Redraw` is produced whenever there has been no input for this long. Used
+    /// to keep live information, like the playhead, up to date.
+    pub redraw_interval: Option<Duration>,
+    /// Once set, polling returns an error so that the UI exits. Unlike C-c, this may be set from
+    /// another thread.
+    pub quit: Arc<AtomicBool>,
+}
+
+/// The keys that trigger each event. Keys are written as either a single character like `"k"` or
+/// the name of a special key like `"up"`, `"esc"`, or `"enter"`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    /// The keys for `Event::Up`.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub up: Vec<KeyCode>,
+    /// The keys for `Event::Down`.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub down: Vec<KeyCode>,
+    /// The keys for `Event::Left`.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub left: Vec<KeyCode>,
+    /// The keys for `Event::Right`.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub right: Vec<KeyCode>,
+    /// The keys for `Event::Back`.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub back: Vec<KeyCode>,
+    /// The keys for `Event::Enter`.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub enter: Vec<KeyCode>,
+    /// The keys for `Event::PageUp`.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub page_up: Vec<KeyCode>,
+    /// The keys for `Event::PageDown`.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub page_down: Vec<KeyCode>,
+    /// The keys that transpose the armed track down by a semitone.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub transpose_down: Vec<KeyCode>,
+    /// The keys that transpose the armed track up by a semitone.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub transpose_up: Vec<KeyCode>,
+    /// The keys that transpose the armed track down by an octave.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub octave_down: Vec<KeyCode>,
+    /// The keys that transpose the armed track up by an octave.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub octave_up: Vec<KeyCode>,
+    /// The keys that capture the recently played midi into the armed track.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub capture: Vec<KeyCode>,
+    /// The keys that arm or disarm the selected track in the tracks list.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub toggle_arm: Vec<KeyCode>,
+    /// The keys that switch between the A and B params on a params page.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub toggle_ab: Vec<KeyCode>,
+    /// The keys that undo the most recent change.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub undo: Vec<KeyCode>,
+    /// The keys that redo the most recently undone change.
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub redo: Vec<KeyCode>,
+}
 
 /// A user input event.
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
@@ -25,13 +99,124 @@ pub enum Event {
     Back,
     /// The enter key was pressed.
     Enter,
+    /// The page up key was pressed.
+    PageUp,
+    /// The page down key was pressed.
+    PageDown,
+    /// Transpose the armed track by the number of semitones. Handled on every page.
+    Transpose(i8),
+    /// Capture the recently played midi into the armed track. Handled on every page.
+    Capture,
+    /// Arm or disarm the selected track.
+    ToggleArm,
+    /// Switch between the A and B params of the track.
+    ToggleAb,
+    /// Undo the most recent change. Handled on every page.
+    Undo,
+    /// Redo the most recently undone change. Handled on every page.
+    Redo,
     /// A redraw was requested.
     Redraw,
+    /// A character key that is not bound to any other event was pressed.
+    Char(char),
+    /// The backspace key was pressed.
+    Backspace,
+}
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        KeyBindings {
+            up: vec![KeyCode::Up],
+            down: vec![KeyCode::Down],
+            left: vec![KeyCode::Left],
+            right: vec![KeyCode::Right],
+            back: vec![KeyCode::Esc],
+            enter: vec![KeyCode::Enter],
+            page_up: vec![KeyCode::PageUp],
+            page_down: vec![KeyCode::PageDown],
+            transpose_down: vec![KeyCode::F(3)],
+            transpose_up: vec![KeyCode::F(4)],
+            octave_down: vec![KeyCode::F(1)],
+            octave_up: vec![KeyCode::F(2)],
+            capture: vec![KeyCode::F(5)],
+            toggle_arm: vec![KeyCode::Tab],
+            toggle_ab: vec![KeyCode::F(6)],
+            undo: vec![KeyCode::F(7)],
+            redo: vec![KeyCode::F(8)],
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Get the event for the key or `Event::None` if the key is not bound.
+    pub fn event_for_key(&self, key: KeyCode) -> Event {
+        [
+            (&self.up, Event::Up),
+            (&self.down, Event::Down),
+            (&self.left, Event::Left),
+            (&self.right, Event::Right),
+            (&self.back, Event::Back),
+            (&self.enter, Event::Enter),
+            (&self.page_up, Event::PageUp),
+            (&self.page_down, Event::PageDown),
+            (&self.transpose_down, Event::Transpose(-1)),
+            (&self.transpose_up, Event::Transpose(1)),
+            (&self.octave_down, Event::Transpose(-12)),
+            (&self.octave_up, Event::Transpose(12)),
+            (&self.capture, Event::Capture),
+            (&self.toggle_arm, Event::ToggleArm),
+            (&self.toggle_ab, Event::ToggleAb),
+            (&self.undo, Event::Undo),
+            (&self.redo, Event::Redo),
+        ]
+        .into_iter()
+        .find(|(keys, _)| keys.contains(&key))
+        .map(|(_, event)| event)
+        .unwrap_or(Event::None)
+    }
+}
+
+/// Parse a key from a human readable name.
+pub fn parse_key(name: &str) -> Result<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(c));
+    }
+    let key = match name.to_lowercase().as_str() {
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "esc" | "escape" => KeyCode::Esc,
+        "enter" | "return" => KeyCode::Enter,
+        "backspace" => KeyCode::Backspace,
+        "tab" => KeyCode::Tab,
+        "space" => KeyCode::Char(' '),
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "delete" => KeyCode::Delete,
+        f => match f.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+            Some(n @ 1..=12) => KeyCode::F(n),
+            _ => return Err(anyhow!("Unknown key {name:?}.")),
+        },
+    };
+    Ok(key)
+}
+
+/// Deserialize a list of human readable key names.
+fn deserialize_keys<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<KeyCode>, D::Error> {
+    let names = Vec::<String>::deserialize(deserializer)?;
+    names
+        .iter()
+        .map(|name| parse_key(name).map_err(serde::de::Error::custom))
+        .collect()
 }
 
 impl EventPoll {
     /// Iterate over all events indefinitely.
-    pub fn iter(&self) -> impl Iterator<Item = Result<Event>> {
+    pub fn iter(&self) -> impl '_ + Iterator<Item = Result<Event>> {
         self.iter_with_timeout(None)
     }
 
@@ -41,19 +226,29 @@ impl EventPoll {
     fn iter_with_timeout(
         &self,
         timeout: impl Into<Option<Duration>>,
-    ) -> impl Iterator<Item = Result<Event>> {
+    ) -> impl '_ + Iterator<Item = Result<Event>> {
         let timeout = timeout.into();
         let deadline = timeout.map(|t| Instant::now() + t);
         std::iter::from_fn(move || -> Option<Result<Event>> {
+            if self.quit.load(Ordering::Relaxed) {
+                return Some(Err(anyhow!("Exit requested.")));
+            }
             let timeout = deadline
-                .map(|d| d.duration_since(Instant::now()))
+                .map(|d| d.saturating_duration_since(Instant::now()))
                 .unwrap_or(Duration::MAX);
-            let is_ready = match crossterm::event::poll(timeout) {
+            let poll_timeout = match self.redraw_interval {
+                Some(interval) => interval.min(timeout),
+                None => timeout,
+            };
+            let is_ready = match crossterm::event::poll(poll_timeout) {
                 Ok(b) => b,
                 Err(err) => return Some(Err(err.into())),
             };
             if !is_ready {
-                return None;
+                if deadline.is_some_and(|d| d <= Instant::now()) {
+                    return None;
+                }
+                return Some(Ok(Event::Redraw));
             }
             let raw_event = match crossterm::event::read() {
                 Ok(e) => e,
@@ -68,35 +263,14 @@ impl EventPoll {
                     ..
                 }) => return Some(Err(anyhow!("Exit with C-c requested."))),
                 crossterm::event::Event::Key(KeyEvent {
-                    code: KeyCode::Up,
-                    kind: KeyEventKind::Press,
-                    ..
-                }) => Event::Up,
-                crossterm::event::Event::Key(KeyEvent {
-                    code: KeyCode::Down,
-                    kind: KeyEventKind::Press,
-                    ..
-                }) => Event::Down,
-                crossterm::event::Event::Key(KeyEvent {
-                    code: KeyCode::Left,
-                    kind: KeyEventKind::Press,
-                    ..
-                }) => Event::Left,
-                crossterm::event::Event::Key(KeyEvent {
-                    code: KeyCode::Right,
-                    kind: KeyEventKind::Press,
-                    ..
-                }) => Event::Right,
-                crossterm::event::Event::Key(KeyEvent {
-                    code: KeyCode::Esc,
-                    kind: KeyEventKind::Press,
-                    ..
-                }) => Event::Back,
-                crossterm::event::Event::Key(KeyEvent {
-                    code: KeyCode::Enter,
+                    code,
                     kind: KeyEventKind::Press,
                     ..
-                }) => Event::Enter,
+                }) => match (self.key_bindings.event_for_key(code), code) {
+                    (Event::None, KeyCode::Char(c)) => Event::Char(c),
+                    (Event::None, KeyCode::Backspace) => Event::Backspace,
+                    (e, _) => e,
+                },
                 crossterm::event::Event::Resize(_, _) => Event::Redraw,
                 _ => Event::None,
             };
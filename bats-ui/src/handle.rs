@@ -0,0 +1,56 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{Receiver, Sender};
+
+/// A request for the `Ui` that is made from another thread.
+#[derive(Debug)]
+pub enum UiRequest {
+    /// Save the tracks and bpm to a project file. The result is sent once the project has been
+    /// written.
+    SaveProject {
+        /// The path of the project file.
+        path: PathBuf,
+        /// Receives the result of saving.
+        done: Sender<Result<()>>,
+    },
+}
+
+/// Makes requests to a running `Ui` from other threads, for example from a session manager.
+#[derive(Clone, Debug)]
+pub struct UiHandle {
+    /// Set to make the `Ui` exit.
+    pub(crate) quit: Arc<AtomicBool>,
+    /// Sends requests to the `Ui`.
+    pub(crate) requests: Sender<UiRequest>,
+}
+
+impl UiHandle {
+    /// Make the `Ui` exit as if quit was selected from the main menu.
+    pub fn quit(&self) {
+        self.quit.store(true, Ordering::Relaxed);
+    }
+
+    /// The flag that makes the `Ui` exit once it is set. Useful for setting it from a signal
+    /// handler.
+    pub fn quit_flag(&self) -> Arc<AtomicBool> {
+        self.quit.clone()
+    }
+
+    /// Save the tracks and bpm to a project file at `path`. The returned receiver gets the result
+    /// once the project has been written.
+    pub fn save_project(&self, path: PathBuf) -> Receiver<Result<()>> {
+        let (done, result) = crossbeam_channel::bounded(1);
+        if let Err(err) = self.requests.send(UiRequest::SaveProject { path, done }) {
+            let UiRequest::SaveProject { done, .. } = err.into_inner();
+            let _ = done.send(Err(anyhow!("the UI is no longer running")));
+        }
+        result
+    }
+}
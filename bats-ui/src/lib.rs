@@ -1,22 +1,84 @@
-use std::io::Stdout;
+use std::{
+    cell::Cell,
+    io::Stdout,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
 
-use anyhow::Result;
-use bats_async::CommandSender;
+use anyhow::{anyhow, Result};
+use bats_async::{command::TrackContents, CommandSender};
+use bats_dsp::position::Position;
 use bats_lib::{
-    builder::PluginBuilder,
-    plugin::metadata::{Param, ParamType},
+    builder::{EffectBuilder, PluginBuilder},
+    expression::{ExpressionRoute, ExpressionSource},
+    lfo::{LfoWaveform, ParamLfo},
+    macros::{MacroMapping, MacroTarget},
+    midi_filter::{MidiFilter, VelocityCurve},
+    plugin::{
+        compressor::Compressor,
+        metadata::{Metadata, Param, ParamType, PluginCategory},
+        sampler::Sampler,
+        BatsEffect,
+    },
+    preset::Preset,
+    recorder::RecordSource,
+    sequence::Sequence,
+    track::{MidiDestination, TrackColor},
+    transport::{MetronomeSubdivision, TransportSync},
+    tuning::Tuning,
     Bats,
 };
-use bats_state::{BatsState, TrackDetails};
-use events::EventPoll;
+use bats_state::{BatsState, SliceMode, TrackDetails};
+use bmidi::{ControlFunction, U7};
+use events::{EventPoll, KeyBindings};
+use handle::{UiHandle, UiRequest};
 use log::{info, warn};
 use menu::{Menu, MenuAction, SelectorMenu};
-use ratatui::{prelude::CrosstermBackend, style::Color, Terminal};
+use oscilloscope::Oscilloscope;
+use piano_roll::PianoRoll;
+use ratatui::{prelude::CrosstermBackend, Terminal};
+use serde::Deserialize;
+use status_bar::StatusBar;
+use text_input::TextInput;
+use theme::{track_color, Theme, ThemePreset};
 
 pub mod bats_state;
 pub mod events;
+pub mod handle;
 pub mod menu;
+pub mod oscilloscope;
+pub mod piano_roll;
 pub mod selector;
+pub mod status_bar;
+pub mod text_input;
+pub mod theme;
+
+/// Configuration for the Ui.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct UiConfig {
+    /// The colors to use. Either the name of a preset, like `"light"`, or a table of colors.
+    #[serde(deserialize_with = "theme::deserialize_theme")]
+    pub theme: Theme,
+    /// The keys that trigger each event.
+    pub key_bindings: KeyBindings,
+    /// The directory to save and load plugin presets from. Presets are disabled if unset.
+    pub presets_dir: Option<PathBuf>,
+    /// If opening a track from the tracks list also arms it.
+    pub arm_on_select: bool,
+}
+
+impl Default for UiConfig {
+    fn default() -> UiConfig {
+        UiConfig {
+            theme: Theme::default(),
+            key_bindings: KeyBindings::default(),
+            presets_dir: None,
+            arm_on_select: true,
+        }
+    }
+}
 
 /// Runs the Ui.
 pub struct Ui {
@@ -26,12 +88,24 @@ pub struct Ui {
     event_poll: EventPoll,
     /// Contains bats related state information.
     bats_state: BatsState,
+    /// The colors to use.
+    theme: Theme,
+    /// The directory to save and load presets from.
+    presets_dir: Option<PathBuf>,
+    /// If opening a track from the tracks list also arms it.
+    arm_on_select: bool,
+    /// Sends requests to `bats_state` from other threads.
+    requests: crossbeam_channel::Sender<UiRequest>,
 }
 
 impl Ui {
+    /// How often to redraw the UI when there is no user input.
+    const REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+
     /// Create a new `Ui`.
-    pub fn new(bats: &Bats, commands: CommandSender) -> Result<Ui> {
-        let bats_state = BatsState::new(bats, commands);
+    pub fn new(bats: &Bats, commands: CommandSender, config: UiConfig) -> Result<Ui> {
+        let (requests, request_receiver) = crossbeam_channel::unbounded();
+        let bats_state = BatsState::new(bats, commands).with_requests(request_receiver);
         // Initialize the terminal user interface.
         let backend = CrosstermBackend::new(std::io::stdout());
         let mut terminal = Terminal::new(backend)?;
@@ -46,22 +120,65 @@ impl Ui {
         info!("Initialized UI.");
         Ok(Ui {
             terminal,
-            event_poll: EventPoll {},
+            event_poll: EventPoll {
+                key_bindings: config.key_bindings,
+                redraw_interval: Some(Ui::REDRAW_INTERVAL),
+                quit: Arc::default(),
+            },
             bats_state,
+            theme: config.theme,
+            presets_dir: config.presets_dir,
+            arm_on_select: config.arm_on_select,
+            requests,
         })
     }
 
-    /// Run the UI.
+    /// Get a handle for making requests to the UI from other threads.
+    pub fn handle(&self) -> UiHandle {
+        UiHandle {
+            quit: self.event_poll.quit.clone(),
+            requests: self.requests.clone(),
+        }
+    }
+
+    /// Run the UI until quit is selected or requested through a `UiHandle`.
     pub fn run(&mut self) -> Result<()> {
+        let res = self.run_main_menu();
+        if self.event_poll.quit.load(Ordering::Relaxed) {
+            info!("Quit was requested.");
+            self.bats_state.stop_disk_recording();
+            return Ok(());
+        }
+        res
+    }
+
+    /// Run the main menu page.
+    fn run_main_menu(&mut self) -> Result<()> {
         #[derive(Copy, Clone)]
         enum MainMenuItem {
             Tracks,
             Metronome,
+            MasterCompressor,
+            AuxBuses,
+            Macros,
+            Scenes,
+            Export,
+            DiskRecording,
+            Oscilloscope,
+            Settings,
             Quit,
         }
         let menu_items = [
             MainMenuItem::Tracks,
             MainMenuItem::Metronome,
+            MainMenuItem::MasterCompressor,
+            MainMenuItem::AuxBuses,
+            MainMenuItem::Macros,
+            MainMenuItem::Scenes,
+            MainMenuItem::Export,
+            MainMenuItem::DiskRecording,
+            MainMenuItem::Oscilloscope,
+            MainMenuItem::Settings,
             MainMenuItem::Quit,
         ];
         let mut menu = SelectorMenu::new(
@@ -70,14 +187,46 @@ impl Ui {
             |i: &MainMenuItem| match i {
                 MainMenuItem::Tracks => "Tracks".to_string(),
                 MainMenuItem::Metronome => "Metronome".to_string(),
+                MainMenuItem::MasterCompressor => "Master Compressor".to_string(),
+                MainMenuItem::AuxBuses => "Aux Buses".to_string(),
+                MainMenuItem::Macros => "Macros".to_string(),
+                MainMenuItem::Scenes => "Scenes".to_string(),
+                MainMenuItem::Export => "Export Loop".to_string(),
+                MainMenuItem::DiskRecording => "Record To Disk".to_string(),
+                MainMenuItem::Oscilloscope => "Oscilloscope".to_string(),
+                MainMenuItem::Settings => "Settings".to_string(),
                 MainMenuItem::Quit => "Quit".to_string(),
             },
-        );
+        )
+        .with_theme(self.theme);
         loop {
-            match menu.run(&self.event_poll, &mut self.terminal)? {
+            menu.set_theme(self.theme);
+            match menu.run(
+                &self.event_poll,
+                &mut self.terminal,
+                &StatusBar::new(&self.bats_state, self.theme),
+            )? {
                 Some(MainMenuItem::Tracks) => self.run_tracks()?,
                 Some(MainMenuItem::Metronome) => self.run_metronome()?,
-                Some(MainMenuItem::Quit) => return Ok(()),
+                Some(MainMenuItem::MasterCompressor) => Self::edit_compressor(
+                    "Master Compressor".to_string(),
+                    self.theme,
+                    &self.event_poll,
+                    &mut self.terminal,
+                    &self.bats_state,
+                    None,
+                )?,
+                Some(MainMenuItem::AuxBuses) => self.run_aux_buses()?,
+                Some(MainMenuItem::Macros) => self.run_macros()?,
+                Some(MainMenuItem::Scenes) => self.run_scenes()?,
+                Some(MainMenuItem::Export) => self.run_export()?,
+                Some(MainMenuItem::DiskRecording) => self.run_disk_recording()?,
+                Some(MainMenuItem::Oscilloscope) => self.run_oscilloscope()?,
+                Some(MainMenuItem::Settings) => self.run_settings()?,
+                Some(MainMenuItem::Quit) => {
+                    self.bats_state.stop_disk_recording();
+                    return Ok(());
+                }
                 None => (),
             }
         }
@@ -86,18 +235,39 @@ impl Ui {
     /// Run the track menu page. This contains all tracks.
     fn run_tracks(&mut self) -> Result<()> {
         let tracks = self.bats_state.tracks_vec();
-        let mut menu =
-            SelectorMenu::new("Tracks".to_string(), tracks, |t: &TrackDetails| t.title());
-        if let Some(track) = menu.run(&self.event_poll, &mut self.terminal)? {
+        let mut menu = SelectorMenu::new("Tracks".to_string(), tracks, |t: &TrackDetails| {
+            if self.bats_state.armed() == t.id {
+                format!("{} [armed]", t.title())
+            } else {
+                t.title()
+            }
+        })
+        .with_theme(self.theme)
+        .with_item_color(|t: &TrackDetails| t.color.map(track_color))
+        .with_extra_event_handler(|event, t: &TrackDetails| match event {
+            events::Event::ToggleArm => {
+                self.bats_state.toggle_armed(t.id);
+                MenuAction::Redraw
+            }
+            _ => MenuAction::None,
+        });
+        let selected = menu.run(
+            &self.event_poll,
+            &mut self.terminal,
+            &StatusBar::new(&self.bats_state, self.theme),
+        )?;
+        drop(menu);
+        if let Some(track) = selected {
             let track = self.bats_state.track_by_id(track.id).unwrap().clone();
             if track.plugin_metadata.name == "empty" {
                 if let Some(plugin_builder) = Self::select_plugin(
                     format!("Select Plugin for {}", track.title()),
+                    self.theme,
+                    &self.bats_state,
                     &self.event_poll,
                     &mut self.terminal,
                 )? {
-                    let plugin = plugin_builder.build(self.bats_state.sample_rate());
-                    self.bats_state.set_plugin(track.id, plugin);
+                    self.bats_state.load_plugin(track.id, plugin_builder);
                 }
             }
             self.run_single_track(track.id)?;
@@ -110,14 +280,36 @@ impl Ui {
         let min_metronome_volume = 2f32.powi(-10);
         #[derive(Copy, Clone)]
         enum Item {
+            Playing,
             Bpm,
             Volume,
+            Subdivision,
+            Swing,
             Recording,
+            Loop,
+            LoopStart,
+            LoopEnd,
+            FollowJack,
+            TimebaseMaster,
             Back,
         }
+        let bar = Position::from_beats_bars(1, 0.0);
         let mut menu = SelectorMenu::new(
             "Metronome".to_string(),
-            [Item::Bpm, Item::Volume, Item::Recording, Item::Back],
+            [
+                Item::Playing,
+                Item::Bpm,
+                Item::Volume,
+                Item::Subdivision,
+                Item::Swing,
+                Item::Recording,
+                Item::Loop,
+                Item::LoopStart,
+                Item::LoopEnd,
+                Item::FollowJack,
+                Item::TimebaseMaster,
+                Item::Back,
+            ],
             |i: &Item| match i {
                 Item::Bpm => format!("BPM: {bpm}", bpm = self.bats_state.bpm()),
                 Item::Volume => {
@@ -126,6 +318,20 @@ impl Ui {
                         volume = ParamType::Decibel.formatted(self.bats_state.metronome_volume())
                     )
                 }
+                Item::Subdivision => {
+                    let subdivision = match self.bats_state.metronome_subdivision().clicks_per_beat
+                    {
+                        1 => "Off".to_string(),
+                        2 => "8ths".to_string(),
+                        4 => "16ths".to_string(),
+                        n => format!("{n} per beat"),
+                    };
+                    format!("Subdivision: {subdivision}")
+                }
+                Item::Swing => format!(
+                    "Swing: {swing:.0}%",
+                    swing = self.bats_state.metronome_subdivision().swing * 100.0
+                ),
                 Item::Recording => {
                     let enabled = if self.bats_state.recording_enabled() {
                         1.0
@@ -137,10 +343,99 @@ impl Ui {
                         enabled = ParamType::Bool.formatted(enabled)
                     )
                 }
+                Item::Playing => {
+                    let enabled = if self.bats_state.playing() { 1.0 } else { 0.0 };
+                    format!(
+                        "Playing: {enabled}",
+                        enabled = ParamType::Bool.formatted(enabled)
+                    )
+                }
+                Item::Loop => {
+                    let enabled = if self.bats_state.looping() { 1.0 } else { 0.0 };
+                    format!(
+                        "Loop: {enabled}",
+                        enabled = ParamType::Bool.formatted(enabled)
+                    )
+                }
+                Item::LoopStart => format!(
+                    "Loop Start: Bar {bar}",
+                    bar = self.bats_state.loop_range().start.bar() + 1
+                ),
+                Item::LoopEnd => format!(
+                    "Loop End: Bar {bar}",
+                    bar = self.bats_state.loop_range().end.bar()
+                ),
+                Item::FollowJack => {
+                    let enabled = if self.bats_state.transport_sync().follow {
+                        1.0
+                    } else {
+                        0.0
+                    };
+                    format!(
+                        "Follow JACK Transport: {enabled}",
+                        enabled = ParamType::Bool.formatted(enabled)
+                    )
+                }
+                Item::TimebaseMaster => {
+                    let enabled = if self.bats_state.transport_sync().timebase_master {
+                        1.0
+                    } else {
+                        0.0
+                    };
+                    format!(
+                        "JACK Timebase Master: {enabled}",
+                        enabled = ParamType::Bool.formatted(enabled)
+                    )
+                }
                 Item::Back => "Back".to_string(),
             },
         )
+        .with_theme(self.theme)
         .with_extra_event_handler(|event, selected| match (event, selected) {
+            (events::Event::Left, Item::Playing) => {
+                self.bats_state.set_playing(false);
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::Playing) => {
+                self.bats_state.set_playing(true);
+                MenuAction::Redraw
+            }
+            (events::Event::Left, Item::Loop) => {
+                self.bats_state.set_looping(false);
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::Loop) => {
+                self.bats_state.set_looping(true);
+                MenuAction::Redraw
+            }
+            (events::Event::Left, Item::LoopStart) => {
+                self.bats_state.modify_loop_range(|r| {
+                    if r.start < bar {
+                        r
+                    } else {
+                        r.start - bar..r.end
+                    }
+                });
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::LoopStart) => {
+                self.bats_state.modify_loop_range(|r| r.start + bar..r.end);
+                MenuAction::Redraw
+            }
+            (events::Event::Left, Item::LoopEnd) => {
+                self.bats_state.modify_loop_range(|r| {
+                    if r.end < bar {
+                        r
+                    } else {
+                        r.start..r.end - bar
+                    }
+                });
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::LoopEnd) => {
+                self.bats_state.modify_loop_range(|r| r.start..r.end + bar);
+                MenuAction::Redraw
+            }
             (events::Event::Left, Item::Volume) => {
                 self.bats_state.modify_metronome(|v| {
                     if v <= min_metronome_volume {
@@ -161,6 +456,38 @@ impl Ui {
                 });
                 MenuAction::Redraw
             }
+            (events::Event::Left, Item::Subdivision) => {
+                self.bats_state
+                    .modify_metronome_subdivision(|s| MetronomeSubdivision {
+                        clicks_per_beat: s.clicks_per_beat / 2,
+                        ..s
+                    });
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::Subdivision) => {
+                self.bats_state
+                    .modify_metronome_subdivision(|s| MetronomeSubdivision {
+                        clicks_per_beat: (s.clicks_per_beat * 2).min(4),
+                        ..s
+                    });
+                MenuAction::Redraw
+            }
+            (events::Event::Left, Item::Swing) => {
+                self.bats_state
+                    .modify_metronome_subdivision(|s| MetronomeSubdivision {
+                        swing: s.swing - 0.01,
+                        ..s
+                    });
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::Swing) => {
+                self.bats_state
+                    .modify_metronome_subdivision(|s| MetronomeSubdivision {
+                        swing: s.swing + 0.01,
+                        ..s
+                    });
+                MenuAction::Redraw
+            }
             (events::Event::Left, Item::Bpm) => {
                 self.bats_state.modify_bpm(|v| v - 1.0);
                 MenuAction::Redraw
@@ -177,147 +504,2392 @@ impl Ui {
                 self.bats_state.set_recording(true);
                 MenuAction::Redraw
             }
+            (events::Event::Left, Item::FollowJack) => {
+                self.bats_state.set_transport_sync(TransportSync {
+                    follow: false,
+                    ..self.bats_state.transport_sync()
+                });
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::FollowJack) => {
+                self.bats_state.set_transport_sync(TransportSync {
+                    follow: true,
+                    ..self.bats_state.transport_sync()
+                });
+                MenuAction::Redraw
+            }
+            (events::Event::Left, Item::TimebaseMaster) => {
+                self.bats_state.set_transport_sync(TransportSync {
+                    timebase_master: false,
+                    ..self.bats_state.transport_sync()
+                });
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::TimebaseMaster) => {
+                self.bats_state.set_transport_sync(TransportSync {
+                    timebase_master: true,
+                    ..self.bats_state.transport_sync()
+                });
+                MenuAction::Redraw
+            }
             _ => MenuAction::None,
         });
-        while let Some(item) = menu.run(&self.event_poll, &mut self.terminal)? {
+        while let Some(item) = menu.run(
+            &self.event_poll,
+            &mut self.terminal,
+            &StatusBar::new(&self.bats_state, self.theme),
+        )? {
             match item {
-                Item::Bpm => (),
-                Item::Volume => (),
+                Item::Bpm => {
+                    let mut input = TextInput::new(
+                        "Enter BPM".to_string(),
+                        self.bats_state.bpm().to_string(),
+                        parse_bpm,
+                    )
+                    .with_theme(self.theme);
+                    if let Some(bpm) = input.run(
+                        &self.event_poll,
+                        &mut self.terminal,
+                        &StatusBar::new(&self.bats_state, self.theme),
+                    )? {
+                        self.bats_state.modify_bpm(|_| bpm);
+                    }
+                }
+                Item::Volume | Item::Subdivision | Item::Swing => (),
                 Item::Recording => self.bats_state.toggle_recording(),
+                Item::Playing => self.bats_state.set_playing(!self.bats_state.playing()),
+                Item::Loop => self.bats_state.set_looping(!self.bats_state.looping()),
+                Item::LoopStart | Item::LoopEnd => (),
+                Item::FollowJack => {
+                    let sync = self.bats_state.transport_sync();
+                    self.bats_state.set_transport_sync(TransportSync {
+                        follow: !sync.follow,
+                        ..sync
+                    });
+                }
+                Item::TimebaseMaster => {
+                    let sync = self.bats_state.transport_sync();
+                    self.bats_state.set_transport_sync(TransportSync {
+                        timebase_master: !sync.timebase_master,
+                        ..sync
+                    });
+                }
                 Item::Back => return Ok(()),
             }
         }
         Ok(())
     }
 
-    /// Run the page for a single track. This has links to other pages for the track such as
-    /// changing the plugin and adjusting the params.
-    fn run_single_track(&mut self, track_id: usize) -> Result<()> {
-        self.bats_state.set_armed(track_id);
+    /// Run the aux buses page. This contains all aux buses.
+    fn run_aux_buses(&mut self) -> Result<()> {
+        let buses: Vec<usize> = (0..Bats::AUX_BUS_COUNT).collect();
+        let mut menu = SelectorMenu::new("Aux Buses".to_string(), buses, |bus: &usize| {
+            let effects: Vec<&str> = self
+                .bats_state
+                .aux_bus(*bus)
+                .map(|b| b.effects.iter().map(|e| e.effect.name()).collect())
+                .unwrap_or_default();
+            match effects.as_slice() {
+                [] => aux_bus_name(*bus),
+                effects => format!("{} [{}]", aux_bus_name(*bus), effects.join(", ")),
+            }
+        })
+        .with_theme(self.theme);
+        while let Some(bus) = menu.run(
+            &self.event_poll,
+            &mut self.terminal,
+            &StatusBar::new(&self.bats_state, self.theme),
+        )? {
+            Self::run_aux_bus(
+                self.theme,
+                &self.event_poll,
+                &mut self.terminal,
+                &self.bats_state,
+                bus,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Run the page for a single aux bus. This contains the return volume and the effects.
+    fn run_aux_bus(
+        theme: Theme,
+        event_poll: &EventPoll,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        bats_state: &BatsState,
+        bus: usize,
+    ) -> Result<()> {
         #[derive(Copy, Clone)]
-        enum TrackMenuItem {
-            ChangeVolume,
-            ChangePlugin,
-            Params,
-            ClearSequence,
+        enum Item {
+            Volume,
+            Effect(usize),
+            AddEffect,
         }
-        let menu_items = [
-            TrackMenuItem::ChangeVolume,
-            TrackMenuItem::ChangePlugin,
-            TrackMenuItem::Params,
-            TrackMenuItem::ClearSequence,
-        ];
-        let mut menu =
-            SelectorMenu::new("".to_string(), &menu_items, |i: &TrackMenuItem| match i {
-                TrackMenuItem::ChangeVolume => {
-                    format!(
-                        "Volume: {volume}",
-                        volume = ParamType::Decibel
-                            .formatted(self.bats_state.track_by_id(track_id).unwrap().volume)
+        loop {
+            let details = bats_state.aux_bus(bus).unwrap_or_default();
+            let items: Vec<Item> = std::iter::once(Item::Volume)
+                .chain((0..details.effects.len()).map(Item::Effect))
+                .chain(std::iter::once(Item::AddEffect))
+                .collect();
+            let mut menu = SelectorMenu::new(aux_bus_name(bus), items, |i: &Item| match i {
+                Item::Volume => format!(
+                    "Volume: {volume}",
+                    volume = ParamType::Decibel.formatted(
+                        bats_state
+                            .aux_bus(bus)
+                            .map(|b| b.volume)
+                            .unwrap_or_default()
                     )
-                }
-                TrackMenuItem::ChangePlugin => "Change Plugin".to_string(),
-                TrackMenuItem::Params => "Params".to_string(),
-                TrackMenuItem::ClearSequence => "Clear Sequence".to_string(),
+                ),
+                Item::Effect(idx) => format!(
+                    "{n}. {name}",
+                    n = idx + 1,
+                    name = details.effects[*idx].effect.name()
+                ),
+                Item::AddEffect => "Add Effect".to_string(),
             })
-            .with_extra_event_handler(|event, action| match (action, event) {
-                (TrackMenuItem::ChangeVolume, events::Event::Left) => {
-                    self.bats_state
-                        .modify_track_volume(track_id, |v| v.volume / 1.05);
+            .with_extra_event_handler(|event, item| match (event, item) {
+                (events::Event::Left, Item::Volume) => {
+                    bats_state.modify_aux_volume(bus, decrease_gain);
                     MenuAction::Redraw
                 }
-                (TrackMenuItem::ChangeVolume, events::Event::Right) => {
-                    self.bats_state
-                        .modify_track_volume(track_id, |v| v.volume * 1.05);
+                (events::Event::Right, Item::Volume) => {
+                    bats_state.modify_aux_volume(bus, increase_gain);
                     MenuAction::Redraw
                 }
                 _ => MenuAction::None,
-            });
-        loop {
-            menu.set_title(format!(
-                "Track - {}",
-                self.bats_state.track_by_id(track_id).unwrap().title()
-            ));
-            let selected = match menu.run(&self.event_poll, &mut self.terminal)? {
-                Some(s) => s,
+            })
+            .with_theme(theme);
+            match menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))? {
                 None => return Ok(()),
-            };
-            match selected {
-                TrackMenuItem::ChangePlugin => {
-                    if let Ok(Some(b)) = Self::select_plugin(
-                        format!(
-                            "Change Plugin for {}",
-                            self.bats_state.track_by_id(track_id).unwrap().title()
-                        ),
-                        &self.event_poll,
-                        &mut self.terminal,
-                    ) {
-                        let plugin = b.build(self.bats_state.sample_rate());
-                        self.bats_state.set_plugin(track_id, plugin);
+                Some(Item::Volume) => (),
+                Some(Item::Effect(idx)) => {
+                    Self::edit_aux_effect(theme, event_poll, terminal, bats_state, bus, idx)?
+                }
+                Some(Item::AddEffect) => {
+                    let mut effect_menu = SelectorMenu::new(
+                        format!("Add Effect to {}", aux_bus_name(bus)),
+                        EffectBuilder::ALL,
+                        |e: &EffectBuilder| e.name().to_string(),
+                    )
+                    .with_theme(theme);
+                    if let Some(effect) =
+                        effect_menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))?
+                    {
+                        bats_state.add_aux_effect(bus, effect);
                     }
                 }
-                TrackMenuItem::ChangeVolume => (),
-                TrackMenuItem::Params => Self::edit_params(
-                    &self.event_poll,
-                    &mut self.terminal,
-                    &self.bats_state,
-                    track_id,
-                )?,
-                TrackMenuItem::ClearSequence => self.bats_state.set_sequence(track_id, Vec::new()),
             }
         }
     }
 
-    /// Select a plugin and return it. If the selection is canceled, then `Ok(None)` is returned.
-    fn select_plugin(
-        title: String,
-        event_poll: &EventPoll,
-        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
-    ) -> Result<Option<PluginBuilder>> {
-        let mut menu = SelectorMenu::new(title, PluginBuilder::ALL, |b: &PluginBuilder| {
-            b.name().to_string()
-        });
-        menu.run(event_poll, terminal)
-    }
-
-    /// Edit the params for the track with `track_id`.
-    fn edit_params(
+    /// Edit the params of the effect at index `effect` in the aux bus. The effect can also be
+    /// removed from the bus.
+    fn edit_aux_effect(
+        theme: Theme,
         event_poll: &EventPoll,
         terminal: &mut Terminal<CrosstermBackend<Stdout>>,
         bats_state: &BatsState,
-        track_id: usize,
+        bus: usize,
+        effect: usize,
     ) -> Result<()> {
-        let track = bats_state.track_by_id(track_id).unwrap().clone();
-        let title = format!("{} Params", track.title());
-        let mut menu = SelectorMenu::new(title, &track.plugin_metadata.params, |p: &Param| {
-            let value = bats_state
-                .track_by_id(track_id)
-                .unwrap()
-                .params
-                .get(&p.id)
-                .copied()
-                .unwrap_or(0.0);
+        #[derive(Copy, Clone)]
+        enum Item {
+            Param(Param),
+            Remove,
+        }
+        let effect_details = |bats_state: &BatsState| {
+            bats_state
+                .aux_bus(bus)
+                .and_then(|b| b.effects.get(effect).cloned())
+        };
+        let Some(details) = effect_details(bats_state) else {
+            return Ok(());
+        };
+        let params = details.effect.metadata().params;
+        let name_width = param_name_width(params);
+        let param_value = |param: &Param| {
+            effect_details(bats_state)
+                .and_then(|d| d.params.get(&param.id).copied())
+                .unwrap_or(param.default_value)
+        };
+        let items: Vec<Item> = params
+            .iter()
+            .copied()
+            .map(Item::Param)
+            .chain(std::iter::once(Item::Remove))
+            .collect();
+        let mut menu = SelectorMenu::new(
+            format!("{} - {}", aux_bus_name(bus), details.effect.name()),
+            items,
+            |i: &Item| match i {
+                Item::Param(p) => param_row(p, param_value(p), name_width),
+                Item::Remove => "Remove".to_string(),
+            },
+        )
+        .with_extra_event_handler(|event, item| match (event, item) {
+            (events::Event::Left, Item::Param(param)) => {
+                bats_state.modify_aux_effect_param(bus, effect, param.id, |v| v / 1.05);
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::Param(param)) => {
+                bats_state.modify_aux_effect_param(bus, effect, param.id, |v| v * 1.05);
+                MenuAction::Redraw
+            }
+            _ => MenuAction::None,
+        })
+        .with_theme(theme);
+        loop {
+            match menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))? {
+                None => return Ok(()),
+                Some(Item::Remove) => {
+                    bats_state.remove_aux_effect(bus, effect);
+                    return Ok(());
+                }
+                Some(Item::Param(param)) => {
+                    let mut input = TextInput::new(
+                        format!("Enter {}", param.name),
+                        param.param_type.formatted(param_value(&param)).to_string(),
+                        |text| parse_param(&param, text),
+                    )
+                    .with_theme(theme);
+                    if let Some(v) =
+                        input.run(event_poll, terminal, &StatusBar::new(bats_state, theme))?
+                    {
+                        bats_state.modify_aux_effect_param(bus, effect, param.id, |_| v);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run the macros page. This contains all macro knobs.
+    fn run_macros(&mut self) -> Result<()> {
+        let knobs: Vec<usize> = (0..Bats::MACRO_COUNT).collect();
+        let mut menu = SelectorMenu::new("Macros".to_string(), knobs, |knob: &usize| {
+            let k = self.bats_state.macro_knob(*knob).unwrap_or_default();
             format!(
-                "{name}: {value}",
-                name = p.name,
-                value = p.param_type.formatted(value),
+                "Macro {n}: {value} ({cc}, {count} targets)",
+                n = knob + 1,
+                value = ParamType::Percent.formatted(k.value),
+                cc = cc_name(k.cc),
+                count = k.mappings.len(),
             )
         })
-        .with_extra_event_handler(|event, param| match event {
+        .with_extra_event_handler(|event, knob| match event {
             events::Event::Left => {
-                bats_state.modify_param(track_id, param.id, |v| v / 1.05);
+                self.bats_state.modify_macro_value(*knob, |v| v - 0.05);
                 MenuAction::Redraw
             }
             events::Event::Right => {
-                bats_state.modify_param(track_id, param.id, |v| v * 1.05);
+                self.bats_state.modify_macro_value(*knob, |v| v + 0.05);
                 MenuAction::Redraw
             }
             _ => MenuAction::None,
         })
-        .with_color(Color::Blue);
-        menu.run(event_poll, terminal)?;
+        .with_theme(self.theme);
+        while let Some(knob) = menu.run(
+            &self.event_poll,
+            &mut self.terminal,
+            &StatusBar::new(&self.bats_state, self.theme),
+        )? {
+            Self::run_macro(
+                self.theme,
+                &self.event_poll,
+                &mut self.terminal,
+                &self.bats_state,
+                knob,
+            )?;
+        }
         Ok(())
     }
+
+    /// Run the page for a single macro knob. Selecting a target removes it.
+    fn run_macro(
+        theme: Theme,
+        event_poll: &EventPoll,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        bats_state: &BatsState,
+        knob: usize,
+    ) -> Result<()> {
+        #[derive(Copy, Clone)]
+        enum Item {
+            Value,
+            Cc,
+            Mapping(usize),
+            AddParam,
+            AddVolume,
+            CrossfadeA,
+            CrossfadeB,
+        }
+        loop {
+            let k = bats_state.macro_knob(knob).unwrap_or_default();
+            let items: Vec<Item> = [Item::Value, Item::Cc]
+                .into_iter()
+                .chain((0..k.mappings.len()).map(Item::Mapping))
+                .chain([
+                    Item::AddParam,
+                    Item::AddVolume,
+                    Item::CrossfadeA,
+                    Item::CrossfadeB,
+                ])
+                .collect();
+            let mut menu =
+                SelectorMenu::new(format!("Macro {}", knob + 1), items, |i: &Item| match i {
+                    Item::Value => format!(
+                        "Value: {}",
+                        ParamType::Percent
+                            .formatted(bats_state.macro_knob(knob).unwrap_or_default().value)
+                    ),
+                    Item::Cc => format!("MIDI: {}", cc_name(k.cc)),
+                    Item::Mapping(idx) => {
+                        format!(
+                            "Remove {}",
+                            macro_mapping_name(bats_state, &k.mappings[*idx])
+                        )
+                    }
+                    Item::AddParam => "Add Param Target".to_string(),
+                    Item::AddVolume => "Add Volume Target".to_string(),
+                    Item::CrossfadeA => "Add Crossfader Track A".to_string(),
+                    Item::CrossfadeB => "Add Crossfader Track B".to_string(),
+                })
+                .with_extra_event_handler(|event, item| match (event, item) {
+                    (events::Event::Left, Item::Value) => {
+                        bats_state.modify_macro_value(knob, |v| v - 0.05);
+                        MenuAction::Redraw
+                    }
+                    (events::Event::Right, Item::Value) => {
+                        bats_state.modify_macro_value(knob, |v| v + 0.05);
+                        MenuAction::Redraw
+                    }
+                    _ => MenuAction::None,
+                })
+                .with_theme(theme);
+            let item = match menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))? {
+                Some(i) => i,
+                None => return Ok(()),
+            };
+            let select_track = |terminal: &mut Terminal<CrosstermBackend<Stdout>>| {
+                SelectorMenu::new(
+                    "Select Track".to_string(),
+                    bats_state.tracks_vec(),
+                    |t: &TrackDetails| t.title(),
+                )
+                .with_theme(theme)
+                .run(event_poll, terminal, &StatusBar::new(bats_state, theme))
+            };
+            match item {
+                Item::Value => (),
+                Item::Cc => {
+                    let mut input = TextInput::new(
+                        "Enter MIDI CC Number or None".to_string(),
+                        k.cc.map(|cc| u8::from(cc.0).to_string())
+                            .unwrap_or_default(),
+                        parse_cc,
+                    )
+                    .with_theme(theme);
+                    if let Some(cc) =
+                        input.run(event_poll, terminal, &StatusBar::new(bats_state, theme))?
+                    {
+                        bats_state.set_macro_cc(knob, cc);
+                    }
+                }
+                Item::Mapping(idx) => bats_state.remove_macro_mapping(knob, idx),
+                Item::AddParam => {
+                    let Some(track) = select_track(terminal)? else {
+                        continue;
+                    };
+                    let params = track.plugin_metadata.params;
+                    let name_width = param_name_width(params);
+                    let Some(param) = SelectorMenu::new(
+                        format!("Select Param for {}", track.title()),
+                        params,
+                        |p: &Param| format!("{:<name_width$}", p.name),
+                    )
+                    .with_theme(theme)
+                    .run(
+                        event_poll,
+                        terminal,
+                        &StatusBar::new(bats_state, theme),
+                    )?
+                    else {
+                        continue;
+                    };
+                    if let Some((min, max)) =
+                        Self::enter_macro_range(theme, event_poll, terminal, bats_state, &param)?
+                    {
+                        let target = MacroTarget::Param {
+                            track_id: track.id,
+                            param_id: param.id,
+                        };
+                        bats_state.add_macro_mappings(knob, &[MacroMapping { target, min, max }]);
+                    }
+                }
+                Item::AddVolume => {
+                    let Some(track) = select_track(terminal)? else {
+                        continue;
+                    };
+                    if let Some((min, max)) = Self::enter_macro_range(
+                        theme,
+                        event_poll,
+                        terminal,
+                        bats_state,
+                        &VOLUME_PARAM,
+                    )? {
+                        let target = MacroTarget::TrackVolume { track_id: track.id };
+                        bats_state.add_macro_mappings(knob, &[MacroMapping { target, min, max }]);
+                    }
+                }
+                Item::CrossfadeA | Item::CrossfadeB => {
+                    let Some(track) = select_track(terminal)? else {
+                        continue;
+                    };
+                    let mappings = match item {
+                        Item::CrossfadeA => MacroMapping::crossfader(&[track.id], &[]),
+                        _ => MacroMapping::crossfader(&[], &[track.id]),
+                    };
+                    bats_state.add_macro_mappings(knob, &mappings);
+                }
+            }
+        }
+    }
+
+    /// Run the scenes page. Scenes save the params of all tracks and the morph fades the params
+    /// from one scene to another.
+    fn run_scenes(&mut self) -> Result<()> {
+        #[derive(Copy, Clone)]
+        enum Item {
+            Save,
+            Scene(usize),
+            Morph,
+            Seconds,
+        }
+        let scene_name = |scene: Option<usize>| match scene {
+            Some(s) => format!("Scene {}", s + 1),
+            None => "None".to_string(),
+        };
+        let mut menu = SelectorMenu::new(
+            "Scenes".to_string(),
+            [
+                Item::Save,
+                Item::Scene(0),
+                Item::Scene(1),
+                Item::Morph,
+                Item::Seconds,
+            ],
+            |i: &Item| {
+                let morph = self.bats_state.morph();
+                match i {
+                    Item::Save => format!(
+                        "Save Scene ({count} saved)",
+                        count = self.bats_state.scene_count()
+                    ),
+                    Item::Scene(side) => format!(
+                        "Morph {ab}: {scene}",
+                        ab = if *side == 0 { "A" } else { "B" },
+                        scene = scene_name(morph.scenes[*side]),
+                    ),
+                    Item::Morph => format!(
+                        "Morph: {target}",
+                        target = ParamType::Percent.formatted(morph.target)
+                    ),
+                    Item::Seconds => format!(
+                        "Morph Time: {seconds}",
+                        seconds = ParamType::Duration.formatted(morph.seconds)
+                    ),
+                }
+            },
+        )
+        .with_extra_event_handler(|event, item| match (event, item) {
+            (events::Event::Left, Item::Morph) => {
+                let target = self.bats_state.morph().target;
+                self.bats_state.set_morph_target(target - 0.1);
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::Morph) => {
+                let target = self.bats_state.morph().target;
+                self.bats_state.set_morph_target(target + 0.1);
+                MenuAction::Redraw
+            }
+            (events::Event::Left, Item::Seconds) => {
+                self.bats_state.modify_morph_seconds(|s| s - 0.25);
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::Seconds) => {
+                self.bats_state.modify_morph_seconds(|s| s + 0.25);
+                MenuAction::Redraw
+            }
+            _ => MenuAction::None,
+        })
+        .with_theme(self.theme);
+        while let Some(item) = menu.run(
+            &self.event_poll,
+            &mut self.terminal,
+            &StatusBar::new(&self.bats_state, self.theme),
+        )? {
+            match item {
+                Item::Save => {
+                    self.bats_state.save_scene();
+                }
+                Item::Scene(side) => {
+                    let scenes: Vec<usize> = (0..self.bats_state.scene_count()).collect();
+                    if let Some(scene) =
+                        SelectorMenu::new("Select Scene".to_string(), scenes, |s: &usize| {
+                            scene_name(Some(*s))
+                        })
+                        .with_theme(self.theme)
+                        .run(
+                            &self.event_poll,
+                            &mut self.terminal,
+                            &StatusBar::new(&self.bats_state, self.theme),
+                        )?
+                    {
+                        self.bats_state.set_morph_scene(side, scene);
+                    }
+                }
+                Item::Morph => {
+                    let target = if self.bats_state.morph().target < 0.5 {
+                        1.0
+                    } else {
+                        0.0
+                    };
+                    self.bats_state.set_morph_target(target);
+                }
+                Item::Seconds => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Ask for the range that a macro knob sets `param` to.
+    fn enter_macro_range(
+        theme: Theme,
+        event_poll: &EventPoll,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        bats_state: &BatsState,
+        param: &Param,
+    ) -> Result<Option<(f32, f32)>> {
+        let mut input = TextInput::new(
+            format!("Enter {} Range", param.name),
+            format!(
+                "{}, {}",
+                param.param_type.formatted(param.min_value),
+                param.param_type.formatted(param.max_value)
+            ),
+            |text| parse_param_range(param, text),
+        )
+        .with_theme(theme);
+        input.run(event_poll, terminal, &StatusBar::new(bats_state, theme))
+    }
+
+    /// Ask for a path and export a single loop to it.
+    fn run_export(&mut self) -> Result<()> {
+        let mut input = TextInput::new(
+            "Export Loop To".to_string(),
+            "bats-loop.wav".to_string(),
+            |text| {
+                let path = PathBuf::from(text.trim());
+                match path.extension() {
+                    Some(ext) if ext == "wav" => Ok(path),
+                    _ => Err(anyhow!("{text:?} must end with .wav.")),
+                }
+            },
+        )
+        .with_theme(self.theme);
+        let path = match input.run(
+            &self.event_poll,
+            &mut self.terminal,
+            &StatusBar::new(&self.bats_state, self.theme),
+        )? {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        let mut menu = SelectorMenu::new("Export".to_string(), [false, true], |stems: &bool| {
+            if *stems {
+                "Master And Track Stems".to_string()
+            } else {
+                "Master Only".to_string()
+            }
+        })
+        .with_theme(self.theme);
+        if let Some(stems) = menu.run(
+            &self.event_poll,
+            &mut self.terminal,
+            &StatusBar::new(&self.bats_state, self.theme),
+        )? {
+            self.bats_state.export_loop(path, stems);
+        }
+        Ok(())
+    }
+
+    /// Stop recording to disk if audio is being recorded. Otherwise, ask for a path and the audio
+    /// to record and start recording to disk.
+    fn run_disk_recording(&mut self) -> Result<()> {
+        if self.bats_state.disk_recording().is_some() {
+            self.bats_state.stop_disk_recording();
+            return Ok(());
+        }
+        let mut input = TextInput::new(
+            "Record To".to_string(),
+            "bats-recording.wav".to_string(),
+            |text| {
+                let path = PathBuf::from(text.trim());
+                match path.extension() {
+                    Some(ext) if ext == "wav" => Ok(path),
+                    _ => Err(anyhow!("{text:?} must end with .wav.")),
+                }
+            },
+        )
+        .with_theme(self.theme);
+        let path = match input.run(
+            &self.event_poll,
+            &mut self.terminal,
+            &StatusBar::new(&self.bats_state, self.theme),
+        )? {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        let sources = std::iter::once(RecordSource::Master)
+            .chain((0..self.bats_state.tracks_vec().len()).map(RecordSource::Track))
+            .collect::<Vec<_>>();
+        let mut menu = SelectorMenu::new(
+            "Record Source".to_string(),
+            sources,
+            |s: &RecordSource| match s {
+                RecordSource::Master => "Master".to_string(),
+                RecordSource::Track(id) => self
+                    .bats_state
+                    .track_by_id(*id)
+                    .map(|t| t.title())
+                    .unwrap_or_default(),
+            },
+        )
+        .with_theme(self.theme);
+        if let Some(source) = menu.run(
+            &self.event_poll,
+            &mut self.terminal,
+            &StatusBar::new(&self.bats_state, self.theme),
+        )? {
+            self.bats_state.start_disk_recording(path, source);
+        }
+        Ok(())
+    }
+
+    /// Run the oscilloscope page. The master output is only streamed to the UI while the page is
+    /// open.
+    fn run_oscilloscope(&mut self) -> Result<()> {
+        #[derive(Copy, Clone)]
+        enum Item {
+            Window,
+            Back,
+        }
+        let seconds = Cell::new(2.0f32);
+        let max_seconds = BatsState::SCOPE_SECONDS as f32;
+        self.bats_state.start_scope();
+        let mut menu = SelectorMenu::new(
+            "Oscilloscope".to_string(),
+            [Item::Window, Item::Back],
+            |i: &Item| match i {
+                Item::Window => format!("Window: {} seconds", seconds.get()),
+                Item::Back => "Back".to_string(),
+            },
+        )
+        .with_theme(self.theme)
+        .with_extra_event_handler(|event, selected| match (event, selected) {
+            (events::Event::Left, Item::Window) => {
+                seconds.set((seconds.get() / 2.0).max(0.125));
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::Window) => {
+                seconds.set((seconds.get() * 2.0).min(max_seconds));
+                MenuAction::Redraw
+            }
+            _ => MenuAction::None,
+        })
+        .with_panel(16, |frame, area| {
+            let points = self.bats_state.scope_points(seconds.get());
+            let capacity = (seconds.get() * BatsState::SCOPE_POINTS_PER_SECOND as f32) as usize;
+            let scope = Oscilloscope::new(&points, capacity).with_color(self.theme.foreground);
+            frame.render_widget(scope, area);
+        });
+        while let Some(item) = menu.run(
+            &self.event_poll,
+            &mut self.terminal,
+            &StatusBar::new(&self.bats_state, self.theme),
+        )? {
+            match item {
+                Item::Window => (),
+                Item::Back => break,
+            }
+        }
+        self.bats_state.stop_scope();
+        Ok(())
+    }
+
+    /// Run the settings page.
+    fn run_settings(&mut self) -> Result<()> {
+        #[derive(Copy, Clone)]
+        enum Item {
+            Theme,
+            MidiInput(usize),
+            DirectOutputs,
+            Latency,
+            RecordLatency,
+            OverloadProtection,
+            Back,
+        }
+        let theme = Cell::new(self.theme);
+        let bats_state = &self.bats_state;
+        let track_count = bats_state.tracks_vec().len();
+        let items: Vec<Item> = std::iter::once(Item::Theme)
+            .chain((0..Bats::MIDI_INPUT_PORT_COUNT).map(Item::MidiInput))
+            .chain([
+                Item::DirectOutputs,
+                Item::Latency,
+                Item::RecordLatency,
+                Item::OverloadProtection,
+                Item::Back,
+            ])
+            .collect();
+        let mut menu = SelectorMenu::new("Settings".to_string(), items, |i: &Item| match i {
+            Item::Theme => format!(
+                "Theme: {name}",
+                name = ThemePreset::from_theme(theme.get())
+                    .map(ThemePreset::name)
+                    .unwrap_or("custom")
+            ),
+            Item::MidiInput(port) => format!(
+                "MIDI Input {number}: {track}",
+                number = port + 1,
+                track = match bats_state.midi_input_route(*port) {
+                    Some(track_id) => format!("Track {}", track_id + 1),
+                    None => "Armed Track".to_string(),
+                }
+            ),
+            Item::DirectOutputs => format!(
+                "Direct Track Outputs: {}",
+                if bats_state.direct_outputs() {
+                    "On"
+                } else {
+                    "Off"
+                }
+            ),
+            Item::Latency => format!(
+                "Round Trip Latency: {}",
+                match bats_state.latency_seconds() {
+                    Some(seconds) => ParamType::Duration.formatted(seconds).to_string(),
+                    None => "n/a".to_string(),
+                }
+            ),
+            Item::RecordLatency => format!(
+                "Record Latency Compensation: {}",
+                ParamType::Duration.formatted(bats_state.record_latency_seconds())
+            ),
+            Item::OverloadProtection => format!(
+                "Overload Protection: {}",
+                match bats_state.overload_threshold() {
+                    Some(threshold) => format!("{threshold:.0}% DSP"),
+                    None => "Off".to_string(),
+                }
+            ),
+            Item::Back => "Back".to_string(),
+        })
+        .with_extra_event_handler(|event, selected| match (event, selected) {
+            (events::Event::Left | events::Event::Right, Item::OverloadProtection) => {
+                let step = match event {
+                    events::Event::Left => -BatsState::OVERLOAD_THRESHOLD_STEP,
+                    _ => BatsState::OVERLOAD_THRESHOLD_STEP,
+                };
+                // Stepping below the lowest threshold turns protection off.
+                let threshold = match bats_state.overload_threshold() {
+                    Some(threshold) => Some(threshold + step),
+                    None if step > 0.0 => Some(*BatsState::OVERLOAD_THRESHOLD_RANGE.start()),
+                    None => None,
+                }
+                .filter(|t| *t >= *BatsState::OVERLOAD_THRESHOLD_RANGE.start())
+                .map(|t| t.min(*BatsState::OVERLOAD_THRESHOLD_RANGE.end()));
+                bats_state.set_overload_threshold(threshold);
+                MenuAction::Redraw
+            }
+            (events::Event::Left, Item::RecordLatency) => {
+                bats_state.set_record_latency_seconds(bats_state.record_latency_seconds() - 0.001);
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::RecordLatency) => {
+                bats_state.set_record_latency_seconds(bats_state.record_latency_seconds() + 0.001);
+                MenuAction::Redraw
+            }
+            (events::Event::Left | events::Event::Right, Item::Theme) => {
+                let offset = match event {
+                    events::Event::Left => ThemePreset::ALL.len() - 1,
+                    _ => 1,
+                };
+                let idx = ThemePreset::from_theme(theme.get())
+                    .and_then(|p| ThemePreset::ALL.iter().position(|other| *other == p))
+                    .map(|idx| (idx + offset) % ThemePreset::ALL.len())
+                    .unwrap_or(0);
+                theme.set(ThemePreset::ALL[idx].theme());
+                // Exit the menu so that it is redrawn with the new theme.
+                MenuAction::Select(Item::Theme)
+            }
+            (events::Event::Left | events::Event::Right, Item::MidiInput(port)) => {
+                // Cycle through the armed track, represented by 0, and then each track.
+                let offset = match event {
+                    events::Event::Left => track_count,
+                    _ => 1,
+                };
+                let current = bats_state.midi_input_route(*port).map_or(0, |t| t + 1);
+                let next = (current + offset) % (track_count + 1);
+                bats_state.set_midi_input_route(*port, next.checked_sub(1));
+                MenuAction::Redraw
+            }
+            (events::Event::Left | events::Event::Right, Item::DirectOutputs) => {
+                bats_state.set_direct_outputs(!bats_state.direct_outputs());
+                MenuAction::Redraw
+            }
+            _ => MenuAction::None,
+        });
+        loop {
+            menu.set_theme(theme.get());
+            let selected = menu.run(
+                &self.event_poll,
+                &mut self.terminal,
+                &StatusBar::new(bats_state, theme.get()),
+            )?;
+            self.theme = theme.get();
+            match selected {
+                Some(Item::DirectOutputs) => {
+                    bats_state.set_direct_outputs(!bats_state.direct_outputs())
+                }
+                Some(Item::RecordLatency) => {
+                    // Default to the measured round trip latency if it is known.
+                    let seconds = bats_state
+                        .latency_seconds()
+                        .unwrap_or(bats_state.record_latency_seconds());
+                    let mut input = TextInput::new(
+                        "Enter Record Latency Compensation".to_string(),
+                        ParamType::Duration.formatted(seconds).to_string(),
+                        parse_duration,
+                    )
+                    .with_theme(theme.get());
+                    if let Some(seconds) = input.run(
+                        &self.event_poll,
+                        &mut self.terminal,
+                        &StatusBar::new(bats_state, theme.get()),
+                    )? {
+                        bats_state.set_record_latency_seconds(seconds);
+                    }
+                }
+                Some(Item::OverloadProtection) => {
+                    let threshold = match bats_state.overload_threshold() {
+                        Some(_) => None,
+                        None => Some(BatsState::DEFAULT_OVERLOAD_THRESHOLD),
+                    };
+                    bats_state.set_overload_threshold(threshold);
+                }
+                Some(Item::Theme | Item::MidiInput(_) | Item::Latency) => (),
+                Some(Item::Back) | None => return Ok(()),
+            }
+        }
+    }
+
+    /// Run the page for a single track. This has links to other pages for the track such as
+    /// changing the plugin and adjusting the params.
+    fn run_single_track(&mut self, track_id: usize) -> Result<()> {
+        if self.arm_on_select {
+            self.bats_state.set_armed(track_id);
+        }
+        #[derive(Copy, Clone)]
+        enum TrackMenuItem {
+            ChangeVolume,
+            ChangePlugin,
+            StreamSample,
+            SliceSample,
+            Slices,
+            Params,
+            Expression,
+            Lfos,
+            MidiFilter,
+            Tuning,
+            Compressor,
+            Input,
+            MidiDestination,
+            LoopLength,
+            Name,
+            Color,
+            Send(usize),
+            CopyTo,
+            Freeze,
+            ClearSequence,
+            ClearAutomation,
+        }
+        let menu_items: Vec<TrackMenuItem> = [
+            TrackMenuItem::ChangeVolume,
+            TrackMenuItem::ChangePlugin,
+            TrackMenuItem::StreamSample,
+            TrackMenuItem::SliceSample,
+            TrackMenuItem::Slices,
+            TrackMenuItem::Params,
+            TrackMenuItem::Expression,
+            TrackMenuItem::Lfos,
+            TrackMenuItem::MidiFilter,
+            TrackMenuItem::Tuning,
+            TrackMenuItem::Compressor,
+            TrackMenuItem::Input,
+            TrackMenuItem::MidiDestination,
+            TrackMenuItem::LoopLength,
+        ]
+        .into_iter()
+        .chain((0..Bats::AUX_BUS_COUNT).map(TrackMenuItem::Send))
+        .chain([
+            TrackMenuItem::Name,
+            TrackMenuItem::Color,
+            TrackMenuItem::CopyTo,
+            TrackMenuItem::Freeze,
+            TrackMenuItem::ClearSequence,
+            TrackMenuItem::ClearAutomation,
+        ])
+        .collect();
+        let mut menu =
+            SelectorMenu::new("".to_string(), &menu_items, |i: &TrackMenuItem| match i {
+                TrackMenuItem::ChangeVolume => {
+                    format!(
+                        "Volume: {volume}",
+                        volume = ParamType::Decibel
+                            .formatted(self.bats_state.track_by_id(track_id).unwrap().volume)
+                    )
+                }
+                TrackMenuItem::ChangePlugin => "Change Plugin".to_string(),
+                TrackMenuItem::StreamSample => "Stream Sample".to_string(),
+                TrackMenuItem::SliceSample => "Slice Sample".to_string(),
+                TrackMenuItem::Slices => format!(
+                    "Slices: {count}",
+                    count = self.bats_state.track_by_id(track_id).unwrap().slice_count
+                ),
+                TrackMenuItem::Params => "Params".to_string(),
+                TrackMenuItem::Expression => "Expression".to_string(),
+                TrackMenuItem::Lfos => "LFOs".to_string(),
+                TrackMenuItem::MidiFilter => "MIDI Filter".to_string(),
+                TrackMenuItem::Tuning => format!(
+                    "Tuning: {name}",
+                    name = self.bats_state.track_by_id(track_id).unwrap().tuning.name()
+                ),
+                TrackMenuItem::Compressor => "Compressor".to_string(),
+                TrackMenuItem::Input => format!(
+                    "Input: {input}",
+                    input = match self.bats_state.track_by_id(track_id).unwrap().input {
+                        Some(input) => self.bats_state.track_by_id(input).unwrap().title(),
+                        None => "none".to_string(),
+                    }
+                ),
+                TrackMenuItem::MidiDestination => format!(
+                    "MIDI To: {destination}",
+                    destination = self
+                        .bats_state
+                        .track_by_id(track_id)
+                        .unwrap()
+                        .midi_destination
+                        .name()
+                ),
+                TrackMenuItem::LoopLength => {
+                    match self.bats_state.track_by_id(track_id).unwrap().loop_length {
+                        Some(length) if length.bar() == 1 => "Loop: 1 bar".to_string(),
+                        Some(length) => format!("Loop: {bars} bars", bars = length.bar()),
+                        None => "Loop: transport".to_string(),
+                    }
+                }
+                TrackMenuItem::Send(bus) => format!(
+                    "Send {bus_name}: {level}",
+                    bus_name = aux_bus_name(*bus),
+                    level = ParamType::Decibel
+                        .formatted(self.bats_state.track_by_id(track_id).unwrap().sends[*bus])
+                ),
+                TrackMenuItem::Name => "Name".to_string(),
+                TrackMenuItem::Color => format!(
+                    "Color: {color}",
+                    color = self
+                        .bats_state
+                        .track_by_id(track_id)
+                        .unwrap()
+                        .color
+                        .map(TrackColor::name)
+                        .unwrap_or("none")
+                ),
+                TrackMenuItem::CopyTo => "Copy To Track".to_string(),
+                TrackMenuItem::Freeze => {
+                    let frozen = self
+                        .bats_state
+                        .track_by_id(track_id)
+                        .is_some_and(|t| t.frozen.is_some());
+                    if frozen { "Unfreeze" } else { "Freeze" }.to_string()
+                }
+                TrackMenuItem::ClearSequence => "Clear Sequence".to_string(),
+                TrackMenuItem::ClearAutomation => "Clear Automation".to_string(),
+            })
+            .with_extra_event_handler(|event, action| match (action, event) {
+                (TrackMenuItem::ChangeVolume, events::Event::Left) => {
+                    self.bats_state
+                        .modify_track_volume(track_id, |v| v.volume / 1.05);
+                    MenuAction::Redraw
+                }
+                (TrackMenuItem::ChangeVolume, events::Event::Right) => {
+                    self.bats_state
+                        .modify_track_volume(track_id, |v| v.volume * 1.05);
+                    MenuAction::Redraw
+                }
+                (TrackMenuItem::Tuning, events::Event::Left) => {
+                    self.bats_state.set_tuning(track_id, Tuning::default());
+                    MenuAction::Redraw
+                }
+                (TrackMenuItem::MidiDestination, events::Event::Left | events::Event::Right) => {
+                    let offset = match event {
+                        events::Event::Left => MidiDestination::ALL.len() - 1,
+                        _ => 1,
+                    };
+                    self.bats_state.cycle_midi_destination(track_id, offset);
+                    MenuAction::Redraw
+                }
+                (TrackMenuItem::LoopLength, events::Event::Left) => {
+                    self.bats_state.modify_loop_length_bars(track_id, -1);
+                    MenuAction::Redraw
+                }
+                (TrackMenuItem::LoopLength, events::Event::Right) => {
+                    self.bats_state.modify_loop_length_bars(track_id, 1);
+                    MenuAction::Redraw
+                }
+                (TrackMenuItem::Send(bus), events::Event::Left) => {
+                    self.bats_state
+                        .modify_aux_send(track_id, *bus, decrease_gain);
+                    MenuAction::Redraw
+                }
+                (TrackMenuItem::Send(bus), events::Event::Right) => {
+                    self.bats_state
+                        .modify_aux_send(track_id, *bus, increase_gain);
+                    MenuAction::Redraw
+                }
+                _ => MenuAction::None,
+            })
+            .with_theme(self.theme)
+            .with_panel(12, |frame, area| {
+                let sequence = self
+                    .bats_state
+                    .track_by_id(track_id)
+                    .map(|t| t.sequence)
+                    .unwrap_or_default();
+                let loop_range = if self.bats_state.looping() {
+                    Some(self.bats_state.loop_range())
+                } else {
+                    None
+                };
+                let piano_roll = PianoRoll::new(sequence.notes(), self.bats_state.position())
+                    .with_loop_range(loop_range)
+                    .with_colors(self.theme.foreground, self.theme.highlight);
+                frame.render_widget(piano_roll, area);
+            });
+        loop {
+            menu.set_title(format!(
+                "Track - {}",
+                self.bats_state.track_by_id(track_id).unwrap().title()
+            ));
+            let selected = match menu.run(
+                &self.event_poll,
+                &mut self.terminal,
+                &StatusBar::new(&self.bats_state, self.theme),
+            )? {
+                Some(s) => s,
+                None => return Ok(()),
+            };
+            match selected {
+                TrackMenuItem::ChangePlugin => {
+                    if let Ok(Some(b)) = Self::select_plugin(
+                        format!(
+                            "Change Plugin for {}",
+                            self.bats_state.track_by_id(track_id).unwrap().title()
+                        ),
+                        self.theme,
+                        &self.bats_state,
+                        &self.event_poll,
+                        &mut self.terminal,
+                    ) {
+                        self.bats_state.load_plugin(track_id, b);
+                    }
+                }
+                TrackMenuItem::StreamSample => {
+                    let mut input =
+                        TextInput::new("Stream Sample".to_string(), String::new(), |text| {
+                            let path = PathBuf::from(text.trim());
+                            match path.extension() {
+                                Some(ext) if ext == "wav" => Ok(path),
+                                _ => Err(anyhow!("{text:?} must end with .wav.")),
+                            }
+                        })
+                        .with_theme(self.theme);
+                    if let Some(path) = input.run(
+                        &self.event_poll,
+                        &mut self.terminal,
+                        &StatusBar::new(&self.bats_state, self.theme),
+                    )? {
+                        self.bats_state.stream_sample(track_id, path);
+                    }
+                }
+                TrackMenuItem::SliceSample => {
+                    let mut input =
+                        TextInput::new("Slice Sample".to_string(), String::new(), |text| {
+                            let path = PathBuf::from(text.trim());
+                            match path.extension() {
+                                Some(ext) if ext == "wav" => Ok(path),
+                                _ => Err(anyhow!("{text:?} must end with .wav.")),
+                            }
+                        })
+                        .with_theme(self.theme);
+                    let Some(path) = input.run(
+                        &self.event_poll,
+                        &mut self.terminal,
+                        &StatusBar::new(&self.bats_state, self.theme),
+                    )?
+                    else {
+                        continue;
+                    };
+                    let modes = [
+                        SliceMode::Onsets,
+                        SliceMode::Grid(4),
+                        SliceMode::Grid(8),
+                        SliceMode::Grid(16),
+                        SliceMode::Grid(32),
+                    ];
+                    let mut menu =
+                        SelectorMenu::new("Slice By".to_string(), modes, |m: &SliceMode| match m {
+                            SliceMode::Onsets => "Onsets".to_string(),
+                            SliceMode::Grid(count) => format!("{count} Equal Slices"),
+                        })
+                        .with_theme(self.theme);
+                    if let Some(mode) = menu.run(
+                        &self.event_poll,
+                        &mut self.terminal,
+                        &StatusBar::new(&self.bats_state, self.theme),
+                    )? {
+                        self.bats_state.slice_sample(track_id, path, mode);
+                        Self::run_slices(
+                            self.theme,
+                            &self.event_poll,
+                            &mut self.terminal,
+                            &self.bats_state,
+                            track_id,
+                        )?;
+                    }
+                }
+                TrackMenuItem::Slices => Self::run_slices(
+                    self.theme,
+                    &self.event_poll,
+                    &mut self.terminal,
+                    &self.bats_state,
+                    track_id,
+                )?,
+                TrackMenuItem::ChangeVolume | TrackMenuItem::Send(_) => (),
+                TrackMenuItem::Params => Self::edit_params(
+                    self.theme,
+                    &self.event_poll,
+                    &mut self.terminal,
+                    &self.bats_state,
+                    self.presets_dir.as_deref(),
+                    track_id,
+                )?,
+                TrackMenuItem::Expression => Self::edit_expression_routes(
+                    self.theme,
+                    &self.event_poll,
+                    &mut self.terminal,
+                    &self.bats_state,
+                    track_id,
+                )?,
+                TrackMenuItem::Lfos => Self::edit_lfos(
+                    self.theme,
+                    &self.event_poll,
+                    &mut self.terminal,
+                    &self.bats_state,
+                    track_id,
+                )?,
+                TrackMenuItem::MidiFilter => Self::edit_midi_filter(
+                    self.theme,
+                    &self.event_poll,
+                    &mut self.terminal,
+                    &self.bats_state,
+                    track_id,
+                )?,
+                TrackMenuItem::Tuning => {
+                    let mut scl_input =
+                        TextInput::new("Scala Scale".to_string(), String::new(), |text| {
+                            let path = PathBuf::from(text.trim());
+                            match path.extension() {
+                                Some(ext) if ext == "scl" => Ok(path),
+                                _ => Err(anyhow!("{text:?} must end with .scl.")),
+                            }
+                        })
+                        .with_theme(self.theme);
+                    let Some(scl) = scl_input.run(
+                        &self.event_poll,
+                        &mut self.terminal,
+                        &StatusBar::new(&self.bats_state, self.theme),
+                    )?
+                    else {
+                        continue;
+                    };
+                    let mut kbm_input = TextInput::new(
+                        "Keyboard Mapping (empty for default)".to_string(),
+                        String::new(),
+                        |text| {
+                            if text.trim().is_empty() {
+                                return Ok(None);
+                            }
+                            let path = PathBuf::from(text.trim());
+                            match path.extension() {
+                                Some(ext) if ext == "kbm" => Ok(Some(path)),
+                                _ => Err(anyhow!("{text:?} must end with .kbm.")),
+                            }
+                        },
+                    )
+                    .with_theme(self.theme);
+                    if let Some(kbm) = kbm_input.run(
+                        &self.event_poll,
+                        &mut self.terminal,
+                        &StatusBar::new(&self.bats_state, self.theme),
+                    )? {
+                        self.bats_state.load_tuning(track_id, scl, kbm);
+                    }
+                }
+                TrackMenuItem::Compressor => Self::edit_compressor(
+                    format!(
+                        "{} Compressor",
+                        self.bats_state.track_by_id(track_id).unwrap().title()
+                    ),
+                    self.theme,
+                    &self.event_poll,
+                    &mut self.terminal,
+                    &self.bats_state,
+                    Some(track_id),
+                )?,
+                TrackMenuItem::Input => Self::run_track_input(
+                    self.theme,
+                    &self.event_poll,
+                    &mut self.terminal,
+                    &self.bats_state,
+                    track_id,
+                )?,
+                TrackMenuItem::MidiDestination => {
+                    self.bats_state.cycle_midi_destination(track_id, 1)
+                }
+                TrackMenuItem::LoopLength => self.bats_state.modify_loop_length_bars(track_id, 1),
+                TrackMenuItem::Name => {
+                    let name = self.bats_state.track_by_id(track_id).unwrap().name;
+                    let mut input = TextInput::new("Enter Track Name".to_string(), name, |text| {
+                        Ok(text.to_string())
+                    })
+                    .with_theme(self.theme);
+                    if let Some(name) = input.run(
+                        &self.event_poll,
+                        &mut self.terminal,
+                        &StatusBar::new(&self.bats_state, self.theme),
+                    )? {
+                        self.bats_state.set_track_name(track_id, &name);
+                    }
+                }
+                TrackMenuItem::Color => {
+                    let colors: Vec<_> = std::iter::once(None)
+                        .chain(TrackColor::ALL.iter().copied().map(Some))
+                        .collect();
+                    let mut menu = SelectorMenu::new(
+                        "Select Track Color".to_string(),
+                        colors,
+                        |c: &Option<TrackColor>| {
+                            c.map(TrackColor::name).unwrap_or("none").to_string()
+                        },
+                    )
+                    .with_theme(self.theme)
+                    .with_item_color(|c: &Option<TrackColor>| c.map(track_color));
+                    if let Some(color) = menu.run(
+                        &self.event_poll,
+                        &mut self.terminal,
+                        &StatusBar::new(&self.bats_state, self.theme),
+                    )? {
+                        self.bats_state.set_track_color(track_id, color);
+                    }
+                }
+                TrackMenuItem::CopyTo => Self::run_copy_track(
+                    self.theme,
+                    &self.event_poll,
+                    &mut self.terminal,
+                    &self.bats_state,
+                    track_id,
+                )?,
+                TrackMenuItem::Freeze => {
+                    let frozen = self
+                        .bats_state
+                        .track_by_id(track_id)
+                        .is_some_and(|t| t.frozen.is_some());
+                    if frozen {
+                        self.bats_state.unfreeze_track(track_id);
+                    } else {
+                        self.bats_state.freeze_track(track_id);
+                    }
+                }
+                TrackMenuItem::ClearSequence => {
+                    self.bats_state.set_sequence(track_id, Sequence::new());
+                    self.bats_state.set_loop_length(track_id, None);
+                }
+                TrackMenuItem::ClearAutomation => self.bats_state.clear_automation(track_id),
+            }
+        }
+    }
+
+    /// List the slices of the sampler of the track. Selecting a slice previews it.
+    fn run_slices(
+        theme: Theme,
+        event_poll: &EventPoll,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        bats_state: &BatsState,
+        track_id: usize,
+    ) -> Result<()> {
+        let track = bats_state.track_by_id(track_id).unwrap();
+        let slices: Vec<usize> = (0..track.slice_count).collect();
+        let mut menu = SelectorMenu::new(
+            format!("Slices - {}", track.title()),
+            slices,
+            |slice: &usize| {
+                format!(
+                    "Slice {number} ({note})",
+                    number = slice + 1,
+                    note = Sampler::slice_note(*slice)
+                )
+            },
+        )
+        .with_theme(theme);
+        while let Some(slice) =
+            menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))?
+        {
+            bats_state.preview_note(track_id, Sampler::slice_note(slice));
+        }
+        Ok(())
+    }
+
+    /// Select the track whose output is mixed into the track with `track_id`. Tracks that would
+    /// create a cycle are not listed.
+    fn run_track_input(
+        theme: Theme,
+        event_poll: &EventPoll,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        bats_state: &BatsState,
+        track_id: usize,
+    ) -> Result<()> {
+        let title = bats_state.track_by_id(track_id).unwrap().title();
+        let inputs: Vec<Option<TrackDetails>> = std::iter::once(None)
+            .chain(
+                bats_state
+                    .tracks_vec()
+                    .into_iter()
+                    .filter(|t| bats_state.can_set_track_input(track_id, t.id))
+                    .map(Some),
+            )
+            .collect();
+        let mut menu = SelectorMenu::new(
+            format!("Input for {title}"),
+            inputs,
+            |t: &Option<TrackDetails>| match t {
+                Some(t) => t.title(),
+                None => "none".to_string(),
+            },
+        )
+        .with_theme(theme)
+        .with_item_color(|t: &Option<TrackDetails>| {
+            t.as_ref().and_then(|t| t.color).map(track_color)
+        });
+        if let Some(input) = menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))? {
+            bats_state.set_track_input(track_id, input.map(|t| t.id));
+        }
+        Ok(())
+    }
+
+    /// Select a track and the contents of the track with `track_id` to copy onto it.
+    fn run_copy_track(
+        theme: Theme,
+        event_poll: &EventPoll,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        bats_state: &BatsState,
+        track_id: usize,
+    ) -> Result<()> {
+        let title = bats_state.track_by_id(track_id).unwrap().title();
+        let tracks: Vec<_> = bats_state
+            .tracks_vec()
+            .into_iter()
+            .filter(|t| t.id != track_id)
+            .collect();
+        let mut menu = SelectorMenu::new(format!("Copy {title} To"), tracks, |t: &TrackDetails| {
+            t.title()
+        })
+        .with_theme(theme)
+        .with_item_color(|t: &TrackDetails| t.color.map(track_color));
+        let target = match menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))? {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+        let mut menu = SelectorMenu::new(
+            format!("Copy From {title} To {}", target.title()),
+            TrackContents::ALL,
+            |c: &TrackContents| c.name().to_string(),
+        )
+        .with_theme(theme);
+        if let Some(what) = menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))? {
+            bats_state.copy_track(track_id, target.id, what);
+        }
+        Ok(())
+    }
+
+    /// Select a plugin and return it. The category is selected first and then the plugin within
+    /// the category. If the selection is canceled, then `Ok(None)` is returned.
+    fn select_plugin(
+        title: String,
+        theme: Theme,
+        bats_state: &BatsState,
+        event_poll: &EventPoll,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    ) -> Result<Option<PluginBuilder>> {
+        let categories: Vec<PluginCategory> = PluginCategory::ALL
+            .iter()
+            .copied()
+            .filter(|c| PluginBuilder::by_category(*c).next().is_some())
+            .collect();
+        let mut category_menu = SelectorMenu::new(title, categories, |c: &PluginCategory| {
+            format!(
+                "{name} ({count})",
+                name = c.name(),
+                count = PluginBuilder::by_category(*c).count()
+            )
+        })
+        .with_theme(theme);
+        while let Some(category) =
+            category_menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))?
+        {
+            let mut menu = SelectorMenu::new(
+                category.name().to_string(),
+                PluginBuilder::by_category(category).collect::<Vec<_>>(),
+                |b: &PluginBuilder| match b.metadata().tags {
+                    [] => b.name().to_string(),
+                    tags => format!("{name} [{tags}]", name = b.name(), tags = tags.join(", ")),
+                },
+            )
+            .with_theme(theme);
+            if let Some(b) = menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))? {
+                return Ok(Some(b));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Edit the params for the track with `track_id`. Page up and page down switch between the
+    /// param pages of the plugin. The params may be randomized, and if `presets_dir` is set, then
+    /// presets may also be saved and loaded. The params may be compared against a stored copy by
+    /// switching between A and B.
+    fn edit_params(
+        theme: Theme,
+        event_poll: &EventPoll,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        bats_state: &BatsState,
+        presets_dir: Option<&Path>,
+        track_id: usize,
+    ) -> Result<()> {
+        #[derive(Copy, Clone)]
+        enum Item {
+            Param(Param),
+            /// Move by the given number of pages.
+            Page(isize),
+            Randomize,
+            LockParams,
+            CompareAb,
+            SavePreset,
+            LoadPreset,
+        }
+        let track = bats_state.track_by_id(track_id).unwrap().clone();
+        let metadata = track.plugin_metadata;
+        let page_count = metadata.page_count();
+        let name_width = param_name_width(metadata.params);
+        let mut page = 0;
+        'pages: loop {
+            let title = match metadata.page_name(page) {
+                Some(name) => format!(
+                    "{track} Params - {name} ({number}/{page_count})",
+                    track = track.title(),
+                    number = page + 1,
+                ),
+                None => format!("{} Params", track.title()),
+            };
+            let mut items: Vec<Item> = metadata
+                .page_params(page)
+                .copied()
+                .map(Item::Param)
+                .collect();
+            if !metadata.params.is_empty() {
+                items.extend([Item::Randomize, Item::LockParams, Item::CompareAb]);
+            }
+            if presets_dir.is_some() {
+                items.extend([Item::SavePreset, Item::LoadPreset]);
+            }
+            if items.is_empty() {
+                return Ok(());
+            }
+            let mut menu = SelectorMenu::new(title, items, |i: &Item| match i {
+                Item::Param(p) => {
+                    let value = bats_state
+                        .track_by_id(track_id)
+                        .unwrap()
+                        .params
+                        .get(&p.id)
+                        .copied()
+                        .unwrap_or(0.0);
+                    param_row(p, value, name_width)
+                }
+                Item::Page(_) => String::new(),
+                Item::Randomize => "Randomize".to_string(),
+                Item::LockParams => "Lock Params".to_string(),
+                Item::CompareAb => match bats_state.ab_compare(track_id) {
+                    None => "Compare A/B: off".to_string(),
+                    Some(ab) if ab.is_b => "Compare A/B: B".to_string(),
+                    Some(_) => "Compare A/B: A".to_string(),
+                },
+                Item::SavePreset => "Save Preset".to_string(),
+                Item::LoadPreset => "Load Preset".to_string(),
+            })
+            .with_extra_event_handler(|event, item| match (event, item) {
+                (events::Event::Left, Item::Param(param)) => {
+                    bats_state.modify_param(track_id, param.id, |v| v / 1.05);
+                    MenuAction::Redraw
+                }
+                (events::Event::Right, Item::Param(param)) => {
+                    bats_state.modify_param(track_id, param.id, |v| v * 1.05);
+                    MenuAction::Redraw
+                }
+                (events::Event::Left, Item::CompareAb) => {
+                    bats_state.stop_ab(track_id);
+                    MenuAction::Redraw
+                }
+                (events::Event::ToggleAb, _) => {
+                    bats_state.toggle_ab(track_id);
+                    MenuAction::Redraw
+                }
+                (events::Event::PageUp, _) if page_count > 1 => MenuAction::Select(Item::Page(-1)),
+                (events::Event::PageDown, _) if page_count > 1 => MenuAction::Select(Item::Page(1)),
+                _ => MenuAction::None,
+            })
+            .with_theme(theme)
+            .with_color(theme.highlight);
+            while let Some(item) =
+                menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))?
+            {
+                match (item, presets_dir) {
+                    (Item::Param(param), _) => {
+                        let value = bats_state.param(track_id, param.id);
+                        let mut input = TextInput::new(
+                            format!("Enter {}", param.name),
+                            param.param_type.formatted(value).to_string(),
+                            |text| parse_param(&param, text),
+                        )
+                        .with_theme(theme);
+                        if let Some(v) =
+                            input.run(event_poll, terminal, &StatusBar::new(bats_state, theme))?
+                        {
+                            bats_state.modify_param(track_id, param.id, |_| v);
+                        }
+                    }
+                    (Item::Page(step), _) => {
+                        page = (page as isize + step).rem_euclid(page_count as isize) as usize;
+                        continue 'pages;
+                    }
+                    (Item::Randomize, _) => bats_state.randomize_params(track_id),
+                    (Item::LockParams, _) => {
+                        Self::edit_param_locks(theme, event_poll, terminal, bats_state, track_id)?
+                    }
+                    (Item::CompareAb, _) => bats_state.toggle_ab(track_id),
+                    (Item::SavePreset, Some(dir)) => {
+                        let mut input =
+                            TextInput::new("Save Preset As".to_string(), String::new(), |name| {
+                                let preset = bats_state
+                                    .preset(track_id, name.trim().to_string())
+                                    .ok_or_else(|| {
+                                    anyhow!("Track {track_id} has no plugin.")
+                                })?;
+                                preset.save(dir)
+                            })
+                            .with_theme(theme);
+                        if let Some(path) =
+                            input.run(event_poll, terminal, &StatusBar::new(bats_state, theme))?
+                        {
+                            info!("Saved preset to {path:?}.");
+                        }
+                    }
+                    (Item::LoadPreset, Some(dir)) => {
+                        let presets = match PluginBuilder::from_name(metadata.name) {
+                            Some(plugin) => Preset::load_all(dir, plugin),
+                            None => Vec::new(),
+                        };
+                        if presets.is_empty() {
+                            warn!("No presets found for {}.", metadata.name);
+                            continue;
+                        }
+                        let mut preset_menu = SelectorMenu::new(
+                            format!("Load Preset for {}", track.title()),
+                            presets,
+                            |p: &Preset| p.name.clone(),
+                        )
+                        .with_theme(theme);
+                        if let Some(preset) = preset_menu.run(
+                            event_poll,
+                            terminal,
+                            &StatusBar::new(bats_state, theme),
+                        )? {
+                            bats_state.load_preset(track_id, preset);
+                        }
+                    }
+                    (Item::SavePreset | Item::LoadPreset, None) => (),
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    /// Choose which params of the track with `track_id` are left unchanged when the params are
+    /// randomized.
+    fn edit_param_locks(
+        theme: Theme,
+        event_poll: &EventPoll,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        bats_state: &BatsState,
+        track_id: usize,
+    ) -> Result<()> {
+        let track = bats_state.track_by_id(track_id).unwrap().clone();
+        let params = track.plugin_metadata.params;
+        let name_width = param_name_width(params);
+        let mut menu = SelectorMenu::new(
+            format!("{} Locked Params", track.title()),
+            params.to_vec(),
+            |p: &Param| {
+                let locked = if bats_state.param_locked(track_id, p.id) {
+                    1.0
+                } else {
+                    0.0
+                };
+                format!(
+                    "{name:<name_width$}  {locked}",
+                    name = p.name,
+                    locked = ParamType::Bool.formatted(locked)
+                )
+            },
+        )
+        .with_extra_event_handler(|event, param| match event {
+            events::Event::Left => {
+                bats_state.set_param_locked(track_id, param.id, false);
+                MenuAction::Redraw
+            }
+            events::Event::Right => {
+                bats_state.set_param_locked(track_id, param.id, true);
+                MenuAction::Redraw
+            }
+            _ => MenuAction::None,
+        })
+        .with_theme(theme);
+        while let Some(param) =
+            menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))?
+        {
+            let locked = bats_state.param_locked(track_id, param.id);
+            bats_state.set_param_locked(track_id, param.id, !locked);
+        }
+        Ok(())
+    }
+
+    /// Edit the compressor for the track with `track_id` or for the master bus if `track_id` is
+    /// `None`. The params are only shown while the compressor is enabled.
+    fn edit_compressor(
+        title: String,
+        theme: Theme,
+        event_poll: &EventPoll,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        bats_state: &BatsState,
+        track_id: Option<usize>,
+    ) -> Result<()> {
+        #[derive(Copy, Clone)]
+        enum Item {
+            Enabled,
+            Param(Param),
+        }
+        let params = Compressor::new(bats_state.sample_rate()).metadata().params;
+        let name_width = param_name_width(params);
+        loop {
+            let enabled = bats_state.compressor(track_id).is_some();
+            let mut items = vec![Item::Enabled];
+            if enabled {
+                items.extend(params.iter().copied().map(Item::Param));
+            }
+            let mut menu = SelectorMenu::new(title.clone(), items, |i: &Item| match i {
+                Item::Enabled => format!("Enabled: {}", if enabled { "On" } else { "Off" }),
+                Item::Param(p) => {
+                    let value = bats_state
+                        .compressor(track_id)
+                        .and_then(|c| c.get(&p.id).copied())
+                        .unwrap_or(p.default_value);
+                    param_row(p, value, name_width)
+                }
+            })
+            .with_extra_event_handler(|event, item| match (event, item) {
+                (events::Event::Left, Item::Param(param)) => {
+                    bats_state.modify_compressor_param(track_id, param.id, |v| v / 1.05);
+                    MenuAction::Redraw
+                }
+                (events::Event::Right, Item::Param(param)) => {
+                    bats_state.modify_compressor_param(track_id, param.id, |v| v * 1.05);
+                    MenuAction::Redraw
+                }
+                _ => MenuAction::None,
+            })
+            .with_theme(theme);
+            match menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))? {
+                None => return Ok(()),
+                Some(Item::Enabled) => bats_state.set_compressor_enabled(track_id, !enabled),
+                Some(Item::Param(param)) => {
+                    let value = bats_state
+                        .compressor(track_id)
+                        .and_then(|c| c.get(&param.id).copied())
+                        .unwrap_or(param.default_value);
+                    let mut input = TextInput::new(
+                        format!("Enter {}", param.name),
+                        param.param_type.formatted(value).to_string(),
+                        |text| parse_param(&param, text),
+                    )
+                    .with_theme(theme);
+                    if let Some(v) =
+                        input.run(event_poll, terminal, &StatusBar::new(bats_state, theme))?
+                    {
+                        bats_state.modify_compressor_param(track_id, param.id, |_| v);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Edit the filter that is applied to the midi input of the track with `track_id`. Left and
+    /// right change the value and enter toggles on and off values.
+    fn edit_midi_filter(
+        theme: Theme,
+        event_poll: &EventPoll,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        bats_state: &BatsState,
+        track_id: usize,
+    ) -> Result<()> {
+        #[derive(Copy, Clone)]
+        enum Item {
+            IgnoreAftertouch,
+            IgnoreCc,
+            LowNote,
+            HighNote,
+            Transpose,
+            Layer,
+            VelocityCurve,
+            FixedVelocity,
+        }
+        let filter = || {
+            bats_state
+                .track_by_id(track_id)
+                .map(|t| t.midi_filter)
+                .unwrap_or_default()
+        };
+        let on_off = |enabled: bool| if enabled { "On" } else { "Off" };
+        let step = |item: Item, steps: i8| {
+            bats_state.modify_midi_filter(track_id, |f| match item {
+                Item::IgnoreAftertouch => MidiFilter {
+                    ignore_aftertouch: steps > 0,
+                    ..f
+                },
+                Item::IgnoreCc => MidiFilter {
+                    ignore_cc: steps > 0,
+                    ..f
+                },
+                Item::LowNote => MidiFilter {
+                    low_note: f
+                        .low_note
+                        .step(steps)
+                        .unwrap_or(f.low_note)
+                        .min(f.high_note),
+                    ..f
+                },
+                Item::HighNote => MidiFilter {
+                    high_note: f
+                        .high_note
+                        .step(steps)
+                        .unwrap_or(f.high_note)
+                        .max(f.low_note),
+                    ..f
+                },
+                Item::Transpose => MidiFilter {
+                    transpose: (f.transpose + steps)
+                        .clamp(-MidiFilter::MAX_TRANSPOSE, MidiFilter::MAX_TRANSPOSE),
+                    ..f
+                },
+                Item::Layer => MidiFilter {
+                    layer: steps > 0,
+                    ..f
+                },
+                Item::VelocityCurve => {
+                    let all = VelocityCurve::ALL;
+                    let current = all.iter().position(|c| *c == f.velocity_curve).unwrap_or(0);
+                    MidiFilter {
+                        velocity_curve: all[(current as isize + steps as isize)
+                            .rem_euclid(all.len() as isize)
+                            as usize],
+                        ..f
+                    }
+                }
+                Item::FixedVelocity => MidiFilter {
+                    fixed_velocity: Some(match f.fixed_velocity {
+                        Some(v) => U7::from_u8_lossy(
+                            (u8::from(v) as i16 + steps as i16).clamp(1, 127) as u8,
+                        ),
+                        None => MidiFilter::DEFAULT_FIXED_VELOCITY,
+                    }),
+                    ..f
+                },
+            })
+        };
+        let mut menu = SelectorMenu::new(
+            format!(
+                "{} MIDI Filter",
+                bats_state.track_by_id(track_id).unwrap().title()
+            ),
+            [
+                Item::IgnoreAftertouch,
+                Item::IgnoreCc,
+                Item::LowNote,
+                Item::HighNote,
+                Item::Transpose,
+                Item::Layer,
+                Item::VelocityCurve,
+                Item::FixedVelocity,
+            ],
+            |i: &Item| {
+                let f = filter();
+                match i {
+                    Item::IgnoreAftertouch => {
+                        format!("Ignore Aftertouch: {}", on_off(f.ignore_aftertouch))
+                    }
+                    Item::IgnoreCc => format!("Ignore CC: {}", on_off(f.ignore_cc)),
+                    Item::LowNote => format!("Lowest Note: {}", f.low_note),
+                    Item::HighNote => format!("Highest Note: {}", f.high_note),
+                    Item::Transpose => format!("Transpose: {:+} semitones", f.transpose),
+                    Item::Layer => format!("Layer With Armed Track: {}", on_off(f.layer)),
+                    Item::VelocityCurve => format!("Velocity Curve: {}", f.velocity_curve.name()),
+                    Item::FixedVelocity => match f.fixed_velocity {
+                        Some(v) => format!("Fixed Velocity: {}", u8::from(v)),
+                        None => "Fixed Velocity: Off".to_string(),
+                    },
+                }
+            },
+        )
+        .with_extra_event_handler(|event, item| match event {
+            events::Event::Left => {
+                step(*item, -1);
+                MenuAction::Redraw
+            }
+            events::Event::Right => {
+                step(*item, 1);
+                MenuAction::Redraw
+            }
+            _ => MenuAction::None,
+        })
+        .with_theme(theme);
+        while let Some(item) = menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))? {
+            let f = filter();
+            match item {
+                Item::IgnoreAftertouch => step(item, if f.ignore_aftertouch { -1 } else { 1 }),
+                Item::IgnoreCc => step(item, if f.ignore_cc { -1 } else { 1 }),
+                Item::Layer => step(item, if f.layer { -1 } else { 1 }),
+                Item::VelocityCurve => step(item, 1),
+                Item::FixedVelocity => match f.fixed_velocity {
+                    Some(_) => bats_state.modify_midi_filter(track_id, |f| MidiFilter {
+                        fixed_velocity: None,
+                        ..f
+                    }),
+                    None => step(item, 1),
+                },
+                Item::LowNote | Item::HighNote | Item::Transpose => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Edit the routes from midi expression controllers to params for the track with `track_id`.
+    /// Left and right change the param that the controller is routed to and enter sets the range
+    /// that the controller is scaled to.
+    fn edit_expression_routes(
+        theme: Theme,
+        event_poll: &EventPoll,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        bats_state: &BatsState,
+        track_id: usize,
+    ) -> Result<()> {
+        let track = bats_state.track_by_id(track_id).unwrap();
+        let params = track.plugin_metadata.params;
+        let cycle_param = |source: ExpressionSource, step: isize| {
+            let current = bats_state
+                .expression_route(track_id, source)
+                .and_then(|r| params.iter().position(|p| p.id == r.param_id))
+                .map(|idx| idx as isize + 1)
+                .unwrap_or(0);
+            // Index 0 means that the source is not routed.
+            let next = (current + step).rem_euclid(params.len() as isize + 1) as usize;
+            let route = next
+                .checked_sub(1)
+                .map(|idx| &params[idx])
+                .map(|p| ExpressionRoute {
+                    source,
+                    param_id: p.id,
+                    min: p.min_value,
+                    max: p.max_value,
+                });
+            bats_state.set_expression_route(track_id, source, route);
+        };
+        let mut menu = SelectorMenu::new(
+            format!("{} Expression", track.title()),
+            ExpressionSource::ALL,
+            |source: &ExpressionSource| {
+                let route = bats_state.expression_route(track_id, *source);
+                match route.and_then(|r| Some((r, track.plugin_metadata.param_by_id(r.param_id)?)))
+                {
+                    Some((r, p)) => format!(
+                        "{source}: {param} ({min} to {max})",
+                        source = source.name(),
+                        param = p.name,
+                        min = p.param_type.formatted(r.min),
+                        max = p.param_type.formatted(r.max),
+                    ),
+                    None => format!("{}: None", source.name()),
+                }
+            },
+        )
+        .with_extra_event_handler(|event, source| match event {
+            events::Event::Left => {
+                cycle_param(*source, -1);
+                MenuAction::Redraw
+            }
+            events::Event::Right => {
+                cycle_param(*source, 1);
+                MenuAction::Redraw
+            }
+            _ => MenuAction::None,
+        })
+        .with_theme(theme);
+        while let Some(source) =
+            menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))?
+        {
+            let route = match bats_state.expression_route(track_id, source) {
+                Some(r) => r,
+                None => continue,
+            };
+            let param = match track.plugin_metadata.param_by_id(route.param_id) {
+                Some(p) => p,
+                None => continue,
+            };
+            let mut input = TextInput::new(
+                format!("Enter {} Range for {}", param.name, source.name()),
+                format!(
+                    "{}, {}",
+                    param.param_type.formatted(route.min),
+                    param.param_type.formatted(route.max)
+                ),
+                |text| parse_param_range(param, text),
+            )
+            .with_theme(theme);
+            if let Some((min, max)) =
+                input.run(event_poll, terminal, &StatusBar::new(bats_state, theme))?
+            {
+                bats_state.set_expression_route(
+                    track_id,
+                    source,
+                    Some(ExpressionRoute { min, max, ..route }),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Edit the LFOs that modulate params for the track with `track_id`. Enter edits an LFO or
+    /// adds a new LFO for the first param.
+    fn edit_lfos(
+        theme: Theme,
+        event_poll: &EventPoll,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        bats_state: &BatsState,
+        track_id: usize,
+    ) -> Result<()> {
+        #[derive(Copy, Clone)]
+        enum Item {
+            Lfo(usize),
+            Add,
+        }
+        let track = bats_state.track_by_id(track_id).unwrap().clone();
+        let metadata = track.plugin_metadata;
+        loop {
+            let lfos = bats_state.lfos(track_id);
+            let mut items: Vec<Item> = (0..lfos.len()).map(Item::Lfo).collect();
+            if !metadata.params.is_empty() {
+                items.push(Item::Add);
+            }
+            let mut menu = SelectorMenu::new(
+                format!("{} LFOs", track.title()),
+                items,
+                |i: &Item| match i {
+                    Item::Lfo(idx) => lfo_name(metadata, &lfos[*idx]),
+                    Item::Add => "Add LFO".to_string(),
+                },
+            )
+            .with_theme(theme);
+            let idx = match menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))? {
+                None => return Ok(()),
+                Some(Item::Lfo(idx)) => idx,
+                Some(Item::Add) => {
+                    let param = &metadata.params[0];
+                    bats_state.modify_lfos(track_id, |lfos| {
+                        lfos.push(ParamLfo {
+                            param_id: param.id,
+                            waveform: LfoWaveform::default(),
+                            beats: 1.0,
+                            center: (param.min_value + param.max_value) / 2.0,
+                            depth: (param.max_value - param.min_value) / 2.0,
+                        })
+                    });
+                    lfos.len()
+                }
+            };
+            Self::edit_lfo(theme, event_poll, terminal, bats_state, track_id, idx)?;
+        }
+    }
+
+    /// Edit the LFO at index `idx` of the track with `track_id`. Left and right change the param,
+    /// waveform, and rate. Enter sets the center and depth.
+    fn edit_lfo(
+        theme: Theme,
+        event_poll: &EventPoll,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        bats_state: &BatsState,
+        track_id: usize,
+        idx: usize,
+    ) -> Result<()> {
+        #[derive(Copy, Clone)]
+        enum Item {
+            Param,
+            Waveform,
+            Rate,
+            Center,
+            Depth,
+            Remove,
+        }
+        let track = bats_state.track_by_id(track_id).unwrap().clone();
+        let params = track.plugin_metadata.params;
+        let lfo = || bats_state.lfos(track_id).get(idx).copied();
+        let modify = |f: &dyn Fn(&mut ParamLfo)| {
+            bats_state.modify_lfos(track_id, |lfos| {
+                if let Some(lfo) = lfos.get_mut(idx) {
+                    f(lfo);
+                }
+            })
+        };
+        let param = |lfo: &ParamLfo| params.iter().find(|p| p.id == lfo.param_id).copied();
+        let cycle_param = |step: isize| {
+            modify(&|lfo| {
+                let current = params
+                    .iter()
+                    .position(|p| p.id == lfo.param_id)
+                    .unwrap_or(0);
+                let next =
+                    &params[(current as isize + step).rem_euclid(params.len() as isize) as usize];
+                *lfo = ParamLfo {
+                    param_id: next.id,
+                    center: (next.min_value + next.max_value) / 2.0,
+                    depth: (next.max_value - next.min_value) / 2.0,
+                    ..*lfo
+                };
+            })
+        };
+        let cycle_waveform = |step: isize| {
+            modify(&|lfo| {
+                let all = LfoWaveform::ALL;
+                let current = all.iter().position(|w| *w == lfo.waveform).unwrap_or(0);
+                lfo.waveform =
+                    all[(current as isize + step).rem_euclid(all.len() as isize) as usize];
+            })
+        };
+        let mut menu = SelectorMenu::new(
+            format!("{} LFO {}", track.title(), idx + 1),
+            [
+                Item::Param,
+                Item::Waveform,
+                Item::Rate,
+                Item::Center,
+                Item::Depth,
+                Item::Remove,
+            ],
+            |i: &Item| {
+                let lfo = match lfo() {
+                    Some(l) => l,
+                    None => return String::new(),
+                };
+                let param_type = param(&lfo).map(|p| p.param_type).unwrap_or_default();
+                match i {
+                    Item::Param => format!(
+                        "Param: {}",
+                        param(&lfo).map(|p| p.name).unwrap_or("Unknown")
+                    ),
+                    Item::Waveform => format!("Waveform: {}", lfo.waveform.name()),
+                    Item::Rate => format!("Rate: {}", beats_name(lfo.beats)),
+                    Item::Center => format!("Center: {}", param_type.formatted(lfo.center)),
+                    Item::Depth => format!("Depth: {}", param_type.formatted(lfo.depth)),
+                    Item::Remove => "Remove".to_string(),
+                }
+            },
+        )
+        .with_extra_event_handler(|event, item| match (event, item) {
+            (events::Event::Left, Item::Param) => {
+                cycle_param(-1);
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::Param) => {
+                cycle_param(1);
+                MenuAction::Redraw
+            }
+            (events::Event::Left, Item::Waveform) => {
+                cycle_waveform(-1);
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::Waveform) => {
+                cycle_waveform(1);
+                MenuAction::Redraw
+            }
+            (events::Event::Left, Item::Rate) => {
+                modify(&|lfo| lfo.beats = (lfo.beats / 2.0).max(ParamLfo::MIN_BEATS));
+                MenuAction::Redraw
+            }
+            (events::Event::Right, Item::Rate) => {
+                modify(&|lfo| lfo.beats = (lfo.beats * 2.0).min(ParamLfo::MAX_BEATS));
+                MenuAction::Redraw
+            }
+            _ => MenuAction::None,
+        })
+        .with_theme(theme);
+        while let Some(item) = menu.run(event_poll, terminal, &StatusBar::new(bats_state, theme))? {
+            let (lfo, param) = match lfo().and_then(|l| Some((l, param(&l)?))) {
+                Some(v) => v,
+                None => return Ok(()),
+            };
+            let (name, value) = match item {
+                Item::Param | Item::Waveform | Item::Rate => continue,
+                Item::Center => ("Center", lfo.center),
+                Item::Depth => ("Depth", lfo.depth),
+                Item::Remove => {
+                    bats_state.modify_lfos(track_id, |lfos| {
+                        lfos.remove(idx);
+                    });
+                    return Ok(());
+                }
+            };
+            let mut input = TextInput::new(
+                format!("Enter LFO {name} for {}", param.name),
+                param.param_type.formatted(value).to_string(),
+                |text| match item {
+                    Item::Center => parse_param(&param, text),
+                    _ => param
+                        .param_type
+                        .parse(text)
+                        .ok_or_else(|| anyhow!("{text:?} is not a valid value.")),
+                },
+            )
+            .with_theme(theme);
+            if let Some(v) = input.run(event_poll, terminal, &StatusBar::new(bats_state, theme))? {
+                match item {
+                    Item::Center => modify(&|lfo| lfo.center = v),
+                    _ => modify(&|lfo| lfo.depth = v.abs()),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The human readable name of an LFO that shows the param, waveform, and rate.
+fn lfo_name(metadata: &Metadata, lfo: &ParamLfo) -> String {
+    format!(
+        "{param}: {waveform} every {beats}",
+        param = metadata
+            .param_by_id(lfo.param_id)
+            .map(|p| p.name)
+            .unwrap_or("Unknown"),
+        waveform = lfo.waveform.name(),
+        beats = beats_name(lfo.beats),
+    )
+}
+
+/// The human readable length of `beats`, such as `"1/4 beat"` or `"2 beats"`.
+fn beats_name(beats: f32) -> String {
+    match beats {
+        b if b < 1.0 => format!("1/{} beat", (1.0 / b).round()),
+        1.0 => "1 beat".to_string(),
+        b => format!("{b} beats"),
+    }
+}
+
+/// The param used to enter the range of track volume macro targets.
+const VOLUME_PARAM: Param = Param {
+    id: 0,
+    name: "volume",
+    param_type: ParamType::Decibel,
+    default_value: 1.0,
+    min_value: 0.0,
+    max_value: 4.0,
+};
+
+/// The human readable name of a macro knob's midi CC.
+fn cc_name(cc: Option<ControlFunction>) -> String {
+    match cc {
+        Some(cc) => format!("CC {}", u8::from(cc.0)),
+        None => "No CC".to_string(),
+    }
+}
+
+/// The human readable description of a macro target and its range.
+fn macro_mapping_name(bats_state: &BatsState, mapping: &MacroMapping) -> String {
+    let (track_id, param) = match mapping.target {
+        MacroTarget::Param { track_id, param_id } => (
+            track_id,
+            bats_state
+                .track_by_id(track_id)
+                .and_then(|t| t.plugin_metadata.param_by_id(param_id).copied()),
+        ),
+        MacroTarget::TrackVolume { track_id } => (track_id, Some(VOLUME_PARAM)),
+    };
+    match param {
+        Some(p) => format!(
+            "Track {track}: {name} ({min} to {max})",
+            track = track_id + 1,
+            name = p.name,
+            min = p.param_type.formatted(mapping.min),
+            max = p.param_type.formatted(mapping.max),
+        ),
+        None => format!("Track {}: unknown param", track_id + 1),
+    }
+}
+
+/// Parse a duration such as `"10ms"` or `"0.5s"`. Plain numbers are in seconds.
+fn parse_duration(text: &str) -> Result<f32> {
+    match ParamType::Duration.parse(text) {
+        Some(seconds) if seconds >= 0.0 => Ok(seconds),
+        _ => Err(anyhow!("{text:?} is not a valid duration.")),
+    }
+}
+
+/// Parse a midi CC number such as `"20"`, or `"none"` for no CC.
+fn parse_cc(text: &str) -> Result<Option<ControlFunction>> {
+    let text = text.trim().to_lowercase();
+    if text.is_empty() || text == "none" {
+        return Ok(None);
+    }
+    match text.parse::<u8>() {
+        Ok(n) if n <= u8::from(U7::MAX) => Ok(Some(ControlFunction(U7::from_u8_lossy(n)))),
+        _ => Err(anyhow!(
+            "{text:?} is not a valid CC number between 0 and 127."
+        )),
+    }
+}
+
+/// The human readable name of the aux bus.
+fn aux_bus_name(bus: usize) -> String {
+    format!("Aux {}", bus + 1)
+}
+
+/// The smallest gain above silence that `increase_gain` and `decrease_gain` step through.
+const MIN_GAIN: f32 = 1.0 / 1024.0;
+
+/// Increase `gain` by 3 dB, or from silence to `MIN_GAIN`.
+fn increase_gain(gain: f32) -> f32 {
+    if gain < MIN_GAIN {
+        MIN_GAIN
+    } else {
+        gain * 2f32.sqrt()
+    }
+}
+
+/// Decrease `gain` by 3 dB, or to silence once it is at `MIN_GAIN`.
+fn decrease_gain(gain: f32) -> f32 {
+    if gain <= MIN_GAIN {
+        0.0
+    } else {
+        gain / 2f32.sqrt()
+    }
+}
+
+/// The width of the widest name in `params`.
+fn param_name_width(params: &[Param]) -> usize {
+    params.iter().map(|p| p.name.len()).max().unwrap_or(0)
+}
+
+/// Format `param` as columns with the name padded to `name_width`, a bar that shows where `value`
+/// is within the range of the param, and the value.
+fn param_row(param: &Param, value: f32, name_width: usize) -> String {
+    const BAR_WIDTH: usize = 20;
+    let filled = (param.normalized(value) * BAR_WIDTH as f32).round() as usize;
+    format!(
+        "{name:<name_width$}  {filled}{empty}  {value}",
+        name = param.name,
+        filled = "█".repeat(filled),
+        empty = "░".repeat(BAR_WIDTH - filled),
+        value = param.param_type.formatted(value),
+    )
+}
+
+/// Parse a range for `param` such as `"100Hz, 2kHz"`. Both values must be within the param's min
+/// and max values.
+fn parse_param_range(param: &Param, text: &str) -> Result<(f32, f32)> {
+    let (min, max) = text
+        .split_once(',')
+        .ok_or_else(|| anyhow!("Range must be two values separated by a comma."))?;
+    Ok((parse_param(param, min)?, parse_param(param, max)?))
+}
+
+/// Parse a BPM value such as `"117.5"` or `"117.5 BPM"`.
+fn parse_bpm(text: &str) -> Result<f32> {
+    let text = text.trim().to_lowercase();
+    let bpm: f32 = text
+        .trim_end_matches("bpm")
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("{text:?} is not a valid BPM."))?;
+    let range = BatsState::BPM_RANGE;
+    if !range.contains(&bpm) {
+        return Err(anyhow!(
+            "BPM must be between {min} and {max}.",
+            min = range.start(),
+            max = range.end()
+        ));
+    }
+    Ok(bpm)
+}
+
+/// Parse a value for `param` and ensure it is within the param's min and max values.
+fn parse_param(param: &Param, text: &str) -> Result<f32> {
+    let value = param
+        .param_type
+        .parse(text)
+        .ok_or_else(|| anyhow!("{text:?} is not a valid value for {}.", param.name))?;
+    if !(param.min_value..=param.max_value).contains(&value) {
+        return Err(anyhow!(
+            "{name} must be between {min} and {max}.",
+            name = param.name,
+            min = param.param_type.formatted(param.min_value),
+            max = param.param_type.formatted(param.max_value),
+        ));
+    }
+    Ok(value)
 }
 
 impl Drop for Ui {
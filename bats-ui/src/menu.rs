@@ -1,13 +1,20 @@
 use anyhow::Result;
 use ratatui::{
-    prelude::Alignment,
+    prelude::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Style},
-    widgets, Frame, Terminal,
+    widgets::{
+        self,
+        block::{Position, Title},
+        ListState,
+    },
+    Frame, Terminal,
 };
 
 use crate::{
     events::{Event, EventPoll},
     selector::Selector,
+    status_bar::StatusBar,
+    theme::Theme,
 };
 
 /// A menu action to perform.
@@ -30,8 +37,19 @@ pub trait Menu {
     /// Handle a user event and return the menu action that should be performed.
     fn handle_event(&mut self, event: Event) -> Result<MenuAction<Self::Item>>;
 
-    /// Draw the menu. Typically called at the start of run and whenever redraw is requested.
-    fn draw(&mut self, frame: &mut Frame);
+    /// Draw the menu within `area`. Typically called at the start of run and whenever redraw is
+    /// requested.
+    fn draw(&mut self, frame: &mut Frame, area: Rect);
+
+    /// Draw the menu with `status_bar` below it.
+    fn draw_with_status_bar(&mut self, frame: &mut Frame, status_bar: &StatusBar) {
+        let areas = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(StatusBar::HEIGHT)])
+            .split(frame.size());
+        self.draw(frame, areas[0]);
+        status_bar.draw(frame, areas[1]);
+    }
 
     /// Run the menu. Typically, the default implementation should be used as this is the main
     /// helper the trait provides.
@@ -39,16 +57,21 @@ pub trait Menu {
         &mut self,
         event_poll: &EventPoll,
         terminal: &mut Terminal<T>,
+        status_bar: &StatusBar,
     ) -> Result<Option<Self::Item>> {
-        terminal.draw(|f| self.draw(f))?;
+        terminal.draw(|f| self.draw_with_status_bar(f, status_bar))?;
         for event_or_err in event_poll.iter() {
             let event = event_or_err?;
+            if status_bar.handle_global_event(event) {
+                terminal.draw(|f| self.draw_with_status_bar(f, status_bar))?;
+                continue;
+            }
             match self.handle_event(event)? {
                 MenuAction::None => (),
                 MenuAction::Select(item) => return Ok(Some(item)),
                 MenuAction::Exit => return Ok(None),
                 MenuAction::Redraw => {
-                    terminal.draw(|f| self.draw(f))?;
+                    terminal.draw(|f| self.draw_with_status_bar(f, status_bar))?;
                 }
             }
         }
@@ -59,13 +82,26 @@ pub trait Menu {
 /// A function that handles events for a selector.
 type SelectorEventHandler<'a, T> = dyn 'a + FnMut(Event, &T) -> MenuAction<T>;
 
+/// A function that returns the color of an item in a selector or `None` to use the menu color.
+type SelectorItemColor<'a, T> = dyn 'a + Fn(&T) -> Option<Color>;
+
+/// A function that draws an extra panel below a selector menu.
+type SelectorPanelDrawer<'a> = dyn 'a + FnMut(&mut Frame, Rect);
+
 /// A basic menu that selects an item of type `T`.
 pub struct SelectorMenu<'a, T, F, A: AsRef<[T]>> {
     title: String,
     selection: Selector<T, A>,
     formatter: F,
     extra_event_handler: Box<SelectorEventHandler<'a, T>>,
+    item_color: Box<SelectorItemColor<'a, T>>,
     color: Color,
+    background: Color,
+    panel: Option<(u16, Box<SelectorPanelDrawer<'a>>)>,
+    /// The text typed to filter the items. Only items whose text contains the filter are shown.
+    filter: String,
+    /// The scroll state of the list.
+    list_state: ListState,
 }
 
 impl<'a, T, F, A: AsRef<[T]>> SelectorMenu<'a, T, F, A> {
@@ -77,33 +113,112 @@ impl<'a, T, F, A: AsRef<[T]>> SelectorMenu<'a, T, F, A> {
             selection: Selector::new(items),
             formatter,
             extra_event_handler: Box::new(|_, _| MenuAction::None),
+            item_color: Box::new(|_| None),
             color: Color::White,
+            background: Color::Black,
+            panel: None,
+            filter: String::new(),
+            list_state: ListState::default(),
         }
     }
 
     /// Add an extra handler. Allows doing extra actions with unused user input. Typically, the only
-    /// user input that `SelectorMenu` uses are the up/down arrow keys, exit, and enter.
+    /// user input that `SelectorMenu` uses are the up/down arrow keys, exit, enter, and typed
+    /// characters for filtering. The handler is not called if no items match the filter.
     pub fn with_extra_event_handler<'b>(
         self,
         handler: impl 'b + FnMut(Event, &T) -> MenuAction<T>,
-    ) -> SelectorMenu<'b, T, F, A> {
+    ) -> SelectorMenu<'b, T, F, A>
+    where
+        'a: 'b,
+    {
         SelectorMenu {
             extra_event_handler: Box::new(handler),
             ..self
         }
     }
 
+    /// Set the color of individual items. Items where `item_color` returns `None` use the menu
+    /// color.
+    pub fn with_item_color<'b>(
+        self,
+        item_color: impl 'b + Fn(&T) -> Option<Color>,
+    ) -> SelectorMenu<'b, T, F, A>
+    where
+        'a: 'b,
+    {
+        SelectorMenu {
+            item_color: Box::new(item_color),
+            ..self
+        }
+    }
+
     /// Set the color of the menu.
     pub fn with_color(self, color: Color) -> Self {
         SelectorMenu { color, ..self }
     }
 
+    /// Add a panel with `height` rows below the menu. `draw` is called with the area of the panel
+    /// whenever the menu is drawn.
+    pub fn with_panel<'b>(
+        self,
+        height: u16,
+        draw: impl 'b + FnMut(&mut Frame, Rect),
+    ) -> SelectorMenu<'b, T, F, A>
+    where
+        'a: 'b,
+    {
+        SelectorMenu {
+            panel: Some((height, Box::new(draw))),
+            ..self
+        }
+    }
+
+    /// Set the colors of the menu from `theme`.
+    pub fn with_theme(self, theme: Theme) -> Self {
+        SelectorMenu {
+            color: theme.foreground,
+            background: theme.background,
+            ..self
+        }
+    }
+
+    /// Set the colors of the menu from `theme`.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.color = theme.foreground;
+        self.background = theme.background;
+    }
+
     /// Set the title.
     pub fn set_title(&mut self, title: String) {
         self.title = title;
     }
 }
 
+impl<'a, T, F: Fn(&T) -> String, A: AsRef<[T]>> SelectorMenu<'a, T, F, A> {
+    /// Show only the items whose text contains the filter, ignoring case.
+    fn apply_filter(&mut self) {
+        let filter = self.filter.to_lowercase();
+        let formatter = &self.formatter;
+        self.selection
+            .filter(|item| formatter(item).to_lowercase().contains(&filter));
+    }
+
+    /// The text that shows the position of the selected item and the number of items.
+    fn count_text(&self) -> String {
+        let position = self.selection.selected_index().map_or(0, |idx| idx + 1);
+        let len = self.selection.visible_len();
+        if self.filter.is_empty() {
+            format!("{position}/{len}")
+        } else {
+            format!(
+                "{position}/{len} of {total}",
+                total = self.selection.total()
+            )
+        }
+    }
+}
+
 impl<'a, T: Clone, F: Fn(&T) -> String, A: AsRef<[T]>> Menu for SelectorMenu<'a, T, F, A> {
     type Item = T;
 
@@ -117,35 +232,89 @@ impl<'a, T: Clone, F: Fn(&T) -> String, A: AsRef<[T]>> Menu for SelectorMenu<'a,
                 self.selection.select_by(1);
                 MenuAction::Redraw
             }
+            Event::Back if !self.filter.is_empty() => {
+                self.filter.clear();
+                self.apply_filter();
+                MenuAction::Redraw
+            }
             Event::Back => MenuAction::Exit,
-            Event::Enter => MenuAction::Select(self.selection.selected().clone()),
+            Event::Enter => match self.selection.selected() {
+                Some(item) => MenuAction::Select(item.clone()),
+                None => MenuAction::None,
+            },
             Event::Redraw => MenuAction::Redraw,
-            other => (self.extra_event_handler)(other, self.selection.selected()),
+            Event::Char(c) => {
+                self.filter.push(c);
+                self.apply_filter();
+                MenuAction::Redraw
+            }
+            Event::Backspace => match self.filter.pop() {
+                Some(_) => {
+                    self.apply_filter();
+                    MenuAction::Redraw
+                }
+                None => MenuAction::None,
+            },
+            other => match self.selection.selected() {
+                Some(item) => (self.extra_event_handler)(other, item),
+                None => MenuAction::None,
+            },
         };
         Ok(action)
     }
 
-    fn draw(&mut self, frame: &mut Frame) {
+    fn draw(&mut self, frame: &mut Frame, area: Rect) {
         let items: Vec<_> = self
             .selection
             .iter()
             .map(|(selected, item)| {
                 let selected = if selected { ">>" } else { "  " };
                 let item_text = (self.formatter)(item);
-                widgets::ListItem::new(format!("{selected} {item_text}"))
+                let list_item = widgets::ListItem::new(format!("{selected} {item_text}"));
+                match (self.item_color)(item) {
+                    Some(color) => list_item.style(Style::default().fg(color)),
+                    None => list_item,
+                }
             })
             .collect();
-        frame.render_widget(
+        let (menu_area, panel_area) = match self.panel {
+            None => (area, None),
+            Some((height, _)) => {
+                let areas = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(0), Constraint::Length(height)])
+                    .split(area);
+                (areas[0], Some(areas[1]))
+            }
+        };
+        let mut block = widgets::Block::default()
+            .title(self.title.as_str())
+            .title_alignment(Alignment::Center)
+            .title(
+                Title::from(self.count_text())
+                    .position(Position::Bottom)
+                    .alignment(Alignment::Right),
+            )
+            .borders(widgets::Borders::ALL)
+            .border_type(widgets::BorderType::Rounded);
+        if !self.filter.is_empty() {
+            block = block.title(
+                Title::from(format!("Filter: {}", self.filter))
+                    .position(Position::Bottom)
+                    .alignment(Alignment::Left),
+            );
+        }
+        // The list state scrolls the viewport so that the selected item is always visible.
+        self.list_state.select(self.selection.selected_index());
+        frame.render_stateful_widget(
             widgets::List::new(items)
-                .block(
-                    widgets::Block::default()
-                        .title(self.title.as_str())
-                        .title_alignment(Alignment::Center)
-                        .borders(widgets::Borders::ALL)
-                        .border_type(widgets::BorderType::Rounded),
-                )
-                .style(Style::default().fg(self.color).bg(Color::Black)),
-            frame.size(),
-        )
+                .block(block)
+                .style(Style::default().fg(self.color).bg(self.background)),
+            menu_area,
+            &mut self.list_state,
+        );
+        if let (Some((_, draw)), Some(area)) = (self.panel.as_mut(), panel_area) {
+            draw(frame, area);
+        }
     }
 }
@@ -0,0 +1,82 @@
+use ratatui::{
+    prelude::{Buffer, Rect},
+    style::Color,
+    symbols::Marker,
+    widgets::{
+        canvas::{Canvas, Line},
+        Block, Borders, Widget,
+    },
+};
+
+/// Draws the recent master output as a waveform. Each point is drawn as a vertical line from its
+/// lowest to its highest sample.
+pub struct Oscilloscope<'a> {
+    /// The `(lowest, highest)` sample for each point, oldest first.
+    points: &'a [(f32, f32)],
+    /// The number of points that fit across the width.
+    capacity: usize,
+    /// The color for the waveform.
+    color: Color,
+    /// The color for points that clip.
+    clip_color: Color,
+}
+
+impl<'a> Oscilloscope<'a> {
+    /// Create a new `Oscilloscope` that draws `points` right aligned within a width of `capacity`
+    /// points.
+    pub fn new(points: &'a [(f32, f32)], capacity: usize) -> Oscilloscope<'a> {
+        Oscilloscope {
+            points,
+            capacity: capacity.max(points.len()),
+            color: Color::White,
+            clip_color: Color::Red,
+        }
+    }
+
+    /// Set the color for the waveform.
+    pub fn with_color(self, color: Color) -> Oscilloscope<'a> {
+        Oscilloscope { color, ..self }
+    }
+
+    /// The title that summarizes the peak of the points.
+    fn title(&self) -> String {
+        let peak = self
+            .points
+            .iter()
+            .map(|(low, high)| low.abs().max(high.abs()))
+            .fold(0.0, f32::max);
+        if peak >= 1.0 {
+            format!(
+                "Oscilloscope - CLIPPING - peak {:+.1} dB",
+                20.0 * peak.log10()
+            )
+        } else if peak == 0.0 {
+            "Oscilloscope - silent".to_string()
+        } else {
+            format!("Oscilloscope - peak {:+.1} dB", 20.0 * peak.log10())
+        }
+    }
+}
+
+impl<'a> Widget for Oscilloscope<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let offset = self.capacity - self.points.len();
+        Canvas::default()
+            .block(Block::default().title(self.title()).borders(Borders::ALL))
+            .marker(Marker::Braille)
+            .x_bounds([0.0, self.capacity as f64])
+            .y_bounds([-1.0, 1.0])
+            .paint(|ctx| {
+                for (idx, (low, high)) in self.points.iter().enumerate() {
+                    let x = (offset + idx) as f64;
+                    let color = if low.abs() >= 1.0 || high.abs() >= 1.0 {
+                        self.clip_color
+                    } else {
+                        self.color
+                    };
+                    ctx.draw(&Line::new(x, *low as f64, x, *high as f64, color));
+                }
+            })
+            .render(area, buf);
+    }
+}
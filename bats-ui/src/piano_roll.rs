@@ -0,0 +1,148 @@
+use std::ops::Range;
+
+use bats_dsp::position::Position;
+use bats_lib::{sequence, transport::Transport};
+use bmidi::Note;
+use ratatui::{
+    prelude::{Buffer, Rect},
+    style::Color,
+    symbols::Marker,
+    widgets::{
+        canvas::{Canvas, Line},
+        Block, Borders, Widget,
+    },
+};
+
+/// A read only view of a midi sequence where notes are drawn against beats.
+pub struct PianoRoll<'a> {
+    /// The notes to draw.
+    notes: &'a [sequence::Note],
+    /// The current position of the transport.
+    playhead: Position,
+    /// The region that the transport loops within or `None` if the transport does not loop.
+    loop_range: Option<Range<Position>>,
+    /// The color for notes.
+    note_color: Color,
+    /// The color for the playhead.
+    playhead_color: Color,
+}
+
+/// A note with a start and end in beats.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct NoteSpan {
+    /// The note.
+    note: Note,
+    /// The beat that the note starts on.
+    start: f64,
+    /// The beat that the note ends on.
+    end: f64,
+}
+
+impl<'a> PianoRoll<'a> {
+    /// Create a new `PianoRoll` for `notes`.
+    pub fn new(notes: &'a [sequence::Note], playhead: Position) -> PianoRoll<'a> {
+        PianoRoll {
+            notes,
+            playhead,
+            loop_range: Some(Position::MIN..Position::new(Transport::LOOP_BEATS as f64)),
+            note_color: Color::White,
+            playhead_color: Color::Blue,
+        }
+    }
+
+    /// Set the colors for notes and the playhead.
+    pub fn with_colors(self, note_color: Color, playhead_color: Color) -> PianoRoll<'a> {
+        PianoRoll {
+            note_color,
+            playhead_color,
+            ..self
+        }
+    }
+
+    /// Set the region that the transport loops within. `None` draws the whole sequence.
+    pub fn with_loop_range(self, loop_range: Option<Range<Position>>) -> PianoRoll<'a> {
+        PianoRoll { loop_range, ..self }
+    }
+
+    /// The range of beats to draw.
+    fn x_bounds(&self, spans: &[NoteSpan]) -> [f64; 2] {
+        match &self.loop_range {
+            Some(r) => [r.start.as_beats_f64(), r.end.as_beats_f64()],
+            None => {
+                let end = spans
+                    .iter()
+                    .map(|s| s.end)
+                    .fold(self.playhead.as_beats_f64(), f64::max);
+                [0.0, end.max(Transport::LOOP_BEATS as f64)]
+            }
+        }
+    }
+
+    /// Convert the notes into spans. Notes that end past the end of the loop are split into a
+    /// span that ends at the loop end and a span that starts at the loop start.
+    fn note_spans(&self) -> Vec<NoteSpan> {
+        let mut spans = Vec::with_capacity(self.notes.len());
+        for note in self.notes {
+            let start = note.start.as_beats_f64();
+            let end = start + note.length.as_beats_f64();
+            let pitch = note.pitch;
+            let (loop_start, loop_end) = match &self.loop_range {
+                Some(r) => (r.start.as_beats_f64(), r.end.as_beats_f64()),
+                None => (0.0, f64::INFINITY),
+            };
+            if start < loop_end && end > loop_end {
+                spans.push(NoteSpan {
+                    note: pitch,
+                    start,
+                    end: loop_end,
+                });
+                spans.push(NoteSpan {
+                    note: pitch,
+                    start: loop_start,
+                    end: loop_start + end - loop_end,
+                });
+            } else {
+                spans.push(NoteSpan {
+                    note: pitch,
+                    start,
+                    end,
+                });
+            }
+        }
+        spans
+    }
+}
+
+impl<'a> Widget for PianoRoll<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let spans = self.note_spans();
+        let (low, high) = spans
+            .iter()
+            .map(|s| u8::from(s.note))
+            .fold(None, |acc: Option<(u8, u8)>, n| match acc {
+                None => Some((n, n)),
+                Some((low, high)) => Some((low.min(n), high.max(n))),
+            })
+            .unwrap_or((u8::from(Note::C3), u8::from(Note::C5)));
+        let playhead = self.playhead.as_beats_f64();
+        Canvas::default()
+            .block(Block::default().title("Sequence").borders(Borders::ALL))
+            .marker(Marker::Braille)
+            .x_bounds(self.x_bounds(&spans))
+            .y_bounds([low as f64 - 1.0, high as f64 + 1.0])
+            .paint(|ctx| {
+                for span in spans.iter() {
+                    let y = u8::from(span.note) as f64;
+                    ctx.draw(&Line::new(span.start, y, span.end, y, self.note_color));
+                }
+                ctx.draw(&Line::new(
+                    playhead,
+                    low as f64 - 1.0,
+                    playhead,
+                    high as f64 + 1.0,
+                    self.playhead_color,
+                ));
+            })
+            .render(area, buf);
+    }
+}
@@ -3,6 +3,9 @@ use std::marker::PhantomData;
 /// Helps manage selection from a list.
 pub struct Selector<T, A: AsRef<[T]>> {
     items: A,
+    /// The indices of the items that match the filter.
+    visible: Vec<usize>,
+    /// The index into `visible` of the selected item.
     selected: usize,
     _data_type: PhantomData<T>,
 }
@@ -11,35 +14,76 @@ impl<T, A: AsRef<[T]>> Selector<T, A> {
     /// Create a new selector that points to the first item of `items`. `items` must not be empty.
     pub fn new(items: A) -> Self {
         assert!(!items.as_ref().is_empty());
+        let visible = (0..items.as_ref().len()).collect();
         Selector {
             items,
+            visible,
             selected: 0,
             _data_type: PhantomData,
         }
     }
 
-    /// Iterate over all items. The iterator contains `(true_if_selected, &item)`.
+    /// Iterate over all items that match the filter. The iterator contains
+    /// `(true_if_selected, &item)`.
     pub fn iter(&self) -> impl Iterator<Item = (bool, &T)> {
-        self.items
-            .as_ref()
+        self.visible
             .iter()
             .enumerate()
-            .map(|(idx, item)| (idx == self.selected, item))
+            .map(|(idx, item_idx)| (idx == self.selected, &self.items.as_ref()[*item_idx]))
+    }
+
+    /// Return a reference to the currently selected item or `None` if no items match the filter.
+    pub fn selected(&self) -> Option<&T> {
+        self.visible
+            .get(self.selected)
+            .map(|idx| &self.items.as_ref()[*idx])
+    }
+
+    /// Return the position of the selected item within the items that match the filter or `None`
+    /// if no items match the filter.
+    pub fn selected_index(&self) -> Option<usize> {
+        if self.visible.is_empty() {
+            None
+        } else {
+            Some(self.selected)
+        }
     }
 
-    /// Return a reference to the currently selected item.
-    pub fn selected(&self) -> &T {
-        &self.items.as_ref()[self.selected]
+    /// The number of items that match the filter.
+    pub fn visible_len(&self) -> usize {
+        self.visible.len()
+    }
+
+    /// The number of items, including the ones that do not match the filter.
+    pub fn total(&self) -> usize {
+        self.items.as_ref().len()
+    }
+
+    /// Only show the items where `keep` returns true. The selection stays on the same item if it
+    /// still matches, otherwise the first matching item is selected.
+    pub fn filter(&mut self, keep: impl Fn(&T) -> bool) {
+        let selected = self.visible.get(self.selected).copied();
+        self.visible = self
+            .items
+            .as_ref()
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| keep(item))
+            .map(|(idx, _)| idx)
+            .collect();
+        self.selected = selected
+            .and_then(|s| self.visible.iter().position(|idx| *idx == s))
+            .unwrap_or(0);
     }
 
     /// Advance the selection by `pos`. If `pos` is negative, then the selection moves backwards.
     ///
     /// Note: Selection wraps around.
     pub fn select_by(&mut self, pos: isize) {
-        if pos < 0 {
-            self.select_by(pos + self.items.as_ref().len() as isize);
-        } else {
-            self.selected = (self.selected + pos as usize).rem_euclid(self.items.as_ref().len());
+        let len = self.visible.len() as isize;
+        if len == 0 {
+            return;
         }
+        self.selected = (self.selected as isize + pos).rem_euclid(len) as usize;
     }
 }
@@ -0,0 +1,153 @@
+use ratatui::{
+    prelude::{Alignment, Rect},
+    style::Style,
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+use crate::{
+    bats_state::BatsState,
+    events::Event,
+    theme::{track_color, Theme},
+};
+
+/// A bar that is displayed at the bottom of every page.
+pub struct StatusBar<'a> {
+    /// The bats state to display.
+    bats_state: &'a BatsState,
+    /// The colors to use.
+    theme: Theme,
+}
+
+impl<'a> StatusBar<'a> {
+    /// The height of the status bar.
+    pub const HEIGHT: u16 = 3;
+
+    /// The number of beats in each bar.
+    const BEATS_PER_BAR: u32 = 4;
+
+    /// Create a new `StatusBar`.
+    pub fn new(bats_state: &'a BatsState, theme: Theme) -> StatusBar<'a> {
+        StatusBar { bats_state, theme }
+    }
+
+    /// Handle events that apply on every page, like transposing the armed track. Returns true if
+    /// the event was handled.
+    pub fn handle_global_event(&self, event: Event) -> bool {
+        match event {
+            Event::Transpose(semitones) => {
+                self.bats_state
+                    .modify_transpose(|t| t.saturating_add(semitones));
+                true
+            }
+            Event::Capture => {
+                self.bats_state.capture_midi_history();
+                true
+            }
+            Event::Undo => {
+                self.bats_state.undo();
+                true
+            }
+            Event::Redo => {
+                self.bats_state.redo();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Draw the status bar in `area`.
+    pub fn draw(&self, frame: &mut Frame, area: Rect) {
+        let position = self.bats_state.position();
+        let beat = position.beat();
+        let is_flashing = position.sub_beat() < u32::MAX / 4;
+        let flash_color = match (is_flashing, beat % StatusBar::BEATS_PER_BAR) {
+            (false, _) => self.theme.background,
+            (true, 0) => self.theme.highlight,
+            (true, _) => self.theme.foreground,
+        };
+        let (armed, armed_style) = match self.bats_state.track_by_id(self.bats_state.armed()) {
+            Some(t) => (
+                t.title(),
+                t.color
+                    .map(|c| Style::default().fg(track_color(c)))
+                    .unwrap_or_default(),
+            ),
+            None => ("disarmed".to_string(), Style::default()),
+        };
+        let (record_text, record_style) = if self.bats_state.is_exporting() {
+            ("EXPORTING", Style::default().fg(self.theme.highlight))
+        } else if self.bats_state.recording_enabled() {
+            ("REC", Style::default().fg(self.theme.highlight))
+        } else {
+            ("rec off", Style::default())
+        };
+        let disk_text = match self.bats_state.disk_recording() {
+            Some(_) => " DISK",
+            None => "",
+        };
+        let transpose_text = match self.bats_state.transpose() {
+            0 => String::new(),
+            semitones => format!(" {semitones:+}st"),
+        };
+        let xruns_text = match self.bats_state.xruns() {
+            0 => String::new(),
+            xruns => format!(" | Xruns: {xruns}"),
+        };
+        let overload_text = if self.bats_state.overloaded() {
+            " OVERLOAD"
+        } else {
+            ""
+        };
+        let disconnected_text = if self.bats_state.is_disconnected() {
+            " | DISCONNECTED, reconnecting"
+        } else {
+            ""
+        };
+        let dropped_text = match self.bats_state.dropped() {
+            0 => String::new(),
+            dropped => format!(" | Dropped: {dropped}"),
+        };
+        let cpu_load = match self.bats_state.cpu_load() {
+            Some(load) => format!("{load:.1}%"),
+            None => "n/a".to_string(),
+        };
+        let dsp_load = match self.bats_state.dsp_load() {
+            Some(load) => format!("{load:.1}%"),
+            None => "n/a".to_string(),
+        };
+        let line = Line::from(vec![
+            Span::styled("●", Style::default().fg(flash_color)),
+            Span::raw(format!(
+                " Bar {bar} Beat {beat} | ",
+                bar = beat / StatusBar::BEATS_PER_BAR + 1,
+                beat = beat % StatusBar::BEATS_PER_BAR + 1,
+            )),
+            Span::styled(record_text, record_style),
+            Span::styled(disk_text, Style::default().fg(self.theme.highlight)),
+            Span::raw(" | Armed: "),
+            Span::styled(armed, armed_style),
+            Span::styled(transpose_text, Style::default().fg(self.theme.highlight)),
+            Span::raw(format!(
+                " | {bpm:.1} BPM | CPU: {cpu_load} | DSP: {dsp_load}",
+                bpm = self.bats_state.bpm(),
+            )),
+            Span::styled(overload_text, Style::default().fg(self.theme.highlight)),
+            Span::styled(xruns_text, Style::default().fg(self.theme.highlight)),
+            Span::styled(dropped_text, Style::default().fg(self.theme.highlight)),
+            Span::styled(disconnected_text, Style::default().fg(self.theme.highlight)),
+        ]);
+        frame.render_widget(
+            Paragraph::new(line)
+                .alignment(Alignment::Left)
+                .block(Block::default().borders(Borders::ALL))
+                .style(
+                    Style::default()
+                        .fg(self.theme.foreground)
+                        .bg(self.theme.background),
+                ),
+            area,
+        );
+    }
+}
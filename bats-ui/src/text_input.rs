@@ -0,0 +1,110 @@
+use anyhow::Result;
+use ratatui::{
+    prelude::{Alignment, Rect},
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, BorderType, Borders, Paragraph},
+    Frame,
+};
+
+use crate::{
+    events::Event,
+    menu::{Menu, MenuAction},
+    theme::Theme,
+};
+
+/// A function that parses the text into a value.
+type TextInputParser<'a, T> = dyn 'a + Fn(&str) -> Result<T>;
+
+/// A menu for typing in a value of type `T`.
+pub struct TextInput<'a, T> {
+    title: String,
+    text: String,
+    error: Option<String>,
+    parser: Box<TextInputParser<'a, T>>,
+    color: Color,
+    background: Color,
+    error_color: Color,
+}
+
+impl<'a, T> TextInput<'a, T> {
+    /// Create a new text input with the given title. `parser` is called on the text when enter is
+    /// pressed. If parsing fails, the error is displayed and the user may keep editing.
+    pub fn new(
+        title: String,
+        initial_text: String,
+        parser: impl 'a + Fn(&str) -> Result<T>,
+    ) -> TextInput<'a, T> {
+        TextInput {
+            title,
+            text: initial_text,
+            error: None,
+            parser: Box::new(parser),
+            color: Color::White,
+            background: Color::Black,
+            error_color: Color::Red,
+        }
+    }
+
+    /// Set the colors of the text input from `theme`.
+    pub fn with_theme(self, theme: Theme) -> Self {
+        TextInput {
+            color: theme.foreground,
+            background: theme.background,
+            error_color: theme.highlight,
+            ..self
+        }
+    }
+}
+
+impl<'a, T> Menu for TextInput<'a, T> {
+    type Item = T;
+
+    fn handle_event(&mut self, event: Event) -> Result<MenuAction<Self::Item>> {
+        let action = match event {
+            Event::Char(c) => {
+                self.text.push(c);
+                self.error = None;
+                MenuAction::Redraw
+            }
+            Event::Backspace => {
+                self.text.pop();
+                self.error = None;
+                MenuAction::Redraw
+            }
+            Event::Enter => match (self.parser)(&self.text) {
+                Ok(v) => MenuAction::Select(v),
+                Err(err) => {
+                    self.error = Some(err.to_string());
+                    MenuAction::Redraw
+                }
+            },
+            Event::Back => MenuAction::Exit,
+            Event::Redraw => MenuAction::Redraw,
+            _ => MenuAction::None,
+        };
+        Ok(action)
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rect) {
+        let mut lines = vec![Line::from(format!("> {}_", self.text))];
+        if let Some(err) = &self.error {
+            lines.push(Line::from(Span::styled(
+                err.as_str(),
+                Style::default().fg(self.error_color),
+            )));
+        }
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(
+                    Block::default()
+                        .title(self.title.as_str())
+                        .title_alignment(Alignment::Center)
+                        .borders(Borders::ALL)
+                        .border_type(BorderType::Rounded),
+                )
+                .style(Style::default().fg(self.color).bg(self.background)),
+            area,
+        );
+    }
+}
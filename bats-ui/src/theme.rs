@@ -0,0 +1,118 @@
+use bats_lib::track::TrackColor;
+use ratatui::style::Color;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// The colors used by the UI.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// The color for most text and borders.
+    pub foreground: Color,
+    /// The color for menus that edit values, such as the params menu.
+    pub highlight: Color,
+    /// The background color.
+    pub background: Color,
+}
+
+/// A built in theme.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemePreset {
+    /// Light text on a dark background.
+    Dark,
+    /// Bright colors on a black background.
+    HighContrast,
+    /// Dark text on a light background.
+    Light,
+}
+
+impl Theme {
+    /// The default theme.
+    pub const DARK: Theme = Theme {
+        foreground: Color::White,
+        highlight: Color::Blue,
+        background: Color::Black,
+    };
+
+    /// A theme with bright colors on a black background.
+    pub const HIGH_CONTRAST: Theme = Theme {
+        foreground: Color::White,
+        highlight: Color::LightYellow,
+        background: Color::Black,
+    };
+
+    /// A theme with dark text on a light background.
+    pub const LIGHT: Theme = Theme {
+        foreground: Color::Black,
+        highlight: Color::Blue,
+        background: Color::White,
+    };
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::DARK
+    }
+}
+
+impl ThemePreset {
+    /// All the theme presets.
+    pub const ALL: &'static [ThemePreset] = &[
+        ThemePreset::Dark,
+        ThemePreset::HighContrast,
+        ThemePreset::Light,
+    ];
+
+    /// The human readable name of the preset.
+    pub fn name(self) -> &'static str {
+        match self {
+            ThemePreset::Dark => "dark",
+            ThemePreset::HighContrast => "high contrast",
+            ThemePreset::Light => "light",
+        }
+    }
+
+    /// Get the theme for the preset.
+    pub fn theme(self) -> Theme {
+        match self {
+            ThemePreset::Dark => Theme::DARK,
+            ThemePreset::HighContrast => Theme::HIGH_CONTRAST,
+            ThemePreset::Light => Theme::LIGHT,
+        }
+    }
+
+    /// Get the preset that matches `theme` or `None` if `theme` is a custom theme.
+    pub fn from_theme(theme: Theme) -> Option<ThemePreset> {
+        ThemePreset::ALL
+            .iter()
+            .copied()
+            .find(|p| p.theme() == theme)
+    }
+}
+
+/// Get the terminal color for a track color.
+pub fn track_color(color: TrackColor) -> Color {
+    match color {
+        TrackColor::Red => Color::Red,
+        TrackColor::Green => Color::Green,
+        TrackColor::Yellow => Color::Yellow,
+        TrackColor::Blue => Color::Blue,
+        TrackColor::Magenta => Color::Magenta,
+        TrackColor::Cyan => Color::Cyan,
+    }
+}
+
+/// Deserialize a theme from either the name of a preset, like `"light"`, or a table of colors.
+pub fn deserialize_theme<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Theme, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ThemeOrPreset {
+        Preset(ThemePreset),
+        Theme(Theme),
+    }
+    let theme = match ThemeOrPreset::deserialize(deserializer)? {
+        ThemeOrPreset::Preset(p) => p.theme(),
+        ThemeOrPreset::Theme(t) => t,
+    };
+    Ok(theme)
+}
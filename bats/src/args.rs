@@ -1,14 +1,142 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use bats::config::Config;
+use bats_lib::builder::{BatsBuilder, PluginBuilder, TrackBuilder};
+use clap::{Parser, ValueEnum};
 
 /// Command line arguments for bats.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    /// If true, then ports will automatically be connected.
-    #[arg(long, default_value_t = true)]
-    pub auto_connect: bool,
+    /// The path to the config file. Defaults to `~/.config/bats/config.toml`.
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// If true, then ports will automatically be connected. Overrides the config file.
+    #[arg(long)]
+    pub auto_connect: Option<bool>,
+
+    /// The initial beats per minute. Overrides the config file.
+    #[arg(long)]
+    pub bpm: Option<f32>,
 
     /// The amount of logging to perform. The values are OFF, ERROR, WARN, INFO, DEBUG, and TRACE.
     #[arg(long, default_value_t = log::LevelFilter::Info)]
     pub log_level: log::LevelFilter,
+
+    /// The number of tracks. Overrides the config file.
+    #[arg(long)]
+    pub tracks: Option<usize>,
+
+    /// The plugin for a track as `<track>:<plugin>`, like `1:toof`. Tracks are numbered from 1.
+    /// May be repeated. Overrides the config file and the project.
+    #[arg(long = "track", value_parser = parse_track_plugin)]
+    pub track_plugins: Vec<(usize, PluginBuilder)>,
+
+    /// The project file to load the bpm and tracks from instead of the config file. Ignored when
+    /// bats is started by a session manager.
+    #[arg(long)]
+    pub project: Option<PathBuf>,
+
+    /// The audio backend to use.
+    #[arg(long, value_enum, default_value_t = Backend::Jack)]
+    pub backend: Backend,
+}
+
+impl Args {
+    /// Load the config file and override any values that were set in the args.
+    pub fn load_config(&self) -> anyhow::Result<Config> {
+        let mut config = match self.config.clone().or_else(Config::default_path) {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+        if let Some(auto_connect) = self.auto_connect {
+            config.auto_connect = auto_connect;
+        }
+        if let Some(bpm) = self.bpm {
+            config.bpm = bpm;
+        }
+        if let Some(tracks) = self.tracks {
+            config.tracks = tracks;
+        }
+        for (track_id, plugin) in self.track_plugins.iter().copied() {
+            if config.track_plugins.len() <= track_id {
+                config
+                    .track_plugins
+                    .resize(track_id + 1, PluginBuilder::Empty);
+            }
+            config.track_plugins[track_id] = plugin;
+            config.tracks = config.tracks.max(track_id + 1);
+        }
+        if config.ui.presets_dir.is_none() {
+            config.ui.presets_dir = Config::default_presets_dir();
+        }
+        Ok(config)
+    }
+
+    /// Override any values of `project` that were set in the args.
+    pub fn apply_to_project(&self, project: &mut BatsBuilder) {
+        if let Some(bpm) = self.bpm {
+            project.bpm = bpm;
+        }
+        if let Some(tracks) = self.tracks {
+            project.tracks.resize(tracks, TrackBuilder::default());
+        }
+        for (track_id, plugin) in self.track_plugins.iter().copied() {
+            if project.tracks.len() <= track_id {
+                project.tracks.resize(track_id + 1, TrackBuilder::default());
+            }
+            project.tracks[track_id].plugin = plugin;
+        }
+    }
+}
+
+/// Parse a `<track>:<plugin>` argument into the 0 based track index and the plugin.
+fn parse_track_plugin(arg: &str) -> Result<(usize, PluginBuilder), String> {
+    let (track, plugin) = arg
+        .split_once(':')
+        .ok_or_else(|| format!("expected <track>:<plugin> but got {arg:?}"))?;
+    let track: usize = track
+        .parse()
+        .map_err(|err| format!("invalid track {track:?}: {err}"))?;
+    if track == 0 {
+        return Err("tracks are numbered from 1".to_string());
+    }
+    let plugins = PluginBuilder::all();
+    let plugin = plugins
+        .iter()
+        .copied()
+        .find(|p| p.name().eq_ignore_ascii_case(plugin))
+        .ok_or_else(|| {
+            let names: Vec<_> = plugins.iter().map(|p| p.name()).collect();
+            format!(
+                "unknown plugin {plugin:?}, expected one of {}",
+                names.join(", ")
+            )
+        })?;
+    Ok((track - 1, plugin))
+}
+
+/// The supported audio backends.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    /// Use JACK for audio and midi.
+    Jack,
+    /// Use cpal for audio. Midi input is not supported. Requires the `cpal` feature.
+    #[cfg(feature = "cpal")]
+    Cpal,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_track_plugin_uses_0_based_tracks() {
+        assert_eq!(parse_track_plugin("1:toof"), Ok((0, PluginBuilder::Toof)));
+        assert_eq!(parse_track_plugin("3:Empty"), Ok((2, PluginBuilder::Empty)));
+        assert!(parse_track_plugin("0:toof").is_err());
+        assert!(parse_track_plugin("1:piano").is_err());
+        assert!(parse_track_plugin("toof").is_err());
+    }
 }
@@ -0,0 +1,23 @@
+use anyhow::Result;
+use bats_async::CommandReceiver;
+use bats_dsp::sample_rate::SampleRate;
+use bats_lib::Bats;
+
+/// An audio backend that drives a `Bats` instance.
+pub trait AudioBackend {
+    /// The name of the backend.
+    fn name(&self) -> &'static str;
+
+    /// The sample rate that `Bats` should be built with.
+    fn sample_rate(&self) -> SampleRate;
+
+    /// The buffer size that `Bats` should be built with.
+    fn buffer_size(&self) -> usize;
+
+    /// Start processing audio with `bats`. Commands from `commands` are executed before each
+    /// buffer is processed.
+    fn start(&mut self, bats: Bats, commands: CommandReceiver) -> Result<()>;
+
+    /// Stop processing audio.
+    fn stop(&mut self) -> Result<()>;
+}
@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use bats_lib::{
+    builder::{PluginBuilder, TrackBuilder},
+    control_surface::SurfaceProfile,
+    Bats,
+};
+use bats_ui::UiConfig;
+use log::info;
+use serde::Deserialize;
+
+/// Startup options and defaults. Loaded from `~/.config/bats/config.toml` by default.
+///
+/// Example:
+///
+/// ```toml
+/// bpm = 100.0
+/// auto_connect = false
+/// tracks = 4
+/// track_plugins = ["Toof", "Toof"]
+///
+/// [ui.theme]
+/// foreground = "White"
+/// highlight = "Green"
+/// background = "Black"
+///
+/// [ui.key_bindings]
+/// up = ["up", "k"]
+/// down = ["down", "j"]
+///
+/// [control_surface]
+/// lit_velocity = 21
+/// unlit_velocity = 1
+/// pads = [
+///     { pad = 81, action = { ArmTrack = 0 } },
+///     { pad = 11, action = "TogglePlaying" },
+/// ]
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The initial beats per minute.
+    pub bpm: f32,
+    /// If true, then ports will automatically be connected.
+    pub auto_connect: bool,
+    /// The number of tracks.
+    pub tracks: usize,
+    /// The initial plugin for each track. Tracks without an entry start with an empty plugin.
+    pub track_plugins: Vec<PluginBuilder>,
+    /// Configuration for the UI.
+    pub ui: UiConfig,
+    /// The pad mapping for the control surface or `None` if no control surface is used. An empty
+    /// `[control_surface]` table uses the Launchpad profile.
+    pub control_surface: Option<SurfaceProfile>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            bpm: 120.0,
+            auto_connect: true,
+            tracks: Bats::DEFAULT_TRACK_COUNT,
+            track_plugins: Vec::new(),
+            ui: UiConfig::default(),
+            control_surface: None,
+        }
+    }
+}
+
+impl Config {
+    /// The default path for the config file.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("bats").join("config.toml"))
+    }
+
+    /// The default directory for plugin presets.
+    pub fn default_presets_dir() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("bats").join("presets"))
+    }
+
+    /// Load the config from `path`. If the file does not exist, then the default config is
+    /// returned.
+    pub fn load(path: impl AsRef<Path>) -> Result<Config> {
+        let path = path.as_ref();
+        if !path.exists() {
+            info!("Config file {path:?} does not exist, using default config.");
+            return Ok(Config::default());
+        }
+        info!("Loading config from {path:?}.");
+        let contents = std::fs::read_to_string(path)?;
+        Config::from_toml(&contents)
+    }
+
+    /// Parse the config from a toml string.
+    pub fn from_toml(contents: &str) -> Result<Config> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    /// Get the builders for all the tracks.
+    pub fn track_builders(&self) -> Vec<TrackBuilder> {
+        (0..self.tracks)
+            .map(|idx| TrackBuilder {
+                plugin: self.track_plugins.get(idx).copied().unwrap_or_default(),
+                ..TrackBuilder::default()
+            })
+            .collect()
+    }
+}
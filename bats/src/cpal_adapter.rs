@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Result};
+use bats_async::CommandReceiver;
+use bats_dsp::sample_rate::SampleRate;
+use bats_lib::Bats;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use log::{error, info, warn};
+
+use crate::backend::AudioBackend;
+
+/// An `AudioBackend` that uses cpal. This supports ALSA, CoreAudio, WASAPI, and others but does
+/// not support midi input.
+pub struct CpalBackend {
+    /// The output device.
+    device: cpal::Device,
+    /// The config for the output stream.
+    config: cpal::StreamConfig,
+    /// The output stream. Only set after the backend has started.
+    stream: Option<cpal::Stream>,
+}
+
+impl CpalBackend {
+    /// The number of frames that bats processes at a time. cpal may request any number of frames
+    /// so output is buffered in chunks of this size.
+    const BUFFER_SIZE: usize = 512;
+
+    /// Create a new `CpalBackend` using the default output device of the default host.
+    pub fn new() -> Result<CpalBackend> {
+        let host = cpal::default_host();
+        let device = host
+            .default_output_device()
+            .ok_or_else(|| anyhow!("No default output device found for {:?}.", host.id()))?;
+        info!(
+            "Using cpal output device {:?}.",
+            device.name().unwrap_or_default()
+        );
+        let default_config = device.default_output_config()?;
+        let sample_rate = default_config.sample_rate();
+        let config = device
+            .supported_output_configs()?
+            .find(|c| {
+                c.sample_format() == cpal::SampleFormat::F32
+                    && c.min_sample_rate() <= sample_rate
+                    && sample_rate <= c.max_sample_rate()
+            })
+            .ok_or_else(|| anyhow!("Output device does not support f32 samples."))?
+            .with_sample_rate(sample_rate)
+            .config();
+        Ok(CpalBackend {
+            device,
+            config,
+            stream: None,
+        })
+    }
+}
+
+impl AudioBackend for CpalBackend {
+    fn name(&self) -> &'static str {
+        "cpal"
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        SampleRate::new(self.config.sample_rate.0 as f32)
+    }
+
+    fn buffer_size(&self) -> usize {
+        CpalBackend::BUFFER_SIZE
+    }
+
+    fn start(&mut self, bats: Bats, commands: CommandReceiver) -> Result<()> {
+        if self.stream.is_some() {
+            return Err(anyhow!("cpal backend has already been started."));
+        }
+        warn!("Midi input is not supported by the cpal backend.");
+        let mut processor = Processor::new(bats, commands);
+        let channels = self.config.channels as usize;
+        let stream = self.device.build_output_stream(
+            &self.config,
+            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| processor.process(data, channels),
+            |err| error!("cpal stream error: {err}"),
+            None,
+        )?;
+        stream.play()?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        if let Some(stream) = self.stream.take() {
+            stream.pause()?;
+        }
+        Ok(())
+    }
+}
+
+/// Processes bats in fixed size chunks and writes interleaved output.
+struct Processor {
+    /// The bats processing object.
+    bats: Bats,
+    /// Command queue for the bats processing object.
+    commands: CommandReceiver,
+    /// The left channel of the last processed chunk.
+    left: Vec<f32>,
+    /// The right channel of the last processed chunk.
+    right: Vec<f32>,
+    /// The index of the next frame to output from `left` and `right`.
+    next_frame: usize,
+}
+
+impl Processor {
+    /// Create a new `Processor`.
+    fn new(bats: Bats, commands: CommandReceiver) -> Processor {
+        let buffer_size = bats.buffer_size;
+        Processor {
+            bats,
+            commands,
+            left: vec![0.0; buffer_size],
+            right: vec![0.0; buffer_size],
+            next_frame: buffer_size,
+        }
+    }
+
+    /// Fill the interleaved `data` with `channels` channels.
+    fn process(&mut self, data: &mut [f32], channels: usize) {
+        for frame in data.chunks_mut(channels) {
+            if self.next_frame == self.left.len() {
+                self.process_chunk();
+            }
+            let (left, right) = (self.left[self.next_frame], self.right[self.next_frame]);
+            self.next_frame += 1;
+            match frame {
+                [] => (),
+                [mono] => *mono = 0.5 * (left + right),
+                [l, r, rest @ ..] => {
+                    *l = left;
+                    *r = right;
+                    rest.fill(0.0);
+                }
+            }
+        }
+    }
+
+    /// Process the next chunk into `left` and `right`.
+    fn process_chunk(&mut self) {
+        self.commands.execute_all(&mut self.bats);
+        self.bats.process(&[], &mut self.left, &mut self.right);
+        self.commands.publish_events(&mut self.bats);
+        self.next_frame = 0;
+    }
+}
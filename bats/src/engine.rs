@@ -0,0 +1,87 @@
+use anyhow::{anyhow, Result};
+use bats_async::{new_async_commander, CommandReceiver, CommandSender};
+use bats_lib::{
+    builder::{BatsBuilder, TrackBuilder},
+    Bats,
+};
+use log::error;
+
+use crate::backend::AudioBackend;
+
+/// Runs a bats instance on an audio backend without any UI.
+pub struct Engine {
+    /// The audio backend.
+    backend: Box<dyn AudioBackend>,
+    /// Used to send commands to the bats instance.
+    commands: CommandSender,
+    /// The bats instance and command receiver. These are moved to the backend on `start`.
+    pending: Option<(Bats, CommandReceiver)>,
+    /// True if the engine is running.
+    is_running: bool,
+}
+
+impl Engine {
+    /// Create a new `Engine` with the given tracks. Processing does not begin until `start` is
+    /// called.
+    pub fn new(backend: Box<dyn AudioBackend>, bpm: f32, tracks: Vec<TrackBuilder>) -> Engine {
+        let bats = BatsBuilder {
+            sample_rate: backend.sample_rate(),
+            buffer_size: backend.buffer_size(),
+            bpm,
+            tracks,
+        }
+        .build();
+        let (commands, receiver) = new_async_commander();
+        Engine {
+            backend,
+            commands,
+            pending: Some((bats, receiver)),
+            is_running: false,
+        }
+    }
+
+    /// Get the bats instance. Returns `None` if the engine has been started as the instance is
+    /// then owned by the audio backend.
+    pub fn bats(&self) -> Option<&Bats> {
+        self.pending.as_ref().map(|(bats, _)| bats)
+    }
+
+    /// Get the sender for commands to the bats instance.
+    pub fn commands(&self) -> &CommandSender {
+        &self.commands
+    }
+
+    /// Returns true if the engine is processing audio.
+    pub fn is_running(&self) -> bool {
+        self.is_running
+    }
+
+    /// Start processing audio. An engine can only be started once.
+    pub fn start(&mut self) -> Result<()> {
+        let (bats, receiver) = self
+            .pending
+            .take()
+            .ok_or_else(|| anyhow!("Engine has already been started."))?;
+        self.backend.start(bats, receiver)?;
+        self.is_running = true;
+        Ok(())
+    }
+
+    /// Stop processing audio.
+    pub fn stop(&mut self) -> Result<()> {
+        if self.is_running {
+            self.backend.stop()?;
+            self.is_running = false;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Engine {
+    /// Stop processing audio if the engine is still running.
+    fn drop(&mut self) {
+        if let Err(err) = self.stop() {
+            error!("Failed to stop engine: {err}");
+        }
+    }
+}
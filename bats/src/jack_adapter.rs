@@ -1,33 +1,707 @@
-use anyhow::Result;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
 use bats_async::CommandReceiver;
+use bats_dsp::{position::Position, sample_rate::SampleRate};
 use bats_lib::Bats;
 use jack::PortSpec;
 use log::{error, info, warn};
 
-/// Implements the JACK processor.
+use crate::backend::AudioBackend;
+
+/// A JACK client that is processing audio.
+type ActiveClient = jack::AsyncClient<NotificationHandler, ProcessHandler>;
+
+/// An `AudioBackend` that uses JACK.
+///
+/// If the JACK server shuts down, a background thread waits for it to come back and connects a new
+/// client. The ports are registered again, their connections are restored, and processing resumes
+/// with the same `Bats` instance.
+pub struct JackBackend {
+    /// The client before it has been activated.
+    client: Option<jack::Client>,
+    /// The connection to the JACK server after the client has been activated. `None` while
+    /// reconnecting. Shared with the thread that reconnects to the JACK server.
+    connection: Arc<Mutex<Option<Connection>>>,
+    /// The sample rate of the client.
+    sample_rate: SampleRate,
+    /// The buffer size of the client.
+    buffer_size: usize,
+    /// The name of the JACK client.
+    name: String,
+    /// If true, then ports will automatically be connected.
+    auto_connect: bool,
+    /// Set to stop the thread that reconnects to the JACK server.
+    stopped: Arc<AtomicBool>,
+}
+
+impl JackBackend {
+    /// Create a new `JackBackend` with a client named `name`. This connects to the JACK server but
+    /// does not start processing until `start` is called.
+    pub fn new(name: &str, auto_connect: bool) -> Result<JackBackend> {
+        let (client, status) = jack::Client::new(name, jack::ClientOptions::NO_START_SERVER)?;
+        info!("Started JACK client {:?}.", client);
+        info!("JACK status is {:?}", status);
+        Ok(JackBackend {
+            sample_rate: SampleRate::new(client.sample_rate() as f32),
+            buffer_size: client.buffer_size() as usize,
+            client: Some(client),
+            connection: Arc::default(),
+            name: name.to_string(),
+            auto_connect,
+            stopped: Arc::default(),
+        })
+    }
+}
+
+impl AudioBackend for JackBackend {
+    fn name(&self) -> &'static str {
+        "jack"
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    fn start(&mut self, bats: Bats, commands: CommandReceiver) -> Result<()> {
+        let client = self
+            .client
+            .take()
+            .ok_or_else(|| anyhow!("JACK backend has already been started."))?;
+        let core = Arc::new(Mutex::new(Core { bats, commands }));
+        let port_connections = Arc::default();
+        let connection = Connection::activate(
+            client,
+            core.clone(),
+            self.auto_connect,
+            Arc::clone(&port_connections),
+        )?;
+        *self.connection.lock().unwrap() = Some(connection);
+        spawn_reconnect_daemon(
+            self.connection.clone(),
+            core,
+            self.name.clone(),
+            self.auto_connect,
+            port_connections,
+            self.stopped.clone(),
+        );
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.stopped.store(true, Ordering::Relaxed);
+        let connection = self.connection.lock().unwrap().take();
+        if let Some(connection) = connection {
+            connection.deactivate()?;
+        }
+        Ok(())
+    }
+}
+
+/// The state that is processed by JACK. It outlives the JACK client so that processing can resume
+/// after reconnecting to the JACK server.
 #[derive(Debug)]
-pub struct ProcessHandler {
+pub struct Core {
     /// The bats processing object.
     bats: Bats,
-    /// The IO ports.
-    ports: Ports,
     /// Command queue for the bats processing object.
     commands: CommandReceiver,
-    /// An intermediate midi buffer.
-    midi_buffer: Vec<(u32, bmidi::MidiMessage)>,
+}
+
+/// An activated client that is connected to the JACK server.
+struct Connection {
+    /// The activated client. Shared with the threads that use the client outside of the process
+    /// thread.
+    client: Arc<ActiveClient>,
+    /// Set by the `NotificationHandler` once the JACK server has shut down the client.
+    is_shutdown: Arc<AtomicBool>,
+    /// Handles the latency callback. Must outlive `client`.
+    latency_handler: Box<LatencyHandler>,
+}
+
+impl Connection {
+    /// Activate `client` to process `core`. While the client is running, the connections of its
+    /// ports are recorded into `port_connections`. Connections that were recorded by a previous
+    /// client are restored once their ports exist.
+    fn activate(
+        client: jack::Client,
+        core: Arc<Mutex<Core>>,
+        auto_connect: bool,
+        port_connections: Arc<Mutex<Vec<PortConnection>>>,
+    ) -> Result<Connection> {
+        let (direct_ports_sender, direct_ports) = crossbeam_channel::bounded(1);
+        let (midi_out_ports_sender, midi_out_ports) = crossbeam_channel::bounded(1);
+        let process_handler = ProcessHandler::new(&client, core, direct_ports, midi_out_ports)?;
+        let maybe_connector = maybe_make_connector(&process_handler, auto_connect);
+        let notification_handler = process_handler.notification_handler();
+        let is_shutdown = notification_handler.is_shutdown.clone();
+        let requests = process_handler.requests.clone();
+        let timebase_handler = process_handler.timebase_handler.clone();
+        let latency_handler = Box::new(LatencyHandler::new(&client, &process_handler.ports)?);
+        latency_handler.register(&client)?;
+        let active_client = Arc::new(client.activate_async(notification_handler, process_handler)?);
+        spawn_client_daemon(
+            Arc::downgrade(&active_client),
+            requests,
+            direct_ports_sender,
+            midi_out_ports_sender,
+            latency_handler.outputs.clone(),
+            timebase_handler,
+        );
+        spawn_port_connections_daemon(
+            Arc::downgrade(&active_client),
+            is_shutdown.clone(),
+            port_connections,
+        );
+        spawn_connector_daemon(Arc::downgrade(&active_client), maybe_connector);
+        Ok(Connection {
+            client: active_client,
+            is_shutdown,
+            latency_handler,
+        })
+    }
+
+    /// Deactivate and close the client. A client that was shut down by the JACK server is only
+    /// closed.
+    fn deactivate(self) -> Result<()> {
+        let Connection {
+            mut client,
+            is_shutdown,
+            latency_handler,
+        } = self;
+        // The client daemons may be holding a reference while they use the client.
+        let client = loop {
+            match Arc::try_unwrap(client) {
+                Ok(c) => break c,
+                Err(c) => {
+                    client = c;
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+            }
+        };
+        let res = if is_shutdown.load(Ordering::Relaxed) {
+            drop(client);
+            Ok(())
+        } else {
+            client.deactivate().map(drop)
+        };
+        // The latency handler must outlive the client.
+        drop(latency_handler);
+        Ok(res?)
+    }
+}
+
+/// Spawn a thread that waits for the JACK server to shut down the client in `connection` and then
+/// connects a new client named `name` that resumes processing `core`. The thread exits once
+/// `stopped` is set.
+fn spawn_reconnect_daemon(
+    connection: Arc<Mutex<Option<Connection>>>,
+    core: Arc<Mutex<Core>>,
+    name: String,
+    auto_connect: bool,
+    port_connections: Arc<Mutex<Vec<PortConnection>>>,
+    stopped: Arc<AtomicBool>,
+) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_millis(500));
+        let mut connection = connection.lock().unwrap();
+        if stopped.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(c) = connection.as_ref() {
+            if !c.is_shutdown.load(Ordering::Relaxed) {
+                continue;
+            }
+        }
+        if let Some(c) = connection.take() {
+            warn!("Lost the connection to the JACK server, waiting for it to come back.");
+            if let Err(err) = c.deactivate() {
+                error!("Failed to close the JACK client: {err}");
+            }
+            core.lock().unwrap().commands.set_disconnected(true);
+        }
+        match reconnect(&core, &name, auto_connect, &port_connections) {
+            Ok(c) => {
+                info!("Reconnected to the JACK server.");
+                *connection = Some(c);
+            }
+            Err(err) => {
+                info!("Failed to reconnect to the JACK server: {err}");
+                drop(connection);
+                std::thread::sleep(Duration::from_secs(2));
+            }
+        }
+    });
+}
+
+/// Connect a new client named `name` to the JACK server and resume processing `core` with it.
+fn reconnect(
+    core: &Arc<Mutex<Core>>,
+    name: &str,
+    auto_connect: bool,
+    port_connections: &Arc<Mutex<Vec<PortConnection>>>,
+) -> Result<Connection> {
+    let (client, status) = jack::Client::new(name, jack::ClientOptions::NO_START_SERVER)?;
+    info!("Started JACK client {:?} with status {:?}.", client, status);
+    {
+        // The restarted server may run with different settings.
+        let mut core = core.lock().unwrap();
+        let Core { bats, commands } = &mut *core;
+        commands.set_sample_rate(bats, SampleRate::new(client.sample_rate() as f32));
+        commands.set_buffer_size(bats, client.buffer_size() as usize);
+        commands.set_disconnected(false);
+    }
+    Connection::activate(client, core.clone(), auto_connect, port_connections.clone())
+        .inspect_err(|_| core.lock().unwrap().commands.set_disconnected(true))
+}
+
+/// Requests from the `ProcessHandler` for actions that must be performed on the JACK client
+/// outside of the process thread.
+#[derive(Debug, Default)]
+pub struct ClientRequests {
+    /// The number of tracks to register direct output ports for. 0 if there is no request.
+    direct_ports: AtomicUsize,
+    /// The number of tracks to register midi output ports for. 0 if there is no request.
+    midi_out_ports: AtomicUsize,
+    /// True if JACK should run in freewheel mode.
+    freewheel: AtomicBool,
+    /// True if bats should be the JACK timebase master.
+    timebase_master: AtomicBool,
+}
+
+/// Spawn a thread that handles `requests` from the `ProcessHandler`. These are handled outside
+/// of the process thread since they are not realtime safe. The thread exits once `client` is
+/// dropped.
+///
+/// Direct output ports and midi output ports are sent to the `ProcessHandler` through
+/// `direct_ports` and `midi_out_ports` once registered.
+fn spawn_client_daemon(
+    client: Weak<ActiveClient>,
+    requests: Arc<ClientRequests>,
+    direct_ports: crossbeam_channel::Sender<Vec<DirectPorts>>,
+    midi_out_ports: crossbeam_channel::Sender<Vec<jack::Port<jack::MidiOut>>>,
+    latency_outputs: Arc<Mutex<Vec<jack::Port<jack::Unowned>>>>,
+    timebase_handler: Arc<TimebaseHandler>,
+) {
+    std::thread::spawn(move || {
+        let mut has_direct_ports = false;
+        let mut has_midi_out_ports = false;
+        let mut freewheel = false;
+        let mut timebase_master = false;
+        loop {
+            std::thread::sleep(Duration::from_millis(100));
+            let client = match client.upgrade() {
+                Some(c) => c,
+                None => return,
+            };
+            let track_count = requests.direct_ports.swap(0, Ordering::Relaxed);
+            if track_count > 0 && !has_direct_ports {
+                match DirectPorts::register_all(client.as_client(), track_count) {
+                    Ok(ports) => {
+                        info!("Registered direct outputs for {track_count} tracks.");
+                        let mut outputs = latency_outputs.lock().unwrap();
+                        for p in ports.iter() {
+                            for port in [&p.left, &p.right] {
+                                let name = port.name().ok();
+                                outputs
+                                    .extend(name.and_then(|n| client.as_client().port_by_name(&n)));
+                            }
+                        }
+                        let _ = direct_ports.send(ports);
+                        has_direct_ports = true;
+                    }
+                    Err(err) => error!("Failed to register direct output ports: {err}"),
+                }
+            }
+            let track_count = requests.midi_out_ports.swap(0, Ordering::Relaxed);
+            if track_count > 0 && !has_midi_out_ports {
+                let ports: Result<Vec<_>, _> = (1..=track_count)
+                    .map(|n| {
+                        client
+                            .as_client()
+                            .register_port(&format!("track_{n}_midi_out"), jack::MidiOut)
+                    })
+                    .collect();
+                match ports {
+                    Ok(ports) => {
+                        info!("Registered midi outputs for {track_count} tracks.");
+                        let _ = midi_out_ports.send(ports);
+                        has_midi_out_ports = true;
+                    }
+                    Err(err) => error!("Failed to register midi output ports: {err}"),
+                }
+            }
+            let want_freewheel = requests.freewheel.load(Ordering::Relaxed);
+            if want_freewheel != freewheel {
+                // Safety: The client is kept alive by `client` for the duration of the call.
+                let res = unsafe {
+                    jack_sys::jack_set_freewheel(client.as_client().raw(), want_freewheel as _)
+                };
+                match res {
+                    0 => freewheel = want_freewheel,
+                    err => error!("Failed to set JACK freewheel to {want_freewheel}: {err}"),
+                }
+            }
+            let want_timebase_master = requests.timebase_master.load(Ordering::Relaxed);
+            if want_timebase_master != timebase_master {
+                match timebase_handler.set_master(client.as_client(), want_timebase_master) {
+                    Ok(()) => timebase_master = want_timebase_master,
+                    Err(err) => error!("{err}"),
+                }
+            }
+        }
+    });
+}
+
+fn maybe_make_connector(
+    process_handler: &ProcessHandler,
+    enable_connector: bool,
+) -> Option<Box<dyn Send + FnMut()>> {
+    if enable_connector {
+        Some(match process_handler.connector() {
+            Ok(f) => f,
+            Err(err) => {
+                error!("Failed to create port connector! IO ports will have to be connected manually. Error: {}", err);
+                Box::new(|| {})
+            }
+        })
+    } else {
+        None
+    }
+}
+
+/// Spawn a thread that periodically runs `connector`. The thread exits once `client` is dropped.
+fn spawn_connector_daemon(client: Weak<ActiveClient>, connector: Option<Box<dyn Send + FnMut()>>) {
+    if let Some(mut connector) = connector {
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_secs(1));
+            while client.strong_count() > 0 {
+                connector();
+                std::thread::sleep(std::time::Duration::from_secs(5));
+            }
+        });
+    }
+}
+
+/// A connection between a bats port and a port of another client.
+#[derive(Clone, Debug, PartialEq)]
+struct PortConnection {
+    /// The name of the bats port without the client name.
+    port: String,
+    /// The full name of the port of the other client.
+    other: String,
+    /// True if the bats port is an output.
+    is_output: bool,
+}
+
+/// Spawn a thread that records the connections of the ports of `client` into `port_connections`
+/// until the JACK server shuts down the client. The connections that are in `port_connections`
+/// when the thread starts are restored once both of their ports exist. The thread exits once
+/// `client` is dropped.
+fn spawn_port_connections_daemon(
+    client: Weak<ActiveClient>,
+    is_shutdown: Arc<AtomicBool>,
+    port_connections: Arc<Mutex<Vec<PortConnection>>>,
+) {
+    let mut pending = port_connections.lock().unwrap().clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_secs(1));
+        let client = match client.upgrade() {
+            Some(c) => c,
+            None => return,
+        };
+        if is_shutdown.load(Ordering::Relaxed) {
+            return;
+        }
+        pending.retain(|c| !restore_port_connection(client.as_client(), c));
+        // Keep the previous connections if the ports could not be queried.
+        if let Some(mut connections) = record_port_connections(client.as_client()) {
+            connections.extend(pending.iter().cloned());
+            *port_connections.lock().unwrap() = connections;
+        }
+    });
+}
+
+/// Get the connections of all the ports of `client` or `None` if `client` has no ports.
+fn record_port_connections(client: &jack::Client) -> Option<Vec<PortConnection>> {
+    let prefix = format!("{}:", client.name());
+    let (own, others): (Vec<String>, Vec<String>) = client
+        .ports(None, None, jack::PortFlags::empty())
+        .into_iter()
+        .partition(|n| n.starts_with(&prefix));
+    if own.is_empty() {
+        return None;
+    }
+    let mut connections = Vec::new();
+    for name in own {
+        let port = client.port_by_name(&name)?;
+        let is_output = port.flags().contains(jack::PortFlags::IS_OUTPUT);
+        connections.extend(
+            others
+                .iter()
+                .filter(|other| port.is_connected_to(other).unwrap_or(false))
+                .map(|other| PortConnection {
+                    port: name[prefix.len()..].to_string(),
+                    other: other.clone(),
+                    is_output,
+                }),
+        );
+    }
+    Some(connections)
+}
+
+/// Connect the ports of `connection`. Returns true if the ports are connected or false if the
+/// ports do not exist yet.
+fn restore_port_connection(client: &jack::Client, connection: &PortConnection) -> bool {
+    let port_name = format!("{}:{}", client.name(), connection.port);
+    let port = match client.port_by_name(&port_name) {
+        Some(p) => p,
+        None => return false,
+    };
+    if port.is_connected_to(&connection.other).unwrap_or(false) {
+        return true;
+    }
+    let (source, destination) = if connection.is_output {
+        (port_name.as_str(), connection.other.as_str())
+    } else {
+        (connection.other.as_str(), port_name.as_str())
+    };
+    match client.connect_ports_by_name(source, destination) {
+        Ok(()) => {
+            info!("Restored connection from {source} to {destination}.");
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Implements the JACK processor.
+#[derive(Debug)]
+pub struct ProcessHandler {
+    /// The bats processing object and its command queue. The lock is only held while processing,
+    /// so it is free once the JACK server has shut down the client.
+    core: Arc<Mutex<Core>>,
+    /// The IO ports.
+    ports: Ports,
+    /// An intermediate midi buffer. Each event is tagged with the index of its input port.
+    midi_buffer: Vec<(usize, u32, bmidi::MidiMessage)>,
+    /// A sample rate that has been reported by JACK but not yet applied. 0 if there is no pending
+    /// sample rate.
+    pending_sample_rate: Arc<AtomicU32>,
+    /// The number of xruns that have been reported by JACK but not yet forwarded to `commands`.
+    pending_xruns: Arc<AtomicUsize>,
+    /// The per-track direct output ports. Empty until direct outputs are first enabled.
+    direct_ports: Vec<DirectPorts>,
+    /// Receives the direct output ports once they have been registered.
+    direct_ports_receiver: crossbeam_channel::Receiver<Vec<DirectPorts>>,
+    /// The per-track midi output ports. Empty until a track first sends midi to its midi output.
+    midi_out_ports: Vec<jack::Port<jack::MidiOut>>,
+    /// Receives the midi output ports once they have been registered.
+    midi_out_ports_receiver: crossbeam_channel::Receiver<Vec<jack::Port<jack::MidiOut>>>,
+    /// Requests for the client that are handled outside of the process thread.
+    requests: Arc<ClientRequests>,
+    /// Publishes the bpm to JACK when bats is the timebase master.
+    timebase_handler: Arc<TimebaseHandler>,
+    /// The JACK transport frame that is expected at the start of the next cycle if the JACK
+    /// transport has not been moved. `None` if the JACK transport is not being followed.
+    expected_jack_frame: Option<jack::Frames>,
+    /// True if the JACK transport was rolling during the last cycle. `None` if the JACK transport
+    /// is not being followed.
+    jack_rolling: Option<bool>,
 }
 
 impl ProcessHandler {
     /// Create a new `ProcessHandler` with ports registered from `c`.
-    pub fn new(c: &jack::Client, bats: Bats, commands: CommandReceiver) -> Result<ProcessHandler> {
+    /// Direct output ports and midi output ports are received from `direct_ports` and
+    /// `midi_out_ports` after they are requested through `requests`.
+    pub fn new(
+        c: &jack::Client,
+        core: Arc<Mutex<Core>>,
+        direct_ports: crossbeam_channel::Receiver<Vec<DirectPorts>>,
+        midi_out_ports: crossbeam_channel::Receiver<Vec<jack::Port<jack::MidiOut>>>,
+    ) -> Result<ProcessHandler> {
         Ok(ProcessHandler {
-            bats,
+            core,
             ports: Ports::new(c)?,
-            commands,
             midi_buffer: Vec::with_capacity(4096),
+            pending_sample_rate: Arc::new(AtomicU32::new(0)),
+            pending_xruns: Arc::new(AtomicUsize::new(0)),
+            direct_ports: Vec::new(),
+            direct_ports_receiver: direct_ports,
+            midi_out_ports: Vec::new(),
+            midi_out_ports_receiver: midi_out_ports,
+            requests: Arc::default(),
+            timebase_handler: Arc::default(),
+            expected_jack_frame: None,
+            jack_rolling: None,
         })
     }
 
+    /// Follow the JACK transport if it is enabled. Starting, stopping, and moving the JACK
+    /// transport is applied to bats. Starting or stopping bats starts or stops the JACK transport.
+    fn follow_jack_transport(
+        &mut self,
+        bats: &mut Bats,
+        client: &jack::Client,
+        frames: jack::Frames,
+    ) {
+        if !bats.transport_sync.follow {
+            self.expected_jack_frame = None;
+            self.jack_rolling = None;
+            return;
+        }
+        let transport = client.transport();
+        let jack::TransportStatePosition { pos, state } = match transport.query() {
+            Ok(p) => p,
+            Err(err) => {
+                error!("Failed to query JACK transport: {err}");
+                return;
+            }
+        };
+        let rolling = state == jack::TransportState::Rolling;
+        let started = rolling && self.jack_rolling != Some(true);
+        if self.jack_rolling != Some(rolling) {
+            bats.playing = rolling;
+        } else if bats.playing != rolling {
+            let res = if bats.playing {
+                transport.start()
+            } else {
+                transport.stop()
+            };
+            if let Err(err) = res {
+                error!("Failed to start or stop JACK transport: {err}");
+            }
+        }
+        self.jack_rolling = Some(rolling);
+        if !bats.transport_sync.timebase_master {
+            if let Some(bbt) = pos.bbt() {
+                let bpm = bbt.bpm as f32;
+                if bpm > 0.0 && bpm != bats.transport.bpm() {
+                    bats.transport.set_bpm(bats.sample_rate, bpm);
+                }
+            }
+        }
+        let frame = pos.frame();
+        if started || self.expected_jack_frame != Some(frame) {
+            let position = bats.transport.position_at_frame(frame as u64);
+            bats.transport.set_position(position);
+        }
+        self.expected_jack_frame = Some(if rolling {
+            frame.wrapping_add(frames)
+        } else {
+            frame
+        });
+    }
+
+    /// Copy the direct outputs from bats to the direct output ports. If direct outputs are enabled
+    /// but there are no ports, then the ports are requested.
+    fn write_direct_outputs(&mut self, bats: &Bats, ps: &jack::ProcessScope) {
+        if self.direct_ports.is_empty() {
+            if let Ok(ports) = self.direct_ports_receiver.try_recv() {
+                // Replacing an empty `Vec` does not deallocate.
+                self.direct_ports = ports;
+            } else if !bats.direct_outputs.is_empty() {
+                self.requests
+                    .direct_ports
+                    .store(bats.direct_outputs.len(), Ordering::Relaxed);
+                return;
+            }
+        }
+        for (idx, ports) in self.direct_ports.iter_mut().enumerate() {
+            let left = ports.left.as_mut_slice(ps);
+            let right = ports.right.as_mut_slice(ps);
+            match bats.direct_outputs.get(idx) {
+                Some(output) => {
+                    let len = left.len().min(output.len());
+                    left[..len].copy_from_slice(&output.left[..len]);
+                    right[..len].copy_from_slice(&output.right[..len]);
+                    left[len..].fill(0.0);
+                    right[len..].fill(0.0);
+                }
+                None => {
+                    left.fill(0.0);
+                    right.fill(0.0);
+                }
+            }
+        }
+    }
+
+    /// Write the midi output of each track to its midi output port. If a track sends midi to its
+    /// midi output but there are no ports, then the ports are requested.
+    fn write_midi_outputs(&mut self, bats: &Bats, ps: &jack::ProcessScope) {
+        if self.midi_out_ports.is_empty() {
+            if let Ok(ports) = self.midi_out_ports_receiver.try_recv() {
+                // Replacing an empty `Vec` does not deallocate.
+                self.midi_out_ports = ports;
+            } else if bats.tracks.iter().any(|t| t.midi_destination.to_midi_out()) {
+                self.requests
+                    .midi_out_ports
+                    .store(bats.tracks.len(), Ordering::Relaxed);
+            }
+        }
+        for (port, track) in self.midi_out_ports.iter_mut().zip(bats.tracks.iter()) {
+            let mut writer = port.writer(ps);
+            for (frame, msg) in track.midi_out.iter() {
+                let mut bytes = [0u8; 3];
+                let len = match msg.copy_to_slice(&mut bytes) {
+                    Ok(len) => len,
+                    Err(_) => continue,
+                };
+                let raw = jack::RawMidi {
+                    time: *frame,
+                    bytes: &bytes[..len],
+                };
+                if let Err(err) = writer.write(&raw) {
+                    error!("Failed to write midi output: {err}");
+                }
+            }
+        }
+    }
+
+    /// Write the midi that lights up the pads of the control surface to the control surface
+    /// output port.
+    fn write_control_surface_feedback(&mut self, bats: &Bats, ps: &jack::ProcessScope) {
+        let surface = match bats.control_surface.as_ref() {
+            Some(s) => s,
+            None => return,
+        };
+        let mut writer = self.ports.surface_out.writer(ps);
+        for (frame, msg) in surface.feedback.iter() {
+            let mut bytes = [0u8; 3];
+            let len = match msg.copy_to_slice(&mut bytes) {
+                Ok(len) => len,
+                Err(_) => continue,
+            };
+            let raw = jack::RawMidi {
+                time: *frame,
+                bytes: &bytes[..len],
+            };
+            if let Err(err) = writer.write(&raw) {
+                error!("Failed to write control surface feedback: {err}");
+            }
+        }
+    }
+
+    /// Create a `NotificationHandler` that forwards sample rate changes and xruns to this
+    /// `ProcessHandler`.
+    pub fn notification_handler(&self) -> NotificationHandler {
+        NotificationHandler {
+            pending_sample_rate: self.pending_sample_rate.clone(),
+            pending_xruns: self.pending_xruns.clone(),
+            is_shutdown: Arc::default(),
+        }
+    }
+
     /// Returns a function that connects this `ProcessHandler`'s
     /// virtual ports to physical ports.
     pub fn connector(&self) -> Result<Box<dyn Send + FnMut()>> {
@@ -63,20 +737,16 @@ impl ProcessHandler {
                 Some(jack::MidiOut.jack_port_type()),
                 jack::PortFlags::IS_TERMINAL | jack::PortFlags::IS_OUTPUT,
             );
+            // All physical midi inputs go to the first port. Other ports must be connected
+            // manually.
+            let midi_input = &virtual_ports.midi_inputs[0];
             for i in physical_midi_in {
-                let p = connector_client
-                    .port_by_name(&virtual_ports.midi_input)
-                    .unwrap();
+                let p = connector_client.port_by_name(midi_input).unwrap();
                 if p.is_connected_to(&i).unwrap_or(false) {
                     continue;
                 }
-                info!(
-                    "Connecting midi port {} to {}.",
-                    i, virtual_ports.midi_input
-                );
-                if let Err(err) =
-                    connector_client.connect_ports_by_name(&i, &virtual_ports.midi_input)
-                {
+                info!("Connecting midi port {} to {}.", i, midi_input);
+                if let Err(err) = connector_client.connect_ports_by_name(&i, midi_input) {
                     warn!("Failed to connect midi input: {}", err);
                 }
             }
@@ -86,25 +756,85 @@ impl ProcessHandler {
 
 impl jack::ProcessHandler for ProcessHandler {
     /// Process inputs and fill outputs.
-    fn process(&mut self, _: &jack::Client, ps: &jack::ProcessScope) -> jack::Control {
+    fn process(&mut self, client: &jack::Client, ps: &jack::ProcessScope) -> jack::Control {
         self.midi_buffer.clear();
-        for m in self.ports.midi.iter(ps) {
-            if let Ok(msg) = bmidi::MidiMessage::from_bytes(m.bytes) {
-                self.midi_buffer.push((m.time, msg));
+        for (port, midi) in self.ports.midi.iter().enumerate() {
+            for m in midi.iter(ps) {
+                if let Ok(msg) = bmidi::MidiMessage::from_bytes(m.bytes) {
+                    // Keep the buffer sorted by frame. Events on the same frame keep the order
+                    // that they were received in.
+                    let idx = self.midi_buffer.partition_point(|(_, t, _)| *t <= m.time);
+                    self.midi_buffer.insert(idx, (port, m.time, msg));
+                }
+            }
+        }
+        // Cloning the `Arc` does not allocate. It lets `core` be borrowed alongside `self`.
+        let core = self.core.clone();
+        let mut core = match core.try_lock() {
+            Ok(c) => c,
+            Err(_) => {
+                self.ports.left.as_mut_slice(ps).fill(0.0);
+                self.ports.right.as_mut_slice(ps).fill(0.0);
+                return jack::Control::Continue;
             }
+        };
+        let Core { bats, commands } = &mut *core;
+        let sample_rate = self.pending_sample_rate.swap(0, Ordering::Relaxed);
+        if sample_rate != 0 {
+            commands.set_sample_rate(bats, SampleRate::new(sample_rate as f32));
         }
-        self.commands.execute_all(&mut self.bats);
-        self.bats.process(
+        commands.execute_all(bats);
+        bats.handle_control_surface(
+            self.ports
+                .surface_in
+                .iter(ps)
+                .filter_map(|m| bmidi::MidiMessage::from_bytes(m.bytes).ok()),
+        );
+        self.follow_jack_transport(bats, client, ps.n_frames());
+        self.timebase_handler
+            .bpm
+            .store(bats.transport.bpm().to_bits(), Ordering::Relaxed);
+        self.requests
+            .timebase_master
+            .store(bats.transport_sync.timebase_master, Ordering::Relaxed);
+        bats.process_ports(
             self.midi_buffer.as_slice(),
             self.ports.left.as_mut_slice(ps),
             self.ports.right.as_mut_slice(ps),
         );
+        self.write_direct_outputs(bats, ps);
+        self.write_midi_outputs(bats, ps);
+        self.write_control_surface_feedback(bats, ps);
+        // Render captures faster than realtime.
+        self.requests
+            .freewheel
+            .store(bats.capture.is_some(), Ordering::Relaxed);
+        commands.publish_events(bats);
+        commands.set_cpu_load(client.cpu_load());
+        commands.add_xruns(self.pending_xruns.swap(0, Ordering::Relaxed));
+        commands.set_latency(self.ports.round_trip_latency());
+        jack::Control::Continue
+    }
+
+    /// Resize all buffers. JACK calls this outside of `process` so allocating is allowed.
+    fn buffer_size(&mut self, _: &jack::Client, size: jack::Frames) -> jack::Control {
+        info!("Buffer size set to {size}.");
+        let mut core = self.core.lock().unwrap();
+        let Core { bats, commands } = &mut *core;
+        commands.set_buffer_size(bats, size as usize);
         jack::Control::Continue
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-pub struct NotificationHandler {}
+#[derive(Clone, Debug)]
+pub struct NotificationHandler {
+    /// Where to store sample rate changes for the `ProcessHandler` to pick up.
+    pending_sample_rate: Arc<AtomicU32>,
+    /// Where to count xruns for the `ProcessHandler` to pick up.
+    pending_xruns: Arc<AtomicUsize>,
+    /// Set once the JACK server has shut down the client.
+    is_shutdown: Arc<AtomicBool>,
+}
 
 impl jack::NotificationHandler for NotificationHandler {
     fn thread_init(&self, _: &jack::Client) {
@@ -112,10 +842,11 @@ impl jack::NotificationHandler for NotificationHandler {
     }
 
     fn shutdown(&mut self, status: jack::ClientStatus, reason: &str) {
-        info!(
-            "Shutting down with status {:?} for reason: {}",
+        error!(
+            "JACK shut down the client with status {:?} for reason: {}",
             status, reason
         );
+        self.is_shutdown.store(true, Ordering::Relaxed);
     }
 
     fn freewheel(&mut self, _: &jack::Client, is_freewheel_enabled: bool) {
@@ -123,10 +854,9 @@ impl jack::NotificationHandler for NotificationHandler {
     }
 
     fn sample_rate(&mut self, _: &jack::Client, sample_rate: jack::Frames) -> jack::Control {
-        error!(
-            "Sample Rate set to {}. Changing sample rate is not supported.",
-            sample_rate
-        );
+        info!("Sample Rate set to {}.", sample_rate);
+        self.pending_sample_rate
+            .store(sample_rate, Ordering::Relaxed);
         jack::Control::Continue
     }
 
@@ -182,6 +912,7 @@ impl jack::NotificationHandler for NotificationHandler {
 
     fn xrun(&mut self, _: &jack::Client) -> jack::Control {
         error!("Buffer xrun occurred. This may cause dropped input and corrupted audio output.");
+        self.pending_xruns.fetch_add(1, Ordering::Relaxed);
         jack::Control::Continue
     }
 }
@@ -193,8 +924,12 @@ pub struct Ports {
     left: jack::Port<jack::AudioOut>,
     /// The right audio output buffer.
     right: jack::Port<jack::AudioOut>,
-    /// The midi input.
-    midi: jack::Port<jack::MidiIn>,
+    /// The midi inputs. Each one can be routed to a different track.
+    midi: Vec<jack::Port<jack::MidiIn>>,
+    /// The midi input for the pads of the control surface.
+    surface_in: jack::Port<jack::MidiIn>,
+    /// The midi output that lights up the pads of the control surface.
+    surface_out: jack::Port<jack::MidiOut>,
 }
 
 impl Ports {
@@ -203,24 +938,233 @@ impl Ports {
         Ok(Ports {
             left: c.register_port("left", jack::AudioOut)?,
             right: c.register_port("right", jack::AudioOut)?,
-            midi: c.register_port("midi", jack::MidiIn)?,
+            midi: (1..=Bats::MIDI_INPUT_PORT_COUNT)
+                .map(|i| c.register_port(&format!("midi_{i}"), jack::MidiIn))
+                .collect::<Result<_, _>>()?,
+            surface_in: c.register_port("control_surface_in", jack::MidiIn)?,
+            surface_out: c.register_port("control_surface_out", jack::MidiOut)?,
         })
     }
 
+    /// Get the worst case latency in frames from a midi input being captured to the audio output
+    /// being played back.
+    fn round_trip_latency(&self) -> u32 {
+        let capture = self
+            .midi
+            .iter()
+            .map(|p| p.get_latency_range(jack::LatencyType::Capture).1)
+            .max()
+            .unwrap_or(0);
+        let playback = [&self.left, &self.right]
+            .iter()
+            .map(|p| p.get_latency_range(jack::LatencyType::Playback).1)
+            .max()
+            .unwrap_or(0);
+        capture + playback
+    }
+
     /// Get all the port names.
     pub fn port_names(&self) -> Result<PortNames> {
         Ok(PortNames {
             audio_outputs: [self.left.name()?, self.right.name()?],
-            midi_input: self.midi.name()?,
+            midi_inputs: self
+                .midi
+                .iter()
+                .map(|p| p.name())
+                .collect::<Result<_, _>>()?,
         })
     }
 }
 
+/// Handles the JACK latency callback. Bats does not add any latency of its own so the capture
+/// latency of the midi inputs is reported on the audio outputs and the playback latency of the
+/// audio outputs is reported on the midi inputs.
+struct LatencyHandler {
+    /// The midi input ports.
+    inputs: Vec<jack::Port<jack::Unowned>>,
+    /// The audio output ports. Direct output ports are added as they are registered.
+    outputs: Arc<Mutex<Vec<jack::Port<jack::Unowned>>>>,
+}
+
+impl LatencyHandler {
+    /// Create a new `LatencyHandler` for `ports`.
+    fn new(c: &jack::Client, ports: &Ports) -> Result<LatencyHandler> {
+        let names = ports.port_names()?;
+        let port_by_name = |name: &String| {
+            c.port_by_name(name)
+                .ok_or_else(|| anyhow!("could not find port {name}"))
+        };
+        Ok(LatencyHandler {
+            inputs: names
+                .midi_inputs
+                .iter()
+                .map(port_by_name)
+                .collect::<Result<_>>()?,
+            outputs: Arc::new(Mutex::new(
+                names
+                    .audio_outputs
+                    .iter()
+                    .map(port_by_name)
+                    .collect::<Result<_>>()?,
+            )),
+        })
+    }
+
+    /// Register the latency callback for the client. Must be called before the client is
+    /// activated and `self` must outlive the client.
+    fn register(&self, c: &jack::Client) -> Result<()> {
+        let arg = self as *const LatencyHandler as *mut std::ffi::c_void;
+        // Safety: `self` is boxed by `JackBackend` and is only dropped after the client has been
+        // deactivated.
+        let res =
+            unsafe { jack_sys::jack_set_latency_callback(c.raw(), Some(latency_callback), arg) };
+        match res {
+            0 => Ok(()),
+            err => Err(anyhow!(
+                "failed to set JACK latency callback, error code {err}"
+            )),
+        }
+    }
+
+    /// Propagate the latency for `mode`.
+    fn latency(&self, mode: jack::LatencyType) {
+        let outputs = match self.outputs.lock() {
+            Ok(o) => o,
+            Err(_) => return,
+        };
+        let (from, to): (&[_], &[_]) = match mode {
+            jack::LatencyType::Capture => (&self.inputs, &outputs),
+            jack::LatencyType::Playback => (&outputs, &self.inputs),
+        };
+        let range = from
+            .iter()
+            .map(|p| p.get_latency_range(mode))
+            .reduce(|(a_min, a_max), (b_min, b_max)| (a_min.min(b_min), a_max.max(b_max)))
+            .unwrap_or((0, 0));
+        for port in to {
+            port.set_latency_range(mode, range);
+        }
+    }
+}
+
+/// The JACK latency callback. `arg` must point to a `LatencyHandler`.
+unsafe extern "C" fn latency_callback(
+    mode: jack_sys::jack_latency_callback_mode_t,
+    arg: *mut std::ffi::c_void,
+) {
+    let handler = &*(arg as *const LatencyHandler);
+    let mode = match mode {
+        jack_sys::JackCaptureLatency => jack::LatencyType::Capture,
+        _ => jack::LatencyType::Playback,
+    };
+    handler.latency(mode);
+}
+
+/// Publishes bar, beat, and tick information to the JACK transport when bats is the timebase
+/// master. The position is derived from the JACK transport frame so it runs linearly even when the
+/// bats transport loops.
+#[derive(Debug, Default)]
+pub struct TimebaseHandler {
+    /// The bpm stored as the bits of an `f32`.
+    bpm: AtomicU32,
+}
+
+impl TimebaseHandler {
+    /// The number of ticks in a beat.
+    const TICKS_PER_BEAT: f64 = 1920.0;
+
+    /// Become the timebase master of `c` if `master` is true or release it otherwise. `self` must
+    /// outlive the client.
+    fn set_master(&self, c: &jack::Client, master: bool) -> Result<()> {
+        let arg = self as *const TimebaseHandler as *mut std::ffi::c_void;
+        // Safety: `self` is owned by the `ProcessHandler` which is only dropped after the client
+        // has been deactivated.
+        let res = unsafe {
+            if master {
+                jack_sys::jack_set_timebase_callback(c.raw(), 0, Some(timebase_callback), arg)
+            } else {
+                jack_sys::jack_release_timebase(c.raw())
+            }
+        };
+        match res {
+            0 => {
+                info!("JACK timebase master set to {master}.");
+                Ok(())
+            }
+            err => Err(anyhow!(
+                "failed to set JACK timebase master to {master}, error code {err}"
+            )),
+        }
+    }
+
+    /// Fill in the bar, beat, and tick information for `pos`.
+    fn fill_position(&self, pos: &mut jack::TransportPosition) {
+        let bpm = f32::from_bits(self.bpm.load(Ordering::Relaxed)) as f64;
+        let frame_rate = match pos.frame_rate() {
+            Some(r) => r as f64,
+            None => return,
+        };
+        let beats_per_bar = Position::BEATS_PER_BAR as f64;
+        let beats = pos.frame() as f64 * bpm / (60.0 * frame_rate);
+        let bar = (beats / beats_per_bar).floor();
+        let beat = (beats - bar * beats_per_bar).floor();
+        let tick = (beats.fract() * TimebaseHandler::TICKS_PER_BEAT) as usize;
+        let bbt = jack::TransportBBT {
+            bar: bar as usize + 1,
+            beat: beat as usize + 1,
+            tick: tick.min(TimebaseHandler::TICKS_PER_BEAT as usize - 1),
+            sig_num: beats_per_bar as f32,
+            sig_denom: 4.0,
+            ticks_per_beat: TimebaseHandler::TICKS_PER_BEAT,
+            bpm,
+            bar_start_tick: bar * beats_per_bar * TimebaseHandler::TICKS_PER_BEAT,
+        };
+        let _ = pos.set_bbt(Some(bbt));
+    }
+}
+
+/// The JACK timebase callback. `arg` must point to a `TimebaseHandler`.
+unsafe extern "C" fn timebase_callback(
+    _state: jack_sys::jack_transport_state_t,
+    _nframes: jack_sys::jack_nframes_t,
+    pos: *mut jack_sys::jack_position_t,
+    _new_pos: std::ffi::c_int,
+    arg: *mut std::ffi::c_void,
+) {
+    let handler = &*(arg as *const TimebaseHandler);
+    // `TransportPosition` is a transparent wrapper around `jack_position_t`.
+    let pos = &mut *(pos as *mut jack::TransportPosition);
+    handler.fill_position(pos);
+}
+
+/// The direct output ports for a single track.
+#[derive(Debug)]
+pub struct DirectPorts {
+    /// The left audio output.
+    left: jack::Port<jack::AudioOut>,
+    /// The right audio output.
+    right: jack::Port<jack::AudioOut>,
+}
+
+impl DirectPorts {
+    /// Register the direct output ports for `track_count` tracks.
+    fn register_all(c: &jack::Client, track_count: usize) -> Result<Vec<DirectPorts>> {
+        (1..=track_count)
+            .map(|n| {
+                Ok(DirectPorts {
+                    left: c.register_port(&format!("track_{n}_left"), jack::AudioOut)?,
+                    right: c.register_port(&format!("track_{n}_right"), jack::AudioOut)?,
+                })
+            })
+            .collect()
+    }
+}
+
 /// Holds all the ports by name.
 #[derive(Debug)]
 pub struct PortNames {
     /// The audio output ports.
     pub audio_outputs: [String; 2],
-    /// The midi input port.
-    pub midi_input: String,
+    /// The midi input ports.
+    pub midi_inputs: Vec<String>,
 }
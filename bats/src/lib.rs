@@ -0,0 +1,9 @@
+pub use engine::Engine;
+
+pub mod backend;
+pub mod config;
+#[cfg(feature = "cpal")]
+pub mod cpal_adapter;
+pub mod engine;
+pub mod jack_adapter;
+pub mod nsm;
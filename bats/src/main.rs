@@ -1,79 +1,124 @@
-use anyhow::Result;
-use bats_async::new_async_commander;
-use bats_dsp::sample_rate::SampleRate;
-use bats_lib::{builder::BatsBuilder, Bats};
-use clap::Parser;
-use log::{error, info};
+use std::path::Path;
 
-use crate::jack_adapter::NotificationHandler;
+use anyhow::{anyhow, Result};
+use bats::{backend::AudioBackend, config::Config, jack_adapter, nsm::NsmClient, Engine};
+use bats_async::command::Command;
+use bats_lib::{
+    builder::BatsBuilder, control_surface::ControlSurface, plugin::soundfont,
+    registry::RegisteredPlugin,
+};
+use clap::Parser;
+use log::{info, warn};
 
 pub mod args;
-pub mod jack_adapter;
 
 fn main() -> Result<()> {
+    // Plugins must be registered before parsing args since tracks may use them.
+    let (plugins, plugin_errors) = register_plugins();
     let args = args::Args::parse();
     env_logger::builder()
         .filter_level(args.log_level)
         .try_init()
         .unwrap();
+    info!(
+        "Registered plugins: {:?}",
+        plugins.iter().map(|p| p.name()).collect::<Vec<_>>()
+    );
+    for err in plugin_errors {
+        warn!("{err:#}");
+    }
     info!("Parsed args: {:?}", args);
     info!("Current Dir: {:?}", std::env::current_dir().unwrap(),);
     info!("Raw args: {:?}", std::env::args());
     info!("Pared args: {:?}", args);
 
-    let (client, status) = jack::Client::new("bats", jack::ClientOptions::NO_START_SERVER)?;
-    info!("Started JACK client {:?}.", client);
-    info!("JACK status is {:?}", status);
+    let config = args.load_config()?;
+    info!("Loaded config: {:?}", config);
+
+    let session = NsmClient::from_env()?;
+    let (client_name, project_path) = match &session {
+        // A session starts with no project file until it is first saved.
+        Some((_, open)) => (
+            open.client_id.as_str(),
+            Some(open.project.clone()).filter(|p| p.exists()),
+        ),
+        None => ("bats", args.project.clone()),
+    };
+    let project = match project_path.map(|p| load_project(&p)).transpose() {
+        Ok(p) => p,
+        Err(err) => {
+            if let Some((client, _)) = &session {
+                client.reply_open(&Err(anyhow!("{err}")))?;
+            }
+            return Err(err);
+        }
+    };
+    let (bpm, tracks) = match project {
+        Some(mut p) => {
+            args.apply_to_project(&mut p);
+            (p.bpm, p.tracks)
+        }
+        None => (config.bpm, config.track_builders()),
+    };
 
-    let bats = make_bats(&client);
-    let (command_sender, command_receiver) = new_async_commander();
-    let mut ui = bats_ui::Ui::new(&bats, command_sender)?;
-    let process_handler = jack_adapter::ProcessHandler::new(&client, bats, command_receiver)?;
-    let maybe_connector = maybe_make_connector(&process_handler, args.auto_connect);
-    let client = client.activate_async(NotificationHandler {}, process_handler)?;
-    spawn_connector_daemon(maybe_connector);
+    let backend = make_backend(args.backend, &config, client_name)?;
+    info!("Using {} audio backend.", backend.name());
+    let mut engine = Engine::new(backend, bpm, tracks);
+    if let Some(profile) = config.control_surface.clone() {
+        let surface = Box::new(ControlSurface::new(profile));
+        engine
+            .commands()
+            .send(Command::SetControlSurface(Some(surface)))?;
+    }
+    let bats = engine
+        .bats()
+        .ok_or_else(|| anyhow!("Engine was started before the UI was created."))?;
+    let mut ui = bats_ui::Ui::new(bats, engine.commands().clone(), config.ui)?;
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, ui.handle().quit_flag())?;
+    engine.start()?;
+    if let Some((client, open)) = session {
+        client.reply_open(&Ok(()))?;
+        client.spawn_handler(ui.handle(), open.project);
+    }
 
     ui.run()?;
     info!("Exiting bats!");
-    client.deactivate()?;
+    engine.stop()?;
     Ok(())
 }
 
-fn make_bats(client: &jack::Client) -> Bats {
-    BatsBuilder {
-        sample_rate: SampleRate::new(client.sample_rate() as f32),
-        buffer_size: client.buffer_size() as usize,
-        bpm: 120.0,
-        tracks: Default::default(),
+/// Register the installed SoundFonts and, with the `vst3` feature, VST3 instruments. Returns the
+/// registered plugins and the errors for plugins that could not be registered.
+fn register_plugins() -> (Vec<RegisteredPlugin>, Vec<anyhow::Error>) {
+    #[allow(unused_mut)]
+    let (mut plugins, mut errors) =
+        soundfont::register_soundfonts(&soundfont::default_search_paths());
+    #[cfg(all(feature = "vst3", target_os = "linux"))]
+    {
+        use bats_lib::plugin::vst3;
+        let (vst3_plugins, vst3_errors) = vst3::register_instruments(&vst3::default_search_paths());
+        plugins.extend(vst3_plugins);
+        errors.extend(vst3_errors);
     }
-    .build()
+    (plugins, errors)
 }
 
-fn maybe_make_connector(
-    process_handler: &jack_adapter::ProcessHandler,
-    enable_connector: bool,
-) -> Option<Box<dyn Send + FnMut()>> {
-    if enable_connector {
-        Some(match process_handler.connector() {
-            Ok(f) => f,
-            Err(err) => {
-                error!("Failed to create port connector! IO ports will have to be connected manually. Error: {}", err);
-                Box::new(|| {})
-            }
-        })
-    } else {
-        None
-    }
+fn load_project(path: &Path) -> Result<BatsBuilder> {
+    info!("Loading project from {path:?}.");
+    BatsBuilder::load(path).map_err(|err| anyhow!("failed to load project {path:?}: {err}"))
 }
 
-fn spawn_connector_daemon(connector: Option<Box<dyn Send + FnMut()>>) {
-    if let Some(mut connector) = connector {
-        std::thread::spawn(move || {
-            std::thread::sleep(std::time::Duration::from_secs(1));
-            loop {
-                connector();
-                std::thread::sleep(std::time::Duration::from_secs(5));
-            }
-        });
+fn make_backend(
+    backend: args::Backend,
+    config: &Config,
+    client_name: &str,
+) -> Result<Box<dyn AudioBackend>> {
+    match backend {
+        args::Backend::Jack => Ok(Box::new(jack_adapter::JackBackend::new(
+            client_name,
+            config.auto_connect,
+        )?)),
+        #[cfg(feature = "cpal")]
+        args::Backend::Cpal => Ok(Box::new(bats::cpal_adapter::CpalBackend::new()?)),
     }
 }
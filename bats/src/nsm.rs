@@ -0,0 +1,359 @@
+use std::{
+    ffi::OsString,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use bats_ui::handle::UiHandle;
+use log::{error, info, warn};
+
+/// A client for the Non/New Session Manager (NSM). NSM starts bats with the `NSM_URL` environment
+/// variable set to the address of the session server. The server tells bats where to load and
+/// save its project and quits bats with `SIGTERM`.
+///
+/// See <https://new-session-manager.jackaudio.org/api/index.html> for the protocol.
+pub struct NsmClient {
+    /// The socket for talking to the session server.
+    socket: UdpSocket,
+    /// The address of the session server.
+    server: SocketAddr,
+}
+
+/// The project that the session server asked bats to open.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NsmOpen {
+    /// The path of the project file.
+    pub project: PathBuf,
+    /// The name to use for the JACK client.
+    pub client_id: String,
+}
+
+impl NsmClient {
+    /// The major version of the NSM API that is implemented.
+    const API_MAJOR: i32 = 1;
+    /// The minor version of the NSM API that is implemented.
+    const API_MINOR: i32 = 2;
+    /// How long to wait for the session server to ask bats to open a project.
+    const OPEN_TIMEOUT: Duration = Duration::from_secs(10);
+    /// How long to wait for the UI to save the project.
+    const SAVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Announce bats to the session server at `NSM_URL` and wait for it to ask bats to open a
+    /// project. Returns `None` if bats was not started by a session manager.
+    pub fn from_env() -> Result<Option<(NsmClient, NsmOpen)>> {
+        let url = match std::env::var("NSM_URL") {
+            Ok(url) => url,
+            Err(_) => return Ok(None),
+        };
+        let client = NsmClient::connect(&url)?;
+        client.announce()?;
+        let open = client.wait_for_open()?;
+        Ok(Some((client, open)))
+    }
+
+    /// Connect to the session server at `url`, which looks like `osc.udp://host:port/`.
+    fn connect(url: &str) -> Result<NsmClient> {
+        let address = url
+            .strip_prefix("osc.udp://")
+            .map(|a| a.trim_end_matches('/'))
+            .ok_or_else(|| anyhow!("NSM_URL {url:?} is not an osc.udp url"))?;
+        let server = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("could not resolve NSM_URL {url:?}"))?;
+        let local: SocketAddr = if server.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        Ok(NsmClient {
+            socket: UdpSocket::bind(local)?,
+            server,
+        })
+    }
+
+    /// Send `message` to the session server.
+    fn send(&self, message: &OscMessage) -> Result<()> {
+        self.socket.send_to(&message.encode(), self.server)?;
+        Ok(())
+    }
+
+    /// Wait for the next message from the session server.
+    fn recv(&self) -> Result<OscMessage> {
+        let mut buffer = [0u8; 4096];
+        loop {
+            let (len, from) = self.socket.recv_from(&mut buffer)?;
+            if from != self.server {
+                warn!(
+                    "Ignoring OSC message from {from}, expected messages from {}.",
+                    self.server
+                );
+                continue;
+            }
+            match OscMessage::decode(&buffer[..len]) {
+                Ok(m) => return Ok(m),
+                Err(err) => warn!("Ignoring invalid OSC message: {err}"),
+            }
+        }
+    }
+
+    /// Announce bats to the session server.
+    fn announce(&self) -> Result<()> {
+        let executable = std::env::args()
+            .next()
+            .and_then(|a| {
+                PathBuf::from(a)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "bats".to_string());
+        self.send(&OscMessage::new(
+            "/nsm/server/announce",
+            vec![
+                OscArg::Str("bats".to_string()),
+                OscArg::Str(":".to_string()),
+                OscArg::Str(executable),
+                OscArg::Int(NsmClient::API_MAJOR),
+                OscArg::Int(NsmClient::API_MINOR),
+                OscArg::Int(std::process::id() as i32),
+            ],
+        ))
+    }
+
+    /// Wait for the session server to ask bats to open a project.
+    fn wait_for_open(&self) -> Result<NsmOpen> {
+        self.socket
+            .set_read_timeout(Some(NsmClient::OPEN_TIMEOUT))?;
+        loop {
+            let message = self.recv()?;
+            match (message.path.as_str(), message.args.as_slice()) {
+                ("/reply", [OscArg::Str(path), OscArg::Str(reply), ..])
+                    if path == "/nsm/server/announce" =>
+                {
+                    info!("Announced to the session manager: {reply}");
+                }
+                ("/error", [OscArg::Str(path), OscArg::Int(code), OscArg::Str(reason), ..])
+                    if path == "/nsm/server/announce" =>
+                {
+                    return Err(anyhow!(
+                        "session manager rejected bats with error {code}: {reason}"
+                    ));
+                }
+                (
+                    "/nsm/client/open",
+                    [OscArg::Str(path), OscArg::Str(_display_name), OscArg::Str(client_id), ..],
+                ) => {
+                    self.socket.set_read_timeout(None)?;
+                    // The path is a prefix for the files of the client, not a file name.
+                    let mut project = OsString::from(path);
+                    project.push(".toml");
+                    return Ok(NsmOpen {
+                        project: project.into(),
+                        client_id: client_id.clone(),
+                    });
+                }
+                _ => warn!("Ignoring unexpected message from the session manager: {message:?}"),
+            }
+        }
+    }
+
+    /// Reply to the open request from the session server.
+    pub fn reply_open(&self, result: &Result<()>) -> Result<()> {
+        self.reply("/nsm/client/open", result)
+    }
+
+    /// Reply to the request at `path` from the session server with the outcome in `result`.
+    fn reply(&self, path: &str, result: &Result<()>) -> Result<()> {
+        let message = match result {
+            Ok(()) => OscMessage::new(
+                "/reply",
+                vec![OscArg::Str(path.to_string()), OscArg::Str("OK".to_string())],
+            ),
+            Err(err) => OscMessage::new(
+                "/error",
+                vec![
+                    OscArg::Str(path.to_string()),
+                    // The general error code.
+                    OscArg::Int(-1),
+                    OscArg::Str(err.to_string()),
+                ],
+            ),
+        };
+        self.send(&message)
+    }
+
+    /// Spawn a thread that saves the project to `project` through `ui` whenever the session
+    /// server asks bats to save.
+    pub fn spawn_handler(self, ui: UiHandle, project: PathBuf) {
+        std::thread::spawn(move || loop {
+            let message = match self.recv() {
+                Ok(m) => m,
+                Err(err) => {
+                    error!("Stopped listening to the session manager: {err}");
+                    return;
+                }
+            };
+            let res = match message.path.as_str() {
+                "/nsm/client/save" => {
+                    let res = ui
+                        .save_project(project.clone())
+                        .recv_timeout(NsmClient::SAVE_TIMEOUT)
+                        .unwrap_or_else(|err| Err(anyhow!("failed to save project: {err}")));
+                    self.reply("/nsm/client/save", &res)
+                }
+                "/nsm/client/open" => self.reply(
+                    "/nsm/client/open",
+                    &Err(anyhow!("bats can not switch projects while running")),
+                ),
+                _ => {
+                    info!("Ignoring message from the session manager: {message:?}");
+                    Ok(())
+                }
+            };
+            if let Err(err) = res {
+                error!("Failed to reply to the session manager: {err}");
+            }
+        });
+    }
+}
+
+/// An argument of an OSC message. Only the types used by NSM are supported.
+#[derive(Clone, Debug, PartialEq)]
+enum OscArg {
+    /// A 32 bit integer.
+    Int(i32),
+    /// A string.
+    Str(String),
+}
+
+/// An OSC message.
+#[derive(Clone, Debug, PartialEq)]
+struct OscMessage {
+    /// The address pattern, like `/nsm/client/save`.
+    path: String,
+    /// The arguments.
+    args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    /// Create a new message for `path` with `args`.
+    fn new(path: &str, args: Vec<OscArg>) -> OscMessage {
+        OscMessage {
+            path: path.to_string(),
+            args,
+        }
+    }
+
+    /// Encode the message as an OSC packet.
+    fn encode(&self) -> Vec<u8> {
+        let mut packet = Vec::new();
+        write_osc_string(&mut packet, &self.path);
+        let tags: String = std::iter::once(',')
+            .chain(self.args.iter().map(|a| match a {
+                OscArg::Int(_) => 'i',
+                OscArg::Str(_) => 's',
+            }))
+            .collect();
+        write_osc_string(&mut packet, &tags);
+        for arg in self.args.iter() {
+            match arg {
+                OscArg::Int(i) => packet.extend_from_slice(&i.to_be_bytes()),
+                OscArg::Str(s) => write_osc_string(&mut packet, s),
+            }
+        }
+        packet
+    }
+
+    /// Decode an OSC packet. Bundles and argument types other than integers and strings are not
+    /// supported.
+    fn decode(packet: &[u8]) -> Result<OscMessage> {
+        let mut rest = packet;
+        let path = read_osc_string(&mut rest)?;
+        if !path.starts_with('/') {
+            return Err(anyhow!("unsupported OSC packet {path:?}"));
+        }
+        let tags = if rest.is_empty() {
+            ",".to_string()
+        } else {
+            read_osc_string(&mut rest)?
+        };
+        let tags = tags
+            .strip_prefix(',')
+            .ok_or_else(|| anyhow!("invalid OSC type tags {tags:?}"))?;
+        let args = tags
+            .chars()
+            .map(|tag| match tag {
+                'i' => {
+                    let bytes = rest
+                        .get(..4)
+                        .ok_or_else(|| anyhow!("OSC integer is truncated"))?;
+                    rest = &rest[4..];
+                    Ok(OscArg::Int(i32::from_be_bytes(bytes.try_into()?)))
+                }
+                's' => Ok(OscArg::Str(read_osc_string(&mut rest)?)),
+                tag => Err(anyhow!("unsupported OSC type tag {tag:?}")),
+            })
+            .collect::<Result<_>>()?;
+        Ok(OscMessage { path, args })
+    }
+}
+
+/// Write `s` as a null terminated string padded to a multiple of 4 bytes.
+fn write_osc_string(packet: &mut Vec<u8>, s: &str) {
+    packet.extend_from_slice(s.as_bytes());
+    let padding = 4 - s.len() % 4;
+    packet.extend(std::iter::repeat_n(0, padding));
+}
+
+/// Read a null terminated string that is padded to a multiple of 4 bytes from the start of
+/// `rest` and advance `rest` past it.
+fn read_osc_string(rest: &mut &[u8]) -> Result<String> {
+    let len = rest
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| anyhow!("OSC string is not terminated"))?;
+    let s = std::str::from_utf8(&rest[..len])?.to_string();
+    let padded_len = (len / 4 + 1) * 4;
+    *rest = rest.get(padded_len..).unwrap_or(&[]);
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_pads_strings_and_uses_big_endian_integers() {
+        let message = OscMessage::new(
+            "/reply",
+            vec![OscArg::Str("save".to_string()), OscArg::Int(-1)],
+        );
+        let mut expected = Vec::new();
+        expected.extend_from_slice(b"/reply\0\0");
+        expected.extend_from_slice(b",si\0");
+        expected.extend_from_slice(b"save\0\0\0\0");
+        expected.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(message.encode(), expected);
+    }
+
+    #[test]
+    fn decode_reverses_encode() {
+        let message = OscMessage::new(
+            "/nsm/client/open",
+            vec![
+                OscArg::Str("/sessions/song/bats.nABCD".to_string()),
+                OscArg::Str("bats".to_string()),
+                OscArg::Str("bats.nABCD".to_string()),
+                OscArg::Int(7),
+            ],
+        );
+        assert_eq!(OscMessage::decode(&message.encode()).unwrap(), message);
+        assert_eq!(
+            OscMessage::decode(b"/nsm/client/save\0\0\0\0").unwrap(),
+            OscMessage::new("/nsm/client/save", Vec::new())
+        );
+        assert!(OscMessage::decode(b"#bundle\0").is_err());
+    }
+}
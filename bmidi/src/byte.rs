@@ -15,12 +15,31 @@ impl U7 {
     /// The maximum value for a u7 data byte.
     pub const MAX: U7 = U7(0x80 - 0x01);
 
+    /// Create a `U7` from a `u8`. Returns `None` if `data` is greater than 127. Can be used in
+    /// `const` contexts.
+    ///
+    /// ```
+    /// const VELOCITY: bmidi::U7 = match bmidi::U7::new(100) {
+    ///     Some(v) => v,
+    ///     None => panic!(),
+    /// };
+    /// assert_eq!(u8::from(VELOCITY), 100);
+    /// ```
+    #[inline(always)]
+    pub const fn new(data: u8) -> Option<U7> {
+        if data > U7::MAX.0 {
+            None
+        } else {
+            Some(U7(data))
+        }
+    }
+
     /// Convert a `u8` into a `U7` without bounds checking.
     ///
     /// # Safety
     /// Behavior is undefined if data > 127.
     #[inline(always)]
-    pub unsafe fn from_unchecked(data: u8) -> U7 {
+    pub const unsafe fn from_unchecked(data: u8) -> U7 {
         U7(data)
     }
 
@@ -89,12 +108,29 @@ impl U14 {
     /// The maximum value for a u7 data byte.
     pub const MAX: U14 = U14(0x4000 - 0x0001);
 
-    /// Convert a `u8` into a `U7` without bounds checking.
+    /// Create a `U14` from a `u16`. Returns `None` if `data` is greater than 16383. Can be used in
+    /// `const` contexts.
+    #[inline(always)]
+    pub const fn new(data: u16) -> Option<U14> {
+        if data > U14::MAX.0 {
+            None
+        } else {
+            Some(U14(data))
+        }
+    }
+
+    /// Create a `U14` from a `u16`. Only the 14 least significant bits of `data` are kept.
+    #[inline(always)]
+    pub const fn from_u16_lossy(data: u16) -> U14 {
+        U14(data & 0x3FFF)
+    }
+
+    /// Convert a `u16` into a `U14` without bounds checking.
     ///
     /// # Safety
     /// Behavior is undefined if data is > 16383.
     #[inline(always)]
-    pub unsafe fn from_unchecked(data: u16) -> U14 {
+    pub const unsafe fn from_unchecked(data: u16) -> U14 {
         U14(data)
     }
 
@@ -164,7 +200,7 @@ mod tests {
 
     #[test]
     fn try_from_out_of_range_fails() {
-        for n in 0x80..=std::u8::MAX {
+        for n in 0x80..=u8::MAX {
             assert_eq!(U7::try_from(n), Err(Error::DataByteOutOfRange));
         }
     }
@@ -210,7 +246,7 @@ mod tests {
 
     #[test]
     fn try_from_out_of_range_16_fails() {
-        for n in 0x4000..=std::u16::MAX {
+        for n in 0x4000..=u16::MAX {
             assert_eq!(U14::try_from(n), Err(Error::U14OutOfRange));
         }
     }
@@ -241,6 +277,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn new_checks_range() {
+        assert_eq!(U7::new(127), Some(U7::MAX));
+        assert_eq!(U7::new(128), None);
+        assert_eq!(U14::new(16383), Some(U14::MAX));
+        assert_eq!(U14::new(16384), None);
+        assert_eq!(U14::from_u16_lossy(16384), U14::MIN);
+    }
+
     #[test]
     fn test_from_u8_lossy() {
         assert_eq!(U7::from_u8_lossy(0), U7::try_from(0).unwrap());
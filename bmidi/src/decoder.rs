@@ -0,0 +1,201 @@
+use crate::MidiMessage;
+
+/// Decodes a stream of MIDI bytes, such as the bytes read from ALSA raw MIDI or a serial DIN port,
+/// that may be split at any point into chunks. Messages that span chunks are held until they are
+/// complete.
+///
+/// Running status is supported for channel messages. Realtime messages, like `TimingClock`, may
+/// appear between any two bytes, including within another message, and are yielded immediately.
+/// SysEx data is skipped and yielded as `MidiMessage::SysEx` once the end byte is received.
+///
+/// # Example
+/// ```
+/// use bmidi::{Channel, MidiDecoder, MidiMessage, Note, U7};
+/// let mut decoder = MidiDecoder::new();
+/// assert_eq!(decoder.decode(&[0x90, 0x3C]).next(), None);
+/// assert_eq!(
+///     decoder.decode(&[0x7F]).collect::<Vec<_>>(),
+///     vec![MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX)]
+/// );
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MidiDecoder {
+    /// The status byte of the message being decoded or `None` if there is no status byte.
+    status: Option<u8>,
+    /// The data bytes that have been received for the current message.
+    data: [u8; 2],
+    /// The number of bytes in `data`.
+    len: usize,
+}
+
+impl MidiDecoder {
+    /// Create a new decoder that has not received any bytes.
+    pub const fn new() -> MidiDecoder {
+        MidiDecoder {
+            status: None,
+            data: [0; 2],
+            len: 0,
+        }
+    }
+
+    /// Decode the next chunk of bytes. Returns an iterator over the messages that are completed by
+    /// `bytes`. Any incomplete message at the end of `bytes` is completed by later chunks.
+    pub fn decode<'a>(&'a mut self, bytes: &'a [u8]) -> MidiBytesIter<'a> {
+        MidiBytesIter {
+            decoder: self,
+            bytes: bytes.iter(),
+        }
+    }
+
+    /// Add a single byte. Returns the message if `byte` completes one.
+    pub fn push(&mut self, byte: u8) -> Option<MidiMessage> {
+        if byte >= 0xF8 {
+            // Realtime messages do not interrupt the current message.
+            return MidiMessage::from_bytes(&[byte]).ok();
+        }
+        if byte & 0x80 == 0x80 {
+            return self.push_status(byte);
+        }
+        let status = self.status?;
+        if status == 0xF0 {
+            // Skip SysEx data.
+            return None;
+        }
+        self.data[self.len] = byte;
+        self.len += 1;
+        if self.len < data_len(status) {
+            return None;
+        }
+        let len = self.len;
+        self.len = 0;
+        if status >= 0xF0 {
+            // System common messages do not support running status.
+            self.status = None;
+        }
+        let bytes = [status, self.data[0], self.data[1]];
+        MidiMessage::from_bytes(&bytes[..1 + len]).ok()
+    }
+
+    /// Start a new message with the status byte `status`.
+    fn push_status(&mut self, status: u8) -> Option<MidiMessage> {
+        let in_sysex = self.status == Some(0xF0);
+        self.len = 0;
+        self.status = Some(status);
+        match status {
+            0xF7 => {
+                self.status = None;
+                in_sysex.then_some(MidiMessage::SysEx)
+            }
+            0xF0 => None,
+            _ if data_len(status) == 0 => {
+                self.status = None;
+                MidiMessage::from_bytes(&[status]).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The number of data bytes that follow the status byte `status`.
+fn data_len(status: u8) -> usize {
+    match status {
+        0x80..=0xBF | 0xE0..=0xEF | 0xF2 => 2,
+        0xC0..=0xDF | 0xF1 | 0xF3 => 1,
+        _ => 0,
+    }
+}
+
+/// Iterates over the messages decoded from a chunk of bytes. Created with `MidiDecoder::decode`.
+#[derive(Debug)]
+pub struct MidiBytesIter<'a> {
+    /// The decoder that holds incomplete messages between chunks.
+    decoder: &'a mut MidiDecoder,
+    /// The remaining bytes of the chunk.
+    bytes: core::slice::Iter<'a, u8>,
+}
+
+impl Iterator for MidiBytesIter<'_> {
+    type Item = MidiMessage;
+
+    fn next(&mut self) -> Option<MidiMessage> {
+        for byte in self.bytes.by_ref() {
+            if let Some(message) = self.decoder.push(*byte) {
+                return Some(message);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Channel, ControlFunction, Note, U7};
+
+    fn decode_chunks(chunks: &[&[u8]]) -> Vec<MidiMessage> {
+        let mut decoder = MidiDecoder::new();
+        chunks
+            .iter()
+            .flat_map(|chunk| decoder.decode(chunk).collect::<Vec<_>>())
+            .collect()
+    }
+
+    #[test]
+    fn messages_are_decoded_across_chunks() {
+        assert_eq!(
+            decode_chunks(&[&[0x91], &[0x40, 0x7F, 0xB1, 0x01], &[0x10]]),
+            vec![
+                MidiMessage::NoteOn(Channel::Ch2, Note::E4, U7::MAX),
+                MidiMessage::ControlChange(
+                    Channel::Ch2,
+                    ControlFunction::MODULATION_WHEEL,
+                    U7::from_u8_lossy(0x10)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn running_status_repeats_channel_messages() {
+        assert_eq!(
+            decode_chunks(&[&[0x90, 0x3C, 0x40, 0x3E], &[0x40, 0x3C, 0x00]]),
+            vec![
+                MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::from_u8_lossy(0x40)),
+                MidiMessage::NoteOn(Channel::Ch1, Note::D4, U7::from_u8_lossy(0x40)),
+                MidiMessage::NoteOff(Channel::Ch1, Note::C4, U7::MIN),
+            ]
+        );
+    }
+
+    #[test]
+    fn realtime_messages_are_yielded_within_other_messages() {
+        assert_eq!(
+            decode_chunks(&[&[0x90, 0xF8, 0x3C], &[0xFA, 0x40]]),
+            vec![
+                MidiMessage::TimingClock,
+                MidiMessage::Start,
+                MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::from_u8_lossy(0x40)),
+            ]
+        );
+    }
+
+    #[test]
+    fn sysex_data_is_skipped() {
+        assert_eq!(
+            decode_chunks(&[&[0xF0, 0x7E, 0x00], &[0xF8, 0x06, 0xF7, 0xC0, 0x05]]),
+            vec![
+                MidiMessage::TimingClock,
+                MidiMessage::SysEx,
+                MidiMessage::ProgramChange(Channel::Ch1, U7::from_u8_lossy(0x05)),
+            ]
+        );
+    }
+
+    #[test]
+    fn data_without_status_is_ignored() {
+        assert_eq!(
+            decode_chunks(&[&[0x3C, 0x40, 0xF6, 0x01]]),
+            vec![MidiMessage::TuneRequest]
+        );
+    }
+}
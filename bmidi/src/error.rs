@@ -1,4 +1,4 @@
-use std::{error::Error, fmt};
+use core::fmt;
 
 /// Midi decoding errors.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -38,7 +38,8 @@ pub enum FromBytesError {
     U14OutOfRange,
 }
 
-impl Error for FromBytesError {}
+#[cfg(feature = "std")]
+impl std::error::Error for FromBytesError {}
 
 impl fmt::Display for FromBytesError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -53,7 +54,8 @@ pub enum ToSliceError {
     BufferTooSmall,
 }
 
-impl Error for ToSliceError {}
+#[cfg(feature = "std")]
+impl std::error::Error for ToSliceError {}
 
 impl fmt::Display for ToSliceError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
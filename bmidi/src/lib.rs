@@ -1,16 +1,22 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
 mod byte;
 mod cc;
+mod decoder;
 mod error;
 mod midi_message;
 mod note;
+mod transform;
 
 pub use byte::{U14, U7};
 pub use cc::ControlFunction;
+pub use decoder::{MidiBytesIter, MidiDecoder};
 pub use error::{FromBytesError, ToSliceError};
 pub use midi_message::{
     Channel, ControlValue, MidiMessage, PitchBend, ProgramNumber, Song, SongPosition, Velocity,
 };
 pub use note::Note;
+pub use transform::MidiIterExt;
 
 /// Use `FromBytesError` instead.
 pub type Error = FromBytesError;
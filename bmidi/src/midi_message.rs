@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use crate::{ControlFunction, Error, Note, ToSliceError, U14, U7};
 use core::convert::TryFrom;
 
+#[cfg(feature = "std")]
 use std::io;
 
 /// Holds information based on the Midi 1.0 spec.
@@ -143,6 +144,54 @@ impl TryFrom<&[u8]> for MidiMessage {
     }
 }
 
+impl MidiMessage {
+    /// Create a note on message. A velocity of `0` is sent as a note on but is decoded as a note
+    /// off by `MidiMessage::from_bytes`.
+    ///
+    /// ```
+    /// use bmidi::{Channel, MidiMessage, Note, U7};
+    /// const MIDDLE_C: MidiMessage = MidiMessage::note_on(Channel::Ch1, Note::C4, U7::MAX);
+    /// ```
+    #[inline(always)]
+    pub const fn note_on(channel: Channel, note: Note, velocity: Velocity) -> MidiMessage {
+        MidiMessage::NoteOn(channel, note, velocity)
+    }
+
+    /// Create a note off message.
+    #[inline(always)]
+    pub const fn note_off(channel: Channel, note: Note, velocity: Velocity) -> MidiMessage {
+        MidiMessage::NoteOff(channel, note, velocity)
+    }
+
+    /// Create a control change message.
+    #[inline(always)]
+    pub const fn cc(
+        channel: Channel,
+        function: ControlFunction,
+        value: ControlValue,
+    ) -> MidiMessage {
+        MidiMessage::ControlChange(channel, function, value)
+    }
+
+    /// Create a program change message.
+    #[inline(always)]
+    pub const fn program_change(channel: Channel, program: ProgramNumber) -> MidiMessage {
+        MidiMessage::ProgramChange(channel, program)
+    }
+
+    /// Create a channel pressure message.
+    #[inline(always)]
+    pub const fn channel_pressure(channel: Channel, pressure: Velocity) -> MidiMessage {
+        MidiMessage::ChannelPressure(channel, pressure)
+    }
+
+    /// Create a pitch bend message. `8192` is no bend.
+    #[inline(always)]
+    pub const fn pitch_bend(channel: Channel, bend: PitchBend) -> MidiMessage {
+        MidiMessage::PitchBendChange(channel, bend)
+    }
+}
+
 impl<'a> MidiMessage {
     /// Construct a midi message from bytes.
     pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, Error> {
@@ -254,6 +303,7 @@ impl<'a> MidiMessage {
     }
 }
 
+#[cfg(feature = "std")]
 impl io::Read for MidiMessage {
     // Use MidiMessage::copy_from_slice instead.
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
@@ -306,7 +356,7 @@ pub enum Channel {
 
 impl Channel {
     /// Get a MIDI channel from an index that is between 0 and 15 inclusive.
-    pub fn from_index(i: u8) -> Result<Channel, Error> {
+    pub const fn from_index(i: u8) -> Result<Channel, Error> {
         match i {
             0 => Ok(Channel::Ch1),
             1 => Ok(Channel::Ch2),
@@ -330,7 +380,7 @@ impl Channel {
 
     /// The index of this midi channel. The returned value is between 0 and 15
     /// inclusive.
-    pub fn index(self) -> u8 {
+    pub const fn index(self) -> u8 {
         match self {
             Channel::Ch1 => 0,
             Channel::Ch2 => 1,
@@ -353,7 +403,7 @@ impl Channel {
 
     /// The number of this midi channel. The returned value is between 1 and 16
     /// inclusive.
-    pub fn number(self) -> u8 {
+    pub const fn number(self) -> u8 {
         self.index() + 1
     }
 }
@@ -523,4 +573,20 @@ mod test {
         );
         assert_eq!(MidiMessage::Start.channel(), None);
     }
+
+    #[test]
+    fn const_constructors_match_decoded_bytes() {
+        const MESSAGES: [MidiMessage; 5] = [
+            MidiMessage::note_on(Channel::Ch2, Note::C4, U7::MAX),
+            MidiMessage::note_off(Channel::Ch2, Note::C4, U7::MIN),
+            MidiMessage::cc(Channel::Ch3, ControlFunction::MODULATION_WHEEL, U7::MAX),
+            MidiMessage::program_change(Channel::Ch4, U7::MIN),
+            MidiMessage::pitch_bend(Channel::Ch5, U14::MAX),
+        ];
+        for message in MESSAGES {
+            let mut bytes = [0u8; 3];
+            let len = message.copy_to_slice(&mut bytes).unwrap();
+            assert_eq!(MidiMessage::from_bytes(&bytes[..len]), Ok(message));
+        }
+    }
 }
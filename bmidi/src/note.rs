@@ -226,14 +226,14 @@ impl Note {
     /// # Safety
     /// `note` must be less than or equal to 127.
     #[inline(always)]
-    pub unsafe fn from_u8_unchecked(note: u8) -> Note {
+    pub const unsafe fn from_u8_unchecked(note: u8) -> Note {
         core::mem::transmute(note)
     }
 
     /// Create a note from a `u8`. Only the 7 least significant bits of `note` are used.
     #[inline(always)]
-    pub fn from_u8_lossy(note: u8) -> Note {
-        Note::from(crate::U7::from_u8_lossy(note))
+    pub const fn from_u8_lossy(note: u8) -> Note {
+        unsafe { Note::from_u8_unchecked(note & 0x7F) }
     }
 
     /// The frequency using the standard 440Hz tuning.
@@ -244,6 +244,7 @@ impl Note {
     /// let note = bmidi::Note::A3;
     /// sing(note.to_freq_f32());
     /// ```
+    #[cfg(feature = "std")]
     #[inline(always)]
     pub fn to_freq_f32(self) -> f32 {
         let exp = (f32::from(self as u8) + 36.376_316) / 12.0;
@@ -258,6 +259,7 @@ impl Note {
     /// let note = bmidi::Note::A3;
     /// sing(note.to_freq_f64());
     /// ```
+    #[cfg(feature = "std")]
     #[inline(always)]
     pub fn to_freq_f64(self) -> f64 {
         let exp = (f64::from(self as u8) + 36.376_316_562_295_91) / 12.0;
@@ -0,0 +1,181 @@
+use crate::{Channel, MidiMessage, Note, U7};
+
+impl MidiMessage {
+    /// The note of note on, note off, and polyphonic key pressure messages.
+    pub fn note(&self) -> Option<Note> {
+        match self {
+            MidiMessage::NoteOff(_, note, _)
+            | MidiMessage::NoteOn(_, note, _)
+            | MidiMessage::PolyphonicKeyPressure(_, note, _) => Some(*note),
+            _ => None,
+        }
+    }
+
+    /// Move the message to `channel`. Messages without a channel are returned unchanged.
+    pub fn with_channel(self, channel: Channel) -> MidiMessage {
+        match self {
+            MidiMessage::NoteOff(_, n, v) => MidiMessage::NoteOff(channel, n, v),
+            MidiMessage::NoteOn(_, n, v) => MidiMessage::NoteOn(channel, n, v),
+            MidiMessage::PolyphonicKeyPressure(_, n, v) => {
+                MidiMessage::PolyphonicKeyPressure(channel, n, v)
+            }
+            MidiMessage::ControlChange(_, f, v) => MidiMessage::ControlChange(channel, f, v),
+            MidiMessage::ProgramChange(_, p) => MidiMessage::ProgramChange(channel, p),
+            MidiMessage::ChannelPressure(_, v) => MidiMessage::ChannelPressure(channel, v),
+            MidiMessage::PitchBendChange(_, b) => MidiMessage::PitchBendChange(channel, b),
+            msg => msg,
+        }
+    }
+
+    /// Transpose the note of the message by `semitones`. Returns `None` if the note is transposed
+    /// out of the midi range. Messages without a note are returned unchanged.
+    pub fn transposed(self, semitones: i8) -> Option<MidiMessage> {
+        let msg = match self {
+            MidiMessage::NoteOff(c, n, v) => MidiMessage::NoteOff(c, n.step(semitones).ok()?, v),
+            MidiMessage::NoteOn(c, n, v) => MidiMessage::NoteOn(c, n.step(semitones).ok()?, v),
+            MidiMessage::PolyphonicKeyPressure(c, n, v) => {
+                MidiMessage::PolyphonicKeyPressure(c, n.step(semitones).ok()?, v)
+            }
+            msg => msg,
+        };
+        Some(msg)
+    }
+
+    /// Multiply the velocity of note on messages by `scale`. The velocity is kept between `1` and
+    /// `127` so that a note on is never turned into a note off. Other messages are returned
+    /// unchanged.
+    pub fn with_scaled_velocity(self, scale: f32) -> MidiMessage {
+        match self {
+            MidiMessage::NoteOn(c, n, v) => {
+                let v = (u8::from(v) as f32 * scale + 0.5).clamp(1.0, 127.0) as u8;
+                MidiMessage::NoteOn(c, n, U7::from_u8_lossy(v))
+            }
+            msg => msg,
+        }
+    }
+}
+
+/// Combinators for iterators of timed midi messages, `(frame, message)`, such as the midi input
+/// of a single buffer. Each combinator is lazy and does not allocate.
+///
+/// # Example
+/// ```
+/// use bmidi::{Channel, MidiIterExt, MidiMessage, Note, U7};
+/// let input = [
+///     (0, MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX)),
+///     (8, MidiMessage::NoteOn(Channel::Ch2, Note::C4, U7::MAX)),
+/// ];
+/// let output: Vec<_> = input
+///     .into_iter()
+///     .only_channel(Channel::Ch1)
+///     .transpose(12)
+///     .remap_channel(Channel::Ch10)
+///     .collect();
+/// assert_eq!(output, vec![(0, MidiMessage::NoteOn(Channel::Ch10, Note::C5, U7::MAX))]);
+/// ```
+pub trait MidiIterExt: Iterator<Item = (u32, MidiMessage)> + Sized {
+    /// Keep only the messages that match `predicate`.
+    fn filter_messages(
+        self,
+        mut predicate: impl FnMut(&MidiMessage) -> bool,
+    ) -> impl Iterator<Item = (u32, MidiMessage)> {
+        self.filter(move |(_, msg)| predicate(msg))
+    }
+
+    /// Keep only the channel messages on `channel`. Messages without a channel, like
+    /// `TimingClock`, are kept.
+    fn only_channel(self, channel: Channel) -> impl Iterator<Item = (u32, MidiMessage)> {
+        self.filter_messages(move |msg| msg.channel().is_none_or(|c| c == channel))
+    }
+
+    /// Transpose notes by `semitones`. Notes that are transposed out of the midi range are
+    /// dropped.
+    fn transpose(self, semitones: i8) -> impl Iterator<Item = (u32, MidiMessage)> {
+        self.filter_map(move |(frame, msg)| Some((frame, msg.transposed(semitones)?)))
+    }
+
+    /// Move all channel messages to `channel`.
+    fn remap_channel(self, channel: Channel) -> impl Iterator<Item = (u32, MidiMessage)> {
+        self.map(move |(frame, msg)| (frame, msg.with_channel(channel)))
+    }
+
+    /// Multiply the velocity of note on messages by `scale`.
+    fn scale_velocity(self, scale: f32) -> impl Iterator<Item = (u32, MidiMessage)> {
+        self.map(move |(frame, msg)| (frame, msg.with_scaled_velocity(scale)))
+    }
+}
+
+impl<I: Iterator<Item = (u32, MidiMessage)>> MidiIterExt for I {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ControlFunction, U14};
+
+    #[test]
+    fn transposed_drops_notes_out_of_range() {
+        let msg = MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX);
+        assert_eq!(
+            msg.transposed(-12),
+            Some(MidiMessage::NoteOn(Channel::Ch1, Note::C3, U7::MAX))
+        );
+        assert_eq!(msg.transposed(100), None);
+        assert_eq!(
+            MidiMessage::TimingClock.transposed(100),
+            Some(MidiMessage::TimingClock)
+        );
+    }
+
+    #[test]
+    fn scaled_velocity_never_turns_note_on_into_note_off() {
+        let msg = MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::from_u8_lossy(100));
+        assert_eq!(
+            msg.with_scaled_velocity(0.5),
+            MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::from_u8_lossy(50))
+        );
+        assert_eq!(
+            msg.with_scaled_velocity(0.0),
+            MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::from_u8_lossy(1))
+        );
+        assert_eq!(
+            msg.with_scaled_velocity(2.0),
+            MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX)
+        );
+    }
+
+    #[test]
+    fn combinators_compose() {
+        let input = [
+            (
+                0,
+                MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::from_u8_lossy(100)),
+            ),
+            (1, MidiMessage::NoteOn(Channel::Ch2, Note::D4, U7::MAX)),
+            (
+                2,
+                MidiMessage::ControlChange(Channel::Ch1, ControlFunction::DAMPER_PEDAL, U7::MAX),
+            ),
+            (3, MidiMessage::PitchBendChange(Channel::Ch1, U14::MAX)),
+            (4, MidiMessage::TimingClock),
+        ];
+        let output: Vec<_> = input
+            .into_iter()
+            .only_channel(Channel::Ch1)
+            .filter_messages(|msg| !matches!(msg, MidiMessage::ControlChange(..)))
+            .transpose(2)
+            .scale_velocity(0.5)
+            .remap_channel(Channel::Ch3)
+            .collect();
+        assert_eq!(
+            output,
+            vec![
+                (
+                    0,
+                    MidiMessage::NoteOn(Channel::Ch3, Note::D4, U7::from_u8_lossy(50))
+                ),
+                (3, MidiMessage::PitchBendChange(Channel::Ch3, U14::MAX)),
+                (4, MidiMessage::TimingClock),
+            ]
+        );
+    }
+}